@@ -0,0 +1,151 @@
+use axum::{
+    Json, Router,
+    extract::State,
+    http::{HeaderMap, header::AUTHORIZATION},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use metrics_exporter_prometheus::PrometheusHandle;
+use share::{
+    auth::{AdminAuthError, AdminTokens},
+    models::api::{ApiData, ApiMsg, ApiResponse, ResponseStatus},
+};
+
+use crate::consumer::ConsumerRegistry;
+
+#[derive(Clone)]
+struct AdminState {
+    admin_tokens: AdminTokens,
+    consumers: ConsumerRegistry,
+    metrics: PrometheusHandle,
+}
+
+async fn list_consumers(State(state): State<AdminState>, headers: HeaderMap) -> Response {
+    let authorization = headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok());
+
+    let (status, message) = match state.admin_tokens.authorize(authorization) {
+        Ok(()) => {
+            return Json(ApiResponse {
+                status: ResponseStatus::Ok,
+                data: ApiData::Data(state.consumers.snapshot()),
+                message: ApiMsg::OK,
+            })
+            .into_response();
+        }
+        Err(AdminAuthError::MissingToken) => (ResponseStatus::Unauthorized, ApiMsg::Unauthorized),
+        Err(AdminAuthError::InvalidToken) => (ResponseStatus::Forbidden, ApiMsg::EndpointForbidden),
+    };
+
+    ApiResponse::<()> {
+        status,
+        data: ApiData::Empty,
+        message,
+    }
+    .into_response()
+}
+
+async fn render_metrics(State(state): State<AdminState>) -> String {
+    state.metrics.render()
+}
+
+/// `/metrics` is left open like the web service's, it is scraped by
+/// Prometheus and carries no ballot data.
+pub fn admin_routes(
+    admin_tokens: AdminTokens,
+    consumers: ConsumerRegistry,
+    metrics: PrometheusHandle,
+) -> Router {
+    Router::new()
+        .route("/consumers", get(list_consumers))
+        .route("/metrics", get(render_metrics))
+        .with_state(AdminState {
+            admin_tokens,
+            consumers,
+            metrics,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::Request, http::StatusCode};
+    use tower::ServiceExt as _;
+
+    use metrics_exporter_prometheus::PrometheusBuilder;
+
+    use super::*;
+
+    fn test_metrics() -> PrometheusHandle {
+        PrometheusBuilder::new().build_recorder().handle()
+    }
+
+    async fn consumers(authorization: Option<&str>) -> (StatusCode, serde_json::Value) {
+        let registry = ConsumerRegistry::default();
+        registry
+            .register("save_score", "ark-vote.save_score")
+            .heartbeat();
+        let router = admin_routes(AdminTokens::new(["secret"]), registry, test_metrics());
+
+        let mut req = Request::get("/consumers");
+        if let Some(authorization) = authorization {
+            req = req.header(AUTHORIZATION, authorization);
+        }
+
+        let response = router
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_consumers_requires_token() {
+        assert_eq!(consumers(None).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            consumers(Some("Bearer wrong")).await.0,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_consumers_lists_status() {
+        let (status, body) = consumers(Some("Bearer secret")).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let consumer = &body["data"][0];
+        assert_eq!(consumer["name"], "save_score");
+        assert_eq!(consumer["state"], "running");
+        assert!(consumer["last_heartbeat"].is_string());
+        assert!(consumer["last_message"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_metrics_are_rendered_without_token() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let router = admin_routes(
+            AdminTokens::new(["secret"]),
+            ConsumerRegistry::default(),
+            recorder.handle(),
+        );
+        metrics::with_local_recorder(&recorder, || {
+            metrics::counter!("votes_saved_overflow_total").increment(2);
+        });
+
+        let response = router
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(
+            String::from_utf8_lossy(&body).contains("votes_saved_overflow_total 2"),
+            "{}",
+            String::from_utf8_lossy(&body)
+        );
+    }
+}
@@ -2,23 +2,19 @@ use std::time::Duration;
 
 pub const CONSUMER_BATCH_SIZE: usize = 200;
 pub const CONSUMER_RETRY_DELAY: Duration = Duration::from_secs(5);
+pub const CONSUMER_MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+pub const CONSUMER_MAX_RESTARTS: u32 = 10;
+pub const CONSUMER_RESTART_RESET_AFTER: Duration = Duration::from_secs(600);
+pub const CONSUMER_STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
 pub const DLQ_RETRY_DELAY: Duration = Duration::from_secs(10);
 pub const DLQ_MAX_RETRIES: u32 = 5;
+pub const DLQ_PAYLOAD_ZSTD_LEVEL: i32 = 3;
+pub const CANDIDATE_POOL_CACHE_TTL: Duration = Duration::from_secs(60);
 
-pub const LUA_SCRIPT_UPDATE_SCORES: &str = r#"
-local win_id = ARGV[1]
-local lose_id = ARGV[2]
-local multiplier = ARGV[3]
-
-redis.call("HINCRBY", "op_stats", win_id..":win", multiplier)
-redis.call("HINCRBY", "op_stats", lose_id..":lose", multiplier)
-redis.call("HINCRBY", "op_matrix", win_id..":"..lose_id, multiplier)
-redis.call("HINCRBY", "op_matrix", lose_id..":"..win_id, -multiplier)
-
-redis.call("INCR", "total_valid_ballots")
-
-return 1
-"#;
+/// Header of a republished pairwise ballot holding the operators its code was
+/// issued for, `left,right`. The failed attempt already consumed the code, the
+/// retry checks the ballot against this instead.
+pub const RETRY_SHOWN_HEADER: &str = "X-Shown-Operators";
 
 pub const LUA_SCRIPT_IP_COUNTER: &str = r#"
 local counter_key = KEYS[1]
@@ -69,44 +65,91 @@ return results
 
 pub const LUA_SCRIPT_BATCH_SCORE_UPDATE_SCRIPT: &str = r#"
 -- KEYS: empty (we use ARGV for dynamic key generation)
--- ARGV: topic_id1, win_id1, lose_id1, multiplier1, topic_id2, win_id2, lose_id2, multiplier2, ...
--- Each score update takes 4 arguments: topic_id, win_id, lose_id, multiplier
+-- ARGV: score_ceiling, update_count,
+--       topic_id1, win_id1, lose_id1, multiplier1, games1, topic_id2, ...,
+--       cooccur_topic_id1, operator_a1, operator_b1, count1, cooccur_topic_id2, ...
+-- Each of the update_count score updates takes 5 arguments: topic_id, win_id,
+-- lose_id, multiplier, games. games is the number of comparisons summed into
+-- multiplier, counted in the <topic_id>:op_counter hash under "min:max" and
+-- as wins of win_id under "min:max:a_wins" (win_id is min) or "min:max:b_wins",
+-- and in <topic_id>:valid_ballots_count.
+-- The remaining arguments come in fours, operator_a < operator_b, each pair
+-- counted in the <topic_id>:op_cooccur hash under "a:b". Both go in the same
+-- run so a batch is either fully counted or not at all.
+-- score_ceiling caps every op_stats win/lose counter, 0 leaves them unbounded.
+-- HINCRBY errors instead of wrapping once a counter would overflow i64, which
+-- aborts the remaining updates of the batch; the ceiling keeps counters clear of it.
 
 -- local valid_ballots_count = KEYS[1]
-local arg_count = #ARGV
+local score_ceiling = tonumber(ARGV[1])
+local update_count = tonumber(ARGV[2])
+local cooccur_start = 3 + update_count * 5
+
+-- 确保参数数量是5的倍数（分数）与4的倍数（共现）
+if cooccur_start > #ARGV + 1 or (#ARGV + 1 - cooccur_start) % 4 ~= 0 then
+    return redis.error_reply("invalid argument count: expected 5 per update and 4 per pair")
+end
 
--- 确保参数数量是4的倍数
-if arg_count % 4 ~= 0 then
-    return redis.error_reply("invalid argument count: must be multiple of 4")
+-- 计数不允许为负，且不超过 score_ceiling
+local function incr_stat(key, field, amount)
+    local value = redis.call("HINCRBY", key, field, amount)
+    if value < 0 then
+        redis.call("HSET", key, field, 0)
+    elseif score_ceiling > 0 and value > score_ceiling then
+        redis.call("HSET", key, field, score_ceiling)
+    end
 end
 
-for i = 1, arg_count, 4 do
+local refused = 0
+
+for i = 3, cooccur_start - 1, 5 do
     local topic_id = ARGV[i]
     local win_id = tonumber(ARGV[i + 1])
     local lose_id = tonumber(ARGV[i + 2])
     local multiplier = tonumber(ARGV[i + 3])
+    local games = tonumber(ARGV[i + 4])
 
-    local op_stats_key = topic_id .. ":op_stats"
-    local op_matrix_key = topic_id .. ":op_matrix"
-
-    redis.call("HINCRBY", op_stats_key, win_id..":win", multiplier)
-    redis.call("HINCRBY", op_stats_key, lose_id..":lose", multiplier)
-    redis.call("HINCRBY", op_matrix_key, win_id..":"..lose_id, multiplier)
-    redis.call("HINCRBY", op_matrix_key, lose_id..":"..win_id, -multiplier)
+    if multiplier < 0 then
+        refused = refused + 1
+    else
+        local op_stats_key = topic_id .. ":op_stats"
+        local op_matrix_key = topic_id .. ":op_matrix"
+
+        incr_stat(op_stats_key, win_id..":win", multiplier)
+        incr_stat(op_stats_key, lose_id..":lose", multiplier)
+        redis.call("HINCRBY", op_matrix_key, win_id..":"..lose_id, multiplier)
+        redis.call("HINCRBY", op_matrix_key, lose_id..":"..win_id, -multiplier)
+        local op_counter_key = topic_id .. ":op_counter"
+        local pair = math.min(win_id, lose_id) .. ":" .. math.max(win_id, lose_id)
+        local wins = win_id < lose_id and ":a_wins" or ":b_wins"
+        redis.call("HINCRBY", op_counter_key, pair, games)
+        redis.call("HINCRBY", op_counter_key, pair .. wins, games)
+
+        local valid_ballots_key = topic_id .. ":valid_ballots_count"
+        redis.call("INCRBY", valid_ballots_key, games)
+    end
+end
 
-    local valid_ballots_key = topic_id .. ":valid_ballots_count"
-    redis.call("INCR", valid_ballots_key)
+for i = cooccur_start, #ARGV, 4 do
+    local op_cooccur_key = ARGV[i] .. ":op_cooccur"
+    redis.call("HINCRBY", op_cooccur_key, ARGV[i + 1] .. ":" .. ARGV[i + 2], tonumber(ARGV[i + 3]))
 end
 
-return 1
+return refused
 "#;
 
+// KEYS are ballot keys `<topic_id>:ballot:<ballot_id>`. Every code actually
+// consumed is counted as resolved in `<topic_id>:ballot_codes`.
 pub const LUA_SCRIPT_GET_DEL_MANY: &str = r#"
 local results = {}
 for i, key in ipairs(KEYS) do
     local value = redis.call("GET", key)
     if value then
         redis.call("DEL", key)
+        local topic_id = string.match(key, "^(.*):ballot:")
+        if topic_id then
+            redis.call("HINCRBY", topic_id .. ":ballot_codes", "resolved", 1)
+        end
     end
     results[i] = value
 end
@@ -115,7 +158,12 @@ return results
 
 pub const LUA_SCRIPT_DEL_MUTIPLE: &str = r#"
 for i, key in ipairs(KEYS) do
-    redis.call("DEL", key)
+    if redis.call("DEL", key) == 1 then
+        local topic_id = string.match(key, "^(.*):ballot:")
+        if topic_id then
+            redis.call("HINCRBY", topic_id .. ":ballot_codes", "resolved", 1)
+        end
+    end
 end
 return 1
 "#;
@@ -1,21 +1,18 @@
 use std::{borrow::Cow, sync::Arc};
 
 use futures::StreamExt as _;
-use share::{config::AppConfig, models::api::BallotSkipRequest};
+use share::{config::AppConfig, models::api::BallotSkipRequest, tracing::LogSampler};
 
-use crate::{
-    AppDatabase,
-    constants::{CONSUMER_BATCH_SIZE, CONSUMER_RETRY_DELAY},
-    error::AppError,
-};
+use crate::{AppDatabase, constants::CONSUMER_BATCH_SIZE, error::AppError};
 
-use super::normalize_subject;
+use super::{ConsumerHandle, RestartPolicy, normalize_subject, run_with_restart};
 
 pub async fn ballot_skip_consumer(
     filter_subject: Cow<'static, str>,
     stream: async_nats::jetstream::stream::Stream,
     database: Arc<AppDatabase>,
-    _app_config: Arc<AppConfig>,
+    app_config: Arc<AppConfig>,
+    status: ConsumerHandle,
 ) -> Result<(), AppError> {
     let normalized_subject = normalize_subject(&filter_subject);
     let process_name = format!("{normalized_subject}-consumer");
@@ -34,6 +31,8 @@ pub async fn ballot_skip_consumer(
         .get_multiplexed_async_connection()
         .await?;
 
+    let log_sampler = LogSampler::new(app_config.tracing.debug_sample_rate);
+
     std::thread::Builder::new()
         .name(process_name.to_string())
         .spawn(move || {
@@ -48,16 +47,21 @@ pub async fn ballot_skip_consumer(
                 .build()
                 .unwrap();
 
-            runtime.block_on(async {
-                tokio::select! {
-                    res = process_ballot_skip(&consumer, &mut conn, &database.redis.del_multiple_script) => {
-                        if let Err(e) = res {
-                            tracing::error!("error in process_ballot_skip: {}", e);
-                            tokio::time::sleep(CONSUMER_RETRY_DELAY).await;
-                        }
-                    },
-                }
-            });
+            runtime.block_on(run_with_restart(
+                "process_ballot_skip",
+                &status,
+                RestartPolicy::default(),
+                async || {
+                    process_ballot_skip(
+                        &consumer,
+                        &mut conn,
+                        &database.redis.del_multiple_script,
+                        &log_sampler,
+                        &status,
+                    )
+                    .await
+                },
+            ));
         })?;
 
     Ok(())
@@ -69,11 +73,18 @@ async fn process_ballot_skip(
     >,
     conn: &mut redis::aio::MultiplexedConnection,
     del_multiple_script: &redis::Script,
+    log_sampler: &LogSampler,
+    status: &ConsumerHandle,
 ) -> Result<(), AppError> {
     let mut count = 0;
     let mut batch_messages = Vec::with_capacity(CONSUMER_BATCH_SIZE);
 
     loop {
+        if status.stop_requested() {
+            status.stopped();
+            return Ok(());
+        }
+        status.heartbeat();
         let mut messages = consumer
             .fetch()
             .max_messages(CONSUMER_BATCH_SIZE)
@@ -98,6 +109,7 @@ async fn process_ballot_skip(
                 }
                 Err(e) => {
                     tracing::error!("error fetching ballot skip request message: {}", e);
+                    status.record_error(&e);
                     continue;
                 }
             }
@@ -110,10 +122,11 @@ async fn process_ballot_skip(
 
         let processed_count = batch_messages.len();
         count += processed_count;
+        status.record_messages(processed_count);
 
         let ballot_keys = batch_messages
             .iter()
-            .map(|msg| format!("{}:ballot:{}", msg.topic_id, msg.ballot_id))
+            .map(|msg| msg.topic_id.ballot_key(&msg.ballot_id))
             .collect::<Vec<_>>();
 
         let _: () = del_multiple_script
@@ -121,7 +134,7 @@ async fn process_ballot_skip(
             .invoke_async(conn)
             .await?;
 
-        if count % 1000 == 0 || processed_count > 0 {
+        if processed_count > 0 && log_sampler.sample() {
             tracing::debug!("processed {} ballot skip request messages", count);
         }
 
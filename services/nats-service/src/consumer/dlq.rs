@@ -6,16 +6,26 @@ use serde::{Deserialize, Serialize};
 use share::config::AppConfig;
 
 use crate::{
-    constants::{CONSUMER_BATCH_SIZE, CONSUMER_RETRY_DELAY},
+    constants::{CONSUMER_BATCH_SIZE, DLQ_PAYLOAD_ZSTD_LEVEL},
     db::AppDatabase,
     error::AppError,
 };
 
-use super::normalize_subject;
+use super::{ConsumerHandle, RestartPolicy, normalize_subject, run_with_restart};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadCompression {
+    #[default]
+    None,
+    Zstd,
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DeadLetterMessage {
-    pub original_payload: String, // base64 encoded
+    pub original_payload: String, // base64 encoded, compressed with `compression`
+    #[serde(default)]
+    pub compression: PayloadCompression,
     pub error_message: String,
     pub retry_count: u32,
     pub first_error_timestamp: i64,
@@ -23,11 +33,38 @@ pub struct DeadLetterMessage {
     pub subject: async_nats::Subject,
 }
 
+impl DeadLetterMessage {
+    /// Compresses the payload with zstd and base64 encodes it.
+    pub fn encode_payload(payload: &[u8]) -> Result<(String, PayloadCompression), AppError> {
+        let compressed = zstd::encode_all(payload, DLQ_PAYLOAD_ZSTD_LEVEL)?;
+        Ok((
+            general_purpose::STANDARD.encode(compressed),
+            PayloadCompression::Zstd,
+        ))
+    }
+
+    /// Restores the original payload. Documents written before compression was
+    /// introduced have no `compression` field and are only base64 decoded.
+    pub fn decode_payload(&self) -> Result<Vec<u8>, AppError> {
+        let decoded = general_purpose::STANDARD
+            .decode(&self.original_payload)
+            .map_err(|e| {
+                AppError::InvalidBallotFormat(format!("failed to decode DLQ payload: {e}"))
+            })?;
+
+        match self.compression {
+            PayloadCompression::None => Ok(decoded),
+            PayloadCompression::Zstd => Ok(zstd::decode_all(decoded.as_slice())?),
+        }
+    }
+}
+
 pub async fn dlq_consumer(
     filter_subject: Cow<'_, str>,
     stream: async_nats::jetstream::stream::Stream,
     database: Arc<AppDatabase>,
     _app_config: Arc<AppConfig>,
+    status: ConsumerHandle,
 ) -> Result<(), AppError> {
     let normalized_subject = normalize_subject(&filter_subject);
     let process_name = format!("{normalized_subject}-consumer");
@@ -54,16 +91,12 @@ pub async fn dlq_consumer(
                 .build()
                 .unwrap();
 
-            runtime.block_on(async {
-                tokio::select! {
-                    res = process_dead_letter_queue(&consumer, &database) => {
-                        if let Err(e) = res {
-                            tracing::error!("error in process_dead_letter_queue: {}", e);
-                            tokio::time::sleep(CONSUMER_RETRY_DELAY).await;
-                        }
-                    },
-                }
-            });
+            runtime.block_on(run_with_restart(
+                "process_dead_letter_queue",
+                &status,
+                RestartPolicy::default(),
+                async || process_dead_letter_queue(&consumer, &database, &status).await,
+            ));
         })?;
 
     Ok(())
@@ -74,10 +107,16 @@ async fn process_dead_letter_queue(
         async_nats::jetstream::consumer::pull::Config,
     >,
     database: &AppDatabase,
+    status: &ConsumerHandle,
 ) -> Result<(), AppError> {
     let mut messages_groups = Vec::with_capacity(CONSUMER_BATCH_SIZE);
 
     loop {
+        if status.stop_requested() {
+            status.stopped();
+            return Ok(());
+        }
+        status.heartbeat();
         let mut messages = consumer.fetch().max_messages(10).messages().await?;
 
         while let Some(message) = messages.next().await {
@@ -85,6 +124,7 @@ async fn process_dead_letter_queue(
                 Ok(msg) => messages_groups.push(msg),
                 Err(e) => {
                     tracing::error!("error getting DLQ message: {}", e);
+                    status.record_error(&e);
                     continue;
                 }
             }
@@ -95,6 +135,7 @@ async fn process_dead_letter_queue(
             continue;
         }
 
+        status.record_messages(messages_groups.len());
         for msg in messages_groups.drain(..) {
             match process_dead_letter_message(&msg.payload, database).await {
                 Ok(_) => {
@@ -108,6 +149,7 @@ async fn process_dead_letter_queue(
                         "failed to process DLQ message: {}. acknowledging to prevent infinite loop.",
                         e
                     );
+                    status.record_error(&e);
                     if let Err(ack_err) = msg.double_ack().await {
                         tracing::error!("failed to acknowledge failed DLQ message: {}", ack_err);
                     }
@@ -131,9 +173,7 @@ async fn process_dead_letter_message(
 
     tracing::info!("dead letter message logged to MongoDB for analysis");
 
-    let original_payload = general_purpose::STANDARD
-        .decode(&dlq_message.original_payload)
-        .map_err(|e| AppError::InvalidBallotFormat(format!("failed to decode DLQ payload: {e}")))?;
+    let original_payload = dlq_message.decode_payload()?;
 
     tracing::warn!(
         "dead letter message processed: subject={}, error={}, payload={:?}",
@@ -144,3 +184,51 @@ async fn process_dead_letter_message(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_message(
+        original_payload: String,
+        compression: PayloadCompression,
+    ) -> DeadLetterMessage {
+        DeadLetterMessage {
+            original_payload,
+            compression,
+            error_message: "test".to_string(),
+            retry_count: 0,
+            first_error_timestamp: 0,
+            last_error_timestamp: 0,
+            subject: "ark-vote.save_score".into(),
+        }
+    }
+
+    #[test]
+    fn test_payload_round_trip() {
+        let payload = br#"{"info":{"topic_id":"crisis_v2_season_4_1","ballot_id":"1-abc"},"win":1001,"lose":1002}"#;
+
+        let (encoded, compression) = DeadLetterMessage::encode_payload(payload).unwrap();
+        assert_eq!(compression, PayloadCompression::Zstd);
+
+        let message = create_test_message(encoded, compression);
+        assert_eq!(message.decode_payload().unwrap(), payload);
+    }
+
+    #[test]
+    fn test_decode_uncompressed_payload() {
+        let payload = b"legacy payload";
+        let json = serde_json::json!({
+            "original_payload": general_purpose::STANDARD.encode(payload),
+            "error_message": "test",
+            "retry_count": 0,
+            "first_error_timestamp": 0,
+            "last_error_timestamp": 0,
+            "subject": "ark-vote.save_score",
+        });
+
+        let message: DeadLetterMessage = serde_json::from_value(json).unwrap();
+        assert_eq!(message.compression, PayloadCompression::None);
+        assert_eq!(message.decode_payload().unwrap(), payload);
+    }
+}
@@ -1,6 +1,8 @@
 mod ballot_skip;
 mod dlq;
+mod restart;
 mod save_score;
+mod status;
 
 use std::{borrow::Cow, collections::HashMap, pin::Pin, sync::Arc};
 
@@ -8,6 +10,9 @@ use ballot_skip::ballot_skip_consumer;
 use dlq::dlq_consumer;
 use save_score::save_score_consumer;
 use share::config::AppConfig;
+pub use status::{ConsumerHandle, ConsumerRegistry};
+
+use restart::{RestartPolicy, run_with_restart};
 
 use crate::db::AppDatabase;
 
@@ -21,6 +26,7 @@ type ConsumerStarter =
         stream: async_nats::jetstream::stream::Stream,
         database: Arc<AppDatabase>,
         app_config: Arc<AppConfig>,
+        status: ConsumerHandle,
     ) -> Pin<Box<dyn futures::Future<Output = eyre::Result<()>> + Send + 'static>>;
 
 #[derive(Debug)]
@@ -36,10 +42,13 @@ pub fn available_consumers() -> HashMap<&'static str, ConsumerConfig> {
                 $name,
                 ConsumerConfig {
                     name: $name,
-                    starter: |subject, stream, db, vote_config| {
+                    starter: |subject, stream, db, vote_config, status| {
                         Box::pin(async move {
-                            if let Err(e) = $func(subject, stream, db, vote_config).await {
+                            if let Err(e) =
+                                $func(subject, stream, db, vote_config, status.clone()).await
+                            {
                                 tracing::error!("{} failed: {}", $name, e);
+                                status.failed(&e);
                             }
                             Ok(())
                         })
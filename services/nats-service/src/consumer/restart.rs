@@ -0,0 +1,159 @@
+use std::time::{Duration, Instant};
+
+use share::retry::exponential_backoff;
+
+use crate::{
+    constants::{
+        CONSUMER_MAX_RESTARTS, CONSUMER_MAX_RETRY_DELAY, CONSUMER_RESTART_RESET_AFTER,
+        CONSUMER_RETRY_DELAY,
+    },
+    error::AppError,
+};
+
+use super::ConsumerHandle;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RestartPolicy {
+    /// Consecutive failures tolerated before the consumer gives up.
+    pub max_restarts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// A run lasting at least this long resets the failure streak.
+    pub reset_after: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: CONSUMER_MAX_RESTARTS,
+            base_delay: CONSUMER_RETRY_DELAY,
+            max_delay: CONSUMER_MAX_RETRY_DELAY,
+            reset_after: CONSUMER_RESTART_RESET_AFTER,
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Exponential backoff for the `attempt`-th consecutive restart, starting
+    /// at 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        exponential_backoff(self.base_delay, self.max_delay, attempt.saturating_sub(1))
+    }
+}
+
+/// Runs a consumer loop, restarting it with backoff whenever it returns an
+/// error. Returns once the loop exits cleanly or `max_restarts` consecutive
+/// failures have been reached.
+pub async fn run_with_restart(
+    name: &str,
+    status: &ConsumerHandle,
+    policy: RestartPolicy,
+    mut process: impl AsyncFnMut() -> Result<(), AppError>,
+) {
+    let mut attempt = 0;
+
+    loop {
+        let started = Instant::now();
+        let Err(e) = process().await else {
+            return;
+        };
+
+        if started.elapsed() >= policy.reset_after {
+            attempt = 0;
+        }
+        attempt += 1;
+
+        if attempt > policy.max_restarts {
+            tracing::error!(
+                "{} failed {} times in a row, giving up: {}",
+                name,
+                attempt,
+                e
+            );
+            status.failed(&e);
+            return;
+        }
+
+        let delay = policy.delay(attempt);
+        tracing::error!(
+            "error in {}: {}. restarting in {:?} (attempt {}/{})",
+            name,
+            e,
+            delay,
+            attempt,
+            policy.max_restarts
+        );
+        status.restarting(&e);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consumer::{ConsumerRegistry, status::ConsumerState};
+
+    fn policy(max_restarts: u32) -> RestartPolicy {
+        RestartPolicy {
+            max_restarts,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+            reset_after: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_restart_delay_backoff() {
+        let policy = RestartPolicy {
+            base_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(60),
+            ..RestartPolicy::default()
+        };
+
+        assert_eq!(policy.delay(1), Duration::from_secs(5));
+        assert_eq!(policy.delay(2), Duration::from_secs(10));
+        assert_eq!(policy.delay(4), Duration::from_secs(40));
+        assert_eq!(policy.delay(5), Duration::from_secs(60));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn test_restarts_after_error() {
+        let registry = ConsumerRegistry::default();
+        let status = registry.register("save_score", "ark-vote.save_score");
+
+        let mut runs = 0;
+        run_with_restart("save_score", &status, policy(3), async || {
+            runs += 1;
+            match runs {
+                1 | 2 => Err(AppError::InvalidParticipants),
+                _ => Ok(()),
+            }
+        })
+        .await;
+
+        assert_eq!(runs, 3);
+        let status = &registry.snapshot()[0];
+        assert_eq!(status.restarts, 2);
+        assert_eq!(status.errors, 2);
+        assert_ne!(status.state, ConsumerState::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_restarts() {
+        let registry = ConsumerRegistry::default();
+        let status = registry.register("dlq", "ark-vote.dlq");
+
+        let mut runs = 0;
+        run_with_restart("dlq", &status, policy(2), async || {
+            runs += 1;
+            Err(AppError::InvalidParticipants)
+        })
+        .await;
+
+        assert_eq!(runs, 3);
+        let status = &registry.snapshot()[0];
+        assert_eq!(status.restarts, 2);
+        assert_eq!(status.state, ConsumerState::Stopped);
+    }
+}
@@ -4,29 +4,67 @@ use std::{
     sync::Arc,
 };
 
-use base64::{Engine as _, engine::general_purpose};
 use futures::StreamExt as _;
 use redis::AsyncCommands as _;
 use share::{
-    config::{AppConfig, VoteConfig},
-    models::database::{
-        Ballot, GroupwiseBallot, PairwiseBallot, PluralityBallot, SetwiseBallot, StoredBallot,
+    config::{AppConfig, IpLimits, VoteConfig},
+    metrics::{BALLOT_CODES_RESOLVED_FIELD, LabelCap, count_ballot_code, saved_per_topic},
+    models::{
+        database::{
+            Ballot, BallotInfo, GroupwiseBallot, PairwiseBallot, PluralityBallot, SetwiseBallot,
+            StoredBallot, TopicVoteOverrides,
+        },
+        topic_id::TopicId,
     },
+    reputation::{combine_multipliers, fetch_user_reputations},
+    tracing::LogSampler,
 };
 
 use crate::{
     AppDatabase,
-    constants::{CONSUMER_BATCH_SIZE, CONSUMER_RETRY_DELAY, DLQ_MAX_RETRIES, DLQ_RETRY_DELAY},
+    constants::{CONSUMER_BATCH_SIZE, DLQ_MAX_RETRIES, DLQ_RETRY_DELAY, RETRY_SHOWN_HEADER},
     consumer::dlq::DeadLetterMessage,
     error::AppError,
+    pool::{CachedTopic, CandidatePoolCache},
 };
 
-use super::normalize_subject;
+use super::{ConsumerHandle, RestartPolicy, normalize_subject, run_with_restart};
 
 #[derive(Debug, Default)]
 struct BatchProcessResult {
     success_count: usize,
-    failed_messages: Vec<async_nats::jetstream::Message>,
+    /// Ballots scored by topic, see `record_votes_saved`.
+    saved_per_topic: HashMap<String, u64>,
+    /// Ballots that were not scored, as indices into the processed batch.
+    failed_messages: Vec<(usize, AppError)>,
+}
+
+/// Counts saved ballots in `votes_saved_total` labelled by topic. Every label
+/// value is a series Prometheus keeps until restart, so only the first
+/// `vote.throughput_metric_topics` topics get one and the ballots of later
+/// topics are summed in the unlabelled `votes_saved_overflow_total`. Rates
+/// summed over both stay exact.
+fn record_votes_saved(saved_per_topic: &HashMap<String, u64>, labels: &LabelCap) {
+    for (topic_id, &count) in saved_per_topic {
+        if labels.admit(topic_id) {
+            metrics::counter!("votes_saved_total", "topic_id" => topic_id.clone()).increment(count);
+        } else {
+            metrics::counter!("votes_saved_overflow_total").increment(count);
+        }
+    }
+}
+
+/// Counts a ballot that could not be scored, in `ballots_retried_total` when
+/// the error is transient and it goes back to the retry path, otherwise in
+/// `ballots_invalid_total`. Returns whether it is retried.
+fn record_ballot_failure(err: &AppError) -> bool {
+    if err.is_need_send_to_dlq() {
+        metrics::counter!("ballots_retried_total").increment(1);
+        true
+    } else {
+        metrics::counter!("ballots_invalid_total").increment(1);
+        false
+    }
 }
 
 pub async fn save_score_consumer(
@@ -34,6 +72,7 @@ pub async fn save_score_consumer(
     stream: async_nats::jetstream::stream::Stream,
     database: Arc<AppDatabase>,
     app_config: Arc<AppConfig>,
+    status: ConsumerHandle,
 ) -> Result<(), AppError> {
     let normalized_subject = normalize_subject(&filter_subject);
     let process_name = format!("{normalized_subject}-consumer");
@@ -66,16 +105,21 @@ pub async fn save_score_consumer(
                 .build()
                 .unwrap();
 
-            runtime.block_on(async {
-                tokio::select! {
-                    res = process_save_score_messages(&consumer, &mut conn, &database, &app_config) => {
-                        if let Err(e) = res {
-                            tracing::error!("error in process_save_score_messages: {}", e);
-                            tokio::time::sleep(CONSUMER_RETRY_DELAY).await;
-                        }
-                    },
-                }
-            });
+            runtime.block_on(run_with_restart(
+                "process_save_score_messages",
+                &status,
+                RestartPolicy::default(),
+                async || {
+                    process_save_score_messages(
+                        &consumer,
+                        &mut conn,
+                        &database,
+                        &app_config,
+                        &status,
+                    )
+                    .await
+                },
+            ));
         })?;
 
     Ok(())
@@ -84,6 +128,22 @@ pub async fn save_score_consumer(
 struct PairwiseBallotItem<'a> {
     ballot: PairwiseBallot<'a>,
     message: async_nats::jetstream::Message,
+    /// The ballot came from a bundle, `message` then carries other ballots
+    /// too.
+    bundled: bool,
+    /// Operators of the ballot's code when it is a retry, see
+    /// `RETRY_SHOWN_HEADER`.
+    shown: Option<(i32, i32)>,
+}
+
+impl PairwiseBallotItem<'_> {
+    /// Payload to republish when this ballot alone has to be retried.
+    fn retry_payload(&self) -> Result<Vec<u8>, AppError> {
+        match self.bundled {
+            true => Ok(serde_json::to_vec(&Ballot::Pairwise(self.ballot.clone()))?),
+            false => Ok(self.message.payload.to_vec()),
+        }
+    }
 }
 
 struct SetwiseBallotItem<'a> {
@@ -122,37 +182,44 @@ impl<'a> BallotMessageGroup<'a> {
         &mut self,
         message: async_nats::jetstream::Message,
     ) -> Option<async_nats::jetstream::Message> {
-        match serde_json::from_slice::<Ballot>(&message.payload) {
-            Ok(Ballot::Pairwise(ballot)) => {
-                self.pairwise.push(PairwiseBallotItem { ballot, message });
-                None
+        let (ballots, bundled) = match parse_ballots(&message.payload) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                tracing::warn!("invalid ballot format: {}. acknowledging message.", e);
+                return Some(message);
             }
-            Ok(Ballot::Setwise(ballot)) => {
-                self.setwise.push(SetwiseBallotItem {
+        };
+
+        // retries carry a single ballot
+        let shown = match bundled {
+            true => None,
+            false => retried_shown(&message),
+        };
+        for ballot in ballots {
+            let message = message.clone();
+            match ballot {
+                Ballot::Pairwise(ballot) => self.pairwise.push(PairwiseBallotItem {
+                    ballot,
+                    message,
+                    bundled,
+                    shown,
+                }),
+                Ballot::Setwise(ballot) => self.setwise.push(SetwiseBallotItem {
                     ballot,
                     _message: message,
-                });
-                None
-            }
-            Ok(Ballot::Groupwise(ballot)) => {
-                self.groupwise.push(GroupwiseBallotItem {
+                }),
+                Ballot::Groupwise(ballot) => self.groupwise.push(GroupwiseBallotItem {
                     ballot,
                     _message: message,
-                });
-                None
-            }
-            Ok(Ballot::Plurality(ballot)) => {
-                self.plurality.push(PluralityBallotItem {
+                }),
+                Ballot::Plurality(ballot) => self.plurality.push(PluralityBallotItem {
                     ballot,
                     _message: message,
-                });
-                None
-            }
-            Err(e) => {
-                tracing::warn!("invalid ballot format: {}. acknowledging message.", e);
-                Some(message)
+                }),
             }
         }
+
+        None
     }
 
     fn take_all(
@@ -179,6 +246,39 @@ impl<'a> BallotMessageGroup<'a> {
     }
 }
 
+/// Parses a save score payload, either a single ballot or a JSON array of
+/// ballots published by the web service's ballot bundler. The flag is set for
+/// arrays.
+fn parse_ballots<'a>(payload: &[u8]) -> Result<(Vec<Ballot<'a>>, bool), serde_json::Error> {
+    match payload.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'[') => serde_json::from_slice(payload).map(|ballots| (ballots, true)),
+        _ => serde_json::from_slice(payload).map(|ballot| (vec![ballot], false)),
+    }
+}
+
+/// Operators a retried ballot's code was issued for, `None` for a first
+/// attempt.
+fn retried_shown(message: &async_nats::jetstream::Message) -> Option<(i32, i32)> {
+    parse_shown(message.headers.as_ref()?.get(RETRY_SHOWN_HEADER)?.as_str())
+}
+
+fn parse_shown(value: &str) -> Option<(i32, i32)> {
+    let (left, right) = value.split_once(',')?;
+    Some((left.parse().ok()?, right.parse().ok()?))
+}
+
+/// Bundled ballots share their message, so each message is acknowledged
+/// once.
+fn unique_messages<'m>(
+    messages: impl IntoIterator<Item = &'m async_nats::jetstream::Message>,
+) -> Vec<&'m async_nats::jetstream::Message> {
+    let mut seen = HashSet::new();
+    messages
+        .into_iter()
+        .filter(|message| seen.insert(message.reply.as_deref()))
+        .collect()
+}
+
 async fn process_save_score_messages(
     consumer: &async_nats::jetstream::consumer::Consumer<
         async_nats::jetstream::consumer::pull::Config,
@@ -186,11 +286,19 @@ async fn process_save_score_messages(
     conn: &mut redis::aio::MultiplexedConnection,
     database: &AppDatabase,
     app_config: &AppConfig,
+    status: &ConsumerHandle,
 ) -> Result<(), AppError> {
     let mut count = 0;
     let mut ballot_groups = BallotMessageGroup::with_capacity(CONSUMER_BATCH_SIZE);
+    let log_sampler = LogSampler::new(app_config.tracing.debug_sample_rate);
+    let saved_topic_labels = &database.saved_topic_labels;
 
     loop {
+        if status.stop_requested() {
+            status.stopped();
+            return Ok(());
+        }
+        status.heartbeat();
         let mut messages = consumer
             .fetch()
             .max_messages(CONSUMER_BATCH_SIZE)
@@ -210,6 +318,7 @@ async fn process_save_score_messages(
                 }
                 Err(e) => {
                     tracing::error!("error getting message: {}", e);
+                    status.record_error(&e);
                     continue;
                 }
             }
@@ -221,47 +330,99 @@ async fn process_save_score_messages(
         }
 
         let (pairwise, setwise, groupwise, plurality) = ballot_groups.take_all();
+        status.record_messages(pairwise.len() + setwise.len() + groupwise.len() + plurality.len());
 
         if !setwise.is_empty() {
             let result = process_setwise_ballot_batch(&setwise, conn, database, app_config).await;
-            if let Err(e) = result {
-                tracing::error!("failed to process setwise ballots: {}", e);
+            match result {
+                Ok(result) => record_votes_saved(&result.saved_per_topic, saved_topic_labels),
+                Err(e) => {
+                    tracing::error!("failed to process setwise ballots: {}", e);
+                    status.record_error(&e);
+                }
             }
         }
 
         if !groupwise.is_empty() {
             let result =
                 process_groupwise_ballot_batch(&groupwise, conn, database, app_config).await;
-            if let Err(e) = result {
-                tracing::error!("failed to process groupwise ballots: {}", e);
+            match result {
+                Ok(result) => record_votes_saved(&result.saved_per_topic, saved_topic_labels),
+                Err(e) => {
+                    tracing::error!("failed to process groupwise ballots: {}", e);
+                    status.record_error(&e);
+                }
             }
         }
 
         if !plurality.is_empty() {
             let result =
                 process_plurality_ballot_batch(&plurality, conn, database, app_config).await;
-            if let Err(e) = result {
-                tracing::error!("failed to process plurality ballots: {}", e);
+            match result {
+                Ok(result) => record_votes_saved(&result.saved_per_topic, saved_topic_labels),
+                Err(e) => {
+                    tracing::error!("failed to process plurality ballots: {}", e);
+                    status.record_error(&e);
+                }
             }
         }
 
-        match process_pairwise_ballot_batch(&pairwise, conn, database, app_config).await {
+        // codes are consumed before anything else can fail, the operators they
+        // were issued for go with every retry so it is not refused for a spent code
+        let (issued, result) =
+            match validate_pairwise_ballots(&pairwise, &database.redis.get_del_many_script, conn)
+                .await
+            {
+                Ok(validation_results) => {
+                    let issued = issued_operators(&validation_results);
+                    let result = process_pairwise_ballot_batch(
+                        &pairwise,
+                        validation_results,
+                        conn,
+                        database,
+                        app_config,
+                    )
+                    .await;
+                    (issued, result)
+                }
+                Err(e) => (HashMap::new(), Err(e)),
+            };
+        let shown_of = |item: &PairwiseBallotItem<'_>| {
+            issued.get(item.ballot.info.ballot_id.as_ref()).copied()
+        };
+
+        match result {
             Ok(result) => {
                 count += result.success_count;
+                record_votes_saved(&result.saved_per_topic, saved_topic_labels);
 
-                if count % 1000 == 0 || result.success_count > 0 {
+                if result.success_count > 0 && log_sampler.sample() {
                     tracing::debug!("processed {} save score messages", count);
                 }
 
-                for msg in result.failed_messages {
-                    tracing::error!("failed to process ballot: {:?}. Sending to DLQ.", msg);
-                    if let Err(e) = handle_failed_messages(
-                        &database.jetstream,
-                        (&msg, &AppError::InvalidParticipants),
-                    )
-                    .await
-                    {
-                        tracing::error!("failed to handle failed message: {}", e);
+                for (index, err) in result.failed_messages {
+                    let item = &pairwise[index];
+                    let msg = &item.message;
+                    if record_ballot_failure(&err) {
+                        tracing::error!("failed to process ballot: {}. Sending to DLQ.", err);
+                        status.record_error(&err);
+                        let handled = match item.retry_payload() {
+                            Ok(payload) => {
+                                handle_failed_messages(
+                                    &database.jetstream,
+                                    (msg, &payload, shown_of(item), &err),
+                                )
+                                .await
+                            }
+                            Err(e) => Err(e),
+                        };
+                        if let Err(e) = handled {
+                            // left unacknowledged, the message is redelivered
+                            tracing::error!("failed to handle failed message: {}", e);
+                            continue;
+                        }
+                    } else {
+                        tracing::warn!("dropping invalid ballot: {}. acknowledging message.", err);
                     }
                     if let Err(e) = msg.double_ack().await {
                         tracing::error!("failed to double_ack failed message: {}", e);
@@ -270,11 +431,38 @@ async fn process_save_score_messages(
             }
             Err(e) => {
                 tracing::error!("batch processing failed: {}", e);
+                status.record_error(&e);
+                // a bundle is acknowledged once all of its ballots went
+                // through, and left for redelivery if any of them failed
+                let mut unhandled = HashSet::new();
                 for msg in pairwise.iter() {
-                    if let Err(e) =
-                        process_single_pairwise_fallback(msg, conn, database, app_config).await
+                    match process_single_pairwise_fallback(
+                        msg,
+                        shown_of(msg),
+                        conn,
+                        database,
+                        app_config,
+                    )
+                    .await
                     {
-                        tracing::error!("fallback processing failed: {}", e);
+                        Ok(true) => record_votes_saved(
+                            &HashMap::from([(msg.ballot.info.topic_id.to_string(), 1)]),
+                            saved_topic_labels,
+                        ),
+                        Ok(false) => {}
+                        Err(e) => {
+                            tracing::error!("fallback processing failed: {}", e);
+                            unhandled.insert(msg.message.reply.as_deref());
+                        }
+                    }
+                }
+                let handled = pairwise
+                    .iter()
+                    .map(|item| &item.message)
+                    .filter(|message| !unhandled.contains(&message.reply.as_deref()));
+                for msg in unique_messages(handled) {
+                    if let Err(e) = msg.double_ack().await {
+                        tracing::error!("failed to double_ack fallback message: {}", e);
                     }
                 }
             }
@@ -282,8 +470,10 @@ async fn process_save_score_messages(
     }
 }
 
+/// Scores a batch whose codes `validate_pairwise_ballots` already consumed.
 async fn process_pairwise_ballot_batch(
     ballots: &[PairwiseBallotItem<'_>],
+    mut validation_results: HashMap<String, Result<(i32, i32), AppError>>,
     conn: &mut redis::aio::MultiplexedConnection,
     database: &AppDatabase,
     app_config: &AppConfig,
@@ -296,44 +486,59 @@ async fn process_pairwise_ballot_batch(
     let mut failed_messages = Vec::new();
     let mut ignored_messages = Vec::new();
 
-    // 第一步：批量验证ballot codes
-    let validation_results =
-        validate_pairwise_ballots(ballots, &database.redis.get_del_many_script, conn).await?;
-
     // 第二步：批量计算IP倍数
-    let ip_multipliers = calculate_pairwise_multipliers(
-        ballots,
+    let infos: Vec<&BallotInfo<'_>> = ballots.iter().map(|item| &item.ballot.info).collect();
+    let ip_multipliers = calculate_ip_multipliers(
+        &infos,
         vote_config,
+        &database.candidate_pools,
         &database.redis.batch_ip_counter_script,
         conn,
     )
     .await?;
 
+    let user_reputations = if vote_config.reputation.enabled {
+        fetch_user_reputations(&infos, conn).await?
+    } else {
+        HashMap::new()
+    };
+    let multiplier_of = |ballot: &PairwiseBallot<'_>| {
+        ballot_multiplier(
+            &ballot.info,
+            &ip_multipliers,
+            &user_reputations,
+            vote_config,
+        )
+    };
+
     // 第三步：过滤有效的ballot并准备批量操作
     let mut valid_ballots = Vec::new();
-    let mut score_updates = HashMap::new(); // (win_id, lose_id) -> total_multiplier
+    let mut score_updates = HashMap::new(); // (topic_id, win_id, lose_id) -> (total_multiplier, games)
 
-    for item in ballots.iter() {
+    for (index, item) in ballots.iter().enumerate() {
         // 验证ballot code
         let (ballot_left, ballot_right) =
-            match validation_results.get(item.ballot.info.ballot_id.as_ref()) {
-                Some(Ok((left, right))) => (*left, *right),
-                Some(Err(e)) => {
-                    if !matches!(e, AppError::InvalidBallotCode(_)) {
-                        tracing::warn!("ballot vaild error: {}", e);
-                        failed_messages.push(item.message.clone());
-                        continue;
-                    }
+            match validation_results.remove(item.ballot.info.ballot_id.as_ref()) {
+                Some(Ok((left, right))) => (left, right),
+                Some(Err(AppError::InvalidBallotCode(_))) => {
                     ignored_messages.push(item.message.clone());
                     continue;
                 }
+                Some(Err(e)) => {
+                    tracing::warn!("ballot vaild error: {}", e);
+                    failed_messages.push((index, e));
+                    continue;
+                }
                 None => {
                     tracing::warn!(
                         "invalid ballot code: {} for code={}",
                         item.ballot.info.ballot_id,
                         item.ballot.info.ballot_id
                     );
-                    failed_messages.push(item.message.clone());
+                    failed_messages.push((
+                        index,
+                        AppError::InvalidBallotCode(item.ballot.info.ballot_id.to_string()),
+                    ));
                     continue;
                 }
             };
@@ -349,44 +554,41 @@ async fn process_pairwise_ballot_batch(
                 item.ballot.lose,
                 item.ballot.info.ballot_id
             );
-            failed_messages.push(item.message.clone());
+            failed_messages.push((index, AppError::InvalidParticipants));
             continue;
         }
 
-        let multiplier = ip_multipliers
-            .get(item.ballot.info.ip.as_ref())
-            .copied()
-            .unwrap_or(vote_config.low_multiplier);
+        if let Err(e) = database.candidate_pools.check_ballot(&item.ballot).await {
+            tracing::warn!(
+                "ballot rejected by topic check: {} for code={}",
+                e,
+                item.ballot.info.ballot_id
+            );
+            failed_messages.push((index, e));
+            continue;
+        }
 
-        *score_updates
+        let multiplier = multiplier_of(&item.ballot);
+
+        let (total, games) = score_updates
             .entry((
                 item.ballot.info.topic_id.to_string(),
                 item.ballot.win,
                 item.ballot.lose,
             ))
-            .or_insert(0) += multiplier;
+            .or_insert((0, 0));
+        *total += multiplier;
+        *games += 1;
 
         valid_ballots.push(item);
     }
 
-    // 第四步：批量执行分数更新
-    batch_update_scores(
-        score_updates,
-        &database.redis.batch_score_update_script,
-        conn,
-    )
-    .await?;
-
-    // 第五步：批量插入MongoDB
-    // 先按照topic_id分组
+    // 第四步：按照topic_id分组
     let mut grouped_ballots: HashMap<String, Vec<StoredBallot>> = HashMap::new();
 
     for item in valid_ballots.iter() {
         let topic_id = item.ballot.info.topic_id.to_string();
-        let multiplier = ip_multipliers
-            .get(item.ballot.info.ip.as_ref())
-            .copied()
-            .unwrap_or(vote_config.low_multiplier);
+        let multiplier = multiplier_of(&item.ballot);
 
         let stored_ballot = StoredBallot {
             ballot: Ballot::Pairwise(item.ballot.clone()),
@@ -399,23 +601,28 @@ async fn process_pairwise_ballot_batch(
             .push(stored_ballot);
     }
 
-    for (topic_id, ballots) in grouped_ballots.into_iter() {
-        let ballot_collection = database
-            .mongo_database
-            .collection::<StoredBallot>(&format!("ballots_{}", topic_id));
+    // 第五步：批量执行分数与共现更新，再批量插入MongoDB
+    batch_update_scores(
+        score_updates,
+        cooccurrence_counts(&grouped_ballots),
+        vote_config.score_ceiling,
+        &database.redis.batch_score_update_script,
+        conn,
+    )
+    .await?;
 
-        ballot_collection.insert_many(&ballots).await?;
-    }
+    let saved_per_topic = saved_per_topic(&grouped_ballots);
+    insert_sampled_ballots(grouped_ballots, database, app_config).await?;
 
     // 第六步：确认所有成功处理的消息
-    for msg in valid_ballots.iter() {
-        if let Err(e) = msg.message.double_ack().await {
+    for msg in unique_messages(valid_ballots.iter().map(|item| &item.message)) {
+        if let Err(e) = msg.double_ack().await {
             tracing::error!("failed to double_ack successful message: {}", e);
         }
     }
 
     // 第七步：确认所有需要丢掉的消息
-    for msg in ignored_messages.iter() {
+    for msg in unique_messages(&ignored_messages) {
         if let Err(e) = msg.double_ack().await {
             tracing::error!("failed to double_ack ignored message: {}", e);
         }
@@ -423,293 +630,574 @@ async fn process_pairwise_ballot_batch(
 
     Ok(BatchProcessResult {
         success_count: valid_ballots.len() + ignored_messages.len(),
+        saved_per_topic,
         failed_messages,
     })
 }
 
+/// Consumes the ballot codes of a batch, keyed by code. Retries are not
+/// looked up, they carry the operators of their already consumed code.
 async fn validate_pairwise_ballots(
     ballots: &[PairwiseBallotItem<'_>],
     get_del_many_script: &redis::Script,
     conn: &mut redis::aio::MultiplexedConnection,
 ) -> Result<HashMap<String, Result<(i32, i32), AppError>>, AppError> {
-    if ballots.is_empty() {
-        return Ok(HashMap::new());
-    }
-
-    let code_keys: Vec<(String, &str)> = ballots
+    let infos: Vec<&BallotInfo<'_>> = ballots
         .iter()
-        .map(|item| {
-            let info = &item.ballot.info;
+        .filter(|item| item.shown.is_none())
+        .map(|item| &item.ballot.info)
+        .collect();
+    let shown = take_ballot_codes(&infos, get_del_many_script, conn).await?;
 
-            (
-                format!("{}:ballot:{}", info.topic_id, info.ballot_id),
-                info.ballot_id.as_ref(),
-            )
+    let mut results: HashMap<_, _> = shown
+        .into_iter()
+        .map(|(code, result)| {
+            let result = result.and_then(|operators| match operators[..] {
+                [left, right] => Ok((left, right)),
+                _ => Err(AppError::InvalidBallotFormat(
+                    "expected format: left,right".to_string(),
+                )),
+            });
+            (code, result)
         })
         .collect();
+    for item in ballots {
+        if let Some(shown) = item.shown {
+            results.insert(item.ballot.info.ballot_id.to_string(), Ok(shown));
+        }
+    }
 
-    let keys: Vec<String> = code_keys.iter().map(|(k, _)| k.clone()).collect();
-    let values: Vec<Option<String>> = get_del_many_script.key(&keys).invoke_async(conn).await?;
+    Ok(results)
+}
 
-    fn parse_ballot_info(info: &str) -> Result<(i32, i32), AppError> {
-        let parts: Vec<&str> = info.split(',').collect();
-        if parts.len() != 2 {
-            return Err(AppError::InvalidBallotFormat(
-                "expected format: left,right".to_string(),
-            ));
+/// Operators of the consumed codes, kept for the retries of their ballots.
+fn issued_operators(
+    validation_results: &HashMap<String, Result<(i32, i32), AppError>>,
+) -> HashMap<String, (i32, i32)> {
+    validation_results
+        .iter()
+        .filter_map(|(code, result)| Some((code.clone(), *result.as_ref().ok()?)))
+        .collect()
+}
+
+/// Consumes the ballot codes of `infos` and returns, keyed by code, the
+/// operators each one was issued for. Codes that do not exist (anymore) map
+/// to `InvalidBallotCode`.
+async fn take_ballot_codes(
+    infos: &[&BallotInfo<'_>],
+    get_del_many_script: &redis::Script,
+    conn: &mut redis::aio::MultiplexedConnection,
+) -> Result<HashMap<String, Result<Vec<i32>, AppError>>, AppError> {
+    if infos.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    // topic ids were checked when the ballots were saved, one that does not
+    // parse never had a code
+    let mut results = HashMap::new();
+    let mut keyed = Vec::with_capacity(infos.len());
+    for info in infos {
+        match TopicId::parse(info.topic_id.as_ref()) {
+            Ok(topic_id) => keyed.push((info, topic_id.ballot_key(&info.ballot_id))),
+            Err(_) => {
+                let code = info.ballot_id.to_string();
+                results.insert(code.clone(), Err(AppError::InvalidBallotCode(code)));
+            }
         }
+    }
+    if keyed.is_empty() {
+        return Ok(results);
+    }
 
-        let left = parts[0].parse().map_err(|_| {
-            AppError::InvalidBallotFormat("left part is not a valid integer".to_string())
-        })?;
+    let keys: Vec<&String> = keyed.iter().map(|(_, key)| key).collect();
+    let values: Vec<Option<String>> = get_del_many_script.key(&keys).invoke_async(conn).await?;
 
-        let right = parts[1].parse().map_err(|_| {
-            AppError::InvalidBallotFormat("right part is not a valid integer".to_string())
-        })?;
+    fn parse_operators(value: &str) -> Result<Vec<i32>, AppError> {
+        value
+            .split(',')
+            .map(|id| {
+                id.parse().map_err(|_| {
+                    AppError::InvalidBallotFormat(format!("{id} is not a valid operator id"))
+                })
+            })
+            .collect()
+    }
 
-        Ok((left, right))
+    for ((info, _), value) in keyed.into_iter().zip(values) {
+        let code = info.ballot_id.to_string();
+        let result = match value {
+            Some(value) => parse_operators(&value),
+            None => Err(AppError::InvalidBallotCode(code.clone())),
+        };
+        results.insert(code, result);
     }
 
-    let results: HashMap<String, Result<(i32, i32), AppError>> = code_keys
-        .into_iter()
-        .zip(values.into_iter())
-        .map(|((_, code), value)| {
-            let result = match value {
-                Some(info) => parse_ballot_info(&info),
-                None => Err(AppError::InvalidBallotCode(code.to_string())),
-            };
-            (code.to_string(), result)
+    Ok(results)
+}
+
+/// A setwise, groupwise or plurality ballot may only show the operators its
+/// code was issued for.
+fn check_shown(ballot: &Ballot<'_>, shown: &[i32]) -> Result<(), AppError> {
+    if ballot.operators().iter().all(|id| shown.contains(id)) {
+        Ok(())
+    } else {
+        Err(AppError::InvalidParticipants)
+    }
+}
+
+async fn ballot_store_sample_rate(
+    topic_id: &str,
+    database: &AppDatabase,
+    vote_config: &VoteConfig,
+) -> Result<u64, AppError> {
+    Ok(match database.candidate_pools.get(topic_id).await? {
+        Some(cached) => cached
+            .topic
+            .vote_overrides
+            .ballot_store_sample_rate(vote_config),
+        None => vote_config.ballot_store_sample_rate,
+    })
+}
+
+/// Writes the sampled share of each topic's ballots to MongoDB, see
+/// `vote.ballot_store_sample_rate`. Topics are inserted concurrently, a topic
+/// failing does not block the others. Returns the first error.
+async fn insert_sampled_ballots(
+    mut grouped_ballots: HashMap<String, Vec<StoredBallot<'_>>>,
+    database: &AppDatabase,
+    app_config: &AppConfig,
+) -> Result<(), AppError> {
+    for (topic_id, ballots) in grouped_ballots.iter_mut() {
+        let rate = ballot_store_sample_rate(topic_id, database, &app_config.vote).await?;
+        ballots.retain(|stored| stored.ballot.info().is_stored_at(rate));
+    }
+    grouped_ballots.retain(|_, ballots| !ballots.is_empty());
+
+    let mut inserts = futures::stream::iter(grouped_ballots.iter())
+        .map(|(topic_id, ballots)| async move {
+            let ballot_collection = database
+                .mongo_database
+                .collection::<StoredBallot>(&format!("ballots_{}", topic_id));
+
+            (topic_id, ballot_collection.insert_many(ballots).await)
         })
-        .collect();
+        .buffer_unordered(app_config.database.mongodb_insert_concurrency.max(1));
 
-    Ok(results)
+    let mut first_error = None;
+    while let Some((topic_id, result)) = inserts.next().await {
+        if let Err(e) = result {
+            tracing::error!("Failed to insert ballots of topic {}: {}", topic_id, e);
+            first_error.get_or_insert(e);
+        }
+    }
+    match first_error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
 }
 
-async fn calculate_pairwise_multipliers(
-    ballots: &[PairwiseBallotItem<'_>],
+/// Multipliers of the batch keyed by `(topic_id, ip)`. The ip counters of
+/// each topic are checked against its own limits, see `TopicVoteOverrides`.
+async fn calculate_ip_multipliers(
+    infos: &[&BallotInfo<'_>],
     vote_config: &VoteConfig,
+    candidate_pools: &CandidatePoolCache,
     batch_ip_counter_script: &redis::Script,
     conn: &mut redis::aio::MultiplexedConnection,
-) -> Result<HashMap<String, i32>, AppError> {
-    if ballots.is_empty() {
-        return Ok(HashMap::new());
+) -> Result<HashMap<(String, String), i32>, AppError> {
+    let mut ips_by_topic: HashMap<&str, Vec<&str>> = HashMap::new();
+    for info in infos {
+        ips_by_topic
+            .entry(info.topic_id.as_ref())
+            .or_default()
+            .push(info.ip.as_ref());
     }
 
     let mut results = HashMap::new();
+    for (topic_id, ips) in ips_by_topic {
+        let cached = candidate_pools.get(topic_id).await?;
+        let limits = topic_ip_limits(cached.as_ref(), vote_config);
 
-    let ips: HashSet<&str> = ballots.iter().map(|b| b.ballot.info.ip.as_ref()).collect();
-
-    let keys: Vec<String> = ballots
-        .iter()
-        .map(|b| {
-            let info = &b.ballot.info;
-            format!("{}:ip_counter:{}", info.topic_id.as_ref(), info.ip.as_ref())
-        })
-        .collect();
+        // ballots of such a topic never had a code, they are not counted
+        let Ok(topic_id) = TopicId::parse(topic_id) else {
+            tracing::warn!("Skipping the ip counters of invalid topic id {}", topic_id);
+            continue;
+        };
+        let keys: Vec<String> = ips.iter().map(|ip| topic_id.ip_counter_key(ip)).collect();
+        let script_results: Vec<i32> = batch_ip_counter_script
+            .key(&keys)
+            .arg(vote_config.ip_counter_expire_seconds)
+            .arg(limits.max_ip_limit)
+            .arg(limits.base_multiplier)
+            .arg(limits.low_multiplier)
+            .invoke_async(conn)
+            .await?;
 
-    let ips_vec: Vec<&str> = ips.into_iter().collect();
+        // an ip saving several ballots keeps the multiplier of its last one
+        for (ip, multiplier) in ips.into_iter().zip(script_results) {
+            results.insert((topic_id.to_string(), ip.to_string()), multiplier);
+        }
+    }
 
-    let script_results: Vec<i32> = batch_ip_counter_script
-        .key(&keys)
-        .arg(vote_config.ip_counter_expire_seconds)
-        .arg(vote_config.max_ip_limit)
-        .arg(vote_config.base_multiplier)
-        .arg(vote_config.low_multiplier)
-        .invoke_async(conn)
-        .await?;
+    Ok(results)
+}
 
-    for (ip, multiplier) in ips_vec.into_iter().zip(script_results.into_iter()) {
-        results.insert(ip.to_string(), multiplier);
+/// IP counter settings of a topic, the global ones for unknown topics.
+fn topic_ip_limits(cached: Option<&CachedTopic>, vote_config: &VoteConfig) -> IpLimits {
+    match cached {
+        Some(cached) => cached.topic.vote_overrides.resolve(vote_config),
+        None => TopicVoteOverrides::default().resolve(vote_config),
     }
+}
 
-    Ok(results)
+/// Weight of one ballot: zero when throttled, otherwise its ip multiplier
+/// combined with the user's reputation.
+fn ballot_multiplier(
+    info: &BallotInfo<'_>,
+    ip_multipliers: &HashMap<(String, String), i32>,
+    user_reputations: &HashMap<String, f64>,
+    vote_config: &VoteConfig,
+) -> i32 {
+    if info.throttled {
+        return 0;
+    }
+    let ip_multiplier = ip_multipliers
+        .get(&(info.topic_id.to_string(), info.ip.to_string()))
+        .copied()
+        .unwrap_or(vote_config.low_multiplier);
+    let reputation = info
+        .user_id
+        .as_deref()
+        .and_then(|user_id| user_reputations.get(user_id).copied());
+
+    combine_multipliers(
+        ip_multiplier,
+        reputation,
+        vote_config.reputation.max_reputation,
+    )
 }
 
+/// Applies the summed multipliers to `op_stats` and `op_matrix`, counts the
+/// games of each pair in `op_counter` and the `cooccurrences` in `op_cooccur`,
+/// all in one script run so a retried batch is never counted in part.
 async fn batch_update_scores(
-    updates: HashMap<(String, i32, i32), i32>, // ((topic_id, win_id, lose_id), total_multiplier)
+    updates: HashMap<(String, i32, i32), (i32, i64)>, // ((topic_id, win_id, lose_id), (total_multiplier, games))
+    cooccurrences: HashMap<(String, i32, i32), i64>,  // ((topic_id, operator_a, operator_b), count)
+    score_ceiling: Option<i64>,
     batch_score_update_script: &redis::Script,
     conn: &mut redis::aio::MultiplexedConnection,
 ) -> Result<(), AppError> {
-    if updates.is_empty() {
+    if updates.is_empty() && cooccurrences.is_empty() {
         return Ok(());
     }
 
-    // 准备参数：topic_id1, win_id1, lose_id1, multiplier1, topic_id2, win_id2, lose_id2, multiplier2, ...
-    let mut args = Vec::with_capacity(updates.len() * 4);
-    for ((topic_id, win_id, lose_id), multiplier) in updates {
+    // 准备参数：topic_id1, win_id1, lose_id1, multiplier1, games1, topic_id2, ...
+    let mut args = Vec::with_capacity(updates.len() * 5 + cooccurrences.len() * 4);
+    let update_count = updates.len();
+    for ((topic_id, win_id, lose_id), (multiplier, games)) in updates {
         args.push(topic_id);
         args.push(win_id.to_string());
         args.push(lose_id.to_string());
         args.push(multiplier.to_string());
+        args.push(games.to_string());
+    }
+    // 之后是共现：topic_id1, operator_a1, operator_b1, count1, ...
+    for ((topic_id, a, b), count) in cooccurrences {
+        args.push(topic_id);
+        args.push(a.to_string());
+        args.push(b.to_string());
+        args.push(count.to_string());
     }
 
     // 执行批量分数更新脚本
-    let _results: () = batch_score_update_script
+    let refused: i64 = batch_score_update_script
+        .arg(score_ceiling.unwrap_or(0))
+        .arg(update_count)
         .arg(&args)
         .invoke_async(conn)
         .await?;
+    if refused > 0 {
+        tracing::warn!(
+            "refused {} score updates with a negative multiplier",
+            refused
+        );
+    }
 
     Ok(())
 }
 
+/// Operator pairs of the stored ballots, counted for `<topic_id>:op_cooccur`.
+/// Ballots refused by the score update script are skipped, so pairwise counts
+/// match the games behind `op_matrix`.
+fn cooccurrence_counts(
+    grouped_ballots: &HashMap<String, Vec<StoredBallot<'_>>>,
+) -> HashMap<(String, i32, i32), i64> {
+    let mut counts = HashMap::new();
+    for (topic_id, ballots) in grouped_ballots {
+        for stored in ballots.iter().filter(|stored| stored.multiplier >= 0) {
+            for (a, b) in stored.ballot.cooccurrence_pairs() {
+                *counts.entry((topic_id.clone(), a, b)).or_insert(0) += 1;
+            }
+        }
+    }
+    counts
+}
+
 async fn process_setwise_ballot_batch(
     ballots: &[SetwiseBallotItem<'_>],
-    _conn: &mut redis::aio::MultiplexedConnection,
+    conn: &mut redis::aio::MultiplexedConnection,
     database: &AppDatabase,
-    _app_config: &AppConfig,
+    app_config: &AppConfig,
 ) -> Result<BatchProcessResult, AppError> {
-    tracing::debug!("Processing setwise ballot batch, but this feature is not implemented yet.");
+    let limits = &app_config.vote.ballot_size_limits;
 
-    // only save the ballot to mongoDB for now
-    let mut grouped_ballots: HashMap<String, Vec<StoredBallot>> = HashMap::new();
+    let mut valid_ballots = Vec::with_capacity(ballots.len());
     for item in ballots.iter() {
-        let topic_id = item.ballot.info.topic_id.to_string();
-        let stored_ballot = StoredBallot {
-            ballot: Ballot::Setwise(item.ballot.clone()),
-            multiplier: 1, // Placeholder multiplier, adjust as needed
-        };
-
-        grouped_ballots
-            .entry(topic_id)
-            .or_default()
-            .push(stored_ballot);
-    }
-
-    for (topic_id, ballots) in grouped_ballots.into_iter() {
-        let ballot_collection = database
-            .mongo_database
-            .collection::<StoredBallot>(&format!("ballots_{}", topic_id));
-
-        ballot_collection.insert_many(&ballots).await?;
+        if !limits.set_fits(&item.ballot.left_set) || !limits.set_fits(&item.ballot.right_set) {
+            tracing::warn!(
+                "dropping setwise ballot {} exceeding {} operators per set",
+                item.ballot.info.ballot_id,
+                limits.max_set_size
+            );
+            continue;
+        }
+        valid_ballots.push(Ballot::Setwise(item.ballot.clone()));
     }
 
-    tracing::debug!(
-        "Processed {} setwise ballots, but no score updates were made.",
-        ballots.len()
-    );
+    let saved_per_topic =
+        process_scored_ballot_batch(valid_ballots, conn, database, app_config).await?;
 
     Ok(BatchProcessResult {
         success_count: ballots.len(),
+        saved_per_topic,
         failed_messages: Vec::new(), // No failed messages in this case
     })
 }
 
 async fn process_groupwise_ballot_batch(
     ballots: &[GroupwiseBallotItem<'_>],
-    _conn: &mut redis::aio::MultiplexedConnection,
+    conn: &mut redis::aio::MultiplexedConnection,
     database: &AppDatabase,
-    _app_config: &AppConfig,
+    app_config: &AppConfig,
 ) -> Result<BatchProcessResult, AppError> {
-    tracing::debug!("Processing groupwise ballot batch, but this feature is not implemented yet.");
+    let limits = &app_config.vote.ballot_size_limits;
 
-    // only save the ballot to mongoDB for now
-    let mut grouped_ballots: HashMap<String, Vec<StoredBallot>> = HashMap::new();
+    let mut valid_ballots = Vec::with_capacity(ballots.len());
     for item in ballots.iter() {
-        let topic_id = item.ballot.info.topic_id.to_string();
-        let stored_ballot = StoredBallot {
-            ballot: Ballot::Groupwise(item.ballot.clone()),
-            multiplier: 1, // Placeholder multiplier, adjust as needed
-        };
-
-        grouped_ballots
-            .entry(topic_id)
-            .or_default()
-            .push(stored_ballot);
-    }
-
-    for (topic_id, ballots) in grouped_ballots.into_iter() {
-        let ballot_collection = database
-            .mongo_database
-            .collection::<StoredBallot>(&format!("ballots_{}", topic_id));
-
-        ballot_collection.insert_many(&ballots).await?;
+        if !limits.group_fits(&item.ballot.left_group)
+            || !limits.group_fits(&item.ballot.right_group)
+        {
+            tracing::warn!(
+                "dropping groupwise ballot {} exceeding {} operators per group",
+                item.ballot.info.ballot_id,
+                limits.max_group_size
+            );
+            continue;
+        }
+        valid_ballots.push(Ballot::Groupwise(item.ballot.clone()));
     }
 
-    tracing::debug!(
-        "Processed {} groupwise ballots, but no score updates were made.",
-        ballots.len()
-    );
+    let saved_per_topic =
+        process_scored_ballot_batch(valid_ballots, conn, database, app_config).await?;
 
     Ok(BatchProcessResult {
         success_count: ballots.len(),
+        saved_per_topic,
         failed_messages: Vec::new(), // No failed messages in this case
     })
 }
 
 async fn process_plurality_ballot_batch(
     ballots: &[PluralityBallotItem<'_>],
-    _conn: &mut redis::aio::MultiplexedConnection,
+    conn: &mut redis::aio::MultiplexedConnection,
     database: &AppDatabase,
-    _app_config: &AppConfig,
+    app_config: &AppConfig,
 ) -> Result<BatchProcessResult, AppError> {
-    tracing::debug!("Processing plurality ballot batch, but this feature is not implemented yet.");
+    let valid_ballots = ballots
+        .iter()
+        .map(|item| Ballot::Plurality(item.ballot.clone()))
+        .collect();
 
-    // only save the ballot to mongoDB for now
-    let mut grouped_ballots: HashMap<String, Vec<StoredBallot>> = HashMap::new();
-    for item in ballots.iter() {
-        let topic_id = item.ballot.info.topic_id.to_string();
-        let stored_ballot = StoredBallot {
-            ballot: Ballot::Plurality(item.ballot.clone()),
-            multiplier: 1, // Placeholder multiplier, adjust as needed
+    let saved_per_topic =
+        process_scored_ballot_batch(valid_ballots, conn, database, app_config).await?;
+
+    Ok(BatchProcessResult {
+        success_count: ballots.len(),
+        saved_per_topic,
+        failed_messages: Vec::new(), // No failed messages in this case
+    })
+}
+
+/// Setwise, groupwise and plurality ballots. They are checked like pairwise
+/// ones: the code is consumed and must have been issued for the operators
+/// shown, which must belong to the pool of a topic open when the ballot was
+/// cast. Invalid ballots are dropped. Each remaining ballot weighs as much as
+/// a pairwise one, see `Ballot::weighted_score_pairs`. Returns the saved
+/// ballots by topic.
+async fn process_scored_ballot_batch(
+    ballots: Vec<Ballot<'_>>,
+    conn: &mut redis::aio::MultiplexedConnection,
+    database: &AppDatabase,
+    app_config: &AppConfig,
+) -> Result<HashMap<String, u64>, AppError> {
+    if ballots.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let infos: Vec<&BallotInfo<'_>> = ballots.iter().map(Ballot::info).collect();
+    let mut shown = take_ballot_codes(&infos, &database.redis.get_del_many_script, conn).await?;
+
+    let mut valid_ballots = Vec::with_capacity(ballots.len());
+    for ballot in ballots {
+        let code = ballot.info().ballot_id.as_ref();
+        let checked = match shown.remove(code) {
+            Some(Ok(operators)) => check_shown(&ballot, &operators),
+            Some(Err(e)) => Err(e),
+            None => Err(AppError::InvalidBallotCode(code.to_string())),
+        };
+        let checked = match checked {
+            Ok(()) => database.candidate_pools.check_scored_ballot(&ballot).await,
+            Err(e) => Err(e),
         };
+        match checked {
+            Ok(()) => valid_ballots.push(ballot),
+            Err(e) if e.is_need_send_to_dlq() => return Err(e),
+            Err(e) => tracing::warn!("dropping invalid ballot {}: {}", code, e),
+        }
+    }
+    if valid_ballots.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let vote_config = &app_config.vote;
+    let infos: Vec<&BallotInfo<'_>> = valid_ballots.iter().map(Ballot::info).collect();
+    let ip_multipliers = calculate_ip_multipliers(
+        &infos,
+        vote_config,
+        &database.candidate_pools,
+        &database.redis.batch_ip_counter_script,
+        conn,
+    )
+    .await?;
+    let user_reputations = if vote_config.reputation.enabled {
+        fetch_user_reputations(&infos, conn).await?
+    } else {
+        HashMap::new()
+    };
+
+    let mut score_updates = HashMap::new();
+    let mut grouped_ballots: HashMap<String, Vec<StoredBallot>> = HashMap::new();
+    for ballot in valid_ballots {
+        let topic_id = ballot.info().topic_id.to_string();
+        let multiplier = ballot_multiplier(
+            ballot.info(),
+            &ip_multipliers,
+            &user_reputations,
+            vote_config,
+        );
+        add_score_updates(&mut score_updates, &topic_id, &ballot, multiplier);
 
         grouped_ballots
             .entry(topic_id)
             .or_default()
-            .push(stored_ballot);
+            .push(StoredBallot { ballot, multiplier });
     }
 
-    for (topic_id, ballots) in grouped_ballots.into_iter() {
-        let ballot_collection = database
-            .mongo_database
-            .collection::<StoredBallot>(&format!("ballots_{}", topic_id));
+    batch_update_scores(
+        score_updates,
+        cooccurrence_counts(&grouped_ballots),
+        vote_config.score_ceiling,
+        &database.redis.batch_score_update_script,
+        conn,
+    )
+    .await?;
 
-        ballot_collection.insert_many(&ballots).await?;
-    }
+    let saved = saved_per_topic(&grouped_ballots);
+    insert_sampled_ballots(grouped_ballots, database, app_config).await?;
 
-    tracing::debug!(
-        "Processed {} plurality ballots, but no score updates were made.",
-        ballots.len()
-    );
+    Ok(saved)
+}
 
-    Ok(BatchProcessResult {
-        success_count: ballots.len(),
-        failed_messages: Vec::new(), // No failed messages in this case
-    })
+/// Adds the weighted comparisons of `ballot` to the batch's score updates,
+/// each one counting as a game of its pair.
+fn add_score_updates(
+    score_updates: &mut HashMap<(String, i32, i32), (i32, i64)>,
+    topic_id: &str,
+    ballot: &Ballot<'_>,
+    multiplier: i32,
+) {
+    for ((win, lose), weight) in ballot.weighted_score_pairs(multiplier) {
+        let (total, games) = score_updates
+            .entry((topic_id.to_string(), win, lose))
+            .or_insert((0, 0));
+        *total += weight;
+        *games += 1;
+    }
 }
 
 // 回退处理单个消息（当批量处理失败时使用）
+/// Scores one ballot on its own, returns whether it was saved. `shown` are
+/// the operators of its code if the batch already consumed it. The message
+/// is left to the caller to acknowledge, as other ballots may share it.
 async fn process_single_pairwise_fallback(
     msg: &PairwiseBallotItem<'_>,
+    shown: Option<(i32, i32)>,
     conn: &mut redis::aio::MultiplexedConnection,
     database: &AppDatabase,
     app_config: &AppConfig,
-) -> Result<(), AppError> {
-    match process_single_ballot(&msg.ballot, conn, database, app_config).await {
-        Ok(_) => {
-            msg.message.double_ack().await?;
+) -> Result<bool, AppError> {
+    let shown = match shown.or(msg.shown) {
+        Some(shown) => Ok(shown),
+        None => {
+            validate_ballot(
+                msg.ballot.info.topic_id.as_ref(),
+                msg.ballot.info.ballot_id.as_ref(),
+                conn,
+            )
+            .await
         }
+    };
+    let (shown, result) = match shown {
+        Ok(shown) => (
+            Some(shown),
+            process_single_ballot(&msg.ballot, shown, conn, database, app_config).await,
+        ),
+        Err(e) => (None, Err(e)),
+    };
+
+    match result {
+        Ok(_) => Ok(true),
         Err(e) => {
-            if e.is_need_send_to_dlq() {
+            if record_ballot_failure(&e) {
                 tracing::error!("failed to process ballot: {}. Sending to DLQ.", e);
-                handle_failed_messages(&database.jetstream, (&msg.message, &e)).await?;
+                let payload = msg.retry_payload()?;
+                handle_failed_messages(&database.jetstream, (&msg.message, &payload, shown, &e))
+                    .await?;
             } else {
                 tracing::warn!(
                     "invalid ballot format or participants: {}. acknowledging message.",
                     e
                 );
             }
-            msg.message.double_ack().await?;
+            Ok(false)
         }
     }
-    Ok(())
 }
 
+/// Republishes the payload of a failed ballot for a retry, or sends it to the
+/// DLQ once the retries ran out. `shown` are the operators of its code when
+/// the code was consumed, see `RETRY_SHOWN_HEADER`. The message is
+/// acknowledged by the caller.
 async fn handle_failed_messages(
     jetstream: &async_nats::jetstream::Context,
-    message: (&async_nats::jetstream::Message, &AppError),
+    message: (
+        &async_nats::jetstream::Message,
+        &[u8],
+        Option<(i32, i32)>,
+        &AppError,
+    ),
 ) -> Result<(), AppError> {
-    let (message, error_info) = message;
+    let (message, payload, shown, error_info) = message;
 
     let headers = message.headers.as_ref();
     let retry_count: u32 = headers
@@ -725,8 +1213,10 @@ async fn handle_failed_messages(
     let current_timestamp = chrono::Utc::now().timestamp();
 
     if retry_count >= DLQ_MAX_RETRIES {
+        let (original_payload, compression) = DeadLetterMessage::encode_payload(payload)?;
         let dlq_message = DeadLetterMessage {
-            original_payload: general_purpose::STANDARD.encode(&message.payload),
+            original_payload,
+            compression,
             error_message: format!("max retries exceeded. Last error: {error_info}"),
             retry_count,
             first_error_timestamp,
@@ -741,10 +1231,6 @@ async fn handle_failed_messages(
         } else {
             tracing::info!("message sent to DLQ after {} retries", retry_count);
         }
-
-        if let Err(e) = message.double_ack().await {
-            tracing::error!("failed to acknowledge DLQ message: {}", e);
-        }
     } else {
         let mut headers = async_nats::HeaderMap::new();
         headers.insert("X-Retry-Count", (retry_count + 1).to_string().as_str());
@@ -753,37 +1239,35 @@ async fn handle_failed_messages(
             first_error_timestamp.to_string().as_str(),
         );
         headers.insert("X-Last-error", error_info.to_string().as_str());
+        if let Some((left, right)) = shown {
+            headers.insert(RETRY_SHOWN_HEADER, format!("{left},{right}").as_str());
+        }
 
         tokio::time::sleep(DLQ_RETRY_DELAY).await;
 
         if let Err(e) = jetstream
-            .publish_with_headers("ark-vote.save_score", headers, message.payload.clone())
+            .publish_with_headers("ark-vote.save_score", headers, payload.to_vec().into())
             .await
         {
             tracing::error!("failed to republish message for retry: {}", e);
-            let _ = message.double_ack().await;
         } else {
             tracing::debug!("message republished for retry #{}", retry_count + 1);
-            if let Err(e) = message.double_ack().await {
-                tracing::error!("failed to acknowledge retried message: {}", e);
-            }
         }
     }
 
     Ok(())
 }
 
+/// Scores one ballot whose code was issued for the `shown` operators.
 async fn process_single_ballot(
     ballot: &PairwiseBallot<'_>,
+    shown: (i32, i32),
     conn: &mut redis::aio::MultiplexedConnection,
     database: &AppDatabase,
     app_config: &AppConfig,
 ) -> Result<(), AppError> {
     let vote_config = &app_config.vote;
-
-    let (ballot_left, ballot_right) = validate_ballot(ballot.info.ballot_id.as_ref(), conn).await?;
-
-    let valid_ids = [ballot_left, ballot_right];
+    let valid_ids = [shown.0, shown.1];
     if !valid_ids.contains(&ballot.win)
         || !valid_ids.contains(&ballot.lose)
         || ballot.win == ballot.lose
@@ -797,40 +1281,66 @@ async fn process_single_ballot(
         return Err(AppError::InvalidParticipants);
     }
 
-    let multiplier = calculate_multiplier(
-        ballot.info.ip.as_ref(),
-        vote_config,
+    database.candidate_pools.check_ballot(ballot).await?;
+
+    let cached = database.candidate_pools.get(&ballot.info.topic_id).await?;
+    let ip_multiplier = calculate_multiplier(
+        &ballot.info,
+        topic_ip_limits(cached.as_ref(), vote_config),
+        vote_config.ip_counter_expire_seconds,
         &database.redis.ip_counter_script,
         conn,
     )
     .await?;
+    let user_reputations = if vote_config.reputation.enabled {
+        fetch_user_reputations(&[&ballot.info], conn).await?
+    } else {
+        HashMap::new()
+    };
+    let multiplier = ballot_multiplier(
+        &ballot.info,
+        &HashMap::from([(
+            (ballot.info.topic_id.to_string(), ballot.info.ip.to_string()),
+            ip_multiplier,
+        )]),
+        &user_reputations,
+        vote_config,
+    );
 
-    let _: () = database
-        .redis
-        .score_update_script
-        .arg(ballot.win)
-        .arg(ballot.lose)
-        .arg(multiplier)
-        .invoke_async(conn)
-        .await?;
-
-    let ballot = Ballot::Pairwise(ballot.clone());
-    let stored_ballot = StoredBallot { ballot, multiplier };
-
-    let ballot_collection = database
-        .mongo_database
-        .collection::<StoredBallot>("ballots");
-    ballot_collection.insert_one(&stored_ballot).await?;
+    // the same writes as a batch of one, so the topic's keys, the ceiling and
+    // the stored ballots match the batch path
+    let topic_id = ballot.info.topic_id.to_string();
+    let grouped_ballots = HashMap::from([(
+        topic_id.clone(),
+        vec![StoredBallot {
+            ballot: Ballot::Pairwise(ballot.clone()),
+            multiplier,
+        }],
+    )]);
+    batch_update_scores(
+        HashMap::from([((topic_id, ballot.win, ballot.lose), (multiplier, 1))]),
+        cooccurrence_counts(&grouped_ballots),
+        vote_config.score_ceiling,
+        &database.redis.batch_score_update_script,
+        conn,
+    )
+    .await?;
 
-    Ok(())
+    insert_sampled_ballots(grouped_ballots, database, app_config).await
 }
 
 async fn validate_ballot(
+    topic_id: &str,
     code: &str,
     conn: &mut redis::aio::MultiplexedConnection,
 ) -> Result<(i32, i32), AppError> {
-    let ballot_key = format!("ballot:{code}");
-    let value: Option<String> = conn.get_del(&ballot_key).await?;
+    let Ok(topic_id) = TopicId::parse(topic_id) else {
+        return Err(AppError::InvalidBallotCode(code.to_string()));
+    };
+    let value: Option<String> = conn.get_del(topic_id.ballot_key(code)).await?;
+    if value.is_some() {
+        count_ballot_code(conn, &topic_id, BALLOT_CODES_RESOLVED_FIELD).await;
+    }
 
     match value {
         Some(info) => {
@@ -855,21 +1365,362 @@ async fn validate_ballot(
 }
 
 async fn calculate_multiplier(
-    ip: &str,
-    vote_config: &VoteConfig,
+    info: &BallotInfo<'_>,
+    limits: IpLimits,
+    expire_seconds: usize,
     ip_counter_script: &redis::Script,
     conn: &mut redis::aio::MultiplexedConnection,
 ) -> Result<i32, AppError> {
-    let counter_key = format!("ip_counter:{ip}");
+    let topic_id = TopicId::parse(info.topic_id.as_ref())
+        .map_err(|_| AppError::UnknownTopic(info.topic_id.to_string()))?;
+    let counter_key = topic_id.ip_counter_key(&info.ip);
 
     let multiplier: i32 = ip_counter_script
         .key(&counter_key)
-        .arg(vote_config.ip_counter_expire_seconds)
-        .arg(vote_config.max_ip_limit)
-        .arg(vote_config.base_multiplier)
-        .arg(vote_config.low_multiplier)
+        .arg(expire_seconds)
+        .arg(limits.max_ip_limit)
+        .arg(limits.base_multiplier)
+        .arg(limits.low_multiplier)
         .invoke_async(conn)
         .await?;
 
     Ok(multiplier)
 }
+
+#[cfg(test)]
+mod tests {
+    use share::{
+        models::{
+            api::{GroupwiseSelection, Results1v1MatrixPair},
+            topic_id::TopicId,
+        },
+        scores::reset_topic_scores,
+        test_util::test_topic,
+    };
+
+    use super::*;
+    use crate::constants::{LUA_SCRIPT_BATCH_SCORE_UPDATE_SCRIPT, LUA_SCRIPT_IP_COUNTER};
+
+    fn vote_config() -> VoteConfig {
+        serde_json::from_value(serde_json::json!({
+            "base_multiplier": 100,
+            "low_multiplier": 1,
+            "max_ip_limit": 1000,
+            "ip_counter_expire_seconds": 86400,
+            "preset_vote_topic": [],
+        }))
+        .unwrap()
+    }
+
+    fn curated_topic(id: &str) -> CachedTopic {
+        let now = chrono::Utc::now();
+        let mut topic = test_topic(id, now, now + chrono::Duration::hours(1));
+        topic.vote_overrides =
+            serde_json::from_str(r#"{"max_ip_limit": 1, "low_multiplier": 0}"#).unwrap();
+        CachedTopic {
+            topic: Arc::new(topic),
+            pool: Arc::new(HashSet::new()),
+        }
+    }
+
+    const PAIRWISE: &str = r#"{"topic_type":"pairwise","info":{"topic_id":"t","ballot_id":"1-abc","ip":"127.0.0.1","user_agent":"ua","timestamp":0},"win":1,"lose":2}"#;
+
+    #[test]
+    fn test_parse_single_ballot() {
+        let (ballots, bundled) = parse_ballots(PAIRWISE.as_bytes()).unwrap();
+
+        assert_eq!(ballots.len(), 1);
+        assert!(!bundled);
+    }
+
+    #[test]
+    fn test_parse_bundled_ballots() {
+        let payload = format!(" [{PAIRWISE},{PAIRWISE}]");
+        let (ballots, bundled) = parse_ballots(payload.as_bytes()).unwrap();
+
+        assert_eq!(ballots.len(), 2);
+        assert!(bundled);
+        assert!(matches!(&ballots[1], Ballot::Pairwise(b) if b.win == 1 && b.lose == 2));
+    }
+
+    #[test]
+    fn test_parse_invalid_payload() {
+        assert!(parse_ballots(b"[{}]").is_err());
+        assert!(parse_ballots(b"not json").is_err());
+    }
+
+    fn info() -> BallotInfo<'static> {
+        BallotInfo {
+            topic_id: "t".into(),
+            ballot_id: "1-abc".into(),
+            ip: "127.0.0.1".into(),
+            user_agent: "ua".into(),
+            timestamp: 0,
+            user_id: None,
+            throttled: false,
+        }
+    }
+
+    fn scored_ballots() -> [Ballot<'static>; 3] {
+        [
+            Ballot::Setwise(SetwiseBallot {
+                info: info(),
+                left_set: vec![1, 2, 3],
+                right_set: vec![4, 5, 6],
+                selected_left: vec![1],
+                selected_right: vec![4, 5],
+            }),
+            Ballot::Groupwise(GroupwiseBallot {
+                info: info(),
+                left_group: vec![1, 2],
+                right_group: vec![3, 4],
+                selected_group: GroupwiseSelection::Left,
+            }),
+            Ballot::Plurality(PluralityBallot {
+                info: info(),
+                candidates: vec![1, 2, 3, 4],
+                selected: 2,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_check_shown() {
+        for ballot in scored_ballots() {
+            assert!(check_shown(&ballot, &[1, 2, 3, 4, 5, 6]).is_ok());
+            assert!(matches!(
+                check_shown(&ballot, &[1, 2, 3]),
+                Err(AppError::InvalidParticipants)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_scored_ballots_weigh_like_pairwise() {
+        let mut pairwise = HashMap::new();
+        let ballot = Ballot::Pairwise(PairwiseBallot {
+            info: info(),
+            win: 1,
+            lose: 2,
+        });
+        add_score_updates(&mut pairwise, "t", &ballot, 100);
+        assert_eq!(pairwise[&("t".to_string(), 1, 2)], (100, 1));
+
+        for ballot in scored_ballots() {
+            let mut updates = HashMap::new();
+            add_score_updates(&mut updates, "t", &ballot, 100);
+
+            let total: i32 = updates.values().map(|(total, _)| total).sum();
+            let games: i64 = updates.values().map(|(_, games)| games).sum();
+            assert_eq!(total, 100);
+            assert_eq!(games, ballot.score_pairs().len() as i64);
+        }
+    }
+
+    #[test]
+    fn test_cooccurrence_counts_skip_refused_ballots() {
+        let (ballots, _) = parse_ballots(
+            br#"[
+                {"topic_type":"setwise","info":{"topic_id":"t","ballot_id":"1-a","ip":"127.0.0.1","user_agent":"ua","timestamp":0},"left_set":[1,2],"right_set":[3],"selected_left":[1],"selected_right":[]},
+                {"topic_type":"pairwise","info":{"topic_id":"t","ballot_id":"1-b","ip":"127.0.0.1","user_agent":"ua","timestamp":0},"win":1,"lose":4}
+            ]"#,
+        )
+        .unwrap();
+        let mut ballots = ballots.into_iter();
+        let grouped = HashMap::from([(
+            "t".to_string(),
+            vec![
+                StoredBallot {
+                    ballot: ballots.next().unwrap(),
+                    multiplier: 100,
+                },
+                StoredBallot {
+                    ballot: ballots.next().unwrap(),
+                    multiplier: -1,
+                },
+            ],
+        )]);
+
+        assert_eq!(
+            cooccurrence_counts(&grouped),
+            HashMap::from([
+                (("t".to_string(), 1, 2), 1),
+                (("t".to_string(), 1, 3), 1),
+                (("t".to_string(), 2, 3), 1),
+            ])
+        );
+    }
+
+    /// Needs a local Redis: `cargo test -p nats-service pair_games -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_batch_update_scores_counts_pair_games() {
+        let client = redis::Client::open("redis://127.0.0.1:6379").unwrap();
+        let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+        let topic = TopicId::parse("pair-games-test").unwrap();
+        reset_topic_scores(&mut conn, &topic).await.unwrap();
+
+        let script = redis::Script::new(LUA_SCRIPT_BATCH_SCORE_UPDATE_SCRIPT);
+        let updates = HashMap::from([
+            ((topic.to_string(), 1002, 1001), (30, 3)),
+            ((topic.to_string(), 1001, 1002), (50, 1)),
+            ((topic.to_string(), 1001, 2001), (10, 1)),
+        ]);
+        let cooccurrences = HashMap::from([((topic.to_string(), 1001, 1002), 4)]);
+        batch_update_scores(updates, cooccurrences, None, &script, &mut conn)
+            .await
+            .unwrap();
+
+        let (matrix, counter, cooccur): (
+            HashMap<String, i64>,
+            HashMap<String, i64>,
+            HashMap<String, i64>,
+        ) = redis::pipe()
+            .hgetall(topic.op_matrix_key())
+            .hgetall(topic.op_counter_key())
+            .hgetall(topic.op_cooccur_key())
+            .query_async(&mut conn)
+            .await
+            .unwrap();
+        let valid_ballots: i64 = conn.get(topic.valid_ballots_count_key()).await.unwrap();
+        let pairs = Results1v1MatrixPair::from_hashes(&matrix, &counter, 10);
+        reset_topic_scores(&mut conn, &topic).await.unwrap();
+
+        assert_eq!(cooccur, HashMap::from([("1001:1002".to_string(), 4)]));
+        assert_eq!(valid_ballots, 5);
+        assert_eq!(pairs.len(), 2);
+        let pair = &pairs[0];
+        assert_eq!((pair.a, pair.b), (1001, 1002));
+        // the one heavy win of 1001 leads the net score, not the games
+        assert_eq!((pair.total, pair.net), (4, 20));
+        assert_eq!((pair.a_wins, pair.b_wins), (1, 3));
+        let pair = &pairs[1];
+        assert_eq!((pair.a, pair.b), (1001, 2001));
+        assert_eq!((pair.total, pair.net, pair.a_wins), (1, 10, 1));
+    }
+
+    #[test]
+    fn test_retries_keep_the_issued_operators() {
+        let validation_results = HashMap::from([
+            ("1-a".to_string(), Ok((1001, 1002))),
+            (
+                "1-b".to_string(),
+                Err(AppError::InvalidBallotCode("1-b".to_string())),
+            ),
+        ]);
+        let issued = issued_operators(&validation_results);
+        assert_eq!(issued, HashMap::from([("1-a".to_string(), (1001, 1002))]));
+
+        let (left, right) = issued["1-a"];
+        assert_eq!(parse_shown(&format!("{left},{right}")), Some((1001, 1002)));
+        assert_eq!(parse_shown("1001"), None);
+        assert_eq!(parse_shown("1001,x"), None);
+    }
+
+    #[test]
+    fn test_ballot_failures_are_counted_by_cause() {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+
+        metrics::with_local_recorder(&recorder, || {
+            assert!(!record_ballot_failure(&AppError::InvalidParticipants));
+            assert!(!record_ballot_failure(&AppError::InvalidBallotCode(
+                "1-a".to_string()
+            )));
+            assert!(record_ballot_failure(&AppError::Redis(
+                redis::RedisError::from((redis::ErrorKind::IoError, "connection reset"))
+            )));
+        });
+
+        let rendered = handle.render();
+        assert!(rendered.contains("ballots_invalid_total 2"), "{rendered}");
+        assert!(rendered.contains("ballots_retried_total 1"), "{rendered}");
+    }
+
+    #[test]
+    fn test_record_votes_saved_caps_topic_labels() {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let labels = LabelCap::new(1);
+
+        metrics::with_local_recorder(&recorder, || {
+            record_votes_saved(&HashMap::from([("a".to_string(), 2)]), &labels);
+            record_votes_saved(
+                &HashMap::from([("a".to_string(), 1), ("b".to_string(), 3)]),
+                &labels,
+            );
+        });
+
+        let rendered = handle.render();
+        assert!(
+            rendered.contains(r#"votes_saved_total{topic_id="a"} 3"#),
+            "{rendered}"
+        );
+        assert!(!rendered.contains(r#"topic_id="b""#), "{rendered}");
+        assert!(
+            rendered.contains("votes_saved_overflow_total 3"),
+            "{rendered}"
+        );
+    }
+
+    #[test]
+    fn test_topic_ip_limits_follow_overrides() {
+        let config = vote_config();
+        let cached = curated_topic("curated");
+
+        assert_eq!(
+            topic_ip_limits(Some(&cached), &config),
+            IpLimits {
+                max_ip_limit: 1,
+                base_multiplier: 100,
+                low_multiplier: 0,
+            }
+        );
+        assert_eq!(
+            topic_ip_limits(None, &config),
+            TopicVoteOverrides::default().resolve(&config)
+        );
+    }
+
+    /// Needs a local Redis: `cargo test -p nats-service single_ballot_multiplier -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_single_ballot_multiplier_uses_topic_limits() {
+        let client = redis::Client::open("redis://127.0.0.1:6379").unwrap();
+        let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+        let topic = TopicId::parse("single-multiplier-test").unwrap();
+        reset_topic_scores(&mut conn, &topic).await.unwrap();
+
+        let config = vote_config();
+        let cached = curated_topic(topic.as_str());
+        let (ballots, _) = parse_ballots(
+            PAIRWISE
+                .replace(
+                    r#""topic_id":"t""#,
+                    r#""topic_id":"single-multiplier-test""#,
+                )
+                .as_bytes(),
+        )
+        .unwrap();
+        let script = redis::Script::new(LUA_SCRIPT_IP_COUNTER);
+
+        let mut multipliers = Vec::new();
+        for _ in 0..2 {
+            multipliers.push(
+                calculate_multiplier(
+                    ballots[0].info(),
+                    topic_ip_limits(Some(&cached), &config),
+                    config.ip_counter_expire_seconds,
+                    &script,
+                    &mut conn,
+                )
+                .await
+                .unwrap(),
+            );
+        }
+        reset_topic_scores(&mut conn, &topic).await.unwrap();
+
+        // the global limit of 1000 would have weighed both at 100
+        assert_eq!(multipliers, [100, 0]);
+    }
+}
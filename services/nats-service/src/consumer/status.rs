@@ -0,0 +1,227 @@
+use std::{
+    fmt::Display,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsumerState {
+    Starting,
+    Running,
+    Restarting,
+    Stopped,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ConsumerStatus {
+    pub name: &'static str,
+    pub subject: String,
+    pub state: ConsumerState,
+    pub started_at: DateTime<Utc>,
+    /// Last iteration of the consumer loop, stale when the thread is stuck
+    /// or gone.
+    pub last_heartbeat: Option<DateTime<Utc>>,
+    /// Last time a non-empty batch was fetched.
+    pub last_message: Option<DateTime<Utc>>,
+    pub processed: u64,
+    pub errors: u64,
+    pub restarts: u64,
+    pub last_error: Option<String>,
+}
+
+/// Runtime state of every started consumer, shared with the admin endpoint.
+#[derive(Clone, Default)]
+pub struct ConsumerRegistry {
+    statuses: Arc<DashMap<&'static str, ConsumerStatus>>,
+    stopping: Arc<AtomicBool>,
+}
+
+impl ConsumerRegistry {
+    pub fn register(&self, name: &'static str, subject: &str) -> ConsumerHandle {
+        self.statuses.insert(
+            name,
+            ConsumerStatus {
+                name,
+                subject: subject.to_string(),
+                state: ConsumerState::Starting,
+                started_at: Utc::now(),
+                last_heartbeat: None,
+                last_message: None,
+                processed: 0,
+                errors: 0,
+                restarts: 0,
+                last_error: None,
+            },
+        );
+
+        ConsumerHandle {
+            name,
+            registry: self.clone(),
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<ConsumerStatus> {
+        let mut statuses: Vec<_> = self
+            .statuses
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        statuses.sort_unstable_by_key(|status| status.name);
+        statuses
+    }
+
+    /// Asks every consumer loop to return after its current batch.
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::Relaxed);
+    }
+
+    /// Waits until every consumer is stopped, checking every `poll`.
+    pub async fn stopped(&self, poll: Duration) {
+        while self
+            .statuses
+            .iter()
+            .any(|entry| entry.state != ConsumerState::Stopped)
+        {
+            tokio::time::sleep(poll).await;
+        }
+    }
+}
+
+/// Handle a consumer loop reports its progress through.
+#[derive(Clone)]
+pub struct ConsumerHandle {
+    name: &'static str,
+    registry: ConsumerRegistry,
+}
+
+impl ConsumerHandle {
+    fn update(&self, f: impl FnOnce(&mut ConsumerStatus)) {
+        if let Some(mut status) = self.registry.statuses.get_mut(self.name) {
+            f(&mut status);
+        }
+    }
+
+    pub fn heartbeat(&self) {
+        self.update(|status| {
+            status.state = ConsumerState::Running;
+            status.last_heartbeat = Some(Utc::now());
+        });
+    }
+
+    pub fn record_messages(&self, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        self.update(|status| {
+            status.last_message = Some(Utc::now());
+            status.processed += count as u64;
+        });
+    }
+
+    pub fn record_error(&self, error: &dyn Display) {
+        self.update(|status| {
+            status.errors += 1;
+            status.last_error = Some(error.to_string());
+        });
+    }
+
+    /// Records a failed run of the consumer loop that is about to be
+    /// restarted.
+    pub fn restarting(&self, error: &dyn Display) {
+        self.record_error(error);
+        self.update(|status| {
+            status.state = ConsumerState::Restarting;
+            status.restarts += 1;
+        });
+    }
+
+    /// Marks the consumer as stopped by the error that ended its loop.
+    pub fn failed(&self, error: &dyn Display) {
+        self.record_error(error);
+        self.update(|status| status.state = ConsumerState::Stopped);
+    }
+
+    /// Whether the service is shutting down, checked by the consumer loop
+    /// before each batch.
+    pub fn stop_requested(&self) -> bool {
+        self.registry.stopping.load(Ordering::Relaxed)
+    }
+
+    /// Marks the consumer as stopped after it returned on `stop_requested`.
+    pub fn stopped(&self) {
+        self.update(|status| status.state = ConsumerState::Stopped);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consumer_status_lifecycle() {
+        let registry = ConsumerRegistry::default();
+        let handle = registry.register("save_score", "ark-vote.save_score");
+
+        let status = &registry.snapshot()[0];
+        assert_eq!(status.state, ConsumerState::Starting);
+        assert!(status.last_heartbeat.is_none());
+
+        handle.heartbeat();
+        handle.record_messages(0);
+        let status = &registry.snapshot()[0];
+        assert_eq!(status.state, ConsumerState::Running);
+        assert!(status.last_heartbeat.is_some());
+        assert!(status.last_message.is_none());
+
+        handle.record_messages(3);
+        handle.record_error(&"redis timeout");
+        handle.failed(&"connection closed");
+        let status = &registry.snapshot()[0];
+        assert_eq!(status.state, ConsumerState::Stopped);
+        assert_eq!(status.processed, 3);
+        assert_eq!(status.errors, 2);
+        assert_eq!(status.last_error.as_deref(), Some("connection closed"));
+    }
+
+    #[test]
+    fn test_snapshot_is_sorted_by_name() {
+        let registry = ConsumerRegistry::default();
+        registry.register("save_score", "ark-vote.save_score");
+        registry.register("ballot_skip", "ark-vote.ballot_skip");
+        registry.register("dlq", "ark-vote.dlq");
+
+        let names: Vec<_> = registry.snapshot().iter().map(|s| s.name).collect();
+        assert_eq!(names, ["ballot_skip", "dlq", "save_score"]);
+    }
+
+    #[tokio::test]
+    async fn test_stop_waits_for_every_consumer() {
+        let registry = ConsumerRegistry::default();
+        let save_score = registry.register("save_score", "ark-vote.save_score");
+        let dlq = registry.register("dlq", "ark-vote.dlq");
+        assert!(!save_score.stop_requested());
+
+        registry.stop();
+        assert!(save_score.stop_requested() && dlq.stop_requested());
+
+        save_score.stopped();
+        let poll = Duration::from_millis(1);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), registry.stopped(poll))
+                .await
+                .is_err()
+        );
+
+        dlq.stopped();
+        registry.stopped(poll).await;
+    }
+}
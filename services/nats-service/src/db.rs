@@ -1,7 +1,12 @@
+use std::sync::Arc;
+
+use share::metrics::LabelCap;
+
+use crate::pool::CandidatePoolCache;
+
 #[derive(Clone)]
 pub struct RedisService {
     pub client: redis::Client,
-    pub score_update_script: redis::Script,
     pub ip_counter_script: redis::Script,
     pub batch_ip_counter_script: redis::Script,
     pub batch_score_update_script: redis::Script,
@@ -13,5 +18,10 @@ pub struct RedisService {
 pub struct AppDatabase {
     pub redis: RedisService,
     pub mongo_database: mongodb::Database,
+    pub nats_client: async_nats::Client,
     pub jetstream: async_nats::jetstream::Context,
+    pub candidate_pools: Arc<CandidatePoolCache>,
+    /// Topics labelled in `votes_saved_total`, shared by every consumer so a
+    /// restarted one does not admit new labels.
+    pub saved_topic_labels: Arc<LabelCap>,
 }
@@ -1,4 +1,5 @@
 use redis::RedisError;
+use share::models::database::TopicWindowState;
 
 #[derive(thiserror::Error, Debug)]
 pub enum AppError {
@@ -12,6 +13,14 @@ pub enum AppError {
     InvalidBallotFormat(String),
     #[error("invalid match participants")]
     InvalidParticipants,
+    #[error("participants not in candidate pool: {0} vs {1}")]
+    ParticipantNotInPool(i32, i32),
+    #[error("operator not in candidate pool: {0}")]
+    OperatorNotInPool(i32),
+    #[error("unknown topic: {0}")]
+    UnknownTopic(String),
+    #[error("topic {0} is not open: {1:?}")]
+    TopicNotOpen(String, TopicWindowState),
     #[error("jetStream error: {0}")]
     JetStream(#[from] async_nats::error::Error<async_nats::jetstream::context::PublishErrorKind>),
     #[error("serde JSON error: {0}")]
@@ -39,6 +48,10 @@ impl AppError {
         !matches!(
             self,
             AppError::InvalidParticipants
+                | AppError::ParticipantNotInPool(_, _)
+                | AppError::OperatorNotInPool(_)
+                | AppError::UnknownTopic(_)
+                | AppError::TopicNotOpen(_, _)
                 | AppError::InvalidBallotCode(_)
                 | AppError::InvalidBallotFormat(_)
         )
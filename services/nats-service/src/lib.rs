@@ -1,31 +1,43 @@
 use std::{borrow::Cow, sync::Arc};
 
+mod admin;
 mod constants;
 mod consumer;
 mod db;
 mod error;
+mod pool;
 
 use eyre::{Context, Result};
-use share::config::AppConfig;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use share::{
+    auth::AdminTokens,
+    bootstrap::{self, load_character_infos},
+    config::AppConfig,
+    metrics::LabelCap,
+    signal,
+};
 
 use crate::{
     constants::{
-        LUA_SCRIPT_BATCH_IP_COUNTER_SCRIPT, LUA_SCRIPT_BATCH_SCORE_UPDATE_SCRIPT,
-        LUA_SCRIPT_DEL_MUTIPLE, LUA_SCRIPT_GET_DEL_MANY, LUA_SCRIPT_IP_COUNTER,
-        LUA_SCRIPT_UPDATE_SCORES,
+        CONSUMER_STOP_POLL_INTERVAL, LUA_SCRIPT_BATCH_IP_COUNTER_SCRIPT,
+        LUA_SCRIPT_BATCH_SCORE_UPDATE_SCRIPT, LUA_SCRIPT_DEL_MUTIPLE, LUA_SCRIPT_GET_DEL_MANY,
+        LUA_SCRIPT_IP_COUNTER,
     },
-    consumer::available_consumers,
+    consumer::{ConsumerRegistry, available_consumers},
     db::{AppDatabase, RedisService},
+    pool::CandidatePoolCache,
 };
 
 pub struct NatsService {
     config: Arc<AppConfig>,
+    consumers: ConsumerRegistry,
 }
 
 impl NatsService {
     pub fn new(config: AppConfig) -> Self {
         Self {
             config: Arc::new(config),
+            consumers: ConsumerRegistry::default(),
         }
     }
 
@@ -34,6 +46,9 @@ impl NatsService {
 
         let stream = self.create_jetstream_setup(&database.jetstream).await?;
 
+        // before the consumers, whose metrics are dropped until the recorder exists
+        self.start_admin_server().await?;
+
         self.start_consumers(&stream, &database).await?;
 
         tracing::info!("nats service started successfully");
@@ -44,31 +59,42 @@ impl NatsService {
             .context("failed to receive shutdown signal")?;
 
         tracing::info!("shutting down nats consumer");
+        let timeout = self.config.shutdown.timeout();
+        self.consumers.stop();
+        signal::bounded(
+            "consumers",
+            timeout,
+            self.consumers.stopped(CONSUMER_STOP_POLL_INTERVAL),
+        )
+        .await;
+        if let Some(result) =
+            signal::bounded("nats drain", timeout, database.nats_client.drain()).await
+        {
+            result?;
+        }
+
         Ok(())
     }
 
     async fn setup_database(&self) -> Result<Arc<AppDatabase>> {
-        let nats_client = async_nats::connect(&self.config.nats.url)
-            .await
-            .context("failed to connect to nats")?;
+        let nats_client = bootstrap::connect_nats(&self.config.nats).await?;
+        let jetstream = async_nats::jetstream::new(nats_client.clone());
 
-        let jetstream = async_nats::jetstream::new(nats_client);
-
-        let database_config = &self.config.database;
-
-        let redis_client = redis::Client::open(&*database_config.redis_url)
-            .context("failed to create Redis client")?;
-
-        let mongodb_client = mongodb::Client::with_uri_str(&database_config.mongodb_url)
-            .await
-            .context("failed to connect to MongoDB")?;
+        let (redis_client, _) = bootstrap::connect_redis(&self.config.database).await?;
+        let mongo_database = bootstrap::connect_mongodb_database(&self.config.database).await?;
 
-        let mongo_database = mongodb_client.database(&database_config.mongodb_database);
+        let character_infos = load_character_infos().context("failed to load character table")?;
+        tracing::debug!("character infos loaded: {}", character_infos.len());
+        let candidate_pools = Arc::new(CandidatePoolCache::new(
+            &mongo_database,
+            character_infos,
+            self.config.vote.max_preset_depth,
+            self.config.nats.closed_topic_ballots,
+        ));
 
         Ok(Arc::new(AppDatabase {
             redis: RedisService {
                 client: redis_client,
-                score_update_script: redis::Script::new(LUA_SCRIPT_UPDATE_SCORES),
                 ip_counter_script: redis::Script::new(LUA_SCRIPT_IP_COUNTER),
                 batch_ip_counter_script: redis::Script::new(LUA_SCRIPT_BATCH_IP_COUNTER_SCRIPT),
                 batch_score_update_script: redis::Script::new(LUA_SCRIPT_BATCH_SCORE_UPDATE_SCRIPT),
@@ -76,7 +102,10 @@ impl NatsService {
                 del_multiple_script: redis::Script::new(LUA_SCRIPT_DEL_MUTIPLE),
             },
             mongo_database,
+            nats_client,
             jetstream,
+            candidate_pools,
+            saved_topic_labels: Arc::new(LabelCap::new(self.config.vote.throughput_metric_topics)),
         }))
     }
 
@@ -104,7 +133,8 @@ impl NatsService {
                         consumer.name,
                         subject
                     );
-                    (consumer.starter)(subject, stream, db, self.config.clone()).await?;
+                    let status = self.consumers.register(consumer.name, &subject);
+                    (consumer.starter)(subject, stream, db, self.config.clone(), status).await?;
                 }
                 None => {
                     tracing::warn!("no consumer found for: {}", config.name);
@@ -127,6 +157,34 @@ impl NatsService {
         Ok(())
     }
 
+    async fn start_admin_server(&self) -> Result<()> {
+        let Some(addr) = &self.config.nats.admin_listen else {
+            return Ok(());
+        };
+
+        let metrics = PrometheusBuilder::new()
+            .install_recorder()
+            .context("failed to install the metrics recorder")?;
+        let app = admin::admin_routes(
+            AdminTokens::from_config(&self.config.admin),
+            self.consumers.clone(),
+            metrics,
+        );
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .context("failed to bind nats admin listener")?;
+
+        tracing::info!("starting nats admin server on {}", addr);
+
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!("nats admin server failed: {}", e);
+            }
+        });
+
+        Ok(())
+    }
+
     async fn create_jetstream_setup(
         &self,
         jetstream: &async_nats::jetstream::Context,
@@ -0,0 +1,224 @@
+use std::{collections::HashSet, sync::Arc, time::Instant};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use mongodb::{Collection, bson::doc};
+use share::{
+    config::ClosedTopicBallots,
+    models::{
+        database::{Ballot, BallotInfo, PairwiseBallot, TopicWindowState, VotingTopic},
+        excel::CharacterInfo,
+    },
+};
+
+use crate::{constants::CANDIDATE_POOL_CACHE_TTL, error::AppError};
+
+#[derive(Clone)]
+pub struct CachedTopic {
+    pub topic: Arc<VotingTopic>,
+    pub pool: Arc<HashSet<i32>>,
+}
+
+struct PoolEntry {
+    cached: CachedTopic,
+    loaded_at: Instant,
+}
+
+/// Read-through cache of topic candidate pools, loaded from the `topics`
+/// collection on miss or once an entry is older than `CANDIDATE_POOL_CACHE_TTL`.
+pub struct CandidatePoolCache {
+    topic_collection: Collection<VotingTopic>,
+    character_infos: Vec<CharacterInfo>,
+    max_preset_depth: usize,
+    closed_topic_ballots: ClosedTopicBallots,
+    entries: DashMap<String, PoolEntry>,
+}
+
+impl CandidatePoolCache {
+    pub fn new(
+        mongo: &mongodb::Database,
+        character_infos: Vec<CharacterInfo>,
+        max_preset_depth: usize,
+        closed_topic_ballots: ClosedTopicBallots,
+    ) -> Self {
+        Self {
+            topic_collection: mongo.collection::<VotingTopic>("topics"),
+            character_infos,
+            max_preset_depth,
+            closed_topic_ballots,
+            entries: DashMap::new(),
+        }
+    }
+
+    pub async fn get(&self, topic_id: &str) -> Result<Option<CachedTopic>, AppError> {
+        if let Some(entry) = self.entries.get(topic_id)
+            && entry.loaded_at.elapsed() < CANDIDATE_POOL_CACHE_TTL
+        {
+            return Ok(Some(entry.cached.clone()));
+        }
+
+        let Some(topic) = self
+            .topic_collection
+            .find_one(doc! { "id": topic_id })
+            .await?
+        else {
+            self.entries.remove(topic_id);
+            return Ok(None);
+        };
+
+        let pool: Arc<HashSet<i32>> = Arc::new(
+            topic
+                .resolve_pool(&self.character_infos, self.max_preset_depth)
+                .into_iter()
+                .collect(),
+        );
+        tracing::debug!(
+            "loaded candidate pool for topic {}: {} operators",
+            topic_id,
+            pool.len()
+        );
+
+        let cached = CachedTopic {
+            topic: Arc::new(topic),
+            pool,
+        };
+        self.entries.insert(
+            topic_id.to_string(),
+            PoolEntry {
+                cached: cached.clone(),
+                loaded_at: Instant::now(),
+            },
+        );
+
+        Ok(Some(cached))
+    }
+
+    /// Checks that the ballot was cast while the topic was open, that the
+    /// topic has not closed since under `ClosedTopicBallots::Drop`, and that
+    /// both participants belong to the topic's candidate pool.
+    pub async fn check_ballot(&self, ballot: &PairwiseBallot<'_>) -> Result<(), AppError> {
+        let cached = self.open_topic(&ballot.info).await?;
+
+        if cached.pool.contains(&ballot.win) && cached.pool.contains(&ballot.lose) {
+            Ok(())
+        } else {
+            Err(AppError::ParticipantNotInPool(ballot.win, ballot.lose))
+        }
+    }
+
+    /// `check_ballot` for setwise, groupwise and plurality ballots, every
+    /// operator shown must belong to the candidate pool.
+    pub async fn check_scored_ballot(&self, ballot: &Ballot<'_>) -> Result<(), AppError> {
+        let cached = self.open_topic(ballot.info()).await?;
+
+        match first_outside_pool(ballot, &cached.pool) {
+            Some(id) => Err(AppError::OperatorNotInPool(id)),
+            None => Ok(()),
+        }
+    }
+
+    async fn open_topic(&self, info: &BallotInfo<'_>) -> Result<CachedTopic, AppError> {
+        let topic_id = info.topic_id.as_ref();
+        let Some(cached) = self.get(topic_id).await? else {
+            return Err(AppError::UnknownTopic(topic_id.to_string()));
+        };
+
+        let cast_at = DateTime::<Utc>::from_timestamp_millis(info.timestamp)
+            .ok_or_else(|| AppError::InvalidBallotFormat("invalid timestamp".to_string()))?;
+        if let Err(state) = check_window(
+            &cached.topic,
+            cast_at,
+            Utc::now(),
+            self.closed_topic_ballots,
+        ) {
+            return Err(AppError::TopicNotOpen(topic_id.to_string(), state));
+        }
+
+        Ok(cached)
+    }
+}
+
+fn first_outside_pool(ballot: &Ballot<'_>, pool: &HashSet<i32>) -> Option<i32> {
+    ballot.operators().into_iter().find(|id| !pool.contains(id))
+}
+
+/// Window state rejecting a ballot cast at `cast_at` and processed at `now`.
+fn check_window(
+    topic: &VotingTopic,
+    cast_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+    closed_topic_ballots: ClosedTopicBallots,
+) -> Result<(), TopicWindowState> {
+    match topic.window_state_at(cast_at) {
+        TopicWindowState::Open => {}
+        state => return Err(state),
+    }
+
+    match (closed_topic_ballots, topic.window_state_at(now)) {
+        (ClosedTopicBallots::Drop, TopicWindowState::Closed) => Err(TopicWindowState::Closed),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use share::{models::database::PluralityBallot, test_util::test_topic};
+
+    use super::*;
+
+    fn topic(open_time: DateTime<Utc>, close_time: DateTime<Utc>) -> VotingTopic {
+        test_topic("test_topic", open_time, close_time)
+    }
+
+    #[test]
+    fn test_check_window_closed_topic() {
+        let now = Utc::now();
+        let topic = topic(now - Duration::days(2), now - Duration::hours(1));
+        let cast_at = now - Duration::hours(2);
+
+        assert_eq!(
+            check_window(&topic, cast_at, now, ClosedTopicBallots::Apply),
+            Ok(())
+        );
+        assert_eq!(
+            check_window(&topic, cast_at, now, ClosedTopicBallots::Drop),
+            Err(TopicWindowState::Closed)
+        );
+        assert_eq!(
+            check_window(&topic, now, now, ClosedTopicBallots::Apply),
+            Err(TopicWindowState::Closed)
+        );
+    }
+
+    #[test]
+    fn test_first_outside_pool() {
+        let ballot = Ballot::Plurality(PluralityBallot {
+            info: BallotInfo {
+                topic_id: "test_topic".into(),
+                ballot_id: "1-abc".into(),
+                ip: "127.0.0.1".into(),
+                user_agent: "ua".into(),
+                timestamp: 0,
+                user_id: None,
+                throttled: false,
+            },
+            candidates: vec![3, 1, 2],
+            selected: 1,
+        });
+
+        assert_eq!(first_outside_pool(&ballot, &HashSet::from([1, 2, 3])), None);
+        assert_eq!(first_outside_pool(&ballot, &HashSet::from([1, 3])), Some(2));
+    }
+
+    #[test]
+    fn test_check_window_open_topic() {
+        let now = Utc::now();
+        let topic = topic(now - Duration::days(1), now + Duration::days(1));
+
+        assert_eq!(
+            check_window(&topic, now, now, ClosedTopicBallots::Drop),
+            Ok(())
+        );
+    }
+}
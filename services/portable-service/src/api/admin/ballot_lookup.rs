@@ -0,0 +1,46 @@
+use actix_web::{post, web};
+use mongodb::bson::doc;
+use share::models::{
+    api::{
+        ApiData, ApiMsg, ApiResponse, BallotLookupRequest, BallotLookupResponse, BallotRecord,
+        ResponseStatus,
+    },
+    database::StoredBallot,
+};
+
+use crate::{AppState, error::AppError};
+
+#[post("/ballot/lookup")]
+pub async fn ballot_lookup_fn(
+    state: web::Data<AppState>,
+    web::Json(req): web::Json<BallotLookupRequest>,
+) -> Result<ApiResponse<BallotLookupResponse>, AppError> {
+    if state
+        .topic_service
+        .get_topic(&req.topic_id)
+        .await?
+        .is_none()
+    {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    }
+
+    let ballot = state
+        .database
+        .mongo_read_database
+        .collection::<StoredBallot>(&format!("ballots_{}", req.topic_id))
+        .find_one(doc! { StoredBallot::BALLOT_ID_FIELD: &req.ballot_id })
+        .await?;
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(BallotLookupResponse {
+            found: ballot.is_some(),
+            ballot: ballot.as_ref().map(BallotRecord::from),
+        }),
+        message: ApiMsg::OK,
+    })
+}
@@ -0,0 +1,120 @@
+use actix_web::{
+    HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::AUTHORIZATION,
+    middleware::Next,
+    web,
+};
+use share::{
+    auth::{AdminAuthError, AdminTokens},
+    models::api::{ApiData, ApiMsg, ApiResponse, ResponseStatus},
+};
+
+use crate::error::http_status;
+
+mod ballot_lookup;
+mod sub_profession_blocklist;
+mod topic_freeze_pool;
+mod topic_info;
+mod topic_pause;
+mod topic_reopen;
+mod topic_reset_scores;
+mod topic_update;
+
+use ballot_lookup::ballot_lookup_fn;
+use sub_profession_blocklist::{sub_profession_blocklist_fn, sub_profession_blocklist_update_fn};
+use topic_freeze_pool::topic_freeze_pool_fn;
+use topic_info::topic_info_fn;
+use topic_pause::topic_pause_fn;
+use topic_reopen::topic_reopen_fn;
+use topic_reset_scores::topic_reset_scores_fn;
+use topic_update::topic_update_fn;
+
+pub async fn require_admin_token(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let authorization = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    let result = match req.app_data::<web::Data<AdminTokens>>() {
+        Some(admin_tokens) => admin_tokens.authorize(authorization),
+        None => Err(AdminAuthError::InvalidToken),
+    };
+
+    let (status, message) = match result {
+        Ok(()) => {
+            return next
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body);
+        }
+        Err(AdminAuthError::MissingToken) => (ResponseStatus::Unauthorized, ApiMsg::Unauthorized),
+        Err(AdminAuthError::InvalidToken) => (ResponseStatus::Forbidden, ApiMsg::EndpointForbidden),
+    };
+
+    let response = HttpResponse::build(http_status(status)).json(ApiResponse::<()> {
+        status,
+        data: ApiData::Empty,
+        message,
+    });
+
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+/// Routes mounted under the `/admin` scope, behind `require_admin_token`.
+pub fn admin_config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/ping", web::get().to(|| async { "pong" })) // 校验 admin token
+        .service(sub_profession_blocklist_fn)
+        .service(sub_profession_blocklist_update_fn)
+        .service(topic_freeze_pool_fn)
+        .service(topic_info_fn)
+        .service(topic_pause_fn)
+        .service(topic_reopen_fn)
+        .service(topic_reset_scores_fn)
+        .service(topic_update_fn)
+        .service(ballot_lookup_fn);
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http::StatusCode, middleware::from_fn, test};
+
+    use super::*;
+
+    async fn ping(authorization: Option<&str>) -> StatusCode {
+        let app = test::init_service(
+            App::new().service(
+                web::scope("/admin")
+                    .app_data(web::Data::new(AdminTokens::new(["secret"])))
+                    .wrap(from_fn(require_admin_token))
+                    .configure(admin_config),
+            ),
+        )
+        .await;
+
+        let mut req = test::TestRequest::get().uri("/admin/ping");
+        if let Some(authorization) = authorization {
+            req = req.insert_header((AUTHORIZATION, authorization));
+        }
+
+        test::call_service(&app, req.to_request()).await.status()
+    }
+
+    #[actix_web::test]
+    async fn test_admin_missing_token() {
+        assert_eq!(ping(None).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_admin_wrong_token() {
+        assert_eq!(ping(Some("Bearer wrong")).await, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_admin_correct_token() {
+        assert_eq!(ping(Some("Bearer secret")).await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,37 @@
+use actix_web::{get, post, web};
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, ResponseStatus, SubProfessionBlocklistPayload,
+};
+
+use crate::{AppState, error::AppError};
+
+#[get("/blocklist/sub_professions")]
+pub async fn sub_profession_blocklist_fn(
+    state: web::Data<AppState>,
+) -> Result<ApiResponse<SubProfessionBlocklistPayload>, AppError> {
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(SubProfessionBlocklistPayload {
+            sub_professions: state.topic_service.blocked_sub_professions(),
+        }),
+        message: ApiMsg::OK,
+    })
+}
+
+#[post("/blocklist/sub_professions")]
+pub async fn sub_profession_blocklist_update_fn(
+    state: web::Data<AppState>,
+    web::Json(req): web::Json<SubProfessionBlocklistPayload>,
+) -> Result<ApiResponse<SubProfessionBlocklistPayload>, AppError> {
+    state
+        .topic_service
+        .set_blocked_sub_professions(req.sub_professions);
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(SubProfessionBlocklistPayload {
+            sub_professions: state.topic_service.blocked_sub_professions(),
+        }),
+        message: ApiMsg::OK,
+    })
+}
@@ -0,0 +1,41 @@
+use actix_web::{post, web};
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicFreezePoolRequest, TopicFreezePoolResponse,
+};
+
+use crate::{AppState, error::AppError};
+
+#[post("/topic/freeze_pool")]
+pub async fn topic_freeze_pool_fn(
+    state: web::Data<AppState>,
+    web::Json(req): web::Json<TopicFreezePoolRequest>,
+) -> Result<ApiResponse<TopicFreezePoolResponse>, AppError> {
+    let Some(mut topic) = state.topic_service.get_topic(&req.topic_id).await? else {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    };
+
+    topic.freeze_pool(
+        &state.character_infos,
+        state.topic_service.max_preset_depth(),
+    );
+    let pool_size = topic.frozen_pool.as_ref().map_or(0, Vec::len);
+    state
+        .topic_service
+        .update_topic(topic, vec!["frozen_pool".to_string()])
+        .await?;
+    tracing::info!(
+        "Froze candidate pool of topic {}: {} operators",
+        req.topic_id,
+        pool_size
+    );
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(TopicFreezePoolResponse { pool_size }),
+        message: ApiMsg::OK,
+    })
+}
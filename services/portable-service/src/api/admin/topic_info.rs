@@ -0,0 +1,26 @@
+use actix_web::{post, web};
+use share::models::api::{
+    AdminTopicInfoResponse, ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicInfoRequest,
+};
+
+use crate::{AppState, error::AppError};
+
+#[post("/topic/info")]
+pub async fn topic_info_fn(
+    state: web::Data<AppState>,
+    web::Json(req): web::Json<TopicInfoRequest>,
+) -> Result<ApiResponse<AdminTopicInfoResponse>, AppError> {
+    let Some(topic) = state.topic_service.get_topic(&req.topic_id).await? else {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    };
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(topic.into()),
+        message: ApiMsg::OK,
+    })
+}
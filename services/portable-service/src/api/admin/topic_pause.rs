@@ -0,0 +1,39 @@
+use actix_web::{post, web};
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicInfoResponse, TopicPauseRequest,
+};
+
+use crate::{AppState, error::AppError};
+
+#[post("/topic/pause")]
+pub async fn topic_pause_fn(
+    state: web::Data<AppState>,
+    web::Json(req): web::Json<TopicPauseRequest>,
+) -> Result<ApiResponse<TopicInfoResponse>, AppError> {
+    let Some(mut topic) = state.topic_service.get_topic(&req.topic_id).await? else {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    };
+
+    if topic.paused != req.paused {
+        topic.paused = req.paused;
+        state
+            .topic_service
+            .update_topic(topic.clone(), vec!["paused".to_string()])
+            .await?;
+        tracing::info!(
+            "{} voting on topic {}",
+            if req.paused { "Paused" } else { "Resumed" },
+            req.topic_id
+        );
+    }
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(topic.into()),
+        message: ApiMsg::OK,
+    })
+}
@@ -0,0 +1,47 @@
+use actix_web::{post, web};
+use chrono::Utc;
+use share::models::api::{ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicReopenRequest};
+
+use crate::{AppState, error::AppError};
+
+#[post("/topic/reopen")]
+pub async fn topic_reopen_fn(
+    state: web::Data<AppState>,
+    web::Json(req): web::Json<TopicReopenRequest>,
+) -> Result<ApiResponse<ApiData<String>>, AppError> {
+    let Some(previous) = state.topic_service.get_topic(&req.topic_id).await? else {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    };
+
+    let mut reopened = previous.clone();
+    if let Err(err) = reopened.reopen(req.open_time, req.close_time, Utc::now()) {
+        tracing::debug!("Rejected reopen of topic {}: {:?}", req.topic_id, err);
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: err.into(),
+        });
+    }
+    if state.vote.freeze_candidate_pools && reopened.frozen_pool.is_none() {
+        reopened.freeze_pool(
+            &state.character_infos,
+            state.topic_service.max_preset_depth(),
+        );
+    }
+
+    state
+        .topic_service
+        .reopen_topic(&previous, reopened)
+        .await?;
+    tracing::info!("Reopened topic {} until {}", req.topic_id, req.close_time);
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Empty,
+        message: ApiMsg::OK,
+    })
+}
@@ -0,0 +1,75 @@
+use actix_web::{post, web};
+use mongodb::bson::{Document, doc};
+use share::{
+    models::api::{
+        ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicResetScoresRequest,
+        TopicResetScoresResponse,
+    },
+    scores::reset_topic_scores,
+};
+
+use crate::{AppState, error::AppError, state::ResultsType};
+
+#[post("/topic/reset_scores")]
+pub async fn topic_reset_scores_fn(
+    state: web::Data<AppState>,
+    web::Json(req): web::Json<TopicResetScoresRequest>,
+) -> Result<ApiResponse<TopicResetScoresResponse>, AppError> {
+    if req.confirm != req.topic_id {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::ResetConfirmationMismatch,
+        });
+    }
+
+    if state
+        .topic_service
+        .get_topic(&req.topic_id)
+        .await?
+        .is_none()
+    {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    }
+
+    let mut conn = state.database.redis.connection.clone();
+    let keys_removed = reset_topic_scores(&mut conn, &req.topic_id).await?;
+
+    let ballots_removed = if req.drop_ballots {
+        let collection = state
+            .database
+            .mongo_database
+            .collection::<Document>(&format!("ballots_{}", req.topic_id));
+        // the count is of the very documents removed, unlike a count before a drop
+        collection.delete_many(doc! {}).await?.deleted_count
+    } else {
+        0
+    };
+
+    for results_type in ResultsType::ALL {
+        state
+            .results_cache_store
+            .invalidate(&(req.topic_id.to_string(), results_type))
+            .await;
+    }
+
+    tracing::warn!(
+        "Reset scores of topic {}: {} keys, {} ballots removed",
+        req.topic_id,
+        keys_removed,
+        ballots_removed
+    );
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(TopicResetScoresResponse {
+            keys_removed,
+            ballots_removed,
+        }),
+        message: ApiMsg::OK,
+    })
+}
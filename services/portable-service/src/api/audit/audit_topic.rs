@@ -1,5 +1,5 @@
 use actix_web::{post, web};
-use share::models::api::{ApiData, ApiMsg, ApiResponse, AuditTopicRequest};
+use share::models::api::{ApiData, ApiMsg, ApiResponse, AuditTopicRequest, ResponseStatus};
 
 use crate::{AppState, error::AppError};
 
@@ -7,13 +7,13 @@ use crate::{AppState, error::AppError};
 pub async fn audit_topic_fn(
     state: web::Data<AppState>,
     web::Json(req): web::Json<AuditTopicRequest>,
-) -> Result<web::Json<ApiResponse<ApiData<String>>>, AppError> {
+) -> Result<ApiResponse<ApiData<String>>, AppError> {
     if true {
-        return Ok(web::Json(ApiResponse {
-            status: 403,
+        return Ok(ApiResponse {
+            status: ResponseStatus::Forbidden,
             data: ApiData::Empty,
             message: ApiMsg::EndpointForbidden,
-        }));
+        });
     }
 
     let topic_id = req.topic_id;
@@ -22,9 +22,9 @@ pub async fn audit_topic_fn(
         .audit_topic(&topic_id, req.audit_info)
         .await?;
 
-    Ok(web::Json(ApiResponse {
-        status: 0,
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
         data: ApiData::Empty,
         message: ApiMsg::OK,
-    }))
+    })
 }
@@ -1,27 +1,27 @@
 use actix_web::{post, web};
-use share::models::api::{ApiData, ApiMsg, ApiResponse, AuditTopicsListResponse};
+use share::models::api::{ApiData, ApiMsg, ApiResponse, AuditTopicsListResponse, ResponseStatus};
 
 use crate::{AppState, error::AppError};
 
 #[post("/audit/need_audit_topics")]
 pub async fn audit_topics_list_fn(
     state: web::Data<AppState>,
-) -> Result<web::Json<ApiResponse<AuditTopicsListResponse>>, AppError> {
+) -> Result<ApiResponse<AuditTopicsListResponse>, AppError> {
     if true {
-        return Ok(web::Json(ApiResponse {
-            status: 403,
+        return Ok(ApiResponse {
+            status: ResponseStatus::Forbidden,
             data: ApiData::Empty,
             message: ApiMsg::EndpointForbidden,
-        }));
+        });
     }
 
     let audit_topics = state.topic_service.get_need_audit_topics().await?;
 
-    Ok(web::Json(ApiResponse {
-        status: 0,
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
         data: ApiData::Data(AuditTopicsListResponse {
             topics: audit_topics,
         }),
         message: ApiMsg::OK,
-    }))
+    })
 }
@@ -1,11 +1,17 @@
 use actix_web::{Responder, post, web};
-use rand::{Rng, distr::Alphanumeric, seq::IndexedRandom as _};
-use share::models::{
-    api::{ApiData, ApiMsg, ApiResponse, BallotCreateRequest, BallotCreateResponse},
-    database::VotingTopicType,
+use rand::{Rng, RngCore, distr::Alphanumeric};
+use share::{
+    models::{
+        api::{
+            ApiData, ApiMsg, ApiResponse, BallotCreateRequest, BallotCreateResponse, ResponseStatus,
+        },
+        database::VotingTopicType,
+        excel::CharacterInfo,
+    },
+    selection::{PairingConstraint, SelectionContext, select_pair},
 };
 
-use crate::{AppState, constants::BALLOT_CODE_RANDOM_LENGTH, error::AppError};
+use crate::{AppState, error::AppError};
 
 fn generate_random_string(length: usize) -> String {
     rand::rng()
@@ -15,15 +21,15 @@ fn generate_random_string(length: usize) -> String {
         .collect()
 }
 
-fn select_operators(operator_ids: &[i32]) -> Result<(i32, i32), AppError> {
-    if operator_ids.len() < 2 {
-        return Err(AppError::InsufficientOperators);
-    }
-
-    let mut rng = rand::rng();
-    let selected: [i32; 2] = operator_ids.choose_multiple_array(&mut rng).unwrap();
-
-    Ok((selected[0], selected[1]))
+fn select_operators(
+    operator_ids: &[i32],
+    character_infos: &[CharacterInfo],
+    constraint: PairingConstraint,
+    context: &SelectionContext<'_>,
+    rng: &mut dyn RngCore,
+) -> Result<(i32, i32), AppError> {
+    select_pair(operator_ids, character_infos, constraint, context, rng)
+        .ok_or(AppError::InsufficientOperators)
 }
 
 #[post("/ballot/new")]
@@ -31,78 +37,147 @@ pub async fn ballot_create_fn(
     state: web::Data<AppState>,
     web::Json(req): web::Json<BallotCreateRequest>,
 ) -> actix_web::Result<impl Responder> {
+    if let Some(id) = req.invalid_operator_id() {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::InvalidOperatorId(id),
+        });
+    }
+
     let topic = match state.topic_service.get_topic(&req.topic_id).await {
-        Ok(Some(topic)) if topic.is_topic_active() => topic,
+        Ok(Some(topic)) => topic,
         Ok(None) => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotFound,
-            }));
-        }
-        Ok(_) => {
-            return Ok(web::Json(ApiResponse {
-                status: 500,
-                data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotActive,
-            }));
+            });
         }
-        Err(_) => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InternalError,
+            });
         }
     };
+    if let Some(disabled) =
+        ApiResponse::topic_type_disabled(&state.vote.enabled_topic_types, &topic.topic_type)
+    {
+        return Ok(disabled);
+    }
+    if let Some(refused) = ApiResponse::ballots_refused(&topic) {
+        return Ok(refused);
+    }
 
     let candidate_pool = match state
         .topic_service
         .get_candidate_pool(&topic.id, &state.character_infos)
         .await
     {
-        Some(pool) => pool,
-        None => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+        Ok(pool) => pool,
+        Err(err) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: err.into(),
+            });
         }
     };
+    if let Err(err) = topic
+        .topic_type
+        .check_operator_count(candidate_pool.len(), &state.vote.ballot_size_limits)
+    {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: err.into(),
+        });
+    }
 
     match topic.topic_type {
         VotingTopicType::Pairwise => {
-            let (left, right) = select_operators(&candidate_pool)?;
+            let games = match state.vote.pairing_constraint {
+                PairingConstraint::FavorUndersampled => Some(
+                    state
+                        .operator_games
+                        .get(
+                            state.database.redis.connection.clone(),
+                            &req.topic_id,
+                            &candidate_pool,
+                            state.vote.undersampled_bias,
+                        )
+                        .await,
+                ),
+                _ => None,
+            };
+            let shown = match state.vote.pairing_constraint {
+                PairingConstraint::AvoidRecentlyShown => {
+                    state
+                        .vote
+                        .recently_shown
+                        .load(state.database.redis.connection.clone(), &topic.id)
+                        .await
+                }
+                _ => Vec::new(),
+            };
+            let seed = state.vote.selection_seed(&topic.vote_overrides);
+            let (left, right) = state.selection_rngs.with_rng(&topic.id, seed, |rng| {
+                select_operators(
+                    &candidate_pool,
+                    &state.character_infos,
+                    state.vote.pairing_constraint,
+                    &SelectionContext {
+                        recent_winners: req.recent_winners(),
+                        recently_shown: &shown,
+                        games: games.as_deref(),
+                    },
+                    rng,
+                )
+            })?;
+            if state.vote.pairing_constraint == PairingConstraint::AvoidRecentlyShown {
+                state.vote.recently_shown.record(
+                    state.database.redis.connection.clone(),
+                    &topic.id,
+                    [left, right],
+                );
+            }
 
-            let id = state.snowflake.next_id().unwrap();
-            let random_string = generate_random_string(BALLOT_CODE_RANDOM_LENGTH);
+            let id = state.snowflake.next_id().map_err(AppError::from)?;
+            let random_string =
+                generate_random_string(state.vote.ballot_code_length(&topic.vote_overrides));
             let ballot_id = format!("{id}-{random_string}");
 
-            let ballot_key = format!("{}:ballot:{ballot_id}", topic.id);
+            let ballot_key = req.topic_id.ballot_key(&ballot_id);
 
             state
                 .ballot_cache_store
                 .insert(ballot_key, (left, right))
                 .await;
 
-            let rsp = BallotCreateResponse::Pairwise {
+            let mut rsp = BallotCreateResponse::Pairwise {
                 topic_id: topic.id,
                 ballot_id,
                 left,
                 right,
+                portraits: None,
             };
+            if req.include_portraits {
+                rsp.embed_portraits(&state.character_portraits.load());
+            }
 
-            Ok(web::Json(ApiResponse {
-                status: 0,
+            Ok(ApiResponse {
+                status: ResponseStatus::Ok,
                 data: ApiData::Data(rsp),
                 message: ApiMsg::OK,
-            }))
+            })
         }
-        _ => Ok(web::Json(ApiResponse {
-            status: 1,
+        _ => Ok(ApiResponse {
+            status: ResponseStatus::Unsupported,
             data: ApiData::Empty,
             message: ApiMsg::UnsupportedTopicType,
-        })),
+        }),
     }
 }
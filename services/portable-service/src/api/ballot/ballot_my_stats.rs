@@ -0,0 +1,52 @@
+use actix_web::{Responder, dev::ConnectionInfo, post, web};
+use redis::AsyncCommands as _;
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, BallotMyStatsRequest, BallotMyStatsResponse, ResponseStatus,
+};
+
+use crate::{AppState, error::AppError};
+
+#[post("/ballot/my_stats")]
+pub async fn ballot_my_stats_fn(
+    state: web::Data<AppState>,
+    conn: ConnectionInfo,
+    web::Json(req): web::Json<BallotMyStatsRequest>,
+) -> actix_web::Result<impl Responder> {
+    let topic = match state.topic_service.get_topic(&req.topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
+                data: ApiData::Empty,
+                message: ApiMsg::TargetTopicNotFound,
+            });
+        }
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
+                data: ApiData::Empty,
+                message: ApiMsg::InternalError,
+            });
+        }
+    };
+
+    let realip_remote_addr = conn.realip_remote_addr().unwrap_or("unknown");
+    let counter_key = req.topic_id.ip_counter_key(realip_remote_addr);
+
+    let mut redis_conn = state.database.redis.connection.clone();
+    let vote_count: Option<i64> = redis_conn.get(&counter_key).await.map_err(AppError::from)?;
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(BallotMyStatsResponse::new(
+            topic.id,
+            vote_count.unwrap_or(0),
+            topic
+                .vote_overrides
+                .max_ip_limit
+                .unwrap_or(state.vote.max_ip_limit),
+        )),
+        message: ApiMsg::OK,
+    })
+}
@@ -1,7 +1,12 @@
 use actix_web::{HttpRequest, Responder, dev::ConnectionInfo, post, web};
-use share::models::{
-    api::{ApiData, ApiMsg, ApiResponse, BallotSaveRequest, BallotSaveResponse, PairwiseSaveScore},
-    database::{Ballot, BallotInfo, PairwiseBallot},
+use share::{
+    config::SaveThrottleMode,
+    models::api::{
+        ApiData, ApiMsg, ApiResponse, BallotSaveRequest, BallotSaveResponse, PairwiseSaveScore,
+        ResponseStatus,
+    },
+    reputation::{USER_TOKEN_HEADER, authenticate_user},
+    throttle::is_save_throttled,
 };
 
 use crate::AppState;
@@ -13,69 +18,108 @@ pub async fn ballot_save_fn(
     req2: HttpRequest,
     web::Json(req): web::Json<BallotSaveRequest>,
 ) -> actix_web::Result<impl Responder> {
-    let _target_topic = match state.topic_service.get_topic(req.topic_id()).await {
-        Ok(Some(topic)) if topic.is_topic_active() && topic.topic_type.matches_request(&req) => {
-            topic
-        }
+    let target_topic = match state.topic_service.get_topic(req.topic_id()).await {
         Ok(Some(topic)) if !topic.topic_type.matches_request(&req) => {
             tracing::error!(
                 "Request topic type mismatch: expected {:?}, got {:?}",
                 topic.topic_type,
                 req
             );
-            return Ok(web::Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::BadRequest,
                 data: ApiData::Empty,
                 message: ApiMsg::RequestTopicTypeMismatch,
-            }));
-        }
-        Ok(Some(topic)) if !topic.is_topic_active() => {
-            tracing::error!("Target topic is not active: {:?}", topic);
-            return Ok(web::Json(ApiResponse {
-                status: 500,
-                data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotActive,
-            }));
+            });
         }
+        Ok(Some(topic)) => topic,
         Ok(None) => {
-            tracing::error!("Target topic not found: {:?}", req.topic_id());
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+            tracing::error!("Target topic not found: {}", req.topic_id());
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotFound,
-            }));
+            });
         }
-        Ok(Some(_)) => {
-            tracing::error!(
-                "Unexpected error while fetching topic: {:?}",
-                req.topic_id()
-            );
-            return Ok(web::Json(ApiResponse {
-                status: 500,
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id(), e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
                 message: ApiMsg::InternalError,
-            }));
+            });
         }
-        Err(_) => {
-            tracing::error!("Target topic not found: {:?}", req.topic_id());
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+    };
+    if let Some(disabled) =
+        ApiResponse::topic_type_disabled(&state.vote.enabled_topic_types, &target_topic.topic_type)
+    {
+        return Ok(disabled);
+    }
+    if let Some(refused) = ApiResponse::ballots_refused(&target_topic) {
+        tracing::debug!("Target topic does not accept ballots: {}", target_topic.id);
+        return Ok(refused);
+    }
+    if let Some(unsupported) = ApiResponse::ballot_save_unsupported(&req) {
+        return Ok(unsupported);
+    }
+
+    if let Err(limit) = req.check_size_limits(&state.vote.ballot_size_limits) {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::BallotSetTooLarge(limit),
+        });
+    }
+
+    if let Some(id) = req.invalid_operator_id() {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::InvalidOperatorId(id),
+        });
+    }
+
+    let user_token = req2
+        .headers()
+        .get(USER_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok());
+    let user_id = match authenticate_user(state.vote.user_tokens.as_ref(), user_token) {
+        Ok(user_id) => user_id,
+        Err(e) => {
+            tracing::debug!("Rejected user token: {}", e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::Unauthorized,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InvalidUserToken,
+            });
         }
     };
 
-    let ballot_key = format!("{}:ballot:{}", req.topic_id(), req.ballot_id());
+    // checked before the code is taken out of the cache, so a rejected save
+    // can be retried
+    let now = chrono::Utc::now().timestamp_millis();
+    let too_soon =
+        state
+            .vote
+            .ballot_grace
+            .is_too_soon(&state.snowflake, req.ballot_id(), now as u64);
+    if too_soon && state.vote.ballot_grace.mode == SaveThrottleMode::Reject {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::BallotSavedTooSoon,
+        });
+    }
+
+    let ballot_key = req.topic_id().ballot_key(req.ballot_id());
     let store_value = match state.ballot_cache_store.remove(&ballot_key).await {
         Some(v) => v,
         None => {
             tracing::error!("Ballot not found in cache: {}", ballot_key);
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
                 message: ApiMsg::BallotNotFound,
-            }));
+            });
         }
     };
 
@@ -88,72 +132,66 @@ pub async fn ballot_save_fn(
         .unwrap_or("unknown")
         .to_string();
 
-    match req {
-        BallotSaveRequest::Pairwise(PairwiseSaveScore {
-            topic_id,
-            ballot_id,
-            winner,
-            loser,
-        }) => {
-            if winner == loser {
-                tracing::error!(
-                    "Winner and loser cannot be the same: winner={}, loser={}",
-                    winner,
-                    loser
-                );
-                return Ok(web::Json(ApiResponse {
-                    status: 400,
-                    data: ApiData::Empty,
-                    message: ApiMsg::BallotWinnerCannotBeLoser,
-                }));
-            }
-
-            let (ballot_left, ballot_right) = (store_value.0, store_value.1);
-
-            let valid_ids = [ballot_left, ballot_right];
-            if !valid_ids.contains(&winner) || !valid_ids.contains(&loser) {
-                tracing::error!("Invalid winner or loser ID: {} vs {}", winner, loser);
-                return Ok(web::Json(ApiResponse {
-                    status: 400,
-                    data: ApiData::Empty,
-                    message: ApiMsg::InvalidBallotCode(format!(
-                        "Invalid winner or loser ID: {} vs {}",
-                        winner, loser
-                    )),
-                }));
-            }
-
-            let ballot = Ballot::Pairwise(PairwiseBallot {
-                info: BallotInfo {
-                    topic_id: topic_id.into(),
-                    ballot_id: ballot_id.into(),
-                    ip: realip_remote_addr.into(),
-                    user_agent: user_agent.into(),
-                    timestamp: chrono::Utc::now().timestamp_millis(),
-                },
-                win: winner,
-                lose: loser,
+    if let BallotSaveRequest::Pairwise(PairwiseSaveScore { winner, loser, .. }) = &req {
+        if winner == loser {
+            tracing::error!(
+                "Winner and loser cannot be the same: winner={}, loser={}",
+                winner,
+                loser
+            );
+            return Ok(ApiResponse {
+                status: ResponseStatus::BadRequest,
+                data: ApiData::Empty,
+                message: ApiMsg::BallotWinnerCannotBeLoser,
             });
+        }
 
-            if let Err(e) = state.ballot_processor.submit_ballot(ballot) {
-                tracing::error!("Failed to submit ballot to processor: {}", e);
-                return Ok(web::Json(ApiResponse {
-                    status: 500,
-                    data: ApiData::Empty,
-                    message: ApiMsg::InternalError,
-                }));
-            }
-
-            Ok(web::Json(ApiResponse {
-                status: 0,
-                data: ApiData::Data(BallotSaveResponse { code: 0 }),
-                message: ApiMsg::OK,
-            }))
+        let (ballot_left, ballot_right) = (store_value.0, store_value.1);
+
+        let valid_ids = [ballot_left, ballot_right];
+        if !valid_ids.contains(winner) || !valid_ids.contains(loser) {
+            tracing::error!("Invalid winner or loser ID: {} vs {}", winner, loser);
+            return Ok(ApiResponse {
+                status: ResponseStatus::BadRequest,
+                data: ApiData::Empty,
+                message: ApiMsg::InvalidBallotCode(format!(
+                    "Invalid winner or loser ID: {} vs {}",
+                    winner, loser
+                )),
+            });
         }
-        _ => Ok(web::Json(ApiResponse {
-            status: 400,
+    }
+
+    let throttled = is_save_throttled(
+        state.database.redis.connection.clone(),
+        &state.vote.save_throttle,
+        &realip_remote_addr,
+    )
+    .await;
+    if throttled && state.vote.save_throttle.mode == SaveThrottleMode::Reject {
+        return Ok(ApiResponse {
+            status: ResponseStatus::TooManyRequests,
+            data: ApiData::Empty,
+            message: ApiMsg::RateLimited,
+        });
+    }
+
+    let mut ballot = req.into_ballot(realip_remote_addr, user_agent, now);
+    ballot.info_mut().user_id = user_id.map(Into::into);
+    ballot.info_mut().throttled = throttled || too_soon;
+
+    if let Err(e) = state.ballot_processor.submit_ballot(ballot) {
+        tracing::error!("Failed to submit ballot to processor: {}", e);
+        return Ok(ApiResponse {
+            status: ResponseStatus::InternalError,
             data: ApiData::Empty,
             message: ApiMsg::InternalError,
-        })),
+        });
     }
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(BallotSaveResponse { code: 0 }),
+        message: ApiMsg::OK,
+    })
 }
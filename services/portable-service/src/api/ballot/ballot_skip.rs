@@ -1,5 +1,7 @@
 use actix_web::{Responder, post, web};
-use share::models::api::{ApiData, ApiMsg, ApiResponse, BallotSkipRequest, BallotSkipResponse};
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, BallotSkipRequest, BallotSkipResponse, ResponseStatus,
+};
 
 use crate::AppState;
 
@@ -11,36 +13,37 @@ pub async fn ballot_skip_fn(
     let _ = match state.topic_service.get_topic(&req.topic_id).await {
         Ok(Some(topic)) if topic.is_topic_active() => topic,
         Ok(None) => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotFound,
-            }));
+            });
         }
         Ok(_) => {
-            return Ok(web::Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::Forbidden,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotActive,
-            }));
+            });
         }
-        Err(_) => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InternalError,
+            });
         }
     };
 
     state
         .ballot_cache_store
-        .remove(&format!("{}:ballot:{}", req.topic_id, req.ballot_id))
+        .remove(&req.topic_id.ballot_key(&req.ballot_id))
         .await;
 
-    Ok(web::Json(ApiResponse {
-        status: 200,
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
         data: ApiData::Data(BallotSkipResponse { code: 0 }),
         message: ApiMsg::OK,
-    }))
+    })
 }
@@ -0,0 +1,27 @@
+use std::convert::Infallible;
+
+use actix_web::{Responder, post, web};
+use share::models::api::{BallotSkipBatchResponse, BallotSkipRequest};
+
+use crate::AppState;
+
+#[post("/ballot/skip_batch")]
+pub async fn ballot_skip_batch_fn(
+    state: web::Data<AppState>,
+    web::Json(reqs): web::Json<Vec<BallotSkipRequest>>,
+) -> actix_web::Result<impl Responder> {
+    let state = &state;
+    // removing a ballot that already expired from the cache is a no-op
+    Ok(BallotSkipBatchResponse::respond(
+        reqs,
+        |topic_id| async move { state.topic_service.get_topic(&topic_id).await },
+        |req| async move {
+            state
+                .ballot_cache_store
+                .invalidate(&req.topic_id.ballot_key(&req.ballot_id))
+                .await;
+            Ok::<_, Infallible>(())
+        },
+    )
+    .await)
+}
@@ -1,11 +1,12 @@
 use actix_web::{Responder, get, web};
 use rand::{Rng, distr::Alphanumeric, seq::IndexedRandom as _};
 use share::models::{
-    api::{ApiData, ApiMsg, ApiResponse, BallotCreateResponse},
+    api::{ApiData, ApiMsg, ApiResponse, BallotCreateResponse, ResponseStatus},
     database::VotingTopicType,
+    topic_id::TopicId,
 };
 
-use crate::{AppState, constants::BALLOT_CODE_RANDOM_LENGTH, error::AppError};
+use crate::{AppState, error::AppError};
 
 fn generate_random_string(length: usize) -> String {
     rand::rng()
@@ -37,40 +38,48 @@ pub async fn bench_ballot_create_fn(
     {
         Ok(Some(topic)) if topic.is_topic_active() => topic,
         Ok(None) => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotFound,
-            }));
+            });
         }
         Ok(_) => {
-            return Ok(web::Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::Forbidden,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotActive,
-            }));
+            });
         }
-        Err(_) => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+        Err(e) => {
+            tracing::error!("Failed to load topic crisis_v2_season_4_1_benchtest: {}", e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InternalError,
+            });
         }
     };
+    let Ok(topic_id) = TopicId::parse(topic.id.as_str()) else {
+        return Ok(ApiResponse {
+            status: ResponseStatus::InternalError,
+            data: ApiData::Empty,
+            message: ApiMsg::InternalError,
+        });
+    };
 
     let candidate_pool = match state
         .topic_service
         .get_candidate_pool(&topic.id, &state.character_infos)
         .await
     {
-        Some(pool) => pool,
-        None => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+        Ok(pool) => pool,
+        Err(err) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: err.into(),
+            });
         }
     };
 
@@ -78,11 +87,12 @@ pub async fn bench_ballot_create_fn(
         VotingTopicType::Pairwise => {
             let (left, right) = select_operators(&candidate_pool)?;
 
-            let id = state.snowflake.next_id().unwrap();
-            let random_string = generate_random_string(BALLOT_CODE_RANDOM_LENGTH);
+            let id = state.snowflake.next_id().map_err(AppError::from)?;
+            let random_string =
+                generate_random_string(state.vote.ballot_code_length(&topic.vote_overrides));
             let ballot_id = format!("{id}-{random_string}");
 
-            let ballot_key = format!("{}:ballot:{ballot_id}", topic.id);
+            let ballot_key = topic_id.ballot_key(&ballot_id);
 
             state
                 .ballot_cache_store
@@ -94,18 +104,19 @@ pub async fn bench_ballot_create_fn(
                 ballot_id,
                 left,
                 right,
+                portraits: None,
             };
 
-            Ok(web::Json(ApiResponse {
-                status: 0,
+            Ok(ApiResponse {
+                status: ResponseStatus::Ok,
                 data: ApiData::Data(rsp),
                 message: ApiMsg::OK,
-            }))
+            })
         }
-        _ => Ok(web::Json(ApiResponse {
-            status: 1,
+        _ => Ok(ApiResponse {
+            status: ResponseStatus::Unsupported,
             data: ApiData::Empty,
             message: ApiMsg::UnsupportedTopicType,
-        })),
+        }),
     }
 }
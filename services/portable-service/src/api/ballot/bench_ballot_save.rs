@@ -1,6 +1,6 @@
 use actix_web::{HttpRequest, Responder, dev::ConnectionInfo, get, web};
 use share::models::{
-    api::{ApiData, ApiMsg, ApiResponse, BallotSaveResponse},
+    api::{ApiData, ApiMsg, ApiResponse, BallotSaveResponse, ResponseStatus},
     database::{Ballot, BallotInfo, PairwiseBallot},
 };
 
@@ -19,32 +19,33 @@ pub async fn bench_ballot_save_fn(
     {
         Ok(Some(topic)) if topic.is_topic_active() => topic,
         Ok(Some(topic)) if !topic.is_topic_active() => {
-            return Ok(web::Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::Forbidden,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotActive,
-            }));
+            });
         }
         Ok(None) => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotFound,
-            }));
+            });
         }
         Ok(Some(_)) => {
-            return Ok(web::Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
                 message: ApiMsg::InternalError,
-            }));
+            });
         }
-        Err(_) => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+        Err(e) => {
+            tracing::error!("Failed to load topic crisis_v2_season_4_1_benchtest: {}", e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InternalError,
+            });
         }
     };
 
@@ -54,11 +55,11 @@ pub async fn bench_ballot_save_fn(
             (key, value)
         }
         None => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
                 message: ApiMsg::BallotNotFound,
-            }));
+            });
         }
     };
 
@@ -79,6 +80,8 @@ pub async fn bench_ballot_save_fn(
             ip: realip_remote_addr.into(),
             user_agent: user_agent.into(),
             timestamp: chrono::Utc::now().timestamp_millis(),
+            user_id: None,
+            throttled: false,
         },
         win: store_value.0,
         lose: store_value.1,
@@ -86,16 +89,16 @@ pub async fn bench_ballot_save_fn(
 
     if let Err(e) = state.ballot_processor.submit_ballot(ballot) {
         tracing::error!("Failed to submit ballot to processor: {}", e);
-        return Ok(web::Json(ApiResponse {
-            status: 500,
+        return Ok(ApiResponse {
+            status: ResponseStatus::InternalError,
             data: ApiData::Empty,
             message: ApiMsg::InternalError,
-        }));
+        });
     }
 
-    Ok(web::Json(ApiResponse {
-        status: 0,
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
         data: ApiData::Data(BallotSaveResponse { code: 0 }),
         message: ApiMsg::OK,
-    }))
+    })
 }
@@ -1,11 +1,15 @@
 mod ballot_create;
+mod ballot_my_stats;
 mod ballot_save;
 mod ballot_skip;
+mod ballot_skip_batch;
 mod bench_ballot_create;
 mod bench_ballot_save;
 
 pub use ballot_create::ballot_create_fn;
+pub use ballot_my_stats::ballot_my_stats_fn;
 pub use ballot_save::ballot_save_fn;
 pub use ballot_skip::ballot_skip_fn;
+pub use ballot_skip_batch::ballot_skip_batch_fn;
 pub use bench_ballot_create::bench_ballot_create_fn;
 pub use bench_ballot_save::bench_ballot_save_fn;
@@ -1,5 +1,8 @@
+mod admin;
 mod audit;
 mod ballot;
+mod operators;
+mod panic;
 mod results;
 mod topic;
 
@@ -7,17 +10,33 @@ pub use ballot::bench_ballot_create_fn;
 pub use ballot::bench_ballot_save_fn;
 
 pub use ballot::ballot_create_fn;
+pub use ballot::ballot_my_stats_fn;
 pub use ballot::ballot_save_fn;
+pub use ballot::ballot_skip_batch_fn;
 pub use ballot::ballot_skip_fn;
 
 pub use results::results_1v1_matrix_fn;
+pub use results::results_by_profession_fn;
+pub use results::results_condorcet_fn;
+pub use results::results_dominant_matchups_fn;
+pub use results::results_final_order_delta_fn;
 pub use results::results_final_order_fn;
 pub use results::results_operator_timeline_fn;
+pub use results::results_partners_fn;
+pub use results::results_rank_movement_fn;
+pub use results::results_velocity_fn;
+
+pub use operators::operators_list_fn;
 
 pub use topic::topic_candidate_pool_fn;
 pub use topic::topic_create_fn;
 pub use topic::topic_info_fn;
 pub use topic::topic_list_active_fn;
+pub use topic::topic_validate_pool_fn;
+
+pub use admin::{admin_config, require_admin_token};
+
+pub use panic::catch_panic;
 
 pub use audit::audit_topic_fn;
 pub use audit::audit_topics_list_fn;
@@ -0,0 +1,3 @@
+mod operators_list;
+
+pub use operators_list::operators_list_fn;
@@ -0,0 +1,22 @@
+use actix_web::{get, web};
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, OperatorsListQuery, OperatorsListResponse, ResponseStatus,
+};
+
+use crate::AppState;
+
+#[get("/operators")]
+pub async fn operators_list_fn(
+    state: web::Data<AppState>,
+    web::Query(query): web::Query<OperatorsListQuery>,
+) -> ApiResponse<OperatorsListResponse> {
+    ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(OperatorsListResponse::build(
+            &state.character_infos,
+            &state.character_portraits.load(),
+            &query,
+        )),
+        message: ApiMsg::OK,
+    }
+}
@@ -0,0 +1,90 @@
+use std::{any::Any, panic::AssertUnwindSafe};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+};
+use futures::FutureExt as _;
+
+use crate::error::AppError;
+
+/// Turns a panicking handler into a regular `ApiResponse` with status 500
+/// instead of dropping the connection.
+///
+/// The request is not cloned to build the response, routing needs to own it
+/// exclusively, so the panic is answered through `AppError` instead.
+pub async fn catch_panic(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let method = req.method().clone();
+    let path = req.path().to_owned();
+
+    match AssertUnwindSafe(next.call(req)).catch_unwind().await {
+        Ok(res) => res,
+        Err(panic) => {
+            tracing::error!(
+                "Handler for {} {} panicked: {}",
+                method,
+                path,
+                panic_message(panic.as_ref())
+            );
+
+            Err(AppError::HandlerPanicked.into())
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(msg) = panic.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = panic.downcast_ref::<String>() {
+        msg
+    } else {
+        "unknown panic"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        App, body::MessageBody as _, http::StatusCode, middleware::from_fn, test, web,
+    };
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_panicking_handler_returns_api_response() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(catch_panic))
+                .route(
+                    "/panic",
+                    web::get().to(|| async {
+                        if true {
+                            panic!("deliberate panic");
+                        }
+                        "unreachable"
+                    }),
+                )
+                .route("/ok", web::get().to(|| async { "ok" })),
+        )
+        .await;
+
+        // the server renders the error, like any other `AppError`
+        let req = test::TestRequest::get().uri("/panic").to_request();
+        let err = test::try_call_service(&app, req).await.err().unwrap();
+        let res = err.error_response();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = res.into_body().try_into_bytes().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["status"], 500);
+        assert_eq!(body["message"], "InternalError");
+
+        let req = test::TestRequest::get().uri("/ok").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
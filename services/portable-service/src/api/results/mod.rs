@@ -1,10 +1,24 @@
 mod results_1v1_matrix;
+mod results_by_profession;
+mod results_condorcet;
+mod results_dominant_matchups;
 mod results_final_order;
+mod results_final_order_delta;
+mod results_partners;
+mod results_rank_movement;
 mod results_timeseries;
+mod results_velocity;
 
 pub use results_1v1_matrix::results_1v1_matrix_fn;
+pub use results_by_profession::results_by_profession_fn;
+pub use results_condorcet::results_condorcet_fn;
+pub use results_dominant_matchups::results_dominant_matchups_fn;
 pub use results_final_order::results_final_order_fn;
+pub use results_final_order_delta::results_final_order_delta_fn;
+pub use results_partners::results_partners_fn;
+pub use results_rank_movement::results_rank_movement_fn;
 pub use results_timeseries::results_operator_timeline_fn;
+pub use results_velocity::results_velocity_fn;
 
 pub use results_final_order::OperatorsInfo;
 pub use results_final_order::generate_operators_info;
@@ -1,74 +1,131 @@
 use std::{collections::HashMap, sync::Arc};
 
-use actix_web::{Responder, post, web};
-use redis::AsyncCommands;
-use share::models::api::{
-    ApiData, ApiMsg, ApiResponse, Results1v1MatrixItem, Results1v1MatrixRequest,
-    Results1v1MatrixResponse,
+use actix_web::{HttpRequest, Responder, post, web};
+use chrono::Utc;
+use share::{
+    metrics::CacheKind,
+    models::api::{
+        ApiData, ApiMsg, ApiResponse, MatrixFormat, ResponseStatus, Results1v1MatrixItem,
+        Results1v1MatrixPair, Results1v1MatrixRequest, Results1v1MatrixResponse,
+    },
 };
 
-use crate::{AppState, state::ResultsType};
+use crate::{AppState, record_cache_lookup, state::ResultsType, utils::negotiate_timed};
 
 #[post("/results/1v1_matrix")]
 pub async fn results_1v1_matrix_fn(
     state: web::Data<AppState>,
+    http_req: HttpRequest,
     web::Json(req): web::Json<Results1v1MatrixRequest>,
 ) -> actix_web::Result<impl Responder> {
+    let response = matrix_1v1(&state, req).await?;
+
+    Ok(negotiate_timed(&http_req, response, |matrix| {
+        matrix.computed_at
+    }))
+}
+
+async fn matrix_1v1(
+    state: &AppState,
+    req: Results1v1MatrixRequest,
+) -> actix_web::Result<ApiResponse<Arc<Results1v1MatrixResponse>>> {
     let target_topic = match state.topic_service.get_topic(&req.topic_id).await {
         Ok(Some(topic)) if topic.topic_type.supports_1v1_matrix() => topic,
+        Ok(None) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
+                data: ApiData::Empty,
+                message: ApiMsg::TargetTopicNotFound,
+            });
+        }
         Ok(_) => {
-            return Ok(web::Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::BadRequest,
                 data: ApiData::Empty,
                 message: ApiMsg::CurTopicNotSupport1v1Matrix,
-            }));
+            });
         }
-        Err(_) => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InternalError,
+            });
         }
     };
 
-    let cache_key = (target_topic.id, ResultsType::Matrix1v1);
+    let results_type = match req.format {
+        MatrixFormat::Keyed => ResultsType::Matrix1v1,
+        MatrixFormat::Pairs => ResultsType::Matrix1v1Pairs,
+    };
+    let cache_key = (target_topic.id, results_type);
     if let Some(cached) = state.results_cache_store.get(&cache_key).await
         && let Some(matrix) = cached.matrix
     {
-        return Ok(web::Json(ApiResponse {
-            status: 0,
+        record_cache_lookup(CacheKind::Results, true);
+        return Ok(ApiResponse {
+            status: ResponseStatus::Ok,
             data: ApiData::Data(matrix.clone()),
             message: ApiMsg::OK,
-        }));
+        });
     }
+    record_cache_lookup(CacheKind::Results, false);
 
     let mut conn = state.database.redis.connection.clone();
 
-    let target_key = format!("{}:op_matrix", cache_key.0);
-    let data: HashMap<String, i64> = match conn.hgetall(target_key).await {
+    // both hashes in one round trip
+    let (data, counter_data): (HashMap<String, i64>, HashMap<String, i64>) = match redis::pipe()
+        .hgetall(req.topic_id.op_matrix_key())
+        .hgetall(req.topic_id.op_counter_key())
+        .query_async(&mut conn)
+        .await
+    {
         Ok(data) => data,
         Err(_) => {
-            return Ok(web::Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
                 message: ApiMsg::InternalError,
-            }));
+            });
         }
     };
 
-    let target_key = format!("{}:op_counter", cache_key.0);
-    let counter_data: HashMap<String, i64> = match conn.hgetall(target_key).await {
-        Ok(data) => data,
-        Err(_) => {
-            return Ok(web::Json(ApiResponse {
-                status: 500,
-                data: ApiData::Empty,
-                message: ApiMsg::InternalError,
-            }));
-        }
-    };
+    let mut cached = state
+        .results_cache_store
+        .get(&cache_key)
+        .await
+        .unwrap_or_default();
+
+    let response = Arc::new(match req.format {
+        MatrixFormat::Keyed => Results1v1MatrixResponse {
+            items: merge_matrix(data, &counter_data),
+            pairs: Vec::new(),
+            computed_at: Utc::now(),
+        },
+        MatrixFormat::Pairs => Results1v1MatrixResponse {
+            items: HashMap::new(),
+            pairs: Results1v1MatrixPair::from_hashes(&data, &counter_data, state.base_multiplier),
+            computed_at: Utc::now(),
+        },
+    });
+
+    cached.matrix = Some(response.clone());
+    state.results_cache_store.insert(cache_key, cached).await;
 
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(response),
+        message: ApiMsg::OK,
+    })
+}
+
+/// Pairs each `op_matrix` score with the game count `op_counter` keeps
+/// under the ordered `min:max` key.
+fn merge_matrix(
+    data: HashMap<String, i64>,
+    counter_data: &HashMap<String, i64>,
+) -> HashMap<String, Results1v1MatrixItem> {
     let mut rsp = HashMap::new();
     for (key, value) in data {
         // key format: 1233:201, order not guaranteed
@@ -101,20 +158,30 @@ pub async fn results_1v1_matrix_fn(
         );
     }
 
-    let mut cached = state
-        .results_cache_store
-        .get(&cache_key)
-        .await
-        .unwrap_or_default();
+    rsp
+}
 
-    let response = Arc::new(Results1v1MatrixResponse(rsp));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    cached.matrix = Some(response.clone());
-    state.results_cache_store.insert(cache_key, cached).await;
+    #[test]
+    fn test_merge_matrix_counts_either_order() {
+        let data = HashMap::from([
+            ("201:1233".to_string(), 3),
+            ("1233:201".to_string(), -3),
+            ("7:8".to_string(), 1),
+            ("bad".to_string(), 5),
+        ]);
+        let counters = HashMap::from([("201:1233".to_string(), 9)]);
 
-    Ok(web::Json(ApiResponse {
-        status: 0,
-        data: ApiData::Data(response),
-        message: ApiMsg::OK,
-    }))
+        let merged = merge_matrix(data, &counters);
+        assert_eq!(merged.len(), 4);
+        assert_eq!(merged["201:1233"].score, 3);
+        assert_eq!(merged["201:1233"].count, 9);
+        assert_eq!(merged["1233:201"].score, -3);
+        assert_eq!(merged["1233:201"].count, 9);
+        assert_eq!(merged["7:8"].count, 0);
+        assert_eq!(merged["bad"].count, 0);
+    }
 }
@@ -0,0 +1,36 @@
+use actix_web::{Responder, post, web};
+use share::models::api::{
+    ApiData, ApiResponse, ResultsByProfessionRequest, ResultsByProfessionResponse,
+    ResultsFinalOrderRequest,
+};
+
+use super::results_final_order::final_order;
+use crate::AppState;
+
+#[post("/results/by_profession")]
+pub async fn results_by_profession_fn(
+    state: web::Data<AppState>,
+    web::Json(req): web::Json<ResultsByProfessionRequest>,
+) -> actix_web::Result<impl Responder> {
+    let final_order_req = ResultsFinalOrderRequest {
+        topic_id: req.topic_id,
+        tier_breakpoints: None,
+        ranking_method: req.ranking_method,
+        limit: None,
+        format: Default::default(),
+    };
+    let final_order = final_order(&state, final_order_req).await?;
+
+    Ok(ApiResponse {
+        status: final_order.status,
+        data: match final_order.data {
+            ApiData::Data(final_order) => ApiData::Data(ResultsByProfessionResponse::build(
+                &final_order,
+                &state.character_infos,
+                req.limit,
+            )),
+            ApiData::Empty => ApiData::Empty,
+        },
+        message: final_order.message,
+    })
+}
@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+use actix_web::{Responder, post, web};
+use redis::AsyncCommands;
+use share::models::api::{ResultsCondorcetRequest, ResultsCondorcetResponse};
+
+use crate::AppState;
+
+#[post("/results/condorcet")]
+pub async fn results_condorcet_fn(
+    state: web::Data<AppState>,
+    web::Json(req): web::Json<ResultsCondorcetRequest>,
+) -> actix_web::Result<impl Responder> {
+    let mut conn = state.database.redis.connection.clone();
+    Ok(ResultsCondorcetResponse::respond(
+        state.topic_service.get_topic(&req.topic_id).await,
+        async |topic_id: &str| {
+            state
+                .topic_service
+                .get_candidate_pool(topic_id, &state.character_infos)
+                .await
+        },
+        async || {
+            conn.hgetall::<_, HashMap<String, i64>>(req.topic_id.op_matrix_key())
+                .await
+        },
+        &state.character_infos,
+    )
+    .await)
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use actix_web::{Responder, post, web};
+use redis::AsyncCommands;
+use share::models::{
+    api::{
+        ApiData, ApiMsg, ApiResponse, DominantMatchupItem, ResponseStatus,
+        ResultsDominantMatchupsRequest, ResultsDominantMatchupsResponse,
+    },
+    excel::CharacterInfo,
+};
+use share::ranking::pair_records;
+
+use crate::AppState;
+
+/// Builds the dominant matchups from the `op_counter` (games and wins per
+/// pair, keyed by `min:max`) and `op_matrix` (net weighted score, stored for
+/// both directions) hashes, see `pair_records`.
+fn compute_dominant_matchups(
+    matrix: &HashMap<String, i64>,
+    counter: &HashMap<String, i64>,
+    base_multiplier: i32,
+    min_win_rate: f64,
+    min_games: i64,
+    character_infos: &[CharacterInfo],
+) -> Vec<DominantMatchupItem> {
+    let operator_name = |id: i32| {
+        character_infos
+            .iter()
+            .find(|op| op.id == id)
+            .map(|op| op.name.clone())
+            .unwrap_or_else(|| format!("Unknown Operator {}", id))
+    };
+
+    let mut items: Vec<DominantMatchupItem> = pair_records(matrix, counter, base_multiplier)
+        .into_iter()
+        .filter(|record| record.games as i64 >= min_games)
+        .filter_map(|record| {
+            let (winner_id, loser_id, wins) = if record.a_wins >= record.b_wins {
+                (record.a, record.b, record.a_wins)
+            } else {
+                (record.b, record.a, record.b_wins)
+            };
+            let games = record.games as i64;
+            let win_rate = wins * 100.0 / record.games;
+
+            (win_rate >= min_win_rate).then(|| DominantMatchupItem {
+                winner_id,
+                winner_name: operator_name(winner_id),
+                loser_id,
+                loser_name: operator_name(loser_id),
+                games,
+                win_rate,
+            })
+        })
+        .collect();
+
+    items.sort_by(|a, b| {
+        b.win_rate
+            .total_cmp(&a.win_rate)
+            .then_with(|| b.games.cmp(&a.games))
+    });
+
+    items
+}
+
+#[post("/results/dominant_matchups")]
+pub async fn results_dominant_matchups_fn(
+    state: web::Data<AppState>,
+    web::Json(req): web::Json<ResultsDominantMatchupsRequest>,
+) -> actix_web::Result<impl Responder> {
+    let target_topic = match state.topic_service.get_topic(&req.topic_id).await {
+        Ok(Some(topic)) if topic.topic_type.supports_1v1_matrix() => topic,
+        Ok(None) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
+                data: ApiData::Empty,
+                message: ApiMsg::TargetTopicNotFound,
+            });
+        }
+        Ok(_) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::BadRequest,
+                data: ApiData::Empty,
+                message: ApiMsg::CurTopicNotSupport1v1Matrix,
+            });
+        }
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
+                data: ApiData::Empty,
+                message: ApiMsg::InternalError,
+            });
+        }
+    };
+
+    let mut conn = state.database.redis.connection.clone();
+
+    let target_key = req.topic_id.op_matrix_key();
+    let matrix: HashMap<String, i64> = match conn.hgetall(target_key).await {
+        Ok(data) => data,
+        Err(_) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
+                data: ApiData::Empty,
+                message: ApiMsg::InternalError,
+            });
+        }
+    };
+
+    let target_key = req.topic_id.op_counter_key();
+    let counter: HashMap<String, i64> = match conn.hgetall(target_key).await {
+        Ok(data) => data,
+        Err(_) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
+                data: ApiData::Empty,
+                message: ApiMsg::InternalError,
+            });
+        }
+    };
+
+    let items = compute_dominant_matchups(
+        &matrix,
+        &counter,
+        state.base_multiplier,
+        req.min_win_rate,
+        req.min_games,
+        &state.character_infos,
+    );
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(ResultsDominantMatchupsResponse {
+            topic_id: target_topic.id,
+            items,
+        }),
+        message: ApiMsg::OK,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_data() -> (HashMap<String, i64>, HashMap<String, i64>) {
+        // 1001 beat 1002 in 90 of 100 games, 2001 beat 1001 in 45 of 50 games,
+        // 1002 and 2001 split 10 games evenly
+        let matrix = HashMap::from([
+            ("1001:1002".to_string(), 8000),
+            ("1002:1001".to_string(), -8000),
+            ("1001:2001".to_string(), -4000),
+            ("2001:1001".to_string(), 4000),
+            ("1002:2001".to_string(), 0),
+            ("2001:1002".to_string(), 0),
+        ]);
+        let counter = HashMap::from([
+            ("1001:1002".to_string(), 100),
+            ("1001:2001".to_string(), 50),
+            ("1002:2001".to_string(), 10),
+        ]);
+        (matrix, counter)
+    }
+
+    #[test]
+    fn test_compute_dominant_matchups() {
+        let (matrix, counter) = create_test_data();
+        let items = compute_dominant_matchups(&matrix, &counter, 100, 80.0, 20, &[]);
+
+        assert_eq!(items.len(), 2);
+
+        assert_eq!((items[0].winner_id, items[0].loser_id), (1001, 1002));
+        assert_eq!(items[0].games, 100);
+        assert_eq!(items[0].win_rate, 90.0);
+
+        assert_eq!((items[1].winner_id, items[1].loser_id), (2001, 1001));
+        assert_eq!(items[1].games, 50);
+        assert_eq!(items[1].win_rate, 90.0);
+    }
+
+    #[test]
+    fn test_compute_dominant_matchups_thresholds() {
+        let (matrix, counter) = create_test_data();
+
+        let items = compute_dominant_matchups(&matrix, &counter, 100, 80.0, 60, &[]);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].winner_id, 1001);
+
+        let items = compute_dominant_matchups(&matrix, &counter, 100, 95.0, 0, &[]);
+        assert!(items.is_empty());
+
+        let items = compute_dominant_matchups(&matrix, &counter, 100, 0.0, 0, &[]);
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[2].win_rate, 50.0);
+        assert_eq!(items[2].winner_name, "Unknown Operator 1002");
+    }
+
+    #[test]
+    fn test_compute_dominant_matchups_recorded_wins() {
+        // a few heavy wins of 1001 lead the net score, 1002 won most games
+        let matrix = HashMap::from([
+            ("1001:1002".to_string(), 600),
+            ("1002:1001".to_string(), -600),
+        ]);
+        let counter = HashMap::from([
+            ("1001:1002".to_string(), 10),
+            ("1001:1002:a_wins".to_string(), 2),
+            ("1001:1002:b_wins".to_string(), 8),
+        ]);
+
+        let items = compute_dominant_matchups(&matrix, &counter, 100, 80.0, 0, &[]);
+        assert_eq!(items.len(), 1);
+        assert_eq!((items[0].winner_id, items[0].loser_id), (1002, 1001));
+        assert_eq!(items[0].win_rate, 80.0);
+    }
+}
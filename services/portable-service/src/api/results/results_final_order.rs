@@ -1,30 +1,44 @@
 use std::{collections::HashMap, sync::Arc};
 
-use actix_web::{Responder, post, web};
+use actix_web::{HttpRequest, Responder, post, web};
+use chrono::Utc;
 use ordered_float::OrderedFloat;
-use share::models::{
-    api::{
-        ApiData, ApiMsg, ApiResponse, FinalOrderItem, ResultsFinalOrderRequest,
-        ResultsFinalOrderResponse,
+use redis::AsyncCommands;
+use share::{
+    metrics::CacheKind,
+    models::{
+        api::{
+            ApiData, ApiMsg, ApiResponse, FinalOrderItem, ResponseStatus, ResultsFinalOrderRequest,
+            ResultsFinalOrderResponse,
+        },
+        excel::CharacterInfo,
+        format::{ResultFormat, apply_result_format, validate_result_format},
+        tier::{assign_tiers, validate_tier_breakpoints},
+        topic_id::TopicId,
     },
-    excel::CharacterInfo,
+    ranking::{RankingMethod, colley_ratings, pair_records},
+    scores::parse_operator_counts,
 };
 
-use crate::{AppState, state::ResultsType};
+use crate::{
+    AppState, record_cache_lookup,
+    state::{FinalOrderTop, ResultsCacheValue, ResultsType},
+    utils::negotiate,
+};
 
 #[derive(Debug)]
-struct OperatorResult {
-    id: i32,
-    win: i64,
-    lose: i64,
-
-    name: String,
-    score: f64,
-    rate: f64,
+pub(super) struct OperatorResult {
+    pub(super) id: i32,
+    pub(super) win: i64,
+    pub(super) lose: i64,
+
+    pub(super) name: String,
+    pub(super) score: f64,
+    pub(super) rate: f64,
 }
 
 impl OperatorResult {
-    fn new(name: String, id: i32, win: i64, lose: i64) -> Self {
+    pub(super) fn new(name: String, id: i32, win: i64, lose: i64) -> Self {
         let total = win + lose;
         let rate = match total {
             t if t > 0 => win as f64 * 100.0 / t as f64,
@@ -43,6 +57,23 @@ impl OperatorResult {
     }
 }
 
+impl From<OperatorResult> for FinalOrderItem {
+    fn from(r: OperatorResult) -> Self {
+        let format = ResultFormat::default();
+        FinalOrderItem {
+            name: r.name,
+            id: r.id,
+            win: r.win,
+            lose: r.lose,
+            score: format.score(r.score),
+            rate: format.rate(r.rate),
+            tier: None,
+            score_value: r.score,
+            rate_value: r.rate,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct OperatorsInfo {
     pub operator_ids: Vec<i32>,
@@ -56,15 +87,10 @@ pub fn generate_operators_info(
     character_infos: &[CharacterInfo],
 ) -> OperatorsInfo {
     let num_operators = operator_ids.len();
+    let names = operator_names(character_infos);
     let reverse_operators_id_dict: HashMap<i32, String> = operator_ids
         .iter()
-        .filter_map(|&id| {
-            character_infos
-                .iter()
-                .find(|op| op.id == id)
-                .map(|op| (id, op.name.clone()))
-                .or_else(|| Some((id, format!("Unknown Operator {}", id))))
-        })
+        .map(|&id| (id, operator_name(&names, id)))
         .collect();
     let win_fields: Vec<String> = operator_ids
         .iter()
@@ -84,57 +110,126 @@ pub fn generate_operators_info(
     }
 }
 
+/// Operator names by id, so naming a pool does not scan the character table
+/// once per operator.
+fn operator_names(character_infos: &[CharacterInfo]) -> HashMap<i32, &str> {
+    character_infos
+        .iter()
+        .map(|op| (op.id, op.name.as_str()))
+        .collect()
+}
+
+fn operator_name(names: &HashMap<i32, &str>, id: i32) -> String {
+    names
+        .get(&id)
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| format!("Unknown Operator {id}"))
+}
+
 #[post("/results/final_order")]
 pub async fn results_final_order_fn(
     state: web::Data<AppState>,
+    http_req: HttpRequest,
     web::Json(req): web::Json<ResultsFinalOrderRequest>,
 ) -> actix_web::Result<impl Responder> {
+    let response = final_order(&state, req).await?;
+
+    Ok(negotiate(&http_req, response))
+}
+
+pub(super) async fn final_order(
+    state: &AppState,
+    req: ResultsFinalOrderRequest,
+) -> actix_web::Result<ApiResponse<Arc<ResultsFinalOrderResponse>>> {
+    if let Some(breakpoints) = &req.tier_breakpoints
+        && !validate_tier_breakpoints(breakpoints)
+    {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::InvalidTierBreakpoints,
+        });
+    }
+    if !validate_result_format(&req.format) {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::InvalidResultFormat,
+        });
+    }
+
     let target_topic = match state.topic_service.get_topic(&req.topic_id).await {
         Ok(Some(topic)) if topic.topic_type.supports_final_order() => topic,
+        Ok(None) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
+                data: ApiData::Empty,
+                message: ApiMsg::TargetTopicNotFound,
+            });
+        }
         Ok(_) => {
             tracing::debug!("Topic {} does not support final order", req.topic_id);
-            return Ok(web::Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::BadRequest,
                 data: ApiData::Empty,
                 message: ApiMsg::CurTopicNotSupportFinalOrder,
-            }));
+            });
         }
-        Err(_) => {
-            tracing::debug!("Topic {} not found", req.topic_id);
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InternalError,
+            });
         }
     };
 
-    let cache_key = (target_topic.id, ResultsType::FinalOrder);
-    if let Some(cached) = state.results_cache_store.get(&cache_key).await
-        && let Some(final_order) = &cached.final_order
-    {
+    let results_type = match req.ranking_method {
+        RankingMethod::Rate => ResultsType::FinalOrder,
+        RankingMethod::Colley => ResultsType::FinalOrderColley,
+    };
+    let cache_key = (target_topic.id, results_type);
+    let cached = state
+        .results_cache_store
+        .get(&cache_key)
+        .await
+        .and_then(|cached| cached_final_order(cached, req.limit));
+    if let Some(final_order) = cached {
         tracing::debug!("Cache hit for final order of topic {}", req.topic_id);
-        return Ok(web::Json(ApiResponse {
-            status: 0,
-            data: ApiData::Data(final_order.clone()),
+        record_cache_lookup(CacheKind::Results, true);
+        return Ok(ApiResponse {
+            status: ResponseStatus::Ok,
+            data: ApiData::Data(present(
+                final_order,
+                req.limit,
+                req.tier_breakpoints.as_deref(),
+                &req.format,
+            )),
             message: ApiMsg::OK,
-        }));
+        });
     }
+    record_cache_lookup(CacheKind::Results, false);
 
     let candidate_pool = match state
         .topic_service
         .get_candidate_pool(&cache_key.0, &state.character_infos)
         .await
     {
-        Some(pool) => pool,
-        None => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+        Ok(pool) => pool,
+        Err(err) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: err.into(),
+            });
         }
     };
+    if let Some(limit) = req.limit
+        && req.ranking_method == RankingMethod::Rate
+    {
+        return top_final_order(state, req, cache_key, &candidate_pool, limit).await;
+    }
     let operators_info = generate_operators_info(&candidate_pool, &state.character_infos);
     let num_operators = operators_info.num_operators;
 
@@ -144,7 +239,7 @@ pub async fn results_final_order_fn(
         .database
         .redis
         .final_order_script
-        .key(&req.topic_id)
+        .key(req.topic_id.as_str())
         .arg(&operators_info.op_stats_all_fields)
         .invoke_async(&mut conn)
         .await
@@ -152,15 +247,28 @@ pub async fn results_final_order_fn(
         Ok(result) => result,
         Err(err) => {
             tracing::error!("Failed to execute Lua script for final order: {}", err);
-            return Ok(web::Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
                 message: ApiMsg::InternalError,
-            }));
+            });
         }
     };
 
-    let (win_counts, lose_counts) = parse_operator_counts(&operator_values, num_operators);
+    let Some((win_counts, lose_counts)) = parse_operator_counts(&operator_values, num_operators)
+    else {
+        tracing::error!(
+            "Final order script for topic {} returned {} values, expected {}",
+            req.topic_id,
+            operator_values.len(),
+            2 * num_operators
+        );
+        return Ok(ApiResponse {
+            status: ResponseStatus::InternalError,
+            data: ApiData::Empty,
+            message: ApiMsg::InternalError,
+        });
+    };
 
     let mut results = build_operator_results(
         &operators_info.operator_ids,
@@ -169,29 +277,28 @@ pub async fn results_final_order_fn(
         &lose_counts,
     );
 
-    results.sort_by(|a, b| {
-        (OrderedFloat(b.rate), OrderedFloat(b.score), b.win, a.id).cmp(&(
-            OrderedFloat(a.rate),
-            OrderedFloat(a.score),
-            a.win,
-            b.id,
-        ))
-    });
+    sort_operator_results(&mut results);
+
+    let mut ranking_method = RankingMethod::Rate;
+    if req.ranking_method == RankingMethod::Colley {
+        match colley_order(state, &req.topic_id, &operators_info.operator_ids).await {
+            Some(ratings) => {
+                sort_by_ratings(&mut results, &ratings);
+                ranking_method = RankingMethod::Colley;
+            }
+            None => tracing::warn!(
+                "Colley ranking unavailable for topic {}, falling back to rate",
+                req.topic_id
+            ),
+        }
+    }
 
     let response = Arc::new(ResultsFinalOrderResponse {
-        topic_id: req.topic_id,
-        items: results
-            .into_iter()
-            .map(|r| FinalOrderItem {
-                name: r.name,
-                id: r.id,
-                win: r.win,
-                lose: r.lose,
-                score: format!("{:.2}", r.score),
-                rate: format!("{:.1}%", r.rate),
-            })
-            .collect(),
+        topic_id: req.topic_id.into(),
+        items: results.into_iter().map(FinalOrderItem::from).collect(),
         count: total_valid_ballots.unwrap_or(0),
+        ranking_method,
+        computed_at: Utc::now(),
     });
 
     let mut cached = state
@@ -203,28 +310,208 @@ pub async fn results_final_order_fn(
     cached.final_order = Some(response.clone());
     state.results_cache_store.insert(cache_key, cached).await;
 
-    Ok(web::Json(ApiResponse {
-        status: 0,
-        data: ApiData::Data(response),
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(present(
+            response,
+            req.limit,
+            req.tier_breakpoints.as_deref(),
+            &req.format,
+        )),
         message: ApiMsg::OK,
-    }))
+    })
 }
 
-fn parse_operator_counts(values: &[Option<String>], num_operators: usize) -> (Vec<i64>, Vec<i64>) {
-    let win_counts: Vec<i64> = values[..num_operators]
-        .iter()
-        .map(|v| v.as_ref().and_then(|s| s.parse().ok()).unwrap_or(0))
-        .collect();
+/// The full order when cached, or a cached head long enough for `limit`.
+fn cached_final_order(
+    cached: ResultsCacheValue,
+    limit: Option<usize>,
+) -> Option<Arc<ResultsFinalOrderResponse>> {
+    cached.final_order.or_else(|| {
+        let top = cached.final_order_top?;
+        (limit? <= top.limit).then_some(top.response)
+    })
+}
 
-    let lose_counts: Vec<i64> = values[num_operators..]
-        .iter()
-        .map(|v| v.as_ref().and_then(|s| s.parse().ok()).unwrap_or(0))
-        .collect();
+/// The cached response is shared between requests, so it is truncated,
+/// tiers are assigned and non default formats are applied on a copy.
+fn present(
+    response: Arc<ResultsFinalOrderResponse>,
+    limit: Option<usize>,
+    breakpoints: Option<&[f64]>,
+    format: &ResultFormat,
+) -> Arc<ResultsFinalOrderResponse> {
+    let truncate = limit.is_some_and(|limit| limit < response.items.len());
+    let reformat = *format != ResultFormat::default();
+    if !truncate && breakpoints.is_none() && !reformat {
+        return response;
+    }
 
-    (win_counts, lose_counts)
+    let mut response = (*response).clone();
+    if let Some(limit) = limit {
+        response.items.truncate(limit);
+    }
+    if let Some(breakpoints) = breakpoints {
+        assign_tiers(&mut response.items, breakpoints);
+    }
+    if reformat {
+        apply_result_format(&mut response.items, format);
+    }
+    Arc::new(response)
 }
 
-fn build_operator_results(
+/// Head of the rate ordered final order, ranked by the top script so only
+/// `limit` operators leave Redis. Cached next to the full order, and served
+/// to later requests for at most as many operators.
+async fn top_final_order(
+    state: &AppState,
+    req: ResultsFinalOrderRequest,
+    cache_key: (String, ResultsType),
+    candidate_pool: &[i32],
+    limit: usize,
+) -> actix_web::Result<ApiResponse<Arc<ResultsFinalOrderResponse>>> {
+    let mut conn = state.database.redis.connection.clone();
+
+    let (top, total_valid_ballots): (Vec<i64>, Option<i64>) = match state
+        .database
+        .redis
+        .final_order_top_script
+        .key(req.topic_id.as_str())
+        .arg(limit)
+        .arg(candidate_pool)
+        .invoke_async(&mut conn)
+        .await
+    {
+        Ok(result) => result,
+        Err(err) => {
+            tracing::error!("Failed to execute Lua script for final order top: {}", err);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
+                data: ApiData::Empty,
+                message: ApiMsg::InternalError,
+            });
+        }
+    };
+
+    let Some(results) = parse_top_results(&top, &state.character_infos) else {
+        tracing::error!(
+            "Final order top script for topic {} returned {} values, expected triples",
+            req.topic_id,
+            top.len()
+        );
+        return Ok(ApiResponse {
+            status: ResponseStatus::InternalError,
+            data: ApiData::Empty,
+            message: ApiMsg::InternalError,
+        });
+    };
+
+    let response = Arc::new(ResultsFinalOrderResponse {
+        topic_id: req.topic_id.into(),
+        items: results.into_iter().map(FinalOrderItem::from).collect(),
+        count: total_valid_ballots.unwrap_or(0),
+        ranking_method: RankingMethod::Rate,
+        computed_at: Utc::now(),
+    });
+
+    let mut cached = state
+        .results_cache_store
+        .get(&cache_key)
+        .await
+        .unwrap_or_default();
+    if cached
+        .final_order_top
+        .as_ref()
+        .is_none_or(|top| top.limit < limit)
+    {
+        cached.final_order_top = Some(FinalOrderTop {
+            limit,
+            response: response.clone(),
+        });
+        state.results_cache_store.insert(cache_key, cached).await;
+    }
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(present(
+            response,
+            None,
+            req.tier_breakpoints.as_deref(),
+            &req.format,
+        )),
+        message: ApiMsg::OK,
+    })
+}
+
+/// Reads the `{id, win, lose}` triples returned by the top script, keeping
+/// their order. Returns `None` if the values are not whole triples.
+pub(super) fn parse_top_results(
+    values: &[i64],
+    character_infos: &[CharacterInfo],
+) -> Option<Vec<OperatorResult>> {
+    if !values.len().is_multiple_of(3) {
+        return None;
+    }
+
+    let names = operator_names(character_infos);
+    values
+        .chunks_exact(3)
+        .map(|triple| {
+            let id = i32::try_from(triple[0]).ok()?;
+            let name = operator_name(&names, id);
+
+            Some(OperatorResult::new(name, id, triple[1], triple[2]))
+        })
+        .collect()
+}
+
+/// Colley ratings for the candidate pool from the `op_matrix` and `op_counter`
+/// hashes. Returns `None` if either hash can not be read or the pool is too
+/// large to solve.
+async fn colley_order(
+    state: &AppState,
+    topic_id: &TopicId,
+    operator_ids: &[i32],
+) -> Option<HashMap<i32, f64>> {
+    let mut conn = state.database.redis.connection.clone();
+
+    let matrix: HashMap<String, i64> = conn
+        .hgetall(topic_id.op_matrix_key())
+        .await
+        .inspect_err(|err| tracing::error!("Failed to read op_matrix of {}: {}", topic_id, err))
+        .ok()?;
+    let counter: HashMap<String, i64> = conn
+        .hgetall(topic_id.op_counter_key())
+        .await
+        .inspect_err(|err| tracing::error!("Failed to read op_counter of {}: {}", topic_id, err))
+        .ok()?;
+
+    colley_ratings(
+        operator_ids,
+        &pair_records(&matrix, &counter, state.base_multiplier),
+    )
+}
+
+/// Orders operators by descending rating, keeping the rate order for ties.
+pub(super) fn sort_by_ratings(results: &mut [OperatorResult], ratings: &HashMap<i32, f64>) {
+    let rating = |id: i32| OrderedFloat(ratings.get(&id).copied().unwrap_or(0.0));
+    results.sort_by_key(|r| std::cmp::Reverse(rating(r.id)));
+}
+
+/// Orders operators the way the final order is ranked: by rate, then score,
+/// then wins, with the operator id as a stable tie breaker.
+pub(super) fn sort_operator_results(results: &mut [OperatorResult]) {
+    results.sort_by(|a, b| {
+        (OrderedFloat(b.rate), OrderedFloat(b.score), b.win, a.id).cmp(&(
+            OrderedFloat(a.rate),
+            OrderedFloat(a.score),
+            a.win,
+            b.id,
+        ))
+    });
+}
+
+pub(super) fn build_operator_results(
     operator_ids: &[i32],
     reverse_dict: &std::collections::HashMap<i32, String>,
     win_counts: &[i64],
@@ -262,17 +549,18 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_operator_counts() {
-        let values = vec![
-            Some("10".to_string()),
-            Some("20".to_string()),
-            Some("5".to_string()),
-            Some("15".to_string()),
+    fn test_sort_by_ratings() {
+        let mut results = vec![
+            OperatorResult::new("A".to_string(), 1, 80, 20),
+            OperatorResult::new("B".to_string(), 2, 60, 40),
+            OperatorResult::new("C".to_string(), 3, 60, 40),
         ];
-        let (wins, losses) = parse_operator_counts(&values, 2);
+        let ratings = HashMap::from([(1, 0.4), (2, 0.7), (3, 0.4)]);
+
+        sort_by_ratings(&mut results, &ratings);
 
-        assert_eq!(wins, vec![10, 20]);
-        assert_eq!(losses, vec![5, 15]);
+        let ids: Vec<i32> = results.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![2, 1, 3]);
     }
 
     #[test]
@@ -293,4 +581,198 @@ mod tests {
         assert_eq!(results[0].rate, 80.0);
         assert_eq!(results[0].score, 0.15);
     }
+
+    #[test]
+    fn test_parse_top_results() {
+        let infos = vec![CharacterInfo {
+            id: 2,
+            name: "Amiya".to_string(),
+            rarity: share::models::excel::RarityRank::Tier5,
+            profession: share::models::excel::ProfessionCategory::CASTER,
+            sub_profession_id: "corecaster".to_string(),
+            is_not_obtainable: false,
+        }];
+
+        let results = parse_top_results(&[2, 70, 30, 9, 1, 3], &infos).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            (results[0].id, results[0].win, results[0].lose),
+            (2, 70, 30)
+        );
+        assert_eq!(results[0].name, "Amiya");
+        assert_eq!(results[1].name, "Unknown Operator 9");
+
+        assert!(parse_top_results(&[2, 70], &infos).is_none());
+        assert!(parse_top_results(&[i64::MAX, 1, 1], &infos).is_none());
+    }
+
+    #[test]
+    fn test_cached_head_serves_shorter_limits_only() {
+        let response = |count: i64| {
+            Arc::new(ResultsFinalOrderResponse {
+                topic_id: "test".into(),
+                items: Vec::new(),
+                count,
+                ranking_method: RankingMethod::Rate,
+                computed_at: Utc::now(),
+            })
+        };
+        let cached = ResultsCacheValue {
+            final_order_top: Some(FinalOrderTop {
+                limit: 20,
+                response: response(1),
+            }),
+            ..Default::default()
+        };
+
+        assert!(cached_final_order(cached.clone(), Some(10)).is_some());
+        assert!(cached_final_order(cached.clone(), Some(20)).is_some());
+        assert!(cached_final_order(cached.clone(), Some(21)).is_none());
+        assert!(cached_final_order(cached.clone(), None).is_none());
+
+        let full = ResultsCacheValue {
+            final_order: Some(response(2)),
+            ..cached
+        };
+        let order = cached_final_order(full, Some(10)).unwrap();
+        assert_eq!(order.count, 2);
+    }
+
+    /// Pool the top script is compared on. The full script unpacks two fields
+    /// per operator, which Redis Lua caps at about 8000 values.
+    const TOP_POOL_SIZE: i32 = 3000;
+    const TOP_LIMIT: usize = 20;
+
+    /// Fills `op_stats` with spread out counts for operators `1..=TOP_POOL_SIZE`.
+    async fn seed_top_pool(
+        conn: &mut redis::aio::MultiplexedConnection,
+        topic_id: &TopicId,
+    ) -> Vec<i32> {
+        let pool: Vec<i32> = (1..=TOP_POOL_SIZE).collect();
+        let stats: Vec<(String, i64)> = pool
+            .iter()
+            .flat_map(|id| {
+                let id = *id as i64;
+                [
+                    (format!("{id}:win"), id * 7 % 1000),
+                    (format!("{id}:lose"), id * 13 % 1000),
+                ]
+            })
+            .collect();
+        let _: () = conn
+            .hset_multiple(topic_id.op_stats_key(), &stats)
+            .await
+            .unwrap();
+        pool
+    }
+
+    /// Head of the order the way requests without the top script build it:
+    /// fetch the whole pool, sort it and truncate.
+    async fn full_order_head(
+        script: &redis::Script,
+        conn: &mut redis::aio::MultiplexedConnection,
+        topic_id: &TopicId,
+        pool: &[i32],
+    ) -> Vec<i32> {
+        let operators_info = generate_operators_info(pool, &[]);
+        let (values, _): (Vec<Option<String>>, Option<i64>) = script
+            .key(topic_id.as_str())
+            .arg(&operators_info.op_stats_all_fields)
+            .invoke_async(conn)
+            .await
+            .unwrap();
+        let (wins, loses) = parse_operator_counts(&values, pool.len()).unwrap();
+        let mut results = build_operator_results(
+            pool,
+            &operators_info.reverse_operators_id_dict,
+            &wins,
+            &loses,
+        );
+        sort_operator_results(&mut results);
+        results.iter().take(TOP_LIMIT).map(|r| r.id).collect()
+    }
+
+    async fn top_order_head(
+        script: &redis::Script,
+        conn: &mut redis::aio::MultiplexedConnection,
+        topic_id: &TopicId,
+        pool: &[i32],
+    ) -> Vec<i32> {
+        let (values, _): (Vec<i64>, Option<i64>) = script
+            .key(topic_id.as_str())
+            .arg(TOP_LIMIT)
+            .arg(pool)
+            .invoke_async(conn)
+            .await
+            .unwrap();
+        parse_top_results(&values, &[])
+            .unwrap()
+            .iter()
+            .map(|r| r.id)
+            .collect()
+    }
+
+    /// The top script ranks the same head as fetching and sorting the whole
+    /// pool.
+    /// Needs a local Redis: `cargo test -p portable-service final_order_top -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_final_order_top_script_matches_full_order() {
+        use crate::constants::{LUA_SCRIPT_GET_FINAL_ORDER, LUA_SCRIPT_GET_FINAL_ORDER_TOP};
+
+        let topic_id = TopicId::parse("test_final_order_top").unwrap();
+        let client = redis::Client::open("redis://127.0.0.1:6379").unwrap();
+        let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+        let pool = seed_top_pool(&mut conn, &topic_id).await;
+
+        let full_script = redis::Script::new(LUA_SCRIPT_GET_FINAL_ORDER);
+        let top_script = redis::Script::new(LUA_SCRIPT_GET_FINAL_ORDER_TOP);
+        let full_head = full_order_head(&full_script, &mut conn, &topic_id, &pool).await;
+        let top_head = top_order_head(&top_script, &mut conn, &topic_id, &pool).await;
+
+        let _: () = conn.del(topic_id.op_stats_key()).await.unwrap();
+
+        assert_eq!(full_head, top_head);
+    }
+
+    /// Times the head of a large pool through the top script against the full
+    /// script and the Rust side sort, printing the mean time per request.
+    /// Needs a local Redis: `cargo test --release -p portable-service
+    /// bench_final_order_top -- --ignored --nocapture`
+    #[tokio::test]
+    #[ignore]
+    async fn bench_final_order_top_script() {
+        use crate::constants::{LUA_SCRIPT_GET_FINAL_ORDER, LUA_SCRIPT_GET_FINAL_ORDER_TOP};
+
+        const ROUNDS: u32 = 50;
+        let topic_id = TopicId::parse("bench_final_order_top").unwrap();
+        let client = redis::Client::open("redis://127.0.0.1:6379").unwrap();
+        let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+        let pool = seed_top_pool(&mut conn, &topic_id).await;
+
+        let full_script = redis::Script::new(LUA_SCRIPT_GET_FINAL_ORDER);
+        let top_script = redis::Script::new(LUA_SCRIPT_GET_FINAL_ORDER_TOP);
+        // load both scripts before timing
+        full_order_head(&full_script, &mut conn, &topic_id, &pool).await;
+        top_order_head(&top_script, &mut conn, &topic_id, &pool).await;
+
+        let started = std::time::Instant::now();
+        for _ in 0..ROUNDS {
+            full_order_head(&full_script, &mut conn, &topic_id, &pool).await;
+        }
+        let full = started.elapsed() / ROUNDS;
+
+        let started = std::time::Instant::now();
+        for _ in 0..ROUNDS {
+            top_order_head(&top_script, &mut conn, &topic_id, &pool).await;
+        }
+        let top = started.elapsed() / ROUNDS;
+
+        let _: () = conn.del(topic_id.op_stats_key()).await.unwrap();
+
+        println!(
+            "final order head of {TOP_LIMIT} from {TOP_POOL_SIZE} operators: \
+             full script {full:?}, top script {top:?} per request"
+        );
+    }
 }
@@ -0,0 +1,162 @@
+use std::collections::{HashMap, HashSet};
+
+use actix_web::{Responder, post, web};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use share::models::api::{ApiData, ApiMsg, ApiResponse, FinalOrderItem, ResponseStatus};
+
+use crate::state::AppState;
+
+use super::{
+    results_final_order::OperatorResult,
+    results_rank_movement::{RankingComparison, load_ranking_comparison},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct FinalOrderDeltaQuery {
+    pub topic_id: String,
+    /// Time of the ranking the client already has.
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FinalOrderDeltaItem {
+    /// 1-based rank in the live ranking.
+    pub rank: usize,
+    #[serde(flatten)]
+    pub item: FinalOrderItem,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FinalOrderDeltaData {
+    pub topic_id: String,
+    pub since: DateTime<Utc>,
+    /// Set when no snapshot exists at or before `since`, `items` then holds
+    /// the whole ranking instead of a delta.
+    pub full: bool,
+    /// Number of operators in the live ranking.
+    pub total: usize,
+    /// Operators whose rank, win or lose count changed since the snapshot.
+    pub items: Vec<FinalOrderDeltaItem>,
+    /// Operators in the snapshot that are no longer ranked.
+    pub removed: Vec<i32>,
+}
+
+fn compute_final_order_delta(
+    comparison: RankingComparison,
+    since: DateTime<Utc>,
+) -> FinalOrderDeltaData {
+    let RankingComparison {
+        topic_id,
+        current,
+        past,
+    } = comparison;
+    let full = past.is_empty();
+
+    let past_by_id: HashMap<i32, (usize, &OperatorResult)> = past
+        .iter()
+        .enumerate()
+        .map(|(i, r)| (r.id, (i + 1, r)))
+        .collect();
+    let current_ids: HashSet<i32> = current.iter().map(|r| r.id).collect();
+    let removed = past
+        .iter()
+        .filter(|r| !current_ids.contains(&r.id))
+        .map(|r| r.id)
+        .collect();
+
+    let total = current.len();
+    let items = current
+        .into_iter()
+        .enumerate()
+        .filter(|(i, r)| match past_by_id.get(&r.id) {
+            Some((past_rank, p)) => *past_rank != i + 1 || p.win != r.win || p.lose != r.lose,
+            None => true,
+        })
+        .map(|(i, r)| FinalOrderDeltaItem {
+            rank: i + 1,
+            item: FinalOrderItem::from(r),
+        })
+        .collect();
+
+    FinalOrderDeltaData {
+        topic_id,
+        since,
+        full,
+        total,
+        items,
+        removed,
+    }
+}
+
+#[post("/results/final_order_delta")]
+pub async fn results_final_order_delta_fn(
+    state: web::Data<AppState>,
+    web::Json(params): web::Json<FinalOrderDeltaQuery>,
+) -> actix_web::Result<impl Responder> {
+    let comparison = match load_ranking_comparison(&state, &params.topic_id, params.since).await {
+        Ok(comparison) => comparison,
+        Err((status, message)) => {
+            return Ok(ApiResponse {
+                status,
+                data: ApiData::Empty,
+                message,
+            });
+        }
+    };
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(compute_final_order_delta(comparison, params.since)),
+        message: ApiMsg::OK,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::results::results_final_order::sort_operator_results;
+
+    fn ranking(results: &[(i32, i64, i64)]) -> Vec<OperatorResult> {
+        let mut results: Vec<_> = results
+            .iter()
+            .map(|&(id, win, lose)| OperatorResult::new(format!("op_{id}"), id, win, lose))
+            .collect();
+        sort_operator_results(&mut results);
+        results
+    }
+
+    fn delta(current: &[(i32, i64, i64)], past: &[(i32, i64, i64)]) -> FinalOrderDeltaData {
+        let comparison = RankingComparison {
+            topic_id: "topic".to_string(),
+            current: ranking(current),
+            past: ranking(past),
+        };
+        compute_final_order_delta(comparison, Utc::now())
+    }
+
+    #[test]
+    fn test_delta_only_changed_operators() {
+        let data = delta(
+            &[(1, 90, 10), (2, 60, 40), (3, 20, 80), (5, 5, 95)],
+            &[(1, 90, 10), (2, 50, 50), (3, 20, 80), (4, 10, 90)],
+        );
+
+        assert!(!data.full);
+        assert_eq!(data.total, 4);
+        let changed: Vec<_> = data.items.iter().map(|i| (i.item.id, i.rank)).collect();
+        // 1 and 3 kept their rank and counts, 2 gained votes and 5 is new
+        assert_eq!(changed, [(2, 2), (5, 4)]);
+        assert_eq!(data.removed, [4]);
+    }
+
+    #[test]
+    fn test_delta_without_snapshot_is_full() {
+        let data = delta(&[(1, 90, 10), (2, 60, 40)], &[]);
+
+        assert!(data.full);
+        assert_eq!(data.total, 2);
+        assert_eq!(data.items.len(), 2);
+        assert!(data.removed.is_empty());
+    }
+}
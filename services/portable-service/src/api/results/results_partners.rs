@@ -0,0 +1,86 @@
+use std::{collections::HashMap, sync::Arc};
+
+use actix_web::{Responder, post, web};
+use redis::AsyncCommands;
+use share::{
+    metrics::CacheKind,
+    models::api::{
+        ApiData, ApiMsg, ApiResponse, ResponseStatus, ResultsPartnersRequest,
+        ResultsPartnersResponse,
+    },
+};
+
+use crate::{
+    AppState, record_cache_lookup,
+    state::{ResultsCacheValue, ResultsType},
+};
+
+#[post("/results/partners")]
+pub async fn results_partners_fn(
+    state: web::Data<AppState>,
+    web::Json(req): web::Json<ResultsPartnersRequest>,
+) -> actix_web::Result<impl Responder> {
+    let target_topic = match state.topic_service.get_topic(&req.topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
+                data: ApiData::Empty,
+                message: ApiMsg::TargetTopicNotFound,
+            });
+        }
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
+                data: ApiData::Empty,
+                message: ApiMsg::InternalError,
+            });
+        }
+    };
+
+    let cache_key = (target_topic.id.clone(), ResultsType::Partners);
+    let cooccur = match state.results_cache_store.get(&cache_key).await {
+        Some(ResultsCacheValue {
+            cooccur: Some(cooccur),
+            ..
+        }) => {
+            record_cache_lookup(CacheKind::Results, true);
+            cooccur
+        }
+        _ => {
+            record_cache_lookup(CacheKind::Results, false);
+
+            let mut conn = state.database.redis.connection.clone();
+            let cooccur: HashMap<String, i64> =
+                match conn.hgetall(req.topic_id.op_cooccur_key()).await {
+                    Ok(data) => data,
+                    Err(_) => {
+                        return Ok(ApiResponse {
+                            status: ResponseStatus::InternalError,
+                            data: ApiData::Empty,
+                            message: ApiMsg::InternalError,
+                        });
+                    }
+                };
+            let cooccur = Arc::new(cooccur);
+            let cached = ResultsCacheValue {
+                cooccur: Some(cooccur.clone()),
+                ..Default::default()
+            };
+            state.results_cache_store.insert(cache_key, cached).await;
+            cooccur
+        }
+    };
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(ResultsPartnersResponse::build(
+            target_topic.id,
+            req.operator_id,
+            &cooccur,
+            req.limit,
+        )),
+        message: ApiMsg::OK,
+    })
+}
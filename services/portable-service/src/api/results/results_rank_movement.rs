@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use actix_web::{Responder, post, web};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt as _;
+use mongodb::bson;
+use serde::{Deserialize, Serialize};
+use share::{
+    models::api::{ApiData, ApiMsg, ApiResponse, ResponseStatus},
+    scores::parse_operator_counts,
+};
+
+use crate::{api::generate_operators_info, state::AppState, timeseries::OperatorStatistics};
+
+use super::results_final_order::{OperatorResult, build_operator_results, sort_operator_results};
+
+#[derive(Debug, Deserialize)]
+pub struct RankMovementQuery {
+    pub topic_id: String,
+    pub snapshot_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RankMovementItem {
+    pub id: i32,
+    pub name: String,
+    /// 1-based rank in the live ranking, `None` if the operator has no live data.
+    pub current_rank: Option<usize>,
+    /// 1-based rank in the snapshot, `None` if the operator was not recorded yet.
+    pub past_rank: Option<usize>,
+    /// Positions gained since the snapshot, positive means the operator moved up.
+    pub delta: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RankMovementData {
+    pub topic_id: String,
+    pub snapshot_time: DateTime<Utc>,
+    pub items: Vec<RankMovementItem>,
+}
+
+fn compute_rank_movement(
+    current: &[OperatorResult],
+    past: &[OperatorResult],
+) -> Vec<RankMovementItem> {
+    let past_ranks: HashMap<i32, usize> = past
+        .iter()
+        .enumerate()
+        .map(|(i, r)| (r.id, i + 1))
+        .collect();
+
+    let mut items: Vec<RankMovementItem> = current
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let current_rank = i + 1;
+            let past_rank = past_ranks.get(&r.id).copied();
+            RankMovementItem {
+                id: r.id,
+                name: r.name.clone(),
+                current_rank: Some(current_rank),
+                past_rank,
+                delta: past_rank.map(|p| p as i64 - current_rank as i64),
+            }
+        })
+        .collect();
+
+    // operators only present in the snapshot are appended after the live ranking
+    let current_ids: Vec<i32> = current.iter().map(|r| r.id).collect();
+    items.extend(
+        past.iter()
+            .enumerate()
+            .filter(|(_, r)| !current_ids.contains(&r.id))
+            .map(|(i, r)| RankMovementItem {
+                id: r.id,
+                name: r.name.clone(),
+                current_rank: None,
+                past_rank: Some(i + 1),
+                delta: None,
+            }),
+    );
+
+    items
+}
+
+/// Live ranking of a topic next to its ranking at `snapshot_time`, both sorted.
+pub(super) struct RankingComparison {
+    pub(super) topic_id: String,
+    pub(super) current: Vec<OperatorResult>,
+    /// Empty when no `operator_rates` sample exists at or before the snapshot.
+    pub(super) past: Vec<OperatorResult>,
+}
+
+/// Loads the live ranking and the latest `operator_rates` sample of each
+/// operator taken at or before `snapshot_time`. Errors carry the status and
+/// message to answer with.
+pub(super) async fn load_ranking_comparison(
+    state: &AppState,
+    topic_id: &str,
+    snapshot_time: DateTime<Utc>,
+) -> Result<RankingComparison, (ResponseStatus, ApiMsg)> {
+    let target_topic = match state.topic_service.get_topic(topic_id).await {
+        Ok(Some(topic)) if topic.topic_type.supports_final_order() => topic,
+        Ok(Some(_)) => {
+            return Err((
+                ResponseStatus::BadRequest,
+                ApiMsg::CurTopicNotSupportFinalOrder,
+            ));
+        }
+        Ok(None) => return Err((ResponseStatus::NotFound, ApiMsg::TargetTopicNotFound)),
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", topic_id, e);
+            return Err((ResponseStatus::InternalError, ApiMsg::InternalError));
+        }
+    };
+
+    let candidate_pool = state
+        .topic_service
+        .get_candidate_pool(&target_topic.id, &state.character_infos)
+        .await
+        .map_err(|err| (ResponseStatus::NotFound, err.into()))?;
+    let operators_info = generate_operators_info(&candidate_pool, &state.character_infos);
+
+    // live ranking
+    let mut conn = state.database.redis.connection.clone();
+    let (operator_values, _): (Vec<Option<String>>, Option<i64>) = state
+        .database
+        .redis
+        .final_order_script
+        .key(&target_topic.id)
+        .arg(&operators_info.op_stats_all_fields)
+        .invoke_async(&mut conn)
+        .await
+        .map_err(|err| {
+            tracing::error!("Failed to execute Lua script for rank comparison: {}", err);
+            (ResponseStatus::InternalError, ApiMsg::InternalError)
+        })?;
+    let Some((win_counts, lose_counts)) =
+        parse_operator_counts(&operator_values, operators_info.num_operators)
+    else {
+        tracing::error!(
+            "Final order script for topic {} returned {} values, expected {}",
+            target_topic.id,
+            operator_values.len(),
+            2 * operators_info.num_operators
+        );
+        return Err((ResponseStatus::InternalError, ApiMsg::InternalError));
+    };
+    let mut current = build_operator_results(
+        &operators_info.operator_ids,
+        &operators_info.reverse_operators_id_dict,
+        &win_counts,
+        &lose_counts,
+    );
+    sort_operator_results(&mut current);
+
+    // snapshot ranking, from the latest `operator_rates` sample of each operator
+    let collection = state
+        .database
+        .mongo_read_database
+        .collection::<OperatorStatistics>("operator_rates");
+    let pipeline = vec![
+        bson::doc! {
+            "$match": {
+                "ts": { "$lte": bson::DateTime::from_millis(snapshot_time.timestamp_millis()) },
+                "operator_id": { "$in": &operators_info.operator_ids },
+            }
+        },
+        bson::doc! { "$sort": { "ts": -1 } },
+        bson::doc! {
+            "$group": {
+                "_id": "$operator_id",
+                "win": { "$first": "$win" },
+                "lose": { "$first": "$lose" },
+            }
+        },
+    ];
+
+    let mut cursor = collection.aggregate(pipeline).await.map_err(|err| {
+        tracing::error!("Failed to query operator snapshot: {}", err);
+        (ResponseStatus::InternalError, ApiMsg::InternalError)
+    })?;
+
+    let mut past = Vec::new();
+    while let Some(doc) = cursor.try_next().await.map_err(|err| {
+        tracing::error!("Failed to read operator snapshot: {}", err);
+        (ResponseStatus::InternalError, ApiMsg::InternalError)
+    })? {
+        let (Ok(id), Ok(win), Ok(lose)) =
+            (doc.get_i32("_id"), doc.get_i64("win"), doc.get_i64("lose"))
+        else {
+            continue;
+        };
+        let name = operators_info
+            .reverse_operators_id_dict
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| id.to_string());
+        past.push(OperatorResult::new(name, id, win, lose));
+    }
+    sort_operator_results(&mut past);
+
+    Ok(RankingComparison {
+        topic_id: target_topic.id,
+        current,
+        past,
+    })
+}
+
+#[post("/results/rank_movement")]
+pub async fn results_rank_movement_fn(
+    state: web::Data<AppState>,
+    web::Json(params): web::Json<RankMovementQuery>,
+) -> actix_web::Result<impl Responder> {
+    let comparison =
+        match load_ranking_comparison(&state, &params.topic_id, params.snapshot_time).await {
+            Ok(comparison) => comparison,
+            Err((status, message)) => {
+                return Ok(ApiResponse {
+                    status,
+                    data: ApiData::Empty,
+                    message,
+                });
+            }
+        };
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(RankMovementData {
+            topic_id: comparison.topic_id,
+            snapshot_time: params.snapshot_time,
+            items: compute_rank_movement(&comparison.current, &comparison.past),
+        }),
+        message: ApiMsg::OK,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(id: i32, win: i64, lose: i64) -> OperatorResult {
+        OperatorResult::new(format!("op_{id}"), id, win, lose)
+    }
+
+    #[test]
+    fn test_compute_rank_movement() {
+        let mut current = vec![result(1, 80, 20), result(2, 50, 50), result(3, 90, 10)];
+        let mut past = vec![result(1, 70, 30), result(2, 60, 40), result(4, 10, 90)];
+        sort_operator_results(&mut current);
+        sort_operator_results(&mut past);
+
+        let items = compute_rank_movement(&current, &past);
+        let by_id: HashMap<i32, &RankMovementItem> = items.iter().map(|i| (i.id, i)).collect();
+
+        assert_eq!(items.len(), 4);
+
+        // 3: new operator, ranked first now
+        assert_eq!(by_id[&3].current_rank, Some(1));
+        assert_eq!(by_id[&3].past_rank, None);
+        assert_eq!(by_id[&3].delta, None);
+
+        // 1: first -> second
+        assert_eq!(by_id[&1].current_rank, Some(2));
+        assert_eq!(by_id[&1].past_rank, Some(1));
+        assert_eq!(by_id[&1].delta, Some(-1));
+
+        // 2: second -> third
+        assert_eq!(by_id[&2].delta, Some(-1));
+
+        // 4: removed from the live ranking
+        assert_eq!(by_id[&4].current_rank, None);
+        assert_eq!(by_id[&4].past_rank, Some(3));
+        assert_eq!(items.last().unwrap().id, 4);
+    }
+}
@@ -3,7 +3,7 @@ use chrono::{DateTime, Utc};
 use futures::TryStreamExt as _;
 use mongodb::bson;
 use serde::{Deserialize, Serialize};
-use share::models::api::{ApiData, ApiMsg, ApiResponse};
+use share::models::api::{ApiData, ApiMsg, ApiResponse, ResponseStatus};
 
 use crate::{api::OperatorsInfo, error::AppError, state::AppState, timeseries::OperatorStatistics};
 
@@ -74,7 +74,7 @@ pub async fn results_operator_timeline_fn(
 ) -> actix_web::Result<impl Responder> {
     let collection = state
         .database
-        .mongo_database
+        .mongo_read_database
         .collection::<OperatorStatistics>("operator_rates");
 
     // 构建查询条件
@@ -110,11 +110,11 @@ pub async fn results_operator_timeline_fn(
     let mut cursor: mongodb::Cursor<bson::Document> = match collection.aggregate(pipeline).await {
         Ok(cursor) => cursor,
         Err(_) => {
-            return Ok(web::Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
                 message: ApiMsg::InternalError,
-            }));
+            });
         }
     };
 
@@ -130,13 +130,13 @@ pub async fn results_operator_timeline_fn(
         .get_candidate_pool(&params.topic_id, &state.character_infos)
         .await
     {
-        Some(pool) => pool,
-        None => {
-            return Ok(web::Json(ApiResponse {
-                status: 404,
+        Ok(pool) => pool,
+        Err(err) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: err.into(),
+            });
         }
     };
     let operators_info =
@@ -150,15 +150,15 @@ pub async fn results_operator_timeline_fn(
         operator_count: operators.len(),
     };
 
-    Ok(web::Json(ApiResponse {
-        status: 200,
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
         data: ApiData::Data(TimelineData {
             timeline: timeline_points,
             operators,
             summary,
         }),
         message: ApiMsg::OK,
-    }))
+    })
 }
 
 fn build_timeline_pipeline(
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use actix_web::{post, web};
+use futures::TryStreamExt as _;
+use mongodb::bson::{Bson, Document, doc};
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, MAX_VELOCITY_BUCKETS, ResponseStatus, ResultsVelocityRequest,
+    ResultsVelocityResponse,
+};
+
+use crate::{AppState, error::AppError};
+
+/// Counts the stored ballots of each bucket by their timestamp. Stored
+/// ballots exist for every topic and count votes exactly, the
+/// `operator_rates` snapshots only cover one topic and hold weighted scores.
+#[post("/results/velocity")]
+pub async fn results_velocity_fn(
+    state: web::Data<AppState>,
+    web::Json(req): web::Json<ResultsVelocityRequest>,
+) -> Result<ApiResponse<ResultsVelocityResponse>, AppError> {
+    let Some(bucket_count) = req.bucket_count() else {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::InvalidVelocityWindow(MAX_VELOCITY_BUCKETS),
+        });
+    };
+    let Some(topic) = state.topic_service.get_topic(&req.topic_id).await? else {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    };
+
+    let start_ms = req.start_time.timestamp_millis();
+    let bucket_ms = req.bucket_ms();
+    let end_ms = start_ms + bucket_count as i64 * bucket_ms;
+    let pipeline = vec![
+        doc! { "$match": { "info.timestamp": { "$gte": start_ms, "$lt": end_ms } } },
+        doc! {
+            "$group": {
+                "_id": {
+                    "$subtract": [
+                        "$info.timestamp",
+                        { "$mod": [{ "$subtract": ["$info.timestamp", start_ms] }, bucket_ms] },
+                    ]
+                },
+                "count": { "$sum": 1 },
+            }
+        },
+    ];
+
+    let mut cursor = state
+        .database
+        .mongo_read_database
+        .collection::<Document>(&format!("ballots_{}", topic.id))
+        .aggregate(pipeline)
+        .await?;
+    let mut counts = HashMap::new();
+    while let Some(group) = cursor.try_next().await? {
+        let count = match group.get("count") {
+            Some(Bson::Int32(count)) => *count as u64,
+            Some(Bson::Int64(count)) => *count as u64,
+            _ => continue,
+        };
+        if let Ok(bucket_start) = group.get_i64("_id") {
+            counts.insert(bucket_start, count);
+        }
+    }
+
+    let sample_rate = topic
+        .vote_overrides
+        .ballot_store_sample_rate
+        .unwrap_or(state.vote.ballot_store_sample_rate);
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(ResultsVelocityResponse::build(
+            &req,
+            bucket_count,
+            &counts,
+            sample_rate,
+        )),
+        message: ApiMsg::OK,
+    })
+}
@@ -2,8 +2,10 @@ mod topic_candidate_pool;
 mod topic_create;
 mod topic_info;
 mod topic_list_active;
+mod topic_validate_pool;
 
 pub use topic_candidate_pool::topic_candidate_pool_fn;
 pub use topic_create::topic_create_fn;
 pub use topic_info::topic_info_fn;
 pub use topic_list_active::topic_list_active_fn;
+pub use topic_validate_pool::topic_validate_pool_fn;
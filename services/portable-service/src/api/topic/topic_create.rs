@@ -1,8 +1,13 @@
 use actix_web::{post, web};
 use chrono::Utc;
-use share::models::{
-    api::{ApiData, ApiMsg, ApiResponse, TopicCreateRequest, TopicCreateResponse},
-    database::{CreateTopicStatus, VotingTopic},
+use share::{
+    bootstrap::ensure_ballot_index,
+    models::{
+        api::{
+            ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicCreateRequest, TopicCreateResponse,
+        },
+        database::{CreateTopicStatus, VotingTopic},
+    },
 };
 use uuid::Uuid;
 
@@ -12,15 +17,22 @@ use crate::{AppState, error::AppError};
 pub async fn topic_create_fn(
     state: web::Data<AppState>,
     web::Json(req): web::Json<TopicCreateRequest>,
-) -> Result<web::Json<ApiResponse<TopicCreateResponse>>, AppError> {
+) -> Result<ApiResponse<TopicCreateResponse>, AppError> {
     if true {
-        return Ok(web::Json(ApiResponse {
-            status: 403,
+        return Ok(ApiResponse {
+            status: ResponseStatus::Forbidden,
             data: ApiData::Empty,
             message: ApiMsg::EndpointForbidden,
-        }));
+        });
+    }
+
+    if let Some(disabled) =
+        ApiResponse::topic_type_disabled(&state.vote.enabled_topic_types, &req.topic_type)
+    {
+        return Ok(disabled);
     }
 
+    let idempotent = req.idempotent;
     let topic = VotingTopic {
         id: if req.id.is_empty() {
             Uuid::new_v4().to_string()
@@ -38,25 +50,89 @@ pub async fn topic_create_fn(
         close_time: req.close_time,
         is_active: false,
         status: CreateTopicStatus::WaitingAudit,
+        vote_overrides: Default::default(),
+        frozen_pool: None,
+        paused: false,
     };
 
+    if let Err(e) = topic.validate(state.topic_service.max_preset_depth()) {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: e.into(),
+        });
+    }
+
+    match state.topic_service.get_topic(&topic.id).await {
+        Ok(Some(existing)) => return Ok(existing_topic_response(existing, &topic, idempotent)),
+        Ok(None) => {}
+        Err(e) => {
+            tracing::error!(
+                "Failed to look up topic {} before creating it: {}",
+                topic.id,
+                e
+            );
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
+                data: ApiData::Empty,
+                message: ApiMsg::TopicCreateFailed,
+            });
+        }
+    }
+
     match state.topic_service.create_topic(&topic).await {
-        Ok(_) => Ok(web::Json(ApiResponse {
-            status: 0,
-            data: ApiData::Data(TopicCreateResponse {
-                id: topic.id,
-                is_active: topic.is_active,
-                status: topic.status,
-            }),
-            message: ApiMsg::OK,
-        })),
+        Ok(None) => {
+            if let Err(e) = ensure_ballot_index(&state.database.mongo_database, &topic.id).await {
+                tracing::warn!("Failed to index ballots of topic {}: {}", topic.id, e);
+            }
+
+            Ok(ApiResponse {
+                status: ResponseStatus::Ok,
+                data: ApiData::Data(TopicCreateResponse {
+                    id: topic.id,
+                    is_active: topic.is_active,
+                    status: topic.status,
+                    already_existed: false,
+                }),
+                message: ApiMsg::OK,
+            })
+        }
+        // inserted by a concurrent request since the lookup above
+        Ok(Some(existing)) => Ok(existing_topic_response(existing, &topic, idempotent)),
         Err(e) => {
             tracing::error!("Failed to create topic: {}", e);
-            Ok(web::Json(ApiResponse {
-                status: 500,
+            Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
                 message: ApiMsg::TopicCreateFailed,
-            }))
+            })
+        }
+    }
+}
+
+/// Answers a creation whose topic id is taken: an idempotent retry with the
+/// same content gets the stored topic back, anything else is a conflict.
+fn existing_topic_response(
+    existing: VotingTopic,
+    topic: &VotingTopic,
+    idempotent: bool,
+) -> ApiResponse<TopicCreateResponse> {
+    if idempotent && existing.same_creation_content(topic) {
+        ApiResponse {
+            status: ResponseStatus::Ok,
+            data: ApiData::Data(TopicCreateResponse {
+                id: existing.id,
+                is_active: existing.is_active,
+                status: existing.status,
+                already_existed: true,
+            }),
+            message: ApiMsg::OK,
+        }
+    } else {
+        ApiResponse {
+            status: ResponseStatus::Conflict,
+            data: ApiData::Empty,
+            message: ApiMsg::TopicIdTaken,
         }
     }
 }
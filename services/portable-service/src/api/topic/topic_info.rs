@@ -1,5 +1,7 @@
 use actix_web::{post, web};
-use share::models::api::{ApiData, ApiMsg, ApiResponse, TopicInfoRequest, TopicInfoResponse};
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicInfoRequest, TopicInfoResponse,
+};
 
 use crate::{AppState, error::AppError};
 
@@ -7,30 +9,22 @@ use crate::{AppState, error::AppError};
 pub async fn topic_info_fn(
     state: web::Data<AppState>,
     web::Json(req): web::Json<TopicInfoRequest>,
-) -> Result<web::Json<ApiResponse<TopicInfoResponse>>, AppError> {
+) -> Result<ApiResponse<TopicInfoResponse>, AppError> {
     match state.topic_service.get_topic(&req.topic_id).await {
-        Ok(Some(topic)) => Ok(web::Json(ApiResponse {
-            status: 0,
-            data: ApiData::Data(TopicInfoResponse {
-                id: topic.id,
-                name: topic.name,
-                title: topic.title,
-                description: topic.description,
-                topic_type: topic.topic_type,
-                open_time: topic.open_time,
-                close_time: topic.close_time,
-            }),
+        Ok(Some(topic)) => Ok(ApiResponse {
+            status: ResponseStatus::Ok,
+            data: ApiData::Data(TopicInfoResponse::from(topic)),
             message: ApiMsg::OK,
-        })),
-        Ok(None) => Ok(web::Json(ApiResponse {
-            status: 404,
+        }),
+        Ok(None) => Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
             data: ApiData::Empty,
             message: ApiMsg::TargetTopicNotFound,
-        })),
-        Err(_) => Ok(web::Json(ApiResponse {
-            status: 500,
+        }),
+        Err(_) => Ok(ApiResponse {
+            status: ResponseStatus::InternalError,
             data: ApiData::Empty,
             message: ApiMsg::InternalError,
-        })),
+        }),
     }
 }
@@ -0,0 +1,26 @@
+use actix_web::{post, web};
+use share::models::{
+    api::{ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicValidatePoolResponse},
+    candidate_pool_preset::CandidatePoolPreset,
+};
+
+use crate::AppState;
+
+#[post("/topic/validate_pool")]
+pub async fn topic_validate_pool_fn(
+    state: web::Data<AppState>,
+    web::Json(preset): web::Json<CandidatePoolPreset>,
+) -> ApiResponse<TopicValidatePoolResponse> {
+    let max_depth = state.topic_service.max_preset_depth();
+    let result = preset.validate_pool(&state.character_infos, max_depth, |preset| {
+        state
+            .topic_service
+            .resolve_pool(preset, &state.character_infos)
+    });
+
+    ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(result.into()),
+        message: ApiMsg::OK,
+    }
+}
@@ -1,5 +1,3 @@
-pub const BALLOT_CODE_RANDOM_LENGTH: usize = 8;
-
 pub const LUA_SCRIPT_GET_FINAL_ORDER: &str = r#"
 local topic_id = KEYS[1]
 local fields = ARGV
@@ -13,6 +11,66 @@ local total_ballots = redis.call('GET', valid_ballots_key)
 return {stats, total_ballots}
 "#;
 
+pub const LUA_SCRIPT_GET_FINAL_ORDER_TOP: &str = r#"
+-- KEYS[1]: topic_id
+-- ARGV[1]: number of items to return, ARGV[2..]: candidate pool operator ids
+-- Ranks the pool by rate, then win - lose, then wins, then lower id first,
+-- the same order as the Rust side, and returns the first ARGV[1] operators
+-- as flat {id, win, lose} triples along with the valid ballot count.
+local topic_id = KEYS[1]
+local limit = tonumber(ARGV[1])
+local stats_key = topic_id .. ':op_stats'
+
+local ranked = {}
+-- HMGET in chunks, unpack() is limited by the Lua stack size
+local chunk_size = 500
+for first = 2, #ARGV, chunk_size do
+    local last = math.min(first + chunk_size - 1, #ARGV)
+    local fields = {}
+    for i = first, last do
+        fields[#fields + 1] = ARGV[i] .. ':win'
+        fields[#fields + 1] = ARGV[i] .. ':lose'
+    end
+
+    local stats = redis.call('HMGET', stats_key, unpack(fields))
+    for i = first, last do
+        local offset = 2 * (i - first)
+        local win = tonumber(stats[offset + 1]) or 0
+        local lose = tonumber(stats[offset + 2]) or 0
+        local total = win + lose
+        local rate = 0
+        if total > 0 then
+            rate = win * 100 / total
+        end
+        ranked[#ranked + 1] = {tonumber(ARGV[i]), win, lose, rate, win - lose}
+    end
+end
+
+table.sort(ranked, function(a, b)
+    if a[4] ~= b[4] then
+        return a[4] > b[4]
+    end
+    if a[5] ~= b[5] then
+        return a[5] > b[5]
+    end
+    if a[2] ~= b[2] then
+        return a[2] > b[2]
+    end
+    return a[1] < b[1]
+end)
+
+local top = {}
+for i = 1, math.min(limit, #ranked) do
+    top[#top + 1] = ranked[i][1]
+    top[#top + 1] = ranked[i][2]
+    top[#top + 1] = ranked[i][3]
+end
+
+local total_ballots = redis.call('GET', topic_id .. ':valid_ballots_count')
+
+return {top, total_ballots}
+"#;
+
 pub const LUA_SCRIPT_BATCH_IP_COUNTER_SCRIPT: &str = r#"
 local expire_seconds = ARGV[1]
 local max_ip_limit = tonumber(ARGV[2])
@@ -42,61 +100,68 @@ return results
 
 pub const LUA_SCRIPT_BATCH_SCORE_UPDATE_SCRIPT: &str = r#"
 -- KEYS: empty (we use ARGV for dynamic key generation)
--- ARGV: topic_id1, win_id1, lose_id1, multiplier1, topic_id2, win_id2, lose_id2, multiplier2, ...
--- Each score update takes 4 arguments: topic_id, win_id, lose_id, multiplier
+-- ARGV: score_ceiling, topic_id1, win_id1, lose_id1, multiplier1, games1, topic_id2, ...
+-- Each score update takes 5 arguments: topic_id, win_id, lose_id, multiplier,
+-- games. games is the number of comparisons summed into multiplier, counted
+-- in both <topic_id>:op_counter (games behind op_matrix) and
+-- <topic_id>:op_cooccur under "min:max", as wins of win_id in op_counter under
+-- "min:max:a_wins" (win_id is min) or "min:max:b_wins", and in
+-- <topic_id>:valid_ballots_count. All of it goes in one run so a retried batch
+-- is either fully counted or not at all.
+-- score_ceiling caps every op_stats win/lose counter, 0 leaves them unbounded.
+-- HINCRBY errors instead of wrapping once a counter would overflow i64, which
+-- aborts the remaining updates of the batch; the ceiling keeps counters clear of it.
 
 -- local valid_ballots_count = KEYS[1]
-local arg_count = #ARGV
+local score_ceiling = tonumber(ARGV[1])
+local arg_count = #ARGV - 1
 
--- 确保参数数量是4的倍数
-if arg_count % 4 ~= 0 then
-    return redis.error_reply("invalid argument count: must be multiple of 4")
+-- 确保参数数量是5的倍数
+if arg_count % 5 ~= 0 then
+    return redis.error_reply("invalid argument count: must be multiple of 5")
 end
 
-for i = 1, arg_count, 4 do
-    local topic_id = ARGV[i]
-    local win_id = tonumber(ARGV[i + 1])
-    local lose_id = tonumber(ARGV[i + 2])
-    local multiplier = tonumber(ARGV[i + 3])
-
-    local op_stats_key = topic_id .. ":op_stats"
-    local op_matrix_key = topic_id .. ":op_matrix"
-
-    redis.call("HINCRBY", op_stats_key, win_id..":win", multiplier)
-    redis.call("HINCRBY", op_stats_key, lose_id..":lose", multiplier)
-    redis.call("HINCRBY", op_matrix_key, win_id..":"..lose_id, multiplier)
-    redis.call("HINCRBY", op_matrix_key, lose_id..":"..win_id, -multiplier)
-
-    local valid_ballots_key = topic_id .. ":valid_ballots_count"
-    redis.call("INCR", valid_ballots_key)
+-- 计数不允许为负，且不超过 score_ceiling
+local function incr_stat(key, field, amount)
+    local value = redis.call("HINCRBY", key, field, amount)
+    if value < 0 then
+        redis.call("HSET", key, field, 0)
+    elseif score_ceiling > 0 and value > score_ceiling then
+        redis.call("HSET", key, field, score_ceiling)
+    end
 end
 
-return 1
-"#;
+local refused = 0
 
-pub const LUA_SCRIPT_BATCH_RECORD_1V1_SCRIPT: &str = r#"
--- ARGV: topic_id1, operator1, operator1_1, topic_id2,
-local arg_count = #ARGV
-
--- 确保参数数量是3的倍数
-if arg_count % 3 ~= 0 then
-    return redis.error_reply("invalid argument count: must be multiple of 3")
-end
-
-for i = 1, arg_count, 3 do
+for i = 2, arg_count + 1, 5 do
     local topic_id = ARGV[i]
-    local operator = tonumber(ARGV[i + 1])
-    local operator2 = tonumber(ARGV[i + 2])
+    local win_id = tonumber(ARGV[i + 1])
+    local lose_id = tonumber(ARGV[i + 2])
+    local multiplier = tonumber(ARGV[i + 3])
+    local games = tonumber(ARGV[i + 4])
 
-    -- 保证 operator < operator2，如果不是就交换
-    if operator > operator2 then
-        operator, operator2 = operator2, operator
+    if multiplier < 0 then
+        refused = refused + 1
+    else
+        local op_stats_key = topic_id .. ":op_stats"
+        local op_matrix_key = topic_id .. ":op_matrix"
+
+        incr_stat(op_stats_key, win_id..":win", multiplier)
+        incr_stat(op_stats_key, lose_id..":lose", multiplier)
+        redis.call("HINCRBY", op_matrix_key, win_id..":"..lose_id, multiplier)
+        redis.call("HINCRBY", op_matrix_key, lose_id..":"..win_id, -multiplier)
+
+        local op_counter_key = topic_id .. ":op_counter"
+        local pair = math.min(win_id, lose_id) .. ":" .. math.max(win_id, lose_id)
+        local wins = win_id < lose_id and ":a_wins" or ":b_wins"
+        redis.call("HINCRBY", op_counter_key, pair, games)
+        redis.call("HINCRBY", op_counter_key, pair .. wins, games)
+        redis.call("HINCRBY", topic_id .. ":op_cooccur", pair, games)
+
+        local valid_ballots_key = topic_id .. ":valid_ballots_count"
+        redis.call("INCRBY", valid_ballots_key, games)
     end
-
-    local op_counter_key = topic_id .. ":op_counter"
-    local key = operator .. ":" .. operator2
-    redis.call("HINCRBY", op_counter_key, key, 1)
 end
 
-return 1
+return refused
 "#;
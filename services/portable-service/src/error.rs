@@ -1,6 +1,6 @@
-use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use actix_web::{HttpResponse, ResponseError, error::JsonPayloadError, http::StatusCode, web};
 use redis::RedisError;
-use share::models::api::{ApiData, ApiMsg, ApiResponse};
+use share::models::api::{ApiData, ApiMsg, ApiResponse, ResponseStatus};
 
 #[derive(thiserror::Error, Debug)]
 pub enum AppError {
@@ -16,60 +16,194 @@ pub enum AppError {
     InsufficientOperators,
     #[error("mongo db error: {0}")]
     MongoDb(#[from] mongodb::error::Error),
-    #[error("missing character table json file")]
-    MissingCharacterTableJson,
     #[error("reqwest error: {0}")]
     Reqwest(#[from] reqwest::Error),
     #[error("value access error: {0}")]
     ValueAccess(#[from] mongodb::bson::document::ValueAccessError),
+    #[error("json payload error: {0}")]
+    JsonPayload(#[from] JsonPayloadError),
+    #[error("handler panicked")]
+    HandlerPanicked,
+}
+
+/// `JsonConfig` answering a wrong content type or a malformed body with the
+/// `ApiResponse` envelope instead of actix's plain text error.
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(|err, _req| AppError::from(err).into())
+}
+
+/// The actix status code for `status`, actix is on a different `http`
+/// version than `ResponseStatus::http_status`.
+pub fn http_status(status: ResponseStatus) -> StatusCode {
+    StatusCode::from_u16(status.http_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+impl AppError {
+    fn response_status(&self) -> ResponseStatus {
+        match self {
+            AppError::JsonPayload(
+                JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. },
+            ) => ResponseStatus::PayloadTooLarge,
+            AppError::SerdeJson(_) | AppError::JsonPayload(_) | AppError::InsufficientOperators => {
+                ResponseStatus::BadRequest
+            }
+            AppError::Reqwest(_) => ResponseStatus::BadGateway,
+            AppError::Redis(_)
+            | AppError::Snowflake(_)
+            | AppError::Io(_)
+            | AppError::MongoDb(_)
+            | AppError::ValueAccess(_)
+            | AppError::HandlerPanicked => ResponseStatus::InternalError,
+        }
+    }
+
+    /// Internal failures share `InternalError`, the cause is only logged.
+    fn message(&self) -> ApiMsg {
+        match self {
+            AppError::JsonPayload(
+                JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. },
+            ) => ApiMsg::PayloadTooLarge,
+            AppError::SerdeJson(_) | AppError::JsonPayload(_) => ApiMsg::InvalidJson,
+            AppError::InsufficientOperators => ApiMsg::InsufficientOperators,
+            AppError::Reqwest(_) => ApiMsg::UpstreamUnavailable,
+            AppError::Redis(_)
+            | AppError::Snowflake(_)
+            | AppError::Io(_)
+            | AppError::MongoDb(_)
+            | AppError::ValueAccess(_)
+            | AppError::HandlerPanicked => ApiMsg::InternalError,
+        }
+    }
 }
 
 impl ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
-        let (status_code, error_msg) = match self {
-            AppError::Redis(_) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Database connection error",
-            ),
-            AppError::Snowflake(_) => (StatusCode::INTERNAL_SERVER_ERROR, "ID generation error"),
-            AppError::SerdeJson(_) => (StatusCode::BAD_REQUEST, "Invalid JSON format"),
-            AppError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "File system error"),
-            AppError::InsufficientOperators => (
-                StatusCode::BAD_REQUEST,
-                "Insufficient operators available for comparison",
-            ),
-            AppError::MongoDb(_) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Database operation failed",
-            ),
-            AppError::MissingCharacterTableJson => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Missing character table configuration",
-            ),
-            AppError::Reqwest(_) => (StatusCode::BAD_GATEWAY, "External service unavailable"),
-            AppError::ValueAccess(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Data access error"),
-        };
+        if self.response_status() == ResponseStatus::InternalError {
+            tracing::error!("Request failed: {}", self);
+        }
 
         let error_response = ApiResponse {
-            status: status_code.as_u16() as i32,
+            status: self.response_status(),
             data: ApiData::Empty::<()>,
-            message: ApiMsg::Error(error_msg.to_string()),
+            message: self.message(),
         };
 
-        HttpResponse::build(status_code).json(error_response)
+        HttpResponse::build(self.status_code()).json(error_response)
     }
 
     fn status_code(&self) -> StatusCode {
-        match self {
-            AppError::Redis(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::Snowflake(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::SerdeJson(_) => StatusCode::BAD_REQUEST,
-            AppError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::InsufficientOperators => StatusCode::BAD_REQUEST,
-            AppError::MongoDb(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::MissingCharacterTableJson => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::Reqwest(_) => StatusCode::BAD_GATEWAY,
-            AppError::ValueAccess(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        http_status(self.response_status())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, body::MessageBody as _, test as actix_test};
+    use share::models::api::BallotCreateRequest;
+
+    use super::*;
+
+    fn response_json(error: AppError) -> (StatusCode, serde_json::Value) {
+        let response = error.error_response();
+        let status = response.status();
+        let body = response.into_body().try_into_bytes().unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn test_error_messages_are_structured() {
+        let (status, body) = response_json(AppError::InsufficientOperators);
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "InsufficientOperators");
+
+        let io = std::io::Error::other("disk gone");
+        let (status, body) = response_json(AppError::Io(io));
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["message"], "InternalError");
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_json_returns_api_response() {
+        let app = actix_test::init_service(App::new().app_data(json_config(64)).route(
+            "/ballot/new",
+            web::post().to(|_: web::Json<BallotCreateRequest>| async { "ok" }),
+        ))
+        .await;
+
+        let req = actix_test::TestRequest::post()
+            .uri("/ballot/new")
+            .insert_header(("content-type", "application/json"))
+            .set_payload("{\"topic_id\": ")
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = actix_test::read_body_json(res).await;
+        assert_eq!(body["status"], 400);
+        assert_eq!(body["message"], "InvalidJson");
+        assert!(body["data"].is_null());
+
+        let req = actix_test::TestRequest::post()
+            .uri("/ballot/new")
+            .insert_header(("content-type", "text/plain"))
+            .set_payload(r#"{"topic_id": "crisis_v2_season_4_1"}"#)
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = actix_test::TestRequest::post()
+            .uri("/ballot/new")
+            .set_json(serde_json::json!({ "topic_id": "a".repeat(128) }))
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body: serde_json::Value = actix_test::read_body_json(res).await;
+        assert_eq!(body["message"], "PayloadTooLarge");
+    }
+
+    #[actix_web::test]
+    async fn test_http_status_follows_envelope_status() {
+        async fn respond(status: ResponseStatus) -> actix_web::Result<ApiResponse<()>> {
+            Ok(ApiResponse {
+                status,
+                data: ApiData::Empty,
+                message: ApiMsg::TargetTopicNotFound,
+            })
+        }
+        let app = actix_test::init_service(
+            App::new()
+                .route("/ok", web::post().to(|| respond(ResponseStatus::Ok)))
+                .route(
+                    "/missing",
+                    web::post().to(|| respond(ResponseStatus::NotFound)),
+                )
+                .route(
+                    "/negotiated",
+                    web::post().to(|req: actix_web::HttpRequest| async move {
+                        crate::utils::negotiate(
+                            &req,
+                            ApiResponse::<share::models::api::ResultsFinalOrderResponse> {
+                                status: ResponseStatus::NotFound,
+                                data: ApiData::Empty,
+                                message: ApiMsg::TargetTopicNotFound,
+                            },
+                        )
+                    }),
+                ),
+        )
+        .await;
+
+        for (uri, http, status) in [
+            ("/ok", StatusCode::OK, 0),
+            ("/missing", StatusCode::NOT_FOUND, 404),
+            ("/negotiated", StatusCode::NOT_FOUND, 404),
+        ] {
+            let req = actix_test::TestRequest::post().uri(uri).to_request();
+            let res = actix_test::call_service(&app, req).await;
+            assert_eq!(res.status(), http, "{uri}");
+            let body: serde_json::Value = actix_test::read_body_json(res).await;
+            assert_eq!(body["status"], status, "{uri}");
         }
     }
 }
@@ -8,25 +8,34 @@ use actix_cors::Cors;
 use actix_web::{http::header, middleware, web};
 use actix_web_prom::PrometheusMetricsBuilder;
 use eyre::Context;
-use moka::future::Cache;
-use mongodb::bson::doc;
+use moka::{future::Cache, notification::RemovalCause};
 use once_cell::sync::Lazy;
 use share::{
+    auth::AdminTokens,
+    bootstrap::{self, VoteSettings},
     config::AppConfig,
-    models::{database::VotingTopic, excel::CharacterInfo},
+    metrics::{CACHE_OUTCOME_LABEL, CacheKind, cache_outcome},
+    portrait::{self, PortraitStore},
+    reputation::USER_TOKEN_HEADER,
+    search::OperatorAliases,
+    selection::{OperatorGamesCache, SelectionRngs, SubProfessionBlocklist},
     snowflake::Snowflake,
 };
 
 use crate::{
     api::{
-        audit_topic_fn, audit_topics_list_fn, ballot_create_fn, ballot_save_fn, ballot_skip_fn,
-        bench_ballot_create_fn, bench_ballot_save_fn, results_1v1_matrix_fn,
-        results_final_order_fn, results_operator_timeline_fn, topic_candidate_pool_fn,
-        topic_create_fn, topic_info_fn, topic_list_active_fn,
+        admin_config, audit_topic_fn, audit_topics_list_fn, ballot_create_fn, ballot_my_stats_fn,
+        ballot_save_fn, ballot_skip_batch_fn, ballot_skip_fn, bench_ballot_create_fn,
+        bench_ballot_save_fn, catch_panic, operators_list_fn, require_admin_token,
+        results_1v1_matrix_fn, results_by_profession_fn, results_condorcet_fn,
+        results_dominant_matchups_fn, results_final_order_delta_fn, results_final_order_fn,
+        results_operator_timeline_fn, results_partners_fn, results_rank_movement_fn,
+        results_velocity_fn, topic_candidate_pool_fn, topic_create_fn, topic_info_fn,
+        topic_list_active_fn, topic_validate_pool_fn,
     },
     constants::{
-        LUA_SCRIPT_BATCH_IP_COUNTER_SCRIPT, LUA_SCRIPT_BATCH_RECORD_1V1_SCRIPT,
-        LUA_SCRIPT_BATCH_SCORE_UPDATE_SCRIPT, LUA_SCRIPT_GET_FINAL_ORDER,
+        LUA_SCRIPT_BATCH_IP_COUNTER_SCRIPT, LUA_SCRIPT_BATCH_SCORE_UPDATE_SCRIPT,
+        LUA_SCRIPT_GET_FINAL_ORDER, LUA_SCRIPT_GET_FINAL_ORDER_TOP,
     },
     proc::BallotProcessor,
     state::{AppDatabase, AppState, RedisService},
@@ -49,6 +58,75 @@ pub(crate) fn registry() -> &'static prometheus::Registry {
     &REG
 }
 
+/// Lookups are not counted if the counter could not be registered, a metric
+/// must not take down the cache it observes.
+pub(crate) fn record_cache_lookup(cache: CacheKind, hit: bool) {
+    static METRIC: Lazy<Option<prometheus::IntCounterVec>> = Lazy::new(|| {
+        prometheus::register_int_counter_vec_with_registry!(
+            prometheus::opts!(
+                "cache_lookups_total",
+                "In-process cache lookups by cache and hit/miss outcome",
+            ),
+            &[CacheKind::LABEL, CACHE_OUTCOME_LABEL],
+            registry()
+        )
+        .inspect_err(|e| tracing::error!("failed to register cache_lookups_total: {}", e))
+        .ok()
+    });
+
+    if let Some(metric) = METRIC.as_ref() {
+        metric
+            .with_label_values(&[cache.label(), cache_outcome(hit)])
+            .inc();
+    }
+}
+
+/// Eviction listener of the ballot store. Ballot codes live in process here,
+/// so their fate is known exactly: an explicit removal is a save or skip, an
+/// expiry is an abandoned code. `ballot_code_abandon_ratio` only counts codes
+/// that reached either end. Expiries are reported lazily, during the cache's
+/// housekeeping.
+fn record_ballot_code_removal(key: Arc<String>, _ballot: (i32, i32), cause: RemovalCause) {
+    static CLOSED: Lazy<prometheus::IntCounterVec> = Lazy::new(|| {
+        prometheus::register_int_counter_vec_with_registry!(
+            prometheus::opts!(
+                "ballot_codes_closed_total",
+                "Ballot codes resolved by a save or skip, or expired unused, per topic",
+            ),
+            &["topic_id", "outcome"],
+            registry()
+        )
+        .unwrap()
+    });
+    static ABANDON_RATIO: Lazy<prometheus::GaugeVec> = Lazy::new(|| {
+        prometheus::register_gauge_vec_with_registry!(
+            prometheus::opts!(
+                "ballot_code_abandon_ratio",
+                "Share of closed ballot codes that expired without a save or skip, per topic",
+            ),
+            &["topic_id"],
+            registry()
+        )
+        .unwrap()
+    });
+
+    let outcome = match cause {
+        RemovalCause::Explicit => "resolved",
+        RemovalCause::Expired => "expired",
+        RemovalCause::Replaced | RemovalCause::Size => return,
+    };
+    let Some((topic_id, _)) = key.rsplit_once(":ballot:") else {
+        return;
+    };
+
+    CLOSED.with_label_values(&[topic_id, outcome]).inc();
+    let expired = CLOSED.with_label_values(&[topic_id, "expired"]).get();
+    let resolved = CLOSED.with_label_values(&[topic_id, "resolved"]).get();
+    ABANDON_RATIO
+        .with_label_values(&[topic_id])
+        .set(expired as f64 / (expired + resolved) as f64);
+}
+
 pub struct PortableService {
     config: Arc<AppConfig>,
 }
@@ -63,15 +141,10 @@ impl PortableService {
     async fn setup_database(config: &AppConfig) -> eyre::Result<AppDatabase> {
         let database_config = &config.database;
 
-        let redis_client = redis::Client::open(&*database_config.redis_url)
-            .context("failed to create Redis client")?;
-        let connection = redis_client.get_multiplexed_async_connection().await?;
-
-        let mongodb_client = mongodb::Client::with_uri_str(&database_config.mongodb_url)
-            .await
-            .context("failed to connect to MongoDB")?;
-
-        let mongo_database = mongodb_client.database(&database_config.mongodb_database);
+        let (redis_client, connection) = bootstrap::connect_redis(database_config).await?;
+        let mongo_database = bootstrap::connect_mongodb_database(database_config).await?;
+        let mongo_read_database =
+            bootstrap::connect_mongodb_read_database(database_config, &mongo_database).await?;
 
         Ok(AppDatabase {
             redis: RedisService {
@@ -79,73 +152,69 @@ impl PortableService {
                 connection,
 
                 final_order_script: redis::Script::new(LUA_SCRIPT_GET_FINAL_ORDER),
+                final_order_top_script: redis::Script::new(LUA_SCRIPT_GET_FINAL_ORDER_TOP),
 
                 batch_ip_counter_script: redis::Script::new(LUA_SCRIPT_BATCH_IP_COUNTER_SCRIPT),
                 batch_score_update_script: redis::Script::new(LUA_SCRIPT_BATCH_SCORE_UPDATE_SCRIPT),
-                batch_record_1v1_script: redis::Script::new(LUA_SCRIPT_BATCH_RECORD_1V1_SCRIPT),
             },
             mongo_database,
+            mongo_read_database,
         })
     }
 
-    pub async fn run(self, _shutdown_rx: share::signal::ShutdownRx) -> eyre::Result<()> {
+    pub async fn run(self, mut shutdown_rx: share::signal::ShutdownRx) -> eyre::Result<()> {
         let database = Self::setup_database(&self.config).await?;
 
-        let collection = database.mongo_database.collection::<VotingTopic>("topics");
-
-        for preset_topic in &self.config.vote.preset_vote_topic {
-            let filter = doc! { "id": &preset_topic.id };
-
-            match collection.find_one(filter).await {
-                Ok(Some(_)) => {
-                    let query = doc! { "id": &preset_topic.id };
-                    collection.replace_one(query, preset_topic).await?;
-                    tracing::info!("updated preset voting topic: {}", preset_topic.id);
-                }
-                Ok(None) => {
-                    tracing::info!("inserting preset voting topic: {}", preset_topic.id);
-                    collection.insert_one(preset_topic).await?;
-                }
-                Err(_) => {
-                    // maybe data structure has changed, we force replace
-                    let query = doc! { "id": &preset_topic.id };
-                    collection.replace_one(query, preset_topic).await?;
-                }
-            }
-        }
-
-        let character_infos: Vec<CharacterInfo> = utils::load_character_table()?
-            .into_iter()
-            .filter_map(|(name, data)| {
-                if !name.starts_with("char_") {
-                    return None;
-                }
-                let mut parts = name["char_".len()..].splitn(2, '_');
-                let charid = parts.next()?.parse::<i32>().ok()?;
-                Some(CharacterInfo {
-                    id: charid,
-                    name: data.name,
-                    rarity: data.rarity,
-                    profession: data.profession,
-                    sub_profession_id: data.sub_profession_id,
-                    is_not_obtainable: data.is_not_obtainable,
-                })
-            })
-            .collect();
+        let character_table = bootstrap::load_character_table()?;
+        let character_infos = bootstrap::character_infos(&character_table);
         tracing::debug!("Character infos loaded: {}", character_infos.len());
 
-        let character_portraits = utils::fetch_portrait_image_url().await?;
-        tracing::debug!("Character portraits fetched");
+        self.config.vote.check_ballot_code_length()?;
 
-        let topic_service = Arc::new(TopicService::new(database.mongo_database.clone()));
+        bootstrap::ensure_topic_index(&database.mongo_database)
+            .await
+            .context("failed to index topic ids")?;
+        bootstrap::upsert_preset_topics(
+            &database.mongo_database,
+            &self.config.vote,
+            &character_infos,
+        )
+        .await?;
+        bootstrap::ensure_ballot_indexes(&database.mongo_database)
+            .await
+            .context("failed to index stored ballots")?;
+
+        let character_portraits =
+            utils::fetch_portrait_image_url(&character_table, &self.config.portraits).await;
+        tracing::debug!("Character portraits fetched");
+        let portrait_hints =
+            portrait::spawn_portrait_hints(&character_portraits, &self.config.portraits)
+                .context("failed to start probing portrait images")?;
+        let character_portraits = PortraitStore::new(character_portraits);
+
+        let topic_service = Arc::new(TopicService::new(
+            database.mongo_database.clone(),
+            self.config.topic_cache.clone(),
+            &character_infos,
+            SubProfessionBlocklist::new(self.config.vote.blocked_sub_professions.clone()),
+            self.config.vote.max_preset_depth,
+        ));
         tracing::debug!("TopicService initialized");
 
-        let ballot_processor =
-            Arc::new(BallotProcessor::new(database.clone(), self.config.clone()).await);
+        let ballot_processor = Arc::new(
+            BallotProcessor::new(
+                database.clone(),
+                self.config.clone(),
+                topic_service.clone(),
+                character_infos.clone(),
+            )
+            .await,
+        );
         tracing::debug!("BallotProcessor initialized");
 
         let ballot_cache_store = Cache::builder()
             .time_to_live(Duration::from_secs(24 * 3600))
+            .eviction_listener(record_ballot_code_removal)
             .build_with_hasher(ahash::RandomState::default());
         tracing::debug!("Ballot store initialized");
         let results_cache_store = Cache::builder()
@@ -182,19 +251,38 @@ impl PortableService {
             ));
         }
 
-        actix_web::HttpServer::new(move || {
+        let admin_tokens = web::Data::new(AdminTokens::from_config(&self.config.admin));
+        let vote = VoteSettings::from_config(&self.config.vote);
+        portrait::spawn_portrait_health_check(
+            character_portraits.clone(),
+            &self.config.portraits,
+            {
+                let topic_service = topic_service.clone();
+                move || topic_service.clear_candidate_pool_responses()
+            },
+        )
+        .context("failed to start the portrait CDN health check")?;
+        let operator_aliases = OperatorAliases::new(&self.config.operators.aliases);
+        let selection_rngs = SelectionRngs::default();
+        let operator_games = OperatorGamesCache::default();
+        let shutdown_processor = ballot_processor.clone();
+        let shutdown_timeout = self.config.shutdown.timeout();
+
+        let server = actix_web::HttpServer::new(move || {
             let worker_id = WORKER_COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-            let snowflake = Snowflake::new(
-                self.config.snowflake.datacenter_id,
-                worker_id,
-                self.config.snowflake.epoch,
-            );
+            let snowflake = Snowflake::from_config(&self.config.snowflake, worker_id);
 
             let state = AppState {
                 database: database.clone(),
                 snowflake,
                 character_infos: character_infos.clone(),
                 character_portraits: character_portraits.clone(),
+                portrait_hints: portrait_hints.clone(),
+                operator_aliases: operator_aliases.clone(),
+                vote: vote.clone(),
+                base_multiplier: self.config.vote.base_multiplier,
+                selection_rngs: selection_rngs.clone(),
+                operator_games: operator_games.clone(),
                 topic_service: topic_service.clone(),
                 ballot_cache_store: ballot_cache_store.clone(),
                 results_cache_store: results_cache_store.clone(),
@@ -208,6 +296,7 @@ impl PortableService {
                 .allowed_methods(vec!["GET", "POST"])
                 .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT])
                 .allowed_header(header::CONTENT_TYPE)
+                .allowed_header(USER_TOKEN_HEADER)
                 .max_age(3600);
 
             actix_web::App::new()
@@ -216,26 +305,72 @@ impl PortableService {
                 .service(ballot_create_fn)
                 .service(ballot_save_fn)
                 .service(ballot_skip_fn)
+                .service(ballot_skip_batch_fn)
+                .service(ballot_my_stats_fn)
                 .service(results_1v1_matrix_fn)
+                .service(results_by_profession_fn)
+                .service(results_condorcet_fn)
+                .service(results_dominant_matchups_fn)
                 .service(results_final_order_fn)
+                .service(results_final_order_delta_fn)
+                .service(results_partners_fn)
+                .service(results_rank_movement_fn)
+                .service(results_velocity_fn)
                 .service(topic_candidate_pool_fn)
                 .service(topic_create_fn)
                 .service(topic_info_fn)
                 .service(topic_list_active_fn)
+                .service(topic_validate_pool_fn)
                 .service(audit_topic_fn)
                 .service(audit_topics_list_fn)
                 .service(bench_ballot_create_fn)
                 .service(bench_ballot_save_fn)
                 .service(results_operator_timeline_fn)
+                .service(operators_list_fn)
+                .service(
+                    web::scope("/admin")
+                        .app_data(admin_tokens.clone())
+                        .wrap(middleware::from_fn(require_admin_token))
+                        .configure(admin_config),
+                )
                 .app_data(state)
+                .app_data(error::json_config(self.config.server.max_body_size))
+                .app_data(web::PayloadConfig::new(self.config.server.max_body_size))
+                .wrap(middleware::from_fn(catch_panic))
                 .wrap(cors)
                 .wrap(middleware::Compress::default())
                 .wrap(middleware::NormalizePath::trim())
                 .wrap(middleware::Logger::default().log_level(tracing::log::Level::Debug))
         })
+        // shutdown is driven by share::signal, not actix's own signal handling
+        .disable_signals()
+        .shutdown_timeout(shutdown_timeout.as_secs())
         .bind(bind_addr)?
-        .run()
-        .await?;
+        .run();
+        let server_handle = server.handle();
+        // the server future is what carries out a stop, it has to keep being
+        // polled until the in flight requests are done
+        let mut server_task = tokio::spawn(server);
+
+        tracing::info!("starting portable service on {}", bind_addr);
+        let shutdown_requested = tokio::select! {
+            result = &mut server_task => {
+                result??;
+                false
+            }
+            _ = shutdown_rx.changed() => true,
+        };
+        if shutdown_requested {
+            tracing::info!("shutting down portable service");
+            // stops accepting connections and lets in flight requests
+            // finish within the shutdown timeout
+            server_handle.stop(true).await;
+            server_task.await??;
+        }
+
+        // the requests still running may have queued ballots, drain them
+        // only once the server is gone
+        shutdown_processor.shutdown(shutdown_timeout).await;
 
         Ok(())
     }
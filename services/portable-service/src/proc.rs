@@ -6,19 +6,31 @@ use std::{
     time::Duration,
 };
 
+use chrono::{DateTime, Utc};
+use futures::StreamExt as _;
 use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use prometheus::{
     Histogram, HistogramOpts, IntCounter, IntCounterVec, opts,
     register_int_counter_vec_with_registry,
 };
 use share::{
     config::{AppConfig, VoteConfig},
-    models::database::{
-        Ballot, GroupwiseBallot, PairwiseBallot, PluralityBallot, SetwiseBallot, StoredBallot,
+    metrics::{LabelCap, saved_per_topic},
+    models::{
+        database::{
+            Ballot, BallotInfo, GroupwiseBallot, PairwiseBallot, PluralityBallot, SetwiseBallot,
+            StoredBallot, TopicWindowState, VotingTopic,
+        },
+        excel::CharacterInfo,
+        topic_id::TopicId,
     },
+    reputation::{combine_multipliers, fetch_user_reputations},
+    signal,
+    tracing::LogSampler,
 };
 
-use crate::{error::AppError, registry, state::AppDatabase};
+use crate::{error::AppError, registry, state::AppDatabase, topic::TopicService};
 use prometheus::{IntGaugeVec, register_int_gauge_vec_with_registry};
 
 const BUCKET_START: f64 = 0.002;
@@ -120,6 +132,39 @@ fn inc_total_processed(count: usize) {
     processing_stats_total(ProcessingStatsEnum::TotalProcessed).inc_by(count as u64);
 }
 
+/// Saved ballots by topic. Every topic label is a series Prometheus keeps
+/// until restart, so only the first `vote.throughput_metric_topics` topics get
+/// one and the ballots of later topics are summed in
+/// `votes_saved_overflow_total`. Rates summed over both stay exact.
+fn inc_votes_saved(per_topic: &HashMap<String, u64>, labels: &LabelCap) {
+    static LABELLED: Lazy<IntCounterVec> = Lazy::new(|| {
+        register_int_counter_vec_with_registry!(
+            opts!("votes_saved_total", "Ballots saved per topic"),
+            &["topic_id"],
+            registry()
+        )
+        .unwrap()
+    });
+    static OVERFLOW: Lazy<IntCounter> = Lazy::new(|| {
+        let counter = IntCounter::new(
+            "votes_saved_overflow_total",
+            "Ballots saved for topics beyond the votes_saved_total label cap",
+        )
+        .unwrap();
+
+        registry().register(Box::new(counter.clone())).unwrap();
+        counter
+    });
+
+    for (topic_id, &count) in per_topic {
+        if labels.admit(topic_id) {
+            LABELLED.with_label_values(&[topic_id]).inc_by(count);
+        } else {
+            OVERFLOW.inc_by(count);
+        }
+    }
+}
+
 fn inc_successful_batches() {
     processing_stats_total(ProcessingStatsEnum::SuccessfulBatches).inc();
 }
@@ -206,6 +251,8 @@ struct ProcessingStats {
     failed_batches: usize,
     last_flush_time: std::time::Instant,
     total_batch_time: std::time::Duration,
+    log_sampler: LogSampler,
+    saved_topic_labels: LabelCap,
 }
 
 impl Default for ProcessingStats {
@@ -216,17 +263,36 @@ impl Default for ProcessingStats {
             failed_batches: 0,
             last_flush_time: std::time::Instant::now(),
             total_batch_time: std::time::Duration::ZERO,
+            log_sampler: LogSampler::default(),
+            saved_topic_labels: LabelCap::new(0),
         }
     }
 }
 
+/// What the batch processing reads besides its Redis connection.
+struct ProcessContext<'a> {
+    database: &'a AppDatabase,
+    app_config: &'a AppConfig,
+    topic_service: &'a TopicService,
+    character_infos: &'a [CharacterInfo],
+}
+
 pub struct BallotProcessor {
     sender: tokio::sync::mpsc::UnboundedSender<Ballot<'static>>,
+    shutdown: Arc<tokio::sync::Notify>,
+    finished: Mutex<Option<tokio::sync::oneshot::Receiver<()>>>,
 }
 
 impl BallotProcessor {
-    pub async fn new(database: AppDatabase, app_config: Arc<AppConfig>) -> Self {
+    pub async fn new(
+        database: AppDatabase,
+        app_config: Arc<AppConfig>,
+        topic_service: Arc<TopicService>,
+        character_infos: Vec<CharacterInfo>,
+    ) -> Self {
         let (sender, receiver) = tokio::sync::mpsc::unbounded_channel::<Ballot<'static>>();
+        let shutdown = Arc::new(tokio::sync::Notify::new());
+        let (finished_tx, finished_rx) = tokio::sync::oneshot::channel();
 
         let conn = database
             .redis
@@ -235,6 +301,7 @@ impl BallotProcessor {
             .await
             .expect("failed to create Redis connection");
 
+        let task_shutdown = shutdown.clone();
         thread::spawn(move || {
             let rt = tokio::runtime::Builder::new_multi_thread()
                 .enable_all()
@@ -242,11 +309,32 @@ impl BallotProcessor {
                 .unwrap();
 
             rt.block_on(async move {
-                Self::batch_processor_task(receiver, conn, &database, &app_config).await;
+                let ctx = ProcessContext {
+                    database: &database,
+                    app_config: &app_config,
+                    topic_service: &topic_service,
+                    character_infos: &character_infos,
+                };
+                Self::batch_processor_task(receiver, &task_shutdown, conn, &ctx).await;
             });
+            let _ = finished_tx.send(());
         });
 
-        Self { sender }
+        Self {
+            sender,
+            shutdown,
+            finished: Mutex::new(Some(finished_rx)),
+        }
+    }
+
+    /// Stops accepting ballots and waits at most `timeout` for the pending
+    /// ones to be flushed.
+    pub async fn shutdown(&self, timeout: Duration) {
+        self.shutdown.notify_one();
+        let Some(finished) = self.finished.lock().take() else {
+            return;
+        };
+        signal::bounded("ballot processor flush", timeout, finished).await;
     }
 
     pub fn submit_ballot(&'_ self, ballot: Ballot<'static>) -> eyre::Result<()> {
@@ -255,12 +343,16 @@ impl BallotProcessor {
 
     async fn batch_processor_task(
         mut receiver: tokio::sync::mpsc::UnboundedReceiver<Ballot<'static>>,
+        shutdown: &tokio::sync::Notify,
         mut conn: redis::aio::MultiplexedConnection,
-        database: &AppDatabase,
-        config: &AppConfig,
+        ctx: &ProcessContext<'_>,
     ) {
         let mut ballot_groups = BallotMessageGroup::with_capacity(1000);
-        let mut stats = ProcessingStats::default();
+        let mut stats = ProcessingStats {
+            log_sampler: LogSampler::new(ctx.app_config.tracing.debug_sample_rate),
+            saved_topic_labels: LabelCap::new(ctx.app_config.vote.throughput_metric_topics),
+            ..Default::default()
+        };
 
         let flush_interval = Duration::from_millis(500);
         let stats_log_interval = Duration::from_secs(5);
@@ -283,8 +375,7 @@ impl BallotProcessor {
                                 Self::process_batch_with_retry(
                                     &mut ballot_groups,
                                     &mut conn,
-                                    database,
-                                    config,
+                                    ctx,
                                     &mut stats,
                                 ).await;
                                 last_flush = std::time::Instant::now();
@@ -296,8 +387,7 @@ impl BallotProcessor {
                                 Self::process_batch_with_retry(
                                     &mut ballot_groups,
                                     &mut conn,
-                                    database,
-                                    config,
+                                    ctx,
                                     &mut stats,
                                 ).await;
                             }
@@ -306,13 +396,14 @@ impl BallotProcessor {
                         }
                     }
                 }
+                // closing lets the buffered ballots drain, then `None` flushes them
+                _ = shutdown.notified() => receiver.close(),
                 _ = ticker.tick() => {
                     if !ballot_groups.is_empty() && last_flush.elapsed() >= flush_interval {
                         Self::process_batch_with_retry(
                             &mut ballot_groups,
                             &mut conn,
-                            database,
-                            config,
+                            ctx,
                             &mut stats,
                         ).await;
                         last_flush = std::time::Instant::now();
@@ -353,8 +444,7 @@ impl BallotProcessor {
     async fn process_batch_with_retry(
         ballot_groups: &mut BallotMessageGroup<'_>,
         conn: &mut redis::aio::MultiplexedConnection,
-        database: &AppDatabase,
-        app_config: &AppConfig,
+        ctx: &ProcessContext<'_>,
         stats: &mut ProcessingStats,
     ) {
         let (mut pairwise, mut setwise, mut groupwise, mut plurality) = ballot_groups.take_all();
@@ -366,33 +456,38 @@ impl BallotProcessor {
 
         let timer = batch_process_time().start_timer();
         let start_time = tokio::time::Instant::now();
+        let sampled = stats.log_sampler.sample();
+        let scored = Self::scored_ballots(&setwise, &groupwise, &plurality, ctx);
+        let mut progress = BatchProgress::default();
         for attempt in 1..=3 {
             match Self::process_all_ballot_types(
                 pairwise.make_contiguous(),
-                setwise.make_contiguous(),
-                groupwise.make_contiguous(),
-                plurality.make_contiguous(),
+                &scored,
+                &mut progress,
                 conn,
-                database,
-                app_config,
+                ctx,
+                sampled,
             )
             .await
             {
-                Ok(_) => {
+                Ok(()) => {
                     let duration_secs = timer.stop_and_record();
                     stats.total_batch_time += start_time.elapsed();
                     stats.total_processed += total_count;
                     stats.successful_batches += 1;
                     stats.last_flush_time = std::time::Instant::now();
                     inc_total_processed(total_count);
+                    inc_votes_saved(&progress.saved, &stats.saved_topic_labels);
                     inc_successful_batches();
                     inc_batch_total_process_time(Duration::from_secs_f64(duration_secs));
 
-                    tracing::debug!(
-                        "Successfully processed {} ballots in batch, duration={:?}",
-                        total_count,
-                        start_time.elapsed()
-                    );
+                    if sampled {
+                        tracing::debug!(
+                            "Successfully processed {} ballots in batch, duration={:?}",
+                            total_count,
+                            start_time.elapsed()
+                        );
+                    }
                     return;
                 }
                 Err(e) => {
@@ -407,6 +502,18 @@ impl BallotProcessor {
                     if attempt == 3 {
                         stats.failed_batches += 1;
                         inc_failed_batches();
+                        // scores that went through are counted all the same
+                        inc_votes_saved(&progress.saved, &stats.saved_topic_labels);
+                        // a replay of ballots whose scores went through
+                        // would count them twice
+                        if progress.pairwise.is_scored() {
+                            pairwise.clear();
+                        }
+                        if progress.scored.is_scored() {
+                            setwise.clear();
+                            groupwise.clear();
+                            plurality.clear();
+                        }
                         save_failed_ballots(
                             pairwise.make_contiguous(),
                             setwise.make_contiguous(),
@@ -421,201 +528,536 @@ impl BallotProcessor {
         }
     }
 
-    async fn process_all_ballot_types(
-        pairwise: &[PairwiseBallot<'_>],
-        _setwise: &[SetwiseBallot<'_>],
-        _groupwise: &[GroupwiseBallot<'_>],
-        _plurality: &[PluralityBallot<'_>],
+    /// Runs the steps of the batch `progress` has not finished yet.
+    async fn process_all_ballot_types<'a>(
+        pairwise: &[PairwiseBallot<'a>],
+        scored: &[Ballot<'a>],
+        progress: &mut BatchProgress<'a>,
         conn: &mut redis::aio::MultiplexedConnection,
-        database: &AppDatabase,
-        app_config: &AppConfig,
+        ctx: &ProcessContext<'_>,
+        sampled: bool,
     ) -> Result<(), AppError> {
-        Self::process_pairwise_ballot_batch(pairwise, conn, database, app_config).await?;
-        // Self::process_setwise_ballot_batch(setwise, conn, database, app_config).await?;
-        // Self::process_groupwise_ballot_batch(groupwise, conn, database, app_config).await?;
-        // Self::process_plurality_ballot_batch(plurality, conn, database, app_config).await?;
+        if let BatchStep::Weigh = progress.pairwise {
+            let weighed = Self::weigh_pairwise_ballots(pairwise, conn, ctx, sampled).await?;
+            progress.pairwise = BatchStep::Score(weighed);
+        }
+        Self::finish_step(
+            &mut progress.pairwise,
+            &mut progress.saved,
+            conn,
+            ctx,
+            sampled,
+        )
+        .await?;
 
-        Ok(())
+        if let BatchStep::Weigh = progress.scored {
+            let weighed = Self::weigh_scored_ballots(scored.to_vec(), conn, ctx).await?;
+            progress.scored = BatchStep::Score(weighed);
+        }
+        Self::finish_step(
+            &mut progress.scored,
+            &mut progress.saved,
+            conn,
+            ctx,
+            sampled,
+        )
+        .await
+    }
+
+    /// Setwise, groupwise and plurality ballots within the size limits.
+    fn scored_ballots<'a>(
+        setwise: &VecDeque<SetwiseBallot<'a>>,
+        groupwise: &VecDeque<GroupwiseBallot<'a>>,
+        plurality: &VecDeque<PluralityBallot<'a>>,
+        ctx: &ProcessContext<'_>,
+    ) -> Vec<Ballot<'a>> {
+        let limits = &ctx.app_config.vote.ballot_size_limits;
+        let mut ballots = Vec::with_capacity(setwise.len() + groupwise.len() + plurality.len());
+        for ballot in setwise {
+            if !limits.set_fits(&ballot.left_set) || !limits.set_fits(&ballot.right_set) {
+                tracing::warn!(
+                    "Dropping setwise ballot {} exceeding {} operators per set",
+                    ballot.info.ballot_id,
+                    limits.max_set_size
+                );
+                continue;
+            }
+            ballots.push(Ballot::Setwise(ballot.clone()));
+        }
+        for ballot in groupwise {
+            if !limits.group_fits(&ballot.left_group) || !limits.group_fits(&ballot.right_group) {
+                tracing::warn!(
+                    "Dropping groupwise ballot {} exceeding {} operators per group",
+                    ballot.info.ballot_id,
+                    limits.max_group_size
+                );
+                continue;
+            }
+            ballots.push(Ballot::Groupwise(ballot.clone()));
+        }
+        ballots.extend(plurality.iter().cloned().map(Ballot::Plurality));
+        ballots
     }
 
-    async fn process_pairwise_ballot_batch(
-        ballots: &[PairwiseBallot<'_>],
+    /// Applies the scores of a weighed step, then stores its ballots. The
+    /// step moves on after each write so a retry does not repeat it.
+    async fn finish_step(
+        step: &mut BatchStep<'_>,
+        saved: &mut HashMap<String, u64>,
         conn: &mut redis::aio::MultiplexedConnection,
-        database: &AppDatabase,
-        app_config: &AppConfig,
+        ctx: &ProcessContext<'_>,
+        sampled: bool,
     ) -> Result<(), AppError> {
-        if ballots.is_empty() {
-            return Ok(());
+        let ProcessContext {
+            database,
+            app_config,
+            topic_service,
+            ..
+        } = *ctx;
+
+        if let BatchStep::Score(weighed) = step {
+            // 第二步：批量执行分数更新
+            let start_time = tokio::time::Instant::now();
+            batch_update_scores(
+                &weighed.score_updates,
+                app_config.vote.score_ceiling,
+                &database.redis.batch_score_update_script,
+                conn,
+            )
+            .await?;
+            if sampled {
+                tracing::debug!(
+                    "Batch score updates completed, duration={:?}",
+                    start_time.elapsed()
+                );
+            }
+            for (topic_id, count) in saved_per_topic(&weighed.grouped_ballots) {
+                *saved.entry(topic_id).or_default() += count;
+            }
+            *step = BatchStep::Store(std::mem::take(&mut weighed.grouped_ballots));
         }
 
+        if let BatchStep::Store(grouped_ballots) = step {
+            // 第三步：按话题并发写入MongoDB
+            let start_time = tokio::time::Instant::now();
+            let ballot_count: usize = grouped_ballots.values().map(Vec::len).sum();
+            insert_ballots(grouped_ballots, database, app_config, topic_service).await?;
+            if sampled {
+                tracing::debug!(
+                    "Stored up to {} ballots in MongoDB, duration={:?}",
+                    ballot_count,
+                    start_time.elapsed()
+                );
+            }
+            *step = BatchStep::Done;
+        }
+
+        Ok(())
+    }
+
+    /// Setwise, groupwise and plurality ballots. The save handler refuses
+    /// these until `/ballot/new` issues codes for them, so only ballots
+    /// already queued before that reach here. Ballots cast outside their
+    /// topic's window or showing operators outside its candidate pool are
+    /// dropped. Each remaining ballot weighs as much as a pairwise one, see
+    /// `Ballot::weighted_score_pairs`.
+    async fn weigh_scored_ballots<'a>(
+        mut ballots: Vec<Ballot<'a>>,
+        conn: &mut redis::aio::MultiplexedConnection,
+        ctx: &ProcessContext<'_>,
+    ) -> Result<WeighedBallots<'a>, AppError> {
+        let ProcessContext {
+            database,
+            app_config,
+            topic_service,
+            character_infos,
+        } = *ctx;
+
+        retain_valid_scored_ballots(&mut ballots, topic_service, character_infos).await?;
+        if ballots.is_empty() {
+            return Ok(WeighedBallots::default());
+        }
         let vote_config = &app_config.vote;
 
-        // 第一步：批量计算IP倍数
-        let start_time = tokio::time::Instant::now();
-        let ip_multipliers = calculate_pairwise_multipliers(
-            ballots,
+        let infos: Vec<&BallotInfo<'_>> = ballots.iter().map(Ballot::info).collect();
+        let ip_multipliers = calculate_ip_multipliers(
+            &infos,
             vote_config,
+            topic_service,
             &database.redis.batch_ip_counter_script,
             conn,
         )
         .await?;
-        tracing::debug!(
-            "Calculated IP multipliers for {} ballots, duration={:?}",
-            ballots.len(),
-            start_time.elapsed()
-        );
-
-        let start_time = tokio::time::Instant::now();
-        let (score_updates, grouped_ballots) = {
-            let ballots = ballots.to_vec();
-            let ip_multipliers = ip_multipliers.clone();
-            let low_multiplier = vote_config.low_multiplier;
-
-            let mut score_updates = Vec::with_capacity(ballots.len()); // ((topic_id, win_id, lose_id), total_multiplier)
-            let mut grouped_ballots: HashMap<String, Vec<StoredBallot>> = HashMap::new();
-
-            for item in ballots.iter() {
-                let multiplier = ip_multipliers
-                    .get(item.info.ip.as_ref())
-                    .copied()
-                    .unwrap_or(low_multiplier);
-
-                score_updates.push((
-                    (item.info.topic_id.to_string(), item.win, item.lose),
-                    multiplier,
-                ));
-
-                let stored_ballot = StoredBallot {
-                    ballot: Ballot::Pairwise(item.clone()),
-                    multiplier,
-                };
+        let user_reputations = if vote_config.reputation.enabled {
+            fetch_user_reputations(&infos, conn).await?
+        } else {
+            HashMap::new()
+        };
 
-                grouped_ballots
-                    .entry(item.info.topic_id.to_string())
-                    .or_default()
-                    .push(stored_ballot);
+        let mut weighed = WeighedBallots::default();
+        for ballot in ballots {
+            let topic_id = ballot.info().topic_id.to_string();
+            let multiplier = ballot_multiplier(
+                ballot.info(),
+                &ip_multipliers,
+                &user_reputations,
+                vote_config,
+            );
+            for ((win, lose), weight) in ballot.weighted_score_pairs(multiplier) {
+                weighed.add_score(&topic_id, win, lose, weight);
             }
 
-            (score_updates, grouped_ballots)
-        };
-        tracing::debug!(
-            "Processed pairwise ballot batch, duration={:?}, score_updates.len={}, grouped_ballots.len={}",
-            start_time.elapsed(),
-            score_updates.len(),
-            grouped_ballots.len()
-        );
+            weighed
+                .grouped_ballots
+                .entry(topic_id)
+                .or_default()
+                .push(StoredBallot { ballot, multiplier });
+        }
+
+        Ok(weighed)
+    }
 
-        // 第二步：批量执行分数更新
+    /// Per-phase debug logs are only emitted for `sampled` batches.
+    async fn weigh_pairwise_ballots<'a>(
+        ballots: &[PairwiseBallot<'a>],
+        conn: &mut redis::aio::MultiplexedConnection,
+        ctx: &ProcessContext<'_>,
+        sampled: bool,
+    ) -> Result<WeighedBallots<'a>, AppError> {
+        if ballots.is_empty() {
+            return Ok(WeighedBallots::default());
+        }
+
+        let ProcessContext {
+            database,
+            app_config,
+            topic_service,
+            ..
+        } = *ctx;
+        let vote_config = &app_config.vote;
+
+        // 第一步：批量计算IP倍数
         let start_time = tokio::time::Instant::now();
-        batch_update_scores(
-            score_updates,
-            &database.redis.batch_score_update_script,
-            &database.redis.batch_record_1v1_script,
+        let infos: Vec<&BallotInfo<'_>> = ballots.iter().map(|ballot| &ballot.info).collect();
+        let ip_multipliers = calculate_ip_multipliers(
+            &infos,
+            vote_config,
+            topic_service,
+            &database.redis.batch_ip_counter_script,
             conn,
         )
         .await?;
-        tracing::debug!(
-            "Batch score updates completed, duration={:?}",
-            start_time.elapsed()
-        );
+        if sampled {
+            tracing::debug!(
+                "Calculated IP multipliers for {} ballots, duration={:?}",
+                ballots.len(),
+                start_time.elapsed()
+            );
+        }
 
-        let start_time = tokio::time::Instant::now();
-        for (topic_id, ballots) in grouped_ballots.iter() {
-            let ballot_collection = database
-                .mongo_database
-                .collection::<StoredBallot>(&format!("ballots_{}", topic_id));
+        let user_reputations = if vote_config.reputation.enabled {
+            fetch_user_reputations(&infos, conn).await?
+        } else {
+            HashMap::new()
+        };
 
-            ballot_collection.insert_many(ballots).await?;
+        let mut weighed = WeighedBallots {
+            score_updates: HashMap::with_capacity(ballots.len()),
+            grouped_ballots: HashMap::new(),
+        };
+        for item in ballots.iter() {
+            let multiplier =
+                ballot_multiplier(&item.info, &ip_multipliers, &user_reputations, vote_config);
+
+            weighed.add_score(&item.info.topic_id, item.win, item.lose, multiplier);
+
+            let stored_ballot = StoredBallot {
+                ballot: Ballot::Pairwise(item.clone()),
+                multiplier,
+            };
+
+            weighed
+                .grouped_ballots
+                .entry(item.info.topic_id.to_string())
+                .or_default()
+                .push(stored_ballot);
         }
-        tracing::debug!(
-            "Inserted {} ballots into MongoDB, duration={:?}",
-            grouped_ballots.values().map(|v| v.len()).sum::<usize>(),
-            start_time.elapsed()
-        );
 
-        Ok(())
+        Ok(weighed)
     }
 }
 
-async fn calculate_pairwise_multipliers(
-    ballots: &[PairwiseBallot<'_>],
-    vote_config: &VoteConfig,
-    batch_ip_counter_script: &redis::Script,
-    conn: &mut redis::aio::MultiplexedConnection,
-) -> Result<HashMap<String, i32>, AppError> {
-    if ballots.is_empty() {
-        return Ok(HashMap::new());
+/// Ballots of a step whose ip counters are counted, with the score updates
+/// they weigh.
+#[derive(Debug, Default)]
+struct WeighedBallots<'a> {
+    score_updates: HashMap<(String, i32, i32), (i32, i64)>, // (topic_id, win_id, lose_id) -> (total_multiplier, games)
+    grouped_ballots: HashMap<String, Vec<StoredBallot<'a>>>,
+}
+
+impl WeighedBallots<'_> {
+    /// Counts one comparison of the pair, won by `win`.
+    fn add_score(&mut self, topic_id: &str, win: i32, lose: i32, multiplier: i32) {
+        let (total, games) = self
+            .score_updates
+            .entry((topic_id.to_string(), win, lose))
+            .or_insert((0, 0));
+        *total += multiplier;
+        *games += 1;
     }
+}
 
-    let mut results = HashMap::new();
+/// Where one step of a batch, the pairwise ballots or the others, stands.
+/// Weighing counts ip counters, scoring applies the scores and storing
+/// inserts the ballots, each of them at most once however often the batch
+/// is retried.
+#[derive(Debug, Default)]
+enum BatchStep<'a> {
+    #[default]
+    Weigh,
+    Score(WeighedBallots<'a>),
+    /// Scores are applied, the ballots of the topics not inserted yet are left.
+    Store(HashMap<String, Vec<StoredBallot<'a>>>),
+    Done,
+}
 
-    let ips: HashSet<&str> = ballots.iter().map(|b| b.info.ip.as_ref()).collect();
+impl BatchStep<'_> {
+    fn is_scored(&self) -> bool {
+        matches!(self, Self::Store(_) | Self::Done)
+    }
+}
+
+/// Retries of a batch resume where the previous attempt stopped.
+#[derive(Debug, Default)]
+struct BatchProgress<'a> {
+    pairwise: BatchStep<'a>,
+    scored: BatchStep<'a>,
+    /// Ballots scored so far by topic.
+    saved: HashMap<String, u64>,
+}
 
-    let keys: Vec<String> = ballots
+/// Drops the setwise, groupwise and plurality ballots rejected by
+/// `scored_ballot_rejection`, or whose topic is gone.
+async fn retain_valid_scored_ballots(
+    ballots: &mut Vec<Ballot<'_>>,
+    topic_service: &TopicService,
+    character_infos: &[CharacterInfo],
+) -> Result<(), AppError> {
+    let topic_ids: HashSet<String> = ballots
         .iter()
-        .map(|b| {
-            let info = &b.info;
-            format!("{}:ip_counter:{}", info.topic_id.as_ref(), info.ip.as_ref())
-        })
+        .map(|ballot| ballot.info().topic_id.to_string())
         .collect();
+    let mut topics = HashMap::with_capacity(topic_ids.len());
+    for topic_id in topic_ids {
+        let Some(topic) = topic_service.get_topic(&topic_id).await? else {
+            continue;
+        };
+        let pool: HashSet<i32> = topic_service
+            .get_candidate_pool(&topic_id, character_infos)
+            .await
+            .map(HashSet::from_iter)
+            .unwrap_or_default();
+        topics.insert(topic_id, (topic, pool));
+    }
 
-    let ips_vec: Vec<&str> = ips.into_iter().collect();
+    ballots.retain(|ballot| {
+        let info = ballot.info();
+        let rejection = match topics.get(info.topic_id.as_ref()) {
+            Some((topic, pool)) => scored_ballot_rejection(ballot, topic, pool),
+            None => Some("its topic does not exist".to_string()),
+        };
+        if let Some(reason) = &rejection {
+            tracing::warn!("Dropping ballot {}: {}", info.ballot_id, reason);
+        }
+        rejection.is_none()
+    });
 
-    let script_results: Vec<i32> = batch_ip_counter_script
-        .key(&keys)
-        .arg(vote_config.ip_counter_expire_seconds)
-        .arg(vote_config.max_ip_limit)
-        .arg(vote_config.base_multiplier)
-        .arg(vote_config.low_multiplier)
-        .invoke_async(conn)
-        .await?;
+    Ok(())
+}
+
+/// Why a setwise, groupwise or plurality ballot may not be counted: it must
+/// be cast while the topic was open and only show operators of its pool.
+fn scored_ballot_rejection(
+    ballot: &Ballot<'_>,
+    topic: &VotingTopic,
+    pool: &HashSet<i32>,
+) -> Option<String> {
+    let window_state = DateTime::<Utc>::from_timestamp_millis(ballot.info().timestamp)
+        .map(|cast_at| topic.window_state_at(cast_at));
+    if window_state != Some(TopicWindowState::Open) {
+        return Some(format!("cast while the topic was {window_state:?}"));
+    }
+
+    ballot
+        .operators()
+        .into_iter()
+        .find(|id| !pool.contains(id))
+        .map(|id| format!("operator {id} is not in the candidate pool"))
+}
+
+/// Inserts the ballots of each topic concurrently, a topic failing does not
+/// block the others. Returns the first error.
+///
+/// Only the sample set by `vote.ballot_store_sample_rate` is kept in
+/// `grouped_ballots` and written. Topics that went in are removed from it, so
+/// a retry only inserts the rest.
+async fn insert_ballots(
+    grouped_ballots: &mut HashMap<String, Vec<StoredBallot<'_>>>,
+    database: &AppDatabase,
+    app_config: &AppConfig,
+    topic_service: &TopicService,
+) -> Result<(), AppError> {
+    for (topic_id, ballots) in grouped_ballots.iter_mut() {
+        let rate = topic_service
+            .get_topic(topic_id)
+            .await?
+            .map(|topic| topic.vote_overrides)
+            .unwrap_or_default()
+            .ballot_store_sample_rate(&app_config.vote);
+        ballots.retain(|stored| stored.ballot.info().is_stored_at(rate));
+    }
+    grouped_ballots.retain(|_, ballots| !ballots.is_empty());
+
+    let mut inserts = futures::stream::iter(grouped_ballots.iter())
+        .map(|(topic_id, ballots)| async move {
+            let ballot_collection = database
+                .mongo_database
+                .collection::<StoredBallot>(&format!("ballots_{}", topic_id));
+
+            (topic_id, ballot_collection.insert_many(ballots).await)
+        })
+        .buffer_unordered(app_config.database.mongodb_insert_concurrency.max(1));
+
+    let mut first_error = None;
+    let mut inserted = Vec::new();
+    while let Some((topic_id, result)) = inserts.next().await {
+        match result {
+            Ok(_) => inserted.push(topic_id.clone()),
+            Err(e) => {
+                tracing::error!("Failed to insert ballots of topic {}: {}", topic_id, e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    drop(inserts);
+    for topic_id in inserted {
+        grouped_ballots.remove(&topic_id);
+    }
+    match first_error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
 
-    for (ip, multiplier) in ips_vec.into_iter().zip(script_results.into_iter()) {
-        results.insert(ip.to_string(), multiplier);
+/// Weight of one ballot: zero when throttled, otherwise its ip multiplier
+/// combined with the user's reputation.
+fn ballot_multiplier(
+    info: &BallotInfo<'_>,
+    ip_multipliers: &HashMap<(String, String), i32>,
+    user_reputations: &HashMap<String, f64>,
+    vote_config: &VoteConfig,
+) -> i32 {
+    if info.throttled {
+        return 0;
+    }
+    let ip_multiplier = ip_multipliers
+        .get(&(info.topic_id.to_string(), info.ip.to_string()))
+        .copied()
+        .unwrap_or(vote_config.low_multiplier);
+    let reputation = info
+        .user_id
+        .as_deref()
+        .and_then(|user_id| user_reputations.get(user_id).copied());
+
+    combine_multipliers(
+        ip_multiplier,
+        reputation,
+        vote_config.reputation.max_reputation,
+    )
+}
+
+/// Multipliers of the batch keyed by `(topic_id, ip)`. The ip counters of
+/// each topic are checked against its own limits, see `TopicVoteOverrides`.
+async fn calculate_ip_multipliers(
+    infos: &[&BallotInfo<'_>],
+    vote_config: &VoteConfig,
+    topic_service: &TopicService,
+    batch_ip_counter_script: &redis::Script,
+    conn: &mut redis::aio::MultiplexedConnection,
+) -> Result<HashMap<(String, String), i32>, AppError> {
+    let mut ips_by_topic: HashMap<&str, Vec<&str>> = HashMap::new();
+    for info in infos {
+        ips_by_topic
+            .entry(info.topic_id.as_ref())
+            .or_default()
+            .push(info.ip.as_ref());
+    }
+
+    let mut results = HashMap::new();
+    for (topic_id, ips) in ips_by_topic {
+        let overrides = topic_service
+            .get_topic(topic_id)
+            .await?
+            .map(|topic| topic.vote_overrides)
+            .unwrap_or_default();
+        let limits = overrides.resolve(vote_config);
+
+        // ballots of such a topic never had a code, they are not counted
+        let Ok(topic_id) = TopicId::parse(topic_id) else {
+            tracing::warn!("Skipping the ip counters of invalid topic id {}", topic_id);
+            continue;
+        };
+        let keys: Vec<String> = ips.iter().map(|ip| topic_id.ip_counter_key(ip)).collect();
+        let script_results: Vec<i32> = batch_ip_counter_script
+            .key(&keys)
+            .arg(vote_config.ip_counter_expire_seconds)
+            .arg(limits.max_ip_limit)
+            .arg(limits.base_multiplier)
+            .arg(limits.low_multiplier)
+            .invoke_async(conn)
+            .await?;
+
+        // an ip saving several ballots keeps the multiplier of its last one
+        for (ip, multiplier) in ips.into_iter().zip(script_results) {
+            results.insert((topic_id.to_string(), ip.to_string()), multiplier);
+        }
     }
 
     Ok(results)
 }
 
 async fn batch_update_scores(
-    updates: Vec<((String, i32, i32), i32)>, // ((topic_id, win_id, lose_id), total_multiplier)
+    updates: &HashMap<(String, i32, i32), (i32, i64)>, // (topic_id, win_id, lose_id) -> (total_multiplier, games)
+    score_ceiling: Option<i64>,
     batch_score_update_script: &redis::Script,
-    batch_record_1v1_script: &redis::Script,
     conn: &mut redis::aio::MultiplexedConnection,
 ) -> Result<(), AppError> {
     if updates.is_empty() {
         return Ok(());
     }
 
-    // 准备参数：topic_id1, win_id1, lose_id1, multiplier1, topic_id2, win_id2, lose_id2, multiplier2, ...
-    let mut args = Vec::with_capacity(updates.len() * 4);
-    let mut args2 = Vec::with_capacity(updates.len() * 3);
-    for ((topic_id, win_id, lose_id), multiplier) in updates {
+    // 准备参数：topic_id1, win_id1, lose_id1, multiplier1, games1, topic_id2, ...
+    let mut args = Vec::with_capacity(updates.len() * 5);
+    for ((topic_id, win_id, lose_id), (multiplier, games)) in updates {
         args.push(topic_id.clone());
         args.push(win_id.to_string());
         args.push(lose_id.to_string());
         args.push(multiplier.to_string());
-
-        args2.push(topic_id.clone());
-        let (min_id, max_id) = if win_id < lose_id {
-            (win_id, lose_id)
-        } else {
-            (lose_id, win_id)
-        };
-        args2.push(min_id.to_string());
-        args2.push(max_id.to_string());
+        args.push(games.to_string());
     }
 
     // 执行批量分数更新脚本
-    let _results: () = batch_score_update_script
+    let refused: i64 = batch_score_update_script
+        .arg(score_ceiling.unwrap_or(0))
         .arg(&args)
         .invoke_async(conn)
         .await?;
-
-    let _results: () = batch_record_1v1_script
-        .arg(&args2)
-        .invoke_async(conn)
-        .await?;
+    if refused > 0 {
+        tracing::warn!(
+            "Refused {} score updates with a negative multiplier",
+            refused
+        );
+    }
 
     Ok(())
 }
@@ -676,3 +1118,90 @@ fn save_ballots_to_file<T: serde::Serialize>(ballots: &[T], filename: &str) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use share::test_util::test_topic;
+
+    use super::*;
+
+    fn plurality(timestamp: DateTime<Utc>, candidates: Vec<i32>) -> Ballot<'static> {
+        Ballot::Plurality(PluralityBallot {
+            info: BallotInfo {
+                topic_id: "test_topic".into(),
+                ballot_id: "1-abc".into(),
+                ip: "127.0.0.1".into(),
+                user_agent: "ua".into(),
+                timestamp: timestamp.timestamp_millis(),
+                user_id: None,
+                throttled: false,
+            },
+            candidates,
+            selected: 1,
+        })
+    }
+
+    #[test]
+    fn test_scored_ballot_rejection() {
+        let now = Utc::now();
+        let topic = test_topic(
+            "test_topic",
+            now - Duration::days(1),
+            now + Duration::days(1),
+        );
+        let pool = HashSet::from([1, 2, 3]);
+
+        assert_eq!(
+            scored_ballot_rejection(&plurality(now, vec![1, 2, 3]), &topic, &pool),
+            None
+        );
+        assert!(
+            scored_ballot_rejection(&plurality(now, vec![1, 4]), &topic, &pool)
+                .is_some_and(|reason| reason.contains("operator 4"))
+        );
+        assert!(
+            scored_ballot_rejection(
+                &plurality(now - Duration::days(2), vec![1, 2]),
+                &topic,
+                &pool
+            )
+            .is_some()
+        );
+    }
+
+    #[test]
+    fn test_votes_saved_caps_topic_labels() {
+        let labels = LabelCap::new(1);
+        inc_votes_saved(&HashMap::from([("saved-a".to_string(), 2)]), &labels);
+        inc_votes_saved(
+            &HashMap::from([("saved-a".to_string(), 1), ("saved-b".to_string(), 3)]),
+            &labels,
+        );
+
+        let families = registry().gather();
+        let family = |name: &str| {
+            families
+                .iter()
+                .find(|family| family.name() == name)
+                .unwrap()
+                .get_metric()
+        };
+        let labelled: Vec<(String, f64)> = family("votes_saved_total")
+            .iter()
+            .map(|metric| {
+                (
+                    metric.get_label()[0].value().to_string(),
+                    metric.get_counter().value(),
+                )
+            })
+            .collect();
+        assert_eq!(labelled, [("saved-a".to_string(), 3.0)]);
+        assert_eq!(
+            family("votes_saved_overflow_total")[0]
+                .get_counter()
+                .value(),
+            3.0
+        );
+    }
+}
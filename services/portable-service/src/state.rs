@@ -2,10 +2,14 @@ use std::{collections::HashMap, sync::Arc};
 
 use moka::future::Cache;
 use share::{
+    bootstrap::VoteSettings,
     models::{
-        api::{CharacterPortrait, Results1v1MatrixResponse, ResultsFinalOrderResponse},
+        api::{Results1v1MatrixResponse, ResultsFinalOrderResponse},
         excel::CharacterInfo,
     },
+    portrait::{PortraitHints, PortraitStore},
+    search::OperatorAliases,
+    selection::{OperatorGamesCache, SelectionRngs},
     snowflake::Snowflake,
 };
 
@@ -17,28 +21,57 @@ pub struct RedisService {
     pub connection: redis::aio::MultiplexedConnection,
 
     pub final_order_script: redis::Script,
+    pub final_order_top_script: redis::Script,
 
     pub batch_ip_counter_script: redis::Script,
     pub batch_score_update_script: redis::Script,
-    pub batch_record_1v1_script: redis::Script,
 }
 
 #[derive(Clone)]
 pub struct AppDatabase {
     pub redis: RedisService,
     pub mongo_database: mongodb::Database,
+    /// `database.mongodb_read_url`, or `mongo_database` when unset. Serves the
+    /// admin ballot lookup and results velocity, timeseries and rank movement,
+    /// everything else uses `mongo_database`.
+    pub mongo_read_database: mongodb::Database,
 }
 
 #[derive(Clone, Default)]
 pub struct ResultsCacheValue {
     pub final_order: Option<Arc<ResultsFinalOrderResponse>>,
+    /// Read only when `final_order` is not cached.
+    pub final_order_top: Option<FinalOrderTop>,
     pub matrix: Option<Arc<Results1v1MatrixResponse>>,
+    /// The raw `op_cooccur` hash, every operator's partners are built from it.
+    pub cooccur: Option<Arc<HashMap<String, i64>>>,
+}
+
+/// Head of the rate ordered final order, with the limit the top script ranked
+/// it for.
+#[derive(Clone)]
+pub struct FinalOrderTop {
+    pub limit: usize,
+    pub response: Arc<ResultsFinalOrderResponse>,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ResultsType {
     FinalOrder,
+    FinalOrderColley,
     Matrix1v1,
+    Matrix1v1Pairs,
+    Partners,
+}
+
+impl ResultsType {
+    pub const ALL: [Self; 5] = [
+        Self::FinalOrder,
+        Self::FinalOrderColley,
+        Self::Matrix1v1,
+        Self::Matrix1v1Pairs,
+        Self::Partners,
+    ];
 }
 
 pub struct AppState {
@@ -46,7 +79,15 @@ pub struct AppState {
     pub snowflake: Snowflake,
 
     pub character_infos: Vec<CharacterInfo>,
-    pub character_portraits: HashMap<i32, CharacterPortrait>,
+    pub character_portraits: PortraitStore,
+    pub portrait_hints: PortraitHints,
+    pub operator_aliases: OperatorAliases,
+    pub vote: VoteSettings,
+    pub base_multiplier: i32,
+    /// Shared by all workers so a seeded topic draws from one sequence.
+    pub selection_rngs: SelectionRngs,
+    /// Shared by all workers so a topic's game counts are read once per TTL.
+    pub operator_games: OperatorGamesCache,
 
     pub topic_service: Arc<TopicService>,
     pub ballot_cache_store: Cache<String, (i32, i32), ahash::RandomState>,
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
+use share::scores::parse_operator_counts;
 use tokio::time::{Duration, interval};
 
 use crate::{api::OperatorsInfo, topic::TopicService};
@@ -136,7 +137,14 @@ async fn update_single_batch(
         .await
         .map_err(|e| eyre::eyre!("Redis script execution failed: {}", e))?;
 
-    let (win_counts, lose_counts) = parse_operator_counts(&operator_values, num_operators);
+    let (win_counts, lose_counts) = parse_operator_counts(&operator_values, num_operators)
+        .ok_or_else(|| {
+            eyre::eyre!(
+                "Final order script returned {} values, expected {}",
+                operator_values.len(),
+                2 * num_operators
+            )
+        })?;
 
     let now = mongodb::bson::DateTime::now();
     let results = build_operator_results(operator_ids, &win_counts, &lose_counts, now);
@@ -151,20 +159,6 @@ async fn update_single_batch(
     Ok(())
 }
 
-fn parse_operator_counts(values: &[Option<String>], num_operators: usize) -> (Vec<i64>, Vec<i64>) {
-    let win_counts: Vec<i64> = values[..num_operators]
-        .iter()
-        .map(|v| v.as_ref().and_then(|s| s.parse().ok()).unwrap_or(0))
-        .collect();
-
-    let lose_counts: Vec<i64> = values[num_operators..]
-        .iter()
-        .map(|v| v.as_ref().and_then(|s| s.parse().ok()).unwrap_or(0))
-        .collect();
-
-    (win_counts, lose_counts)
-}
-
 fn build_operator_results(
     operator_ids: &[i32],
     win_counts: &[i64],
@@ -4,19 +4,47 @@ use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use futures::TryStreamExt as _;
 use mongodb::{Collection, bson::doc};
+use once_cell::sync::Lazy;
 use parking_lot::RwLock;
-use share::models::{
-    database::{CreateTopicStatus, TopicAuditInfo, VotingTopic},
-    excel::CharacterInfo,
+use prometheus::{IntGaugeVec, opts, register_int_gauge_vec_with_registry};
+use share::{
+    bootstrap::is_duplicate_key_error,
+    config::TopicCacheConfig,
+    etag::EtaggedBody,
+    metrics::{CacheKind, LabelCap},
+    models::{
+        candidate_pool_preset::{CandidatePoolError, CandidatePoolPreset},
+        database::{CreateTopicStatus, TopicAuditInfo, TopicAuditLogEntry, VotingTopic},
+        excel::CharacterInfo,
+    },
+    selection::SubProfessionBlocklist,
 };
 use tokio::sync::RwLock as AsyncRwLock;
 
-use crate::error::AppError;
+use crate::{error::AppError, record_cache_lookup, registry};
+
+fn pool_size_gauge() -> &'static IntGaugeVec {
+    static METRIC: Lazy<IntGaugeVec> = Lazy::new(|| {
+        register_int_gauge_vec_with_registry!(
+            opts!(
+                "topic_candidate_pool_size",
+                "Resolved candidate pool size per topic",
+            ),
+            &["topic_id"],
+            registry()
+        )
+        .unwrap()
+    });
+
+    &METRIC
+}
 
 #[derive(Debug, Clone)]
 pub struct CacheEntry {
     data: VotingTopic,
     pool: Vec<i32>,
+    /// Serialized `/topic/candidate_pool` response for `pool`.
+    pool_response: Option<Arc<EtaggedBody>>,
     last_accessed: Arc<RwLock<Instant>>,
 }
 
@@ -25,6 +53,7 @@ impl CacheEntry {
         Self {
             data,
             pool: Vec::new(),
+            pool_response: None,
             last_accessed: Arc::new(RwLock::new(Instant::now())),
         }
     }
@@ -39,11 +68,19 @@ impl CacheEntry {
 pub struct TopicCache {
     pub cache: DashMap<String, CacheEntry>,
     pub last_full_refresh: Arc<RwLock<DateTime<Utc>>>,
+    /// Start time of the last successful incremental update, used as the lower
+    /// bound of `updated_at` for the next one.
+    pub last_incremental_refresh: Arc<RwLock<DateTime<Utc>>>,
+    pub blocklist: SubProfessionBlocklist,
+    pub pool_size_labels: Arc<LabelCap>,
+    pub max_preset_depth: usize,
 }
 
 impl TopicCache {
     pub fn get(&self, topic_id: &str) -> Option<VotingTopic> {
-        self.cache.get(topic_id).map(|entry| entry.access())
+        let topic = self.cache.get(topic_id).map(|entry| entry.access());
+        record_cache_lookup(CacheKind::Topic, topic.is_some());
+        topic
     }
 
     pub fn get_pool(&self, topic_id: &str) -> Option<Vec<i32>> {
@@ -52,7 +89,9 @@ impl TopicCache {
 
     pub fn cache_topic_pool(&self, topic_id: &str, pool: Vec<i32>) {
         if let Some(mut entry) = self.cache.get_mut(topic_id) {
+            self.record_pool_size(topic_id, pool.len());
             entry.pool = pool;
+            entry.pool_response = None;
         } else {
             tracing::warn!(
                 "Attempted to cache pool for non-existent topic: {}",
@@ -93,6 +132,94 @@ impl TopicCache {
         inserted_count
     }
 
+    /// Generates and caches the candidate pool of every active topic, returns
+    /// the number of pools cached.
+    pub fn warm_pools(&self, character_infos: &[CharacterInfo]) -> usize {
+        let topics: Vec<_> = self
+            .cache
+            .iter()
+            .filter(|entry| entry.value().data.is_active)
+            .map(|entry| (entry.key().clone(), entry.value().data.clone()))
+            .collect();
+
+        let mut warmed_count = 0;
+        for (topic_id, topic) in topics {
+            let pool = self.generate_topic_pool(&topic, character_infos);
+            if !pool.is_empty() {
+                self.cache_topic_pool(&topic_id, pool);
+                warmed_count += 1;
+            }
+        }
+
+        warmed_count
+    }
+
+    fn generate_pool(
+        &self,
+        preset: &CandidatePoolPreset,
+        character_infos: &[CharacterInfo],
+    ) -> Vec<i32> {
+        self.blocklist.retain_allowed(
+            preset.generate_pool_with_depth(character_infos, self.max_preset_depth),
+            character_infos,
+        )
+    }
+
+    /// Like `generate_pool`, but starts from the topic's frozen pool when it
+    /// has one.
+    fn generate_topic_pool(
+        &self,
+        topic: &VotingTopic,
+        character_infos: &[CharacterInfo],
+    ) -> Vec<i32> {
+        self.blocklist.retain_allowed(
+            topic.resolve_pool(character_infos, self.max_preset_depth),
+            character_infos,
+        )
+    }
+
+    fn record_pool_size(&self, topic_id: &str, size: usize) {
+        if self.pool_size_labels.admit(topic_id) {
+            pool_size_gauge()
+                .with_label_values(&[topic_id])
+                .set(size as i64);
+        } else {
+            tracing::debug!(
+                "Pool size metric topic cap reached, not tracking topic {}",
+                topic_id
+            );
+        }
+    }
+
+    /// Drops every cached pool so they are regenerated on next access.
+    pub fn clear_pools(&self) {
+        for mut entry in self.cache.iter_mut() {
+            entry.pool.clear();
+            entry.pool_response = None;
+        }
+    }
+
+    pub fn clear_pool_responses(&self) {
+        for mut entry in self.cache.iter_mut() {
+            entry.pool_response = None;
+        }
+    }
+
+    pub fn get_pool_response(&self, topic_id: &str) -> Option<Arc<EtaggedBody>> {
+        self.cache
+            .get(topic_id)
+            .and_then(|entry| entry.pool_response.clone())
+    }
+
+    /// Caches the response only if it was built from the currently cached pool.
+    pub fn cache_pool_response(&self, topic_id: &str, pool: &[i32], response: Arc<EtaggedBody>) {
+        if let Some(mut entry) = self.cache.get_mut(topic_id)
+            && entry.pool == pool
+        {
+            entry.pool_response = Some(response);
+        }
+    }
+
     pub fn get_active_topic_ids(&self) -> Vec<String> {
         self.cache
             .iter()
@@ -124,36 +251,65 @@ impl TopicCache {
 #[derive(Clone)]
 pub struct TopicService {
     topic_collection: Collection<VotingTopic>,
+    audit_log_collection: Collection<TopicAuditLogEntry>,
     cache: TopicCache,
 
     refresh_lock: Arc<AsyncRwLock<()>>,
 }
 
 impl TopicService {
-    pub fn new(mongo: mongodb::Database) -> Self {
+    pub fn new(
+        mongo: mongodb::Database,
+        cache_config: TopicCacheConfig,
+        character_infos: &[CharacterInfo],
+        blocklist: SubProfessionBlocklist,
+        max_preset_depth: usize,
+    ) -> Self {
         let topic_collection = mongo.collection::<VotingTopic>("topics");
+        let audit_log_collection = mongo.collection::<TopicAuditLogEntry>("topic_audit_log");
         let topic_cache = TopicCache {
             cache: DashMap::new(),
             last_full_refresh: Arc::new(RwLock::new(Utc::now())),
+            last_incremental_refresh: Arc::new(RwLock::new(Utc::now())),
+            blocklist,
+            pool_size_labels: Arc::new(LabelCap::new(cache_config.pool_size_metric_topics)),
+            max_preset_depth,
         };
         let refresh_lock = Arc::new(AsyncRwLock::new(()));
 
+        let eager_pool_characters = cache_config
+            .eager_pool_generation
+            .then(|| character_infos.to_vec());
+
         tokio::spawn(Self::cache_updater(
             topic_collection.clone(),
             topic_cache.clone(),
+            cache_config,
+            eager_pool_characters,
         ));
 
         Self {
             topic_collection,
+            audit_log_collection,
             cache: topic_cache,
             refresh_lock,
         }
     }
 
-    pub async fn create_topic(&self, topic: &VotingTopic) -> Result<(), AppError> {
-        self.topic_collection.insert_one(topic).await?;
-
-        Ok(())
+    /// Inserts `topic`, or returns the stored topic when its id is taken
+    /// already, e.g. by a concurrent retry of the same creation.
+    pub async fn create_topic(&self, topic: &VotingTopic) -> Result<Option<VotingTopic>, AppError> {
+        match self.topic_collection.insert_one(topic).await {
+            Ok(_) => Ok(None),
+            Err(e) if is_duplicate_key_error(&e) => {
+                let filter = doc! { "id": &topic.id };
+                match self.topic_collection.find_one(filter).await? {
+                    Some(existing) => Ok(Some(existing)),
+                    None => Err(e.into()),
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
     }
 
     pub async fn _update_topic(&self, mut topic: VotingTopic) -> Result<(), AppError> {
@@ -253,30 +409,116 @@ impl TopicService {
         Ok(())
     }
 
+    /// Persists a reopened topic and records the change in the audit log. The
+    /// cache entry is dropped so the next lookup reads the new window.
+    pub async fn reopen_topic(
+        &self,
+        previous: &VotingTopic,
+        mut reopened: VotingTopic,
+    ) -> Result<(), AppError> {
+        let now = Utc::now();
+        reopened.updated_at = Some(now);
+
+        let filter = doc! { "id": &reopened.id };
+        self.topic_collection.replace_one(filter, &reopened).await?;
+        self.cache.cache.remove(&reopened.id);
+
+        let entry = TopicAuditLogEntry::reopen(previous, &reopened, now);
+        self.audit_log_collection.insert_one(&entry).await?;
+
+        Ok(())
+    }
+
+    /// Persists an edited topic and records the changed fields in the audit
+    /// log. Dropping the cache entry also drops its resolved pool, so a new
+    /// candidate pool takes effect on the next lookup.
+    pub async fn update_topic(
+        &self,
+        mut updated: VotingTopic,
+        fields: Vec<String>,
+    ) -> Result<(), AppError> {
+        let now = Utc::now();
+        updated.updated_at = Some(now);
+
+        let filter = doc! { "id": &updated.id };
+        self.topic_collection.replace_one(filter, &updated).await?;
+        self.cache.cache.remove(&updated.id);
+
+        let entry = TopicAuditLogEntry::update(&updated, fields, now);
+        self.audit_log_collection.insert_one(&entry).await?;
+
+        Ok(())
+    }
+
+    pub fn blocked_sub_professions(&self) -> Vec<String> {
+        self.cache.blocklist.sub_professions()
+    }
+
+    /// Replaces the sub profession blocklist and drops the cached pools built
+    /// with the previous one.
+    pub fn set_blocked_sub_professions(&self, sub_professions: Vec<String>) {
+        self.cache.blocklist.replace(sub_professions);
+        self.cache.clear_pools();
+        tracing::info!(
+            "Sub profession blocklist updated: {:?}",
+            self.cache.blocklist.sub_professions()
+        );
+    }
+
+    /// Resolves a preset the way topic pools are, without caching it.
+    pub fn resolve_pool(
+        &self,
+        preset: &CandidatePoolPreset,
+        character_infos: &[CharacterInfo],
+    ) -> Vec<i32> {
+        self.cache.generate_pool(preset, character_infos)
+    }
+
+    pub fn max_preset_depth(&self) -> usize {
+        self.cache.max_preset_depth
+    }
+
+    pub fn get_candidate_pool_response(&self, topic_id: &str) -> Option<Arc<EtaggedBody>> {
+        self.cache.get_pool_response(topic_id)
+    }
+
+    /// Drops the cached candidate pool responses, e.g. after the avatar urls
+    /// changed.
+    pub fn clear_candidate_pool_responses(&self) {
+        self.cache.clear_pool_responses();
+    }
+
+    pub fn cache_candidate_pool_response(
+        &self,
+        topic_id: &str,
+        pool: &[i32],
+        response: Arc<EtaggedBody>,
+    ) {
+        self.cache.cache_pool_response(topic_id, pool, response);
+    }
+
     pub async fn get_candidate_pool(
         &self,
         topic_id: &str,
         character_infos: &[CharacterInfo],
-    ) -> Option<Vec<i32>> {
+    ) -> Result<Vec<i32>, CandidatePoolError> {
         if let Some(pool) = self.cache.get_pool(topic_id)
             && !pool.is_empty()
         {
-            return Some(pool);
+            record_cache_lookup(CacheKind::Pool, true);
+            return Ok(pool);
         }
+        record_cache_lookup(CacheKind::Pool, false);
 
-        match self.get_topic(topic_id).await {
-            Ok(Some(topic)) => {
-                let pool = topic.candidate_pool.generate_pool(character_infos);
-                if !pool.is_empty() {
-                    self.cache.cache_topic_pool(topic_id, pool.clone());
-                    Some(pool)
-                } else {
-                    None
-                }
-            }
-            Ok(None) => None,
-            Err(_) => None,
+        let Ok(Some(topic)) = self.get_topic(topic_id).await else {
+            return Err(CandidatePoolError::TopicNotFound);
+        };
+        let pool = self.cache.generate_topic_pool(&topic, character_infos);
+        if pool.is_empty() {
+            return Err(CandidatePoolError::Empty);
         }
+        self.cache.cache_topic_pool(topic_id, pool.clone());
+        Ok(pool)
     }
 
     pub async fn _refresh_cache(&self) -> Result<usize, AppError> {
@@ -299,23 +541,38 @@ impl TopicService {
         Ok(inserted_count)
     }
 
-    async fn cache_updater(topic_collection: Collection<VotingTopic>, topic_cache: TopicCache) {
+    async fn cache_updater(
+        topic_collection: Collection<VotingTopic>,
+        topic_cache: TopicCache,
+        cache_config: TopicCacheConfig,
+        eager_pool_characters: Option<Vec<CharacterInfo>>,
+    ) {
         const CACHE_UPDATE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
 
-        Self::initial_warm_cache(&topic_collection, &topic_cache)
-            .await
-            .map_err(|e| {
-                tracing::error!("failed to warm up cache: {}", e);
-            })
-            .unwrap_or_else(|_| {
-                tracing::info!("cache warmed up successfully.");
-            });
+        let mut last_full_refresh_attempt = Instant::now();
+        let mut consecutive_errors: u32 = 0;
+
+        Self::initial_warm_cache(
+            &topic_collection,
+            &topic_cache,
+            eager_pool_characters.as_deref(),
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("failed to warm up cache: {}", e);
+        })
+        .unwrap_or_else(|_| {
+            tracing::info!("cache warmed up successfully.");
+        });
 
         loop {
             let start = std::time::Instant::now();
 
-            match Self::incremental_cache_update(&topic_collection, &topic_cache).await {
+            match Self::incremental_cache_update(&topic_collection, &topic_cache, &cache_config)
+                .await
+            {
                 Ok(updated_count) => {
+                    consecutive_errors = 0;
                     if updated_count > 0 {
                         tracing::debug!(
                             "Incremental cache update completed: {} topics updated",
@@ -324,11 +581,30 @@ impl TopicService {
                     }
                 }
                 Err(e) => {
-                    tracing::error!("Error during incremental cache update: {}", e);
-                    if let Err(e) = Self::full_cache_update(&topic_collection, &topic_cache).await {
-                        tracing::error!("Full cache update failed: {}", e);
+                    consecutive_errors += 1;
+                    tracing::error!(
+                        "Error during incremental cache update ({} consecutive): {}",
+                        consecutive_errors,
+                        e
+                    );
+
+                    if last_full_refresh_attempt.elapsed()
+                        < cache_config.min_full_refresh_interval()
+                    {
+                        tracing::debug!(
+                            "Skipping full cache update, last attempt was {:?} ago",
+                            last_full_refresh_attempt.elapsed()
+                        );
                     } else {
-                        tracing::debug!("Full cache update completed successfully.");
+                        last_full_refresh_attempt = Instant::now();
+                        if let Err(e) =
+                            Self::full_cache_update(&topic_collection, &topic_cache).await
+                        {
+                            tracing::error!("Full cache update failed: {}", e);
+                        } else {
+                            consecutive_errors = 0;
+                            tracing::debug!("Full cache update completed successfully.");
+                        }
                     }
                 }
             }
@@ -343,9 +619,11 @@ impl TopicService {
     async fn initial_warm_cache(
         topic_collection: &Collection<VotingTopic>,
         cache: &TopicCache,
+        eager_pool_characters: Option<&[CharacterInfo]>,
     ) -> Result<(), AppError> {
         tracing::info!("warming up cache...");
 
+        let started_at = Utc::now();
         let filter = doc! {};
         let mut cursor = topic_collection.find(filter).await?;
         let mut topics = Vec::new();
@@ -355,20 +633,33 @@ impl TopicService {
         }
 
         let updated_count = cache.insert_batch(&topics);
-        *cache.last_full_refresh.write() = Utc::now();
+        *cache.last_full_refresh.write() = started_at;
+        *cache.last_incremental_refresh.write() = started_at;
 
         tracing::info!("Cache warmed up with {} topics", updated_count);
 
+        if let Some(character_infos) = eager_pool_characters {
+            let pool_started_at = Instant::now();
+            let warmed_count = cache.warm_pools(character_infos);
+            tracing::info!(
+                "Generated candidate pools for {} active topics in {:?}",
+                warmed_count,
+                pool_started_at.elapsed()
+            );
+        }
+
         Ok(())
     }
 
     async fn incremental_cache_update(
         topic_collection: &Collection<VotingTopic>,
         cache: &TopicCache,
+        cache_config: &TopicCacheConfig,
     ) -> Result<usize, mongodb::error::Error> {
-        let last_refresh = *cache.last_full_refresh.read();
+        let started_at = Utc::now();
+        let since = *cache.last_incremental_refresh.read() - cache_config.incremental_overlap();
         let since_filter = doc! {
-            "updated_at": { "$gte": mongodb::bson::to_bson(&last_refresh).unwrap() }
+            "updated_at": { "$gte": mongodb::bson::to_bson(&since).unwrap() }
         };
 
         let mut cursor = topic_collection.find(since_filter).await?;
@@ -380,6 +671,8 @@ impl TopicService {
             }
         }
 
+        *cache.last_incremental_refresh.write() = started_at;
+
         Ok(updated_count)
     }
 
@@ -387,6 +680,7 @@ impl TopicService {
         topic_collection: &Collection<VotingTopic>,
         cache: &TopicCache,
     ) -> Result<usize, mongodb::error::Error> {
+        let started_at = Utc::now();
         let filter = doc! {};
         let mut cursor = topic_collection.find(filter).await?;
         let mut topics = Vec::new();
@@ -396,7 +690,8 @@ impl TopicService {
         }
 
         let updated_count = cache.insert_batch(&topics);
-        *cache.last_full_refresh.write() = Utc::now();
+        *cache.last_full_refresh.write() = started_at;
+        *cache.last_incremental_refresh.write() = started_at;
 
         Ok(updated_count)
     }
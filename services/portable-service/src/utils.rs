@@ -1,24 +1,62 @@
-use std::{collections::HashMap, fs, io::Read as _};
+use std::collections::HashMap;
 
+use actix_web::{
+    HttpRequest, HttpResponse, Responder,
+    http::header::{ACCEPT, CONTENT_TYPE},
+};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use share::models::{api::CharacterPortrait, excel::CharacterData};
-
-use crate::error::AppError;
-
-const CHARACTER_TABLE_FILE: &str = "character_table.json";
-
-pub fn load_character_table() -> Result<HashMap<String, CharacterData>, AppError> {
-    if fs::metadata(CHARACTER_TABLE_FILE).is_err() {
-        tracing::error!("Missing character_table.json file");
-        return Err(AppError::MissingCharacterTableJson);
+use share::{
+    config::PortraitConfig,
+    models::{
+        api::{ApiData, ApiResponse, CharacterPortrait, ResponseStatus},
+        excel::CharacterData,
+        proto::{PROTOBUF_CONTENT_TYPE, ToProto, accepts_protobuf},
+    },
+    portrait::{PORTRAIT_ASSET_URL, fallback_portraits},
+    retry::connect_with_retry,
+};
+
+/// Encodes successful responses as protobuf when the `Accept` header asks for
+/// it, everything else (including errors) is returned as JSON.
+pub fn negotiate<T: Serialize + ToProto>(
+    req: &HttpRequest,
+    response: ApiResponse<T>,
+) -> HttpResponse {
+    match protobuf(req, &response) {
+        Some(proto) => proto,
+        None => response.respond_to(req),
     }
+}
 
-    let mut file = fs::File::open(CHARACTER_TABLE_FILE)?;
-    let mut buf = Vec::new();
-    file.read_to_end(&mut buf)?;
-    let data: HashMap<String, CharacterData> = serde_json::from_slice(&buf)?;
+/// `negotiate` for results that send `computed_at` next to `data` in JSON,
+/// see `TimedApiResponse`.
+pub fn negotiate_timed<T: Serialize + ToProto>(
+    req: &HttpRequest,
+    response: ApiResponse<T>,
+    computed_at: impl FnOnce(&T) -> DateTime<Utc>,
+) -> HttpResponse {
+    match protobuf(req, &response) {
+        Some(proto) => proto,
+        None => response.timed(computed_at).respond_to(req),
+    }
+}
 
-    Ok(data)
+fn protobuf<T: ToProto>(req: &HttpRequest, response: &ApiResponse<T>) -> Option<HttpResponse> {
+    let accept = req.headers().get(ACCEPT).and_then(|v| v.to_str().ok());
+
+    match &response.data {
+        ApiData::Data(data)
+            if response.status == ResponseStatus::Ok && accepts_protobuf(accept) =>
+        {
+            Some(
+                HttpResponse::Ok()
+                    .insert_header((CONTENT_TYPE, PROTOBUF_CONTENT_TYPE))
+                    .body(data.encode_proto()),
+            )
+        }
+        _ => None,
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -37,17 +75,39 @@ struct TorappuApiFileStruct {
     children: Vec<TorappuApiFileData>,
 }
 
-pub async fn fetch_portrait_image_url() -> Result<HashMap<i32, CharacterPortrait>, AppError> {
+/// Lists the portrait images of every operator. Falls back to the
+/// conventional portrait of each operator when the listing cannot be fetched,
+/// so startup does not depend on it.
+pub async fn fetch_portrait_image_url(
+    character_table: &HashMap<String, CharacterData>,
+    config: &PortraitConfig,
+) -> HashMap<i32, CharacterPortrait> {
     const PORTRAIT_IMAGE_STORE_URL: &str =
         "https://torappu.prts.wiki/api/v1/files/raw%2Fchar_portrait";
 
-    let response = reqwest::get(PORTRAIT_IMAGE_STORE_URL)
-        .await?
-        .json::<TorappuApiFileStruct>()
-        .await?;
+    let client = reqwest::Client::new();
+    let response = match connect_with_retry("portrait listing", &config.retry, || async {
+        client
+            .get(PORTRAIT_IMAGE_STORE_URL)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TorappuApiFileStruct>()
+            .await
+    })
+    .await
+    {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!(
+                "Failed to fetch portrait listing, using fallback portraits: {}",
+                e
+            );
+            return fallback_portraits(character_table);
+        }
+    };
 
     let mut table = HashMap::new();
-    let character_table = load_character_table()?;
 
     for data in response.children.iter() {
         if data.is_dir {
@@ -78,10 +138,7 @@ pub async fn fetch_portrait_image_url() -> Result<HashMap<i32, CharacterPortrait
             }
         };
 
-        let avatar_url = format!(
-            "https://torappu.prts.wiki/assets/char_portrait/{}",
-            data.name
-        );
+        let avatar_url = format!("{}/{}", PORTRAIT_ASSET_URL, data.name);
 
         table
             .entry(id)
@@ -95,8 +152,9 @@ pub async fn fetch_portrait_image_url() -> Result<HashMap<i32, CharacterPortrait
                 name: stripped_name.clone(),
                 cn_name: name,
                 avatar: vec![avatar_url],
+                avatar_dimensions: None,
             });
     }
 
-    Ok(table)
+    table
 }
@@ -5,48 +5,83 @@ use governor::state::{InMemoryState, NotKeyed};
 use governor::{Quota, RateLimiter};
 use hdrhistogram::Histogram;
 use reqwest::Client;
-use share::config::AppConfig;
+use share::config::{AppConfig, LoadTestScenario, LoadTestScenarioWeight};
 use share::models::api::{
     ApiData, ApiResponse, BallotCreateRequest, BallotCreateResponse, BallotSaveRequest,
-    BallotSaveResponse, PairwiseSaveScore, Results1v1MatrixRequest, Results1v1MatrixResponse,
-    ResultsFinalOrderRequest, ResultsFinalOrderResponse,
+    BallotSaveResponse, BallotSkipRequest, BallotSkipResponse, FinalOrderItem, MatrixFormat,
+    PairwiseSaveScore, ResponseStatus, Results1v1MatrixRequest, Results1v1MatrixResponse,
+    ResultsFinalOrderRequest, ResultsFinalOrderResponse, TopicInfoRequest, TopicInfoResponse,
+    TopicListActiveResponse,
 };
-use std::collections::HashMap;
+use share::models::topic_id::TopicId;
+use std::collections::{HashMap, hash_map::Entry};
 use std::num::NonZeroU32;
-use std::sync::{
-    Arc,
-    atomic::{AtomicUsize, Ordering},
-};
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::{Semaphore, mpsc};
 
 #[derive(Debug)]
 enum StatEvent {
     Success {
-        ballot_id: String,
-        win: i32,
-        lose: i32,
+        scenario: LoadTestScenario,
         latency_us: u64,
+        ballot: Option<CreatedBallot>,
     },
     Error,
 }
 
+#[derive(Debug)]
+struct CreatedBallot {
+    ballot_id: String,
+    /// `(win, lose)` when the ballot was saved rather than skipped.
+    saved: Option<(i32, i32)>,
+}
+
+#[derive(Default)]
+struct LoadTestStats {
+    histograms: HashMap<LoadTestScenario, Histogram<u64>>,
+    successes: HashMap<LoadTestScenario, usize>,
+    result_map: HashMap<i32, (i64, i64)>,
+    ballot_ids: Vec<String>,
+}
+
+impl LoadTestStats {
+    fn success_count(&self, scenario: LoadTestScenario) -> usize {
+        self.successes.get(&scenario).copied().unwrap_or(0)
+    }
+}
+
 #[derive(Clone)]
 pub struct ServiceTester {
     base_url: String,
+    topic_id: TopicId,
     total_requests: usize,
     concurrency_limit: usize,
     qps_limit: u32,
+    latency_buckets_us: Vec<u64>,
+    settle_timeout: Duration,
+    settle_poll_interval: Duration,
+    schedule: Arc<[LoadTestScenario]>,
+    strict_validation: bool,
 }
 
 impl ServiceTester {
     pub fn new(config: AppConfig) -> Self {
         let test_config = &config.test;
+        let mut latency_buckets_us = test_config.latency_buckets_us.clone();
+        latency_buckets_us.sort_unstable();
+        latency_buckets_us.dedup();
         Self {
             base_url: test_config.base_url.clone(),
+            topic_id: test_config.topic_id.clone(),
             total_requests: test_config.total_requests,
             concurrency_limit: test_config.concurrency_limit,
             qps_limit: test_config.qps_limit,
+            latency_buckets_us,
+            settle_timeout: test_config.settle_timeout(),
+            settle_poll_interval: test_config.settle_poll_interval(),
+            schedule: scenario_schedule(&test_config.scenarios).into(),
+            strict_validation: test_config.strict_validation,
         }
     }
 
@@ -62,44 +97,41 @@ impl ServiceTester {
             None
         };
 
-        let data = ResultsFinalOrderRequest {
-            topic_id: "crisis_v2_season_4_1_benchtest".to_string(),
-        };
+        let data = self.final_order_request();
         let init_data = self.results_final_order(&client, &data).await?;
         let init_score: i64 = init_data.items.iter().map(|i| i.win + i.lose).sum();
         tracing::info!("initial count: {}", init_data.count);
 
         let semaphore = Arc::new(Semaphore::new(self.concurrency_limit));
-        let success_count = Arc::new(AtomicUsize::new(0));
         let (tx, mut rx) = mpsc::channel::<StatEvent>(self.total_requests);
-        let histogram = Arc::new(tokio::sync::Mutex::new(Histogram::<u64>::new(3)?));
-        let mut result_map: HashMap<i32, (i64, i64)> = HashMap::new();
-        let mut ballot_id_collect: Vec<String> = Vec::new();
 
         // Spawn stats collector
-        let hist_clone = Arc::clone(&histogram);
-        let success_clone = Arc::clone(&success_count);
         let stats_handle = tokio::spawn(async move {
+            let mut stats = LoadTestStats::default();
             while let Some(event) = rx.recv().await {
                 match event {
                     StatEvent::Success {
-                        ballot_id,
-                        win,
-                        lose,
+                        scenario,
                         latency_us,
+                        ballot,
                     } => {
-                        success_clone.fetch_add(1, Ordering::Relaxed);
-                        result_map.entry(win).or_default().0 += 1;
-                        result_map.entry(lose).or_default().1 += 1;
-                        ballot_id_collect.push(ballot_id);
-
-                        let mut h = hist_clone.lock().await;
-                        let _ = h.record(latency_us);
+                        *stats.successes.entry(scenario).or_default() += 1;
+                        if let Some(ballot) = ballot {
+                            if let Some((win, lose)) = ballot.saved {
+                                stats.result_map.entry(win).or_default().0 += 1;
+                                stats.result_map.entry(lose).or_default().1 += 1;
+                            }
+                            stats.ballot_ids.push(ballot.ballot_id);
+                        }
+
+                        if let Some(h) = histogram_for(&mut stats.histograms, scenario) {
+                            let _ = h.record(latency_us);
+                        }
                     }
                     StatEvent::Error => {}
                 }
             }
-            (result_map, ballot_id_collect)
+            stats
         });
 
         tracing::info!(
@@ -108,8 +140,9 @@ impl ServiceTester {
         );
         let mut futures = FuturesUnordered::new();
 
-        for _ in 0..self.total_requests {
+        for i in 0..self.total_requests {
             let permit = semaphore.clone().acquire_owned().await?;
+            let scenario = self.schedule[i % self.schedule.len()];
             let this = self.clone();
             let client = client.clone();
             let tx = tx.clone();
@@ -117,7 +150,10 @@ impl ServiceTester {
 
             futures.push(tokio::spawn(async move {
                 let _permit = permit;
-                if let Err(e) = this.single_test_request(client, tx, limiter.as_ref()).await {
+                if let Err(e) = this
+                    .single_test_request(scenario, client, tx, limiter.as_ref())
+                    .await
+                {
                     tracing::error!("request failed: {e}");
                 }
             }));
@@ -125,12 +161,9 @@ impl ServiceTester {
 
         while futures.next().await.is_some() {}
         drop(tx);
-        let (result_map, ballot_id_collect) = stats_handle.await?;
-
-        tracing::info!("waiting 5 seconds for final data to stabilize...");
-        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        let stats = stats_handle.await?;
 
-        let success = success_count.load(Ordering::Relaxed);
+        let success: usize = stats.successes.values().sum();
         tracing::info!(
             total = self.total_requests,
             success,
@@ -138,87 +171,131 @@ impl ServiceTester {
             "requests completed"
         );
 
-        let hist = histogram.lock().await;
-        if !hist.is_empty() {
-            tracing::info!(
-                p50 = hist.value_at_quantile(0.50),
-                p75 = hist.value_at_quantile(0.75),
-                p90 = hist.value_at_quantile(0.90),
-                p95 = hist.value_at_quantile(0.95),
-                p99 = hist.value_at_quantile(0.99),
-                p99_9 = hist.value_at_quantile(0.999),
-                min = hist.min(),
-                max = hist.max(),
-                mean = hist.mean(),
-                stddev = hist.stdev(),
-                "latency statistics (μs)"
-            );
-
-            let under_1ms = hist.count_between(0, 1_000);
-            let under_5ms = hist.count_between(1_001, 5_000);
-            let under_10ms = hist.count_between(1_001, 10_000);
-            let over_10ms = hist.count_between(10_001, u64::MAX);
-
-            tracing::info!(
-                under_1ms,
-                under_5ms,
-                under_10ms,
-                over_10ms,
-                "latency distribution count (μs)"
-            );
+        let mut reported = Vec::new();
+        for &scenario in self.schedule.iter() {
+            if reported.contains(&scenario) {
+                continue;
+            }
+            reported.push(scenario);
+            if let Some(hist) = stats.histograms.get(&scenario) {
+                self.report_latency(scenario, hist);
+            }
         }
 
+        let saved = stats.success_count(LoadTestScenario::CreateSave);
         let final_data = self
-            .results_final_order(
-                &client,
-                &ResultsFinalOrderRequest {
-                    topic_id: "crisis_v2_season_4_1_benchtest".to_string(),
-                },
-            )
+            .wait_for_settled_results(&client, &data, init_data.count + saved as i64)
             .await?;
         let final_score: i64 = final_data.items.iter().map(|i| i.win + i.lose).sum();
 
         // Ensure ballot_id has no duplicates
         let mut seen = std::collections::HashSet::new();
-        for ballot_id in ballot_id_collect {
+        for ballot_id in stats.ballot_ids {
             assert!(seen.insert(ballot_id), "duplicate ballot_id found");
         }
 
-        assert_eq!(
-            final_data.count,
-            init_data.count + success_count.load(Ordering::Relaxed) as i64,
-            "final_data.count != init_data.count + success_count"
-        );
-        assert_eq!(
-            final_score,
-            init_score + success_count.load(Ordering::Relaxed) as i64 * 2,
-            "final_score != init_score + success_count"
-        );
-
-        for item in final_data.items.iter() {
-            let (expected_win, expected_lose) = match result_map.get(&item.id) {
-                Some((win, lose)) => (win, lose),
-                None => continue,
-            };
-            let init = init_data
-                .items
-                .iter()
-                .find(|x| x.id == item.id)
-                .with_context(|| format!("operator ID {} not found", item.id))?;
-
-            assert_eq!(item.name, init.name);
-            assert_eq!(item.win - init.win, *expected_win);
-            assert_eq!(item.lose - init.lose, *expected_lose);
+        if self.strict_validation {
+            check_totals(
+                (init_data.count, init_score),
+                (final_data.count, final_score),
+                saved as i64,
+            )?;
+        } else {
+            tracing::info!(
+                "skipping total checks, {} ballots and {} score from other traffic",
+                final_data.count - init_data.count - saved as i64,
+                final_score - init_score - saved as i64 * 2
+            );
         }
 
+        check_operator_deltas(
+            &init_data.items,
+            &final_data.items,
+            &stats.result_map,
+            self.strict_validation,
+        )?;
+
         tracing::info!("combined results passed validation!");
         tracing::info!("validation all passed!");
 
         Ok(())
     }
 
+    fn report_latency(&self, scenario: LoadTestScenario, hist: &Histogram<u64>) {
+        if hist.is_empty() {
+            return;
+        }
+
+        tracing::info!(
+            scenario = scenario.name(),
+            count = hist.len(),
+            p50 = hist.value_at_quantile(0.50),
+            p75 = hist.value_at_quantile(0.75),
+            p90 = hist.value_at_quantile(0.90),
+            p95 = hist.value_at_quantile(0.95),
+            p99 = hist.value_at_quantile(0.99),
+            p99_9 = hist.value_at_quantile(0.999),
+            min = hist.min(),
+            max = hist.max(),
+            mean = hist.mean(),
+            stddev = hist.stdev(),
+            "latency statistics (μs)"
+        );
+
+        let distribution = latency_distribution(hist, &self.latency_buckets_us)
+            .iter()
+            .enumerate()
+            .map(|(i, count)| format!("{}={}", bucket_label(&self.latency_buckets_us, i), count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        tracing::info!(
+            scenario = scenario.name(),
+            "latency distribution count (μs): {}",
+            distribution
+        );
+    }
+
+    /// Polls the final order until its ballot count reaches `expected_count`
+    /// or `settle_timeout` elapses, returning the last response either way so
+    /// the exact checks report the mismatch.
+    async fn wait_for_settled_results(
+        &self,
+        client: &Client,
+        data: &ResultsFinalOrderRequest,
+        expected_count: i64,
+    ) -> Result<ResultsFinalOrderResponse> {
+        tracing::info!(
+            "waiting up to {:?} for final data to stabilize...",
+            self.settle_timeout
+        );
+        let deadline = Instant::now() + self.settle_timeout;
+
+        loop {
+            let results = self.results_final_order(client, data).await?;
+            if results.count >= expected_count {
+                return Ok(results);
+            }
+            if Instant::now() >= deadline {
+                tracing::warn!(
+                    "final data did not stabilize: count {} of {} expected",
+                    results.count,
+                    expected_count
+                );
+                return Ok(results);
+            }
+
+            tracing::debug!(
+                "final data count {} of {} expected, polling again",
+                results.count,
+                expected_count
+            );
+            tokio::time::sleep(self.settle_poll_interval).await;
+        }
+    }
+
     async fn single_test_request(
         &self,
+        scenario: LoadTestScenario,
         client: Client,
         tx: mpsc::Sender<StatEvent>,
         limiter: Option<&Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>>,
@@ -229,17 +306,52 @@ impl ServiceTester {
 
         let start = Instant::now();
 
-        let data = BallotCreateRequest {
-            topic_id: "crisis_v2_season_4_1_benchtest".to_string(),
+        let result = match scenario {
+            LoadTestScenario::CreateSave => self.create_and_save(&client).await.map(Some),
+            LoadTestScenario::CreateSkip => self.create_and_skip(&client).await.map(Some),
+            LoadTestScenario::FinalOrder => self
+                .results_final_order(&client, &self.final_order_request())
+                .await
+                .map(|_| None),
+            LoadTestScenario::Matrix => self
+                .results_1v1_matrix(
+                    &client,
+                    &Results1v1MatrixRequest {
+                        topic_id: self.topic_id.clone(),
+                        format: MatrixFormat::Keyed,
+                    },
+                )
+                .await
+                .map(|_| None),
         };
-        let compare = match self.ballot_create(&client, &data).await {
-            Ok(c) => c,
+
+        match result {
+            Ok(ballot) => {
+                let latency = start.elapsed().as_micros() as u64;
+                let _ = tx
+                    .send(StatEvent::Success {
+                        scenario,
+                        latency_us: latency,
+                        ballot,
+                    })
+                    .await;
+            }
             Err(e) => {
-                tracing::warn!("new compare failed: {e}");
+                tracing::warn!("{} failed: {e}", scenario.name());
                 let _ = tx.send(StatEvent::Error).await;
-                return Ok(());
             }
-        };
+        }
+
+        Ok(())
+    }
+
+    /// Requests a pairwise ballot, returning `(ballot_id, left, right)`.
+    async fn create_pairwise(&self, client: &Client) -> Result<(String, i32, i32)> {
+        let data = BallotCreateRequest::new(self.topic_id.clone());
+        let compare = self
+            .ballot_create(client, &data)
+            .await
+            .context("new compare failed")?;
 
         let (left, right, ballot_id) = match compare {
             BallotCreateResponse::Pairwise {
@@ -248,56 +360,66 @@ impl ServiceTester {
                 ballot_id,
                 ..
             } => (left, right, ballot_id),
-            _ => {
-                tracing::warn!("unexpected compare response type");
-                let _ = tx.send(StatEvent::Error).await;
-                return Ok(());
-            }
+            _ => return Err(eyre::eyre!("unexpected compare response type")),
         };
         assert!(left != right, "left and right should not be the same");
         assert!(left > 0 && right > 0, "left and right should be positive");
 
+        Ok((ballot_id, left, right))
+    }
+
+    async fn create_and_save(&self, client: &Client) -> Result<CreatedBallot> {
+        let (ballot_id, left, right) = self.create_pairwise(client).await?;
+
         let data = BallotSaveRequest::Pairwise(PairwiseSaveScore {
-            topic_id: "crisis_v2_season_4_1_benchtest".to_string(),
+            topic_id: self.topic_id.clone(),
             ballot_id: ballot_id.clone(),
             winner: left,
             loser: right,
         });
+        self.ballot_save(client, &data).await?;
 
-        match self.ballot_save(&client, &data).await {
-            Ok(_) => {
-                let latency = start.elapsed().as_micros() as u64;
-                let _ = tx
-                    .send(StatEvent::Success {
-                        ballot_id,
-                        win: left,
-                        lose: right,
-                        latency_us: latency,
-                    })
-                    .await;
-                Ok(())
-            }
-            Err(e) => {
-                tracing::warn!("ballot save failed: {e}");
-                let _ = tx.send(StatEvent::Error).await;
-                Ok(())
-            }
+        Ok(CreatedBallot {
+            ballot_id,
+            saved: Some((left, right)),
+        })
+    }
+
+    async fn create_and_skip(&self, client: &Client) -> Result<CreatedBallot> {
+        let (ballot_id, _, _) = self.create_pairwise(client).await?;
+
+        let data = BallotSkipRequest {
+            topic_id: self.topic_id.clone(),
+            ballot_id: ballot_id.clone(),
+        };
+        self.ballot_skip(client, &data).await?;
+
+        Ok(CreatedBallot {
+            ballot_id,
+            saved: None,
+        })
+    }
+
+    fn final_order_request(&self) -> ResultsFinalOrderRequest {
+        ResultsFinalOrderRequest {
+            topic_id: self.topic_id.clone(),
+            tier_breakpoints: None,
+            ranking_method: Default::default(),
+            limit: None,
+            format: Default::default(),
         }
     }
 
     async fn check_endpoints_available(&self) -> Result<()> {
         let client = Client::new();
-        self.results_final_order(
-            &client,
-            &ResultsFinalOrderRequest {
-                topic_id: "crisis_v2_season_4_1_benchtest".to_string(),
-            },
-        )
-        .await?;
+        self.check_topic_open(&client).await?;
+        self.results_final_order(&client, &self.final_order_request())
+            .await?;
         self.results_1v1_matrix(
             &client,
             &Results1v1MatrixRequest {
-                topic_id: "crisis_v2_season_4_1_benchtest".to_string(),
+                topic_id: self.topic_id.clone(),
+                format: MatrixFormat::Keyed,
             },
         )
         .await?;
@@ -305,6 +427,53 @@ impl ServiceTester {
         Ok(())
     }
 
+    /// Fails with a readable error when the target topic does not exist or
+    /// is not open for voting.
+    async fn check_topic_open(&self, client: &Client) -> Result<()> {
+        let response = client
+            .post(format!("{}/topic/info", self.base_url))
+            .json(&TopicInfoRequest {
+                topic_id: self.topic_id.clone(),
+            })
+            .send()
+            .await
+            .context("get topic_info failed")?
+            .json::<ApiResponse<TopicInfoResponse>>()
+            .await
+            .context("parsing topic_info failed")?;
+        let ApiData::Data(topic) = response.data else {
+            return Err(eyre::eyre!(
+                "load test topic {} is unavailable: {}",
+                self.topic_id,
+                response.message
+            ));
+        };
+
+        let response = client
+            .post(format!("{}/topic/list", self.base_url))
+            .send()
+            .await
+            .context("get topic_list_active failed")?
+            .json::<ApiResponse<TopicListActiveResponse>>()
+            .await
+            .context("parsing topic_list_active failed")?;
+        let active = match response.data {
+            ApiData::Data(data) => data.topic_ids.iter().any(|id| *id == self.topic_id),
+            ApiData::Empty => false,
+        };
+        if !active {
+            return Err(eyre::eyre!(
+                "load test topic {} is not open for voting (window {} to {})",
+                self.topic_id,
+                topic.open_time,
+                topic.close_time
+            ));
+        }
+
+        tracing::info!("load testing topic {} ({})", topic.id, topic.title);
+        Ok(())
+    }
+
     async fn results_final_order(
         &self,
         client: &Client,
@@ -387,7 +556,7 @@ impl ServiceTester {
             .await
             .context("parsing ballot_save response failed")?;
 
-        if response.status == 0 {
+        if response.status == ResponseStatus::Ok {
             Ok(())
         } else {
             tracing::error!("ballot_save failed: {}", response.message);
@@ -397,4 +566,257 @@ impl ServiceTester {
             ))
         }
     }
+
+    async fn ballot_skip(&self, client: &Client, data: &BallotSkipRequest) -> Result<()> {
+        let res = client
+            .post(format!("{}/ballot/skip", self.base_url))
+            .json(data)
+            .send()
+            .await
+            .context("post ballot_skip failed")?;
+
+        let response = res
+            .json::<ApiResponse<BallotSkipResponse>>()
+            .await
+            .context("parsing ballot_skip response failed")?;
+
+        // servers from before the status codes were unified answer skips
+        // with 200, which reads as `Ok` as well
+        if response.status == ResponseStatus::Ok {
+            Ok(())
+        } else {
+            Err(eyre::eyre!(
+                "ballot_skip failed: status {}",
+                response.status
+            ))
+        }
+    }
+}
+
+/// Checks the ballot count and total score, as `(count, score)`, moved by
+/// exactly the `saved` pairwise ballots, each adding one win and one loss.
+/// Only run in strict mode.
+fn check_totals(init: (i64, i64), last: (i64, i64), saved: i64) -> Result<()> {
+    eyre::ensure!(
+        last.0 == init.0 + saved,
+        "ballot count moved from {} to {}, expected {} saved ballots",
+        init.0,
+        last.0,
+        saved
+    );
+    eyre::ensure!(
+        last.1 == init.1 + saved * 2,
+        "total score moved from {} to {}, expected {} from {} saved ballots",
+        init.1,
+        last.1,
+        saved * 2,
+        saved
+    );
+
+    Ok(())
+}
+
+/// Checks how much each operator's wins and losses moved between the two
+/// snapshots against the ballots the load test saved. Only the operators in
+/// `expected` are checked, unless `strict` also requires every other
+/// operator to be unchanged. That last rule is stricter than the validation
+/// before strict mode, which skipped operators the test did not vote on.
+fn check_operator_deltas(
+    init: &[FinalOrderItem],
+    last: &[FinalOrderItem],
+    expected: &HashMap<i32, (i64, i64)>,
+    strict: bool,
+) -> Result<()> {
+    for item in last {
+        let (expected_win, expected_lose) = match expected.get(&item.id) {
+            Some(&delta) => delta,
+            None if strict => (0, 0),
+            None => continue,
+        };
+        let init = init
+            .iter()
+            .find(|x| x.id == item.id)
+            .with_context(|| format!("operator ID {} not found", item.id))?;
+
+        eyre::ensure!(
+            item.name == init.name,
+            "operator {} renamed from {} to {}",
+            item.id,
+            init.name,
+            item.name
+        );
+        let delta = (item.win - init.win, item.lose - init.lose);
+        eyre::ensure!(
+            delta == (expected_win, expected_lose),
+            "operator {} moved by {:?}, expected {:?}",
+            item.id,
+            delta,
+            (expected_win, expected_lose)
+        );
+    }
+
+    Ok(())
+}
+
+fn histogram_for(
+    histograms: &mut HashMap<LoadTestScenario, Histogram<u64>>,
+    scenario: LoadTestScenario,
+) -> Option<&mut Histogram<u64>> {
+    match histograms.entry(scenario) {
+        Entry::Occupied(entry) => Some(entry.into_mut()),
+        Entry::Vacant(entry) => Some(entry.insert(Histogram::new(3).ok()?)),
+    }
+}
+
+/// Expands the scenario weights into the order requests cycle through, so
+/// each scenario gets its share of every full cycle. Falls back to
+/// `create_save` alone when no scenario has a positive weight.
+fn scenario_schedule(weights: &[LoadTestScenarioWeight]) -> Vec<LoadTestScenario> {
+    let schedule: Vec<_> = weights
+        .iter()
+        .flat_map(|w| std::iter::repeat_n(w.scenario, w.weight as usize))
+        .collect();
+
+    if schedule.is_empty() {
+        vec![LoadTestScenario::CreateSave]
+    } else {
+        schedule
+    }
+}
+
+/// Counts the recorded latencies per bucket, bucket `i` holding the values
+/// up to `bounds[i]` (inclusive) above the previous bound and the last one
+/// everything above the highest bound. `bounds` must be sorted.
+fn latency_distribution(hist: &Histogram<u64>, bounds: &[u64]) -> Vec<u64> {
+    let mut counts = vec![0; bounds.len() + 1];
+    for value in hist.iter_recorded() {
+        let latency = hist.lowest_equivalent(value.value_iterated_to());
+        counts[bounds.partition_point(|&bound| bound < latency)] += value.count_at_value();
+    }
+    counts
+}
+
+fn bucket_label(bounds: &[u64], i: usize) -> String {
+    match (i.checked_sub(1).map(|prev| bounds[prev]), bounds.get(i)) {
+        (None, Some(upper)) => format!("0-{upper}"),
+        (Some(lower), Some(upper)) => format!("{}-{upper}", lower + 1),
+        (Some(lower), None) => format!(">{lower}"),
+        (None, None) => "all".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skip_accepts_old_and_unified_ok_codes() {
+        for code in [0, 200] {
+            let body =
+                serde_json::json!({ "status": code, "data": { "code": 0 }, "message": "OK" });
+            let response: ApiResponse<BallotSkipResponse> = serde_json::from_value(body).unwrap();
+            assert_eq!(response.status, ResponseStatus::Ok);
+        }
+    }
+
+    #[test]
+    fn test_scenario_schedule_follows_weights() {
+        let weights = [
+            LoadTestScenarioWeight {
+                scenario: LoadTestScenario::CreateSave,
+                weight: 3,
+            },
+            LoadTestScenarioWeight {
+                scenario: LoadTestScenario::Matrix,
+                weight: 0,
+            },
+            LoadTestScenarioWeight {
+                scenario: LoadTestScenario::FinalOrder,
+                weight: 1,
+            },
+        ];
+
+        let schedule = scenario_schedule(&weights);
+        assert_eq!(
+            schedule,
+            [
+                LoadTestScenario::CreateSave,
+                LoadTestScenario::CreateSave,
+                LoadTestScenario::CreateSave,
+                LoadTestScenario::FinalOrder,
+            ]
+        );
+        assert_eq!(scenario_schedule(&[]), [LoadTestScenario::CreateSave]);
+    }
+
+    fn item(id: i32, win: i64, lose: i64) -> FinalOrderItem {
+        FinalOrderItem {
+            name: format!("op_{id}"),
+            id,
+            win,
+            lose,
+            score: String::new(),
+            rate: String::new(),
+            tier: None,
+            score_value: 0.0,
+            rate_value: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_operator_deltas_ignore_other_traffic() {
+        let init = [item(1, 10, 5), item(2, 3, 3), item(3, 0, 0)];
+        // operator 3 was voted on by someone else during the run
+        let last = [item(1, 12, 5), item(2, 3, 5), item(3, 4, 1)];
+        let expected = HashMap::from([(1, (2, 0)), (2, (0, 2))]);
+
+        assert!(check_operator_deltas(&init, &last, &expected, false).is_ok());
+        assert!(check_operator_deltas(&init, &last, &expected, true).is_err());
+
+        let quiet = [item(1, 12, 5), item(2, 3, 5), item(3, 0, 0)];
+        assert!(check_operator_deltas(&init, &quiet, &expected, true).is_ok());
+    }
+
+    #[test]
+    fn test_totals_report_other_traffic() {
+        assert!(check_totals((100, 400), (110, 420), 10).is_ok());
+        assert!(check_totals((100, 400), (111, 420), 10).is_err());
+        assert!(check_totals((100, 400), (110, 424), 10).is_err());
+    }
+
+    #[test]
+    fn test_operator_deltas_check_voted_operators() {
+        let init = [item(1, 10, 5), item(2, 3, 3)];
+        let last = [item(1, 11, 5), item(2, 3, 5)];
+        let expected = HashMap::from([(1, (2, 0)), (2, (0, 2))]);
+
+        assert!(check_operator_deltas(&init, &last, &expected, false).is_err());
+    }
+
+    #[test]
+    fn test_latency_buckets_are_disjoint() {
+        let mut hist = Histogram::<u64>::new(3).unwrap();
+        for latency in [200, 1_000, 1_001, 1_500, 2_000] {
+            hist.record(latency).unwrap();
+        }
+
+        let bounds = [1_000, 1_500];
+        assert_eq!(latency_distribution(&hist, &bounds), [2, 2, 1]);
+
+        let labels: Vec<_> = (0..=bounds.len())
+            .map(|i| bucket_label(&bounds, i))
+            .collect();
+        assert_eq!(labels, ["0-1000", "1001-1500", ">1500"]);
+    }
+
+    #[test]
+    fn test_latency_buckets_sum_to_total() {
+        let mut hist = Histogram::<u64>::new(3).unwrap();
+        for latency in (0..50_000).step_by(37) {
+            hist.record(latency).unwrap();
+        }
+
+        let counts = latency_distribution(&hist, &[1_000, 5_000, 10_000]);
+        assert_eq!(counts.iter().sum::<u64>(), hist.len());
+    }
 }
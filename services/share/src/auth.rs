@@ -0,0 +1,104 @@
+use std::{collections::HashSet, sync::Arc};
+
+use crate::config::AdminConfig;
+
+/// Overrides `admin.tokens` from the config file, as a comma separated list.
+pub const ADMIN_TOKENS_ENV: &str = "ARK_VOTE_ADMIN_TOKENS";
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum AdminAuthError {
+    #[error("missing bearer token")]
+    MissingToken,
+    #[error("invalid admin token")]
+    InvalidToken,
+}
+
+/// Set of bearer tokens accepted on `/admin` routes. An empty set rejects
+/// every request.
+#[derive(Clone, Debug, Default)]
+pub struct AdminTokens(Arc<HashSet<String>>);
+
+impl AdminTokens {
+    pub fn new<I, S>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self(Arc::new(
+            tokens
+                .into_iter()
+                .map(Into::into)
+                .filter(|t| !t.is_empty())
+                .collect(),
+        ))
+    }
+
+    pub fn from_config(config: &AdminConfig) -> Self {
+        match std::env::var(ADMIN_TOKENS_ENV) {
+            Ok(tokens) => Self::new(tokens.split(',').map(str::trim)),
+            Err(_) => Self::new(config.tokens.iter().cloned()),
+        }
+    }
+
+    /// Checks an `Authorization` header value of the form `Bearer <token>`.
+    pub fn authorize(&self, authorization: Option<&str>) -> Result<(), AdminAuthError> {
+        let token = authorization
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .ok_or(AdminAuthError::MissingToken)?;
+
+        if self.0.iter().any(|t| constant_time_eq(t, token)) {
+            Ok(())
+        } else {
+            Err(AdminAuthError::InvalidToken)
+        }
+    }
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize_missing_token() {
+        let tokens = AdminTokens::new(["secret"]);
+        assert_eq!(tokens.authorize(None), Err(AdminAuthError::MissingToken));
+        assert_eq!(
+            tokens.authorize(Some("secret")),
+            Err(AdminAuthError::MissingToken)
+        );
+        assert_eq!(
+            tokens.authorize(Some("Bearer ")),
+            Err(AdminAuthError::MissingToken)
+        );
+    }
+
+    #[test]
+    fn test_authorize_wrong_token() {
+        let tokens = AdminTokens::new(["secret"]);
+        assert_eq!(
+            tokens.authorize(Some("Bearer wrong")),
+            Err(AdminAuthError::InvalidToken)
+        );
+        assert_eq!(
+            AdminTokens::default().authorize(Some("Bearer secret")),
+            Err(AdminAuthError::InvalidToken)
+        );
+    }
+
+    #[test]
+    fn test_authorize_correct_token() {
+        let tokens = AdminTokens::new(["secret", "other"]);
+        assert_eq!(tokens.authorize(Some("Bearer secret")), Ok(()));
+        assert_eq!(tokens.authorize(Some("Bearer other")), Ok(()));
+    }
+}
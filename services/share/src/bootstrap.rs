@@ -0,0 +1,345 @@
+//! Startup steps shared by the services: connecting to Redis, MongoDB and
+//! NATS, seeding the preset topics and loading the character table. The
+//! services build their own state on top of the clients returned here.
+
+use std::{collections::HashMap, fs};
+
+use eyre::Context as _;
+use mongodb::bson::doc;
+
+use crate::{
+    config::{
+        BallotGraceConfig, BallotSizeLimits, DatabaseConfig, NatsConfig, SaveThrottleConfig,
+        VoteConfig,
+    },
+    models::{
+        database::{StoredBallot, TopicVoteOverrides, VotingTopic, VotingTopicType},
+        excel::{CharacterData, CharacterInfo},
+    },
+    recently_shown::RecentlyShown,
+    reputation::UserTokenVerifier,
+    retry::connect_with_retry,
+    selection::PairingConstraint,
+    snowflake::{Snowflake, SnowflakeConfig},
+};
+
+pub const CHARACTER_TABLE_FILE: &str = "character_table.json";
+
+#[derive(Debug, thiserror::Error)]
+pub enum BootstrapError {
+    #[error("missing {CHARACTER_TABLE_FILE}")]
+    MissingCharacterTable,
+    #[error("failed to read {CHARACTER_TABLE_FILE}: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse {CHARACTER_TABLE_FILE}: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Reads `character_table.json` from the working directory.
+pub fn load_character_table() -> Result<HashMap<String, CharacterData>, BootstrapError> {
+    if fs::metadata(CHARACTER_TABLE_FILE).is_err() {
+        tracing::error!("Missing {} file", CHARACTER_TABLE_FILE);
+        return Err(BootstrapError::MissingCharacterTable);
+    }
+
+    let buf = fs::read(CHARACTER_TABLE_FILE)?;
+    Ok(serde_json::from_slice(&buf)?)
+}
+
+/// Operators of the character table, keyed `char_<id>_<name>`. Tokens, traps
+/// and other non operator entries are skipped.
+pub fn character_infos(character_table: &HashMap<String, CharacterData>) -> Vec<CharacterInfo> {
+    character_table
+        .iter()
+        .filter_map(|(name, data)| {
+            let mut parts = name.strip_prefix("char_")?.splitn(2, '_');
+            let charid = parts.next()?.parse::<i32>().ok()?;
+            Some(CharacterInfo {
+                id: charid,
+                name: data.name.clone(),
+                rarity: data.rarity,
+                profession: data.profession.clone(),
+                sub_profession_id: data.sub_profession_id.clone(),
+                is_not_obtainable: data.is_not_obtainable,
+            })
+        })
+        .collect()
+}
+
+pub fn load_character_infos() -> Result<Vec<CharacterInfo>, BootstrapError> {
+    Ok(character_infos(&load_character_table()?))
+}
+
+pub async fn connect_nats(config: &NatsConfig) -> eyre::Result<async_nats::Client> {
+    let client = connect_with_retry("nats", &config.connect_retry, || {
+        async_nats::connect(&config.url)
+    })
+    .await
+    .context("failed to connect to nats")?;
+    tracing::debug!("connected to nats at {}", config.url);
+    Ok(client)
+}
+
+/// The Redis client and a multiplexed connection, which also proves the
+/// server is reachable.
+pub async fn connect_redis(
+    config: &DatabaseConfig,
+) -> eyre::Result<(redis::Client, redis::aio::MultiplexedConnection)> {
+    let client =
+        redis::Client::open(&*config.redis_url).context("failed to create Redis client")?;
+    let connection = connect_with_retry("redis", &config.connect_retry, || {
+        client.get_multiplexed_async_connection()
+    })
+    .await
+    .context("failed to connect to Redis")?;
+    tracing::debug!("connected to redis at {}", config.redis_url);
+    Ok((client, connection))
+}
+
+/// `Client::with_uri_str` connects lazily, so ping the server to surface
+/// connection failures during startup.
+pub async fn connect_mongodb(url: &str) -> Result<mongodb::Client, mongodb::error::Error> {
+    let client = mongodb::Client::with_uri_str(url).await?;
+    client
+        .database("admin")
+        .run_command(doc! { "ping": 1 })
+        .await?;
+    Ok(client)
+}
+
+/// The primary MongoDB database, which takes every write.
+pub async fn connect_mongodb_database(config: &DatabaseConfig) -> eyre::Result<mongodb::Database> {
+    let client = connect_with_retry("mongodb", &config.connect_retry, || {
+        connect_mongodb(&config.mongodb_url)
+    })
+    .await
+    .context("failed to connect to MongoDB")?;
+    tracing::debug!(
+        "connected to mongodb at {}, database: {}",
+        config.mongodb_url,
+        config.mongodb_database
+    );
+    Ok(client.database(&config.mongodb_database))
+}
+
+/// The database analytical reads go to: the read replica when one is
+/// configured, `primary` otherwise.
+pub async fn connect_mongodb_read_database(
+    config: &DatabaseConfig,
+    primary: &mongodb::Database,
+) -> eyre::Result<mongodb::Database> {
+    let Some(read_url) = &config.mongodb_read_url else {
+        return Ok(primary.clone());
+    };
+    let client = connect_with_retry("mongodb read replica", &config.connect_retry, || {
+        connect_mongodb(read_url)
+    })
+    .await
+    .context("failed to connect to the MongoDB read replica")?;
+    tracing::debug!("connected to mongodb read replica at {}", read_url);
+    Ok(client.database(&config.mongodb_database))
+}
+
+/// Server code of a write rejected by a unique index.
+const DUPLICATE_KEY_CODE: i32 = 11000;
+
+/// Makes topic ids unique, so two concurrent creations of the same topic
+/// cannot both be inserted. Creating an existing index is a no-op.
+pub async fn ensure_topic_index(database: &mongodb::Database) -> Result<(), mongodb::error::Error> {
+    let index = mongodb::IndexModel::builder()
+        .keys(doc! { "id": 1 })
+        .options(
+            mongodb::options::IndexOptions::builder()
+                .unique(true)
+                .build(),
+        )
+        .build();
+    database
+        .collection::<mongodb::bson::Document>("topics")
+        .create_index(index)
+        .await?;
+    Ok(())
+}
+
+/// Whether a write failed because a unique index already holds its key.
+pub fn is_duplicate_key_error(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(e))
+            if e.code == DUPLICATE_KEY_CODE
+    )
+}
+
+/// Inserts the configured preset topics, or refreshes the stored ones.
+pub async fn upsert_preset_topics(
+    database: &mongodb::Database,
+    vote: &VoteConfig,
+    character_infos: &[CharacterInfo],
+) -> eyre::Result<()> {
+    let collection = database.collection::<VotingTopic>("topics");
+
+    for preset_topic in vote.valid_preset_topics()? {
+        let filter = doc! { "id": &preset_topic.id };
+
+        match collection.find_one(filter).await {
+            Ok(Some(stored)) => {
+                let preset_topic =
+                    vote.prepare_preset_topic(preset_topic, Some(&stored), character_infos);
+                let query = doc! { "id": &preset_topic.id };
+                collection.replace_one(query, &preset_topic).await?;
+                tracing::info!("updated preset voting topic: {}", preset_topic.id);
+            }
+            Ok(None) => {
+                let preset_topic = vote.prepare_preset_topic(preset_topic, None, character_infos);
+                tracing::info!("inserting preset voting topic: {}", preset_topic.id);
+                collection.insert_one(&preset_topic).await?;
+            }
+            Err(_) => {
+                // maybe data structure has changed, we force replace
+                let preset_topic = vote.prepare_preset_topic(preset_topic, None, character_infos);
+                let query = doc! { "id": &preset_topic.id };
+                collection.replace_one(query, &preset_topic).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Indexes the ballot id of the topic's stored ballots, used by the admin
+/// ballot lookup, and their cast time, matched by the velocity and rank
+/// movement aggregations. Creating an existing index is a no-op.
+pub async fn ensure_ballot_index(
+    database: &mongodb::Database,
+    topic_id: &str,
+) -> Result<(), mongodb::error::Error> {
+    let indexes = [StoredBallot::BALLOT_ID_FIELD, StoredBallot::TIMESTAMP_FIELD].map(|field| {
+        mongodb::IndexModel::builder()
+            .keys(doc! { field: 1 })
+            .build()
+    });
+    database
+        .collection::<mongodb::bson::Document>(&format!("ballots_{topic_id}"))
+        .create_indexes(indexes)
+        .await?;
+    Ok(())
+}
+
+/// Runs `ensure_ballot_index` for every topic.
+pub async fn ensure_ballot_indexes(
+    database: &mongodb::Database,
+) -> Result<(), mongodb::error::Error> {
+    let topic_ids = database
+        .collection::<mongodb::bson::Document>("topics")
+        .distinct("id", doc! {})
+        .await?;
+    for topic_id in topic_ids.iter().filter_map(|id| id.as_str()) {
+        ensure_ballot_index(database, topic_id).await?;
+    }
+    Ok(())
+}
+
+impl Snowflake {
+    pub fn from_config(config: &SnowflakeConfig, worker_id: u8) -> Self {
+        Snowflake::new(
+            config.datacenter_id,
+            worker_id,
+            config.epoch,
+            config.max_clock_backward_ms,
+        )
+    }
+}
+
+/// Voting settings read by the request handlers of both services.
+#[derive(Clone)]
+pub struct VoteSettings {
+    pub pairing_constraint: PairingConstraint,
+    pub undersampled_bias: f64,
+    pub max_ip_limit: i32,
+    pub ballot_size_limits: BallotSizeLimits,
+    pub enabled_topic_types: Vec<VotingTopicType>,
+    pub user_tokens: Option<UserTokenVerifier>,
+    pub save_throttle: SaveThrottleConfig,
+    pub ballot_grace: BallotGraceConfig,
+    pub recently_shown: RecentlyShown,
+    pub ballot_store_sample_rate: u64,
+    pub ballot_code_length: usize,
+    pub selection_seed: Option<u64>,
+    pub freeze_candidate_pools: bool,
+}
+
+impl VoteSettings {
+    pub fn from_config(config: &VoteConfig) -> Self {
+        Self {
+            pairing_constraint: config.pairing_constraint,
+            undersampled_bias: config.undersampled_bias,
+            max_ip_limit: config.max_ip_limit,
+            ballot_size_limits: config.ballot_size_limits,
+            enabled_topic_types: config.enabled_topic_types.clone(),
+            user_tokens: UserTokenVerifier::from_config(&config.reputation),
+            save_throttle: config.save_throttle.clone(),
+            ballot_grace: config.ballot_grace.clone(),
+            recently_shown: RecentlyShown::new(config.recently_shown.clone()),
+            ballot_store_sample_rate: config.ballot_store_sample_rate,
+            ballot_code_length: config.ballot_code_length,
+            selection_seed: config.selection_seed,
+            freeze_candidate_pools: config.freeze_candidate_pools,
+        }
+    }
+
+    /// Random suffix length of the ballot codes handed out for a topic.
+    pub fn ballot_code_length(&self, overrides: &TopicVoteOverrides) -> usize {
+        overrides
+            .ballot_code_length
+            .unwrap_or(self.ballot_code_length)
+    }
+
+    /// Seed of the topic's operator selection, `None` for a random one.
+    pub fn selection_seed(&self, overrides: &TopicVoteOverrides) -> Option<u64> {
+        overrides.selection_seed.or(self.selection_seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::error::{Error, ErrorKind, WriteError, WriteFailure};
+
+    use super::*;
+    use crate::models::excel::{ProfessionCategory, RarityRank};
+
+    #[test]
+    fn test_character_infos() {
+        let data = |name: &str| CharacterData {
+            name: name.to_string(),
+            rarity: RarityRank::Tier6,
+            profession: ProfessionCategory::CASTER,
+            sub_profession_id: "corecaster".to_string(),
+            is_not_obtainable: false,
+        };
+        let table = HashMap::from([
+            ("char_002_amiya".to_string(), data("阿米娅")),
+            ("char_1001_amiya2".to_string(), data("阿米娅")),
+            ("token_10000_silent_healrb".to_string(), data("医疗无人机")),
+            ("trap_001_crate".to_string(), data("障碍物")),
+        ]);
+
+        let mut ids: Vec<i32> = character_infos(&table).iter().map(|c| c.id).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, [2, 1001]);
+    }
+
+    fn write_error(code: i32) -> Error {
+        let error: WriteError = mongodb::bson::from_document(doc! {
+            "code": code,
+            "errmsg": "write failed",
+        })
+        .unwrap();
+        ErrorKind::Write(WriteFailure::WriteError(error)).into()
+    }
+
+    #[test]
+    fn test_is_duplicate_key_error() {
+        assert!(is_duplicate_key_error(&write_error(DUPLICATE_KEY_CODE)));
+        assert!(!is_duplicate_key_error(&write_error(121)));
+    }
+}
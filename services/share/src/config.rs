@@ -1,9 +1,19 @@
-use std::path::Path;
+use std::{collections::HashMap, ops::RangeInclusive, path::Path, time::Duration};
 
 use async_nats::jetstream::stream::{RetentionPolicy, StorageType};
 use serde::{Deserialize, de::DeserializeOwned};
 
-use crate::{models::database::VotingTopic, snowflake::SnowflakeConfig};
+use crate::{
+    models::{
+        candidate_pool_preset::MAX_PRESET_DEPTH,
+        database::{TopicValidationError, TopicVoteOverrides, VotingTopic, VotingTopicType},
+        excel::CharacterInfo,
+        topic_id::TopicId,
+    },
+    retry::ConnectRetryConfig,
+    selection::PairingConstraint,
+    snowflake::{Snowflake, SnowflakeConfig},
+};
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct AppConfig {
@@ -17,12 +27,70 @@ pub struct AppConfig {
     pub nats: NatsConfig,
     pub test: TestConfig,
     pub task_manager: TaskManagerConfig,
+    #[serde(default)]
+    pub topic_cache: TopicCacheConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub webhooks: WebhookConfig,
+    #[serde(default)]
+    pub portraits: PortraitConfig,
+    #[serde(default)]
+    pub operators: OperatorsConfig,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    /// Maximum accepted request body size in bytes.
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+    #[serde(default)]
+    pub timeouts: RouteTimeouts,
+}
+
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+fn default_max_body_size() -> usize {
+    DEFAULT_MAX_BODY_SIZE
+}
+
+/// Request timeouts in seconds. `routes` overrides `default_secs` for the
+/// routes nested under a prefix such as `/results`, `0` disables the timeout
+/// for long lived responses.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RouteTimeouts {
+    #[serde(default = "default_timeout_secs")]
+    pub default_secs: u64,
+    #[serde(default)]
+    pub routes: HashMap<String, u64>,
+}
+
+impl Default for RouteTimeouts {
+    fn default() -> Self {
+        Self {
+            default_secs: default_timeout_secs(),
+            routes: HashMap::new(),
+        }
+    }
+}
+
+fn default_timeout_secs() -> u64 {
+    60
+}
+
+impl RouteTimeouts {
+    pub fn for_prefix(&self, prefix: &str) -> Option<Duration> {
+        let secs = self
+            .routes
+            .get(prefix)
+            .copied()
+            .unwrap_or(self.default_secs);
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
 }
 
 impl ServerConfig {
@@ -37,10 +105,366 @@ pub struct VoteConfig {
     pub low_multiplier: i32,
     pub max_ip_limit: i32,
     pub ip_counter_expire_seconds: usize,
+    #[serde(default)]
+    pub pairing_constraint: PairingConstraint,
+    /// Exponent of the game count down-weighting of `FavorUndersampled`,
+    /// 0 draws uniformly.
+    #[serde(default = "default_undersampled_bias")]
+    pub undersampled_bias: f64,
+    #[serde(default)]
+    pub ballot_size_limits: BallotSizeLimits,
+    /// Sub professions excluded from all candidate pools at startup, can be
+    /// changed at runtime through the admin API.
+    #[serde(default)]
+    pub blocked_sub_professions: Vec<String>,
+    #[serde(default)]
+    pub reputation: ReputationConfig,
+    #[serde(default)]
+    pub save_throttle: SaveThrottleConfig,
+    #[serde(default)]
+    pub ballot_grace: BallotGraceConfig,
+    #[serde(default)]
+    pub recently_shown: RecentlyShownConfig,
+    /// Upper bound on each operator's stored win/lose score. Updates past it
+    /// are clamped, leave unset for unbounded counters.
+    #[serde(default)]
+    pub score_ceiling: Option<i64>,
+    /// Writes one in `rate` ballots to the `ballots_<topic_id>` collections,
+    /// 1 stores all of them. Scores in Redis always count every ballot, but
+    /// anything read back from the stored ballots, such as the admin ballot
+    /// lookup, only sees the sample.
+    #[serde(default = "default_ballot_store_sample_rate")]
+    pub ballot_store_sample_rate: u64,
+    /// Length of the random suffix of ballot codes, within
+    /// `BALLOT_CODE_LENGTHS`. Longer codes are harder to guess.
+    #[serde(default = "default_ballot_code_length")]
+    pub ballot_code_length: usize,
+    /// Seeds the operator selection of every topic so the served pairs repeat
+    /// across runs, see `SelectionRngs`. Unset draws from the thread RNG.
+    #[serde(default)]
+    pub selection_seed: Option<u64>,
 
+    /// Log and skip preset topics that fail validation instead of refusing
+    /// to start.
+    #[serde(default)]
+    pub skip_invalid_preset_topics: bool,
+    /// Topic types that can be created and voted on, the others are answered
+    /// with `UnsupportedTopicType`.
+    #[serde(default = "default_enabled_topic_types")]
+    pub enabled_topic_types: Vec<VotingTopicType>,
+    /// Deepest union/intersection/difference nesting resolved in candidate
+    /// pool presets, deeper presets resolve to an empty pool.
+    #[serde(default = "default_max_preset_depth")]
+    pub max_preset_depth: usize,
+    /// Snapshot the resolved candidate pool of a topic when it becomes
+    /// active, see `VotingTopic::frozen_pool`.
+    #[serde(default)]
+    pub freeze_candidate_pools: bool,
+    /// Maximum number of topics labelled in `votes_saved_total`, ballots of
+    /// further topics only count in the unlabelled
+    /// `votes_saved_overflow_total`.
+    #[serde(default = "default_throughput_metric_topics")]
+    pub throughput_metric_topics: usize,
     pub preset_vote_topic: Vec<VotingTopic>,
 }
 
+fn default_undersampled_bias() -> f64 {
+    1.0
+}
+
+fn default_ballot_store_sample_rate() -> u64 {
+    1
+}
+
+/// Random suffix length of ballot codes unless configured otherwise.
+pub const DEFAULT_BALLOT_CODE_LENGTH: usize = 8;
+
+/// Accepted `ballot_code_length` values, globally and per topic.
+pub const BALLOT_CODE_LENGTHS: RangeInclusive<usize> = 6..=64;
+
+fn default_ballot_code_length() -> usize {
+    DEFAULT_BALLOT_CODE_LENGTH
+}
+
+fn default_max_preset_depth() -> usize {
+    MAX_PRESET_DEPTH
+}
+
+fn default_throughput_metric_topics() -> usize {
+    64
+}
+
+fn default_enabled_topic_types() -> Vec<VotingTopicType> {
+    vec![
+        VotingTopicType::Pairwise,
+        VotingTopicType::Setwise,
+        VotingTopicType::Groupwise,
+        VotingTopicType::Plurality,
+    ]
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid preset topic {id:?}: {source}")]
+pub struct InvalidPresetTopic {
+    pub id: String,
+    #[source]
+    pub source: TopicValidationError,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "vote.ballot_code_length must be between {min} and {max}, got {len}",
+    min = BALLOT_CODE_LENGTHS.start(),
+    max = BALLOT_CODE_LENGTHS.end(),
+    len = .0
+)]
+pub struct InvalidBallotCodeLength(pub usize);
+
+impl VoteConfig {
+    /// Checked at startup, before any ballot code is handed out.
+    pub fn check_ballot_code_length(&self) -> Result<(), InvalidBallotCodeLength> {
+        match BALLOT_CODE_LENGTHS.contains(&self.ballot_code_length) {
+            true => Ok(()),
+            false => Err(InvalidBallotCodeLength(self.ballot_code_length)),
+        }
+    }
+
+    /// Preset topics that pass `VotingTopic::validate`, in config order.
+    pub fn valid_preset_topics(&self) -> Result<Vec<&VotingTopic>, InvalidPresetTopic> {
+        validate_preset_topics(
+            &self.preset_vote_topic,
+            self.skip_invalid_preset_topics,
+            self.max_preset_depth,
+        )
+    }
+
+    /// The preset topic as written over the `stored` one at startup. A pool
+    /// frozen on the stored topic is kept even if the preset changed since,
+    /// otherwise an active preset is frozen under `freeze_candidate_pools`.
+    pub fn prepare_preset_topic(
+        &self,
+        preset: &VotingTopic,
+        stored: Option<&VotingTopic>,
+        character_infos: &[CharacterInfo],
+    ) -> VotingTopic {
+        let mut topic = preset.clone();
+        topic.frozen_pool = stored.and_then(|stored| stored.frozen_pool.clone());
+        if self.freeze_candidate_pools && topic.is_active && topic.frozen_pool.is_none() {
+            topic.freeze_pool(character_infos, self.max_preset_depth);
+        }
+        topic
+    }
+}
+
+fn validate_preset_topics(
+    topics: &[VotingTopic],
+    skip_invalid: bool,
+    max_preset_depth: usize,
+) -> Result<Vec<&VotingTopic>, InvalidPresetTopic> {
+    let mut valid = Vec::with_capacity(topics.len());
+    for topic in topics {
+        match topic.validate(max_preset_depth) {
+            Ok(()) => valid.push(topic),
+            Err(e) if skip_invalid => {
+                tracing::warn!("skipping invalid preset topic {:?}: {}", topic.id, e);
+            }
+            Err(source) => {
+                return Err(InvalidPresetTopic {
+                    id: topic.id.clone(),
+                    source,
+                });
+            }
+        }
+    }
+
+    Ok(valid)
+}
+
+/// Upper bounds on the operators per set (setwise) or group (groupwise) ballot,
+/// since each ballot expands into `left * right` pairwise updates.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct BallotSizeLimits {
+    pub max_set_size: usize,
+    pub max_group_size: usize,
+}
+
+impl Default for BallotSizeLimits {
+    fn default() -> Self {
+        Self {
+            max_set_size: 8,
+            max_group_size: 8,
+        }
+    }
+}
+
+impl BallotSizeLimits {
+    pub fn set_fits(&self, set: &[i32]) -> bool {
+        set.len() <= self.max_set_size
+    }
+
+    pub fn group_fits(&self, group: &[i32]) -> bool {
+        group.len() <= self.max_group_size
+    }
+}
+
+/// IP counter settings the ballots of one topic are weighted with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpLimits {
+    pub max_ip_limit: i32,
+    pub base_multiplier: i32,
+    pub low_multiplier: i32,
+}
+
+impl TopicVoteOverrides {
+    /// The topic's IP counter settings, falling back to `config` for the
+    /// fields it does not override.
+    pub fn resolve(&self, config: &VoteConfig) -> IpLimits {
+        IpLimits {
+            max_ip_limit: self.max_ip_limit.unwrap_or(config.max_ip_limit),
+            base_multiplier: self.base_multiplier.unwrap_or(config.base_multiplier),
+            low_multiplier: self.low_multiplier.unwrap_or(config.low_multiplier),
+        }
+    }
+
+    pub fn ballot_store_sample_rate(&self, config: &VoteConfig) -> u64 {
+        self.ballot_store_sample_rate
+            .unwrap_or(config.ballot_store_sample_rate)
+    }
+}
+
+/// Minimum interval between two saves from the same IP, a burst control on
+/// top of the per-topic IP counters.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SaveThrottleConfig {
+    /// 0 disables the check.
+    #[serde(default)]
+    pub min_interval_ms: u64,
+    #[serde(default)]
+    pub mode: SaveThrottleMode,
+}
+
+impl SaveThrottleConfig {
+    pub fn enabled(&self) -> bool {
+        self.min_interval_ms > 0
+    }
+
+    /// Redis key holding the last save slot of `ip`, across topics.
+    pub fn key(ip: &str) -> String {
+        format!("save_throttle:{ip}")
+    }
+}
+
+/// Minimum age of a ballot code before it can be saved, read from the
+/// snowflake timestamp at the front of the ballot id. Saves faster than a
+/// human reaction are most likely scripted.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct BallotGraceConfig {
+    /// 0 disables the check.
+    #[serde(default)]
+    pub min_age_ms: u64,
+    #[serde(default)]
+    pub mode: SaveThrottleMode,
+}
+
+impl BallotGraceConfig {
+    pub fn enabled(&self) -> bool {
+        self.min_age_ms > 0
+    }
+
+    /// Whether `ballot_id` was created less than `min_age_ms` before `now_ms`.
+    /// Ids without a snowflake prefix are left to the ballot code check.
+    pub fn is_too_soon(&self, snowflake: &Snowflake, ballot_id: &str, now_ms: u64) -> bool {
+        if !self.enabled() {
+            return false;
+        }
+
+        let Some(id) = ballot_id
+            .split_once('-')
+            .and_then(|(id, _)| id.parse::<u64>().ok())
+        else {
+            return false;
+        };
+        now_ms < snowflake.timestamp_ms(id).saturating_add(self.min_age_ms)
+    }
+}
+
+/// What happens to a save that arrives within the minimum interval, or before
+/// the ballot grace period is over.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum SaveThrottleMode {
+    /// Answer with `ApiMsg::RateLimited`, or `ApiMsg::BallotSavedTooSoon`
+    /// for the grace period.
+    #[default]
+    Reject,
+    /// Accept the ballot but count it with a zero multiplier.
+    ZeroMultiplier,
+}
+
+/// The per-topic set of operators served by recent ballots, which
+/// `PairingConstraint::AvoidRecentlyShown` keeps out of new pairs.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RecentlyShownConfig {
+    /// How long a served operator stays in the set.
+    #[serde(default = "default_recently_shown_window_ms")]
+    pub window_ms: u64,
+    /// Most operators kept in the set, the oldest are dropped first.
+    #[serde(default = "default_recently_shown_max_operators")]
+    pub max_operators: usize,
+}
+
+impl Default for RecentlyShownConfig {
+    fn default() -> Self {
+        Self {
+            window_ms: default_recently_shown_window_ms(),
+            max_operators: default_recently_shown_max_operators(),
+        }
+    }
+}
+
+impl RecentlyShownConfig {
+    /// Sorted set of operator ids scored by the millisecond they were served.
+    pub fn key(topic_id: &str) -> String {
+        format!("{topic_id}:recently_shown")
+    }
+}
+
+fn default_recently_shown_window_ms() -> u64 {
+    5000
+}
+
+fn default_recently_shown_max_operators() -> usize {
+    32
+}
+
+/// Per-user reputation weights, applied on top of the IP multiplier for ballots
+/// carrying a valid signed user token.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ReputationConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// HMAC secret the user tokens are signed with, overridable with the
+    /// `ARK_VOTE_USER_TOKEN_SECRET` environment variable.
+    #[serde(default)]
+    pub token_secret: String,
+    /// Upper bound applied to the reputation read from `user_rep`.
+    #[serde(default = "default_max_reputation")]
+    pub max_reputation: f64,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            token_secret: String::new(),
+            max_reputation: default_max_reputation(),
+        }
+    }
+}
+
+fn default_max_reputation() -> f64 {
+    4.0
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct CorsConfig {
     pub allow_origin: Vec<String>,
@@ -51,7 +475,24 @@ pub struct CorsConfig {
 pub struct DatabaseConfig {
     pub redis_url: String,
     pub mongodb_url: String,
+    /// Read replica serving the analytical reads of the web and portable
+    /// services: admin ballot lookup, results velocity, timeseries and rank
+    /// movement. Writes, topics and index creation stay on `mongodb_url`,
+    /// which also serves the reads when this is unset.
+    #[serde(default)]
+    pub mongodb_read_url: Option<String>,
     pub mongodb_database: String,
+    /// Topics whose ballots are inserted into MongoDB in parallel when a
+    /// batch spans several topics.
+    #[serde(default = "default_mongodb_insert_concurrency")]
+    pub mongodb_insert_concurrency: usize,
+
+    #[serde(default)]
+    pub connect_retry: ConnectRetryConfig,
+}
+
+fn default_mongodb_insert_concurrency() -> usize {
+    4
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -64,6 +505,14 @@ pub struct TracingConfig {
     pub level: String,
     pub log_file_directory: String,
     pub directives: Vec<String>,
+    /// Only one in this many batches emits the per-batch debug logs of the
+    /// ballot processors, 1 logs every batch.
+    #[serde(default = "default_debug_sample_rate")]
+    pub debug_sample_rate: u64,
+}
+
+fn default_debug_sample_rate() -> u64 {
+    1
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -71,6 +520,67 @@ pub struct NatsConfig {
     pub url: String,
     pub consumers: Vec<NatsConsumerConfig>,
     pub stream_config: NatsStreamConfig,
+
+    #[serde(default)]
+    pub connect_retry: ConnectRetryConfig,
+
+    /// Address of the nats-service admin listener serving `/consumers`,
+    /// disabled when unset.
+    #[serde(default)]
+    pub admin_listen: Option<String>,
+
+    #[serde(default)]
+    pub ballot_bundling: BallotBundlingConfig,
+
+    #[serde(default)]
+    pub closed_topic_ballots: ClosedTopicBallots,
+}
+
+/// What the save_score consumer does with a ballot cast while the topic was
+/// open but processed after it closed.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum ClosedTopicBallots {
+    /// Count the ballot as usual.
+    #[default]
+    Apply,
+    /// Acknowledge the ballot without counting it, for hard cutoffs.
+    Drop,
+}
+
+/// Buffers saved ballots in the web service and publishes them as one JSON
+/// array message, trading up to `window_ms` of latency for fewer messages.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct BallotBundlingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_bundle_max_ballots")]
+    pub max_ballots: usize,
+    #[serde(default = "default_bundle_window_ms")]
+    pub window_ms: u64,
+}
+
+impl Default for BallotBundlingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_ballots: default_bundle_max_ballots(),
+            window_ms: default_bundle_window_ms(),
+        }
+    }
+}
+
+impl BallotBundlingConfig {
+    pub fn window(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.window_ms)
+    }
+}
+
+fn default_bundle_max_ballots() -> usize {
+    100
+}
+
+fn default_bundle_window_ms() -> u64 {
+    20
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -93,15 +603,288 @@ pub struct NatsStreamConfig {
 #[derive(Clone, Debug, Deserialize)]
 pub struct TestConfig {
     pub base_url: String,
+    /// Topic the load test votes on, overridable with `service-test --topic`.
+    /// Has to exist and be open.
+    #[serde(default = "default_test_topic_id")]
+    pub topic_id: TopicId,
     pub total_requests: usize,
     pub concurrency_limit: usize,
     pub max_retry: usize,
     pub qps_limit: u32,
+    /// Upper bounds (inclusive, μs) of the reported latency buckets, a last
+    /// bucket collects everything above the highest one.
+    #[serde(default = "default_latency_buckets_us")]
+    pub latency_buckets_us: Vec<u64>,
+    /// How long the final validation waits for the consumers to catch up
+    /// with the saved ballots.
+    #[serde(default = "default_settle_timeout_secs")]
+    pub settle_timeout_secs: u64,
+    #[serde(default = "default_settle_poll_interval_ms")]
+    pub settle_poll_interval_ms: u64,
+    /// Mix of requests sent by the load test, each picked in proportion to
+    /// its weight.
+    #[serde(default = "default_load_test_scenarios")]
+    pub scenarios: Vec<LoadTestScenarioWeight>,
+    /// Also require the ballot count and the total score to move by exactly
+    /// the saved ballots, and every operator the load test did not vote on to
+    /// stay unchanged. The last rule was not checked before strict mode
+    /// existed. Off by default so other traffic on the topic does not fail
+    /// the validation.
+    #[serde(default)]
+    pub strict_validation: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadTestScenario {
+    CreateSave,
+    CreateSkip,
+    FinalOrder,
+    Matrix,
+}
+
+impl LoadTestScenario {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LoadTestScenario::CreateSave => "create_save",
+            LoadTestScenario::CreateSkip => "create_skip",
+            LoadTestScenario::FinalOrder => "final_order",
+            LoadTestScenario::Matrix => "matrix",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct LoadTestScenarioWeight {
+    pub scenario: LoadTestScenario,
+    pub weight: u32,
+}
+
+fn default_load_test_scenarios() -> Vec<LoadTestScenarioWeight> {
+    vec![LoadTestScenarioWeight {
+        scenario: LoadTestScenario::CreateSave,
+        weight: 1,
+    }]
+}
+
+impl TestConfig {
+    pub fn settle_timeout(&self) -> Duration {
+        Duration::from_secs(self.settle_timeout_secs)
+    }
+
+    pub fn settle_poll_interval(&self) -> Duration {
+        Duration::from_millis(self.settle_poll_interval_ms)
+    }
+}
+
+fn default_settle_timeout_secs() -> u64 {
+    30
+}
+
+fn default_settle_poll_interval_ms() -> u64 {
+    500
+}
+
+fn default_test_topic_id() -> TopicId {
+    TopicId::parse("crisis_v2_season_4_1_benchtest").expect("valid default topic id")
+}
+
+fn default_latency_buckets_us() -> Vec<u64> {
+    vec![1_000, 5_000, 10_000]
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct TaskManagerConfig {
     pub concurrency: usize,
+    /// Queued and running background tasks together, further spawns are
+    /// dropped so stalled tasks cannot pile up without bound. 0 disables
+    /// the cap.
+    #[serde(default = "default_max_in_flight_tasks")]
+    pub max_in_flight: usize,
+}
+
+fn default_max_in_flight_tasks() -> usize {
+    100_000
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TopicCacheConfig {
+    /// Minimum interval between two full topic cache refreshes triggered by
+    /// failed incremental updates.
+    pub min_full_refresh_interval_secs: u64,
+    /// Overlap subtracted from the incremental watermark to tolerate clock skew
+    /// between the writers of `updated_at` and this service.
+    pub incremental_overlap_ms: u64,
+    /// Maximum number of topics labelled in the candidate pool size gauge.
+    #[serde(default = "default_pool_size_metric_topics")]
+    pub pool_size_metric_topics: usize,
+    /// Generate the candidate pools of all active topics while warming the
+    /// cache instead of on first access. Costs startup time.
+    #[serde(default)]
+    pub eager_pool_generation: bool,
+}
+
+impl Default for TopicCacheConfig {
+    fn default() -> Self {
+        Self {
+            min_full_refresh_interval_secs: 30,
+            incremental_overlap_ms: 1_000,
+            pool_size_metric_topics: default_pool_size_metric_topics(),
+            eager_pool_generation: false,
+        }
+    }
+}
+
+fn default_pool_size_metric_topics() -> usize {
+    64
+}
+
+impl TopicCacheConfig {
+    pub fn min_full_refresh_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.min_full_refresh_interval_secs)
+    }
+
+    pub fn incremental_overlap(&self) -> chrono::Duration {
+        chrono::Duration::milliseconds(self.incremental_overlap_ms as i64)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AdminConfig {
+    /// Bearer tokens accepted on `/admin` routes, overridable with the
+    /// `ARK_VOTE_ADMIN_TOKENS` environment variable.
+    #[serde(default)]
+    pub tokens: Vec<String>,
+}
+
+/// Endpoints notified with the final order when a topic closes.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebhookConfig {
+    #[serde(default)]
+    pub urls: Vec<String>,
+    /// HMAC secret the payloads are signed with, overridable with the
+    /// `ARK_VOTE_WEBHOOK_SECRET` environment variable.
+    #[serde(default)]
+    pub secret: String,
+    #[serde(default = "default_webhook_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            urls: Vec::new(),
+            secret: String::new(),
+            max_retries: default_webhook_max_retries(),
+            timeout_secs: default_webhook_timeout_secs(),
+        }
+    }
+}
+
+impl WebhookConfig {
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.timeout_secs)
+    }
+}
+
+fn default_webhook_max_retries() -> u32 {
+    5
+}
+
+fn default_webhook_timeout_secs() -> u64 {
+    10
+}
+
+/// Loading of the operator portraits at startup and the portrait CDN check.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PortraitConfig {
+    /// Portrait images probed for their dimensions in parallel.
+    #[serde(default = "default_portrait_concurrency")]
+    pub concurrency: usize,
+    /// Timeout and retries of the portrait listing and of each image probe.
+    #[serde(default = "default_portrait_retry")]
+    pub retry: ConnectRetryConfig,
+    /// Seconds between checks that the portrait CDN is reachable, 0 turns the
+    /// check off.
+    #[serde(default = "default_portrait_health_check_interval_secs")]
+    pub health_check_interval_secs: u64,
+    /// Served in place of the portrait CDN while it is unreachable. Without
+    /// one the avatar urls are left empty and clients show their bundled
+    /// images.
+    #[serde(default)]
+    pub backup_base_url: Option<String>,
+}
+
+impl Default for PortraitConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: default_portrait_concurrency(),
+            retry: default_portrait_retry(),
+            health_check_interval_secs: default_portrait_health_check_interval_secs(),
+            backup_base_url: None,
+        }
+    }
+}
+
+impl PortraitConfig {
+    pub fn health_check_interval(&self) -> Option<Duration> {
+        (self.health_check_interval_secs > 0)
+            .then(|| Duration::from_secs(self.health_check_interval_secs))
+    }
+}
+
+/// Bounds each step of a graceful shutdown, see `share::signal::bounded`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ShutdownConfig {
+    /// Time a step, e.g. the NATS drain, gets before shutdown moves on.
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_shutdown_timeout_secs(),
+        }
+    }
+}
+
+impl ShutdownConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    10
+}
+
+/// Operator data kept outside the character table.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct OperatorsConfig {
+    /// Alternate names players search by, alias -> operator id. Matched
+    /// case-insensitively, see `OperatorAliases`.
+    #[serde(default)]
+    pub aliases: HashMap<String, i32>,
+}
+
+fn default_portrait_concurrency() -> usize {
+    8
+}
+
+fn default_portrait_health_check_interval_secs() -> u64 {
+    60
+}
+
+fn default_portrait_retry() -> ConnectRetryConfig {
+    ConnectRetryConfig {
+        timeout_ms: 5_000,
+        max_retries: 2,
+        base_delay_ms: 500,
+        max_delay_ms: 5_000,
+    }
 }
 
 impl TomlConfig for AppConfig {
@@ -140,3 +923,134 @@ pub trait TomlConfig: DeserializeOwned {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::test_util::test_topic;
+
+    fn preset(id: &str, close_in_hours: i64) -> VotingTopic {
+        let now = Utc::now();
+        test_topic(id, now, now + Duration::hours(close_in_hours))
+    }
+
+    #[test]
+    fn test_topic_vote_overrides() {
+        let config: VoteConfig = serde_json::from_value(serde_json::json!({
+            "base_multiplier": 100,
+            "low_multiplier": 1,
+            "max_ip_limit": 1000,
+            "ip_counter_expire_seconds": 86400,
+            "preset_vote_topic": [],
+        }))
+        .unwrap();
+
+        let global = TopicVoteOverrides::default().resolve(&config);
+        assert_eq!(
+            global,
+            IpLimits {
+                max_ip_limit: 1000,
+                base_multiplier: 100,
+                low_multiplier: 1,
+            }
+        );
+
+        let mut topic = preset("curated", 1);
+        topic.vote_overrides = serde_json::from_str(r#"{"max_ip_limit": 50}"#).unwrap();
+        assert_eq!(
+            topic.vote_overrides.resolve(&config),
+            IpLimits {
+                max_ip_limit: 50,
+                ..global
+            }
+        );
+    }
+
+    #[test]
+    fn test_ballot_code_length() {
+        let mut config: VoteConfig = serde_json::from_value(serde_json::json!({
+            "base_multiplier": 100,
+            "low_multiplier": 1,
+            "max_ip_limit": 1000,
+            "ip_counter_expire_seconds": 86400,
+            "preset_vote_topic": [],
+        }))
+        .unwrap();
+        assert_eq!(config.ballot_code_length, DEFAULT_BALLOT_CODE_LENGTH);
+        assert!(config.check_ballot_code_length().is_ok());
+
+        config.ballot_code_length = 4;
+        assert!(config.check_ballot_code_length().is_err());
+        config.ballot_code_length = 65;
+        assert!(config.check_ballot_code_length().is_err());
+        config.ballot_code_length = 64;
+        assert!(config.check_ballot_code_length().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_preset_topic_rejected() {
+        let topics = vec![preset("valid", 1), preset("reversed", -1)];
+
+        let err = validate_preset_topics(&topics, false, MAX_PRESET_DEPTH).unwrap_err();
+        assert_eq!(err.id, "reversed");
+        assert_eq!(err.source, TopicValidationError::InvalidWindow);
+
+        let valid = validate_preset_topics(&topics, true, MAX_PRESET_DEPTH).unwrap();
+        assert_eq!(valid.len(), 1);
+        assert_eq!(valid[0].id, "valid");
+    }
+
+    #[test]
+    fn test_prepare_preset_topic_freezes_pool() {
+        let mut config: VoteConfig = serde_json::from_value(serde_json::json!({
+            "base_multiplier": 100,
+            "low_multiplier": 1,
+            "max_ip_limit": 1000,
+            "ip_counter_expire_seconds": 86400,
+            "preset_vote_topic": [],
+        }))
+        .unwrap();
+        let topic = preset("curated", 1);
+
+        assert_eq!(
+            config.prepare_preset_topic(&topic, None, &[]).frozen_pool,
+            None
+        );
+
+        config.freeze_candidate_pools = true;
+        assert_eq!(
+            config.prepare_preset_topic(&topic, None, &[]).frozen_pool,
+            Some(vec![])
+        );
+
+        let mut stored = topic.clone();
+        stored.frozen_pool = Some(vec![1, 2]);
+        config.freeze_candidate_pools = false;
+        assert_eq!(
+            config
+                .prepare_preset_topic(&topic, Some(&stored), &[])
+                .frozen_pool,
+            Some(vec![1, 2])
+        );
+    }
+
+    #[test]
+    fn test_ballot_grace_rejects_fresh_id() {
+        let snowflake = Snowflake::new(1, 1, 1_609_459_200_000, 10);
+        let ballot_id = format!("{}-abcdefgh", snowflake.next_id().unwrap());
+        let created_at = Utc::now().timestamp_millis() as u64;
+        let grace = BallotGraceConfig {
+            min_age_ms: 500,
+            mode: SaveThrottleMode::Reject,
+        };
+
+        assert!(grace.is_too_soon(&snowflake, &ballot_id, created_at));
+        assert!(!grace.is_too_soon(&snowflake, &ballot_id, created_at + 1_000));
+        assert!(!grace.is_too_soon(&snowflake, "not-a-snowflake", created_at));
+
+        let disabled = BallotGraceConfig::default();
+        assert!(!disabled.is_too_soon(&snowflake, &ballot_id, created_at));
+    }
+}
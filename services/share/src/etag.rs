@@ -0,0 +1,61 @@
+use std::hash::{DefaultHasher, Hash as _, Hasher as _};
+
+/// A serialized response body together with its entity tag.
+#[derive(Debug, Clone)]
+pub struct EtaggedBody {
+    pub etag: String,
+    pub body: Vec<u8>,
+}
+
+impl EtaggedBody {
+    pub fn new(body: Vec<u8>) -> Self {
+        Self {
+            etag: compute_etag(&body),
+            body,
+        }
+    }
+}
+
+/// Strong entity tag derived from the body, stable for the lifetime of the
+/// process.
+pub fn compute_etag(body: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Evaluates an `If-None-Match` header against `etag` using the weak
+/// comparison required by RFC 9110.
+pub fn if_none_match(header: Option<&str>, etag: &str) -> bool {
+    let Some(header) = header else {
+        return false;
+    };
+    let etag = etag.trim_start_matches("W/");
+
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_etag_depends_on_body() {
+        assert_eq!(compute_etag(b"[1,2]"), compute_etag(b"[1,2]"));
+        assert_ne!(compute_etag(b"[1,2]"), compute_etag(b"[1,3]"));
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let etag = compute_etag(b"pool");
+
+        assert!(if_none_match(Some(&etag), &etag));
+        assert!(if_none_match(Some(&format!("\"other\", W/{etag}")), &etag));
+        assert!(if_none_match(Some("*"), &etag));
+        assert!(!if_none_match(Some("\"other\""), &etag));
+        assert!(!if_none_match(None, &etag));
+    }
+}
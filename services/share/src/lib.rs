@@ -1,5 +1,21 @@
+pub mod auth;
+pub mod bootstrap;
 pub mod config;
+pub mod etag;
+pub mod metrics;
 pub mod models;
+pub mod portrait;
+pub mod ranking;
+pub mod recently_shown;
+pub mod reputation;
+pub mod retry;
+pub mod scores;
+pub mod search;
+pub mod selection;
 pub mod signal;
 pub mod snowflake;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod throttle;
 pub mod tracing;
+pub mod webhook;
@@ -0,0 +1,168 @@
+use std::collections::{HashMap, HashSet};
+
+use parking_lot::Mutex;
+
+use crate::models::database::StoredBallot;
+
+/// Bounds the number of distinct values a metric label can take. Values seen
+/// first are admitted until `max` is reached, later ones are rejected.
+#[derive(Debug)]
+pub struct LabelCap {
+    max: usize,
+    admitted: Mutex<HashSet<String>>,
+}
+
+impl LabelCap {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            admitted: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn admit(&self, value: &str) -> bool {
+        let mut admitted = self.admitted.lock();
+        if admitted.contains(value) {
+            return true;
+        }
+        if admitted.len() >= self.max {
+            return false;
+        }
+
+        admitted.insert(value.to_string());
+        true
+    }
+}
+
+/// In-process caches whose lookups are counted in `cache_lookups_total`.
+/// Deliberately not labelled by topic to keep cardinality fixed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    Topic,
+    Pool,
+    Results,
+}
+
+impl CacheKind {
+    pub const LABEL: &'static str = "cache";
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Topic => "topic",
+            Self::Pool => "pool",
+            Self::Results => "results",
+        }
+    }
+}
+
+pub const CACHE_OUTCOME_LABEL: &str = "outcome";
+
+pub fn cache_outcome(hit: bool) -> &'static str {
+    if hit { "hit" } else { "miss" }
+}
+
+/// Ballots of a processed batch by topic, what `votes_saved_total` counts.
+/// Taken before sampling, every scored ballot counts as saved whether it is
+/// stored in MongoDB or not.
+pub fn saved_per_topic(
+    grouped_ballots: &HashMap<String, Vec<StoredBallot<'_>>>,
+) -> HashMap<String, u64> {
+    grouped_ballots
+        .iter()
+        .map(|(topic_id, ballots)| (topic_id.clone(), ballots.len() as u64))
+        .collect()
+}
+
+/// Hash counting the ballot codes of a topic: `created` when a code is handed
+/// out, `resolved` when a save or skip consumes it. Codes that are never
+/// resolved expire with their key.
+pub fn ballot_codes_key(topic_id: &str) -> String {
+    format!("{topic_id}:ballot_codes")
+}
+
+pub const BALLOT_CODES_CREATED_FIELD: &str = "created";
+pub const BALLOT_CODES_RESOLVED_FIELD: &str = "resolved";
+
+/// Adds one to a `ballot_codes_key` counter. The counters only feed metrics,
+/// so a failure is logged instead of failing the vote whose code was already
+/// stored or consumed.
+pub async fn count_ballot_code(
+    connection: &mut redis::aio::MultiplexedConnection,
+    topic_id: &str,
+    field: &str,
+) {
+    let result: redis::RedisResult<()> = redis::cmd("HINCRBY")
+        .arg(ballot_codes_key(topic_id))
+        .arg(field)
+        .arg(1)
+        .query_async(connection)
+        .await;
+    if let Err(e) = result {
+        tracing::warn!(
+            "failed to count {} ballot code of topic {}: {}",
+            field,
+            topic_id,
+            e
+        );
+    }
+}
+
+/// Counters read back from `ballot_codes_key`.
+///
+/// Counting instead of listening for expired-key events keeps the cost at one
+/// `HINCRBY` per code and needs no keyspace notifications on the Redis server.
+/// The price is that codes still within their TTL count as unresolved, so the
+/// abandon ratio overstates by at most one TTL worth of traffic.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BallotCodeCounts {
+    pub created: u64,
+    pub resolved: u64,
+}
+
+impl BallotCodeCounts {
+    pub fn unresolved(&self) -> u64 {
+        self.created.saturating_sub(self.resolved)
+    }
+
+    /// Share of created codes never saved or skipped, 0 before any code exists.
+    pub fn abandon_ratio(&self) -> f64 {
+        match self.created {
+            0 => 0.0,
+            created => self.unresolved() as f64 / created as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_cap() {
+        let cap = LabelCap::new(2);
+
+        assert!(cap.admit("a"));
+        assert!(cap.admit("b"));
+        assert!(!cap.admit("c"));
+        assert!(cap.admit("a"));
+    }
+
+    #[test]
+    fn test_ballot_code_counts() {
+        assert_eq!(BallotCodeCounts::default().abandon_ratio(), 0.0);
+
+        let counts = BallotCodeCounts {
+            created: 8,
+            resolved: 6,
+        };
+        assert_eq!(counts.unresolved(), 2);
+        assert_eq!(counts.abandon_ratio(), 0.25);
+
+        // a resolve counted before its create must not underflow
+        let counts = BallotCodeCounts {
+            created: 1,
+            resolved: 2,
+        };
+        assert_eq!(counts.unresolved(), 0);
+    }
+}
@@ -1,12 +1,28 @@
-use std::{collections::HashMap, fmt};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::models::{
-    candidate_pool_preset::CandidatePoolPreset,
-    database::{TopicAuditInfo, VotingTopic},
+use crate::{
+    config::BallotSizeLimits,
+    models::{
+        candidate_pool_preset::{CandidatePoolError, CandidatePoolPreset, PoolPresetError},
+        database::{
+            Ballot, BallotInfo, GroupwiseBallot, InsufficientOperators, PairwiseBallot,
+            PluralityBallot, SetwiseBallot, StoredBallot, TopicAuditInfo, TopicMetadataUpdate,
+            TopicReopenError, TopicUpdateError, TopicValidationError, TopicWindowState,
+            VotingTopic,
+        },
+        excel::{CharacterInfo, ProfessionCategory, RarityRank, first_invalid_operator_id},
+        format::{MAX_RESULT_PRECISION, ResultFormat},
+        topic_id::TopicId,
+    },
+    ranking::{MAX_CONDORCET_OPERATORS, MajorityRecord, RankingMethod, pair_records, smith_set},
 };
 
 use super::database::{CreateTopicStatus, VotingTopicType};
@@ -15,21 +31,48 @@ use super::database::{CreateTopicStatus, VotingTopicType};
 pub enum ApiMsg {
     OK,
     TopicCreateFailed,
+    TopicIdTaken,
     TargetTopicNotFound,
     TargetTopicNotActive,
+    TopicNotYetOpen,
+    TopicClosed,
+    TopicPaused,
     TargetTopicCandidatePoolNotFound,
+    EmptyCandidatePool,
     RequestTopicTypeMismatch,
     CurTopicNotSupportFinalOrder,
     CurTopicNotSupport1v1Matrix,
+    CondorcetPoolTooLarge(usize),
+    InvalidTierBreakpoints,
+    InvalidResultFormat,
     InternalError,
+    InvalidJson,
+    PayloadTooLarge,
+    UpstreamUnavailable,
+    InsufficientOperators,
+    CandidatePoolTooSmall(String),
     BallotWinnerCannotBeLoser,
+    InvalidOperatorId(i32),
 
     UnsupportedTopicType,
 
     BenchBallotNotFound,
     BallotNotFound,
     InvalidBallotCode(String),
+    BallotSetTooLarge(usize),
+    SkipBatchTooLarge(usize),
     EndpointForbidden,
+    Unauthorized,
+    InvalidUserToken,
+    TopicNotApproved,
+    TopicCloseTimeInPast,
+    InvalidTopicWindow,
+    InvalidTopic(String),
+    TopicCandidatePoolLocked,
+    ResetConfirmationMismatch,
+    InvalidVelocityWindow(usize),
+    RateLimited,
+    BallotSavedTooSoon,
     Error(String),
 }
 
@@ -38,11 +81,18 @@ impl fmt::Display for ApiMsg {
         match self {
             ApiMsg::OK => write!(f, "OK"),
             ApiMsg::TopicCreateFailed => write!(f, "Failed to create topic"),
+            ApiMsg::TopicIdTaken => write!(f, "A different topic with this id already exists"),
             ApiMsg::TargetTopicNotFound => write!(f, "Target topic not found"),
             ApiMsg::TargetTopicNotActive => write!(f, "Target topic is not active"),
+            ApiMsg::TopicNotYetOpen => write!(f, "Target topic is not open yet"),
+            ApiMsg::TopicClosed => write!(f, "Target topic is closed"),
+            ApiMsg::TopicPaused => write!(f, "Voting on the target topic is paused"),
             ApiMsg::TargetTopicCandidatePoolNotFound => {
                 write!(f, "Target topic candidate pool not found")
             }
+            ApiMsg::EmptyCandidatePool => {
+                write!(f, "Target topic candidate pool matches no operators")
+            }
             ApiMsg::RequestTopicTypeMismatch => {
                 write!(f, "Request topic type does not match topic type")
             }
@@ -52,20 +102,118 @@ impl fmt::Display for ApiMsg {
             ApiMsg::CurTopicNotSupport1v1Matrix => {
                 write!(f, "Current topic type does not support 1v1 matrix")
             }
+            ApiMsg::CondorcetPoolTooLarge(limit) => {
+                write!(f, "Condorcet results are limited to {} operators", limit)
+            }
+            ApiMsg::InvalidTierBreakpoints => {
+                write!(f, "Tier breakpoints must be sorted in ascending order")
+            }
+            ApiMsg::InvalidResultFormat => write!(
+                f,
+                "Result precision is limited to {} digits and the decimal separator must not be a digit, '-' or '%'",
+                MAX_RESULT_PRECISION
+            ),
             ApiMsg::InternalError => write!(f, "Internal server error"),
+            ApiMsg::InvalidJson => write!(f, "Invalid JSON format"),
+            ApiMsg::PayloadTooLarge => write!(f, "Request body is too large"),
+            ApiMsg::UpstreamUnavailable => write!(f, "External service unavailable"),
+            ApiMsg::InsufficientOperators => {
+                write!(f, "Insufficient operators available for comparison")
+            }
+            ApiMsg::CandidatePoolTooSmall(msg) => write!(f, "{}", msg),
             ApiMsg::BallotWinnerCannotBeLoser => write!(f, "Ballot winner cannot be loser"),
+            ApiMsg::InvalidOperatorId(id) => write!(f, "Invalid operator id {}", id),
 
             ApiMsg::UnsupportedTopicType => write!(f, "Unsupported topic type"),
 
             ApiMsg::BenchBallotNotFound => write!(f, "Bench ballot not found"),
             ApiMsg::BallotNotFound => write!(f, "Ballot not found"),
             ApiMsg::InvalidBallotCode(msg) => write!(f, "{}", msg),
+            ApiMsg::BallotSetTooLarge(limit) => {
+                write!(f, "Ballot sets are limited to {} operators", limit)
+            }
+            ApiMsg::SkipBatchTooLarge(limit) => {
+                write!(f, "Skip batches are limited to {} ballots", limit)
+            }
             ApiMsg::EndpointForbidden => write!(f, "Endpoint forbidden"),
+            ApiMsg::Unauthorized => write!(f, "Unauthorized"),
+            ApiMsg::InvalidUserToken => write!(f, "Invalid user token"),
+            ApiMsg::TopicNotApproved => write!(f, "Target topic is not approved"),
+            ApiMsg::TopicCloseTimeInPast => write!(f, "Topic close time is in the past"),
+            ApiMsg::InvalidTopicWindow => write!(f, "Topic open time must be before close time"),
+            ApiMsg::InvalidTopic(msg) => write!(f, "{}", msg),
+            ApiMsg::TopicCandidatePoolLocked => {
+                write!(f, "Candidate pool cannot change once the topic has votes")
+            }
+            ApiMsg::ResetConfirmationMismatch => {
+                write!(f, "Confirmation does not match the topic id")
+            }
+            ApiMsg::InvalidVelocityWindow(limit) => write!(
+                f,
+                "Velocity window must end after it starts and span at most {} buckets",
+                limit
+            ),
+            ApiMsg::RateLimited => write!(f, "Too many requests, slow down"),
+            ApiMsg::BallotSavedTooSoon => write!(f, "Ballot saved too soon after it was created"),
             ApiMsg::Error(msg) => write!(f, "{}", msg),
         }
     }
 }
 
+impl From<TopicWindowState> for ApiMsg {
+    fn from(state: TopicWindowState) -> Self {
+        match state {
+            TopicWindowState::NotYetOpen => ApiMsg::TopicNotYetOpen,
+            TopicWindowState::Closed => ApiMsg::TopicClosed,
+            TopicWindowState::Inactive | TopicWindowState::Open => ApiMsg::TargetTopicNotActive,
+        }
+    }
+}
+
+impl From<TopicValidationError> for ApiMsg {
+    fn from(err: TopicValidationError) -> Self {
+        match err {
+            TopicValidationError::InvalidOperatorId(id) => ApiMsg::InvalidOperatorId(id),
+            TopicValidationError::InvalidWindow => ApiMsg::InvalidTopicWindow,
+            err => ApiMsg::InvalidTopic(err.to_string()),
+        }
+    }
+}
+
+impl From<CandidatePoolError> for ApiMsg {
+    fn from(err: CandidatePoolError) -> Self {
+        match err {
+            CandidatePoolError::TopicNotFound => ApiMsg::TargetTopicNotFound,
+            CandidatePoolError::Empty => ApiMsg::EmptyCandidatePool,
+        }
+    }
+}
+
+impl From<InsufficientOperators> for ApiMsg {
+    fn from(err: InsufficientOperators) -> Self {
+        ApiMsg::CandidatePoolTooSmall(err.to_string())
+    }
+}
+
+impl From<TopicReopenError> for ApiMsg {
+    fn from(err: TopicReopenError) -> Self {
+        match err {
+            TopicReopenError::NotApproved => ApiMsg::TopicNotApproved,
+            TopicReopenError::CloseTimeInPast => ApiMsg::TopicCloseTimeInPast,
+            TopicReopenError::InvalidWindow => ApiMsg::InvalidTopicWindow,
+        }
+    }
+}
+
+impl From<TopicUpdateError> for ApiMsg {
+    fn from(err: TopicUpdateError) -> Self {
+        match err {
+            TopicUpdateError::Invalid(err) => err.into(),
+            TopicUpdateError::CandidatePoolLocked => ApiMsg::TopicCandidatePoolLocked,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
 #[serde(untagged)]
 pub enum ApiData<T> {
@@ -73,9 +221,109 @@ pub enum ApiData<T> {
     Empty,
 }
 
+/// Outcome of a request as carried in `ApiResponse.status`. Serialized as
+/// its numeric code, which is what clients have always received. Handlers
+/// return `ApiResponse` itself, so the HTTP status is `http_code()`.
+///
+/// Every success is 0. `/ballot/skip`, `/ballot/skip_batch` and the portable
+/// `/results/timeseries` used to send 200 instead, clients of those must
+/// accept 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(into = "i32", try_from = "i32")]
+pub enum ResponseStatus {
+    Ok,
+    /// The request is valid but the target (topic type, ballot kind) does not
+    /// support it.
+    Unsupported,
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    PayloadTooLarge,
+    TooManyRequests,
+    InternalError,
+    BadGateway,
+}
+
+impl ResponseStatus {
+    pub const fn code(self) -> i32 {
+        match self {
+            ResponseStatus::Ok => 0,
+            ResponseStatus::Unsupported => 1,
+            ResponseStatus::BadRequest => 400,
+            ResponseStatus::Unauthorized => 401,
+            ResponseStatus::Forbidden => 403,
+            ResponseStatus::NotFound => 404,
+            ResponseStatus::Conflict => 409,
+            ResponseStatus::PayloadTooLarge => 413,
+            ResponseStatus::TooManyRequests => 429,
+            ResponseStatus::InternalError => 500,
+            ResponseStatus::BadGateway => 502,
+        }
+    }
+
+    /// HTTP status code a response with this status is sent with.
+    pub const fn http_code(self) -> u16 {
+        match self {
+            ResponseStatus::Ok => 200,
+            ResponseStatus::Unsupported | ResponseStatus::BadRequest => 400,
+            ResponseStatus::Unauthorized => 401,
+            ResponseStatus::Forbidden => 403,
+            ResponseStatus::NotFound => 404,
+            ResponseStatus::Conflict => 409,
+            ResponseStatus::PayloadTooLarge => 413,
+            ResponseStatus::TooManyRequests => 429,
+            ResponseStatus::InternalError => 500,
+            ResponseStatus::BadGateway => 502,
+        }
+    }
+
+    pub fn http_status(self) -> axum::http::StatusCode {
+        axum::http::StatusCode::from_u16(self.http_code())
+            .unwrap_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+impl fmt::Display for ResponseStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl From<ResponseStatus> for i32 {
+    fn from(status: ResponseStatus) -> Self {
+        status.code()
+    }
+}
+
+impl TryFrom<i32> for ResponseStatus {
+    type Error = String;
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            // 200 was sent by a few success responses before the codes were
+            // unified, read it as `Ok`.
+            0 | 200 => Ok(ResponseStatus::Ok),
+            1 => Ok(ResponseStatus::Unsupported),
+            400 => Ok(ResponseStatus::BadRequest),
+            401 => Ok(ResponseStatus::Unauthorized),
+            403 => Ok(ResponseStatus::Forbidden),
+            404 => Ok(ResponseStatus::NotFound),
+            409 => Ok(ResponseStatus::Conflict),
+            413 => Ok(ResponseStatus::PayloadTooLarge),
+            429 => Ok(ResponseStatus::TooManyRequests),
+            500 => Ok(ResponseStatus::InternalError),
+            502 => Ok(ResponseStatus::BadGateway),
+            _ => Err(format!("unknown response status {}", code)),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct ApiResponse<T> {
-    pub status: i32,
+    #[schema(value_type = i32)]
+    pub status: ResponseStatus,
     pub data: ApiData<T>,
     pub message: ApiMsg,
 }
@@ -85,27 +333,160 @@ impl Default for ApiResponse<()> {
         Self {
             data: ApiData::Empty,
             message: ApiMsg::OK,
-            status: 0,
+            status: ResponseStatus::Ok,
         }
     }
 }
 
-impl axum::response::IntoResponse for ApiResponse<()> {
-    fn into_response(self) -> axum::response::Response {
-        let status = match self.status {
-            0 => axum::http::StatusCode::OK,
-            404 => axum::http::StatusCode::NOT_FOUND,
-            500 => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            _ if self.status < 500 => axum::http::StatusCode::BAD_REQUEST,
-            _ => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+impl<T> ApiResponse<T> {
+    /// Answer to a ballot create or save on `topic` when it does not accept
+    /// ballots, see `VotingTopic::accepts_ballots`.
+    pub fn ballots_refused(topic: &VotingTopic) -> Option<Self> {
+        if topic.accepts_ballots() {
+            return None;
+        }
+
+        let (status, message) = match topic.window_state() {
+            TopicWindowState::Open => (ResponseStatus::Forbidden, ApiMsg::TopicPaused),
+            window_state => (ResponseStatus::Forbidden, window_state.into()),
+        };
+        Some(Self {
+            status,
+            data: ApiData::Empty,
+            message,
+        })
+    }
+
+    /// Answer to a topic create or ballot on a `topic_type` left out of
+    /// `vote.enabled_topic_types`. Every endpoint reports it as
+    /// `Unsupported`.
+    pub fn topic_type_disabled(
+        enabled: &[VotingTopicType],
+        topic_type: &VotingTopicType,
+    ) -> Option<Self> {
+        if enabled.contains(topic_type) {
+            return None;
+        }
+
+        Some(Self {
+            status: ResponseStatus::Unsupported,
+            data: ApiData::Empty,
+            message: ApiMsg::UnsupportedTopicType,
+        })
+    }
+
+    /// Answer to a save of a topic type `/ballot/new` cannot issue codes for
+    /// yet. Only pairwise ballots can be saved, the others are refused like
+    /// their creates.
+    pub fn ballot_save_unsupported(req: &BallotSaveRequest) -> Option<Self> {
+        match req {
+            BallotSaveRequest::Pairwise(_) => None,
+            _ => Some(Self {
+                status: ResponseStatus::Unsupported,
+                data: ApiData::Empty,
+                message: ApiMsg::UnsupportedTopicType,
+            }),
+        }
+    }
+}
+
+impl<T> ApiResponse<T> {
+    /// Adds when the data was computed next to `data`, nothing for an empty
+    /// response.
+    pub fn timed(self, computed_at: impl FnOnce(&T) -> DateTime<Utc>) -> TimedApiResponse<T> {
+        let computed_at = match &self.data {
+            ApiData::Data(data) => Some(computed_at(data)),
+            ApiData::Empty => None,
         };
-        (status, axum::Json(self)).into_response()
+        TimedApiResponse {
+            response: self,
+            computed_at,
+        }
+    }
+}
+
+/// `ApiResponse` with `computed_at` next to `data`, for results whose `data`
+/// has no room for it.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct TimedApiResponse<T> {
+    #[serde(flatten)]
+    pub response: ApiResponse<T>,
+    /// When the data was computed, cached responses keep the time they were
+    /// first computed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub computed_at: Option<DateTime<Utc>>,
+}
+
+impl<T: Serialize> axum::response::IntoResponse for TimedApiResponse<T> {
+    fn into_response(self) -> axum::response::Response {
+        (self.response.status.http_status(), axum::Json(self)).into_response()
+    }
+}
+
+#[cfg(feature = "actix")]
+impl<T: Serialize> actix_web::Responder for TimedApiResponse<T> {
+    type Body = actix_web::body::BoxBody;
+
+    fn respond_to(self, _req: &actix_web::HttpRequest) -> actix_web::HttpResponse<Self::Body> {
+        let status = actix_web::http::StatusCode::from_u16(self.response.status.http_code())
+            .unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR);
+        actix_web::HttpResponse::build(status).json(self)
+    }
+}
+
+impl<T: Serialize> axum::response::IntoResponse for ApiResponse<T> {
+    fn into_response(self) -> axum::response::Response {
+        (self.status.http_status(), axum::Json(self)).into_response()
+    }
+}
+
+#[cfg(feature = "actix")]
+impl<T: Serialize> actix_web::Responder for ApiResponse<T> {
+    type Body = actix_web::body::BoxBody;
+
+    fn respond_to(self, _req: &actix_web::HttpRequest) -> actix_web::HttpResponse<Self::Body> {
+        let status = actix_web::http::StatusCode::from_u16(self.status.http_code())
+            .unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR);
+        actix_web::HttpResponse::build(status).json(self)
     }
 }
 
-#[derive(Default, Debug, Deserialize, Serialize, ToSchema)]
+/// Largest number of recent winners considered by `/ballot/new`, older
+/// entries are ignored.
+pub const MAX_RECENT_WINNERS: usize = 20;
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct BallotCreateRequest {
-    pub topic_id: String,
+    pub topic_id: TopicId,
+    /// Operators the client recently picked as winners, most recent last.
+    /// Only used by the `AvoidRecentWinners` pairing constraint.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recent_winners: Vec<i32>,
+    /// Embed the portraits of the shown operators in the response.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_portraits: bool,
+}
+
+impl BallotCreateRequest {
+    pub fn new(topic_id: TopicId) -> Self {
+        Self {
+            topic_id,
+            recent_winners: Vec::new(),
+            include_portraits: false,
+        }
+    }
+
+    /// The first non-positive id in `recent_winners`, which are sent back by
+    /// the client.
+    pub fn invalid_operator_id(&self) -> Option<i32> {
+        first_invalid_operator_id(&self.recent_winners)
+    }
+
+    /// The last `MAX_RECENT_WINNERS` entries of `recent_winners`.
+    pub fn recent_winners(&self) -> &[i32] {
+        let start = self.recent_winners.len().saturating_sub(MAX_RECENT_WINNERS);
+        &self.recent_winners[start..]
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
@@ -116,29 +497,87 @@ pub enum BallotCreateResponse {
         ballot_id: String,
         left: i32,
         right: i32,
+        /// Portraits of the shown operators, only set when requested with
+        /// `include_portraits`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        portraits: Option<Vec<CharacterPortrait>>,
     },
     Setwise {
         topic_id: String,
         ballot_id: String,
         left_set: Vec<i32>,
         right_set: Vec<i32>,
+        /// Portraits of the shown operators, only set when requested with
+        /// `include_portraits`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        portraits: Option<Vec<CharacterPortrait>>,
     },
     Groupwise {
         topic_id: String,
         ballot_id: String,
         left_group: Vec<i32>,
         right_group: Vec<i32>,
+        /// Portraits of the shown operators, only set when requested with
+        /// `include_portraits`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        portraits: Option<Vec<CharacterPortrait>>,
     },
     Plurality {
         topic_id: String,
         ballot_id: String,
         candidates: Vec<i32>,
+        /// Portraits of the shown operators, only set when requested with
+        /// `include_portraits`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        portraits: Option<Vec<CharacterPortrait>>,
     },
 }
 
-#[derive(Default, Debug, Deserialize, Serialize, ToSchema)]
+impl BallotCreateResponse {
+    /// Operators shown by the ballot, in display order.
+    pub fn operators(&self) -> Vec<i32> {
+        match self {
+            BallotCreateResponse::Pairwise { left, right, .. } => vec![*left, *right],
+            BallotCreateResponse::Setwise {
+                left_set,
+                right_set,
+                ..
+            } => [&left_set[..], &right_set[..]].concat(),
+            BallotCreateResponse::Groupwise {
+                left_group,
+                right_group,
+                ..
+            } => [&left_group[..], &right_group[..]].concat(),
+            BallotCreateResponse::Plurality { candidates, .. } => candidates.clone(),
+        }
+    }
+
+    /// Embeds the portraits of the shown operators, operators without a
+    /// portrait are left out and logged.
+    pub fn embed_portraits(&mut self, character_portraits: &HashMap<i32, CharacterPortrait>) {
+        let embedded = self
+            .operators()
+            .iter()
+            .filter_map(|id| {
+                let portrait = character_portraits.get(id).cloned();
+                if portrait.is_none() {
+                    tracing::warn!("no portrait to embed for operator {}", id);
+                }
+                portrait
+            })
+            .collect();
+        match self {
+            BallotCreateResponse::Pairwise { portraits, .. }
+            | BallotCreateResponse::Setwise { portraits, .. }
+            | BallotCreateResponse::Groupwise { portraits, .. }
+            | BallotCreateResponse::Plurality { portraits, .. } => *portraits = Some(embedded),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct BallotSkipRequest {
-    pub topic_id: String,
+    pub topic_id: TopicId,
     pub ballot_id: String,
 }
 
@@ -147,7 +586,120 @@ pub struct BallotSkipResponse {
     pub code: i32,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize, ToSchema)]
+/// Largest number of skips accepted by `/ballot/skip_batch`.
+pub const MAX_SKIP_BATCH_SIZE: usize = 200;
+
+/// What became of one skip of a `/ballot/skip_batch` request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BallotSkipOutcome {
+    Skipped,
+    /// The topic is missing or closed, the skip was dropped.
+    TopicNotActive,
+    /// The skip could not be recorded, it may be retried.
+    Failed,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct BallotSkipBatchResponse {
+    pub code: i32,
+    /// Skips of active topics; skips of missing or closed topics are dropped.
+    pub skipped: usize,
+    /// One outcome per requested skip, in request order.
+    pub results: Vec<BallotSkipOutcome>,
+}
+
+impl BallotSkipBatchResponse {
+    /// Body of `/ballot/skip_batch`, the services only differ in how they load
+    /// a topic and record a skip. Every topic is checked before the first skip
+    /// is recorded, a skip that fails is reported and the rest still go on.
+    pub async fn respond<E, S, TopicFut, SkipFut>(
+        reqs: Vec<BallotSkipRequest>,
+        topic: impl Fn(TopicId) -> TopicFut,
+        skip: impl Fn(BallotSkipRequest) -> SkipFut,
+    ) -> ApiResponse<Self>
+    where
+        TopicFut: Future<Output = Result<Option<VotingTopic>, E>>,
+        SkipFut: Future<Output = Result<(), S>>,
+        S: fmt::Display,
+    {
+        if reqs.len() > MAX_SKIP_BATCH_SIZE {
+            return ApiResponse {
+                status: ResponseStatus::BadRequest,
+                data: ApiData::Empty,
+                message: ApiMsg::SkipBatchTooLarge(MAX_SKIP_BATCH_SIZE),
+            };
+        }
+
+        let mut active_topics = HashMap::new();
+        for req in reqs.iter() {
+            if !active_topics.contains_key(&req.topic_id) {
+                let active = matches!(
+                    topic(req.topic_id.clone()).await,
+                    Ok(Some(topic)) if topic.is_topic_active()
+                );
+                active_topics.insert(req.topic_id.clone(), active);
+            }
+        }
+
+        let mut results = Vec::with_capacity(reqs.len());
+        for req in reqs {
+            if !active_topics[&req.topic_id] {
+                results.push(BallotSkipOutcome::TopicNotActive);
+                continue;
+            }
+            let ballot_id = req.ballot_id.clone();
+            results.push(match skip(req).await {
+                Ok(()) => BallotSkipOutcome::Skipped,
+                Err(e) => {
+                    tracing::error!("Failed to skip ballot {}: {}", ballot_id, e);
+                    BallotSkipOutcome::Failed
+                }
+            });
+        }
+
+        ApiResponse {
+            status: ResponseStatus::Ok,
+            data: ApiData::Data(Self {
+                code: 0,
+                skipped: results
+                    .iter()
+                    .filter(|outcome| **outcome == BallotSkipOutcome::Skipped)
+                    .count(),
+                results,
+            }),
+            message: ApiMsg::OK,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct BallotMyStatsRequest {
+    pub topic_id: TopicId,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct BallotMyStatsResponse {
+    pub topic_id: String,
+    pub vote_count: i64,
+    pub max_ip_limit: i32,
+    pub is_rate_limited: bool,
+}
+
+impl BallotMyStatsResponse {
+    pub fn new(topic_id: String, vote_count: i64, max_ip_limit: i32) -> Self {
+        // a negative limit disables the per-IP weighting
+        let is_rate_limited = max_ip_limit >= 0 && vote_count >= max_ip_limit as i64;
+        Self {
+            topic_id,
+            vote_count,
+            max_ip_limit,
+            is_rate_limited,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
 pub enum GroupwiseSelection {
     Left,
     Right,
@@ -155,7 +707,7 @@ pub enum GroupwiseSelection {
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct PairwiseSaveScore {
-    pub topic_id: String,
+    pub topic_id: TopicId,
     pub ballot_id: String,
     pub winner: i32,
     pub loser: i32,
@@ -163,7 +715,7 @@ pub struct PairwiseSaveScore {
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct SetwiseSaveScore {
-    pub topic_id: String,
+    pub topic_id: TopicId,
     pub ballot_id: String,
     pub left_set: Vec<i32>,
     pub right_set: Vec<i32>,
@@ -173,7 +725,7 @@ pub struct SetwiseSaveScore {
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct GroupwiseSaveScore {
-    pub topic_id: String,
+    pub topic_id: TopicId,
     pub ballot_id: String,
     pub left_group: Vec<i32>,
     pub right_group: Vec<i32>,
@@ -182,7 +734,7 @@ pub struct GroupwiseSaveScore {
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct PluralitySaveScore {
-    pub topic_id: String,
+    pub topic_id: TopicId,
     pub ballot_id: String,
     pub candidates: Vec<i32>,
     pub selected: i32,
@@ -198,7 +750,7 @@ pub enum BallotSaveRequest {
 }
 
 impl BallotSaveRequest {
-    pub fn topic_id(&self) -> &String {
+    pub fn topic_id(&self) -> &TopicId {
         match self {
             BallotSaveRequest::Pairwise(data) => &data.topic_id,
             BallotSaveRequest::Setwise(data) => &data.topic_id,
@@ -207,6 +759,47 @@ impl BallotSaveRequest {
         }
     }
 
+    /// Returns the exceeded limit if a set or group of the request is larger
+    /// than allowed. Pairwise and plurality ballots are not bounded.
+    pub fn check_size_limits(&self, limits: &BallotSizeLimits) -> Result<(), usize> {
+        match self {
+            BallotSaveRequest::Setwise(data)
+                if !limits.set_fits(&data.left_set) || !limits.set_fits(&data.right_set) =>
+            {
+                Err(limits.max_set_size)
+            }
+            BallotSaveRequest::Groupwise(data)
+                if !limits.group_fits(&data.left_group)
+                    || !limits.group_fits(&data.right_group) =>
+            {
+                Err(limits.max_group_size)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The first non-positive operator id anywhere in the request.
+    pub fn invalid_operator_id(&self) -> Option<i32> {
+        match self {
+            BallotSaveRequest::Pairwise(data) => {
+                first_invalid_operator_id([&data.winner, &data.loser])
+            }
+            BallotSaveRequest::Setwise(data) => first_invalid_operator_id(
+                data.left_set
+                    .iter()
+                    .chain(&data.right_set)
+                    .chain(&data.selected_left)
+                    .chain(&data.selected_right),
+            ),
+            BallotSaveRequest::Groupwise(data) => {
+                first_invalid_operator_id(data.left_group.iter().chain(&data.right_group))
+            }
+            BallotSaveRequest::Plurality(data) => {
+                first_invalid_operator_id(data.candidates.iter().chain([&data.selected]))
+            }
+        }
+    }
+
     pub fn ballot_id(&self) -> &String {
         match self {
             BallotSaveRequest::Pairwise(data) => &data.ballot_id,
@@ -215,6 +808,51 @@ impl BallotSaveRequest {
             BallotSaveRequest::Plurality(data) => &data.ballot_id,
         }
     }
+
+    /// Converts the request into the ballot published for processing. The
+    /// ballot carries no user id, see `Ballot::info_mut`.
+    pub fn into_ballot<'a>(
+        self,
+        ip: impl Into<Cow<'a, str>>,
+        user_agent: impl Into<Cow<'a, str>>,
+        timestamp: i64,
+    ) -> Ballot<'a> {
+        let info = |topic_id: TopicId, ballot_id: String| BallotInfo {
+            topic_id: String::from(topic_id).into(),
+            ballot_id: ballot_id.into(),
+            ip: ip.into(),
+            user_agent: user_agent.into(),
+            timestamp,
+            user_id: None,
+            throttled: false,
+        };
+
+        match self {
+            BallotSaveRequest::Pairwise(data) => Ballot::Pairwise(PairwiseBallot {
+                info: info(data.topic_id, data.ballot_id),
+                win: data.winner,
+                lose: data.loser,
+            }),
+            BallotSaveRequest::Setwise(data) => Ballot::Setwise(SetwiseBallot {
+                info: info(data.topic_id, data.ballot_id),
+                left_set: data.left_set,
+                right_set: data.right_set,
+                selected_left: data.selected_left,
+                selected_right: data.selected_right,
+            }),
+            BallotSaveRequest::Groupwise(data) => Ballot::Groupwise(GroupwiseBallot {
+                info: info(data.topic_id, data.ballot_id),
+                left_group: data.left_group,
+                right_group: data.right_group,
+                selected_group: data.selected_group,
+            }),
+            BallotSaveRequest::Plurality(data) => Ballot::Plurality(PluralityBallot {
+                info: info(data.topic_id, data.ballot_id),
+                candidates: data.candidates,
+                selected: data.selected,
+            }),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
@@ -230,11 +868,31 @@ pub struct FinalOrderItem {
     pub lose: i64,
     pub score: String,
     pub rate: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier: Option<String>,
+    /// `score` before formatting.
+    #[serde(default)]
+    pub score_value: f64,
+    /// `rate` in percent before formatting.
+    #[serde(default)]
+    pub rate_value: f64,
 }
 
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct ResultsFinalOrderRequest {
-    pub topic_id: String,
+    pub topic_id: TopicId,
+    /// Ascending rate breakpoints (in percent) used to label each item with a tier.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier_breakpoints: Option<Vec<f64>>,
+    /// How items are ordered; falls back to `Rate` when the method can not be applied.
+    #[serde(default)]
+    pub ranking_method: RankingMethod,
+    /// Only return the first `limit` items, all of them when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// How `rate` and `score` are written, `70.0%` and `0.40` by default.
+    #[serde(default)]
+    pub format: ResultFormat,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
@@ -242,11 +900,28 @@ pub struct ResultsFinalOrderResponse {
     pub topic_id: String,
     pub items: Vec<FinalOrderItem>,
     pub count: i64,
+    /// The ranking method actually used to order `items`.
+    #[serde(default)]
+    pub ranking_method: RankingMethod,
+    /// When the scores were read, cached responses keep the time they were
+    /// first computed.
+    pub computed_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub enum MatrixFormat {
+    /// Items keyed by `left:right`, both directions of every pair.
+    #[default]
+    Keyed,
+    /// One `pairs` entry per unordered pair, `items` is left empty.
+    Pairs,
 }
 
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct Results1v1MatrixRequest {
-    pub topic_id: String,
+    pub topic_id: TopicId,
+    #[serde(default)]
+    pub format: MatrixFormat,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
@@ -255,87 +930,1743 @@ pub struct Results1v1MatrixItem {
     pub count: i64,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
-pub struct Results1v1MatrixResponse(pub HashMap<String, Results1v1MatrixItem>);
-
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct TopicCreateRequest {
-    pub id: String,
-    pub name: String,
-    pub title: String,
-    pub description: String,
-    pub topic_type: VotingTopicType,
-    pub candidate_pool: CandidatePoolPreset,
-
-    pub open_time: DateTime<Utc>,
-    pub close_time: DateTime<Utc>,
+/// Record of one unordered pair, `a < b`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct Results1v1MatrixPair {
+    pub a: i32,
+    pub b: i32,
+    /// Wins of `a`, as recorded by the score scripts. Only pairs counted
+    /// before the wins were recorded have them estimated from `net`.
+    pub a_wins: i64,
+    pub b_wins: i64,
+    /// Games played between the two.
+    pub total: i64,
+    /// Weighted score of `a` over `b`, the `a:b` item of the keyed format.
+    pub net: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct TopicCreateResponse {
-    pub id: String,
-    pub is_active: bool,
-    pub status: CreateTopicStatus,
+impl Results1v1MatrixPair {
+    /// Pairs from the `op_matrix` and `op_counter` hashes, ordered by `a`
+    /// then `b`. Pairs without games are left out.
+    pub fn from_hashes(
+        matrix: &HashMap<String, i64>,
+        counter: &HashMap<String, i64>,
+        base_multiplier: i32,
+    ) -> Vec<Self> {
+        let mut pairs: Vec<Self> = pair_records(matrix, counter, base_multiplier)
+            .into_iter()
+            .map(|record| Self {
+                a: record.a,
+                b: record.b,
+                a_wins: record.a_wins.round() as i64,
+                b_wins: record.b_wins.round() as i64,
+                total: record.games as i64,
+                net: matrix
+                    .get(&format!("{}:{}", record.a, record.b))
+                    .copied()
+                    .unwrap_or(0),
+            })
+            .collect();
+        pairs.sort_unstable_by_key(|pair| (pair.a, pair.b));
+        pairs
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct TopicInfoRequest {
-    pub topic_id: String,
+/// Pair items keyed by `left:right`. The JSON `data` stays the plain map
+/// clients have always read, `computed_at` is sent next to it, see
+/// `TimedApiResponse`.
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct Results1v1MatrixResponse {
+    #[serde(flatten)]
+    pub items: HashMap<String, Results1v1MatrixItem>,
+    /// Filled instead of `items` for `MatrixFormat::Pairs`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pairs: Vec<Results1v1MatrixPair>,
+    /// When the matrix was read, cached responses keep the time they were
+    /// first computed.
+    #[serde(skip)]
+    pub computed_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct TopicInfoResponse {
-    pub id: String,
-    pub name: String,
-    pub title: String,
-    pub description: String,
-    pub topic_type: VotingTopicType,
-    pub open_time: DateTime<Utc>,
-    pub close_time: DateTime<Utc>,
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ResultsDominantMatchupsRequest {
+    pub topic_id: TopicId,
+    /// Minimum win rate of the dominant operator, in percent.
+    pub min_win_rate: f64,
+    /// Minimum number of games played between the two operators.
+    pub min_games: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct TopicListActiveResponse {
-    pub topic_ids: Vec<String>,
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct DominantMatchupItem {
+    pub winner_id: i32,
+    pub winner_name: String,
+    pub loser_id: i32,
+    pub loser_name: String,
+    pub games: i64,
+    pub win_rate: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct AuditTopicsListResponse {
-    pub topics: Vec<VotingTopic>,
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ResultsDominantMatchupsResponse {
+    pub topic_id: String,
+    pub items: Vec<DominantMatchupItem>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct AuditTopicRequest {
-    pub topic_id: String,
-    pub audit_info: TopicAuditInfo,
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ResultsCondorcetRequest {
+    pub topic_id: TopicId,
 }
 
+/// Head-to-head record of an operator against the rest of the pool.
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
-pub struct CharacterPortrait {
+pub struct CondorcetItem {
     pub id: i32,
     pub name: String,
-    pub cn_name: String,
-    pub avatar: Vec<String>,
+    pub wins: usize,
+    pub losses: usize,
+    pub ties: usize,
 }
 
-impl Default for CharacterPortrait {
-    fn default() -> Self {
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ResultsCondorcetResponse {
+    pub topic_id: String,
+    /// The operator that beats every other operator head-to-head, if any.
+    pub winner: Option<CondorcetItem>,
+    /// Smallest set of operators that each beat every operator outside of
+    /// it, best Copeland score first. Only the winner if there is one,
+    /// otherwise the operators caught in a cycle.
+    pub smith_set: Vec<CondorcetItem>,
+    pub num_operators: usize,
+    pub computed_at: DateTime<Utc>,
+}
+
+impl ResultsCondorcetResponse {
+    pub fn build(
+        topic_id: String,
+        smith_set: Vec<MajorityRecord>,
+        num_operators: usize,
+        character_infos: &[CharacterInfo],
+    ) -> Self {
+        let smith_set: Vec<CondorcetItem> = smith_set
+            .into_iter()
+            .map(|record| CondorcetItem {
+                id: record.id,
+                name: character_infos
+                    .iter()
+                    .find(|info| info.id == record.id)
+                    .map(|info| info.name.clone())
+                    .unwrap_or_else(|| format!("Unknown Operator {}", record.id)),
+                wins: record.wins,
+                losses: record.losses,
+                ties: record.ties,
+            })
+            .collect();
+        let winner = match smith_set.as_slice() {
+            [winner] => Some(winner.clone()),
+            _ => None,
+        };
+
         Self {
-            id: -1,
-            name: "Unknown".to_string(),
-            cn_name: "未知".to_string(),
-            avatar: vec![],
+            topic_id,
+            winner,
+            smith_set,
+            num_operators,
+            computed_at: Utc::now(),
         }
     }
-}
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct TopicCandidatePoolRequest {
-    pub topic_id: String,
-}
+    /// Body of `/results/condorcet`, the services only differ in how they
+    /// load the topic, its candidate pool and its head-to-head matrix. The
+    /// matrix is only read once the pool is known to be small enough.
+    pub async fn respond<E, M>(
+        topic: Result<Option<VotingTopic>, E>,
+        candidate_pool: impl AsyncFnOnce(&str) -> Result<Vec<i32>, CandidatePoolError>,
+        matrix: impl AsyncFnOnce() -> Result<HashMap<String, i64>, M>,
+        character_infos: &[CharacterInfo],
+    ) -> ApiResponse<Self>
+    where
+        E: fmt::Display,
+        M: fmt::Display,
+    {
+        let error = |status, message| ApiResponse {
+            status,
+            data: ApiData::Empty,
+            message,
+        };
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct TopicCandidatePoolResponse {
-    pub topic_id: String,
-    pub pool: Vec<CharacterPortrait>,
+        let target_topic = match topic {
+            Ok(Some(topic)) if topic.topic_type.supports_1v1_matrix() => topic,
+            Ok(Some(_)) => {
+                return error(
+                    ResponseStatus::BadRequest,
+                    ApiMsg::CurTopicNotSupport1v1Matrix,
+                );
+            }
+            Ok(None) => return error(ResponseStatus::NotFound, ApiMsg::TargetTopicNotFound),
+            Err(e) => {
+                tracing::error!("Failed to load topic: {}", e);
+                return error(ResponseStatus::InternalError, ApiMsg::InternalError);
+            }
+        };
+
+        let candidate_pool = match candidate_pool(&target_topic.id).await {
+            Ok(pool) => pool,
+            Err(err) => return error(ResponseStatus::NotFound, err.into()),
+        };
+        if candidate_pool.len() > MAX_CONDORCET_OPERATORS {
+            return error(
+                ResponseStatus::BadRequest,
+                ApiMsg::CondorcetPoolTooLarge(MAX_CONDORCET_OPERATORS),
+            );
+        }
+
+        let matrix = match matrix().await {
+            Ok(matrix) => matrix,
+            Err(e) => {
+                tracing::error!("Failed to read op_matrix of {}: {}", target_topic.id, e);
+                return error(ResponseStatus::InternalError, ApiMsg::InternalError);
+            }
+        };
+        let Some(smith_set) = smith_set(&candidate_pool, &matrix) else {
+            return error(
+                ResponseStatus::BadRequest,
+                ApiMsg::CondorcetPoolTooLarge(MAX_CONDORCET_OPERATORS),
+            );
+        };
+
+        ApiResponse {
+            status: ResponseStatus::Ok,
+            data: ApiData::Data(Self::build(
+                target_topic.id,
+                smith_set,
+                candidate_pool.len(),
+                character_infos,
+            )),
+            message: ApiMsg::OK,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ResultsPartnersRequest {
+    pub topic_id: TopicId,
+    pub operator_id: i32,
+    /// Only return the `limit` most compared partners, all of them when
+    /// unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct PartnerItem {
+    pub id: i32,
+    /// Ballots showing both operators, whatever the outcome.
+    pub count: i64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ResultsPartnersResponse {
+    pub topic_id: String,
+    pub operator_id: i32,
+    /// Most compared first, ties by operator id.
+    pub partners: Vec<PartnerItem>,
+}
+
+impl ResultsPartnersResponse {
+    /// Collects the partners of `operator_id` from the `op_cooccur` hash,
+    /// keyed `min:max`.
+    pub fn build(
+        topic_id: String,
+        operator_id: i32,
+        cooccur: &HashMap<String, i64>,
+        limit: Option<usize>,
+    ) -> Self {
+        let mut partners: Vec<PartnerItem> = cooccur
+            .iter()
+            .filter(|&(_, &count)| count > 0)
+            .filter_map(|(key, &count)| {
+                let (a, b) = key.split_once(':')?;
+                let (a, b) = (a.parse::<i32>().ok()?, b.parse::<i32>().ok()?);
+                let id = match operator_id {
+                    id if id == a => b,
+                    id if id == b => a,
+                    _ => return None,
+                };
+                Some(PartnerItem { id, count })
+            })
+            .collect();
+
+        partners.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.id.cmp(&b.id)));
+        if let Some(limit) = limit {
+            partners.truncate(limit);
+        }
+
+        Self {
+            topic_id,
+            operator_id,
+            partners,
+        }
+    }
+}
+
+/// Most buckets a `/results/velocity` response can hold.
+pub const MAX_VELOCITY_BUCKETS: usize = 1440;
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ResultsVelocityRequest {
+    pub topic_id: TopicId,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// Width of each bucket, one minute by default.
+    #[serde(default = "default_velocity_bucket_secs")]
+    pub bucket_secs: u32,
+}
+
+fn default_velocity_bucket_secs() -> u32 {
+    60
+}
+
+impl ResultsVelocityRequest {
+    pub fn bucket_ms(&self) -> i64 {
+        i64::from(self.bucket_secs) * 1000
+    }
+
+    /// Buckets covering `[start_time, end_time)`, the last one may reach past
+    /// `end_time`. `None` when the window is empty or needs more than
+    /// `MAX_VELOCITY_BUCKETS`.
+    pub fn bucket_count(&self) -> Option<usize> {
+        let window_ms = (self.end_time - self.start_time).num_milliseconds();
+        if window_ms <= 0 || self.bucket_secs == 0 {
+            return None;
+        }
+        let count = usize::try_from((window_ms + self.bucket_ms() - 1) / self.bucket_ms()).ok()?;
+        (count <= MAX_VELOCITY_BUCKETS).then_some(count)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct VelocityBucket {
+    pub start: DateTime<Utc>,
+    /// Ballots stored with a timestamp inside the bucket.
+    pub count: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ResultsVelocityResponse {
+    pub topic_id: String,
+    pub bucket_secs: u32,
+    /// `vote.ballot_store_sample_rate` of the topic. Above 1 the counts are
+    /// the sampled ballots scaled back up, an estimate of the real volume.
+    pub sample_rate: u64,
+    /// Every bucket of the window in order, empty ones included.
+    pub buckets: Vec<VelocityBucket>,
+}
+
+impl ResultsVelocityResponse {
+    /// Spreads `counts`, the stored ballots keyed by the start of their
+    /// bucket in epoch milliseconds, over every bucket of the window.
+    pub fn build(
+        request: &ResultsVelocityRequest,
+        bucket_count: usize,
+        counts: &HashMap<i64, u64>,
+        sample_rate: u64,
+    ) -> Self {
+        let start_ms = request.start_time.timestamp_millis();
+        let buckets = (0..bucket_count as i64)
+            .map(|i| {
+                let bucket_start = start_ms + i * request.bucket_ms();
+                VelocityBucket {
+                    start: DateTime::from_timestamp_millis(bucket_start).unwrap_or_default(),
+                    count: counts.get(&bucket_start).copied().unwrap_or(0) * sample_rate.max(1),
+                }
+            })
+            .collect();
+
+        Self {
+            topic_id: request.topic_id.to_string(),
+            bucket_secs: request.bucket_secs,
+            sample_rate,
+            buckets,
+        }
+    }
+}
+
+/// Bucket of operators missing from the character table or without a
+/// profession.
+pub const OTHER_PROFESSION: &str = "Other";
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ResultsByProfessionRequest {
+    pub topic_id: TopicId,
+    #[serde(default)]
+    pub ranking_method: RankingMethod,
+    /// Only return the first `limit` operators of each profession, all of
+    /// them when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ResultsByProfessionResponse {
+    pub topic_id: String,
+    /// Final order items per profession, keyed by the profession name or
+    /// `Other`, in final order.
+    pub professions: BTreeMap<String, Vec<FinalOrderItem>>,
+    pub count: i64,
+    pub ranking_method: RankingMethod,
+}
+
+impl ResultsByProfessionResponse {
+    /// Groups the final order by operator profession, keeping the first
+    /// `limit` operators of each group.
+    pub fn build(
+        final_order: &ResultsFinalOrderResponse,
+        character_infos: &[CharacterInfo],
+        limit: Option<usize>,
+    ) -> Self {
+        let professions: HashMap<i32, &ProfessionCategory> = character_infos
+            .iter()
+            .map(|info| (info.id, &info.profession))
+            .collect();
+
+        let mut groups: BTreeMap<String, Vec<FinalOrderItem>> = BTreeMap::new();
+        for item in &final_order.items {
+            let profession = match professions.get(&item.id) {
+                Some(ProfessionCategory::NONE) | None => OTHER_PROFESSION,
+                Some(profession) => profession.as_str(),
+            };
+            let group = groups.entry(profession.to_string()).or_default();
+            if limit.is_none_or(|limit| group.len() < limit) {
+                group.push(item.clone());
+            }
+        }
+
+        Self {
+            topic_id: final_order.topic_id.clone(),
+            professions: groups,
+            count: final_order.count,
+            ranking_method: final_order.ranking_method,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicCreateRequest {
+    pub id: String,
+    pub name: String,
+    pub title: String,
+    pub description: String,
+    pub topic_type: VotingTopicType,
+    pub candidate_pool: CandidatePoolPreset,
+
+    pub open_time: DateTime<Utc>,
+    pub close_time: DateTime<Utc>,
+
+    /// Makes retries safe: when a topic with `id` already exists with the
+    /// same content it is returned instead of failing with `TopicIdTaken`.
+    #[serde(default)]
+    pub idempotent: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicCreateResponse {
+    pub id: String,
+    pub is_active: bool,
+    pub status: CreateTopicStatus,
+    /// The topic was created by an earlier, identical idempotent request.
+    #[serde(default)]
+    pub already_existed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicInfoRequest {
+    pub topic_id: TopicId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicInfoResponse {
+    pub id: String,
+    pub name: String,
+    pub title: String,
+    pub description: String,
+    pub topic_type: VotingTopicType,
+    pub open_time: DateTime<Utc>,
+    pub close_time: DateTime<Utc>,
+    /// Voting is paused by an admin, ballots are refused until it resumes.
+    #[serde(default)]
+    pub paused: bool,
+}
+
+impl From<VotingTopic> for TopicInfoResponse {
+    fn from(topic: VotingTopic) -> Self {
+        Self {
+            id: topic.id,
+            name: topic.name,
+            title: topic.title,
+            description: topic.description,
+            topic_type: topic.topic_type,
+            open_time: topic.open_time,
+            close_time: topic.close_time,
+            paused: topic.paused,
+        }
+    }
+}
+
+/// `TopicInfoResponse` plus the stored preset, used to pre-populate the admin
+/// edit form. The preset stays out of the public response.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AdminTopicInfoResponse {
+    #[serde(flatten)]
+    pub topic: TopicInfoResponse,
+    pub candidate_pool: CandidatePoolPreset,
+}
+
+impl From<VotingTopic> for AdminTopicInfoResponse {
+    fn from(topic: VotingTopic) -> Self {
+        Self {
+            candidate_pool: topic.candidate_pool.clone(),
+            topic: topic.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicListActiveResponse {
+    pub topic_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditTopicsListResponse {
+    pub topics: Vec<VotingTopic>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditTopicRequest {
+    pub topic_id: TopicId,
+    pub audit_info: TopicAuditInfo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SubProfessionBlocklistPayload {
+    pub sub_professions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicReopenRequest {
+    pub topic_id: TopicId,
+    pub close_time: DateTime<Utc>,
+    #[serde(default)]
+    pub open_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicUpdateRequest {
+    pub topic_id: TopicId,
+    #[serde(flatten)]
+    pub update: TopicMetadataUpdate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicUpdateResponse {
+    /// Fields that differed from the stored topic and were written.
+    pub updated_fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicFreezePoolRequest {
+    pub topic_id: TopicId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicFreezePoolResponse {
+    /// Operators in the new snapshot, before the sub profession blocklist.
+    pub pool_size: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicPauseRequest {
+    pub topic_id: TopicId,
+    /// `true` pauses voting, `false` resumes it.
+    pub paused: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicResetScoresRequest {
+    pub topic_id: TopicId,
+    /// Has to repeat `topic_id`, guards against resetting the wrong topic.
+    pub confirm: String,
+    /// Also drop the `ballots_<topic_id>` collection.
+    #[serde(default)]
+    pub drop_ballots: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicResetScoresResponse {
+    pub keys_removed: i64,
+    pub ballots_removed: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BallotLookupRequest {
+    pub topic_id: TopicId,
+    pub ballot_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BallotLookupResponse {
+    /// Whether the consumer stored the ballot. Ballots still queued or
+    /// rejected by the consumer are not found.
+    pub found: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ballot: Option<BallotRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BallotRecord {
+    pub topic_type: VotingTopicType,
+    /// Only set for pairwise ballots.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub win: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lose: Option<i32>,
+    pub multiplier: i32,
+    /// Save time in milliseconds since the epoch.
+    pub timestamp: i64,
+    #[serde(default)]
+    pub throttled: bool,
+}
+
+impl From<&StoredBallot<'_>> for BallotRecord {
+    fn from(stored: &StoredBallot<'_>) -> Self {
+        let (topic_type, win, lose) = match &stored.ballot {
+            Ballot::Pairwise(ballot) => (
+                VotingTopicType::Pairwise,
+                Some(ballot.win),
+                Some(ballot.lose),
+            ),
+            Ballot::Setwise(_) => (VotingTopicType::Setwise, None, None),
+            Ballot::Groupwise(_) => (VotingTopicType::Groupwise, None, None),
+            Ballot::Plurality(_) => (VotingTopicType::Plurality, None, None),
+        };
+        let info = stored.ballot.info();
+
+        Self {
+            topic_type,
+            win,
+            lose,
+            multiplier: stored.multiplier,
+            timestamp: info.timestamp,
+            throttled: info.throttled,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct CharacterPortrait {
+    pub id: i32,
+    pub name: String,
+    pub cn_name: String,
+    pub avatar: Vec<String>,
+    /// Dimensions of each `avatar` image, `None` while not probed yet. Only
+    /// set when requested with `include_image_hints`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_dimensions: Option<Vec<Option<ImageDimensions>>>,
+}
+
+impl Default for CharacterPortrait {
+    fn default() -> Self {
+        Self {
+            id: -1,
+            name: "Unknown".to_string(),
+            cn_name: "未知".to_string(),
+            avatar: vec![],
+            avatar_dimensions: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct ImageDimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicCandidatePoolRequest {
+    pub topic_id: TopicId,
+    /// Adds `avatar_dimensions` to each portrait so clients can reserve space
+    /// before the images load.
+    #[serde(default)]
+    pub include_image_hints: bool,
+    #[serde(default)]
+    pub order_by: CandidatePoolOrder,
+    /// Only operators whose name or one of its aliases contains this, ignoring
+    /// case.
+    #[serde(default)]
+    pub name_contains: Option<String>,
+}
+
+/// Display order of the portraits in a candidate pool response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum CandidatePoolOrder {
+    #[default]
+    Id,
+    /// Highest rarity first, then by name
+    Rarity,
+    Name,
+    /// By profession, highest rarity first within each
+    Profession,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicCandidatePoolResponse {
+    pub topic_id: String,
+    pub pool: Vec<CharacterPortrait>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicValidatePoolResponse {
+    pub valid: bool,
+    /// Operators the preset resolves to, only set when it is valid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_size: Option<usize>,
+    /// One message per problem found.
+    pub errors: Vec<String>,
+}
+
+impl From<Result<Vec<i32>, Vec<PoolPresetError>>> for TopicValidatePoolResponse {
+    fn from(result: Result<Vec<i32>, Vec<PoolPresetError>>) -> Self {
+        match result {
+            Ok(pool) => Self {
+                valid: true,
+                pool_size: Some(pool.len()),
+                errors: Vec::new(),
+            },
+            Err(errors) => Self {
+                valid: false,
+                pool_size: None,
+                errors: errors.iter().map(ToString::to_string).collect(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OperatorsListQuery {
+    /// Only operators of this rarity, e.g. `TIER_6`
+    #[param(value_type = Option<String>)]
+    pub rarity: Option<RarityRank>,
+    /// Only operators of this profession, e.g. `CASTER`
+    #[param(value_type = Option<String>)]
+    pub profession: Option<ProfessionCategory>,
+    #[serde(default)]
+    pub offset: usize,
+    /// Page size, all remaining operators when unset
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OperatorInfo {
+    pub id: i32,
+    pub name: String,
+    pub rarity: RarityRank,
+    pub profession: ProfessionCategory,
+    pub sub_profession_id: String,
+    pub is_not_obtainable: bool,
+    /// `None` for operators without a known portrait.
+    pub portrait: Option<CharacterPortrait>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OperatorsListResponse {
+    /// Operators matching the filters, before pagination.
+    pub total: usize,
+    pub offset: usize,
+    pub items: Vec<OperatorInfo>,
+}
+
+impl OperatorsListResponse {
+    /// Filters the operator catalog by `query` and returns the requested
+    /// page, ordered by id.
+    pub fn build(
+        character_infos: &[CharacterInfo],
+        character_portraits: &HashMap<i32, CharacterPortrait>,
+        query: &OperatorsListQuery,
+    ) -> Self {
+        let mut matching: Vec<&CharacterInfo> = character_infos
+            .iter()
+            .filter(|c| query.rarity.is_none_or(|rarity| c.rarity == rarity))
+            .filter(|c| {
+                query
+                    .profession
+                    .as_ref()
+                    .is_none_or(|profession| &c.profession == profession)
+            })
+            .collect();
+        matching.sort_unstable_by_key(|c| c.id);
+
+        let items = matching
+            .iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .map(|c| OperatorInfo {
+                id: c.id,
+                name: c.name.clone(),
+                rarity: c.rarity,
+                profession: c.profession.clone(),
+                sub_profession_id: c.sub_profession_id.clone(),
+                is_not_obtainable: c.is_not_obtainable,
+                portrait: character_portraits.get(&c.id).cloned(),
+            })
+            .collect();
+
+        Self {
+            total: matching.len(),
+            offset: query.offset,
+            items,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{create_test_characters, test_topic};
+
+    fn topic(id: &str) -> TopicId {
+        TopicId::parse(id).unwrap()
+    }
+
+    fn setwise(left: usize, right: usize) -> BallotSaveRequest {
+        BallotSaveRequest::Setwise(SetwiseSaveScore {
+            topic_id: topic("test_topic"),
+            ballot_id: "1-abc".to_string(),
+            left_set: (0..left as i32).collect(),
+            right_set: (100..100 + right as i32).collect(),
+            selected_left: Vec::new(),
+            selected_right: Vec::new(),
+        })
+    }
+
+    fn groupwise(left: usize, right: usize) -> BallotSaveRequest {
+        BallotSaveRequest::Groupwise(GroupwiseSaveScore {
+            topic_id: topic("test_topic"),
+            ballot_id: "1-abc".to_string(),
+            left_group: (0..left as i32).collect(),
+            right_group: (100..100 + right as i32).collect(),
+            selected_group: GroupwiseSelection::Left,
+        })
+    }
+
+    #[test]
+    fn test_admin_topic_info_flattens_topic() {
+        let now = Utc::now();
+        let topic = test_topic("test_topic", now, now);
+
+        let json = serde_json::to_value(AdminTopicInfoResponse::from(topic)).unwrap();
+        assert_eq!(json["id"], "test_topic");
+        assert_eq!(json["candidate_pool"]["type"], "all");
+    }
+
+    #[test]
+    fn test_results_partners_build() {
+        let cooccur = HashMap::from([
+            ("1:2".to_string(), 5),
+            ("2:3".to_string(), 9),
+            ("2:4".to_string(), 5),
+            ("1:3".to_string(), 20),
+            ("2:5".to_string(), 0),
+        ]);
+
+        let response = ResultsPartnersResponse::build("t".to_string(), 2, &cooccur, None);
+        let partners: Vec<(i32, i64)> = response.partners.iter().map(|p| (p.id, p.count)).collect();
+        assert_eq!(partners, [(3, 9), (1, 5), (4, 5)]);
+
+        let response = ResultsPartnersResponse::build("t".to_string(), 2, &cooccur, Some(1));
+        assert_eq!(response.partners, [PartnerItem { id: 3, count: 9 }]);
+    }
+
+    fn velocity_request(window_secs: i64, bucket_secs: u32) -> ResultsVelocityRequest {
+        let start_time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        ResultsVelocityRequest {
+            topic_id: topic("t"),
+            start_time,
+            end_time: start_time + chrono::Duration::seconds(window_secs),
+            bucket_secs,
+        }
+    }
+
+    #[test]
+    fn test_velocity_bucket_count() {
+        assert_eq!(velocity_request(600, 60).bucket_count(), Some(10));
+        assert_eq!(velocity_request(601, 60).bucket_count(), Some(11));
+        assert_eq!(velocity_request(0, 60).bucket_count(), None);
+        assert_eq!(velocity_request(-60, 60).bucket_count(), None);
+        assert_eq!(velocity_request(600, 0).bucket_count(), None);
+        assert_eq!(velocity_request(86_400, 60).bucket_count(), Some(1440));
+        assert_eq!(velocity_request(86_401, 60).bucket_count(), None);
+    }
+
+    #[test]
+    fn test_velocity_build_fills_gaps() {
+        let request = velocity_request(180, 60);
+        let start_ms = request.start_time.timestamp_millis();
+        let counts = HashMap::from([(start_ms, 4), (start_ms + 120_000, 2)]);
+
+        let response = ResultsVelocityResponse::build(&request, 3, &counts, 1);
+        let buckets: Vec<u64> = response.buckets.iter().map(|b| b.count).collect();
+        assert_eq!(buckets, [4, 0, 2]);
+        assert_eq!(
+            response.buckets[1].start,
+            request.start_time + chrono::Duration::seconds(60)
+        );
+
+        let response = ResultsVelocityResponse::build(&request, 3, &HashMap::new(), 1);
+        assert!(response.buckets.iter().all(|b| b.count == 0));
+
+        let response = ResultsVelocityResponse::build(&request, 3, &counts, 10);
+        assert_eq!(response.buckets[0].count, 40);
+    }
+
+    /// Every message the services answer with, the match fails to compile
+    /// when a variant is added without being listed here.
+    fn all_api_msgs() -> Vec<ApiMsg> {
+        let messages = vec![
+            ApiMsg::OK,
+            ApiMsg::TopicCreateFailed,
+            ApiMsg::TopicIdTaken,
+            ApiMsg::TargetTopicNotFound,
+            ApiMsg::TargetTopicNotActive,
+            ApiMsg::TopicNotYetOpen,
+            ApiMsg::TopicClosed,
+            ApiMsg::TopicPaused,
+            ApiMsg::TargetTopicCandidatePoolNotFound,
+            ApiMsg::EmptyCandidatePool,
+            ApiMsg::RequestTopicTypeMismatch,
+            ApiMsg::CurTopicNotSupportFinalOrder,
+            ApiMsg::CurTopicNotSupport1v1Matrix,
+            ApiMsg::CondorcetPoolTooLarge(8),
+            ApiMsg::InvalidTierBreakpoints,
+            ApiMsg::InvalidResultFormat,
+            ApiMsg::InternalError,
+            ApiMsg::InvalidJson,
+            ApiMsg::PayloadTooLarge,
+            ApiMsg::UpstreamUnavailable,
+            ApiMsg::InsufficientOperators,
+            ApiMsg::CandidatePoolTooSmall("x".to_string()),
+            ApiMsg::BallotWinnerCannotBeLoser,
+            ApiMsg::InvalidOperatorId(-1),
+            ApiMsg::UnsupportedTopicType,
+            ApiMsg::BenchBallotNotFound,
+            ApiMsg::BallotNotFound,
+            ApiMsg::InvalidBallotCode("x".to_string()),
+            ApiMsg::BallotSetTooLarge(8),
+            ApiMsg::SkipBatchTooLarge(8),
+            ApiMsg::EndpointForbidden,
+            ApiMsg::Unauthorized,
+            ApiMsg::InvalidUserToken,
+            ApiMsg::TopicNotApproved,
+            ApiMsg::TopicCloseTimeInPast,
+            ApiMsg::InvalidTopicWindow,
+            ApiMsg::InvalidTopic("x".to_string()),
+            ApiMsg::TopicCandidatePoolLocked,
+            ApiMsg::ResetConfirmationMismatch,
+            ApiMsg::InvalidVelocityWindow(8),
+            ApiMsg::RateLimited,
+            ApiMsg::BallotSavedTooSoon,
+            ApiMsg::Error("x".to_string()),
+        ];
+        for message in &messages {
+            match message {
+                ApiMsg::OK
+                | ApiMsg::TopicCreateFailed
+                | ApiMsg::TopicIdTaken
+                | ApiMsg::TargetTopicNotFound
+                | ApiMsg::TargetTopicNotActive
+                | ApiMsg::TopicNotYetOpen
+                | ApiMsg::TopicClosed
+                | ApiMsg::TopicPaused
+                | ApiMsg::TargetTopicCandidatePoolNotFound
+                | ApiMsg::EmptyCandidatePool
+                | ApiMsg::RequestTopicTypeMismatch
+                | ApiMsg::CurTopicNotSupportFinalOrder
+                | ApiMsg::CurTopicNotSupport1v1Matrix
+                | ApiMsg::CondorcetPoolTooLarge(_)
+                | ApiMsg::InvalidTierBreakpoints
+                | ApiMsg::InvalidResultFormat
+                | ApiMsg::InternalError
+                | ApiMsg::InvalidJson
+                | ApiMsg::PayloadTooLarge
+                | ApiMsg::UpstreamUnavailable
+                | ApiMsg::InsufficientOperators
+                | ApiMsg::CandidatePoolTooSmall(_)
+                | ApiMsg::BallotWinnerCannotBeLoser
+                | ApiMsg::InvalidOperatorId(_)
+                | ApiMsg::UnsupportedTopicType
+                | ApiMsg::BenchBallotNotFound
+                | ApiMsg::BallotNotFound
+                | ApiMsg::InvalidBallotCode(_)
+                | ApiMsg::BallotSetTooLarge(_)
+                | ApiMsg::SkipBatchTooLarge(_)
+                | ApiMsg::EndpointForbidden
+                | ApiMsg::Unauthorized
+                | ApiMsg::InvalidUserToken
+                | ApiMsg::TopicNotApproved
+                | ApiMsg::TopicCloseTimeInPast
+                | ApiMsg::InvalidTopicWindow
+                | ApiMsg::InvalidTopic(_)
+                | ApiMsg::TopicCandidatePoolLocked
+                | ApiMsg::ResetConfirmationMismatch
+                | ApiMsg::InvalidVelocityWindow(_)
+                | ApiMsg::RateLimited
+                | ApiMsg::BallotSavedTooSoon
+                | ApiMsg::Error(_) => {}
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn test_condorcet_response() {
+        let record = |id, wins, losses| MajorityRecord {
+            id,
+            wins,
+            losses,
+            ties: 0,
+        };
+
+        let rsp = ResultsCondorcetResponse::build("t".to_string(), vec![record(2, 2, 0)], 3, &[]);
+        let winner = rsp.winner.unwrap();
+        assert_eq!((winner.id, winner.name.as_str()), (2, "Unknown Operator 2"));
+        assert_eq!(rsp.smith_set.len(), 1);
+
+        let cycle = vec![record(1, 1, 1), record(2, 1, 1), record(3, 1, 1)];
+        let rsp = ResultsCondorcetResponse::build("t".to_string(), cycle, 3, &[]);
+        assert!(rsp.winner.is_none());
+        assert_eq!(rsp.smith_set.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_condorcet_rejects_unknown_topic_before_reading() {
+        let pool = async |_: &str| -> Result<Vec<i32>, CandidatePoolError> {
+            panic!("pool read for a missing topic")
+        };
+        let matrix = async || -> Result<HashMap<String, i64>, String> {
+            panic!("matrix read for a missing topic")
+        };
+
+        let rsp = ResultsCondorcetResponse::respond(Err("gone"), pool, matrix, &[]).await;
+        assert_eq!(rsp.status, ResponseStatus::InternalError);
+        assert!(matches!(rsp.message, ApiMsg::InternalError));
+
+        let rsp = ResultsCondorcetResponse::respond(Ok::<_, &str>(None), pool, matrix, &[]).await;
+        assert_eq!(rsp.status, ResponseStatus::NotFound);
+        assert!(matches!(rsp.message, ApiMsg::TargetTopicNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_condorcet_checks_pool_size_before_reading() {
+        let now = Utc::now();
+        let topic = || Ok::<_, &str>(Some(test_topic("test_topic", now, now)));
+
+        let too_large =
+            async |_: &str| Ok((0..=MAX_CONDORCET_OPERATORS as i32).collect::<Vec<_>>());
+        let unread = async || -> Result<HashMap<String, i64>, String> {
+            panic!("matrix read for an oversized pool")
+        };
+        let rsp = ResultsCondorcetResponse::respond(topic(), too_large, unread, &[]).await;
+        assert_eq!(rsp.status, ResponseStatus::BadRequest);
+        assert!(matches!(rsp.message, ApiMsg::CondorcetPoolTooLarge(_)));
+
+        let pool = async |_: &str| Ok(vec![1001, 1002, 2001]);
+        let matrix = async || {
+            Ok::<_, String>(HashMap::from([
+                ("1001:1002".to_string(), 3),
+                ("1001:2001".to_string(), 1),
+            ]))
+        };
+        let characters = create_test_characters();
+        let rsp = ResultsCondorcetResponse::respond(topic(), pool, matrix, &characters).await;
+        assert_eq!(rsp.status, ResponseStatus::Ok);
+        let ApiData::Data(rsp) = rsp.data else {
+            panic!("no condorcet result");
+        };
+        let winner = rsp.winner.unwrap();
+        assert_eq!((winner.id, winner.name.as_str()), (1001, "AAAA"));
+        assert_eq!(rsp.num_operators, 3);
+    }
+
+    fn skip(topic_id: &str, ballot_id: &str) -> BallotSkipRequest {
+        BallotSkipRequest {
+            topic_id: topic(topic_id),
+            ballot_id: ballot_id.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_skip_batch_checks_topics_before_skipping() {
+        let now = Utc::now();
+        let reqs = vec![
+            skip("open_topic", "1-a"),
+            skip("closed_topic", "1-b"),
+            skip("open_topic", "1-c"),
+            skip("missing_topic", "1-d"),
+        ];
+        let lookups = std::cell::RefCell::new(Vec::new());
+        let skipped = std::cell::RefCell::new(Vec::new());
+
+        let (lookups, skipped) = (&lookups, &skipped);
+        let topic_of = |id: TopicId| async move {
+            assert!(skipped.borrow().is_empty(), "skip recorded before checks");
+            lookups.borrow_mut().push(id.to_string());
+            Ok::<_, ()>(match id.as_str() {
+                "open_topic" => Some(test_topic(
+                    "open_topic",
+                    now - chrono::Duration::hours(1),
+                    now + chrono::Duration::hours(1),
+                )),
+                "closed_topic" => Some(test_topic(
+                    "closed_topic",
+                    now - chrono::Duration::hours(2),
+                    now - chrono::Duration::hours(1),
+                )),
+                _ => None,
+            })
+        };
+        let record = |req: BallotSkipRequest| async move {
+            skipped.borrow_mut().push(req.ballot_id);
+            Ok::<_, String>(())
+        };
+
+        let rsp = BallotSkipBatchResponse::respond(reqs, topic_of, record).await;
+        assert_eq!(rsp.status, ResponseStatus::Ok);
+        let ApiData::Data(data) = rsp.data else {
+            panic!("skip batch without data");
+        };
+        assert_eq!(data.skipped, 2);
+        assert_eq!(
+            data.results,
+            vec![
+                BallotSkipOutcome::Skipped,
+                BallotSkipOutcome::TopicNotActive,
+                BallotSkipOutcome::Skipped,
+                BallotSkipOutcome::TopicNotActive,
+            ]
+        );
+        // each topic is looked up once
+        assert_eq!(lookups.borrow().len(), 3);
+        assert_eq!(*skipped.borrow(), vec!["1-a", "1-c"]);
+    }
+
+    #[tokio::test]
+    async fn test_skip_batch_reports_failed_skips() {
+        let now = Utc::now();
+        let reqs = vec![
+            skip("open_topic", "1-a"),
+            skip("open_topic", "1-b"),
+            skip("open_topic", "1-c"),
+        ];
+        let topic_of = |id: TopicId| async move {
+            Ok::<_, ()>(Some(test_topic(
+                id.as_str(),
+                now - chrono::Duration::hours(1),
+                now + chrono::Duration::hours(1),
+            )))
+        };
+        let record = |req: BallotSkipRequest| async move {
+            match req.ballot_id.as_str() {
+                "1-b" => Err("nats is down"),
+                _ => Ok(()),
+            }
+        };
+
+        let rsp = BallotSkipBatchResponse::respond(reqs, topic_of, record).await;
+        let ApiData::Data(data) = rsp.data else {
+            panic!("skip batch without data");
+        };
+        assert_eq!(data.skipped, 2);
+        assert_eq!(
+            data.results,
+            vec![
+                BallotSkipOutcome::Skipped,
+                BallotSkipOutcome::Failed,
+                BallotSkipOutcome::Skipped,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skip_batch_rejects_oversized_batch_untouched() {
+        let reqs: Vec<_> = (0..=MAX_SKIP_BATCH_SIZE)
+            .map(|n| skip("open_topic", &format!("1-{}", n)))
+            .collect();
+        let topic_of = |_: TopicId| -> std::future::Ready<Result<Option<VotingTopic>, ()>> {
+            panic!("topic read for an oversized batch")
+        };
+        let record = |_: BallotSkipRequest| -> std::future::Ready<Result<(), String>> {
+            panic!("skip recorded for an oversized batch")
+        };
+
+        let rsp = BallotSkipBatchResponse::respond(reqs, topic_of, record).await;
+        assert_eq!(rsp.status, ResponseStatus::BadRequest);
+        assert!(matches!(rsp.message, ApiMsg::SkipBatchTooLarge(_)));
+    }
+
+    #[test]
+    fn test_ballots_refused_on_paused_or_closed_topics() {
+        let now = Utc::now();
+        let mut topic = test_topic(
+            "test_topic",
+            now - chrono::Duration::hours(1),
+            now + chrono::Duration::hours(1),
+        );
+        assert!(ApiResponse::<()>::ballots_refused(&topic).is_none());
+
+        topic.paused = true;
+        let rsp = ApiResponse::<()>::ballots_refused(&topic).unwrap();
+        assert_eq!(rsp.status, ResponseStatus::Forbidden);
+        assert!(matches!(rsp.message, ApiMsg::TopicPaused));
+
+        // a closed topic reports its window, paused or not
+        topic.close_time = now - chrono::Duration::minutes(1);
+        let rsp = ApiResponse::<()>::ballots_refused(&topic).unwrap();
+        assert_eq!(rsp.status, ResponseStatus::Forbidden);
+        assert!(matches!(rsp.message, ApiMsg::TopicClosed));
+    }
+
+    #[test]
+    fn test_topic_window_errors_share_message() {
+        assert!(matches!(
+            ApiMsg::from(TopicValidationError::InvalidWindow),
+            ApiMsg::InvalidTopicWindow
+        ));
+        assert!(matches!(
+            ApiMsg::from(TopicReopenError::InvalidWindow),
+            ApiMsg::InvalidTopicWindow
+        ));
+        assert!(matches!(
+            ApiMsg::from(TopicValidationError::CandidatePoolTooDeep { max: 8 }),
+            ApiMsg::InvalidTopic(_)
+        ));
+    }
+
+    #[test]
+    fn test_disabled_topic_type_is_unsupported() {
+        let enabled = [VotingTopicType::Pairwise, VotingTopicType::Setwise];
+        assert!(
+            ApiResponse::<()>::topic_type_disabled(&enabled, &VotingTopicType::Setwise).is_none()
+        );
+
+        // the same answer for topic creation and for ballots
+        let rsp =
+            ApiResponse::<()>::topic_type_disabled(&enabled, &VotingTopicType::Plurality).unwrap();
+        assert_eq!(rsp.status, ResponseStatus::Unsupported);
+        assert_eq!(rsp.status.code(), 1);
+        assert_eq!(rsp.status.http_code(), 400);
+        assert!(matches!(rsp.message, ApiMsg::UnsupportedTopicType));
+        assert!(ApiResponse::<()>::topic_type_disabled(&[], &VotingTopicType::Pairwise).is_some());
+    }
+
+    #[test]
+    fn test_api_msg_round_trip() {
+        for message in all_api_msgs() {
+            assert!(!message.to_string().is_empty(), "{:?}", message);
+
+            let value = serde_json::to_value(&message).unwrap();
+            let parsed: ApiMsg = serde_json::from_value(value).unwrap();
+            assert_eq!(parsed.to_string(), message.to_string());
+        }
+    }
+
+    #[test]
+    fn test_response_status_wire_format() {
+        let response = ApiResponse::<()> {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        };
+        let value = serde_json::to_value(&response).unwrap();
+        assert_eq!(value["status"], 404);
+
+        let parsed: ApiResponse<()> = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.status, ResponseStatus::NotFound);
+
+        let legacy: ResponseStatus = serde_json::from_str("200").unwrap();
+        assert_eq!(legacy, ResponseStatus::Ok);
+        assert!(serde_json::from_str::<ResponseStatus>("418").is_err());
+    }
+
+    #[test]
+    fn test_response_status_http_code() {
+        assert_eq!(ResponseStatus::Ok.http_code(), 200);
+        assert_eq!(ResponseStatus::Unsupported.http_code(), 400);
+        assert_eq!(ResponseStatus::PayloadTooLarge.http_code(), 413);
+        assert_eq!(ResponseStatus::Conflict.http_code(), 409);
+        assert_eq!(ResponseStatus::TooManyRequests.http_code(), 429);
+    }
+
+    const LIMITS: BallotSizeLimits = BallotSizeLimits {
+        max_set_size: 3,
+        max_group_size: 4,
+    };
+
+    #[test]
+    fn test_set_size_at_limit() {
+        assert_eq!(setwise(3, 3).check_size_limits(&LIMITS), Ok(()));
+        assert_eq!(setwise(0, 0).check_size_limits(&LIMITS), Ok(()));
+    }
+
+    #[test]
+    fn test_set_size_over_limit() {
+        assert_eq!(setwise(4, 3).check_size_limits(&LIMITS), Err(3));
+        assert_eq!(setwise(3, 4).check_size_limits(&LIMITS), Err(3));
+    }
+
+    #[test]
+    fn test_group_size_limits() {
+        assert_eq!(groupwise(4, 4).check_size_limits(&LIMITS), Ok(()));
+        assert_eq!(groupwise(5, 1).check_size_limits(&LIMITS), Err(4));
+    }
+
+    #[test]
+    fn test_pairwise_not_bounded() {
+        let req = BallotSaveRequest::Pairwise(PairwiseSaveScore {
+            topic_id: topic("test_topic"),
+            ballot_id: "1-abc".to_string(),
+            winner: 1,
+            loser: 2,
+        });
+        assert_eq!(req.check_size_limits(&LIMITS), Ok(()));
+    }
+
+    fn assert_info(info: &BallotInfo<'_>) {
+        assert_eq!(info.topic_id, "test_topic");
+        assert_eq!(info.ballot_id, "1-abc");
+        assert_eq!(info.ip, "127.0.0.1");
+        assert_eq!(info.user_agent, "test-agent");
+        assert_eq!(info.timestamp, 42);
+        assert!(info.user_id.is_none());
+    }
+
+    #[test]
+    fn test_pairwise_into_ballot() {
+        let req = BallotSaveRequest::Pairwise(PairwiseSaveScore {
+            topic_id: topic("test_topic"),
+            ballot_id: "1-abc".to_string(),
+            winner: 1,
+            loser: 2,
+        });
+
+        let Ballot::Pairwise(ballot) = req.into_ballot("127.0.0.1", "test-agent", 42) else {
+            panic!("expected a pairwise ballot");
+        };
+        assert_info(&ballot.info);
+        assert_eq!((ballot.win, ballot.lose), (1, 2));
+    }
+
+    #[test]
+    fn test_setwise_into_ballot() {
+        let Ballot::Setwise(ballot) = setwise(2, 1).into_ballot("127.0.0.1", "test-agent", 42)
+        else {
+            panic!("expected a setwise ballot");
+        };
+        assert_info(&ballot.info);
+        assert_eq!(ballot.left_set, vec![0, 1]);
+        assert_eq!(ballot.right_set, vec![100]);
+    }
+
+    #[test]
+    fn test_groupwise_into_ballot() {
+        let Ballot::Groupwise(ballot) = groupwise(1, 2).into_ballot("127.0.0.1", "test-agent", 42)
+        else {
+            panic!("expected a groupwise ballot");
+        };
+        assert_info(&ballot.info);
+        assert_eq!(ballot.left_group, vec![0]);
+        assert_eq!(ballot.right_group, vec![100, 101]);
+        assert!(matches!(ballot.selected_group, GroupwiseSelection::Left));
+    }
+
+    #[test]
+    fn test_plurality_into_ballot() {
+        let req = BallotSaveRequest::Plurality(PluralitySaveScore {
+            topic_id: topic("test_topic"),
+            ballot_id: "1-abc".to_string(),
+            candidates: vec![1, 2, 3],
+            selected: 2,
+        });
+
+        let mut ballot = req.into_ballot("127.0.0.1", "test-agent", 42);
+        assert_info(ballot.info());
+        ballot.info_mut().user_id = Some("user".into());
+        assert_eq!(ballot.info().user_id.as_deref(), Some("user"));
+
+        let Ballot::Plurality(ballot) = ballot else {
+            panic!("expected a plurality ballot");
+        };
+        assert_eq!(ballot.candidates, vec![1, 2, 3]);
+        assert_eq!(ballot.selected, 2);
+    }
+
+    #[test]
+    fn test_ballot_save_invalid_operator_id() {
+        let pairwise = |winner, loser| {
+            BallotSaveRequest::Pairwise(PairwiseSaveScore {
+                topic_id: topic("test_topic"),
+                ballot_id: "1-abc".to_string(),
+                winner,
+                loser,
+            })
+        };
+        assert_eq!(pairwise(1, 2).invalid_operator_id(), None);
+        assert_eq!(pairwise(0, 2).invalid_operator_id(), Some(0));
+        assert_eq!(pairwise(1, -5).invalid_operator_id(), Some(-5));
+
+        // The fixtures number the left side from 0.
+        assert_eq!(setwise(2, 1).invalid_operator_id(), Some(0));
+        assert_eq!(groupwise(1, 2).invalid_operator_id(), Some(0));
+
+        let plurality = BallotSaveRequest::Plurality(PluralitySaveScore {
+            topic_id: topic("test_topic"),
+            ballot_id: "1-abc".to_string(),
+            candidates: vec![1, 2, 3],
+            selected: -1,
+        });
+        assert_eq!(plurality.invalid_operator_id(), Some(-1));
+    }
+
+    #[test]
+    fn test_only_pairwise_saves_are_supported() {
+        let pairwise = BallotSaveRequest::Pairwise(PairwiseSaveScore {
+            topic_id: topic("test_topic"),
+            ballot_id: "1-abc".to_string(),
+            winner: 1,
+            loser: 2,
+        });
+        assert!(ApiResponse::<()>::ballot_save_unsupported(&pairwise).is_none());
+
+        for req in [setwise(2, 1), groupwise(1, 2)] {
+            let rsp = ApiResponse::<()>::ballot_save_unsupported(&req).unwrap();
+            assert_eq!(rsp.status, ResponseStatus::Unsupported);
+            assert!(matches!(rsp.message, ApiMsg::UnsupportedTopicType));
+        }
+    }
+
+    #[test]
+    fn test_ballot_create_invalid_operator_id() {
+        let req = |recent_winners| BallotCreateRequest {
+            recent_winners,
+            ..BallotCreateRequest::new(topic("test_topic"))
+        };
+        assert_eq!(req(vec![]).invalid_operator_id(), None);
+        assert_eq!(req(vec![1, 2]).invalid_operator_id(), None);
+        assert_eq!(req(vec![1, 0]).invalid_operator_id(), Some(0));
+        assert_eq!(req(vec![-5, 2]).invalid_operator_id(), Some(-5));
+    }
+
+    #[test]
+    fn test_recent_winners_bounded() {
+        let req: BallotCreateRequest =
+            serde_json::from_str(r#"{"topic_id": "test_topic"}"#).unwrap();
+        assert!(req.recent_winners().is_empty());
+
+        let req = BallotCreateRequest {
+            recent_winners: (0..MAX_RECENT_WINNERS as i32 + 5).collect(),
+            ..BallotCreateRequest::new(topic("test_topic"))
+        };
+        assert_eq!(req.recent_winners().len(), MAX_RECENT_WINNERS);
+        assert_eq!(req.recent_winners()[0], 5);
+    }
+
+    fn operator_catalog() -> (Vec<CharacterInfo>, HashMap<i32, CharacterPortrait>) {
+        // out of id order, with one guard below six stars
+        let mut infos = create_test_characters();
+        infos.reverse();
+        infos[2].rarity = RarityRank::Tier5;
+        let portraits = HashMap::from([(
+            1002,
+            CharacterPortrait {
+                id: 1002,
+                ..Default::default()
+            },
+        )]);
+
+        (infos, portraits)
+    }
+
+    #[test]
+    fn test_ballot_create_embed_portraits() {
+        let (_, portraits) = operator_catalog();
+        let mut rsp = BallotCreateResponse::Pairwise {
+            topic_id: "test_topic".to_string(),
+            ballot_id: "1-abc".to_string(),
+            left: 1002,
+            right: 1001,
+            portraits: None,
+        };
+        assert!(
+            serde_json::to_value(&rsp)
+                .unwrap()
+                .get("portraits")
+                .is_none()
+        );
+
+        rsp.embed_portraits(&portraits);
+        let BallotCreateResponse::Pairwise {
+            portraits: Some(embedded),
+            ..
+        } = &rsp
+        else {
+            panic!("portraits not embedded");
+        };
+        let ids: Vec<i32> = embedded.iter().map(|p| p.id).collect();
+        assert_eq!(ids, [1002]);
+    }
+
+    #[test]
+    fn test_operators_list_filters() {
+        let (infos, portraits) = operator_catalog();
+        let ids = |query: OperatorsListQuery| {
+            OperatorsListResponse::build(&infos, &portraits, &query)
+                .items
+                .iter()
+                .map(|op| op.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(OperatorsListQuery::default()), [1001, 1002, 2001, 3001]);
+        assert_eq!(
+            ids(OperatorsListQuery {
+                rarity: Some(RarityRank::Tier6),
+                profession: Some(ProfessionCategory::WARRIOR),
+                ..Default::default()
+            }),
+            [1001]
+        );
+
+        let response = OperatorsListResponse::build(&infos, &portraits, &Default::default());
+        assert!(response.items[1].portrait.is_some());
+        assert!(response.items[0].portrait.is_none());
+    }
+
+    #[test]
+    fn test_operators_list_pagination() {
+        let (infos, portraits) = operator_catalog();
+        let query = OperatorsListQuery {
+            offset: 1,
+            limit: Some(2),
+            ..Default::default()
+        };
+
+        let response = OperatorsListResponse::build(&infos, &portraits, &query);
+        assert_eq!(response.total, 4);
+        assert_eq!(response.offset, 1);
+        assert_eq!(
+            response.items.iter().map(|op| op.id).collect::<Vec<_>>(),
+            [1002, 2001]
+        );
+
+        let past_end = OperatorsListQuery {
+            offset: 10,
+            ..Default::default()
+        };
+        let response = OperatorsListResponse::build(&infos, &portraits, &past_end);
+        assert_eq!(response.total, 4);
+        assert!(response.items.is_empty());
+    }
+
+    #[test]
+    fn test_matrix_json_keeps_pair_keys() {
+        let response = Results1v1MatrixResponse {
+            items: HashMap::from([(
+                "1:2".to_string(),
+                Results1v1MatrixItem { score: 3, count: 4 },
+            )]),
+            pairs: Vec::new(),
+            computed_at: "2025-08-27T21:00:00Z".parse().unwrap(),
+        };
+
+        let computed_at = response.computed_at;
+        let json = serde_json::to_value(
+            ApiResponse {
+                status: ResponseStatus::Ok,
+                data: ApiData::Data(response),
+                message: ApiMsg::OK,
+            }
+            .timed(|matrix| matrix.computed_at),
+        )
+        .unwrap();
+        assert_eq!(json["computed_at"], "2025-08-27T21:00:00Z");
+        assert_eq!(json["status"], 0);
+        // typed clients read `data` as a map of pair items
+        let data: HashMap<String, Results1v1MatrixItem> =
+            serde_json::from_value(json["data"].clone()).unwrap();
+        assert_eq!(data["1:2"].score, 3);
+        assert_eq!(data.len(), 1);
+
+        let decoded: TimedApiResponse<Results1v1MatrixResponse> =
+            serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.computed_at, Some(computed_at));
+        let ApiData::Data(matrix) = decoded.response.data else {
+            panic!("no matrix data");
+        };
+        assert_eq!(matrix.items.len(), 1);
+
+        let empty = ApiResponse::<Results1v1MatrixResponse> {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        };
+        let json = serde_json::to_value(empty.timed(|matrix| matrix.computed_at)).unwrap();
+        assert!(json.get("computed_at").is_none());
+    }
+
+    #[test]
+    fn test_matrix_pairs_one_per_unordered_pair() {
+        let matrix = HashMap::from([
+            ("1:2".to_string(), 2),
+            ("2:1".to_string(), -2),
+            ("3:9".to_string(), -1),
+            ("9:3".to_string(), 1),
+        ]);
+        let counters = HashMap::from([
+            ("3:9".to_string(), 3),
+            ("1:2".to_string(), 4),
+            ("5:6".to_string(), 0),
+        ]);
+
+        let pairs = Results1v1MatrixPair::from_hashes(&matrix, &counters, 1);
+        assert_eq!(
+            pairs,
+            vec![
+                Results1v1MatrixPair {
+                    a: 1,
+                    b: 2,
+                    a_wins: 3,
+                    b_wins: 1,
+                    total: 4,
+                    net: 2,
+                },
+                Results1v1MatrixPair {
+                    a: 3,
+                    b: 9,
+                    a_wins: 1,
+                    b_wins: 2,
+                    total: 3,
+                    net: -1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_matrix_pairs_json() {
+        let response = Results1v1MatrixResponse {
+            items: HashMap::new(),
+            pairs: vec![Results1v1MatrixPair {
+                a: 1,
+                b: 2,
+                a_wins: 3,
+                b_wins: 1,
+                total: 4,
+                net: 2,
+            }],
+            computed_at: "2025-08-27T21:00:00Z".parse().unwrap(),
+        };
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["pairs"][0]["a_wins"], 3);
+        assert_eq!(json["pairs"][0]["total"], 4);
+
+        // `pairs` is not mistaken for a keyed item
+        let decoded: Results1v1MatrixResponse = serde_json::from_value(json).unwrap();
+        assert!(decoded.items.is_empty());
+        assert_eq!(decoded.pairs, response.pairs);
+
+        let request: Results1v1MatrixRequest =
+            serde_json::from_str(r#"{"topic_id":"test_topic"}"#).unwrap();
+        assert_eq!(request.format, MatrixFormat::Keyed);
+    }
+
+    #[test]
+    fn test_results_by_profession() {
+        let (infos, _) = operator_catalog();
+        let item = |id| FinalOrderItem {
+            name: format!("op_{id}"),
+            id,
+            win: 0,
+            lose: 0,
+            score: "0.00".to_string(),
+            rate: "0.0%".to_string(),
+            tier: None,
+            score_value: 0.0,
+            rate_value: 0.0,
+        };
+        let final_order = ResultsFinalOrderResponse {
+            topic_id: "test_topic".to_string(),
+            items: [2001, 9, 1002, 3001, 1001].into_iter().map(item).collect(),
+            count: 42,
+            ranking_method: RankingMethod::Rate,
+            computed_at: Utc::now(),
+        };
+        let ids = |limit| {
+            ResultsByProfessionResponse::build(&final_order, &infos, limit)
+                .professions
+                .into_iter()
+                .map(|(profession, items)| {
+                    (profession, items.iter().map(|i| i.id).collect::<Vec<_>>())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ids(None),
+            [
+                ("CASTER".to_string(), vec![2001]),
+                (OTHER_PROFESSION.to_string(), vec![9]),
+                ("PIONEER".to_string(), vec![3001]),
+                ("WARRIOR".to_string(), vec![1002, 1001]),
+            ]
+        );
+        assert_eq!(ids(Some(1))[3], ("WARRIOR".to_string(), vec![1002]));
+        assert_eq!(
+            ResultsByProfessionResponse::build(&final_order, &infos, None).count,
+            42
+        );
+    }
+
+    #[test]
+    fn test_ballot_record_from_stored_ballot() {
+        let stored: StoredBallot = serde_json::from_str(
+            r#"{
+                "topic_type": "pairwise",
+                "info": {
+                    "topic_id": "test_topic",
+                    "ballot_id": "1-abc",
+                    "ip": "127.0.0.1",
+                    "user_agent": "test",
+                    "timestamp": 1700000000000,
+                    "throttled": true
+                },
+                "win": 2,
+                "lose": 3,
+                "multiplier": 0
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            BallotRecord::from(&stored),
+            BallotRecord {
+                topic_type: VotingTopicType::Pairwise,
+                win: Some(2),
+                lose: Some(3),
+                multiplier: 0,
+                timestamp: 1700000000000,
+                throttled: true,
+            }
+        );
+    }
 }
@@ -1,9 +1,43 @@
+use std::collections::HashSet;
+
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::models::excel::{CharacterInfo, ProfessionCategory, RarityRank};
+use crate::models::excel::{
+    CharacterInfo, ProfessionCategory, RarityRank, first_invalid_operator_id,
+};
+
+/// Default deepest nesting of union/intersection/difference presets, see
+/// `vote.max_preset_depth`. Resolving recurses once per level.
+pub const MAX_PRESET_DEPTH: usize = 8;
+
+/// Fewest operators a pool needs to build a ballot from.
+pub const MIN_POOL_SIZE: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PoolPresetError {
+    #[error("preset is nested more than {max} levels deep")]
+    TooDeep { max: usize },
+    #[error("preset has an empty selection list or an inverted rarity range")]
+    Malformed,
+    #[error("unknown operator ids: {0:?}")]
+    UnknownOperators(Vec<i32>),
+    #[error("preset resolves to {0} operators, at least {MIN_POOL_SIZE} are required")]
+    TooFewOperators(usize),
+}
+
+/// Why a topic has no candidate pool to draw ballots or results from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum CandidatePoolError {
+    #[error("topic not found")]
+    TopicNotFound,
+    /// The topic exists but its preset matches no operators, e.g. a filter
+    /// that excludes everyone.
+    #[error("candidate pool is empty")]
+    Empty,
+}
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct CandidatePoolPresetFilter {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rarities: Option<Vec<RarityRank>>,
@@ -27,7 +61,7 @@ pub struct CandidatePoolPresetFilter {
     pub max_rarity: Option<RarityRank>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(tag = "type", content = "params")]
 pub enum CandidatePoolPreset {
     #[serde(rename = "all")]
@@ -70,10 +104,154 @@ pub enum CandidatePoolPreset {
 }
 
 impl CandidatePoolPreset {
-    pub fn generate_pool(&self, character_infos: &[CharacterInfo]) -> Vec<i32> {
-        use std::collections::HashSet;
+    /// Structural check that the preset can select anyone at all: lists are
+    /// not empty, rarity bounds are ordered and nested presets are valid.
+    /// Presets nested more than `max_depth` levels are invalid, the check
+    /// never recurses past it.
+    pub fn is_valid(&self, max_depth: usize) -> bool {
+        let Some(remaining) = max_depth.checked_sub(1) else {
+            return false;
+        };
+
+        match self {
+            Self::All => true,
+            Self::Custom { operator_ids } => !operator_ids.is_empty(),
+            Self::ByRarity { rarities, .. } => !rarities.is_empty(),
+            Self::ByProfession { professions } => !professions.is_empty(),
+            Self::BySubProfession { sub_professions } => !sub_professions.is_empty(),
+            Self::Filter(filter) => match (filter.min_rarity, filter.max_rarity) {
+                (Some(min), Some(max)) => min.to_numeric() <= max.to_numeric(),
+                _ => true,
+            },
+            Self::Union { presets } | Self::Intersection { presets } => {
+                !presets.is_empty() && presets.iter().all(|preset| preset.is_valid(remaining))
+            }
+            Self::Difference { base, exclude } => {
+                base.is_valid(remaining) && exclude.is_valid(remaining)
+            }
+        }
+    }
+
+    /// Whether presets are nested more than `max_depth` levels, a preset
+    /// without nested presets being one level. Never recurses past `max_depth`.
+    pub fn exceeds_depth(&self, max_depth: usize) -> bool {
+        let Some(remaining) = max_depth.checked_sub(1) else {
+            return true;
+        };
 
         match self {
+            Self::Union { presets } | Self::Intersection { presets } => {
+                presets.iter().any(|preset| preset.exceeds_depth(remaining))
+            }
+            Self::Difference { base, exclude } => {
+                base.exceeds_depth(remaining) || exclude.exceeds_depth(remaining)
+            }
+            _ => false,
+        }
+    }
+
+    /// The first non-positive id listed in a `Custom` preset, at any depth.
+    pub fn invalid_operator_id(&self) -> Option<i32> {
+        match self {
+            Self::Custom { operator_ids } => first_invalid_operator_id(operator_ids),
+            Self::Union { presets } | Self::Intersection { presets } => {
+                presets.iter().find_map(Self::invalid_operator_id)
+            }
+            Self::Difference { base, exclude } => base
+                .invalid_operator_id()
+                .or_else(|| exclude.invalid_operator_id()),
+            _ => None,
+        }
+    }
+
+    /// Ids listed in `Custom` presets, at any depth, that are not in
+    /// `character_infos`. Sorted and deduplicated.
+    pub fn unknown_operator_ids(&self, character_infos: &[CharacterInfo]) -> Vec<i32> {
+        fn collect(preset: &CandidatePoolPreset, known: &HashSet<i32>, unknown: &mut Vec<i32>) {
+            match preset {
+                CandidatePoolPreset::Custom { operator_ids } => {
+                    unknown.extend(operator_ids.iter().filter(|id| !known.contains(*id)));
+                }
+                CandidatePoolPreset::Union { presets }
+                | CandidatePoolPreset::Intersection { presets } => {
+                    presets.iter().for_each(|p| collect(p, known, unknown));
+                }
+                CandidatePoolPreset::Difference { base, exclude } => {
+                    collect(base, known, unknown);
+                    collect(exclude, known, unknown);
+                }
+                _ => {}
+            }
+        }
+
+        let known: HashSet<i32> = character_infos.iter().map(|c| c.id).collect();
+        let mut unknown = Vec::new();
+        collect(self, &known, &mut unknown);
+        unknown.sort_unstable();
+        unknown.dedup();
+        unknown
+    }
+
+    /// Checks a client supplied preset and returns the pool `resolve` builds
+    /// from it, or every problem found. A preset nested deeper than
+    /// `max_depth` is rejected before anything else walks it.
+    pub fn validate_pool(
+        &self,
+        character_infos: &[CharacterInfo],
+        max_depth: usize,
+        resolve: impl FnOnce(&Self) -> Vec<i32>,
+    ) -> Result<Vec<i32>, Vec<PoolPresetError>> {
+        if self.exceeds_depth(max_depth) {
+            return Err(vec![PoolPresetError::TooDeep { max: max_depth }]);
+        }
+
+        let mut errors = Vec::new();
+        if !self.is_valid(max_depth) {
+            errors.push(PoolPresetError::Malformed);
+        }
+        let unknown = self.unknown_operator_ids(character_infos);
+        if !unknown.is_empty() {
+            errors.push(PoolPresetError::UnknownOperators(unknown));
+        }
+
+        let pool = resolve(self);
+        if pool.len() < MIN_POOL_SIZE {
+            errors.push(PoolPresetError::TooFewOperators(pool.len()));
+        }
+
+        match errors.is_empty() {
+            true => Ok(pool),
+            false => Err(errors),
+        }
+    }
+
+    /// Resolves the preset against `character_infos`, nested presets at most
+    /// `MAX_PRESET_DEPTH` levels deep.
+    pub fn generate_pool(&self, character_infos: &[CharacterInfo]) -> Vec<i32> {
+        self.generate_pool_with_depth(character_infos, MAX_PRESET_DEPTH)
+    }
+
+    /// Like `generate_pool`, but a preset nested deeper than `max_depth`
+    /// resolves to an empty pool instead of recursing further.
+    pub fn generate_pool_with_depth(
+        &self,
+        character_infos: &[CharacterInfo],
+        max_depth: usize,
+    ) -> Vec<i32> {
+        self.resolve(character_infos, max_depth).unwrap_or_else(|| {
+            tracing::warn!(
+                "candidate pool preset is nested more than {max_depth} levels deep, \
+                 resolving to an empty pool"
+            );
+            Vec::new()
+        })
+    }
+
+    /// `None` once more than `remaining` levels have been entered.
+    fn resolve(&self, character_infos: &[CharacterInfo], remaining: usize) -> Option<Vec<i32>> {
+        let remaining = remaining.checked_sub(1)?;
+
+        let pool = match self {
             Self::All => character_infos.iter().map(|c| c.id).collect(),
 
             Self::Custom { operator_ids } => {
@@ -169,7 +347,7 @@ impl CandidatePoolPreset {
             Self::Union { presets } => {
                 let mut result_set = HashSet::new();
                 for preset in presets {
-                    let pool = preset.generate_pool(character_infos);
+                    let pool = preset.resolve(character_infos, remaining)?;
                     result_set.extend(pool);
                 }
                 result_set.into_iter().collect()
@@ -177,17 +355,19 @@ impl CandidatePoolPreset {
 
             Self::Intersection { presets } => {
                 if presets.is_empty() {
-                    return Vec::new();
+                    return Some(Vec::new());
                 }
 
                 let mut result_set: HashSet<i32> = presets[0]
-                    .generate_pool(character_infos)
+                    .resolve(character_infos, remaining)?
                     .into_iter()
                     .collect();
 
                 for preset in &presets[1..] {
-                    let pool: HashSet<i32> =
-                        preset.generate_pool(character_infos).into_iter().collect();
+                    let pool: HashSet<i32> = preset
+                        .resolve(character_infos, remaining)?
+                        .into_iter()
+                        .collect();
                     result_set = result_set.intersection(&pool).copied().collect();
                 }
 
@@ -195,58 +375,30 @@ impl CandidatePoolPreset {
             }
 
             Self::Difference { base, exclude } => {
-                let base_pool: HashSet<i32> =
-                    base.generate_pool(character_infos).into_iter().collect();
-                let exclude_pool: HashSet<i32> =
-                    exclude.generate_pool(character_infos).into_iter().collect();
+                let base_pool: HashSet<i32> = base
+                    .resolve(character_infos, remaining)?
+                    .into_iter()
+                    .collect();
+                let exclude_pool: HashSet<i32> = exclude
+                    .resolve(character_infos, remaining)?
+                    .into_iter()
+                    .collect();
 
                 base_pool.difference(&exclude_pool).copied().collect()
             }
-        }
+        };
+
+        Some(pool)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::excel::{ProfessionCategory, RarityRank};
-
-    fn create_test_characters() -> Vec<CharacterInfo> {
-        vec![
-            CharacterInfo {
-                id: 1001,
-                name: "AAAA".to_string(),
-                rarity: RarityRank::Tier6,
-                profession: ProfessionCategory::WARRIOR,
-                sub_profession_id: "centurion".to_string(),
-                is_not_obtainable: false,
-            },
-            CharacterInfo {
-                id: 1002,
-                name: "BBBB".to_string(),
-                rarity: RarityRank::Tier6,
-                profession: ProfessionCategory::WARRIOR,
-                sub_profession_id: "sword".to_string(),
-                is_not_obtainable: false,
-            },
-            CharacterInfo {
-                id: 2001,
-                name: "CCCC".to_string(),
-                rarity: RarityRank::Tier6,
-                profession: ProfessionCategory::CASTER,
-                sub_profession_id: "aoedamage".to_string(),
-                is_not_obtainable: false,
-            },
-            CharacterInfo {
-                id: 3001,
-                name: "DDDD".to_string(),
-                rarity: RarityRank::Tier6,
-                profession: ProfessionCategory::PIONEER,
-                sub_profession_id: "pioneer".to_string(),
-                is_not_obtainable: false,
-            },
-        ]
-    }
+    use crate::{
+        models::excel::{ProfessionCategory, RarityRank},
+        test_util::create_test_characters,
+    };
 
     #[test]
     fn test_serialize_deserialize() {
@@ -309,4 +461,98 @@ mod tests {
         .generate_pool(&characters);
         assert_eq!(intersection_pool.len(), 2);
     }
+
+    fn nested(depth: usize) -> CandidatePoolPreset {
+        (1..depth).fold(CandidatePoolPreset::All, |inner, _| {
+            CandidatePoolPreset::Union {
+                presets: vec![inner],
+            }
+        })
+    }
+
+    fn validate(preset: &CandidatePoolPreset) -> Result<Vec<i32>, Vec<PoolPresetError>> {
+        let characters = create_test_characters();
+        preset.validate_pool(&characters, MAX_PRESET_DEPTH, |p| {
+            p.generate_pool(&characters)
+        })
+    }
+
+    #[test]
+    fn test_validate_pool_depth() {
+        assert!(!nested(MAX_PRESET_DEPTH).exceeds_depth(MAX_PRESET_DEPTH));
+        assert!(nested(MAX_PRESET_DEPTH).is_valid(MAX_PRESET_DEPTH));
+        assert!(!nested(MAX_PRESET_DEPTH + 1).is_valid(MAX_PRESET_DEPTH));
+        assert_eq!(validate(&nested(MAX_PRESET_DEPTH)).unwrap().len(), 4);
+
+        assert_eq!(
+            validate(&nested(MAX_PRESET_DEPTH + 1)),
+            Err(vec![PoolPresetError::TooDeep {
+                max: MAX_PRESET_DEPTH
+            }])
+        );
+
+        let deep_difference = CandidatePoolPreset::Difference {
+            base: Box::new(CandidatePoolPreset::All),
+            exclude: Box::new(nested(MAX_PRESET_DEPTH)),
+        };
+        assert!(matches!(
+            validate(&deep_difference).unwrap_err()[..],
+            [PoolPresetError::TooDeep { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_validate_pool_invalid() {
+        let unknown = CandidatePoolPreset::Union {
+            presets: vec![
+                CandidatePoolPreset::Custom {
+                    operator_ids: vec![1001, 9999, 1002],
+                },
+                CandidatePoolPreset::Custom {
+                    operator_ids: vec![9999, 42],
+                },
+            ],
+        };
+        assert_eq!(
+            validate(&unknown),
+            Err(vec![PoolPresetError::UnknownOperators(vec![42, 9999])])
+        );
+
+        let single = CandidatePoolPreset::Custom {
+            operator_ids: vec![3001],
+        };
+        assert_eq!(
+            validate(&single),
+            Err(vec![PoolPresetError::TooFewOperators(1)])
+        );
+
+        let empty = CandidatePoolPreset::Intersection { presets: vec![] };
+        assert_eq!(
+            validate(&empty),
+            Err(vec![
+                PoolPresetError::Malformed,
+                PoolPresetError::TooFewOperators(0)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_generate_pool_depth_limit() {
+        let characters = create_test_characters();
+
+        assert_eq!(nested(3).generate_pool_with_depth(&characters, 3).len(), 4);
+        assert!(
+            nested(4)
+                .generate_pool_with_depth(&characters, 3)
+                .is_empty()
+        );
+
+        // far deeper than the stack would allow without the bound; dropped
+        // iteratively so the test itself does not overflow
+        let mut deep = nested(100_000);
+        assert!(deep.generate_pool(&characters).is_empty());
+        while let CandidatePoolPreset::Union { presets } = &mut deep {
+            deep = presets.pop().unwrap_or(CandidatePoolPreset::All);
+        }
+    }
 }
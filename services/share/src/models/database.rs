@@ -5,11 +5,18 @@ use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::models::candidate_pool_preset::CandidatePoolPreset;
+use crate::{
+    config::{BALLOT_CODE_LENGTHS, BallotSizeLimits},
+    models::{
+        candidate_pool_preset::CandidatePoolPreset,
+        excel::CharacterInfo,
+        topic_id::{TopicId, TopicIdError},
+    },
+};
 
-use super::api::BallotSaveRequest;
+use super::api::{BallotSaveRequest, GroupwiseSelection};
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub enum VotingTopicType {
     Pairwise,  // 两两对比
     Setwise,   // 集合对比
@@ -35,6 +42,57 @@ impl VotingTopicType {
     pub fn supports_1v1_matrix(&self) -> bool {
         matches!(self, VotingTopicType::Pairwise)
     }
+
+    /// Fewest operators a candidate pool needs to fill one ballot: a pair, a
+    /// full set, two disjoint groups or two candidates.
+    pub fn min_operators(&self, limits: &BallotSizeLimits) -> usize {
+        match self {
+            VotingTopicType::Pairwise | VotingTopicType::Plurality => 2,
+            VotingTopicType::Setwise => limits.max_set_size.max(2),
+            VotingTopicType::Groupwise => 2 * limits.max_group_size.max(1),
+        }
+    }
+
+    /// Checked when a ballot is created, before any operator is selected.
+    pub fn check_operator_count(
+        &self,
+        available: usize,
+        limits: &BallotSizeLimits,
+    ) -> Result<(), InsufficientOperators> {
+        let required = self.min_operators(limits);
+        if available >= required {
+            return Ok(());
+        }
+        Err(InsufficientOperators {
+            topic_type: self.clone(),
+            required,
+            available,
+        })
+    }
+}
+
+/// A candidate pool too small for one ballot of its topic's type.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub struct InsufficientOperators {
+    pub topic_type: VotingTopicType,
+    pub required: usize,
+    pub available: usize,
+}
+
+impl std::fmt::Display for InsufficientOperators {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self.topic_type {
+            VotingTopicType::Pairwise => "a pair",
+            VotingTopicType::Setwise => "a full set",
+            VotingTopicType::Groupwise => "two groups",
+            VotingTopicType::Plurality => "two candidates",
+        };
+        write!(
+            f,
+            "{:?} topics need at least {} operators for {}, the candidate pool has {}",
+            self.topic_type, self.required, reason, self.available
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -86,33 +144,381 @@ pub struct VotingTopic {
 
     pub is_active: bool,
     pub status: CreateTopicStatus,
+    #[serde(default, skip_serializing_if = "TopicVoteOverrides::is_empty")]
+    pub vote_overrides: TopicVoteOverrides,
+    /// Pool resolved from `candidate_pool` when the topic became active, used
+    /// instead of resolving the preset again so the operator set stays stable.
+    /// Only set with `vote.freeze_candidate_pools`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frozen_pool: Option<Vec<i32>>,
+    /// Set by admins to stop accepting ballots during an incident without
+    /// closing the topic. Results stay readable, see `accepts_ballots`.
+    #[serde(default)]
+    pub paused: bool,
+}
+
+/// Per-topic overrides of the IP counter settings in `VoteConfig`, unset
+/// fields use the global value. See `TopicVoteOverrides::resolve`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TopicVoteOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_ip_limit: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_multiplier: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low_multiplier: Option<i32>,
+    /// Overrides `vote.ballot_store_sample_rate`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ballot_store_sample_rate: Option<u64>,
+    /// Overrides `vote.ballot_code_length`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ballot_code_length: Option<usize>,
+    /// Overrides `vote.selection_seed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection_seed: Option<u64>,
+}
+
+impl TopicVoteOverrides {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopicWindowState {
+    Inactive,
+    NotYetOpen,
+    Open,
+    Closed,
 }
 
 impl VotingTopic {
     pub fn is_topic_active(&self) -> bool {
-        self.is_active
-            && self.open_time <= chrono::Utc::now()
-            && self.close_time >= chrono::Utc::now()
+        self.window_state() == TopicWindowState::Open
+    }
+
+    /// Whether ballots can be created and saved: the window is open and the
+    /// topic is not paused.
+    pub fn accepts_ballots(&self) -> bool {
+        self.is_topic_active() && !self.paused
+    }
+
+    pub fn window_state(&self) -> TopicWindowState {
+        self.window_state_at(Utc::now())
+    }
+
+    pub fn window_state_at(&self, now: DateTime<Utc>) -> TopicWindowState {
+        if !self.is_active {
+            TopicWindowState::Inactive
+        } else if now < self.open_time {
+            TopicWindowState::NotYetOpen
+        } else if now > self.close_time {
+            TopicWindowState::Closed
+        } else {
+            TopicWindowState::Open
+        }
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+impl VotingTopic {
+    /// The frozen pool when there is one, otherwise the preset resolved
+    /// against `character_infos`.
+    pub fn resolve_pool(&self, character_infos: &[CharacterInfo], max_depth: usize) -> Vec<i32> {
+        match &self.frozen_pool {
+            Some(pool) => pool.clone(),
+            None => self
+                .candidate_pool
+                .generate_pool_with_depth(character_infos, max_depth),
+        }
+    }
+
+    /// Resolves the preset into `frozen_pool`, replacing any earlier
+    /// snapshot.
+    pub fn freeze_pool(&mut self, character_infos: &[CharacterInfo], max_depth: usize) {
+        self.frozen_pool = Some(
+            self.candidate_pool
+                .generate_pool_with_depth(character_infos, max_depth),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum TopicValidationError {
+    #[error(transparent)]
+    InvalidId(#[from] TopicIdError),
+    #[error("topic open time must be before close time")]
+    InvalidWindow,
+    #[error("topic candidate pool is invalid")]
+    InvalidCandidatePool,
+    #[error("topic candidate pool is nested more than {max} levels deep")]
+    CandidatePoolTooDeep { max: usize },
+    #[error("topic candidate pool lists invalid operator id {0}")]
+    InvalidOperatorId(i32),
+    #[error("topic ballot code length {0} is out of range")]
+    InvalidBallotCodeLength(usize),
+}
+
+impl VotingTopic {
+    /// Checks applied to every topic before it is persisted, both for
+    /// `/topic/create` and the presets from the config file. The candidate
+    /// pool may nest at most `max_preset_depth` levels, see
+    /// `vote.max_preset_depth`; it is checked before the pool is walked.
+    pub fn validate(&self, max_preset_depth: usize) -> Result<(), TopicValidationError> {
+        TopicId::parse(self.id.as_str())?;
+        if self.open_time >= self.close_time {
+            return Err(TopicValidationError::InvalidWindow);
+        }
+        if self.candidate_pool.exceeds_depth(max_preset_depth) {
+            return Err(TopicValidationError::CandidatePoolTooDeep {
+                max: max_preset_depth,
+            });
+        }
+        if !self.candidate_pool.is_valid(max_preset_depth) {
+            return Err(TopicValidationError::InvalidCandidatePool);
+        }
+        if let Some(id) = self.candidate_pool.invalid_operator_id() {
+            return Err(TopicValidationError::InvalidOperatorId(id));
+        }
+        if let Some(length) = self.vote_overrides.ballot_code_length
+            && !BALLOT_CODE_LENGTHS.contains(&length)
+        {
+            return Err(TopicValidationError::InvalidBallotCodeLength(length));
+        }
+
+        Ok(())
+    }
+
+    /// Whether `other` holds the same `/topic/create` request content, telling
+    /// a retried creation apart from a different topic reusing the id. Fields
+    /// set by the service (creation time, activation, audit) are ignored and
+    /// the window is compared to the millisecond MongoDB stores.
+    pub fn same_creation_content(&self, other: &VotingTopic) -> bool {
+        self.id == other.id
+            && self.name == other.name
+            && self.title == other.title
+            && self.description == other.description
+            && self.topic_type == other.topic_type
+            && self.candidate_pool == other.candidate_pool
+            && self.open_time.timestamp_millis() == other.open_time.timestamp_millis()
+            && self.close_time.timestamp_millis() == other.close_time.timestamp_millis()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopicReopenError {
+    NotApproved,
+    CloseTimeInPast,
+    InvalidWindow,
+}
+
+impl VotingTopic {
+    /// Reactivates the topic with a new voting window. Only approved topics can
+    /// be reopened and the new window has to end after `now`; `open_time`
+    /// defaults to the current one.
+    pub fn reopen(
+        &mut self,
+        open_time: Option<DateTime<Utc>>,
+        close_time: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<(), TopicReopenError> {
+        if !matches!(self.status, CreateTopicStatus::Approved(_)) {
+            return Err(TopicReopenError::NotApproved);
+        }
+        if close_time <= now {
+            return Err(TopicReopenError::CloseTimeInPast);
+        }
+
+        let open_time = open_time.unwrap_or(self.open_time);
+        if open_time >= close_time {
+            return Err(TopicReopenError::InvalidWindow);
+        }
+
+        self.open_time = open_time;
+        self.close_time = close_time;
+        self.is_active = true;
+
+        Ok(())
+    }
+}
+
+/// Editable metadata of an existing topic, `None` keeps the current value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct TopicMetadataUpdate {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub candidate_pool: Option<CandidatePoolPreset>,
+    #[serde(default)]
+    pub open_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub close_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopicUpdateError {
+    Invalid(TopicValidationError),
+    /// The topic already has votes, which were scored against the old pool.
+    CandidatePoolLocked,
+}
+
+impl VotingTopic {
+    /// Applies `update` and returns the names of the fields that changed. The
+    /// result goes through `validate`; the candidate pool can only change
+    /// while the topic has no votes.
+    pub fn apply_update(
+        &mut self,
+        update: TopicMetadataUpdate,
+        has_votes: bool,
+        max_preset_depth: usize,
+    ) -> Result<Vec<String>, TopicUpdateError> {
+        let mut updated = self.clone();
+        let mut changed = Vec::new();
+
+        fn set<T: PartialEq>(
+            field: &mut T,
+            value: Option<T>,
+            name: &str,
+            changed: &mut Vec<String>,
+        ) {
+            if let Some(value) = value
+                && *field != value
+            {
+                *field = value;
+                changed.push(name.to_string());
+            }
+        }
+
+        set(&mut updated.name, update.name, "name", &mut changed);
+        set(&mut updated.title, update.title, "title", &mut changed);
+        set(
+            &mut updated.description,
+            update.description,
+            "description",
+            &mut changed,
+        );
+        set(
+            &mut updated.candidate_pool,
+            update.candidate_pool,
+            "candidate_pool",
+            &mut changed,
+        );
+        set(
+            &mut updated.open_time,
+            update.open_time,
+            "open_time",
+            &mut changed,
+        );
+        set(
+            &mut updated.close_time,
+            update.close_time,
+            "close_time",
+            &mut changed,
+        );
+
+        if has_votes && changed.iter().any(|field| field == "candidate_pool") {
+            return Err(TopicUpdateError::CandidatePoolLocked);
+        }
+        updated
+            .validate(max_preset_depth)
+            .map_err(TopicUpdateError::Invalid)?;
+
+        *self = updated;
+        Ok(changed)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub enum TopicAuditAction {
+    Reopen {
+        previous_open_time: DateTime<Utc>,
+        previous_close_time: DateTime<Utc>,
+        previous_is_active: bool,
+        open_time: DateTime<Utc>,
+        close_time: DateTime<Utc>,
+    },
+    Update {
+        fields: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopicAuditLogEntry {
+    pub topic_id: String,
+    pub action: TopicAuditAction,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TopicAuditLogEntry {
+    pub fn reopen(previous: &VotingTopic, reopened: &VotingTopic, now: DateTime<Utc>) -> Self {
+        Self {
+            topic_id: reopened.id.clone(),
+            action: TopicAuditAction::Reopen {
+                previous_open_time: previous.open_time,
+                previous_close_time: previous.close_time,
+                previous_is_active: previous.is_active,
+                open_time: reopened.open_time,
+                close_time: reopened.close_time,
+            },
+            created_at: now,
+        }
+    }
+
+    pub fn update(topic: &VotingTopic, fields: Vec<String>, now: DateTime<Utc>) -> Self {
+        Self {
+            topic_id: topic.id.clone(),
+            action: TopicAuditAction::Update { fields },
+            created_at: now,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct BallotInfo<'a> {
     pub topic_id: Cow<'a, str>,
     pub ballot_id: Cow<'a, str>,
     pub ip: Cow<'a, str>,
     pub user_agent: Cow<'a, str>,
     pub timestamp: i64,
+    /// Set only from a verified user token, see `share::reputation`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<Cow<'a, str>>,
+    /// Saved sooner than `vote.save_throttle.min_interval_ms` after the
+    /// previous save from the same IP, or before the ballot code was
+    /// `vote.ballot_grace.min_age_ms` old. Counted with a zero multiplier.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub throttled: bool,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+impl BallotInfo<'_> {
+    /// Whether the ballot is among the one in `rate` written to MongoDB, see
+    /// `vote.ballot_store_sample_rate`. Decided by a fixed hash of the ballot
+    /// id, so a retried batch and every instance, whatever it was built with,
+    /// store the same ballots.
+    pub fn is_stored_at(&self, rate: u64) -> bool {
+        fnv1a(self.ballot_id.as_bytes()).is_multiple_of(rate.max(1))
+    }
+}
+
+/// 64 bit FNV-1a. Unlike `DefaultHasher`, its output is the same in every
+/// Rust release.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct PairwiseBallot<'a> {
     pub info: BallotInfo<'a>,
     pub win: i32,
     pub lose: i32,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct SetwiseBallot<'a> {
     pub info: BallotInfo<'a>,
     pub left_set: Vec<i32>,
@@ -121,22 +527,22 @@ pub struct SetwiseBallot<'a> {
     pub selected_right: Vec<i32>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct GroupwiseBallot<'a> {
     pub info: BallotInfo<'a>,
     pub left_group: Vec<i32>,
     pub right_group: Vec<i32>,
-    pub selected_group: super::api::GroupwiseSelection,
+    pub selected_group: GroupwiseSelection,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct PluralityBallot<'a> {
     pub info: BallotInfo<'a>,
     pub candidates: Vec<i32>,
     pub selected: i32,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(tag = "topic_type", rename_all = "snake_case")]
 pub enum Ballot<'a> {
     Pairwise(PairwiseBallot<'a>),
@@ -145,9 +551,785 @@ pub enum Ballot<'a> {
     Plurality(PluralityBallot<'a>),
 }
 
-#[derive(Debug, Serialize)]
+impl<'a> Ballot<'a> {
+    pub fn info(&self) -> &BallotInfo<'a> {
+        match self {
+            Ballot::Pairwise(ballot) => &ballot.info,
+            Ballot::Setwise(ballot) => &ballot.info,
+            Ballot::Groupwise(ballot) => &ballot.info,
+            Ballot::Plurality(ballot) => &ballot.info,
+        }
+    }
+
+    pub fn info_mut(&mut self) -> &mut BallotInfo<'a> {
+        match self {
+            Ballot::Pairwise(ballot) => &mut ballot.info,
+            Ballot::Setwise(ballot) => &mut ballot.info,
+            Ballot::Groupwise(ballot) => &mut ballot.info,
+            Ballot::Plurality(ballot) => &mut ballot.info,
+        }
+    }
+
+    /// The distinct operators shown in this ballot, sorted. Ballot codes are
+    /// issued for these, stored comma separated (`left,right` for pairwise).
+    pub fn operators(&self) -> Vec<i32> {
+        let mut operators: Vec<i32> = match self {
+            Ballot::Pairwise(ballot) => vec![ballot.win, ballot.lose],
+            Ballot::Setwise(ballot) => [&ballot.left_set[..], &ballot.right_set[..]].concat(),
+            Ballot::Groupwise(ballot) => [&ballot.left_group[..], &ballot.right_group[..]].concat(),
+            Ballot::Plurality(ballot) => ballot.candidates.clone(),
+        };
+        operators.sort_unstable();
+        operators.dedup();
+        operators
+    }
+
+    /// Every pair of distinct operators shown together in this ballot, as
+    /// `(min, max)`, whatever the outcome. These are the fields of the
+    /// `<topic_id>:op_cooccur` hash, keyed `min:max` like `op_counter`.
+    pub fn cooccurrence_pairs(&self) -> Vec<(i32, i32)> {
+        let operators = self.operators();
+
+        let mut pairs = Vec::with_capacity(operators.len() * operators.len().saturating_sub(1) / 2);
+        for (i, &a) in operators.iter().enumerate() {
+            for &b in &operators[i + 1..] {
+                pairs.push((a, b));
+            }
+        }
+        pairs
+    }
+
+    /// The comparisons this ballot stands for, as `(winner, loser)` pairs fed
+    /// to the same score update as pairwise ballots:
+    /// - setwise: within each set, every selected operator beats every
+    ///   unselected one
+    /// - groupwise: every operator of the selected group beats every operator
+    ///   of the other group
+    /// - plurality: the selected candidate beats every other candidate
+    ///
+    /// Selections outside the ballot's operators are ignored.
+    pub fn score_pairs(&self) -> Vec<(i32, i32)> {
+        fn beats(winners: &[i32], losers: &[i32], pairs: &mut Vec<(i32, i32)>) {
+            for &winner in winners {
+                pairs.extend(
+                    losers
+                        .iter()
+                        .filter(|&&loser| loser != winner)
+                        .map(|&loser| (winner, loser)),
+                );
+            }
+        }
+        fn split(set: &[i32], selected: &[i32]) -> (Vec<i32>, Vec<i32>) {
+            set.iter().partition(|&&id| selected.contains(&id))
+        }
+
+        let mut pairs = Vec::new();
+        match self {
+            Ballot::Pairwise(ballot) => beats(&[ballot.win], &[ballot.lose], &mut pairs),
+            Ballot::Setwise(ballot) => {
+                for (set, selected) in [
+                    (&ballot.left_set, &ballot.selected_left),
+                    (&ballot.right_set, &ballot.selected_right),
+                ] {
+                    let (winners, losers) = split(set, selected);
+                    beats(&winners, &losers, &mut pairs);
+                }
+            }
+            Ballot::Groupwise(ballot) => {
+                let (winners, losers) = match ballot.selected_group {
+                    GroupwiseSelection::Left => (&ballot.left_group, &ballot.right_group),
+                    GroupwiseSelection::Right => (&ballot.right_group, &ballot.left_group),
+                };
+                beats(winners, losers, &mut pairs);
+            }
+            Ballot::Plurality(ballot) => {
+                if ballot.candidates.contains(&ballot.selected) {
+                    beats(&[ballot.selected], &ballot.candidates, &mut pairs);
+                }
+            }
+        }
+        pairs.sort_unstable();
+        pairs.dedup();
+        pairs
+    }
+
+    /// `score_pairs` with `multiplier` split between them, so a ballot weighs
+    /// as much as one pairwise ballot however many comparisons it stands for.
+    /// The remainder goes to the first pairs. A negative multiplier is kept
+    /// whole on every pair for the score update to refuse them.
+    pub fn weighted_score_pairs(&self, multiplier: i32) -> Vec<((i32, i32), i32)> {
+        let pairs = self.score_pairs();
+        let count = pairs.len() as i32;
+        if multiplier < 0 || count == 0 {
+            return pairs.into_iter().map(|pair| (pair, multiplier)).collect();
+        }
+
+        let (share, remainder) = (multiplier / count, multiplier % count);
+        pairs
+            .into_iter()
+            .zip(0..)
+            .map(|(pair, i)| (pair, share + i32::from(i < remainder)))
+            .collect()
+    }
+}
+
+/// A ballot as stored in the `ballots_<topic_id>` collections, the ballot
+/// fields and its `topic_type` tag sit next to `multiplier` at the top level.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct StoredBallot<'a> {
     #[serde(flatten)]
     pub ballot: Ballot<'a>,
     pub multiplier: i32,
 }
+
+impl StoredBallot<'_> {
+    /// Path of the ballot id in stored documents, indexed on every
+    /// `ballots_<topic_id>` collection.
+    pub const BALLOT_ID_FIELD: &'static str = "info.ballot_id";
+    /// Path of the cast time in stored documents, indexed for the time range
+    /// matches of the results endpoints.
+    pub const TIMESTAMP_FIELD: &'static str = "info.timestamp";
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::{models::candidate_pool_preset::MAX_PRESET_DEPTH, test_util::test_topic};
+
+    fn create_test_topic(now: DateTime<Utc>) -> VotingTopic {
+        test_topic(
+            "test_topic",
+            now - Duration::hours(1),
+            now + Duration::hours(1),
+        )
+    }
+
+    #[test]
+    fn test_window_state_before_open() {
+        let now = Utc::now();
+        let topic = create_test_topic(now);
+        assert_eq!(
+            topic.window_state_at(now - Duration::hours(2)),
+            TopicWindowState::NotYetOpen
+        );
+    }
+
+    #[test]
+    fn test_window_state_open() {
+        let now = Utc::now();
+        let topic = create_test_topic(now);
+        assert_eq!(topic.window_state_at(now), TopicWindowState::Open);
+        assert_eq!(
+            topic.window_state_at(topic.open_time),
+            TopicWindowState::Open
+        );
+        assert_eq!(
+            topic.window_state_at(topic.close_time),
+            TopicWindowState::Open
+        );
+    }
+
+    #[test]
+    fn test_window_state_after_close() {
+        let now = Utc::now();
+        let topic = create_test_topic(now);
+        assert_eq!(
+            topic.window_state_at(now + Duration::hours(2)),
+            TopicWindowState::Closed
+        );
+    }
+
+    #[test]
+    fn test_window_state_inactive() {
+        let now = Utc::now();
+        let mut topic = create_test_topic(now);
+        topic.is_active = false;
+        assert_eq!(topic.window_state_at(now), TopicWindowState::Inactive);
+    }
+
+    #[test]
+    fn test_paused_topic_rejects_ballots() {
+        let now = Utc::now();
+        let mut topic = create_test_topic(now);
+        assert!(topic.accepts_ballots());
+
+        topic.paused = true;
+        assert!(!topic.accepts_ballots());
+        // pausing leaves the window alone, results and listings still see an
+        // active topic
+        assert!(topic.is_topic_active());
+        assert_eq!(topic.window_state_at(now), TopicWindowState::Open);
+
+        let mut value = serde_json::to_value(create_test_topic(now)).unwrap();
+        value.as_object_mut().unwrap().remove("paused");
+        let stored: VotingTopic = serde_json::from_value(value).unwrap();
+        assert!(!stored.paused);
+    }
+
+    fn approve(topic: &mut VotingTopic, now: DateTime<Utc>) {
+        topic.status = CreateTopicStatus::Approved(TopicAuditInfo {
+            auditor_id: Uuid::nil(),
+            auditor_name: "auditor".to_string(),
+            audit_time: now,
+            audit_reason: String::new(),
+            audit_category: AuditCategory::ContentCompliance,
+        });
+    }
+
+    #[test]
+    fn test_reopen_closed_topic() {
+        let now = Utc::now();
+        let mut topic = create_test_topic(now - Duration::days(1));
+        approve(&mut topic, now);
+        topic.is_active = false;
+
+        let close_time = now + Duration::days(1);
+        assert_eq!(topic.reopen(None, close_time, now), Ok(()));
+        assert!(topic.is_active);
+        assert_eq!(topic.close_time, close_time);
+        assert_eq!(topic.window_state_at(now), TopicWindowState::Open);
+    }
+
+    #[test]
+    fn test_reopen_not_approved() {
+        let now = Utc::now();
+        let mut topic = create_test_topic(now);
+        assert_eq!(
+            topic.reopen(None, now + Duration::days(1), now),
+            Err(TopicReopenError::NotApproved)
+        );
+    }
+
+    #[test]
+    fn test_reopen_close_time_in_past() {
+        let now = Utc::now();
+        let mut topic = create_test_topic(now - Duration::days(1));
+        approve(&mut topic, now);
+        let previous_close_time = topic.close_time;
+
+        assert_eq!(
+            topic.reopen(None, now - Duration::minutes(1), now),
+            Err(TopicReopenError::CloseTimeInPast)
+        );
+        assert_eq!(topic.close_time, previous_close_time);
+    }
+
+    fn ballot_info() -> BallotInfo<'static> {
+        BallotInfo {
+            topic_id: "topic".into(),
+            ballot_id: "ballot".into(),
+            ip: "127.0.0.1".into(),
+            user_agent: "test".into(),
+            timestamp: 1_700_000_000_000,
+            user_id: Some("user".into()),
+            throttled: false,
+        }
+    }
+
+    #[test]
+    fn test_stored_ballot_round_trip() {
+        let ballots = [
+            Ballot::Pairwise(PairwiseBallot {
+                info: ballot_info(),
+                win: 1,
+                lose: 2,
+            }),
+            Ballot::Setwise(SetwiseBallot {
+                info: BallotInfo {
+                    user_id: None,
+                    ..ballot_info()
+                },
+                left_set: vec![1, 2],
+                right_set: vec![3, 4],
+                selected_left: vec![1],
+                selected_right: vec![],
+            }),
+            Ballot::Groupwise(GroupwiseBallot {
+                info: ballot_info(),
+                left_group: vec![1, 2],
+                right_group: vec![3, 4],
+                selected_group: GroupwiseSelection::Right,
+            }),
+            Ballot::Plurality(PluralityBallot {
+                info: BallotInfo {
+                    throttled: true,
+                    ..ballot_info()
+                },
+                candidates: vec![1, 2, 3],
+                selected: 3,
+            }),
+        ];
+
+        for ballot in ballots {
+            let stored = StoredBallot {
+                ballot,
+                multiplier: 100,
+            };
+            let json = serde_json::to_value(&stored).unwrap();
+            assert_eq!(json["multiplier"], 100);
+            assert!(json["topic_type"].is_string());
+
+            let decoded: StoredBallot = serde_json::from_value(json).unwrap();
+            assert_eq!(decoded, stored);
+
+            // the ballots collections hold this shape
+            let document = mongodb::bson::to_document(&stored).unwrap();
+            assert_eq!(document.get_i32("multiplier"), Ok(100));
+            assert!(document.get_str("topic_type").is_ok());
+            assert!(document.get_document("info").is_ok());
+
+            let decoded: StoredBallot = mongodb::bson::from_document(document).unwrap();
+            assert_eq!(decoded, stored);
+        }
+    }
+
+    #[test]
+    fn test_cooccurrence_pairs() {
+        let pairwise = Ballot::Pairwise(PairwiseBallot {
+            info: ballot_info(),
+            win: 7,
+            lose: 3,
+        });
+        assert_eq!(pairwise.cooccurrence_pairs(), [(3, 7)]);
+
+        let setwise = Ballot::Setwise(SetwiseBallot {
+            info: ballot_info(),
+            left_set: vec![3, 1],
+            right_set: vec![2, 3],
+            selected_left: vec![1],
+            selected_right: vec![],
+        });
+        assert_eq!(setwise.cooccurrence_pairs(), [(1, 2), (1, 3), (2, 3)]);
+
+        let plurality = Ballot::Plurality(PluralityBallot {
+            info: ballot_info(),
+            candidates: vec![5],
+            selected: 5,
+        });
+        assert!(plurality.cooccurrence_pairs().is_empty());
+    }
+
+    #[test]
+    fn test_weighted_score_pairs() {
+        let pairwise = Ballot::Pairwise(PairwiseBallot {
+            info: ballot_info(),
+            win: 7,
+            lose: 3,
+        });
+        assert_eq!(pairwise.weighted_score_pairs(100), [((7, 3), 100)]);
+
+        let plurality = Ballot::Plurality(PluralityBallot {
+            info: ballot_info(),
+            candidates: vec![5, 6, 7, 8],
+            selected: 6,
+        });
+        let weighted = plurality.weighted_score_pairs(100);
+        assert_eq!(weighted, [((6, 5), 34), ((6, 7), 33), ((6, 8), 33)]);
+        assert_eq!(weighted.iter().map(|(_, m)| m).sum::<i32>(), 100);
+
+        assert_eq!(
+            plurality.weighted_score_pairs(-1),
+            [((6, 5), -1), ((6, 7), -1), ((6, 8), -1)]
+        );
+        assert_eq!(
+            plurality.weighted_score_pairs(0),
+            [((6, 5), 0), ((6, 7), 0), ((6, 8), 0)]
+        );
+    }
+
+    #[test]
+    fn test_score_pairs_pairwise() {
+        let pairwise = Ballot::Pairwise(PairwiseBallot {
+            info: ballot_info(),
+            win: 7,
+            lose: 3,
+        });
+        assert_eq!(pairwise.score_pairs(), [(7, 3)]);
+    }
+
+    #[test]
+    fn test_score_pairs_setwise() {
+        let setwise = Ballot::Setwise(SetwiseBallot {
+            info: ballot_info(),
+            left_set: vec![1, 2, 3],
+            right_set: vec![4, 5],
+            selected_left: vec![2, 9],
+            selected_right: vec![],
+        });
+        assert_eq!(setwise.score_pairs(), [(2, 1), (2, 3)]);
+    }
+
+    #[test]
+    fn test_score_pairs_groupwise() {
+        let groupwise = Ballot::Groupwise(GroupwiseBallot {
+            info: ballot_info(),
+            left_group: vec![1, 2],
+            right_group: vec![3, 4],
+            selected_group: GroupwiseSelection::Right,
+        });
+        assert_eq!(groupwise.score_pairs(), [(3, 1), (3, 2), (4, 1), (4, 2)]);
+    }
+
+    #[test]
+    fn test_score_pairs_plurality() {
+        let plurality = Ballot::Plurality(PluralityBallot {
+            info: ballot_info(),
+            candidates: vec![5, 6, 7],
+            selected: 6,
+        });
+        assert_eq!(plurality.score_pairs(), [(6, 5), (6, 7)]);
+
+        let unknown = Ballot::Plurality(PluralityBallot {
+            info: ballot_info(),
+            candidates: vec![5, 6],
+            selected: 9,
+        });
+        assert!(unknown.score_pairs().is_empty());
+    }
+
+    #[test]
+    fn test_validate_topic() {
+        let now = Utc::now();
+        assert_eq!(create_test_topic(now).validate(MAX_PRESET_DEPTH), Ok(()));
+
+        let mut topic = create_test_topic(now);
+        topic.id = " ".to_string();
+        assert_eq!(
+            topic.validate(MAX_PRESET_DEPTH),
+            Err(TopicValidationError::InvalidId(
+                TopicIdError::InvalidCharacter(' ')
+            ))
+        );
+
+        let mut topic = create_test_topic(now);
+        topic.open_time = topic.close_time + Duration::hours(1);
+        assert_eq!(
+            topic.validate(MAX_PRESET_DEPTH),
+            Err(TopicValidationError::InvalidWindow)
+        );
+
+        let mut topic = create_test_topic(now);
+        topic.candidate_pool = CandidatePoolPreset::Union {
+            presets: vec![
+                CandidatePoolPreset::All,
+                CandidatePoolPreset::Custom {
+                    operator_ids: vec![],
+                },
+            ],
+        };
+        assert_eq!(
+            topic.validate(MAX_PRESET_DEPTH),
+            Err(TopicValidationError::InvalidCandidatePool)
+        );
+
+        let mut topic = create_test_topic(now);
+        topic.candidate_pool = (0..MAX_PRESET_DEPTH).fold(CandidatePoolPreset::All, |inner, _| {
+            CandidatePoolPreset::Union {
+                presets: vec![inner],
+            }
+        });
+        assert_eq!(
+            topic.validate(MAX_PRESET_DEPTH),
+            Err(TopicValidationError::CandidatePoolTooDeep {
+                max: MAX_PRESET_DEPTH
+            })
+        );
+        assert_eq!(topic.validate(MAX_PRESET_DEPTH + 1), Ok(()));
+
+        let mut topic = create_test_topic(now);
+        topic.vote_overrides.ballot_code_length = Some(128);
+        assert_eq!(
+            topic.validate(MAX_PRESET_DEPTH),
+            Err(TopicValidationError::InvalidBallotCodeLength(128))
+        );
+        topic.vote_overrides.ballot_code_length = Some(16);
+        assert_eq!(topic.validate(MAX_PRESET_DEPTH), Ok(()));
+
+        for id in [0, -5] {
+            let mut topic = create_test_topic(now);
+            topic.candidate_pool = CandidatePoolPreset::Difference {
+                base: Box::new(CandidatePoolPreset::All),
+                exclude: Box::new(CandidatePoolPreset::Custom {
+                    operator_ids: vec![1, id],
+                }),
+            };
+            assert_eq!(
+                topic.validate(MAX_PRESET_DEPTH),
+                Err(TopicValidationError::InvalidOperatorId(id))
+            );
+        }
+    }
+
+    #[test]
+    fn test_reopen_invalid_window() {
+        let now = Utc::now();
+        let mut topic = create_test_topic(now);
+        approve(&mut topic, now);
+
+        assert_eq!(
+            topic.reopen(Some(now + Duration::days(2)), now + Duration::days(1), now),
+            Err(TopicReopenError::InvalidWindow)
+        );
+    }
+
+    #[test]
+    fn test_apply_update_reports_changed_fields() {
+        let now = Utc::now();
+        let mut topic = create_test_topic(now);
+
+        let changed = topic
+            .apply_update(
+                TopicMetadataUpdate {
+                    title: Some("fixed title".to_string()),
+                    name: Some(topic.name.clone()),
+                    close_time: Some(now + Duration::days(1)),
+                    ..Default::default()
+                },
+                true,
+                MAX_PRESET_DEPTH,
+            )
+            .unwrap();
+
+        assert_eq!(changed, ["title", "close_time"]);
+        assert_eq!(topic.title, "fixed title");
+        assert_eq!(topic.close_time, now + Duration::days(1));
+    }
+
+    #[test]
+    fn test_apply_update_locks_candidate_pool_with_votes() {
+        let now = Utc::now();
+        let mut topic = create_test_topic(now);
+        let update = TopicMetadataUpdate {
+            title: Some("fixed title".to_string()),
+            candidate_pool: Some(CandidatePoolPreset::Custom {
+                operator_ids: vec![1, 2],
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            topic.apply_update(update.clone(), true, MAX_PRESET_DEPTH),
+            Err(TopicUpdateError::CandidatePoolLocked)
+        );
+        assert_eq!(topic.title, "Test Title");
+
+        assert!(topic.apply_update(update, false, MAX_PRESET_DEPTH).is_ok());
+        assert_eq!(
+            topic.candidate_pool,
+            CandidatePoolPreset::Custom {
+                operator_ids: vec![1, 2]
+            }
+        );
+    }
+
+    #[test]
+    fn test_apply_update_same_pool_with_votes() {
+        let now = Utc::now();
+        let mut topic = create_test_topic(now);
+
+        let changed = topic
+            .apply_update(
+                TopicMetadataUpdate {
+                    candidate_pool: Some(CandidatePoolPreset::All),
+                    ..Default::default()
+                },
+                true,
+                MAX_PRESET_DEPTH,
+            )
+            .unwrap();
+        assert!(changed.is_empty());
+    }
+
+    #[test]
+    fn test_apply_update_rejects_too_deep_pool() {
+        let now = Utc::now();
+        let mut topic = create_test_topic(now);
+        let deep = (0..MAX_PRESET_DEPTH).fold(CandidatePoolPreset::All, |inner, _| {
+            CandidatePoolPreset::Union {
+                presets: vec![inner],
+            }
+        });
+
+        assert_eq!(
+            topic.apply_update(
+                TopicMetadataUpdate {
+                    candidate_pool: Some(deep),
+                    ..Default::default()
+                },
+                false,
+                MAX_PRESET_DEPTH,
+            ),
+            Err(TopicUpdateError::Invalid(
+                TopicValidationError::CandidatePoolTooDeep {
+                    max: MAX_PRESET_DEPTH
+                }
+            ))
+        );
+        assert_eq!(topic.candidate_pool, CandidatePoolPreset::All);
+    }
+
+    #[test]
+    fn test_apply_update_invalid_window() {
+        let now = Utc::now();
+        let mut topic = create_test_topic(now);
+
+        assert_eq!(
+            topic.apply_update(
+                TopicMetadataUpdate {
+                    open_time: Some(now + Duration::days(2)),
+                    ..Default::default()
+                },
+                false,
+                MAX_PRESET_DEPTH,
+            ),
+            Err(TopicUpdateError::Invalid(
+                TopicValidationError::InvalidWindow
+            ))
+        );
+    }
+
+    #[test]
+    fn test_ballot_store_sample_rate() {
+        let infos: Vec<_> = (0..10_000)
+            .map(|i| BallotInfo {
+                topic_id: Cow::Borrowed("test_topic"),
+                ballot_id: Cow::Owned(format!("{i}-{}", Uuid::new_v4())),
+                ip: Cow::Borrowed("127.0.0.1"),
+                user_agent: Cow::Borrowed(""),
+                timestamp: 0,
+                user_id: None,
+                throttled: false,
+            })
+            .collect();
+        let stored = |rate| infos.iter().filter(|info| info.is_stored_at(rate)).count();
+
+        assert_eq!(stored(0), infos.len());
+        assert_eq!(stored(1), infos.len());
+        assert!((800..1200).contains(&stored(10)), "{}", stored(10));
+        assert!(
+            infos
+                .iter()
+                .all(|info| info.is_stored_at(10) == info.is_stored_at(10))
+        );
+    }
+
+    #[test]
+    fn test_ballot_store_sample_is_pinned() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+
+        let stored: Vec<u64> = (0..20)
+            .filter(|i| {
+                BallotInfo {
+                    topic_id: Cow::Borrowed("test_topic"),
+                    ballot_id: Cow::Owned(format!("{i}-abcdef")),
+                    ip: Cow::Borrowed("127.0.0.1"),
+                    user_agent: Cow::Borrowed(""),
+                    timestamp: 0,
+                    user_id: None,
+                    throttled: false,
+                }
+                .is_stored_at(4)
+            })
+            .collect();
+        assert_eq!(stored, [1, 5, 9, 10, 14, 18]);
+    }
+
+    #[test]
+    fn test_min_operators() {
+        let limits = BallotSizeLimits {
+            max_set_size: 5,
+            max_group_size: 4,
+        };
+
+        assert_eq!(VotingTopicType::Pairwise.min_operators(&limits), 2);
+        assert_eq!(VotingTopicType::Setwise.min_operators(&limits), 5);
+        assert_eq!(VotingTopicType::Groupwise.min_operators(&limits), 8);
+        assert_eq!(VotingTopicType::Plurality.min_operators(&limits), 2);
+
+        let tiny = BallotSizeLimits {
+            max_set_size: 1,
+            max_group_size: 0,
+        };
+        assert_eq!(VotingTopicType::Setwise.min_operators(&tiny), 2);
+        assert_eq!(VotingTopicType::Groupwise.min_operators(&tiny), 2);
+    }
+
+    #[test]
+    fn test_check_operator_count() {
+        let limits = BallotSizeLimits {
+            max_set_size: 5,
+            max_group_size: 4,
+        };
+
+        assert!(
+            VotingTopicType::Groupwise
+                .check_operator_count(8, &limits)
+                .is_ok()
+        );
+        let err = VotingTopicType::Groupwise
+            .check_operator_count(7, &limits)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            InsufficientOperators {
+                topic_type: VotingTopicType::Groupwise,
+                required: 8,
+                available: 7,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Groupwise topics need at least 8 operators for two groups, the candidate pool has 7"
+        );
+        assert!(
+            VotingTopicType::Pairwise
+                .check_operator_count(1, &limits)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_same_creation_content_identical_retry() {
+        let now = Utc::now();
+        let created = create_test_topic(now);
+        // as read back from MongoDB, deactivated in the meantime
+        let mut stored = created.clone();
+        stored.created_at = now - Duration::minutes(5);
+        stored.open_time =
+            DateTime::from_timestamp_millis(created.open_time.timestamp_millis()).unwrap();
+        stored.close_time =
+            DateTime::from_timestamp_millis(created.close_time.timestamp_millis()).unwrap();
+        stored.is_active = false;
+
+        assert!(created.same_creation_content(&stored));
+    }
+
+    #[test]
+    fn test_same_creation_content_conflicting_retry() {
+        let now = Utc::now();
+        let created = create_test_topic(now);
+
+        let renamed = VotingTopic {
+            title: "another title".to_string(),
+            ..created.clone()
+        };
+        assert!(!created.same_creation_content(&renamed));
+
+        let other_pool = VotingTopic {
+            candidate_pool: CandidatePoolPreset::Custom {
+                operator_ids: vec![2, 3],
+            },
+            ..created.clone()
+        };
+        assert!(!created.same_creation_content(&other_pool));
+
+        let later = VotingTopic {
+            close_time: created.close_time + Duration::hours(1),
+            ..created.clone()
+        };
+        assert!(!created.same_creation_content(&later));
+    }
+}
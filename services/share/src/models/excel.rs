@@ -48,6 +48,25 @@ pub enum ProfessionCategory {
     PIONEER = 512,
 }
 
+impl ProfessionCategory {
+    /// The name used in the character table.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProfessionCategory::NONE => "NONE",
+            ProfessionCategory::WARRIOR => "WARRIOR",
+            ProfessionCategory::SNIPER => "SNIPER",
+            ProfessionCategory::TANK => "TANK",
+            ProfessionCategory::MEDIC => "MEDIC",
+            ProfessionCategory::SUPPORT => "SUPPORT",
+            ProfessionCategory::CASTER => "CASTER",
+            ProfessionCategory::SPECIAL => "SPECIAL",
+            ProfessionCategory::TOKEN => "TOKEN",
+            ProfessionCategory::TRAP => "TRAP",
+            ProfessionCategory::PIONEER => "PIONEER",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CharacterData {
@@ -58,6 +77,12 @@ pub struct CharacterData {
     pub is_not_obtainable: bool,
 }
 
+/// The first of `ids` that cannot name an operator. Operator ids are parsed
+/// from `char_<id>_<name>` keys and are always positive.
+pub fn first_invalid_operator_id<'a>(ids: impl IntoIterator<Item = &'a i32>) -> Option<i32> {
+    ids.into_iter().copied().find(|id| *id <= 0)
+}
+
 #[derive(Debug, Clone)]
 pub struct CharacterInfo {
     pub id: i32,
@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::api::FinalOrderItem;
+
+/// Most digits after the decimal separator a client can ask for.
+pub const MAX_RESULT_PRECISION: usize = 6;
+
+/// How the `rate` and `score` strings of the final order are written. The
+/// default gives the historical `70.0%` and `0.40`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct ResultFormat {
+    /// Digits after the decimal separator in `rate`.
+    pub rate_precision: usize,
+    /// Digits after the decimal separator in `score`.
+    pub score_precision: usize,
+    /// Appends `%` to `rate`.
+    pub percent_sign: bool,
+    /// Written in place of `.`, e.g. `,` for most European locales.
+    pub decimal_separator: char,
+}
+
+impl Default for ResultFormat {
+    fn default() -> Self {
+        Self {
+            rate_precision: 1,
+            score_precision: 2,
+            percent_sign: true,
+            decimal_separator: '.',
+        }
+    }
+}
+
+impl ResultFormat {
+    pub fn rate(&self, rate: f64) -> String {
+        let mut rate = self.decimal(rate, self.rate_precision);
+        if self.percent_sign {
+            rate.push('%');
+        }
+        rate
+    }
+
+    pub fn score(&self, score: f64) -> String {
+        self.decimal(score, self.score_precision)
+    }
+
+    fn decimal(&self, value: f64, precision: usize) -> String {
+        let value = format!("{value:.precision$}");
+        match self.decimal_separator {
+            '.' => value,
+            separator => value.replacen('.', &separator.to_string(), 1),
+        }
+    }
+}
+
+/// Precisions are capped at [`MAX_RESULT_PRECISION`], and the separator must
+/// not be mistaken for part of the number.
+pub fn validate_result_format(format: &ResultFormat) -> bool {
+    format.rate_precision <= MAX_RESULT_PRECISION
+        && format.score_precision <= MAX_RESULT_PRECISION
+        && !format.decimal_separator.is_ascii_digit()
+        && !matches!(format.decimal_separator, '-' | '%')
+        && !format.decimal_separator.is_control()
+}
+
+/// Rewrites `rate` and `score` from the raw values kept on each item.
+pub fn apply_result_format(items: &mut [FinalOrderItem], format: &ResultFormat) {
+    for item in items {
+        item.rate = format.rate(item.rate_value);
+        item.score = format.score(item.score_value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_format_matches_fixed_format() {
+        let format = ResultFormat::default();
+        for value in [0.0, 70.0, 66.666, -0.4, 12.345] {
+            assert_eq!(format.rate(value), format!("{:.1}%", value));
+            assert_eq!(format.score(value), format!("{:.2}", value));
+        }
+    }
+
+    #[test]
+    fn test_result_format() {
+        let format = ResultFormat {
+            rate_precision: 3,
+            score_precision: 0,
+            percent_sign: false,
+            decimal_separator: ',',
+        };
+        assert_eq!(format.rate(66.66666), "66,667");
+        assert_eq!(format.score(0.4), "0");
+        assert_eq!(format.score(12.6), "13");
+    }
+
+    #[test]
+    fn test_validate_result_format() {
+        assert!(validate_result_format(&ResultFormat::default()));
+        assert!(validate_result_format(&ResultFormat {
+            decimal_separator: ',',
+            rate_precision: MAX_RESULT_PRECISION,
+            ..Default::default()
+        }));
+        assert!(!validate_result_format(&ResultFormat {
+            rate_precision: MAX_RESULT_PRECISION + 1,
+            ..Default::default()
+        }));
+        assert!(!validate_result_format(&ResultFormat {
+            decimal_separator: '5',
+            ..Default::default()
+        }));
+        assert!(!validate_result_format(&ResultFormat {
+            decimal_separator: '-',
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn test_apply_result_format() {
+        let mut items = vec![FinalOrderItem {
+            name: "AAAA".to_string(),
+            id: 1001,
+            win: 2,
+            lose: 1,
+            score: "0.01".to_string(),
+            rate: "66.7%".to_string(),
+            tier: None,
+            score_value: 0.01,
+            rate_value: 200.0 / 3.0,
+        }];
+
+        apply_result_format(
+            &mut items,
+            &ResultFormat {
+                rate_precision: 2,
+                decimal_separator: ',',
+                ..Default::default()
+            },
+        );
+        assert_eq!(items[0].rate, "66,67%");
+        assert_eq!(items[0].score, "0,01");
+    }
+}
@@ -2,3 +2,7 @@ pub mod api;
 pub mod candidate_pool_preset;
 pub mod database;
 pub mod excel;
+pub mod format;
+pub mod proto;
+pub mod tier;
+pub mod topic_id;
@@ -0,0 +1,254 @@
+use std::{collections::HashMap, sync::Arc};
+
+use prost::Message;
+
+use crate::models::api;
+
+pub const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+/// Whether the `Accept` header explicitly lists protobuf. JSON stays the
+/// default for missing headers and wildcards.
+pub fn accepts_protobuf(accept: Option<&str>) -> bool {
+    let Some(accept) = accept else {
+        return false;
+    };
+
+    accept.split(',').any(|media_range| {
+        let mut params = media_range.split(';').map(str::trim);
+        let media_type = params.next().unwrap_or_default();
+        let rejected = params.any(|p| {
+            p.strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .is_some_and(|q| q <= 0.0)
+        });
+
+        media_type.eq_ignore_ascii_case(PROTOBUF_CONTENT_TYPE) && !rejected
+    })
+}
+
+/// Payloads that can also be served as protobuf, see `proto/results.proto`.
+pub trait ToProto {
+    type Message: Message;
+
+    fn to_proto(&self) -> Self::Message;
+
+    fn encode_proto(&self) -> Vec<u8> {
+        self.to_proto().encode_to_vec()
+    }
+}
+
+impl<T: ToProto> ToProto for Arc<T> {
+    type Message = T::Message;
+
+    fn to_proto(&self) -> Self::Message {
+        self.as_ref().to_proto()
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FinalOrderItem {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(int32, tag = "2")]
+    pub id: i32,
+    #[prost(int64, tag = "3")]
+    pub win: i64,
+    #[prost(int64, tag = "4")]
+    pub lose: i64,
+    #[prost(string, tag = "5")]
+    pub score: String,
+    #[prost(string, tag = "6")]
+    pub rate: String,
+    #[prost(string, optional, tag = "7")]
+    pub tier: Option<String>,
+    #[prost(double, tag = "8")]
+    pub score_value: f64,
+    #[prost(double, tag = "9")]
+    pub rate_value: f64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ResultsFinalOrderResponse {
+    #[prost(string, tag = "1")]
+    pub topic_id: String,
+    #[prost(message, repeated, tag = "2")]
+    pub items: Vec<FinalOrderItem>,
+    #[prost(int64, tag = "3")]
+    pub count: i64,
+    #[prost(string, tag = "4")]
+    pub ranking_method: String,
+    #[prost(int64, tag = "5")]
+    pub computed_at_ms: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Results1v1MatrixItem {
+    #[prost(int64, tag = "1")]
+    pub score: i64,
+    #[prost(int64, tag = "2")]
+    pub count: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Results1v1MatrixPair {
+    #[prost(int32, tag = "1")]
+    pub a: i32,
+    #[prost(int32, tag = "2")]
+    pub b: i32,
+    #[prost(int64, tag = "3")]
+    pub a_wins: i64,
+    #[prost(int64, tag = "4")]
+    pub b_wins: i64,
+    #[prost(int64, tag = "5")]
+    pub total: i64,
+    #[prost(int64, tag = "6")]
+    pub net: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Results1v1MatrixResponse {
+    #[prost(map = "string, message", tag = "1")]
+    pub items: HashMap<String, Results1v1MatrixItem>,
+    #[prost(int64, tag = "2")]
+    pub computed_at_ms: i64,
+    #[prost(message, repeated, tag = "3")]
+    pub pairs: Vec<Results1v1MatrixPair>,
+}
+
+impl From<&api::FinalOrderItem> for FinalOrderItem {
+    fn from(item: &api::FinalOrderItem) -> Self {
+        Self {
+            name: item.name.clone(),
+            id: item.id,
+            win: item.win,
+            lose: item.lose,
+            score: item.score.clone(),
+            rate: item.rate.clone(),
+            tier: item.tier.clone(),
+            score_value: item.score_value,
+            rate_value: item.rate_value,
+        }
+    }
+}
+
+impl ToProto for api::ResultsFinalOrderResponse {
+    type Message = ResultsFinalOrderResponse;
+
+    fn to_proto(&self) -> Self::Message {
+        ResultsFinalOrderResponse {
+            topic_id: self.topic_id.clone(),
+            items: self.items.iter().map(FinalOrderItem::from).collect(),
+            count: self.count,
+            ranking_method: format!("{:?}", self.ranking_method),
+            computed_at_ms: self.computed_at.timestamp_millis(),
+        }
+    }
+}
+
+impl ToProto for api::Results1v1MatrixResponse {
+    type Message = Results1v1MatrixResponse;
+
+    fn to_proto(&self) -> Self::Message {
+        Results1v1MatrixResponse {
+            items: self
+                .items
+                .iter()
+                .map(|(key, item)| {
+                    (
+                        key.clone(),
+                        Results1v1MatrixItem {
+                            score: item.score,
+                            count: item.count,
+                        },
+                    )
+                })
+                .collect(),
+            computed_at_ms: self.computed_at.timestamp_millis(),
+            pairs: self
+                .pairs
+                .iter()
+                .map(|pair| Results1v1MatrixPair {
+                    a: pair.a,
+                    b: pair.b,
+                    a_wins: pair.a_wins,
+                    b_wins: pair.b_wins,
+                    total: pair.total,
+                    net: pair.net,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::ranking::RankingMethod;
+
+    #[test]
+    fn test_accepts_protobuf() {
+        assert!(accepts_protobuf(Some("application/x-protobuf")));
+        assert!(accepts_protobuf(Some(
+            "application/json;q=0.5, Application/X-Protobuf"
+        )));
+        assert!(!accepts_protobuf(None));
+        assert!(!accepts_protobuf(Some("*/*")));
+        assert!(!accepts_protobuf(Some("application/json")));
+        assert!(!accepts_protobuf(Some("application/x-protobuf;q=0")));
+    }
+
+    #[test]
+    fn test_final_order_round_trip() {
+        let response = api::ResultsFinalOrderResponse {
+            topic_id: "crisis_v2_season_4_1".to_string(),
+            items: vec![api::FinalOrderItem {
+                name: "Amiya".to_string(),
+                id: 1,
+                win: 70,
+                lose: 30,
+                score: "0.40".to_string(),
+                rate: "70.0%".to_string(),
+                tier: Some("S".to_string()),
+                score_value: 0.4,
+                rate_value: 70.0,
+            }],
+            count: 100,
+            ranking_method: RankingMethod::Colley,
+            computed_at: Utc.timestamp_millis_opt(1_756_300_000_123).unwrap(),
+        };
+
+        let decoded =
+            ResultsFinalOrderResponse::decode(response.encode_proto().as_slice()).unwrap();
+        assert_eq!(decoded, response.to_proto());
+        assert_eq!(decoded.items[0].tier.as_deref(), Some("S"));
+        assert_eq!(decoded.items[0].rate_value, 70.0);
+        assert_eq!(decoded.ranking_method, "Colley");
+        assert_eq!(decoded.computed_at_ms, 1_756_300_000_123);
+    }
+
+    #[test]
+    fn test_matrix_smaller_than_json() {
+        let response = api::Results1v1MatrixResponse {
+            items: (0..100)
+                .map(|i| {
+                    (
+                        format!("{}:{}", 1000 + i, 2000 + i),
+                        api::Results1v1MatrixItem {
+                            score: i * 10,
+                            count: i,
+                        },
+                    )
+                })
+                .collect(),
+            pairs: Vec::new(),
+            computed_at: Utc::now(),
+        };
+
+        let encoded = response.encode_proto();
+        let decoded = Results1v1MatrixResponse::decode(encoded.as_slice()).unwrap();
+        assert_eq!(decoded, response.to_proto());
+        assert!(encoded.len() < serde_json::to_vec(&response).unwrap().len());
+    }
+}
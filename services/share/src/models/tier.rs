@@ -0,0 +1,91 @@
+use crate::models::api::FinalOrderItem;
+
+const TIER_LABELS: [&str; 7] = ["S", "A", "B", "C", "D", "E", "F"];
+
+/// Breakpoints must be finite and strictly ascending.
+pub fn validate_tier_breakpoints(breakpoints: &[f64]) -> bool {
+    breakpoints.iter().all(|b| b.is_finite()) && breakpoints.windows(2).all(|w| w[0] < w[1])
+}
+
+/// `n` breakpoints split the rate range into `n + 1` tiers, labelled from `S`
+/// for rates at or above the highest breakpoint downwards.
+pub fn tier_label(rate: f64, breakpoints: &[f64]) -> String {
+    let above = breakpoints.iter().filter(|&&b| rate >= b).count();
+    let index = breakpoints.len() - above;
+
+    TIER_LABELS
+        .get(index)
+        .map(|label| label.to_string())
+        .unwrap_or_else(|| format!("T{index}"))
+}
+
+pub fn assign_tiers(items: &mut [FinalOrderItem], breakpoints: &[f64]) {
+    for item in items {
+        let total = item.win + item.lose;
+        let rate = match total {
+            t if t > 0 => item.win as f64 * 100.0 / t as f64,
+            _ => 0.0,
+        };
+        item.tier = Some(tier_label(rate, breakpoints));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_tier_breakpoints() {
+        assert!(validate_tier_breakpoints(&[]));
+        assert!(validate_tier_breakpoints(&[40.0, 50.0, 60.0]));
+        assert!(!validate_tier_breakpoints(&[50.0, 40.0]));
+        assert!(!validate_tier_breakpoints(&[50.0, 50.0]));
+        assert!(!validate_tier_breakpoints(&[f64::NAN]));
+    }
+
+    #[test]
+    fn test_tier_label() {
+        let breakpoints = [40.0, 50.0, 60.0];
+        assert_eq!(tier_label(75.0, &breakpoints), "S");
+        assert_eq!(tier_label(60.0, &breakpoints), "S");
+        assert_eq!(tier_label(55.0, &breakpoints), "A");
+        assert_eq!(tier_label(45.0, &breakpoints), "B");
+        assert_eq!(tier_label(10.0, &breakpoints), "C");
+        assert_eq!(tier_label(10.0, &[]), "S");
+
+        let many: Vec<f64> = (1..=8).map(|i| i as f64 * 10.0).collect();
+        assert_eq!(tier_label(0.0, &many), "T8");
+    }
+
+    #[test]
+    fn test_assign_tiers() {
+        let mut items = vec![
+            FinalOrderItem {
+                name: "AAAA".to_string(),
+                id: 1001,
+                win: 70,
+                lose: 30,
+                score: "0.40".to_string(),
+                rate: "70.0%".to_string(),
+                tier: None,
+                score_value: 0.4,
+                rate_value: 70.0,
+            },
+            FinalOrderItem {
+                name: "BBBB".to_string(),
+                id: 1002,
+                win: 0,
+                lose: 0,
+                score: "0.00".to_string(),
+                rate: "0.0%".to_string(),
+                tier: None,
+                score_value: 0.0,
+                rate_value: 0.0,
+            },
+        ];
+
+        assign_tiers(&mut items, &[50.0]);
+        assert_eq!(items[0].tier.as_deref(), Some("S"));
+        assert_eq!(items[1].tier.as_deref(), Some("A"));
+    }
+}
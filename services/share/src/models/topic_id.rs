@@ -0,0 +1,201 @@
+use std::{borrow::Borrow, fmt, ops::Deref, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Longest topic id accepted.
+pub const MAX_TOPIC_ID_LENGTH: usize = 128;
+
+/// Id of a voting topic. Ids are non-empty, at most [`MAX_TOPIC_ID_LENGTH`]
+/// characters and made of ASCII letters, digits, `_`, `-` and `.`, so they can
+/// not be mistaken for a ballot code or break the `:` separated Redis keys.
+///
+/// Serialized as the plain string, deserializing an invalid id fails.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(try_from = "String", into = "String")]
+#[schema(value_type = String)]
+pub struct TopicId(String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum TopicIdError {
+    #[error("topic id is empty")]
+    Empty,
+    #[error("topic id is longer than {MAX_TOPIC_ID_LENGTH} characters")]
+    TooLong,
+    #[error("topic id contains invalid character {0:?}")]
+    InvalidCharacter(char),
+}
+
+impl TopicId {
+    pub fn parse(id: impl Into<String>) -> Result<Self, TopicIdError> {
+        let id = id.into();
+        if id.is_empty() {
+            return Err(TopicIdError::Empty);
+        }
+        if id.len() > MAX_TOPIC_ID_LENGTH {
+            return Err(TopicIdError::TooLong);
+        }
+        if let Some(c) = id
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')))
+        {
+            return Err(TopicIdError::InvalidCharacter(c));
+        }
+        Ok(Self(id))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Hash of the `{id}:win` and `{id}:lose` counters per operator.
+    pub fn op_stats_key(&self) -> String {
+        format!("{self}:op_stats")
+    }
+
+    /// Hash of the pair scores, keyed by `left:right`.
+    pub fn op_matrix_key(&self) -> String {
+        format!("{self}:op_matrix")
+    }
+
+    /// Hash of the games played per pair, keyed by the ordered `min:max`.
+    pub fn op_counter_key(&self) -> String {
+        format!("{self}:op_counter")
+    }
+
+    pub fn op_cooccur_key(&self) -> String {
+        format!("{self}:op_cooccur")
+    }
+
+    pub fn valid_ballots_count_key(&self) -> String {
+        format!("{self}:valid_ballots_count")
+    }
+
+    pub fn ballot_key(&self, ballot_id: &str) -> String {
+        format!("{self}:ballot:{ballot_id}")
+    }
+
+    pub fn ip_counter_key(&self, ip: &str) -> String {
+        format!("{self}:ip_counter:{ip}")
+    }
+
+    /// Claimed by the instance sending the webhooks of the topic's close.
+    pub fn close_webhook_key(&self) -> String {
+        format!("{self}:close_webhook")
+    }
+}
+
+impl TryFrom<String> for TopicId {
+    type Error = TopicIdError;
+
+    fn try_from(id: String) -> Result<Self, Self::Error> {
+        Self::parse(id)
+    }
+}
+
+impl FromStr for TopicId {
+    type Err = TopicIdError;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        Self::parse(id)
+    }
+}
+
+impl From<TopicId> for String {
+    fn from(id: TopicId) -> Self {
+        id.0
+    }
+}
+
+impl Deref for TopicId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for TopicId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for TopicId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for TopicId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl PartialEq<str> for TopicId {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<String> for TopicId {
+    fn eq(&self, other: &String) -> bool {
+        &self.0 == other
+    }
+}
+
+impl PartialEq<TopicId> for String {
+    fn eq(&self, other: &TopicId) -> bool {
+        self == &other.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_topic_id() {
+        assert!(TopicId::parse("crisis_v2_season_4_1").is_ok());
+        assert!(TopicId::parse("3f2a8c1e-5b7d-4e0a-9c6f-1d2e3f4a5b6c").is_ok());
+        assert!(TopicId::parse("v1.2").is_ok());
+
+        assert_eq!(TopicId::parse(""), Err(TopicIdError::Empty));
+        assert_eq!(
+            TopicId::parse("a".repeat(MAX_TOPIC_ID_LENGTH + 1)),
+            Err(TopicIdError::TooLong)
+        );
+        assert_eq!(
+            TopicId::parse("topic:ballot"),
+            Err(TopicIdError::InvalidCharacter(':'))
+        );
+        assert_eq!(
+            TopicId::parse("弧光作战"),
+            Err(TopicIdError::InvalidCharacter('弧'))
+        );
+    }
+
+    #[test]
+    fn test_topic_id_serde() {
+        let id: TopicId = serde_json::from_str("\"crisis_v2_season_4_1\"").unwrap();
+        assert_eq!(id, "crisis_v2_season_4_1".to_string());
+        assert_eq!(
+            serde_json::to_string(&id).unwrap(),
+            "\"crisis_v2_season_4_1\""
+        );
+
+        assert!(serde_json::from_str::<TopicId>("\"\"").is_err());
+        assert!(serde_json::from_str::<TopicId>("\"a b\"").is_err());
+    }
+
+    #[test]
+    fn test_topic_keys() {
+        let id = TopicId::parse("t1").unwrap();
+        assert_eq!(id.op_stats_key(), "t1:op_stats");
+        assert_eq!(id.op_matrix_key(), "t1:op_matrix");
+        assert_eq!(id.ballot_key("42-abc"), "t1:ballot:42-abc");
+        assert_eq!(id.ip_counter_key("127.0.0.1"), "t1:ip_counter:127.0.0.1");
+        assert_eq!(id.close_webhook_key(), "t1:close_webhook");
+    }
+}
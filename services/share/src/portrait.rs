@@ -0,0 +1,559 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{self, AtomicUsize},
+    },
+};
+
+use futures::StreamExt as _;
+use parking_lot::RwLock;
+
+use crate::{
+    config::PortraitConfig,
+    models::{
+        api::{CandidatePoolOrder, CharacterPortrait, ImageDimensions},
+        excel::{CharacterData, CharacterInfo},
+    },
+    retry::{ConnectError, ConnectRetryConfig, connect_with_retry},
+};
+
+pub const PORTRAIT_ASSET_URL: &str = "https://torappu.prts.wiki/assets/char_portrait";
+
+/// Bytes needed to read the dimensions from a PNG: the signature followed by
+/// the length, type and width/height of the `IHDR` chunk.
+pub const PNG_HEADER_LEN: usize = 24;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Reads the image dimensions from the start of a PNG file.
+pub fn png_dimensions(bytes: &[u8]) -> Option<ImageDimensions> {
+    if bytes.len() < PNG_HEADER_LEN
+        || !bytes.starts_with(PNG_SIGNATURE)
+        || &bytes[12..16] != b"IHDR"
+    {
+        return None;
+    }
+
+    let width = u32::from_be_bytes(bytes[16..20].try_into().ok()?);
+    let height = u32::from_be_bytes(bytes[20..24].try_into().ok()?);
+
+    (width > 0 && height > 0).then_some(ImageDimensions { width, height })
+}
+
+/// Portraits used when the portrait listing cannot be fetched: the elite 0
+/// image of every operator in the character table, under its conventional
+/// `<key>_1.png` name.
+pub fn fallback_portraits(
+    character_table: &HashMap<String, CharacterData>,
+) -> HashMap<i32, CharacterPortrait> {
+    character_table
+        .iter()
+        .filter_map(|(key, data)| {
+            let id = key.strip_prefix("char_")?.split('_').next()?.parse().ok()?;
+            Some((
+                id,
+                CharacterPortrait {
+                    id,
+                    name: key.clone(),
+                    cn_name: data.name.clone(),
+                    avatar: vec![format!("{PORTRAIT_ASSET_URL}/{key}_1.png")],
+                    avatar_dimensions: None,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// The portraits as served, replaced as a whole when the portrait CDN goes
+/// down or recovers. Shared between clones.
+#[derive(Clone, Debug, Default)]
+pub struct PortraitStore(Arc<RwLock<Arc<HashMap<i32, CharacterPortrait>>>>);
+
+impl PortraitStore {
+    pub fn new(portraits: HashMap<i32, CharacterPortrait>) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(portraits))))
+    }
+
+    pub fn load(&self) -> Arc<HashMap<i32, CharacterPortrait>> {
+        self.0.read().clone()
+    }
+
+    pub fn store(&self, portraits: Arc<HashMap<i32, CharacterPortrait>>) {
+        *self.0.write() = portraits;
+    }
+}
+
+/// Moves the avatar urls from [`PORTRAIT_ASSET_URL`] to `base_url`. Without a
+/// base url the avatars are dropped, clients then show their bundled images.
+pub fn rebase_portraits(
+    portraits: &HashMap<i32, CharacterPortrait>,
+    base_url: Option<&str>,
+) -> HashMap<i32, CharacterPortrait> {
+    let base_url = base_url.map(|url| url.trim_end_matches('/'));
+    portraits
+        .iter()
+        .map(|(&id, portrait)| {
+            let avatar = match base_url {
+                Some(base_url) => portrait
+                    .avatar
+                    .iter()
+                    .map(|url| match url.strip_prefix(PORTRAIT_ASSET_URL) {
+                        Some(path) => format!("{base_url}{path}"),
+                        None => url.clone(),
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            (
+                id,
+                CharacterPortrait {
+                    avatar,
+                    avatar_dimensions: None,
+                    ..portrait.clone()
+                },
+            )
+        })
+        .collect()
+}
+
+/// Sorts portraits for display. Ties and operators missing from
+/// `character_infos` fall back to id order, the latter after all others.
+pub fn sort_portraits(
+    pool: &mut [CharacterPortrait],
+    order_by: CandidatePoolOrder,
+    character_infos: &[CharacterInfo],
+) {
+    pool.sort_unstable_by_key(|portrait| portrait.id);
+    if order_by == CandidatePoolOrder::Id {
+        return;
+    }
+
+    let infos: HashMap<i32, &CharacterInfo> =
+        character_infos.iter().map(|info| (info.id, info)).collect();
+    pool.sort_by(|a, b| match (infos.get(&a.id), infos.get(&b.id)) {
+        (Some(a), Some(b)) => {
+            let by_rarity = b.rarity.to_numeric().cmp(&a.rarity.to_numeric());
+            match order_by {
+                CandidatePoolOrder::Id => Ordering::Equal,
+                CandidatePoolOrder::Rarity => by_rarity.then_with(|| a.name.cmp(&b.name)),
+                CandidatePoolOrder::Name => a.name.cmp(&b.name),
+                CandidatePoolOrder::Profession => (a.profession.clone() as i32)
+                    .cmp(&(b.profession.clone() as i32))
+                    .then(by_rarity),
+            }
+        }
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+}
+
+/// Image dimensions per portrait URL, filled in the background after the
+/// portraits are loaded. Shared between clones.
+#[derive(Clone, Debug, Default)]
+pub struct PortraitHints(Arc<RwLock<HashMap<String, ImageDimensions>>>);
+
+impl PortraitHints {
+    pub fn insert(&self, url: String, dimensions: ImageDimensions) {
+        self.0.write().insert(url, dimensions);
+    }
+
+    pub fn get(&self, url: &str) -> Option<ImageDimensions> {
+        self.0.read().get(url).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.read().is_empty()
+    }
+
+    /// Sets `avatar_dimensions` in the order of `avatar`, `None` for images
+    /// not probed yet.
+    pub fn annotate(&self, portrait: &mut CharacterPortrait) {
+        let hints = self.0.read();
+        portrait.avatar_dimensions = Some(
+            portrait
+                .avatar
+                .iter()
+                .map(|url| hints.get(url).copied())
+                .collect(),
+        );
+    }
+}
+
+/// Client of the portrait probes, each request bounded by `retry.timeout_ms`.
+fn probe_client(retry: &ConnectRetryConfig) -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder().timeout(retry.timeout()).build()
+}
+
+/// Probes the dimensions of every portrait image in the background with a
+/// ranged request for the PNG header. Portraits are served without
+/// dimensions until their image has been probed.
+pub fn spawn_portrait_hints(
+    portraits: &HashMap<i32, CharacterPortrait>,
+    config: &PortraitConfig,
+) -> reqwest::Result<PortraitHints> {
+    let concurrency = config.concurrency.max(1);
+    let retry = config.retry.clone();
+    let client = probe_client(&retry)?;
+    let hints = PortraitHints::default();
+    let urls: Vec<String> = portraits
+        .values()
+        .flat_map(|portrait| portrait.avatar.iter().cloned())
+        .collect();
+
+    tokio::spawn({
+        let hints = hints.clone();
+        async move {
+            let start_time = tokio::time::Instant::now();
+            let failed = AtomicUsize::new(0);
+
+            futures::stream::iter(urls)
+                .for_each_concurrent(concurrency, |url| {
+                    let client = &client;
+                    let hints = &hints;
+                    let retry = &retry;
+                    let failed = &failed;
+                    async move {
+                        match probe_with_retry(client, &url, retry).await {
+                            Ok(Some(dimensions)) => hints.insert(url, dimensions),
+                            Ok(None) => tracing::debug!("Portrait {} is not a PNG", url),
+                            Err(_) => {
+                                failed.fetch_add(1, atomic::Ordering::Relaxed);
+                            }
+                        }
+                    }
+                })
+                .await;
+
+            tracing::info!(
+                "Probed portrait images: {} succeeded, {} failed, duration={:?}",
+                hints.len(),
+                failed.into_inner(),
+                start_time.elapsed()
+            );
+        }
+    });
+
+    Ok(hints)
+}
+
+/// Checks every `health_check_interval` that the portrait CDN still answers
+/// at [`PORTRAIT_ASSET_URL`]. While it does not, `store` serves the portraits
+/// moved to `backup_base_url`, and the primary ones again once the CDN
+/// recovers. `on_switch` runs after each swap to drop responses built with
+/// the old urls.
+pub fn spawn_portrait_health_check(
+    store: PortraitStore,
+    config: &PortraitConfig,
+    on_switch: impl Fn() + Send + 'static,
+) -> reqwest::Result<()> {
+    let Some(interval) = config.health_check_interval() else {
+        return Ok(());
+    };
+    let retry = config.retry.clone();
+    let client = probe_client(&retry)?;
+    let primary = store.load();
+    let backup = Arc::new(rebase_portraits(
+        &primary,
+        config.backup_base_url.as_deref(),
+    ));
+    let backup_name = config
+        .backup_base_url
+        .clone()
+        .unwrap_or_else(|| "bundled client images".to_string());
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut serving_primary = true;
+
+        loop {
+            ticker.tick().await;
+            let reachable = probe_reachable(&client, PORTRAIT_ASSET_URL, &retry)
+                .await
+                .is_ok();
+            if reachable == serving_primary {
+                continue;
+            }
+
+            serving_primary = reachable;
+            if reachable {
+                store.store(primary.clone());
+                tracing::info!(
+                    "Portrait CDN is reachable again, switched avatars back to {}",
+                    PORTRAIT_ASSET_URL
+                );
+            } else {
+                store.store(backup.clone());
+                tracing::warn!(
+                    "Portrait CDN is unreachable, switched avatars to {}",
+                    backup_name
+                );
+            }
+            on_switch();
+        }
+    });
+
+    Ok(())
+}
+
+/// Whether `url` answers at all. Only connection errors, timeouts and server
+/// errors count as unreachable, a missing asset says nothing about the CDN.
+async fn probe_reachable(
+    client: &reqwest::Client,
+    url: &str,
+    retry: &ConnectRetryConfig,
+) -> Result<(), ConnectError<reqwest::Error>> {
+    connect_with_retry(url, retry, || async {
+        let response = client.get(url).send().await?;
+        if response.status().is_server_error() {
+            response.error_for_status()?;
+        }
+        Ok::<_, reqwest::Error>(())
+    })
+    .await
+}
+
+/// Dimensions of the PNG at `url`, `None` when it is not a PNG. Retried like
+/// any other connection, a portrait that keeps failing is served without
+/// dimensions.
+async fn probe_with_retry(
+    client: &reqwest::Client,
+    url: &str,
+    retry: &ConnectRetryConfig,
+) -> Result<Option<ImageDimensions>, ConnectError<reqwest::Error>> {
+    connect_with_retry(url, retry, || probe_png_dimensions(client, url)).await
+}
+
+async fn probe_png_dimensions(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<Option<ImageDimensions>, reqwest::Error> {
+    let bytes = client
+        .get(url)
+        .header(
+            reqwest::header::RANGE,
+            format!("bytes=0-{}", PNG_HEADER_LEN - 1),
+        )
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    Ok(png_dimensions(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::excel::RarityRank, test_util::create_test_characters};
+
+    /// Serves `status` on every path, returns the base url.
+    async fn serve_status(status: axum::http::StatusCode) -> String {
+        let app = axum::Router::new().fallback(move || async move { status });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_probe_retries_until_header_is_served() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new().fallback({
+            let attempts = attempts.clone();
+            move || async move {
+                match attempts.fetch_add(1, atomic::Ordering::SeqCst) {
+                    0 => Err(axum::http::StatusCode::SERVICE_UNAVAILABLE),
+                    _ => Ok(png_header(180, 360)),
+                }
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/char_002_amiya_1.png",
+            listener.local_addr().unwrap()
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let retry = ConnectRetryConfig {
+            max_retries: 1,
+            base_delay_ms: 1,
+            ..Default::default()
+        };
+        let client = probe_client(&retry).unwrap();
+        assert_eq!(
+            probe_with_retry(&client, &url, &retry).await.unwrap(),
+            Some(ImageDimensions {
+                width: 180,
+                height: 360
+            })
+        );
+        assert_eq!(attempts.load(atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_missing_asset_keeps_cdn_reachable() {
+        let retry = ConnectRetryConfig {
+            max_retries: 0,
+            ..Default::default()
+        };
+        let client = probe_client(&retry).unwrap();
+
+        let not_found = serve_status(axum::http::StatusCode::NOT_FOUND).await;
+        assert!(probe_reachable(&client, &not_found, &retry).await.is_ok());
+
+        let unavailable = serve_status(axum::http::StatusCode::SERVICE_UNAVAILABLE).await;
+        assert!(
+            probe_reachable(&client, &unavailable, &retry)
+                .await
+                .is_err()
+        );
+    }
+
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        bytes.extend_from_slice(&13u32.to_be_bytes());
+        bytes.extend_from_slice(b"IHDR");
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_png_dimensions() {
+        assert_eq!(
+            png_dimensions(&png_header(180, 360)),
+            Some(ImageDimensions {
+                width: 180,
+                height: 360
+            })
+        );
+    }
+
+    #[test]
+    fn test_png_dimensions_invalid() {
+        assert_eq!(png_dimensions(&png_header(180, 360)[..20]), None);
+        assert_eq!(png_dimensions(&png_header(0, 360)), None);
+
+        let mut jpeg = png_header(180, 360);
+        jpeg[..4].copy_from_slice(b"\xff\xd8\xff\xe0");
+        assert_eq!(png_dimensions(&jpeg), None);
+    }
+
+    #[test]
+    fn test_annotate_portrait() {
+        let hints = PortraitHints::default();
+        let dimensions = ImageDimensions {
+            width: 180,
+            height: 360,
+        };
+        hints.insert("a.png".to_string(), dimensions);
+
+        let mut portrait = CharacterPortrait {
+            avatar: vec!["a.png".to_string(), "b.png".to_string()],
+            ..Default::default()
+        };
+        hints.annotate(&mut portrait);
+
+        assert_eq!(
+            portrait.avatar_dimensions,
+            Some(vec![Some(dimensions), None])
+        );
+    }
+
+    #[test]
+    fn test_fallback_portraits() {
+        let data = |name: &str| CharacterData {
+            name: name.to_string(),
+            rarity: crate::models::excel::RarityRank::Tier6,
+            profession: crate::models::excel::ProfessionCategory::CASTER,
+            sub_profession_id: "corecaster".to_string(),
+            is_not_obtainable: false,
+        };
+        let table = HashMap::from([
+            ("char_002_amiya".to_string(), data("阿米娅")),
+            ("token_10000_silent_healrb".to_string(), data("医疗无人机")),
+        ]);
+
+        let portraits = fallback_portraits(&table);
+        assert_eq!(portraits.len(), 1);
+        assert_eq!(portraits[&2].cn_name, "阿米娅");
+        assert_eq!(
+            portraits[&2].avatar,
+            [format!("{PORTRAIT_ASSET_URL}/char_002_amiya_1.png")]
+        );
+    }
+
+    #[test]
+    fn test_rebase_portraits() {
+        let portraits = HashMap::from([(
+            2,
+            CharacterPortrait {
+                id: 2,
+                avatar: vec![format!("{PORTRAIT_ASSET_URL}/char_002_amiya_1.png")],
+                ..Default::default()
+            },
+        )]);
+
+        let backup = rebase_portraits(&portraits, Some("https://backup.example.com/portraits/"));
+        assert_eq!(
+            backup[&2].avatar,
+            ["https://backup.example.com/portraits/char_002_amiya_1.png"]
+        );
+
+        let local = rebase_portraits(&portraits, None);
+        assert_eq!(local[&2].id, 2);
+        assert!(local[&2].avatar.is_empty());
+
+        let store = PortraitStore::new(portraits);
+        store.store(Arc::new(backup));
+        assert_eq!(
+            store.load()[&2].avatar,
+            ["https://backup.example.com/portraits/char_002_amiya_1.png"]
+        );
+    }
+
+    fn sorted_ids(order_by: CandidatePoolOrder) -> Vec<i32> {
+        let mut infos = create_test_characters();
+        // one guard drops to five stars, so rarity decides within a profession
+        infos[0].rarity = RarityRank::Tier5;
+        let mut pool: Vec<CharacterPortrait> = [3001, 9999, 2001, 1002, 1001]
+            .into_iter()
+            .map(|id| CharacterPortrait {
+                id,
+                ..Default::default()
+            })
+            .collect();
+
+        sort_portraits(&mut pool, order_by, &infos);
+        pool.iter().map(|portrait| portrait.id).collect()
+    }
+
+    #[test]
+    fn test_sort_portraits() {
+        assert_eq!(
+            sorted_ids(CandidatePoolOrder::Id),
+            [1001, 1002, 2001, 3001, 9999]
+        );
+        assert_eq!(
+            sorted_ids(CandidatePoolOrder::Rarity),
+            [1002, 2001, 3001, 1001, 9999]
+        );
+        assert_eq!(
+            sorted_ids(CandidatePoolOrder::Name),
+            [1001, 1002, 2001, 3001, 9999]
+        );
+        assert_eq!(
+            sorted_ids(CandidatePoolOrder::Profession),
+            [1002, 1001, 2001, 3001, 9999]
+        );
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Largest number of operators the Colley system is solved for; the dense
+/// solve is cubic in the operator count.
+pub const MAX_COLLEY_OPERATORS: usize = 512;
+
+/// Largest number of operators the Smith set is computed for; every pair of
+/// operators is compared, so the work is quadratic in the operator count.
+pub const MAX_CONDORCET_OPERATORS: usize = 1024;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub enum RankingMethod {
+    /// Win rate over all ballots.
+    #[default]
+    Rate,
+    /// Colley's method, which accounts for the strength of the opponents each
+    /// operator was compared against.
+    Colley,
+}
+
+/// Games played between two operators and the wins of each of them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PairRecord {
+    pub a: i32,
+    pub b: i32,
+    pub games: f64,
+    pub a_wins: f64,
+    pub b_wins: f64,
+}
+
+/// Estimates the wins of the leading operator of a pair from the number of
+/// games and the net weighted score, as `(games + |net| / base_multiplier) / 2`.
+fn estimate_wins(games: i64, net: i64, base_multiplier: i32) -> f64 {
+    let base_multiplier = base_multiplier.max(1) as f64;
+    ((games as f64 + net.abs() as f64 / base_multiplier) / 2.0).clamp(0.0, games as f64)
+}
+
+/// Builds the pair records from the `op_counter` (games per pair under
+/// `min:max`, the wins of each side under `min:max:a_wins` and
+/// `min:max:b_wins`) and `op_matrix` (net weighted score per `a:b`) hashes.
+///
+/// Pairs only counted before the score scripts recorded the wins have them
+/// estimated from the net score instead, see `estimate_wins`.
+pub fn pair_records(
+    matrix: &HashMap<String, i64>,
+    counter: &HashMap<String, i64>,
+    base_multiplier: i32,
+) -> Vec<PairRecord> {
+    counter
+        .iter()
+        .filter(|&(_, &games)| games > 0)
+        .filter_map(|(key, &games)| {
+            let (a, b) = key.split_once(':')?;
+            let (a, b) = (a.parse::<i32>().ok()?, b.parse::<i32>().ok()?);
+            let recorded = |side: &str| counter.get(&format!("{key}:{side}")).copied();
+
+            let (a_wins, b_wins) = match (recorded("a_wins"), recorded("b_wins")) {
+                (None, None) => {
+                    let net = matrix.get(key).copied().unwrap_or(0);
+                    let leader_wins = estimate_wins(games, net, base_multiplier);
+                    if net >= 0 {
+                        (leader_wins, games as f64 - leader_wins)
+                    } else {
+                        (games as f64 - leader_wins, leader_wins)
+                    }
+                }
+                (a_wins, b_wins) => (a_wins.unwrap_or(0) as f64, b_wins.unwrap_or(0) as f64),
+            };
+
+            Some(PairRecord {
+                a,
+                b,
+                games: games as f64,
+                a_wins,
+                b_wins,
+            })
+        })
+        .collect()
+}
+
+/// Colley ratings for `operator_ids`. Pairs involving other operators are
+/// ignored. Returns `None` above `MAX_COLLEY_OPERATORS` or if the system can
+/// not be solved.
+pub fn colley_ratings(operator_ids: &[i32], pairs: &[PairRecord]) -> Option<HashMap<i32, f64>> {
+    let n = operator_ids.len();
+    if n == 0 || n > MAX_COLLEY_OPERATORS {
+        return None;
+    }
+
+    let index: HashMap<i32, usize> = operator_ids
+        .iter()
+        .enumerate()
+        .map(|(i, &id)| (id, i))
+        .collect();
+
+    // C = 2I + diag(games_i) - games_ij, b_i = 1 + (wins_i - losses_i) / 2
+    let mut c = vec![0.0; n * n];
+    let mut b = vec![1.0; n];
+    for i in 0..n {
+        c[i * n + i] = 2.0;
+    }
+
+    for pair in pairs {
+        let (Some(&i), Some(&j)) = (index.get(&pair.a), index.get(&pair.b)) else {
+            continue;
+        };
+        if i == j {
+            continue;
+        }
+
+        c[i * n + i] += pair.games;
+        c[j * n + j] += pair.games;
+        c[i * n + j] -= pair.games;
+        c[j * n + i] -= pair.games;
+        b[i] += (pair.a_wins - pair.b_wins) / 2.0;
+        b[j] += (pair.b_wins - pair.a_wins) / 2.0;
+    }
+
+    let ratings = cholesky_solve(c, b, n)?;
+
+    Some(operator_ids.iter().copied().zip(ratings).collect())
+}
+
+/// Head-to-head record of an operator against the rest of the pool, a pair
+/// is won when the net weighted score in `op_matrix` is positive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MajorityRecord {
+    pub id: i32,
+    pub wins: usize,
+    pub losses: usize,
+    pub ties: usize,
+}
+
+/// The Smith set of `operator_ids`: the smallest set of operators that each
+/// beat every operator outside of it head-to-head. A single member is the
+/// Condorcet winner. Pairs never compared count as ties. Members are returned
+/// by Copeland score (two per win, one per tie), ties by id.
+///
+/// Runs in `O(n²)` for `n` operators: the members are always a prefix of the
+/// Copeland order, found by growing the prefix until no member fails to beat
+/// an operator behind it. Returns `None` above `MAX_CONDORCET_OPERATORS`.
+pub fn smith_set(
+    operator_ids: &[i32],
+    matrix: &HashMap<String, i64>,
+) -> Option<Vec<MajorityRecord>> {
+    let n = operator_ids.len();
+    if n > MAX_CONDORCET_OPERATORS {
+        return None;
+    }
+
+    let beats: HashSet<(i32, i32)> = matrix
+        .iter()
+        .filter(|&(_, &net)| net > 0)
+        .filter_map(|(key, _)| {
+            let (a, b) = key.split_once(':')?;
+            Some((a.parse::<i32>().ok()?, b.parse::<i32>().ok()?))
+        })
+        .collect();
+
+    let mut records: Vec<MajorityRecord> = operator_ids
+        .iter()
+        .map(|&id| {
+            let mut record = MajorityRecord {
+                id,
+                wins: 0,
+                losses: 0,
+                ties: 0,
+            };
+            for &other in operator_ids.iter().filter(|&&other| other != id) {
+                if beats.contains(&(id, other)) {
+                    record.wins += 1;
+                } else if beats.contains(&(other, id)) {
+                    record.losses += 1;
+                } else {
+                    record.ties += 1;
+                }
+            }
+            record
+        })
+        .collect();
+    records.sort_by(|a, b| {
+        (2 * b.wins + b.ties)
+            .cmp(&(2 * a.wins + a.ties))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+
+    // operators an operator of the set does not beat have to join it, and
+    // the operators already checked keep beating the shrinking rest
+    let mut size = n.min(1);
+    let mut checked = 0;
+    while checked < size {
+        let id = records[checked].id;
+        for (i, other) in records.iter().enumerate().skip(size) {
+            if !beats.contains(&(id, other.id)) {
+                size = i + 1;
+            }
+        }
+        checked += 1;
+    }
+
+    records.truncate(size);
+    Some(records)
+}
+
+/// Solves `a x = b` for a symmetric positive definite `n x n` matrix stored
+/// row-major.
+fn cholesky_solve(mut a: Vec<f64>, mut b: Vec<f64>, n: usize) -> Option<Vec<f64>> {
+    // in-place lower triangular factor L with A = L L^T
+    for j in 0..n {
+        let mut diag = a[j * n + j];
+        for k in 0..j {
+            diag -= a[j * n + k] * a[j * n + k];
+        }
+        if diag <= 0.0 || !diag.is_finite() {
+            return None;
+        }
+        let diag = diag.sqrt();
+        a[j * n + j] = diag;
+
+        for i in j + 1..n {
+            let mut value = a[i * n + j];
+            for k in 0..j {
+                value -= a[i * n + k] * a[j * n + k];
+            }
+            a[i * n + j] = value / diag;
+        }
+    }
+
+    // forward substitution L y = b
+    for i in 0..n {
+        for k in 0..i {
+            b[i] -= a[i * n + k] * b[k];
+        }
+        b[i] /= a[i * n + i];
+    }
+
+    // back substitution L^T x = y
+    for i in (0..n).rev() {
+        for k in i + 1..n {
+            b[i] -= a[k * n + i] * b[k];
+        }
+        b[i] /= a[i * n + i];
+    }
+
+    Some(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(a: i32, b: i32, games: f64, a_wins: f64) -> PairRecord {
+        PairRecord {
+            a,
+            b,
+            games,
+            a_wins,
+            b_wins: games - a_wins,
+        }
+    }
+
+    #[test]
+    fn test_colley_without_games() {
+        let ratings = colley_ratings(&[1, 2, 3], &[]).unwrap();
+        for rating in ratings.values() {
+            assert!((rating - 0.5).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_colley_single_game() {
+        // classic example: one win gives 5/8 and 3/8
+        let ratings = colley_ratings(&[1, 2], &[record(1, 2, 1.0, 1.0)]).unwrap();
+        assert!((ratings[&1] - 0.625).abs() < 1e-9);
+        assert!((ratings[&2] - 0.375).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_colley_strength_of_schedule() {
+        // 1 and 3 both won once, but 1 beat 2 who beat 4 while 3 beat 4
+        let pairs = [
+            record(1, 2, 1.0, 1.0),
+            record(2, 4, 1.0, 1.0),
+            record(3, 4, 1.0, 1.0),
+        ];
+        let ratings = colley_ratings(&[1, 2, 3, 4], &pairs).unwrap();
+
+        assert!(ratings[&1] > ratings[&3]);
+        let total: f64 = ratings.values().sum();
+        assert!((total - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_colley_operator_cap() {
+        let ids: Vec<i32> = (0..=MAX_COLLEY_OPERATORS as i32).collect();
+        assert!(colley_ratings(&ids, &[]).is_none());
+        assert!(colley_ratings(&[], &[]).is_none());
+    }
+
+    fn beats(pairs: &[(i32, i32)]) -> HashMap<String, i64> {
+        pairs
+            .iter()
+            .flat_map(|&(winner, loser)| {
+                [
+                    (format!("{winner}:{loser}"), 100),
+                    (format!("{loser}:{winner}"), -100),
+                ]
+            })
+            .collect()
+    }
+
+    fn smith_ids(operator_ids: &[i32], matrix: &HashMap<String, i64>) -> Vec<i32> {
+        smith_set(operator_ids, matrix)
+            .unwrap()
+            .iter()
+            .map(|record| record.id)
+            .collect()
+    }
+
+    #[test]
+    fn test_condorcet_winner() {
+        let matrix = beats(&[(1, 2), (1, 3), (2, 3)]);
+        let set = smith_set(&[3, 2, 1], &matrix).unwrap();
+        assert_eq!(
+            set,
+            [MajorityRecord {
+                id: 1,
+                wins: 2,
+                losses: 0,
+                ties: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_smith_set_cycle() {
+        // 1 > 2 > 3 > 1 all beat 4, which beats 5
+        let matrix = beats(&[(1, 2), (2, 3), (3, 1), (1, 4), (2, 4), (3, 4), (4, 5)]);
+        assert_eq!(smith_ids(&[1, 2, 3, 4], &matrix), [1, 2, 3]);
+
+        // 5 is never compared with the cycle, so it ties its members and
+        // joins; 4 beats 5 and has to follow it into the set
+        assert_eq!(smith_ids(&[1, 2, 3, 4, 5], &matrix), [1, 2, 3, 5, 4]);
+    }
+
+    #[test]
+    fn test_smith_set_late_member() {
+        // 1 has the best Copeland score but ties 4, which only beats 5
+        let mut matrix = beats(&[(1, 2), (1, 3), (1, 5), (2, 3), (2, 5), (3, 5), (4, 5)]);
+        matrix.extend(beats(&[(2, 4), (3, 4)]));
+        assert_eq!(smith_ids(&[1, 2, 3, 4, 5], &matrix), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_smith_set_limits() {
+        assert_eq!(smith_set(&[], &HashMap::new()), Some(vec![]));
+        assert_eq!(smith_ids(&[7], &HashMap::new()), [7]);
+        // nothing compared yet, everyone ties
+        assert_eq!(smith_ids(&[2, 1], &HashMap::new()), [1, 2]);
+
+        let ids: Vec<i32> = (0..=MAX_CONDORCET_OPERATORS as i32).collect();
+        assert!(smith_set(&ids, &HashMap::new()).is_none());
+    }
+
+    #[test]
+    fn test_pair_records() {
+        let matrix = HashMap::from([("1:2".to_string(), -200), ("2:1".to_string(), 200)]);
+        let counter = HashMap::from([("1:2".to_string(), 4)]);
+
+        let records = pair_records(&matrix, &counter, 100);
+        assert_eq!(records, vec![record(1, 2, 4.0, 1.0)]);
+    }
+
+    #[test]
+    fn test_pair_records_use_recorded_wins() {
+        // one heavy win of 1 outweighs three light wins of 2
+        let matrix = HashMap::from([("1:2".to_string(), 70), ("2:1".to_string(), -70)]);
+        let counter = HashMap::from([
+            ("1:2".to_string(), 4),
+            ("1:2:a_wins".to_string(), 1),
+            ("1:2:b_wins".to_string(), 3),
+        ]);
+
+        let records = pair_records(&matrix, &counter, 10);
+        assert_eq!(records, vec![record(1, 2, 4.0, 1.0)]);
+    }
+}
@@ -0,0 +1,127 @@
+use redis::aio::MultiplexedConnection;
+
+use crate::config::RecentlyShownConfig;
+
+/// Trims the recently shown set of `KEYS[1]` to the window and returns the
+/// operators left in it. Operators passed after the first three arguments are
+/// added first, scored with the current time.
+///
+/// ARGV: now (ms), window (ms), max operators, served operator ids...
+pub const LUA_SCRIPT_RECENTLY_SHOWN: &str = r#"
+local now = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local max_operators = tonumber(ARGV[3])
+
+if #ARGV > 3 then
+    for i = 4, #ARGV do
+        redis.call('ZADD', KEYS[1], now, ARGV[i])
+    end
+    redis.call('ZREMRANGEBYRANK', KEYS[1], 0, -max_operators - 1)
+    redis.call('PEXPIRE', KEYS[1], window)
+end
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - window)
+
+return redis.call('ZRANGE', KEYS[1], 0, -1)
+"#;
+
+/// Operators the ballots of each topic served within `window_ms`, read by the
+/// `AvoidRecentlyShown` pairing constraint. Reading and recording go through
+/// the same script, and only the read waits for Redis.
+#[derive(Clone, Debug)]
+pub struct RecentlyShown {
+    config: RecentlyShownConfig,
+    script: redis::Script,
+}
+
+impl RecentlyShown {
+    pub fn new(config: RecentlyShownConfig) -> Self {
+        Self {
+            config,
+            script: redis::Script::new(LUA_SCRIPT_RECENTLY_SHOWN),
+        }
+    }
+
+    /// Operators of the topic served within the window. Redis errors fall
+    /// back to none, which draws uniformly.
+    pub async fn load(&self, mut conn: MultiplexedConnection, topic_id: &str) -> Vec<i32> {
+        self.invoke(&mut conn, topic_id, &[])
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!(
+                    "Failed to read the recently shown operators of {}: {}",
+                    topic_id,
+                    e
+                );
+                Vec::new()
+            })
+    }
+
+    /// Adds a served pair to the topic's set in the background, trimming the
+    /// entries outside the window and the oldest ones past `max_operators`.
+    pub fn record(&self, mut conn: MultiplexedConnection, topic_id: &str, operators: [i32; 2]) {
+        let shown = self.clone();
+        let topic_id = topic_id.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = shown.invoke(&mut conn, &topic_id, &operators).await {
+                tracing::warn!(
+                    "Failed to record the shown operators of {}: {}",
+                    topic_id,
+                    e
+                );
+            }
+        });
+    }
+
+    async fn invoke(
+        &self,
+        conn: &mut MultiplexedConnection,
+        topic_id: &str,
+        operators: &[i32],
+    ) -> redis::RedisResult<Vec<i32>> {
+        let mut invocation = self.script.key(RecentlyShownConfig::key(topic_id));
+        invocation
+            .arg(chrono::Utc::now().timestamp_millis())
+            .arg(self.config.window_ms)
+            .arg(self.config.max_operators)
+            .arg(operators);
+        invocation.invoke_async(conn).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Needs a local Redis: `cargo test -p share recently_shown -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_recently_shown_keeps_latest_within_window() {
+        let client = redis::Client::open("redis://127.0.0.1:6379").unwrap();
+        let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+        let shown = RecentlyShown::new(RecentlyShownConfig {
+            window_ms: 60_000,
+            max_operators: 3,
+        });
+        let topic_id = "recently-shown-test";
+
+        assert!(shown.load(conn.clone(), topic_id).await.is_empty());
+        shown
+            .invoke(&mut conn, topic_id, &[1001, 1002])
+            .await
+            .unwrap();
+        let latest = shown
+            .invoke(&mut conn, topic_id, &[1003, 1004])
+            .await
+            .unwrap();
+        // the oldest operator falls out past max_operators
+        assert_eq!(latest.len(), 3);
+        assert!(latest.contains(&1003) && latest.contains(&1004));
+        assert_eq!(shown.load(conn.clone(), topic_id).await.len(), 3);
+
+        let _: () = redis::cmd("DEL")
+            .arg(RecentlyShownConfig::key(topic_id))
+            .query_async(&mut conn)
+            .await
+            .unwrap();
+    }
+}
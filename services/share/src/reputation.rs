@@ -0,0 +1,304 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use redis::aio::ConnectionLike;
+use sha2::Sha256;
+
+use crate::{config::ReputationConfig, models::database::BallotInfo};
+
+/// Overrides `vote.reputation.token_secret` from the config file.
+pub const USER_TOKEN_SECRET_ENV: &str = "ARK_VOTE_USER_TOKEN_SECRET";
+
+/// Request header carrying the signed user token on `/ballot/save`.
+pub const USER_TOKEN_HEADER: &str = "X-User-Token";
+
+/// Redis hash mapping user ids to their reputation multiplier.
+pub const USER_REPUTATION_KEY: &str = "user_rep";
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum UserTokenError {
+    #[error("malformed user token")]
+    Malformed,
+    #[error("invalid user token signature")]
+    InvalidSignature,
+    #[error("expired user token")]
+    Expired,
+}
+
+/// Verifies user tokens of the form
+/// `<user_id>.<expires_at>.<base64url(hmac_sha256(<user_id>.<expires_at>))>`,
+/// `expires_at` in unix seconds. The expiry is signed with the user id, so a
+/// leaked token stops counting once it passes.
+#[derive(Clone)]
+pub struct UserTokenVerifier(Arc<[u8]>);
+
+impl UserTokenVerifier {
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self(Arc::from(secret.as_ref()))
+    }
+
+    /// Returns `None` when reputation weighting is disabled or no secret is
+    /// configured, in which case user tokens are ignored.
+    pub fn from_config(config: &ReputationConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        let secret =
+            std::env::var(USER_TOKEN_SECRET_ENV).unwrap_or_else(|_| config.token_secret.clone());
+        if secret.is_empty() {
+            tracing::warn!("Reputation is enabled without a token secret, ignoring user tokens");
+            return None;
+        }
+
+        Some(Self::new(secret))
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    pub fn sign(&self, user_id: &str, expires_at: i64) -> String {
+        let payload = format!("{user_id}.{expires_at}");
+        let signature = self.mac(&payload).finalize().into_bytes();
+        format!("{payload}.{}", URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    /// Returns the user id of a correctly signed token that has not expired
+    /// at `now`, in unix seconds.
+    pub fn verify<'a>(&self, token: &'a str, now: i64) -> Result<&'a str, UserTokenError> {
+        let (payload, signature) = token
+            .trim()
+            .rsplit_once('.')
+            .ok_or(UserTokenError::Malformed)?;
+        let (user_id, expires_at) = payload
+            .rsplit_once('.')
+            .filter(|(user_id, _)| !user_id.is_empty())
+            .ok_or(UserTokenError::Malformed)?;
+        let expires_at: i64 = expires_at.parse().map_err(|_| UserTokenError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| UserTokenError::Malformed)?;
+
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| UserTokenError::InvalidSignature)?;
+        if expires_at <= now {
+            return Err(UserTokenError::Expired);
+        }
+
+        Ok(user_id)
+    }
+}
+
+/// Resolves the user id of a ballot from the user token header. Tokens are
+/// ignored while reputation is disabled, but a present token that fails
+/// verification or has expired is rejected.
+pub fn authenticate_user(
+    verifier: Option<&UserTokenVerifier>,
+    token: Option<&str>,
+) -> Result<Option<String>, UserTokenError> {
+    match (verifier, token) {
+        (Some(verifier), Some(token)) => verifier
+            .verify(token, chrono::Utc::now().timestamp())
+            .map(|id| Some(id.to_string())),
+        _ => Ok(None),
+    }
+}
+
+/// Reads the reputation of every user in the batch from the `user_rep` hash.
+/// Users without an entry are left out.
+pub async fn fetch_user_reputations(
+    infos: &[&BallotInfo<'_>],
+    conn: &mut impl ConnectionLike,
+) -> redis::RedisResult<HashMap<String, f64>> {
+    let user_ids: Vec<&str> = infos
+        .iter()
+        .filter_map(|info| info.user_id.as_deref())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    if user_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let reputations: Vec<Option<f64>> = redis::cmd("HMGET")
+        .arg(USER_REPUTATION_KEY)
+        .arg(&user_ids)
+        .query_async(conn)
+        .await?;
+
+    Ok(user_ids
+        .into_iter()
+        .zip(reputations)
+        .filter_map(|(user_id, reputation)| Some((user_id.to_string(), reputation?)))
+        .collect())
+}
+
+/// Scales the IP multiplier by the user's reputation, clamped to
+/// `0..=max_reputation`. Ballots without a known reputation keep the IP
+/// multiplier.
+pub fn combine_multipliers(
+    ip_multiplier: i32,
+    reputation: Option<f64>,
+    max_reputation: f64,
+) -> i32 {
+    match reputation.filter(|r| r.is_finite()) {
+        Some(reputation) => {
+            let reputation = reputation.clamp(0.0, max_reputation.max(0.0));
+            (ip_multiplier as f64 * reputation).round() as i32
+        }
+        None => ip_multiplier,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use redis::Value;
+
+    use super::*;
+    use crate::test_util::ScriptedRedis;
+
+    const NOW: i64 = 1_750_000_000;
+
+    #[test]
+    fn test_verify_signed_token() {
+        let verifier = UserTokenVerifier::new("secret");
+        let token = verifier.sign("user.42", NOW + 60);
+
+        assert_eq!(verifier.verify(&token, NOW), Ok("user.42"));
+    }
+
+    #[test]
+    fn test_verify_rejects_expired_token() {
+        let verifier = UserTokenVerifier::new("secret");
+        let token = verifier.sign("user", NOW);
+
+        assert_eq!(verifier.verify(&token, NOW - 1), Ok("user"));
+        assert_eq!(verifier.verify(&token, NOW), Err(UserTokenError::Expired));
+
+        // the expiry is signed, pushing it back breaks the signature
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        assert_eq!(
+            verifier.verify(&format!("user.{}.{signature}", NOW + 3600), NOW),
+            Err(UserTokenError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_forged_token() {
+        let verifier = UserTokenVerifier::new("secret");
+        let forged = UserTokenVerifier::new("other").sign("user", NOW + 60);
+
+        assert_eq!(
+            verifier.verify(&forged, NOW),
+            Err(UserTokenError::InvalidSignature)
+        );
+
+        let token = verifier.sign("user", NOW + 60);
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        assert_eq!(
+            verifier.verify(&format!("admin.{}.{signature}", NOW + 60), NOW),
+            Err(UserTokenError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_verify_malformed_token() {
+        let verifier = UserTokenVerifier::new("secret");
+
+        assert_eq!(verifier.verify("user", NOW), Err(UserTokenError::Malformed));
+        assert_eq!(
+            verifier.verify("user.abc", NOW),
+            Err(UserTokenError::Malformed)
+        );
+        assert_eq!(
+            verifier.verify(".1.abc", NOW),
+            Err(UserTokenError::Malformed)
+        );
+        assert_eq!(
+            verifier.verify("user.soon.abc", NOW),
+            Err(UserTokenError::Malformed)
+        );
+        assert_eq!(
+            verifier.verify("user.1.!!", NOW),
+            Err(UserTokenError::Malformed)
+        );
+    }
+
+    #[test]
+    fn test_authenticate_user() {
+        let verifier = UserTokenVerifier::new("secret");
+        let token = verifier.sign("user", i64::MAX);
+
+        assert_eq!(
+            authenticate_user(Some(&verifier), Some(&token)),
+            Ok(Some("user".to_string()))
+        );
+        assert_eq!(authenticate_user(Some(&verifier), None), Ok(None));
+        assert_eq!(authenticate_user(None, Some("forged.abc")), Ok(None));
+        assert_eq!(
+            authenticate_user(Some(&verifier), Some("forged.1.abc")),
+            Err(UserTokenError::InvalidSignature)
+        );
+        assert_eq!(
+            authenticate_user(Some(&verifier), Some(&verifier.sign("user", 0))),
+            Err(UserTokenError::Expired)
+        );
+    }
+
+    #[test]
+    fn test_combine_multipliers() {
+        assert_eq!(combine_multipliers(100, None, 4.0), 100);
+        assert_eq!(combine_multipliers(100, Some(1.5), 4.0), 150);
+        assert_eq!(combine_multipliers(1, Some(2.0), 4.0), 2);
+        assert_eq!(combine_multipliers(100, Some(10.0), 4.0), 400);
+        assert_eq!(combine_multipliers(100, Some(-1.0), 4.0), 0);
+        assert_eq!(combine_multipliers(100, Some(f64::NAN), 4.0), 100);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_user_reputations() {
+        let info = |user_id: Option<&'static str>| BallotInfo {
+            topic_id: Cow::Borrowed("test_topic"),
+            ballot_id: Cow::Borrowed("1-abc"),
+            ip: Cow::Borrowed("127.0.0.1"),
+            user_agent: Cow::Borrowed(""),
+            timestamp: 0,
+            user_id: user_id.map(Cow::Borrowed),
+            throttled: false,
+        };
+        let anonymous = info(None);
+        let mut redis = ScriptedRedis::default();
+        let mut conn = &mut redis;
+        let reputations = fetch_user_reputations(&[&anonymous], &mut conn)
+            .await
+            .unwrap();
+        assert!(reputations.is_empty());
+        assert!(redis.commands.is_empty());
+
+        let (alice, bob) = (info(Some("alice")), info(Some("bob")));
+        let mut redis = ScriptedRedis::new([Value::Array(vec![
+            Value::BulkString(b"1.5".to_vec()),
+            Value::Nil,
+        ])]);
+        let mut conn = &mut redis;
+        let reputations = fetch_user_reputations(&[&alice, &alice, &bob], &mut conn)
+            .await
+            .unwrap();
+        // each user is asked once, whatever the order the set yields them in
+        let asked = &redis.commands[0][2..];
+        assert_eq!(asked.len(), 2);
+        let expected = HashMap::from([(asked[0].clone(), 1.5)]);
+        assert_eq!(reputations, expected);
+    }
+}
@@ -0,0 +1,121 @@
+use std::{future::Future, time::Duration};
+
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ConnectRetryConfig {
+    pub timeout_ms: u64,
+    pub max_retries: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for ConnectRetryConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 5_000,
+            max_retries: 10,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+impl ConnectRetryConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    /// Exponential backoff for the given (zero based) attempt, capped at `max_delay_ms`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        exponential_backoff(
+            Duration::from_millis(self.base_delay_ms),
+            Duration::from_millis(self.max_delay_ms),
+            attempt,
+        )
+    }
+}
+
+/// `base` doubled for each earlier retry, capped at `max`. `retry` is zero
+/// based, the first retry waits `base`.
+pub fn exponential_backoff(base: Duration, max: Duration, retry: u32) -> Duration {
+    base.saturating_mul(1u32 << retry.min(16)).min(max)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConnectError<E> {
+    #[error("connection attempt timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("{0}")]
+    Failed(E),
+}
+
+/// Runs `connect` until it succeeds, retrying with exponential backoff up to
+/// `config.max_retries` times. Each attempt is bounded by `config.timeout_ms`.
+pub async fn connect_with_retry<T, E, F, Fut>(
+    target: &str,
+    config: &ConnectRetryConfig,
+    mut connect: F,
+) -> Result<T, ConnectError<E>>
+where
+    E: std::fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 0;
+
+    loop {
+        let err = match tokio::time::timeout(config.timeout(), connect()).await {
+            Ok(Ok(value)) => {
+                if attempt > 0 {
+                    tracing::info!("connected to {} after {} retries", target, attempt);
+                }
+                return Ok(value);
+            }
+            Ok(Err(e)) => ConnectError::Failed(e),
+            Err(_) => ConnectError::Timeout(config.timeout()),
+        };
+
+        if attempt >= config.max_retries {
+            tracing::error!(
+                "failed to connect to {} after {} retries: {}",
+                target,
+                attempt,
+                err
+            );
+            return Err(err);
+        }
+
+        let delay = config.backoff(attempt);
+        attempt += 1;
+        tracing::warn!(
+            "failed to connect to {}: {}. retrying in {:?} ({}/{})",
+            target,
+            err,
+            delay,
+            attempt,
+            config.max_retries
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let config = ConnectRetryConfig {
+            timeout_ms: 100,
+            max_retries: 5,
+            base_delay_ms: 100,
+            max_delay_ms: 1_000,
+        };
+
+        assert_eq!(config.backoff(0), Duration::from_millis(100));
+        assert_eq!(config.backoff(2), Duration::from_millis(400));
+        assert_eq!(config.backoff(10), Duration::from_millis(1_000));
+        assert_eq!(config.backoff(u32::MAX), Duration::from_millis(1_000));
+    }
+}
@@ -0,0 +1,151 @@
+use redis::aio::MultiplexedConnection;
+
+use crate::models::topic_id::TopicId;
+
+/// Keys asked from each `SCAN` round trip while removing ip counters.
+const IP_COUNTER_SCAN_COUNT: usize = 1000;
+
+/// Deletes the Redis score keys and ip counters of a topic, returns the number
+/// of keys removed.
+///
+/// The score keys go in a single `DEL`, so the results never see a partially
+/// reset topic. The ip counters carry a TTL and only weigh later ballots, they
+/// are walked with `SCAN` afterwards and removed one batch at a time so Redis
+/// is not blocked for the whole keyspace.
+pub async fn reset_topic_scores(
+    connection: &mut MultiplexedConnection,
+    topic_id: &TopicId,
+) -> redis::RedisResult<i64> {
+    let mut removed: i64 = redis::cmd("DEL")
+        .arg(topic_id.op_stats_key())
+        .arg(topic_id.op_matrix_key())
+        .arg(topic_id.op_counter_key())
+        .arg(topic_id.op_cooccur_key())
+        .arg(topic_id.valid_ballots_count_key())
+        .query_async(connection)
+        .await?;
+
+    // topic ids contain no glob characters, the pattern needs no escaping
+    let pattern = topic_id.ip_counter_key("*");
+    let mut cursor = 0u64;
+    loop {
+        let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(&pattern)
+            .arg("COUNT")
+            .arg(IP_COUNTER_SCAN_COUNT)
+            .query_async(connection)
+            .await?;
+        if !keys.is_empty() {
+            removed += redis::cmd("UNLINK")
+                .arg(&keys)
+                .query_async::<i64>(connection)
+                .await?;
+        }
+        if next == 0 {
+            return Ok(removed);
+        }
+        cursor = next;
+    }
+}
+
+/// Splits the final order script output, the `op_stats` wins of every
+/// operator followed by their losses, into win and lose counts. Returns
+/// `None` if the script did not return exactly two values per operator.
+pub fn parse_operator_counts(
+    values: &[Option<String>],
+    num_operators: usize,
+) -> Option<(Vec<i64>, Vec<i64>)> {
+    if values.len() != 2 * num_operators {
+        return None;
+    }
+
+    let win_counts: Vec<i64> = values[..num_operators]
+        .iter()
+        .map(|v| v.as_ref().and_then(|s| s.parse().ok()).unwrap_or(0))
+        .collect();
+
+    let lose_counts: Vec<i64> = values[num_operators..]
+        .iter()
+        .map(|v| v.as_ref().and_then(|s| s.parse().ok()).unwrap_or(0))
+        .collect();
+
+    Some((win_counts, lose_counts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_operator_counts() {
+        let values = vec![
+            Some("10".to_string()),
+            Some("20".to_string()),
+            Some("5".to_string()),
+            Some("15".to_string()),
+        ];
+        let (wins, losses) = parse_operator_counts(&values, 2).unwrap();
+
+        assert_eq!(wins, vec![10, 20]);
+        assert_eq!(losses, vec![5, 15]);
+    }
+
+    #[test]
+    fn test_parse_operator_counts_length_mismatch() {
+        let values = vec![
+            Some("10".to_string()),
+            Some("20".to_string()),
+            Some("5".to_string()),
+        ];
+
+        assert!(parse_operator_counts(&values, 2).is_none());
+        assert!(parse_operator_counts(&values, 3).is_none());
+    }
+
+    /// Needs a local Redis: `cargo test -p share reset_topic_scores -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_reset_topic_scores_keeps_other_topics() {
+        let client = redis::Client::open("redis://127.0.0.1:6379").unwrap();
+        let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+        let topic = TopicId::parse("reset-test").unwrap();
+        let other = TopicId::parse("reset-test-other").unwrap();
+
+        let mut pipe = redis::pipe();
+        for id in [&topic, &other] {
+            pipe.hset(id.op_stats_key(), "1001:win", 3)
+                .hset(id.op_matrix_key(), "1001:1002", 3)
+                .hset(id.op_counter_key(), "1001:1002", 3)
+                .set(id.valid_ballots_count_key(), 3);
+            for n in 0..2500 {
+                pipe.set_ex(
+                    id.ip_counter_key(&format!("10.0.{}.{}", n / 256, n % 256)),
+                    1,
+                    60,
+                );
+            }
+        }
+        let _: () = pipe.query_async(&mut conn).await.unwrap();
+
+        // op_cooccur was never written, 4 score keys and 2500 counters remain
+        assert_eq!(reset_topic_scores(&mut conn, &topic).await.unwrap(), 2504);
+        assert_eq!(reset_topic_scores(&mut conn, &topic).await.unwrap(), 0);
+
+        let other_count: Option<i64> = redis::cmd("GET")
+            .arg(other.valid_ballots_count_key())
+            .query_async(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(other_count, Some(3));
+        let other_counter: Option<i64> = redis::cmd("GET")
+            .arg(other.ip_counter_key("10.0.0.0"))
+            .query_async(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(other_counter, Some(1));
+
+        reset_topic_scores(&mut conn, &other).await.unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+/// Alternate operator names from `[operators.aliases]`, kept apart from the
+/// character table. Lookups ignore case.
+#[derive(Clone, Debug, Default)]
+pub struct OperatorAliases {
+    by_operator: HashMap<i32, Vec<String>>,
+}
+
+impl OperatorAliases {
+    pub fn new<'a>(aliases: impl IntoIterator<Item = (&'a String, &'a i32)>) -> Self {
+        let mut by_operator: HashMap<i32, Vec<String>> = HashMap::new();
+        for (alias, &operator_id) in aliases {
+            let alias = alias.trim().to_lowercase();
+            if alias.is_empty() {
+                continue;
+            }
+            by_operator.entry(operator_id).or_default().push(alias);
+        }
+
+        Self { by_operator }
+    }
+
+    /// Whether `query` is contained in one of the operator's `names` or in one
+    /// of its aliases. An empty query matches every operator.
+    pub fn name_matches(&self, operator_id: i32, names: &[&str], query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        names
+            .iter()
+            .any(|name| name.to_lowercase().contains(&query))
+            || self
+                .by_operator
+                .get(&operator_id)
+                .is_some_and(|aliases| aliases.iter().any(|alias| alias.contains(&query)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases() -> OperatorAliases {
+        let config = HashMap::from([
+            ("Amiya-chan".to_string(), 2),
+            ("Bunny".to_string(), 2),
+            ("Doggo".to_string(), 7),
+        ]);
+        OperatorAliases::new(&config)
+    }
+
+    #[test]
+    fn test_name_matches_alias() {
+        let aliases = aliases();
+
+        assert!(aliases.name_matches(2, &["Amiya"], "amiya"));
+        assert!(aliases.name_matches(2, &["Amiya"], "bun"));
+        assert!(aliases.name_matches(2, &["Amiya"], " AMIYA-CHAN "));
+        assert!(aliases.name_matches(7, &["Texas"], "DOG"));
+        assert!(!aliases.name_matches(7, &["Texas"], "bunny"));
+        assert!(!aliases.name_matches(3, &["Kal'tsit"], "dog"));
+    }
+}
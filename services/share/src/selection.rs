@@ -0,0 +1,769 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use parking_lot::{Mutex, RwLock};
+use rand::{Rng, RngCore, SeedableRng as _, seq::IndexedRandom as _};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+
+use crate::models::{excel::CharacterInfo, topic_id::TopicId};
+
+/// How long the game counts read for `FavorUndersampled` are reused before
+/// the topic's `op_stats` hash is read again.
+pub const OPERATOR_GAMES_TTL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum PairingConstraint {
+    #[default]
+    Any,
+    SameRarity,
+    AdjacentRarity,
+    /// At least one side of the pair is an operator the client has not
+    /// recently picked as a winner.
+    AvoidRecentWinners,
+    /// Both sides are drawn with weights falling with the operator's game
+    /// count, so comparisons shift towards under-sampled operators.
+    FavorUndersampled,
+    /// Both sides are operators no ballot of the topic showed within
+    /// `vote.recently_shown.window_ms`, across all clients.
+    AvoidRecentlyShown,
+}
+
+impl PairingConstraint {
+    fn accepts(&self, a: i32, b: i32) -> bool {
+        match self {
+            PairingConstraint::Any
+            | PairingConstraint::AvoidRecentWinners
+            | PairingConstraint::FavorUndersampled
+            | PairingConstraint::AvoidRecentlyShown => true,
+            PairingConstraint::SameRarity => a == b,
+            PairingConstraint::AdjacentRarity => (a - b).abs() <= 1,
+        }
+    }
+}
+
+/// Sub professions excluded from every candidate pool regardless of the topic
+/// preset. Shared between clones so it can be updated at runtime.
+#[derive(Clone, Debug, Default)]
+pub struct SubProfessionBlocklist(Arc<RwLock<Vec<String>>>);
+
+impl SubProfessionBlocklist {
+    pub fn new(sub_professions: impl IntoIterator<Item = String>) -> Self {
+        let blocklist = Self::default();
+        blocklist.replace(sub_professions);
+        blocklist
+    }
+
+    pub fn replace(&self, sub_professions: impl IntoIterator<Item = String>) {
+        let mut sub_professions: Vec<String> = sub_professions.into_iter().collect();
+        sub_professions.sort_unstable();
+        sub_professions.dedup();
+        *self.0.write() = sub_professions;
+    }
+
+    pub fn sub_professions(&self) -> Vec<String> {
+        self.0.read().clone()
+    }
+
+    /// Removes operators of blocked sub professions from a generated pool.
+    pub fn retain_allowed(
+        &self,
+        mut pool: Vec<i32>,
+        character_infos: &[CharacterInfo],
+    ) -> Vec<i32> {
+        let blocked = self.0.read();
+        if blocked.is_empty() {
+            return pool;
+        }
+
+        let blocked_ids: HashSet<i32> = character_infos
+            .iter()
+            .filter(|c| c.matches_sub_professions(&blocked))
+            .map(|c| c.id)
+            .collect();
+        pool.retain(|id| !blocked_ids.contains(id));
+        pool
+    }
+}
+
+/// Seeded RNGs of the topics with a selection seed, see
+/// `vote.selection_seed`, so the pairs a topic serves repeat across runs given
+/// the same requests in the same order.
+///
+/// Each seeded topic has one `ChaCha8Rng`, whose stream is fixed across rand
+/// versions and platforms unlike `StdRng`, and the draws of all seeded topics
+/// share a mutex, held for one synchronous selection and never across an
+/// await. Serializing the draws keeps the sequence a function of the request
+/// order alone; deriving an RNG per request would need an input known before
+/// selection that repeats across runs, and the ballot id is neither. Topics
+/// without a seed use the thread RNG and never take the lock.
+///
+/// The RNG state lives in this process only. Each service instance draws its
+/// own sequence from the seed, so a topic served by several instances behind a
+/// load balancer is reproducible per instance, not as a whole.
+#[derive(Clone, Debug, Default)]
+pub struct SelectionRngs(Arc<Mutex<HashMap<String, (u64, ChaCha8Rng)>>>);
+
+impl SelectionRngs {
+    /// Runs `f` with the topic's RNG, reseeded when `seed` changed, or with
+    /// the thread RNG when `seed` is `None`.
+    pub fn with_rng<T>(
+        &self,
+        topic_id: &str,
+        seed: Option<u64>,
+        f: impl FnOnce(&mut dyn RngCore) -> T,
+    ) -> T {
+        let Some(seed) = seed else {
+            return f(&mut rand::rng());
+        };
+
+        let mut rngs = self.0.lock();
+        if rngs
+            .get(topic_id)
+            .is_none_or(|(current, _)| *current != seed)
+        {
+            rngs.insert(
+                topic_id.to_string(),
+                (seed, ChaCha8Rng::seed_from_u64(seed)),
+            );
+        }
+        let (_, rng) = rngs.get_mut(topic_id).expect("inserted above");
+        f(rng)
+    }
+}
+
+/// Games played per operator, the sum of its `op_stats` win and lose
+/// counters, weighting `FavorUndersampled` draws by `1 / (1 + games)^bias`.
+#[derive(Clone, Debug, Default)]
+pub struct OperatorGames {
+    games: HashMap<i32, i64>,
+    bias: f64,
+}
+
+impl OperatorGames {
+    pub fn new(games: HashMap<i32, i64>, bias: f64) -> Self {
+        Self { games, bias }
+    }
+
+    /// `op_stats` fields holding the win counts of `pool`, then its lose
+    /// counts.
+    pub fn op_stats_fields(pool: &[i32]) -> Vec<String> {
+        let wins = pool.iter().map(|id| format!("{id}:win"));
+        let loses = pool.iter().map(|id| format!("{id}:lose"));
+        wins.chain(loses).collect()
+    }
+
+    /// Reads the values of the `op_stats_fields` of `pool`, missing counters
+    /// counting as zero.
+    pub fn from_op_stats(pool: &[i32], values: &[Option<i64>], bias: f64) -> Self {
+        let count = |i: usize| values.get(i).copied().flatten().unwrap_or(0).max(0);
+        let games = pool
+            .iter()
+            .enumerate()
+            .map(|(i, &id)| (id, count(i) + count(pool.len() + i)))
+            .collect();
+        Self::new(games, bias)
+    }
+
+    fn weight(&self, id: i32) -> f64 {
+        let games = self.games.get(&id).copied().unwrap_or(0) as f64;
+        (1.0 + games).powf(-self.bias)
+    }
+}
+
+/// Game counts of each topic's candidate pool, read from `op_stats` at most
+/// once per `OPERATOR_GAMES_TTL` instead of on every ballot. The weights only
+/// steer the draw, a second of lag does not matter.
+#[derive(Clone, Debug, Default)]
+pub struct OperatorGamesCache(Arc<Mutex<HashMap<String, CachedGames>>>);
+
+/// Game counts with the time they were read.
+type CachedGames = (Instant, Arc<OperatorGames>);
+
+impl OperatorGamesCache {
+    /// Game counts of `pool`, cached while fresh and covering the whole pool.
+    /// Redis errors fall back to no counts, which draws uniformly, and are
+    /// not cached.
+    pub async fn get(
+        &self,
+        mut conn: impl redis::aio::ConnectionLike,
+        topic_id: &TopicId,
+        pool: &[i32],
+        bias: f64,
+    ) -> Arc<OperatorGames> {
+        let cached = self
+            .0
+            .lock()
+            .get(topic_id.as_str())
+            .and_then(|(at, games)| {
+                (at.elapsed() < OPERATOR_GAMES_TTL
+                    && games.bias == bias
+                    && pool.iter().all(|id| games.games.contains_key(id)))
+                .then(|| games.clone())
+            });
+        if let Some(games) = cached {
+            return games;
+        }
+
+        let values: redis::RedisResult<Vec<Option<i64>>> = redis::cmd("HMGET")
+            .arg(topic_id.op_stats_key())
+            .arg(OperatorGames::op_stats_fields(pool))
+            .query_async(&mut conn)
+            .await;
+        match values {
+            Ok(values) => {
+                let games = Arc::new(OperatorGames::from_op_stats(pool, &values, bias));
+                self.0.lock().insert(
+                    topic_id.as_str().to_string(),
+                    (Instant::now(), games.clone()),
+                );
+                games
+            }
+            Err(e) => {
+                tracing::warn!("Failed to read the game counts of {}: {}", topic_id, e);
+                Arc::default()
+            }
+        }
+    }
+}
+
+/// Per-request inputs of the constraints that look past the pool, each field
+/// read by one constraint only and ignored by the others.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SelectionContext<'a> {
+    /// Operators the client recently voted for, read by `AvoidRecentWinners`.
+    pub recent_winners: &'a [i32],
+    /// Operators recently served to the client, read by `AvoidRecentlyShown`.
+    pub recently_shown: &'a [i32],
+    /// Game counts of the pool, read by `FavorUndersampled`; `None` draws
+    /// uniformly.
+    pub games: Option<&'a OperatorGames>,
+}
+
+/// Picks two distinct operators from `pool`. The first one is chosen uniformly,
+/// the second one among the operators satisfying `constraint`; if fewer than two
+/// operators qualify, falls back to a cross-rarity pairing.
+pub fn select_pair<R: Rng + ?Sized>(
+    pool: &[i32],
+    character_infos: &[CharacterInfo],
+    constraint: PairingConstraint,
+    context: &SelectionContext<'_>,
+    rng: &mut R,
+) -> Option<(i32, i32)> {
+    if pool.len() < 2 {
+        return None;
+    }
+
+    match constraint {
+        PairingConstraint::Any => {
+            let selected: [i32; 2] = pool.choose_multiple_array(rng)?;
+            return Some((selected[0], selected[1]));
+        }
+        PairingConstraint::AvoidRecentWinners => {
+            return select_pair_avoiding(pool, context.recent_winners, rng);
+        }
+        PairingConstraint::FavorUndersampled => {
+            let no_games = OperatorGames::default();
+            return select_pair_weighted(pool, context.games.unwrap_or(&no_games), rng);
+        }
+        PairingConstraint::AvoidRecentlyShown => {
+            return select_pair_unshown(pool, context.recently_shown, rng);
+        }
+        PairingConstraint::SameRarity | PairingConstraint::AdjacentRarity => {}
+    }
+
+    let rarities: HashMap<i32, i32> = character_infos
+        .iter()
+        .map(|c| (c.id, c.rarity.to_numeric()))
+        .collect();
+
+    let first = *pool.choose(rng)?;
+    let first_rarity = rarities.get(&first).copied();
+
+    let candidates: Vec<i32> = pool
+        .iter()
+        .copied()
+        .filter(|&id| id != first)
+        .filter(|id| match (first_rarity, rarities.get(id)) {
+            (Some(a), Some(&b)) => constraint.accepts(a, b),
+            _ => false,
+        })
+        .collect();
+
+    let second = match candidates.choose(rng) {
+        Some(&id) => id,
+        None => {
+            tracing::debug!(
+                "no {:?} partner for operator {}, falling back to any rarity",
+                constraint,
+                first
+            );
+            let others: Vec<i32> = pool.iter().copied().filter(|&id| id != first).collect();
+            *others.choose(rng)?
+        }
+    };
+
+    Some((first, second))
+}
+
+/// Pairs an operator outside `recent_winners` with any other operator, in
+/// random order. Falls back to a uniform pair when every operator in the pool
+/// won recently.
+fn select_pair_avoiding<R: Rng + ?Sized>(
+    pool: &[i32],
+    recent_winners: &[i32],
+    rng: &mut R,
+) -> Option<(i32, i32)> {
+    let recent: HashSet<i32> = recent_winners.iter().copied().collect();
+    let fresh: Vec<i32> = pool
+        .iter()
+        .copied()
+        .filter(|id| !recent.contains(id))
+        .collect();
+
+    let Some(&first) = fresh.choose(rng) else {
+        tracing::debug!("every operator won recently, falling back to a random pair");
+        let selected: [i32; 2] = pool.choose_multiple_array(rng)?;
+        return Some((selected[0], selected[1]));
+    };
+
+    let others: Vec<i32> = pool.iter().copied().filter(|&id| id != first).collect();
+    let second = *others.choose(rng)?;
+
+    match rng.random_bool(0.5) {
+        true => Some((first, second)),
+        false => Some((second, first)),
+    }
+}
+
+/// Pairs two operators outside `recently_shown`. Falls back to a uniform pair
+/// when fewer than two operators of the pool were not shown recently.
+fn select_pair_unshown<R: Rng + ?Sized>(
+    pool: &[i32],
+    recently_shown: &[i32],
+    rng: &mut R,
+) -> Option<(i32, i32)> {
+    let shown: HashSet<i32> = recently_shown.iter().copied().collect();
+    let unshown: Vec<i32> = pool
+        .iter()
+        .copied()
+        .filter(|id| !shown.contains(id))
+        .collect();
+
+    let selected: [i32; 2] = match unshown.choose_multiple_array(rng) {
+        Some(selected) => selected,
+        None => {
+            tracing::debug!(
+                "{} of {} operators shown recently, falling back to a random pair",
+                pool.len() - unshown.len(),
+                pool.len()
+            );
+            pool.choose_multiple_array(rng)?
+        }
+    };
+    Some((selected[0], selected[1]))
+}
+
+/// Draws two distinct operators with `OperatorGames` weights. Falls back to a
+/// uniform pair when the weights can not be sampled.
+fn select_pair_weighted<R: Rng + ?Sized>(
+    pool: &[i32],
+    games: &OperatorGames,
+    rng: &mut R,
+) -> Option<(i32, i32)> {
+    let mut weighted = || -> Option<(i32, i32)> {
+        let first = *pool.choose_weighted(rng, |&id| games.weight(id)).ok()?;
+        let others: Vec<i32> = pool.iter().copied().filter(|&id| id != first).collect();
+        let second = *others.choose_weighted(rng, |&id| games.weight(id)).ok()?;
+        Some((first, second))
+    };
+
+    weighted().or_else(|| {
+        tracing::debug!("invalid operator weights, falling back to a random pair");
+        let selected: [i32; 2] = pool.choose_multiple_array(rng)?;
+        Some((selected[0], selected[1]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use redis::Value;
+
+    use super::*;
+    use crate::{
+        models::excel::{ProfessionCategory, RarityRank},
+        test_util::ScriptedRedis,
+    };
+
+    fn character(id: i32, rarity: RarityRank) -> CharacterInfo {
+        CharacterInfo {
+            id,
+            name: format!("op_{id}"),
+            rarity,
+            profession: ProfessionCategory::WARRIOR,
+            sub_profession_id: "centurion".to_string(),
+            is_not_obtainable: false,
+        }
+    }
+
+    fn character_with_sub_profession(id: i32, sub_profession_id: &str) -> CharacterInfo {
+        CharacterInfo {
+            sub_profession_id: sub_profession_id.to_string(),
+            ..character(id, RarityRank::Tier6)
+        }
+    }
+
+    fn create_test_characters() -> Vec<CharacterInfo> {
+        vec![
+            character(1001, RarityRank::Tier6),
+            character(1002, RarityRank::Tier6),
+            character(2001, RarityRank::Tier5),
+            character(2002, RarityRank::Tier5),
+            character(3001, RarityRank::Tier3),
+        ]
+    }
+
+    fn rarity_of(characters: &[CharacterInfo], id: i32) -> i32 {
+        characters
+            .iter()
+            .find(|c| c.id == id)
+            .unwrap()
+            .rarity
+            .to_numeric()
+    }
+
+    #[test]
+    fn test_select_pair_same_rarity() {
+        let characters = create_test_characters();
+        let pool = vec![1001, 1002, 2001, 2002];
+        let mut rng = rand::rng();
+
+        for _ in 0..100 {
+            let (left, right) = select_pair(
+                &pool,
+                &characters,
+                PairingConstraint::SameRarity,
+                &SelectionContext::default(),
+                &mut rng,
+            )
+            .unwrap();
+            assert_ne!(left, right);
+            assert_eq!(rarity_of(&characters, left), rarity_of(&characters, right));
+        }
+    }
+
+    #[test]
+    fn test_select_pair_adjacent_rarity() {
+        let characters = create_test_characters();
+        let pool = vec![1001, 2001, 3001];
+        let mut rng = rand::rng();
+
+        for _ in 0..100 {
+            let (left, right) = select_pair(
+                &pool,
+                &characters,
+                PairingConstraint::AdjacentRarity,
+                &SelectionContext::default(),
+                &mut rng,
+            )
+            .unwrap();
+            assert_ne!(left, right);
+            if left != 3001 {
+                assert!((rarity_of(&characters, left) - rarity_of(&characters, right)).abs() <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_select_pair_falls_back_to_cross_rarity() {
+        let characters = create_test_characters();
+        let pool = vec![1001, 3001];
+        let mut rng = rand::rng();
+
+        let (left, right) = select_pair(
+            &pool,
+            &characters,
+            PairingConstraint::SameRarity,
+            &SelectionContext::default(),
+            &mut rng,
+        )
+        .unwrap();
+        assert_ne!(left, right);
+        assert!(pool.contains(&left) && pool.contains(&right));
+    }
+
+    #[test]
+    fn test_select_pair_insufficient() {
+        let characters = create_test_characters();
+        let mut rng = rand::rng();
+
+        assert!(
+            select_pair(
+                &[1001],
+                &characters,
+                PairingConstraint::Any,
+                &SelectionContext::default(),
+                &mut rng
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_select_pair_avoids_recent_winners() {
+        let characters = create_test_characters();
+        let pool = vec![1001, 1002, 2001, 2002];
+        let recent_winners = [1001, 1002, 2001];
+        let mut rng = rand::rng();
+
+        for _ in 0..100 {
+            let (left, right) = select_pair(
+                &pool,
+                &characters,
+                PairingConstraint::AvoidRecentWinners,
+                &SelectionContext {
+                    recent_winners: &recent_winners,
+                    ..Default::default()
+                },
+                &mut rng,
+            )
+            .unwrap();
+            assert_ne!(left, right);
+            assert!(left == 2002 || right == 2002);
+        }
+    }
+
+    #[test]
+    fn test_select_pair_avoiding_falls_back_to_random() {
+        let characters = create_test_characters();
+        let pool = vec![1001, 1002];
+        let mut rng = rand::rng();
+
+        let (left, right) = select_pair(
+            &pool,
+            &characters,
+            PairingConstraint::AvoidRecentWinners,
+            &SelectionContext {
+                recent_winners: &[1001, 1002],
+                ..Default::default()
+            },
+            &mut rng,
+        )
+        .unwrap();
+        assert_ne!(left, right);
+        assert!(pool.contains(&left) && pool.contains(&right));
+    }
+
+    #[test]
+    fn test_blocked_sub_professions_never_selected() {
+        use crate::models::candidate_pool_preset::CandidatePoolPreset;
+
+        let characters = vec![
+            character_with_sub_profession(1001, "centurion"),
+            character_with_sub_profession(1002, "centurion"),
+            character_with_sub_profession(1003, "instructor"),
+            character_with_sub_profession(1004, "lord"),
+        ];
+        let blocklist = SubProfessionBlocklist::new(["instructor".to_string()]);
+        let pool = blocklist.retain_allowed(
+            CandidatePoolPreset::Custom {
+                operator_ids: vec![1001, 1002, 1003, 1004],
+            }
+            .generate_pool(&characters),
+            &characters,
+        );
+        assert_eq!(pool, vec![1001, 1002, 1004]);
+
+        let mut rng = rand::rng();
+        for _ in 0..100 {
+            let (left, right) = select_pair(
+                &pool,
+                &characters,
+                PairingConstraint::Any,
+                &SelectionContext::default(),
+                &mut rng,
+            )
+            .unwrap();
+            assert_ne!(left, 1003);
+            assert_ne!(right, 1003);
+        }
+
+        blocklist.replace([]);
+        assert_eq!(
+            blocklist.retain_allowed(vec![1001, 1003], &characters),
+            vec![1001, 1003]
+        );
+    }
+
+    #[test]
+    fn test_select_pair_favors_undersampled() {
+        let pool: Vec<i32> = (1..=10).collect();
+        // operators 1..=5 played thousands of games, 6..=10 almost none
+        let values: Vec<Option<i64>> = pool
+            .iter()
+            .chain(&pool)
+            .map(|&id| Some(if id <= 5 { 5000 } else { 1 }))
+            .collect();
+        let games = OperatorGames::from_op_stats(&pool, &values, 1.0);
+        let mut rng = rand::rng();
+
+        let mut undersampled = 0;
+        for _ in 0..1000 {
+            let (left, right) = select_pair(
+                &pool,
+                &[],
+                PairingConstraint::FavorUndersampled,
+                &SelectionContext {
+                    games: Some(&games),
+                    ..Default::default()
+                },
+                &mut rng,
+            )
+            .unwrap();
+            assert_ne!(left, right);
+            undersampled += [left, right].iter().filter(|&&id| id > 5).count();
+        }
+        assert!(undersampled > 1900, "{undersampled} of 2000 picks");
+
+        let unbiased = OperatorGames::from_op_stats(&pool, &values, 0.0);
+        assert_eq!(unbiased.weight(1), unbiased.weight(10));
+    }
+
+    #[test]
+    fn test_operator_games_from_op_stats() {
+        let pool = [1, 2];
+        assert_eq!(
+            OperatorGames::op_stats_fields(&pool),
+            ["1:win", "2:win", "1:lose", "2:lose"]
+        );
+
+        let games = OperatorGames::from_op_stats(&pool, &[Some(3), None, Some(4), Some(5)], 1.0);
+        assert_eq!(games.games, HashMap::from([(1, 7), (2, 5)]));
+    }
+
+    #[tokio::test]
+    async fn test_operator_games_cache_reads_op_stats_once() {
+        let topic_id = TopicId::parse("games-cache-test").unwrap();
+        let cache = OperatorGamesCache::default();
+        let counts = [Value::Int(3), Value::Nil, Value::Int(4), Value::Int(5)];
+        let mut redis = ScriptedRedis::new([Value::Array(counts.to_vec())]);
+
+        let games = cache.get(&mut redis, &topic_id, &[1, 2], 1.0).await;
+        assert_eq!(games.games, HashMap::from([(1, 7), (2, 5)]));
+        let cached = cache.get(&mut redis, &topic_id, &[2, 1], 1.0).await;
+        assert!(Arc::ptr_eq(&games, &cached));
+        assert_eq!(redis.commands.len(), 1);
+
+        // an operator new to the pool reads the counts again, a failed read
+        // draws uniformly and is not cached
+        let uncached = cache.get(&mut redis, &topic_id, &[1, 2, 3], 1.0).await;
+        assert!(uncached.games.is_empty());
+        assert_eq!(redis.commands.len(), 2);
+    }
+
+    #[test]
+    fn test_select_pair_avoids_recently_shown() {
+        let pool = vec![1001, 1002, 2001, 2002, 3001];
+        let recently_shown = [1001, 2001, 3001];
+        let mut rng = rand::rng();
+
+        for _ in 0..100 {
+            let (left, right) = select_pair(
+                &pool,
+                &[],
+                PairingConstraint::AvoidRecentlyShown,
+                &SelectionContext {
+                    recently_shown: &recently_shown,
+                    ..Default::default()
+                },
+                &mut rng,
+            )
+            .unwrap();
+            assert_ne!(left, right);
+            assert!(!recently_shown.contains(&left));
+            assert!(!recently_shown.contains(&right));
+        }
+    }
+
+    #[test]
+    fn test_select_pair_unshown_falls_back_to_random() {
+        let pool = vec![1001, 1002, 2001];
+        let mut rng = rand::rng();
+
+        let (left, right) = select_pair(
+            &pool,
+            &[],
+            PairingConstraint::AvoidRecentlyShown,
+            &SelectionContext {
+                recently_shown: &[1001, 1002],
+                ..Default::default()
+            },
+            &mut rng,
+        )
+        .unwrap();
+        assert_ne!(left, right);
+        assert!(pool.contains(&left) && pool.contains(&right));
+    }
+
+    fn seeded_pair(rngs: &SelectionRngs, topic_id: &str, pool: &[i32]) -> (i32, i32) {
+        rngs.with_rng(topic_id, Some(42), |rng| {
+            select_pair(
+                pool,
+                &[],
+                PairingConstraint::Any,
+                &SelectionContext::default(),
+                rng,
+            )
+            .unwrap()
+        })
+    }
+
+    #[test]
+    fn test_seeded_selection_repeats_across_runs() {
+        let pool: Vec<i32> = (1..=50).collect();
+        let run = || {
+            let rngs = SelectionRngs::default();
+            (0..20)
+                .map(|_| seeded_pair(&rngs, "test_topic", &pool))
+                .collect::<Vec<_>>()
+        };
+
+        let first = run();
+        assert_eq!(first, run());
+
+        // draws for another topic do not shift the sequence
+        let rngs = SelectionRngs::default();
+        let interleaved: Vec<_> = (0..20)
+            .map(|_| {
+                seeded_pair(&rngs, "other_topic", &pool);
+                seeded_pair(&rngs, "test_topic", &pool)
+            })
+            .collect();
+        assert_eq!(first, interleaved);
+    }
+
+    #[test]
+    fn test_selection_reseeds_on_seed_change() {
+        let rngs = SelectionRngs::default();
+        let draw = |seed| rngs.with_rng("test_topic", Some(seed), |rng| rng.next_u64());
+
+        let first = draw(1);
+        let second = draw(1);
+        assert_ne!(first, second);
+        draw(2);
+        assert_eq!(draw(1), first);
+    }
+
+    #[test]
+    fn test_seeded_stream_is_pinned() {
+        // a seed must give the same draws on every platform and rand version
+        let rngs = SelectionRngs::default();
+        let draws: Vec<u64> = rngs.with_rng("test_topic", Some(42), |rng| {
+            (0..2).map(|_| rng.next_u64()).collect()
+        });
+        assert_eq!(draws, vec![12578764544318200737, 17529487244874322312]);
+    }
+}
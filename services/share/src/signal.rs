@@ -1,3 +1,5 @@
+use std::{future::Future, time::Duration};
+
 pub type ShutdownRx = tokio::sync::watch::Receiver<ShutdownKind>;
 pub type ShutdownTx = tokio::sync::watch::Sender<ShutdownKind>;
 
@@ -47,3 +49,16 @@ impl ShutdownKind {
         });
     }
 }
+
+/// Awaits one shutdown step for at most `timeout`. When it does not finish in
+/// time a warning is logged and `None` returned, so an unresponsive dependency
+/// cannot hold up the shutdown.
+pub async fn bounded<F: Future>(step: &str, timeout: Duration, fut: F) -> Option<F::Output> {
+    match tokio::time::timeout(timeout, fut).await {
+        Ok(output) => Some(output),
+        Err(_) => {
+            tracing::warn!("{} did not finish within {:?}, continuing", step, timeout);
+            None
+        }
+    }
+}
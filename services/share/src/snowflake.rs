@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+};
 
 use chrono::Utc;
 use parking_lot::Mutex;
@@ -14,16 +17,37 @@ const GENERATE_MASK_SEQUENCE: u16 = (1 << BIT_LEN_SEQUENCE) - 1;
 pub enum SnowflakeError {
     #[error("Mutex poisoned")]
     MutexPoisoned,
+    #[error("clock moved backwards by {drift_ms}ms, refusing to generate id")]
+    ClockMovedBackwards { drift_ms: u64 },
+    #[error("worker id lease is not held, refusing to generate id")]
+    WorkerIdUnleased,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct SnowflakeConfig {
     pub datacenter_id: u8,
     pub epoch: u64, // Timestamp in milliseconds
+    /// Backward clock steps up to this many milliseconds are ridden out by
+    /// counting on from the last timestamp instead of failing id generation.
+    #[serde(default = "default_max_clock_backward_ms")]
+    pub max_clock_backward_ms: u64,
+    /// Consecutive worker id lease renewals that may fail on Redis errors
+    /// before the service stops. Id generation is suspended earlier if the
+    /// lease may already have expired.
+    #[serde(default = "default_max_lease_renewal_failures")]
+    pub max_lease_renewal_failures: u32,
+}
+
+fn default_max_clock_backward_ms() -> u64 {
+    10
+}
+
+fn default_max_lease_renewal_failures() -> u32 {
+    3
 }
 
 #[inline(always)]
-fn unix_timestamp_ms() -> u64 {
+pub fn unix_timestamp_ms() -> u64 {
     Utc::now().timestamp_millis() as u64
 }
 
@@ -36,13 +60,22 @@ struct SnowflakeInner {
     epoch: u64,
     data_center_id: u8,
     worker_id: u8,
+    max_clock_backward_ms: u64,
+    /// Set while the worker id lease is not known to be held, another
+    /// instance may be generating ids with the same worker id.
+    suspended: AtomicBool,
+    /// Unix milliseconds from which the worker id lease may have expired, 0
+    /// when the worker id is not leased.
+    lease_deadline_ms: AtomicU64,
     internals: Mutex<Internals>,
 }
 
 pub struct Snowflake(Arc<SnowflakeInner>);
 
 impl Snowflake {
-    pub fn new(data_center_id: u8, worker_id: u8, epoch: u64) -> Self {
+    /// `max_clock_backward_ms` is how far the clock may step backwards before
+    /// `next_id` errors.
+    pub fn new(data_center_id: u8, worker_id: u8, epoch: u64, max_clock_backward_ms: u64) -> Self {
         let sequence = 0;
         let last_timestamp = 0;
 
@@ -50,6 +83,9 @@ impl Snowflake {
             epoch,
             data_center_id,
             worker_id,
+            max_clock_backward_ms,
+            suspended: AtomicBool::new(false),
+            lease_deadline_ms: AtomicU64::new(0),
             internals: Mutex::new(Internals {
                 last_timestamp,
                 sequence,
@@ -58,15 +94,65 @@ impl Snowflake {
     }
 
     pub fn next_id(&self) -> Result<u64, SnowflakeError> {
+        self.next_id_with_clock(unix_timestamp_ms)
+    }
+
+    /// Makes `next_id` fail with `WorkerIdUnleased` on every clone until
+    /// `resume` is called.
+    pub fn suspend(&self) {
+        self.0.suspended.store(true, Ordering::Release);
+    }
+
+    pub fn resume(&self) {
+        self.0.suspended.store(false, Ordering::Release);
+    }
+
+    /// Makes `next_id` fail with `WorkerIdUnleased` from `deadline_ms` (unix
+    /// milliseconds) on, unless the lease is extended again before. Unlike
+    /// `suspend`, this holds even when the renewing task is stuck.
+    pub fn extend_lease(&self, deadline_ms: u64) {
+        self.0
+            .lease_deadline_ms
+            .store(deadline_ms, Ordering::Release);
+    }
+
+    fn next_id_with_clock(&self, clock: impl Fn() -> u64) -> Result<u64, SnowflakeError> {
+        if self.0.suspended.load(Ordering::Acquire) {
+            return Err(SnowflakeError::WorkerIdUnleased);
+        }
+
         let mut internals = self.0.internals.lock();
-        let mut timestamp = unix_timestamp_ms();
+        let mut timestamp = clock();
+
+        let lease_deadline_ms = self.0.lease_deadline_ms.load(Ordering::Acquire);
+        if lease_deadline_ms != 0 && timestamp >= lease_deadline_ms {
+            return Err(SnowflakeError::WorkerIdUnleased);
+        }
+
+        let clock_behind = timestamp < internals.last_timestamp;
+        if clock_behind {
+            // small NTP adjustments keep counting from the last timestamp
+            // until the clock catches up, nothing waits under the lock; large
+            // jumps are reported instead
+            let drift_ms = internals.last_timestamp - timestamp;
+            if drift_ms > self.0.max_clock_backward_ms {
+                return Err(SnowflakeError::ClockMovedBackwards { drift_ms });
+            }
+            tracing::warn!("clock moved backwards by {drift_ms}ms, counting on from the last id");
+            timestamp = internals.last_timestamp;
+        }
 
         if timestamp == internals.last_timestamp {
             internals.sequence = (internals.sequence + 1) & GENERATE_MASK_SEQUENCE;
 
             if internals.sequence == 0 {
+                if clock_behind {
+                    // the next millisecond stays within the tolerated drift,
+                    // the next call checks it against the clock again
+                    timestamp += 1;
+                }
                 while timestamp <= internals.last_timestamp {
-                    timestamp = unix_timestamp_ms();
+                    timestamp = clock();
                 }
             }
         } else {
@@ -83,8 +169,103 @@ impl Snowflake {
     }
 }
 
+impl Snowflake {
+    /// Unix milliseconds at which `id` was generated.
+    pub fn timestamp_ms(&self, id: u64) -> u64 {
+        (id >> (BIT_LEN_SEQUENCE + BIT_LEN_MACHINE_ID + BIT_LEN_DATA_CENTER_ID)) + self.0.epoch
+    }
+}
+
 impl Clone for Snowflake {
     fn clone(&self) -> Self {
         Self(self.0.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    const EPOCH: u64 = 1609459200000;
+
+    fn timestamp_of(id: u64) -> u64 {
+        (id >> (BIT_LEN_SEQUENCE + BIT_LEN_MACHINE_ID + BIT_LEN_DATA_CENTER_ID)) + EPOCH
+    }
+
+    #[test]
+    fn test_small_backward_step_counts_on_without_waiting() {
+        let snowflake = Snowflake::new(1, 1, EPOCH, 10);
+        let now = EPOCH + 1_000;
+        let first = snowflake.next_id_with_clock(|| now).unwrap();
+
+        // the clock stays 5ms behind, every read is counted
+        let reads = AtomicU64::new(0);
+        let behind = || {
+            reads.fetch_add(1, Ordering::SeqCst);
+            now - 5
+        };
+        let mut last = first;
+        for _ in 0..=GENERATE_MASK_SEQUENCE {
+            let id = snowflake.next_id_with_clock(behind).unwrap();
+            assert!(id > last);
+            last = id;
+        }
+
+        // a used up sequence moves to the next millisecond, nobody spins on
+        // the clock
+        assert_eq!(timestamp_of(last), now + 1);
+        assert_eq!(
+            reads.load(Ordering::SeqCst),
+            GENERATE_MASK_SEQUENCE as u64 + 1
+        );
+    }
+
+    #[test]
+    fn test_errors_on_large_backward_step() {
+        let snowflake = Snowflake::new(1, 1, EPOCH, 10);
+        let now = EPOCH + 1_000;
+        snowflake.next_id_with_clock(|| now).unwrap();
+
+        let err = snowflake.next_id_with_clock(|| now - 50).unwrap_err();
+        assert!(matches!(
+            err,
+            SnowflakeError::ClockMovedBackwards { drift_ms: 50 }
+        ));
+
+        // the generator recovers once the clock catches up again
+        assert!(snowflake.next_id_with_clock(|| now + 1).is_ok());
+    }
+
+    #[test]
+    fn test_suspended_generator_refuses_ids_on_every_clone() {
+        let snowflake = Snowflake::new(1, 1, EPOCH, 10);
+        let clone = snowflake.clone();
+
+        snowflake.suspend();
+        assert!(matches!(
+            clone.next_id(),
+            Err(SnowflakeError::WorkerIdUnleased)
+        ));
+
+        snowflake.resume();
+        assert!(clone.next_id().is_ok());
+    }
+
+    #[test]
+    fn test_expired_lease_refuses_ids() {
+        let snowflake = Snowflake::new(1, 1, EPOCH, 10);
+        let now = EPOCH + 1_000;
+
+        snowflake.extend_lease(now + 10);
+        assert!(snowflake.next_id_with_clock(|| now).is_ok());
+        assert!(matches!(
+            snowflake.next_id_with_clock(|| now + 10),
+            Err(SnowflakeError::WorkerIdUnleased)
+        ));
+
+        snowflake.extend_lease(now + 20);
+        assert!(snowflake.next_id_with_clock(|| now + 10).is_ok());
+    }
+}
@@ -0,0 +1,124 @@
+//! Fixtures shared by the tests of every crate in the workspace. Other
+//! crates get them through the `test-util` feature of their `share`
+//! dev-dependency.
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use redis::{Arg, Cmd, RedisFuture, Value, aio::ConnectionLike};
+
+use crate::models::{
+    candidate_pool_preset::CandidatePoolPreset,
+    database::{CreateTopicStatus, VotingTopic, VotingTopicType},
+    excel::{CharacterInfo, ProfessionCategory, RarityRank},
+};
+
+/// Active pairwise topic over every operator, waiting for audit. Tests set
+/// the fields they care about with struct update syntax.
+pub fn test_topic(id: &str, open_time: DateTime<Utc>, close_time: DateTime<Utc>) -> VotingTopic {
+    VotingTopic {
+        id: id.to_string(),
+        name: "Test Topic".to_string(),
+        title: "Test Title".to_string(),
+        description: "This is a test topic.".to_string(),
+        topic_type: VotingTopicType::Pairwise,
+        candidate_pool: CandidatePoolPreset::All,
+        created_at: open_time,
+        updated_at: None,
+        open_time,
+        close_time,
+        is_active: true,
+        status: CreateTopicStatus::WaitingAudit,
+        vote_overrides: Default::default(),
+        frozen_pool: None,
+        paused: false,
+    }
+}
+
+/// Four obtainable six-star operators: two guards, a caster and a vanguard,
+/// named in id order.
+pub fn create_test_characters() -> Vec<CharacterInfo> {
+    vec![
+        CharacterInfo {
+            id: 1001,
+            name: "AAAA".to_string(),
+            rarity: RarityRank::Tier6,
+            profession: ProfessionCategory::WARRIOR,
+            sub_profession_id: "centurion".to_string(),
+            is_not_obtainable: false,
+        },
+        CharacterInfo {
+            id: 1002,
+            name: "BBBB".to_string(),
+            rarity: RarityRank::Tier6,
+            profession: ProfessionCategory::WARRIOR,
+            sub_profession_id: "sword".to_string(),
+            is_not_obtainable: false,
+        },
+        CharacterInfo {
+            id: 2001,
+            name: "CCCC".to_string(),
+            rarity: RarityRank::Tier6,
+            profession: ProfessionCategory::CASTER,
+            sub_profession_id: "aoedamage".to_string(),
+            is_not_obtainable: false,
+        },
+        CharacterInfo {
+            id: 3001,
+            name: "DDDD".to_string(),
+            rarity: RarityRank::Tier6,
+            profession: ProfessionCategory::PIONEER,
+            sub_profession_id: "pioneer".to_string(),
+            is_not_obtainable: false,
+        },
+    ]
+}
+
+/// Redis connection answering each command with the next scripted reply and
+/// recording the commands sent to it. Once the replies run out every command
+/// fails like an unreachable Redis.
+#[derive(Debug, Default)]
+pub struct ScriptedRedis {
+    pub replies: VecDeque<Value>,
+    pub commands: Vec<Vec<String>>,
+}
+
+impl ScriptedRedis {
+    pub fn new(replies: impl IntoIterator<Item = Value>) -> Self {
+        Self {
+            replies: replies.into_iter().collect(),
+            commands: Vec::new(),
+        }
+    }
+}
+
+impl ConnectionLike for &mut ScriptedRedis {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        let args = cmd
+            .args_iter()
+            .filter_map(|arg| match arg {
+                Arg::Simple(arg) => Some(String::from_utf8_lossy(arg).into_owned()),
+                Arg::Cursor => None,
+            })
+            .collect();
+        self.commands.push(args);
+        let reply = self
+            .replies
+            .pop_front()
+            .ok_or_else(|| (redis::ErrorKind::IoError, "connection refused").into());
+        Box::pin(std::future::ready(reply))
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        _cmd: &'a redis::Pipeline,
+        _offset: usize,
+        _count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        unimplemented!("pipelines are not scripted")
+    }
+
+    fn get_db(&self) -> i64 {
+        0
+    }
+}
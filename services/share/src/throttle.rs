@@ -0,0 +1,94 @@
+use redis::aio::ConnectionLike;
+
+use crate::config::SaveThrottleConfig;
+
+/// Whether a save from `ip` arrives within `min_interval_ms` of the previous
+/// one. Only saves that get through claim the slot, Redis errors let the save
+/// through.
+pub async fn is_save_throttled(
+    mut conn: impl ConnectionLike,
+    config: &SaveThrottleConfig,
+    ip: &str,
+) -> bool {
+    if !config.enabled() {
+        return false;
+    }
+
+    let claimed: redis::RedisResult<Option<String>> = redis::cmd("SET")
+        .arg(SaveThrottleConfig::key(ip))
+        .arg(1)
+        .arg("NX")
+        .arg("PX")
+        .arg(config.min_interval_ms)
+        .query_async(&mut conn)
+        .await;
+    match claimed {
+        Ok(claimed) => claimed.is_none(),
+        Err(e) => {
+            tracing::warn!("Failed to check the save interval of {}: {}", ip, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use redis::Value;
+
+    use super::*;
+    use crate::{config::SaveThrottleMode, test_util::ScriptedRedis};
+
+    fn config() -> SaveThrottleConfig {
+        SaveThrottleConfig {
+            min_interval_ms: 200,
+            mode: SaveThrottleMode::Reject,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_second_save_within_interval_is_throttled() {
+        // SET NX answers OK when the slot is claimed and nil when it is taken
+        let mut redis = ScriptedRedis::new([Value::Okay, Value::Nil]);
+
+        assert!(!is_save_throttled(&mut redis, &config(), "10.0.0.1").await);
+        assert!(is_save_throttled(&mut redis, &config(), "10.0.0.1").await);
+        assert_eq!(
+            redis.commands[0],
+            ["SET", "save_throttle:10.0.0.1", "1", "NX", "PX", "200"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disabled_or_unreachable_throttle_lets_saves_through() {
+        let mut redis = ScriptedRedis::default();
+        assert!(!is_save_throttled(&mut redis, &config(), "10.0.0.1").await);
+        assert_eq!(redis.commands.len(), 1);
+
+        let mut redis = ScriptedRedis::default();
+        let disabled = SaveThrottleConfig::default();
+        assert!(!is_save_throttled(&mut redis, &disabled, "10.0.0.1").await);
+        assert!(redis.commands.is_empty());
+    }
+
+    /// Needs a local Redis: `cargo test -p share save_throttle -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_save_throttle_two_saves_within_interval() {
+        let client = redis::Client::open("redis://127.0.0.1:6379").unwrap();
+        let conn = client.get_multiplexed_async_connection().await.unwrap();
+        let config = config();
+        let ip = "save-throttle-test";
+        let _: () = redis::cmd("DEL")
+            .arg(SaveThrottleConfig::key(ip))
+            .query_async(&mut conn.clone())
+            .await
+            .unwrap();
+
+        assert!(!is_save_throttled(conn.clone(), &config, ip).await);
+        assert!(is_save_throttled(conn.clone(), &config, ip).await);
+        assert!(!is_save_throttled(conn.clone(), &config, "other-ip").await);
+
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        assert!(!is_save_throttled(conn, &config, ip).await);
+    }
+}
@@ -1,4 +1,7 @@
-use std::io::IsTerminal as _;
+use std::{
+    io::IsTerminal as _,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use chrono::{DateTime, Utc};
 use chrono_tz::Asia::Shanghai;
@@ -11,6 +14,35 @@ use tracing_subscriber::{
 
 use crate::config::TracingConfig;
 
+/// Lets through one in `rate` calls, used to thin out debug logs emitted for
+/// every processed batch.
+#[derive(Debug)]
+pub struct LogSampler {
+    rate: u64,
+    counter: AtomicU64,
+}
+
+impl LogSampler {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: rate.max(1),
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    pub fn sample(&self) -> bool {
+        self.counter
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.rate)
+    }
+}
+
+impl Default for LogSampler {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
 struct East8Time;
 
 impl FormatTime for East8Time {
@@ -74,3 +106,18 @@ pub fn init_tracing_subscriber(
 
     guard
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_sampler() {
+        let sampler = LogSampler::new(3);
+        let sampled: Vec<bool> = (0..6).map(|_| sampler.sample()).collect();
+        assert_eq!(sampled, [true, false, false, true, false, false]);
+
+        let sampler = LogSampler::new(0);
+        assert!((0..3).all(|_| sampler.sample()));
+    }
+}
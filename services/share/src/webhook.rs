@@ -0,0 +1,210 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use redis::aio::MultiplexedConnection;
+use sha2::Sha256;
+
+use crate::{
+    config::WebhookConfig,
+    models::{
+        database::{TopicWindowState, VotingTopic},
+        topic_id::TopicId,
+    },
+    retry::exponential_backoff,
+};
+
+/// Overrides `webhooks.secret` from the config file.
+pub const WEBHOOK_SECRET_ENV: &str = "ARK_VOTE_WEBHOOK_SECRET";
+
+/// Header carrying `sha256=<hex(hmac_sha256(body))>` on webhook requests.
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Ark-Vote-Signature";
+
+const WEBHOOK_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const WEBHOOK_RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// How long the claim on a topic close is kept. Instances notice the same close
+/// within a topic cache refresh of each other, a topic reopened and closed
+/// again after that is announced again.
+const WEBHOOK_CLAIM_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Signs webhook payloads so receivers can verify they come from us.
+#[derive(Clone)]
+pub struct WebhookSigner(Arc<[u8]>);
+
+impl WebhookSigner {
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self(Arc::from(secret.as_ref()))
+    }
+
+    /// Returns `None` when no secret is configured, in which case payloads
+    /// are sent unsigned.
+    pub fn from_config(config: &WebhookConfig) -> Option<Self> {
+        let secret = std::env::var(WEBHOOK_SECRET_ENV).unwrap_or_else(|_| config.secret.clone());
+        if secret.is_empty() {
+            return None;
+        }
+
+        Some(Self::new(secret))
+    }
+
+    pub fn sign(&self, body: &[u8]) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any length");
+        mac.update(body);
+        let signature: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        format!("sha256={signature}")
+    }
+}
+
+/// Backoff before the `attempt`-th retry of a failed delivery, starting at 1.
+pub fn retry_delay(attempt: u32) -> Duration {
+    exponential_backoff(
+        WEBHOOK_RETRY_BASE_DELAY,
+        WEBHOOK_RETRY_MAX_DELAY,
+        attempt.saturating_sub(1),
+    )
+}
+
+/// Claims the webhooks of a topic close for this instance. Every instance
+/// observes the close, only the one that claims it first sends the webhooks.
+/// Redis errors let the delivery through, a duplicate beats a lost webhook.
+pub async fn claim_topic_close(mut connection: MultiplexedConnection, topic_id: &TopicId) -> bool {
+    let claimed: redis::RedisResult<Option<String>> = redis::cmd("SET")
+        .arg(topic_id.close_webhook_key())
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(WEBHOOK_CLAIM_TTL.as_secs())
+        .query_async(&mut connection)
+        .await;
+    match claimed {
+        Ok(claimed) => claimed.is_some(),
+        Err(e) => {
+            tracing::warn!("Failed to claim webhooks of topic {}: {}", topic_id, e);
+            true
+        }
+    }
+}
+
+/// Detects topics leaving their voting window between two observations of
+/// the topic cache.
+#[derive(Debug, Default)]
+pub struct TopicCloseTracker {
+    open: HashSet<String>,
+}
+
+impl TopicCloseTracker {
+    /// Returns the ids of the topics that were open at the previous
+    /// observation and are now closed or deactivated. Topics that were never
+    /// seen open are not reported, so a restart does not replay old closes.
+    pub fn observe<'a>(
+        &mut self,
+        topics: impl IntoIterator<Item = &'a VotingTopic>,
+        now: DateTime<Utc>,
+    ) -> Vec<String> {
+        let mut open = HashSet::new();
+        let mut closed = Vec::new();
+
+        for topic in topics {
+            if topic.window_state_at(now) == TopicWindowState::Open {
+                open.insert(topic.id.clone());
+            } else if self.open.contains(&topic.id) {
+                closed.push(topic.id.clone());
+            }
+        }
+
+        self.open = open;
+        closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration as ChronoDuration;
+
+    use super::*;
+    use crate::test_util::test_topic;
+
+    fn topic(id: &str, now: DateTime<Utc>, close_in_hours: i64) -> VotingTopic {
+        test_topic(
+            id,
+            now - ChronoDuration::hours(2),
+            now + ChronoDuration::hours(close_in_hours),
+        )
+    }
+
+    #[test]
+    fn test_tracker_reports_transitions_once() {
+        let now = Utc::now();
+        let topics = vec![topic("closing", now, 1), topic("already_closed", now, -1)];
+        let mut tracker = TopicCloseTracker::default();
+
+        assert!(tracker.observe(&topics, now).is_empty());
+
+        let later = now + ChronoDuration::hours(2);
+        assert_eq!(tracker.observe(&topics, later), vec!["closing".to_string()]);
+        assert!(tracker.observe(&topics, later).is_empty());
+    }
+
+    #[test]
+    fn test_tracker_reports_deactivated_topic() {
+        let now = Utc::now();
+        let mut topics = vec![topic("deactivated", now, 1)];
+        let mut tracker = TopicCloseTracker::default();
+        tracker.observe(&topics, now);
+
+        topics[0].is_active = false;
+        assert_eq!(
+            tracker.observe(&topics, now),
+            vec!["deactivated".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_sign_is_stable_hex() {
+        let signer = WebhookSigner::new("secret");
+        let signature = signer.sign(b"{}");
+
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_eq!(signature, signer.sign(b"{}"));
+        assert_ne!(signature, WebhookSigner::new("other").sign(b"{}"));
+    }
+
+    #[test]
+    fn test_retry_delay_backoff() {
+        assert_eq!(retry_delay(1), Duration::from_secs(1));
+        assert_eq!(retry_delay(3), Duration::from_secs(4));
+        assert_eq!(retry_delay(10), Duration::from_secs(60));
+        assert_eq!(retry_delay(u32::MAX), Duration::from_secs(60));
+    }
+
+    /// Needs a local Redis: `cargo test -p share claim_topic_close -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_claim_topic_close_once() {
+        let client = redis::Client::open("redis://127.0.0.1:6379").unwrap();
+        let mut connection = client.get_multiplexed_async_connection().await.unwrap();
+        let topic_id = TopicId::parse("webhook-claim-test").unwrap();
+        let _: () = redis::cmd("DEL")
+            .arg(topic_id.close_webhook_key())
+            .query_async(&mut connection)
+            .await
+            .unwrap();
+
+        assert!(claim_topic_close(connection.clone(), &topic_id).await);
+        assert!(!claim_topic_close(connection.clone(), &topic_id).await);
+
+        let _: () = redis::cmd("DEL")
+            .arg(topic_id.close_webhook_key())
+            .query_async(&mut connection)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use mongodb::bson::doc;
+use share::models::{
+    api::{
+        ApiData, ApiMsg, ApiResponse, BallotLookupRequest, BallotLookupResponse, BallotRecord,
+        ResponseStatus,
+    },
+    database::StoredBallot,
+};
+
+use crate::{AppState, api::utils::ApiJson, error::AppError};
+
+#[utoipa::path(
+    post,
+    path = "/admin/ballot/lookup",
+    request_body = BallotLookupRequest,
+    responses(
+        (status = 200, description = "Look up a stored ballot by id", body = ApiResponse<BallotLookupResponse>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
+        (status = 500, description = "Internal server error", body = ApiResponse<String>)
+    ),
+    tag = "Admin",
+    operation_id = "adminBallotLookup"
+)]
+#[axum::debug_handler]
+pub async fn ballot_lookup(
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<BallotLookupRequest>,
+) -> Result<ApiResponse<BallotLookupResponse>, AppError> {
+    if state
+        .topic_service
+        .get_topic(&req.topic_id)
+        .await?
+        .is_none()
+    {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    }
+
+    let ballot = state
+        .mongodb_read
+        .collection::<StoredBallot>(&format!("ballots_{}", req.topic_id))
+        .find_one(doc! { StoredBallot::BALLOT_ID_FIELD: &req.ballot_id })
+        .await?;
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(BallotLookupResponse {
+            found: ballot.is_some(),
+            ballot: ballot.as_ref().map(BallotRecord::from),
+        }),
+        message: ApiMsg::OK,
+    })
+}
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    extract::{Request, State},
+    http::header::AUTHORIZATION,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use share::{
+    auth::{AdminAuthError, AdminTokens},
+    models::api::{ApiData, ApiMsg, ApiResponse, ResponseStatus},
+};
+
+use crate::AppState;
+
+pub mod ballot_lookup;
+pub mod sub_profession_blocklist;
+pub mod topic_freeze_pool;
+pub mod topic_info;
+pub mod topic_pause;
+pub mod topic_reopen;
+pub mod topic_reset_scores;
+pub mod topic_update;
+
+use ballot_lookup::ballot_lookup;
+use sub_profession_blocklist::{sub_profession_blocklist, sub_profession_blocklist_update};
+use topic_freeze_pool::topic_freeze_pool;
+use topic_info::topic_info;
+use topic_pause::topic_pause;
+use topic_reopen::topic_reopen;
+use topic_reset_scores::topic_reset_scores;
+use topic_update::topic_update;
+
+pub async fn require_admin_token(
+    State(admin_tokens): State<AdminTokens>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let authorization = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+
+    let (status, message) = match admin_tokens.authorize(authorization) {
+        Ok(()) => return next.run(req).await,
+        Err(AdminAuthError::MissingToken) => (ResponseStatus::Unauthorized, ApiMsg::Unauthorized),
+        Err(AdminAuthError::InvalidToken) => (ResponseStatus::Forbidden, ApiMsg::EndpointForbidden),
+    };
+
+    ApiResponse::<()> {
+        status,
+        data: ApiData::Empty,
+        message,
+    }
+    .into_response()
+}
+
+fn with_admin_auth<S>(router: Router<S>, admin_tokens: AdminTokens) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(middleware::from_fn_with_state(
+        admin_tokens,
+        require_admin_token,
+    ))
+}
+
+pub fn admin_routes(admin_tokens: AdminTokens) -> Router<Arc<AppState>> {
+    let router = Router::new()
+        .route("/ping", get(|| async { "pong" })) // 校验 admin token
+        .route(
+            "/blocklist/sub_professions",
+            get(sub_profession_blocklist).post(sub_profession_blocklist_update),
+        )
+        .route("/topic/freeze_pool", post(topic_freeze_pool))
+        .route("/topic/info", post(topic_info))
+        .route("/topic/pause", post(topic_pause))
+        .route("/topic/reopen", post(topic_reopen))
+        .route("/topic/reset_scores", post(topic_reset_scores))
+        .route("/topic/update", post(topic_update))
+        .route("/ballot/lookup", post(ballot_lookup));
+
+    with_admin_auth(router, admin_tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::StatusCode};
+    use tower::ServiceExt as _;
+
+    use super::*;
+
+    async fn ping(authorization: Option<&str>) -> StatusCode {
+        let router: Router = with_admin_auth(
+            Router::new().route("/ping", get(|| async { "pong" })),
+            AdminTokens::new(["secret"]),
+        );
+
+        let mut req = Request::get("/ping");
+        if let Some(authorization) = authorization {
+            req = req.header(AUTHORIZATION, authorization);
+        }
+
+        router
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_admin_missing_token() {
+        assert_eq!(ping(None).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_admin_wrong_token() {
+        assert_eq!(ping(Some("Bearer wrong")).await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_admin_correct_token() {
+        assert_eq!(ping(Some("Bearer secret")).await, StatusCode::OK);
+    }
+}
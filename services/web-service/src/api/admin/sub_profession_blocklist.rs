@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, ResponseStatus, SubProfessionBlocklistPayload,
+};
+
+use crate::{AppState, api::utils::ApiJson, error::AppError};
+
+#[utoipa::path(
+    get,
+    path = "/admin/blocklist/sub_professions",
+    responses(
+        (status = 200, description = "Get blocked sub professions", body = ApiResponse<SubProfessionBlocklistPayload>),
+    ),
+    tag = "Admin",
+    operation_id = "adminSubProfessionBlocklist"
+)]
+#[axum::debug_handler]
+pub async fn sub_profession_blocklist(
+    State(state): State<Arc<AppState>>,
+) -> Result<ApiResponse<SubProfessionBlocklistPayload>, AppError> {
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(SubProfessionBlocklistPayload {
+            sub_professions: state.topic_service.blocked_sub_professions(),
+        }),
+        message: ApiMsg::OK,
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/blocklist/sub_professions",
+    request_body = SubProfessionBlocklistPayload,
+    responses(
+        (status = 200, description = "Replace blocked sub professions", body = ApiResponse<SubProfessionBlocklistPayload>),
+    ),
+    tag = "Admin",
+    operation_id = "adminSubProfessionBlocklistUpdate"
+)]
+#[axum::debug_handler]
+pub async fn sub_profession_blocklist_update(
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<SubProfessionBlocklistPayload>,
+) -> Result<ApiResponse<SubProfessionBlocklistPayload>, AppError> {
+    state
+        .topic_service
+        .set_blocked_sub_professions(req.sub_professions);
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(SubProfessionBlocklistPayload {
+            sub_professions: state.topic_service.blocked_sub_professions(),
+        }),
+        message: ApiMsg::OK,
+    })
+}
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicFreezePoolRequest, TopicFreezePoolResponse,
+};
+
+use crate::{AppState, api::utils::ApiJson, error::AppError};
+
+#[utoipa::path(
+    post,
+    path = "/admin/topic/freeze_pool",
+    request_body = TopicFreezePoolRequest,
+    responses(
+        (status = 200, description = "Snapshot the topic's resolved candidate pool", body = ApiResponse<TopicFreezePoolResponse>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
+        (status = 500, description = "Internal server error", body = ApiResponse<String>)
+    ),
+    tag = "Admin",
+    operation_id = "adminTopicFreezePool"
+)]
+#[axum::debug_handler]
+pub async fn topic_freeze_pool(
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<TopicFreezePoolRequest>,
+) -> Result<ApiResponse<TopicFreezePoolResponse>, AppError> {
+    let Some(mut topic) = state.topic_service.get_topic(&req.topic_id).await? else {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    };
+
+    topic.freeze_pool(
+        &state.character_infos,
+        state.topic_service.max_preset_depth(),
+    );
+    let pool_size = topic.frozen_pool.as_ref().map_or(0, Vec::len);
+    state
+        .topic_service
+        .update_topic(topic, vec!["frozen_pool".to_string()])
+        .await?;
+    tracing::info!(
+        "Froze candidate pool of topic {}: {} operators",
+        req.topic_id,
+        pool_size
+    );
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(TopicFreezePoolResponse { pool_size }),
+        message: ApiMsg::OK,
+    })
+}
@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use share::models::api::{
+    AdminTopicInfoResponse, ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicInfoRequest,
+};
+
+use crate::{AppState, api::utils::ApiJson, error::AppError};
+
+#[utoipa::path(
+    post,
+    path = "/admin/topic/info",
+    request_body = TopicInfoRequest,
+    responses(
+        (status = 200, description = "Get topic information with its stored candidate pool preset", body = ApiResponse<AdminTopicInfoResponse>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
+        (status = 500, description = "Internal server error", body = ApiResponse<String>)
+    ),
+    tag = "Admin",
+    operation_id = "adminTopicInfo"
+)]
+#[axum::debug_handler]
+pub async fn topic_info(
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<TopicInfoRequest>,
+) -> Result<ApiResponse<AdminTopicInfoResponse>, AppError> {
+    let Some(topic) = state.topic_service.get_topic(&req.topic_id).await? else {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    };
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(topic.into()),
+        message: ApiMsg::OK,
+    })
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicInfoResponse, TopicPauseRequest,
+};
+
+use crate::{AppState, api::utils::ApiJson, error::AppError};
+
+#[utoipa::path(
+    post,
+    path = "/admin/topic/pause",
+    request_body = TopicPauseRequest,
+    responses(
+        (status = 200, description = "Pause or resume voting on a topic", body = ApiResponse<TopicInfoResponse>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
+        (status = 500, description = "Internal server error", body = ApiResponse<String>)
+    ),
+    tag = "Admin",
+    operation_id = "adminTopicPause"
+)]
+#[axum::debug_handler]
+pub async fn topic_pause(
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<TopicPauseRequest>,
+) -> Result<ApiResponse<TopicInfoResponse>, AppError> {
+    let Some(mut topic) = state.topic_service.get_topic(&req.topic_id).await? else {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    };
+
+    if topic.paused != req.paused {
+        topic.paused = req.paused;
+        state
+            .topic_service
+            .update_topic(topic.clone(), vec!["paused".to_string()])
+            .await?;
+        tracing::info!(
+            "{} voting on topic {}",
+            if req.paused { "Paused" } else { "Resumed" },
+            req.topic_id
+        );
+    }
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(topic.into()),
+        message: ApiMsg::OK,
+    })
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use chrono::Utc;
+use share::models::api::{ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicReopenRequest};
+
+use crate::{AppState, api::utils::ApiJson, error::AppError};
+
+#[utoipa::path(
+    post,
+    path = "/admin/topic/reopen",
+    request_body = TopicReopenRequest,
+    responses(
+        (status = 200, description = "Reopen topic successfully", body = ApiResponse<String>),
+        (status = 400, description = "Topic cannot be reopened", body = ApiResponse<String>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
+        (status = 500, description = "Internal server error", body = ApiResponse<String>)
+    ),
+    tag = "Admin",
+    operation_id = "adminTopicReopen"
+)]
+#[axum::debug_handler]
+pub async fn topic_reopen(
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<TopicReopenRequest>,
+) -> Result<ApiResponse<ApiData<String>>, AppError> {
+    let Some(previous) = state.topic_service.get_topic(&req.topic_id).await? else {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    };
+
+    let mut reopened = previous.clone();
+    if let Err(err) = reopened.reopen(req.open_time, req.close_time, Utc::now()) {
+        tracing::debug!("Rejected reopen of topic {}: {:?}", req.topic_id, err);
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: err.into(),
+        });
+    }
+    if state.vote.freeze_candidate_pools && reopened.frozen_pool.is_none() {
+        reopened.freeze_pool(
+            &state.character_infos,
+            state.topic_service.max_preset_depth(),
+        );
+    }
+
+    state
+        .topic_service
+        .reopen_topic(&previous, reopened)
+        .await?;
+    tracing::info!("Reopened topic {} until {}", req.topic_id, req.close_time);
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Empty,
+        message: ApiMsg::OK,
+    })
+}
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use mongodb::bson::{Document, doc};
+use share::{
+    models::api::{
+        ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicResetScoresRequest,
+        TopicResetScoresResponse,
+    },
+    scores::reset_topic_scores,
+};
+
+use crate::{AppState, api::utils::ApiJson, error::AppError};
+
+#[utoipa::path(
+    post,
+    path = "/admin/topic/reset_scores",
+    request_body = TopicResetScoresRequest,
+    responses(
+        (status = 200, description = "Reset topic scores successfully", body = ApiResponse<TopicResetScoresResponse>),
+        (status = 400, description = "Confirmation does not match the topic id", body = ApiResponse<String>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
+        (status = 500, description = "Internal server error", body = ApiResponse<String>)
+    ),
+    tag = "Admin",
+    operation_id = "adminTopicResetScores"
+)]
+#[axum::debug_handler]
+pub async fn topic_reset_scores(
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<TopicResetScoresRequest>,
+) -> Result<ApiResponse<TopicResetScoresResponse>, AppError> {
+    if req.confirm != req.topic_id {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::ResetConfirmationMismatch,
+        });
+    }
+
+    if state
+        .topic_service
+        .get_topic(&req.topic_id)
+        .await?
+        .is_none()
+    {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    }
+
+    let mut conn = state.redis.connection.clone();
+    let keys_removed = reset_topic_scores(&mut conn, &req.topic_id).await?;
+    state.partners_cache.remove(req.topic_id.as_str());
+
+    let ballots_removed = if req.drop_ballots {
+        let collection = state
+            .mongodb
+            .collection::<Document>(&format!("ballots_{}", req.topic_id));
+        // the count is of the very documents removed, unlike a count before a drop
+        collection.delete_many(doc! {}).await?.deleted_count
+    } else {
+        0
+    };
+
+    tracing::warn!(
+        "Reset scores of topic {}: {} keys, {} ballots removed",
+        req.topic_id,
+        keys_removed,
+        ballots_removed
+    );
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(TopicResetScoresResponse {
+            keys_removed,
+            ballots_removed,
+        }),
+        message: ApiMsg::OK,
+    })
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use redis::AsyncCommands as _;
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicUpdateRequest, TopicUpdateResponse,
+};
+
+use crate::{AppState, api::utils::ApiJson, error::AppError};
+
+#[utoipa::path(
+    post,
+    path = "/admin/topic/update",
+    request_body = TopicUpdateRequest,
+    responses(
+        (status = 200, description = "Update topic successfully", body = ApiResponse<TopicUpdateResponse>),
+        (status = 400, description = "Invalid topic or candidate pool locked by existing votes", body = ApiResponse<String>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
+        (status = 500, description = "Internal server error", body = ApiResponse<String>)
+    ),
+    tag = "Admin",
+    operation_id = "adminTopicUpdate"
+)]
+#[axum::debug_handler]
+pub async fn topic_update(
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<TopicUpdateRequest>,
+) -> Result<ApiResponse<TopicUpdateResponse>, AppError> {
+    let Some(mut topic) = state.topic_service.get_topic(&req.topic_id).await? else {
+        return Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
+            data: ApiData::Empty,
+            message: ApiMsg::TargetTopicNotFound,
+        });
+    };
+
+    let mut conn = state.redis.connection.clone();
+    let valid_ballots: Option<i64> = conn.get(req.topic_id.valid_ballots_count_key()).await?;
+
+    let updated_fields = match topic.apply_update(
+        req.update,
+        valid_ballots.unwrap_or(0) > 0,
+        state.topic_service.max_preset_depth(),
+    ) {
+        Ok(fields) => fields,
+        Err(err) => {
+            tracing::debug!("Rejected update of topic {}: {:?}", req.topic_id, err);
+            return Ok(ApiResponse {
+                status: ResponseStatus::BadRequest,
+                data: ApiData::Empty,
+                message: err.into(),
+            });
+        }
+    };
+
+    // a new preset is an explicit change, so a frozen pool follows it
+    if topic.frozen_pool.is_some() && updated_fields.iter().any(|f| f == "candidate_pool") {
+        topic.freeze_pool(
+            &state.character_infos,
+            state.topic_service.max_preset_depth(),
+        );
+    }
+
+    if !updated_fields.is_empty() {
+        state
+            .topic_service
+            .update_topic(topic, updated_fields.clone())
+            .await?;
+        tracing::info!("Updated topic {}: {:?}", req.topic_id, updated_fields);
+    }
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(TopicUpdateResponse { updated_fields }),
+        message: ApiMsg::OK,
+    })
+}
@@ -1,9 +1,9 @@
 use std::sync::Arc;
 
-use axum::{Json, extract::State};
-use share::models::api::{ApiData, ApiMsg, ApiResponse, AuditTopicRequest};
+use axum::extract::State;
+use share::models::api::{ApiData, ApiMsg, ApiResponse, AuditTopicRequest, ResponseStatus};
 
-use crate::{AppState, error::AppError};
+use crate::{AppState, api::utils::ApiJson, error::AppError};
 
 #[utoipa::path(
     post,
@@ -20,17 +20,17 @@ use crate::{AppState, error::AppError};
 #[axum::debug_handler]
 pub async fn audit_topic(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<AuditTopicRequest>,
-) -> Result<Json<ApiResponse<ApiData<String>>>, AppError> {
+    ApiJson(req): ApiJson<AuditTopicRequest>,
+) -> Result<ApiResponse<ApiData<String>>, AppError> {
     let topic_id = req.topic_id;
     state
         .topic_service
         .audit_topic(&topic_id, req.audit_info)
         .await?;
 
-    Ok(Json(ApiResponse {
-        status: 0,
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
         data: ApiData::Empty,
         message: ApiMsg::OK,
-    }))
+    })
 }
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
-use axum::{Json, extract::State};
-use share::models::api::{ApiData, ApiMsg, ApiResponse, AuditTopicsListResponse};
+use axum::extract::State;
+use share::models::api::{ApiData, ApiMsg, ApiResponse, AuditTopicsListResponse, ResponseStatus};
 
 use crate::{AppState, error::AppError};
 
@@ -19,14 +19,14 @@ use crate::{AppState, error::AppError};
 #[axum::debug_handler]
 pub async fn audit_topics_list(
     State(state): State<Arc<AppState>>,
-) -> Result<Json<ApiResponse<AuditTopicsListResponse>>, AppError> {
+) -> Result<ApiResponse<AuditTopicsListResponse>, AppError> {
     let audit_topics = state.topic_service.get_need_audit_topics().await?;
 
-    Ok(Json(ApiResponse {
-        status: 0,
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
         data: ApiData::Data(AuditTopicsListResponse {
             topics: audit_topics,
         }),
         message: ApiMsg::OK,
-    }))
+    })
 }
@@ -1,19 +1,18 @@
 use std::sync::Arc;
 
-use axum::{Json, extract::State};
+use axum::extract::State;
 use rand::seq::IndexedRandom as _;
 use redis::AsyncCommands as _;
 use share::models::{
     api::{
         ApiData, ApiMsg, ApiResponse, BallotCreateResponse, BallotSaveRequest, PairwiseSaveScore,
+        ResponseStatus,
     },
     database::VotingTopicType,
+    topic_id::TopicId,
 };
 
-use crate::{
-    AppState, api::utils::generate_random_string, constants::BALLOT_CODE_RANDOM_LENGTH,
-    error::AppError,
-};
+use crate::{AppState, api::utils::generate_random_string, error::AppError};
 
 fn select_operators(operator_ids: &[i32]) -> Result<(i32, i32), AppError> {
     if operator_ids.len() < 2 {
@@ -29,41 +28,50 @@ fn select_operators(operator_ids: &[i32]) -> Result<(i32, i32), AppError> {
 #[axum::debug_handler]
 pub async fn ballot_bench_new(
     State(state): State<Arc<AppState>>,
-) -> Result<Json<ApiResponse<BallotCreateResponse>>, AppError> {
+) -> Result<ApiResponse<BallotCreateResponse>, AppError> {
     let topic = match state
         .topic_service
         .get_topic("crisis_v2_season_4_1_benchtest")
         .await
     {
         Ok(Some(topic)) if topic.is_topic_active() => topic,
+        Ok(None) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
+                data: ApiData::Empty,
+                message: ApiMsg::TargetTopicNotFound,
+            });
+        }
         Ok(_) => {
-            return Ok(Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::Forbidden,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotActive,
-            }));
+            });
         }
-        Err(_) => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+        Err(e) => {
+            tracing::error!("Failed to load topic crisis_v2_season_4_1_benchtest: {}", e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InternalError,
+            });
         }
     };
-    let topic_id = topic.id;
+    let topic_id = TopicId::parse(topic.id)
+        .map_err(|e| AppError::InternalError(format!("Invalid bench topic id: {e}")))?;
     let candidate_pool = match state
         .topic_service
         .get_candidate_pool(&topic_id, &state.character_infos)
         .await
     {
-        Some(pool) => pool,
-        None => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+        Ok(pool) => pool,
+        Err(err) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: err.into(),
+            });
         }
     };
 
@@ -72,11 +80,12 @@ pub async fn ballot_bench_new(
             let (left, right) = select_operators(&candidate_pool)?;
 
             let id = state.snowflake.next_id()?;
-            let random_string = generate_random_string(BALLOT_CODE_RANDOM_LENGTH);
+            let random_string =
+                generate_random_string(state.vote.ballot_code_length(&topic.vote_overrides));
             let ballot_id = format!("{id}-{random_string}");
 
             let mut conn = state.redis.connection.clone();
-            let ballot_key = format!("{topic_id}:ballot:{ballot_id}");
+            let ballot_key = topic_id.ballot_key(&ballot_id);
             let ballot_value = format!("{left},{right}");
             let _: () = conn.set_ex(&ballot_key, &ballot_value, 86400).await?; // 24 hours expiration
 
@@ -91,22 +100,23 @@ pub async fn ballot_bench_new(
             );
 
             let rsp = BallotCreateResponse::Pairwise {
-                topic_id,
+                topic_id: topic_id.into(),
                 ballot_id,
                 left,
                 right,
+                portraits: None,
             };
 
-            Ok(Json(ApiResponse {
-                status: 0,
+            Ok(ApiResponse {
+                status: ResponseStatus::Ok,
                 data: ApiData::Data(rsp),
                 message: ApiMsg::OK,
-            }))
+            })
         }
-        _ => Ok(Json(ApiResponse {
-            status: 1,
+        _ => Ok(ApiResponse {
+            status: ResponseStatus::Unsupported,
             data: ApiData::Empty,
             message: ApiMsg::UnsupportedTopicType,
-        })),
+        }),
     }
 }
@@ -1,44 +1,38 @@
 use std::{net::SocketAddr, sync::Arc};
 
 use axum::{
-    Json,
     extract::{ConnectInfo, State},
     http::HeaderMap,
 };
-use share::models::{
-    api::{ApiData, ApiMsg, ApiResponse, BallotSaveRequest, BallotSaveResponse, PairwiseSaveScore},
-    database::{Ballot, BallotInfo, PairwiseBallot},
+use dashmap::DashMap;
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, BallotSaveRequest, BallotSaveResponse, PairwiseSaveScore,
+    ResponseStatus,
 };
 
 use crate::{AppState, api::utils::publish_and_ack, error::AppError};
 
+/// Takes an arbitrary pending bench ballot out of the store, `None` when the
+/// store is empty (e.g. `bench_save` called before any `bench_new`).
+fn take_bench_ballot(store: &DashMap<String, BallotSaveRequest>) -> Option<BallotSaveRequest> {
+    let key = store.iter().next().map(|entry| entry.key().clone())?;
+    store.remove(&key).map(|(_, req)| req)
+}
+
 #[axum::debug_handler]
 pub async fn ballot_bench_save(
     headers: HeaderMap,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<ApiResponse<BallotSaveResponse>>, AppError> {
-    let key = {
-        match state.bench_ballot_store.iter().next() {
-            Some(entry) => entry.key().clone(),
-            None => {
-                return Ok(Json(ApiResponse {
-                    status: 404,
-                    data: ApiData::Empty,
-                    message: ApiMsg::BenchBallotNotFound,
-                }));
-            }
-        }
-    };
-
-    let req = match state.bench_ballot_store.remove(&key) {
-        Some((_, req)) => req,
+) -> Result<ApiResponse<BallotSaveResponse>, AppError> {
+    let req = match take_bench_ballot(&state.bench_ballot_store) {
+        Some(req) => req,
         None => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
                 message: ApiMsg::BenchBallotNotFound,
-            }));
+            });
         }
     };
 
@@ -47,39 +41,40 @@ pub async fn ballot_bench_save(
             topic
         }
         Ok(Some(topic)) if !topic.topic_type.matches_request(&req) => {
-            return Ok(Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::BadRequest,
                 data: ApiData::Empty,
                 message: ApiMsg::RequestTopicTypeMismatch,
-            }));
+            });
         }
         Ok(Some(topic)) if !topic.is_topic_active() => {
-            return Ok(Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::Forbidden,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotActive,
-            }));
+            });
         }
         Ok(None) => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotFound,
-            }));
+            });
         }
         Ok(Some(_)) => {
-            return Ok(Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
                 message: ApiMsg::InternalError,
-            }));
+            });
         }
-        Err(_) => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id(), e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InternalError,
+            });
         }
     };
 
@@ -89,44 +84,61 @@ pub async fn ballot_bench_save(
         .and_then(|v| v.to_str().ok())
         .unwrap_or("unknown");
 
-    match req {
-        BallotSaveRequest::Pairwise(PairwiseSaveScore {
-            topic_id,
-            ballot_id,
-            winner,
-            loser,
-        }) => {
-            if winner == loser {
-                return Err(AppError::SameParticipant);
-            }
+    match &req {
+        BallotSaveRequest::Pairwise(PairwiseSaveScore { winner, loser, .. }) if winner == loser => {
+            return Err(AppError::SameParticipant);
+        }
+        BallotSaveRequest::Pairwise(_) => {}
+        _ => {
+            return Err(AppError::InternalError(
+                "Unsupported request type".to_string(),
+            ));
+        }
+    }
 
-            let ballot = Ballot::Pairwise(PairwiseBallot {
-                info: BallotInfo {
-                    topic_id: topic_id.into(),
-                    ballot_id: ballot_id.into(),
-                    ip: ip.into(),
-                    user_agent: user_agent.into(),
-                    timestamp: chrono::Utc::now().timestamp_millis(),
-                },
-                win: winner,
-                lose: loser,
-            });
+    let ballot = req.into_ballot(ip, user_agent, chrono::Utc::now().timestamp_millis());
 
-            publish_and_ack(
-                &state.jetstream,
-                "ark-vote.save_score",
-                serde_json::to_vec(&ballot)?,
-            )
-            .await?;
+    publish_and_ack(
+        &state.jetstream,
+        "ark-vote.save_score",
+        serde_json::to_vec(&ballot)?,
+    )
+    .await?;
 
-            Ok(Json(ApiResponse {
-                status: 0,
-                data: ApiData::Data(BallotSaveResponse { code: 0 }),
-                message: ApiMsg::OK,
-            }))
-        }
-        _ => Err(AppError::InternalError(
-            "Unsupported request type".to_string(),
-        )),
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(BallotSaveResponse { code: 0 }),
+        message: ApiMsg::OK,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use share::models::topic_id::TopicId;
+
+    use super::*;
+
+    #[test]
+    fn test_take_bench_ballot_empty_store() {
+        let store = DashMap::new();
+        assert!(take_bench_ballot(&store).is_none());
+    }
+
+    #[test]
+    fn test_take_bench_ballot_removes_entry() {
+        let store = DashMap::new();
+        store.insert(
+            "crisis_v2_season_4_1:ballot:1-abc".to_string(),
+            BallotSaveRequest::Pairwise(PairwiseSaveScore {
+                topic_id: TopicId::parse("crisis_v2_season_4_1").unwrap(),
+                ballot_id: "1-abc".to_string(),
+                winner: 1001,
+                loser: 1002,
+            }),
+        );
+
+        assert!(take_bench_ballot(&store).is_some());
+        assert!(store.is_empty());
+        assert!(take_bench_ballot(&store).is_none());
     }
 }
@@ -1,27 +1,35 @@
 use std::sync::Arc;
 
-use axum::{Json, extract::State};
-use rand::seq::IndexedRandom as _;
+use axum::extract::State;
+use rand::RngCore;
 use redis::AsyncCommands as _;
-use share::models::{
-    api::{ApiData, ApiMsg, ApiResponse, BallotCreateRequest, BallotCreateResponse},
-    database::VotingTopicType,
+use share::{
+    metrics::{BALLOT_CODES_CREATED_FIELD, count_ballot_code},
+    models::{
+        api::{
+            ApiData, ApiMsg, ApiResponse, BallotCreateRequest, BallotCreateResponse, ResponseStatus,
+        },
+        database::VotingTopicType,
+        excel::CharacterInfo,
+    },
+    selection::{PairingConstraint, SelectionContext, select_pair},
 };
 
 use crate::{
-    AppState, api::utils::generate_random_string, constants::BALLOT_CODE_RANDOM_LENGTH,
+    AppState,
+    api::utils::{ApiJson, generate_random_string},
     error::AppError,
 };
 
-fn select_operators(operator_ids: &[i32]) -> Result<(i32, i32), AppError> {
-    if operator_ids.len() < 2 {
-        return Err(AppError::InsufficientOperators);
-    }
-
-    let mut rng = rand::rng();
-    let selected: [i32; 2] = operator_ids.choose_multiple_array(&mut rng).unwrap();
-
-    Ok((selected[0], selected[1]))
+fn select_operators(
+    operator_ids: &[i32],
+    character_infos: &[CharacterInfo],
+    constraint: PairingConstraint,
+    context: &SelectionContext<'_>,
+    rng: &mut dyn RngCore,
+) -> Result<(i32, i32), AppError> {
+    select_pair(operator_ids, character_infos, constraint, context, rng)
+        .ok_or(AppError::InsufficientOperators)
 }
 
 #[utoipa::path(
@@ -30,7 +38,9 @@ fn select_operators(operator_ids: &[i32]) -> Result<(i32, i32), AppError> {
     request_body = BallotCreateRequest,
     responses(
         (status = 200, description = "Create a new ballot", body = ApiResponse<BallotCreateResponse>),
-        (status = 404, description = "Topic not found or inactive", body = ApiResponse<String>),
+        (status = 400, description = "Invalid operator id, candidate pool too small or topic type not enabled", body = ApiResponse<String>),
+        (status = 403, description = "Voting on the topic is paused or closed", body = ApiResponse<String>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
         (status = 500, description = "Internal server error", body = ApiResponse<String>)
     ),
     tag = "Ballot",
@@ -39,72 +49,150 @@ fn select_operators(operator_ids: &[i32]) -> Result<(i32, i32), AppError> {
 #[axum::debug_handler]
 pub async fn ballot_create(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<BallotCreateRequest>,
-) -> Result<Json<ApiResponse<BallotCreateResponse>>, AppError> {
+    ApiJson(req): ApiJson<BallotCreateRequest>,
+) -> Result<ApiResponse<BallotCreateResponse>, AppError> {
+    if let Some(id) = req.invalid_operator_id() {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::InvalidOperatorId(id),
+        });
+    }
+
     let topic = match state.topic_service.get_topic(&req.topic_id).await {
-        Ok(Some(topic)) if topic.is_topic_active() => topic,
-        Ok(_) => {
-            return Ok(Json(ApiResponse {
-                status: 500,
+        Ok(Some(topic)) => topic,
+        Ok(None) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotActive,
-            }));
+                message: ApiMsg::TargetTopicNotFound,
+            });
         }
-        Err(_) => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InternalError,
+            });
         }
     };
+    if let Some(disabled) =
+        ApiResponse::topic_type_disabled(&state.vote.enabled_topic_types, &topic.topic_type)
+    {
+        return Ok(disabled);
+    }
+    if let Some(refused) = ApiResponse::ballots_refused(&topic) {
+        return Ok(refused);
+    }
+
     let topic_id = topic.id;
     let candidate_pool = match state
         .topic_service
         .get_candidate_pool(&topic_id, &state.character_infos)
         .await
     {
-        Some(pool) => pool,
-        None => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+        Ok(pool) => pool,
+        Err(err) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: err.into(),
+            });
         }
     };
+    if let Err(err) = topic
+        .topic_type
+        .check_operator_count(candidate_pool.len(), &state.vote.ballot_size_limits)
+    {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: err.into(),
+        });
+    }
 
     match topic.topic_type {
         VotingTopicType::Pairwise => {
-            let (left, right) = select_operators(&candidate_pool)?;
+            let games = match state.vote.pairing_constraint {
+                PairingConstraint::FavorUndersampled => Some(
+                    state
+                        .operator_games
+                        .get(
+                            state.redis.connection.clone(),
+                            &req.topic_id,
+                            &candidate_pool,
+                            state.vote.undersampled_bias,
+                        )
+                        .await,
+                ),
+                _ => None,
+            };
+            let shown = match state.vote.pairing_constraint {
+                PairingConstraint::AvoidRecentlyShown => {
+                    state
+                        .vote
+                        .recently_shown
+                        .load(state.redis.connection.clone(), &topic_id)
+                        .await
+                }
+                _ => Vec::new(),
+            };
+            let seed = state.vote.selection_seed(&topic.vote_overrides);
+            let (left, right) = state.selection_rngs.with_rng(&topic_id, seed, |rng| {
+                select_operators(
+                    &candidate_pool,
+                    &state.character_infos,
+                    state.vote.pairing_constraint,
+                    &SelectionContext {
+                        recent_winners: req.recent_winners(),
+                        recently_shown: &shown,
+                        games: games.as_deref(),
+                    },
+                    rng,
+                )
+            })?;
+            if state.vote.pairing_constraint == PairingConstraint::AvoidRecentlyShown {
+                state.vote.recently_shown.record(
+                    state.redis.connection.clone(),
+                    &topic_id,
+                    [left, right],
+                );
+            }
 
             let id = state.snowflake.next_id()?;
-            let random_string = generate_random_string(BALLOT_CODE_RANDOM_LENGTH);
+            let random_string =
+                generate_random_string(state.vote.ballot_code_length(&topic.vote_overrides));
             let ballot_id = format!("{id}-{random_string}");
 
             let mut conn = state.redis.connection.clone();
-            let ballot_key = format!("{topic_id}:ballot:{ballot_id}");
+            let ballot_key = req.topic_id.ballot_key(&ballot_id);
             let ballot_value = format!("{left},{right}");
             let _: () = conn.set_ex(&ballot_key, &ballot_value, 86400).await?; // 24 hours expiration
+            count_ballot_code(&mut conn, &topic_id, BALLOT_CODES_CREATED_FIELD).await;
 
-            let rsp = BallotCreateResponse::Pairwise {
+            let mut rsp = BallotCreateResponse::Pairwise {
                 topic_id,
                 ballot_id,
                 left,
                 right,
+                portraits: None,
             };
+            if req.include_portraits {
+                rsp.embed_portraits(&state.character_portraits.load());
+            }
 
-            Ok(Json(ApiResponse {
-                status: 0,
+            Ok(ApiResponse {
+                status: ResponseStatus::Ok,
                 data: ApiData::Data(rsp),
                 message: ApiMsg::OK,
-            }))
+            })
         }
-        _ => Ok(Json(ApiResponse {
-            status: 1,
+        _ => Ok(ApiResponse {
+            status: ResponseStatus::Unsupported,
             data: ApiData::Empty,
             message: ApiMsg::UnsupportedTopicType,
-        })),
+        }),
     }
 }
 
@@ -124,7 +212,14 @@ mod tests {
     #[test]
     fn test_select_operators() {
         let operators = vec![1, 2, 3, 4, 5];
-        let (left, right) = select_operators(&operators).unwrap();
+        let (left, right) = select_operators(
+            &operators,
+            &[],
+            PairingConstraint::Any,
+            &SelectionContext::default(),
+            &mut rand::rng(),
+        )
+        .unwrap();
         assert_ne!(left, right);
         assert!(operators.contains(&left));
         assert!(operators.contains(&right));
@@ -133,6 +228,15 @@ mod tests {
     #[test]
     fn test_select_operators_insufficient() {
         let operators = vec![1];
-        assert!(select_operators(&operators).is_err());
+        assert!(
+            select_operators(
+                &operators,
+                &[],
+                PairingConstraint::Any,
+                &SelectionContext::default(),
+                &mut rand::rng(),
+            )
+            .is_err()
+        );
     }
 }
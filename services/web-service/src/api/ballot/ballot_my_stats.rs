@@ -0,0 +1,66 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::extract::{ConnectInfo, State};
+use redis::AsyncCommands as _;
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, BallotMyStatsRequest, BallotMyStatsResponse, ResponseStatus,
+};
+
+use crate::{AppState, api::utils::ApiJson, error::AppError};
+
+#[utoipa::path(
+    post,
+    path = "/ballot/my_stats",
+    request_body = BallotMyStatsRequest,
+    responses(
+        (status = 200, description = "Vote count of the caller's IP for a topic", body = ApiResponse<BallotMyStatsResponse>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
+        (status = 500, description = "Internal server error", body = ApiResponse<String>)
+    ),
+    tag = "Ballot",
+    operation_id = "ballotMyStats"
+)]
+#[axum::debug_handler]
+pub async fn ballot_my_stats(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<BallotMyStatsRequest>,
+) -> Result<ApiResponse<BallotMyStatsResponse>, AppError> {
+    let topic = match state.topic_service.get_topic(&req.topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
+                data: ApiData::Empty,
+                message: ApiMsg::TargetTopicNotFound,
+            });
+        }
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
+                data: ApiData::Empty,
+                message: ApiMsg::InternalError,
+            });
+        }
+    };
+
+    let ip = addr.ip().to_string();
+    let counter_key = req.topic_id.ip_counter_key(&ip);
+
+    let mut conn = state.redis.connection.clone();
+    let vote_count: Option<i64> = conn.get(&counter_key).await?;
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(BallotMyStatsResponse::new(
+            topic.id,
+            vote_count.unwrap_or(0),
+            topic
+                .vote_overrides
+                .max_ip_limit
+                .unwrap_or(state.vote.max_ip_limit),
+        )),
+        message: ApiMsg::OK,
+    })
+}
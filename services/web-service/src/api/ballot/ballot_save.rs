@@ -1,16 +1,24 @@
 use std::{net::SocketAddr, sync::Arc};
 
 use axum::{
-    Json,
     extract::{ConnectInfo, State},
     http::HeaderMap,
 };
-use share::models::{
-    api::{ApiData, ApiMsg, ApiResponse, BallotSaveRequest, BallotSaveResponse, PairwiseSaveScore},
-    database::{Ballot, BallotInfo, PairwiseBallot},
+use share::{
+    config::SaveThrottleMode,
+    models::api::{
+        ApiData, ApiMsg, ApiResponse, BallotSaveRequest, BallotSaveResponse, PairwiseSaveScore,
+        ResponseStatus,
+    },
+    reputation::{USER_TOKEN_HEADER, authenticate_user},
+    throttle::is_save_throttled,
 };
 
-use crate::{AppState, api::utils::publish_and_ack, error::AppError};
+use crate::{
+    AppState,
+    api::utils::{ApiJson, publish_and_ack},
+    error::AppError,
+};
 
 #[utoipa::path(
     post,
@@ -18,7 +26,9 @@ use crate::{AppState, api::utils::publish_and_ack, error::AppError};
     request_body = BallotSaveRequest,
     responses(
         (status = 200, description = "Save ballot successfully", body = ApiResponse<BallotSaveResponse>),
-        (status = 400, description = "Invalid request", body = ApiResponse<String>),
+        (status = 400, description = "Invalid request, ballot saved too soon, topic type not enabled, not pairwise or not the topic's", body = ApiResponse<String>),
+        (status = 401, description = "Invalid user token", body = ApiResponse<String>),
+        (status = 403, description = "Voting on the topic is paused or closed", body = ApiResponse<String>),
         (status = 404, description = "Topic not found", body = ApiResponse<String>),
         (status = 500, description = "Internal server error", body = ApiResponse<String>)
     ),
@@ -30,46 +40,71 @@ pub async fn ballot_save(
     headers: HeaderMap,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(state): State<Arc<AppState>>,
-    Json(req): Json<BallotSaveRequest>,
-) -> Result<Json<ApiResponse<BallotSaveResponse>>, AppError> {
-    let _target_topic = match state.topic_service.get_topic(req.topic_id()).await {
-        Ok(Some(topic)) if topic.is_topic_active() && topic.topic_type.matches_request(&req) => {
-            topic
-        }
+    ApiJson(req): ApiJson<BallotSaveRequest>,
+) -> Result<ApiResponse<BallotSaveResponse>, AppError> {
+    let target_topic = match state.topic_service.get_topic(req.topic_id()).await {
         Ok(Some(topic)) if !topic.topic_type.matches_request(&req) => {
-            return Ok(Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::BadRequest,
                 data: ApiData::Empty,
                 message: ApiMsg::RequestTopicTypeMismatch,
-            }));
-        }
-        Ok(Some(topic)) if !topic.is_topic_active() => {
-            return Ok(Json(ApiResponse {
-                status: 500,
-                data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotActive,
-            }));
+            });
         }
+        Ok(Some(topic)) => topic,
         Ok(None) => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotFound,
-            }));
+            });
         }
-        Ok(Some(_)) => {
-            return Ok(Json(ApiResponse {
-                status: 500,
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id(), e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
                 message: ApiMsg::InternalError,
-            }));
+            });
         }
-        Err(_) => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+    };
+    if let Some(disabled) =
+        ApiResponse::topic_type_disabled(&state.vote.enabled_topic_types, &target_topic.topic_type)
+    {
+        return Ok(disabled);
+    }
+    if let Some(refused) = ApiResponse::ballots_refused(&target_topic) {
+        return Ok(refused);
+    }
+    if let Some(unsupported) = ApiResponse::ballot_save_unsupported(&req) {
+        return Ok(unsupported);
+    }
+
+    if let Err(limit) = req.check_size_limits(&state.vote.ballot_size_limits) {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::BallotSetTooLarge(limit),
+        });
+    }
+
+    if let Some(id) = req.invalid_operator_id() {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::InvalidOperatorId(id),
+        });
+    }
+
+    let user_token = headers.get(USER_TOKEN_HEADER).and_then(|v| v.to_str().ok());
+    let user_id = match authenticate_user(state.vote.user_tokens.as_ref(), user_token) {
+        Ok(user_id) => user_id,
+        Err(e) => {
+            tracing::debug!("Rejected user token: {}", e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::Unauthorized,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InvalidUserToken,
+            });
         }
     };
 
@@ -79,61 +114,53 @@ pub async fn ballot_save(
         .and_then(|v| v.to_str().ok())
         .unwrap_or("unknown");
 
-    match req {
-        BallotSaveRequest::Pairwise(PairwiseSaveScore {
-            topic_id,
-            ballot_id,
-            winner,
-            loser,
-        }) => {
-            if winner == loser {
-                return Err(AppError::SameParticipant);
-            }
+    if let BallotSaveRequest::Pairwise(PairwiseSaveScore { winner, loser, .. }) = &req
+        && winner == loser
+    {
+        return Err(AppError::SameParticipant);
+    }
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let too_soon =
+        state
+            .vote
+            .ballot_grace
+            .is_too_soon(&state.snowflake, req.ballot_id(), now as u64);
+    if too_soon && state.vote.ballot_grace.mode == SaveThrottleMode::Reject {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::BallotSavedTooSoon,
+        });
+    }
 
-            let ballot = Ballot::Pairwise(PairwiseBallot {
-                info: BallotInfo {
-                    topic_id: topic_id.into(),
-                    ballot_id: ballot_id.into(),
-                    ip: ip.into(),
-                    user_agent: user_agent.into(),
-                    timestamp: chrono::Utc::now().timestamp_millis(),
-                },
-                win: winner,
-                lose: loser,
-            });
+    let throttled = is_save_throttled(
+        state.redis.connection.clone(),
+        &state.vote.save_throttle,
+        &ip,
+    )
+    .await;
+    if throttled && state.vote.save_throttle.mode == SaveThrottleMode::Reject {
+        return Ok(ApiResponse {
+            status: ResponseStatus::TooManyRequests,
+            data: ApiData::Empty,
+            message: ApiMsg::RateLimited,
+        });
+    }
 
-            // state.task_manager.spawn({
-            //     let state = state.clone();
-            //     let ballot = match serde_json::to_vec(&ballot) {
-            //         Ok(data) => data,
-            //         Err(e) => {
-            //             tracing::error!("Failed to serialize ballot: {}", e);
-            //             return Err(AppError::SerdeJson(e));
-            //         }
-            //     };
-            //     move || async move {
-            //         if let Err(e) =
-            //             publish_and_ack(&state.jetstream, "ark-vote.save_score", ballot).await
-            //         {
-            //             tracing::error!("Failed to publish ballot: {}", e);
-            //         }
-            //     }
-            // });
-            publish_and_ack(
-                &state.jetstream,
-                "ark-vote.save_score",
-                serde_json::to_vec(&ballot)?,
-            )
-            .await?;
+    let mut ballot = req.into_ballot(ip, user_agent, now);
+    ballot.info_mut().user_id = user_id.map(Into::into);
+    ballot.info_mut().throttled = throttled || too_soon;
 
-            Ok(Json(ApiResponse {
-                status: 0,
-                data: ApiData::Data(BallotSaveResponse { code: 0 }),
-                message: ApiMsg::OK,
-            }))
-        }
-        _ => Err(AppError::InternalError(
-            "Unsupported request type".to_string(),
-        )),
+    let payload = serde_json::to_vec(&ballot)?;
+    match &state.ballot_bundler {
+        Some(bundler) => bundler.publish(payload).await?,
+        None => publish_and_ack(&state.jetstream, "ark-vote.save_score", payload).await?,
     }
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(BallotSaveResponse { code: 0 }),
+        message: ApiMsg::OK,
+    })
 }
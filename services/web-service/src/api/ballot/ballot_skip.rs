@@ -1,17 +1,24 @@
 use std::sync::Arc;
 
-use axum::{Json, extract::State};
-use share::models::api::{ApiData, ApiMsg, ApiResponse, BallotSkipRequest, BallotSkipResponse};
+use axum::extract::State;
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, BallotSkipRequest, BallotSkipResponse, ResponseStatus,
+};
 
-use crate::{AppState, api::utils::publish_and_ack, error::AppError};
+use crate::{
+    AppState,
+    api::utils::{ApiJson, publish_and_ack},
+    error::AppError,
+};
 
 #[utoipa::path(
     post,
     path = "/ballot/skip",
     request_body = BallotSkipRequest,
     responses(
-        (status = 200, description = "Skip a ballot", body = ApiResponse<BallotSkipResponse>),
-        (status = 404, description = "Topic not found or inactive", body = ApiResponse<String>),
+        (status = 200, description = "Skip a ballot, the status is 0 (it was 200 before)", body = ApiResponse<BallotSkipResponse>),
+        (status = 403, description = "Topic is inactive", body = ApiResponse<String>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
         (status = 500, description = "Internal server error", body = ApiResponse<String>)
     ),
     tag = "Ballot",
@@ -20,39 +27,40 @@ use crate::{AppState, api::utils::publish_and_ack, error::AppError};
 #[axum::debug_handler]
 pub async fn ballot_skip(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<BallotSkipRequest>,
-) -> Result<Json<ApiResponse<BallotSkipResponse>>, AppError> {
+    ApiJson(req): ApiJson<BallotSkipRequest>,
+) -> Result<ApiResponse<BallotSkipResponse>, AppError> {
     let _ = match state.topic_service.get_topic(&req.topic_id).await {
         Ok(Some(topic)) if topic.is_topic_active() => topic,
         Ok(None) => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotFound,
-            }));
+            });
         }
         Ok(_) => {
-            return Ok(Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::Forbidden,
                 data: ApiData::Empty,
                 message: ApiMsg::TargetTopicNotActive,
-            }));
+            });
         }
-        Err(_) => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InternalError,
+            });
         }
     };
 
     let req_data = serde_json::to_vec(&req).map_err(AppError::from)?;
     publish_and_ack(&state.jetstream, "ark-vote.ballot_skip", req_data).await?;
 
-    Ok(Json(ApiResponse {
-        status: 200,
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
         data: ApiData::Data(BallotSkipResponse { code: 0 }),
         message: ApiMsg::OK,
-    }))
+    })
 }
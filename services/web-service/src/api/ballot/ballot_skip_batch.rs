@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use share::models::api::{ApiResponse, BallotSkipBatchResponse, BallotSkipRequest};
+
+use crate::{
+    AppState,
+    api::utils::{ApiJson, publish_and_ack},
+    error::AppError,
+};
+
+#[utoipa::path(
+    post,
+    path = "/ballot/skip_batch",
+    request_body = Vec<BallotSkipRequest>,
+    responses(
+        (status = 200, description = "Skip several ballots with one outcome per skip, the status is 0 (it was 200 before)", body = ApiResponse<BallotSkipBatchResponse>),
+        (status = 400, description = "Too many skips in one batch", body = ApiResponse<String>),
+        (status = 500, description = "Internal server error", body = ApiResponse<String>)
+    ),
+    tag = "Ballot",
+    operation_id = "ballotSkipBatch"
+)]
+#[axum::debug_handler]
+pub async fn ballot_skip_batch(
+    State(state): State<Arc<AppState>>,
+    ApiJson(reqs): ApiJson<Vec<BallotSkipRequest>>,
+) -> Result<ApiResponse<BallotSkipBatchResponse>, AppError> {
+    let state = &state;
+    // deleting a ballot key that already expired is a no-op in the consumer
+    Ok(BallotSkipBatchResponse::respond(
+        reqs,
+        |topic_id| async move { state.topic_service.get_topic(&topic_id).await },
+        |req| async move {
+            let req_data = serde_json::to_vec(&req).map_err(AppError::from)?;
+            publish_and_ack(&state.jetstream, "ark-vote.ballot_skip", req_data).await
+        },
+    )
+    .await)
+}
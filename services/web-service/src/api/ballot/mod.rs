@@ -10,20 +10,26 @@ use crate::state::AppState;
 pub mod ballot_bench_new;
 pub mod ballot_bench_save;
 pub mod ballot_create;
+pub mod ballot_my_stats;
 pub mod ballot_save;
 pub mod ballot_skip;
+pub mod ballot_skip_batch;
 
 use ballot_bench_new::ballot_bench_new;
 use ballot_bench_save::ballot_bench_save;
 use ballot_create::ballot_create;
+use ballot_my_stats::ballot_my_stats;
 use ballot_save::ballot_save;
 use ballot_skip::ballot_skip;
+use ballot_skip_batch::ballot_skip_batch;
 
 pub fn ballot_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/new", post(ballot_create)) // 创建新 ballot
         .route("/save", post(ballot_save)) // 保存 ballot
         .route("/skip", post(ballot_skip)) // 跳过 ballot
+        .route("/skip_batch", post(ballot_skip_batch)) // 批量跳过 ballot
+        .route("/my_stats", post(ballot_my_stats)) // 查询当前 IP 的投票次数
         .route("/bench_new", get(ballot_bench_new))
         .route("/bench_save", get(ballot_bench_save))
 }
@@ -1,27 +1,217 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use axum::Router;
+use share::{auth::AdminTokens, config::RouteTimeouts};
+use tower_http::timeout::TimeoutLayer;
 
 use crate::AppState;
 
+mod admin;
 mod audit;
 mod ballot;
 mod openapi;
+mod operators;
 mod results;
 mod topic;
 mod utils;
 
+use admin::admin_routes;
 use audit::audit_routes;
 use ballot::ballot_routes;
+use operators::operators_routes;
 use results::results_routes;
 use topic::topic_routes;
 
 pub use openapi::ApiDoc;
+pub(crate) use results::results_final_order::final_order;
+pub(crate) use utils::publish_and_ack;
 
-pub fn routes() -> Router<Arc<AppState>> {
-    Router::new()
-        .nest("/topic", topic_routes())
-        .nest("/ballot", ballot_routes())
-        .nest("/audit", audit_routes())
-        .nest("/results", results_routes())
+/// Each route group gets the timeout configured for its prefix in
+/// `server.timeouts.routes`, falling back to `server.timeouts.default_secs`.
+pub fn routes(admin_tokens: AdminTokens, timeouts: &RouteTimeouts) -> Router<Arc<AppState>> {
+    let nest = |router: Router<Arc<AppState>>, prefix: &str, routes: Router<Arc<AppState>>| {
+        router.nest(prefix, with_timeout(routes, timeouts.for_prefix(prefix)))
+    };
+
+    let router = nest(Router::new(), "/admin", admin_routes(admin_tokens));
+    let router = nest(router, "/topic", topic_routes());
+    let router = nest(router, "/ballot", ballot_routes());
+    let router = nest(router, "/audit", audit_routes());
+    let router = nest(router, "/operators", operators_routes());
+    nest(router, "/results", results_routes())
+}
+
+/// Applies a request timeout, `None` leaves the router without one.
+pub fn with_timeout<S>(router: Router<S>, timeout: Option<Duration>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    match timeout {
+        Some(timeout) => router.layer(TimeoutLayer::new(timeout)),
+        None => router,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        extract::DefaultBodyLimit,
+        http::{Request, StatusCode},
+        routing::post,
+    };
+    use share::{
+        config::DEFAULT_MAX_BODY_SIZE,
+        models::api::{ApiData, ApiMsg, ApiResponse, BallotCreateRequest, ResponseStatus},
+    };
+    use tower::ServiceExt as _;
+
+    use super::{utils::ApiJson, *};
+
+    fn body_limited_router() -> Router {
+        Router::new()
+            .route(
+                "/ballot/new",
+                post(|ApiJson(_): ApiJson<BallotCreateRequest>| async { StatusCode::OK }),
+            )
+            .layer(DefaultBodyLimit::max(DEFAULT_MAX_BODY_SIZE))
+    }
+
+    fn json_request(body: String) -> Request<Body> {
+        Request::post("/ballot/new")
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_body_within_limit() {
+        let body = serde_json::json!({ "topic_id": "crisis_v2_season_4_1" }).to_string();
+        let response = body_limited_router()
+            .oneshot(json_request(body))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    async fn response_json(response: axum::response::Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_invalid_json_returns_api_response() {
+        let response = body_limited_router()
+            .oneshot(json_request("{\"topic_id\": ".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response_json(response).await;
+        assert_eq!(body["status"], 400);
+        assert_eq!(body["message"], "InvalidJson");
+        assert!(body["data"].is_null());
+
+        let request = Request::post("/ballot/new")
+            .header("content-type", "text/plain")
+            .body(Body::from(r#"{"topic_id": "crisis_v2_season_4_1"}"#))
+            .unwrap();
+        let response = body_limited_router().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response_json(response).await["message"], "InvalidJson");
+    }
+
+    #[tokio::test]
+    async fn test_route_timeouts() {
+        let timeouts = RouteTimeouts {
+            default_secs: 0,
+            routes: [("/slow".to_string(), 1)].into(),
+        };
+        let sleepy = || {
+            Router::new().route(
+                "/",
+                axum::routing::get(|| async {
+                    tokio::time::sleep(Duration::from_millis(1500)).await;
+                    StatusCode::OK
+                }),
+            )
+        };
+        let router: Router = Router::new()
+            .nest(
+                "/slow",
+                with_timeout(sleepy(), timeouts.for_prefix("/slow")),
+            )
+            .nest(
+                "/stream",
+                with_timeout(sleepy(), timeouts.for_prefix("/stream")),
+            );
+
+        let response = router
+            .clone()
+            .oneshot(Request::get("/slow").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+
+        let response = router
+            .oneshot(Request::get("/stream").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body_rejected() {
+        let body = serde_json::json!({ "topic_id": "a".repeat(DEFAULT_MAX_BODY_SIZE) }).to_string();
+        let response = body_limited_router()
+            .oneshot(json_request(body))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(response_json(response).await["status"], 413);
+    }
+
+    #[tokio::test]
+    async fn test_http_status_follows_envelope_status() {
+        let respond = |status: ResponseStatus| async move {
+            Ok::<_, crate::error::AppError>(ApiResponse::<()> {
+                status,
+                data: ApiData::Empty,
+                message: ApiMsg::TargetTopicNotFound,
+            })
+        };
+        let router: Router = Router::new()
+            .route("/ok", post(move || respond(ResponseStatus::Ok)))
+            .route("/missing", post(move || respond(ResponseStatus::NotFound)))
+            .route(
+                "/negotiated",
+                post(|headers: axum::http::HeaderMap| async move {
+                    utils::negotiate(
+                        &headers,
+                        ApiResponse::<share::models::api::ResultsFinalOrderResponse> {
+                            status: ResponseStatus::NotFound,
+                            data: ApiData::Empty,
+                            message: ApiMsg::TargetTopicNotFound,
+                        },
+                    )
+                }),
+            );
+
+        for (uri, http, status) in [
+            ("/ok", StatusCode::OK, 0),
+            ("/missing", StatusCode::NOT_FOUND, 404),
+            ("/negotiated", StatusCode::NOT_FOUND, 404),
+        ] {
+            let response = router
+                .clone()
+                .oneshot(Request::post(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), http, "{uri}");
+            assert_eq!(response_json(response).await["status"], status, "{uri}");
+        }
+    }
 }
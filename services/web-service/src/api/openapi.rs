@@ -1,11 +1,23 @@
 use utoipa::OpenApi;
 
 use share::models::api::{
-    ApiMsg, AuditTopicsListResponse, BallotCreateRequest, BallotCreateResponse, BallotSaveRequest,
-    BallotSaveResponse, Results1v1MatrixResponse, ResultsFinalOrderRequest,
-    ResultsFinalOrderResponse, TopicCreateRequest, TopicCreateResponse, TopicInfoRequest,
-    TopicInfoResponse, TopicListActiveResponse,
+    AdminTopicInfoResponse, ApiMsg, AuditTopicsListResponse, BallotCreateRequest,
+    BallotCreateResponse, BallotLookupRequest, BallotLookupResponse, BallotMyStatsRequest,
+    BallotMyStatsResponse, BallotRecord, BallotSaveRequest, BallotSaveResponse,
+    BallotSkipBatchResponse, BallotSkipOutcome, BallotSkipRequest, CandidatePoolOrder,
+    CondorcetItem, ImageDimensions, MatrixFormat, OperatorInfo, OperatorsListResponse, PartnerItem,
+    Results1v1MatrixPair, Results1v1MatrixResponse, ResultsByProfessionRequest,
+    ResultsByProfessionResponse, ResultsCondorcetRequest, ResultsCondorcetResponse,
+    ResultsFinalOrderRequest, ResultsFinalOrderResponse, ResultsPartnersRequest,
+    ResultsPartnersResponse, ResultsVelocityRequest, ResultsVelocityResponse,
+    SubProfessionBlocklistPayload, TopicCreateRequest, TopicCreateResponse, TopicFreezePoolRequest,
+    TopicFreezePoolResponse, TopicInfoRequest, TopicInfoResponse, TopicListActiveResponse,
+    TopicPauseRequest, TopicReopenRequest, TopicResetScoresRequest, TopicResetScoresResponse,
+    TopicUpdateRequest, TopicUpdateResponse, TopicValidatePoolResponse, VelocityBucket,
 };
+use share::models::format::ResultFormat;
+use share::models::topic_id::TopicId;
+use share::ranking::RankingMethod;
 
 #[derive(OpenApi)]
 #[openapi(
@@ -14,19 +26,38 @@ use share::models::api::{
         description = "Backend for the Ark Vote application"
     ),
     tags(
+        (name = "Admin", description = "Token protected administration endpoints"),
         (name = "Audit", description = "Topic audit related endpoints"),
         (name = "Ballot", description = "Voting ballot related endpoints"),
+        (name = "Operators", description = "Operator catalog endpoints"),
         (name = "Results", description = "Voting results related endpoints"),
         (name = "Topic", description = "Topic info related endpoints"),
     ),
     paths(
+        crate::api::admin::ballot_lookup::ballot_lookup,
+        crate::api::admin::sub_profession_blocklist::sub_profession_blocklist,
+        crate::api::admin::sub_profession_blocklist::sub_profession_blocklist_update,
+        crate::api::admin::topic_freeze_pool::topic_freeze_pool,
+        crate::api::admin::topic_info::topic_info,
+        crate::api::admin::topic_pause::topic_pause,
+        crate::api::admin::topic_reopen::topic_reopen,
+        crate::api::admin::topic_reset_scores::topic_reset_scores,
+        crate::api::admin::topic_update::topic_update,
         crate::api::audit::audit_topic::audit_topic,
         crate::api::audit::audit_topics_list::audit_topics_list,
         crate::api::ballot::ballot_create::ballot_create,
         crate::api::ballot::ballot_save::ballot_save,
+        crate::api::ballot::ballot_skip_batch::ballot_skip_batch,
+        crate::api::ballot::ballot_my_stats::ballot_my_stats,
         crate::api::results::results_1v1_matrix::results_1v1_matrix,
+        crate::api::results::results_by_profession::results_by_profession,
+        crate::api::results::results_condorcet::results_condorcet,
         crate::api::results::results_final_order::results_final_order,
+        crate::api::results::results_partners::results_partners,
+        crate::api::results::results_velocity::results_velocity,
         crate::api::topic::topic_candidate_pool::topic_candidate_pool,
+        crate::api::topic::topic_validate_pool::topic_validate_pool,
+        crate::api::operators::operators_list::operators_list,
         crate::api::topic::topic_create::topic_create,
         crate::api::topic::topic_info::topic_info,
         crate::api::topic::topic_list_active::topic_list_active,
@@ -37,14 +68,53 @@ use share::models::api::{
         TopicCreateResponse,
         TopicInfoRequest,
         TopicInfoResponse,
+        AdminTopicInfoResponse,
         BallotCreateRequest,
         BallotCreateResponse,
         Results1v1MatrixResponse,
+        Results1v1MatrixPair,
+        MatrixFormat,
         BallotSaveRequest,
         BallotSaveResponse,
+        BallotSkipRequest,
+        BallotSkipBatchResponse,
+        BallotSkipOutcome,
+        BallotMyStatsRequest,
+        BallotMyStatsResponse,
         ResultsFinalOrderRequest,
         ResultsFinalOrderResponse,
+        ResultsByProfessionRequest,
+        ResultsByProfessionResponse,
+        ResultsCondorcetRequest,
+        ResultsCondorcetResponse,
+        CondorcetItem,
+        ResultsPartnersRequest,
+        ResultsPartnersResponse,
+        ResultsVelocityRequest,
+        ResultsVelocityResponse,
+        VelocityBucket,
+        PartnerItem,
+        RankingMethod,
+        ResultFormat,
+        TopicId,
         AuditTopicsListResponse,
+        TopicReopenRequest,
+        TopicPauseRequest,
+        TopicResetScoresRequest,
+        TopicResetScoresResponse,
+        TopicUpdateRequest,
+        TopicUpdateResponse,
+        TopicFreezePoolRequest,
+        TopicFreezePoolResponse,
+        TopicValidatePoolResponse,
+        OperatorsListResponse,
+        OperatorInfo,
+        ImageDimensions,
+        CandidatePoolOrder,
+        SubProfessionBlocklistPayload,
+        BallotLookupRequest,
+        BallotLookupResponse,
+        BallotRecord,
         ApiMsg
     ))
 )]
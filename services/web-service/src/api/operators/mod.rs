@@ -0,0 +1,13 @@
+use std::sync::Arc;
+
+use axum::{Router, routing::get};
+
+use crate::state::AppState;
+
+pub mod operators_list;
+
+use operators_list::operators_list;
+
+pub fn operators_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/", get(operators_list)) // 获取干员目录
+}
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, OperatorsListQuery, OperatorsListResponse, ResponseStatus,
+};
+
+use crate::AppState;
+
+#[utoipa::path(
+    get,
+    path = "/operators",
+    params(OperatorsListQuery),
+    responses(
+        (status = 200, description = "List the operator catalog", body = ApiResponse<OperatorsListResponse>)
+    ),
+    tag = "Operators",
+    operation_id = "operatorsList"
+)]
+#[axum::debug_handler]
+pub async fn operators_list(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<OperatorsListQuery>,
+) -> ApiResponse<OperatorsListResponse> {
+    ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(OperatorsListResponse::build(
+            &state.character_infos,
+            &state.character_portraits.load(),
+            &query,
+        )),
+        message: ApiMsg::OK,
+    }
+}
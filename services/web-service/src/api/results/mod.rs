@@ -5,13 +5,25 @@ use axum::{Router, routing::post};
 use crate::state::AppState;
 
 pub mod results_1v1_matrix;
+pub mod results_by_profession;
+pub mod results_condorcet;
 pub mod results_final_order;
+pub mod results_partners;
+pub mod results_velocity;
 
 use results_1v1_matrix::results_1v1_matrix;
+use results_by_profession::results_by_profession;
+use results_condorcet::results_condorcet;
 use results_final_order::results_final_order;
+use results_partners::results_partners;
+use results_velocity::results_velocity;
 
 pub fn results_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/1v1_matrix", post(results_1v1_matrix))
+        .route("/by_profession", post(results_by_profession))
+        .route("/condorcet", post(results_condorcet))
         .route("/final_order", post(results_final_order))
+        .route("/partners", post(results_partners))
+        .route("/velocity", post(results_velocity))
 }
@@ -1,21 +1,27 @@
 use std::{collections::HashMap, sync::Arc};
 
-use axum::{Json, extract::State};
+use axum::{extract::State, http::HeaderMap, response::Response};
+use chrono::Utc;
 use redis::AsyncCommands;
 use share::models::api::{
-    ApiData, ApiMsg, ApiResponse, Results1v1MatrixItem, Results1v1MatrixRequest,
-    Results1v1MatrixResponse,
+    ApiData, ApiMsg, ApiResponse, MatrixFormat, ResponseStatus, Results1v1MatrixItem,
+    Results1v1MatrixPair, Results1v1MatrixRequest, Results1v1MatrixResponse, TimedApiResponse,
 };
 
-use crate::{AppState, error::AppError};
+use crate::{
+    AppState,
+    api::utils::{ApiJson, negotiate_timed},
+    error::AppError,
+};
 
 #[utoipa::path(
     post,
     path = "/results/1v1_matrix",
     request_body = Results1v1MatrixRequest,
     responses(
-        (status = 200, description = "Get operators 1v1 matrix for a topic", body = ApiResponse<Results1v1MatrixResponse>),
+        (status = 200, description = "Get operators 1v1 matrix for a topic", body = TimedApiResponse<Results1v1MatrixResponse>),
         (status = 400, description = "Bad request", body = ApiResponse<String>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
         (status = 500, description = "Internal server error", body = ApiResponse<String>)
     ),
     tag = "Results",
@@ -24,45 +30,93 @@ use crate::{AppState, error::AppError};
 #[axum::debug_handler]
 pub async fn results_1v1_matrix(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<Results1v1MatrixRequest>,
-) -> Result<Json<ApiResponse<Results1v1MatrixResponse>>, AppError> {
-    let target_topic = match state.topic_service.get_topic(&req.topic_id).await {
-        Ok(Some(topic)) if topic.topic_type.supports_1v1_matrix() => topic,
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<Results1v1MatrixRequest>,
+) -> Result<Response, AppError> {
+    let response = matrix_1v1(&state, req).await?;
+
+    Ok(negotiate_timed(&headers, response, |matrix| {
+        matrix.computed_at
+    }))
+}
+
+async fn matrix_1v1(
+    state: &AppState,
+    req: Results1v1MatrixRequest,
+) -> Result<ApiResponse<Results1v1MatrixResponse>, AppError> {
+    match state.topic_service.get_topic(&req.topic_id).await {
+        Ok(Some(topic)) if topic.topic_type.supports_1v1_matrix() => {}
+        Ok(None) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
+                data: ApiData::Empty,
+                message: ApiMsg::TargetTopicNotFound,
+            });
+        }
         Ok(_) => {
-            return Ok(Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::BadRequest,
                 data: ApiData::Empty,
                 message: ApiMsg::CurTopicNotSupport1v1Matrix,
-            }));
+            });
         }
-        Err(_) => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InternalError,
+            });
         }
-    };
+    }
 
     let mut conn = state.redis.connection.clone();
 
-    let target_key = format!("{}:op_matrix", target_topic.id);
-    let data: HashMap<String, i64> = conn.hgetall(target_key).await?;
+    let response = match req.format {
+        MatrixFormat::Keyed => {
+            let target_key = req.topic_id.op_matrix_key();
+            let data: HashMap<String, i64> = conn.hgetall(target_key).await?;
 
-    let mut rsp = HashMap::new();
-    for (key, value) in data {
-        rsp.insert(
-            key,
-            Results1v1MatrixItem {
-                score: value,
-                count: 1,
-            },
-        );
-    }
+            let mut rsp = HashMap::new();
+            for (key, value) in data {
+                rsp.insert(
+                    key,
+                    Results1v1MatrixItem {
+                        score: value,
+                        count: 1,
+                    },
+                );
+            }
+
+            Results1v1MatrixResponse {
+                items: rsp,
+                pairs: Vec::new(),
+                computed_at: Utc::now(),
+            }
+        }
+        MatrixFormat::Pairs => {
+            // both hashes in one round trip
+            let (data, counter_data): (HashMap<String, i64>, HashMap<String, i64>) = redis::pipe()
+                .hgetall(req.topic_id.op_matrix_key())
+                .hgetall(req.topic_id.op_counter_key())
+                .query_async(&mut conn)
+                .await?;
 
-    Ok(Json(ApiResponse {
-        status: 0,
-        data: ApiData::Data(Results1v1MatrixResponse(rsp)),
+            Results1v1MatrixResponse {
+                items: HashMap::new(),
+                pairs: Results1v1MatrixPair::from_hashes(
+                    &data,
+                    &counter_data,
+                    state.base_multiplier,
+                ),
+                computed_at: Utc::now(),
+            }
+        }
+    };
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(response),
         message: ApiMsg::OK,
-    }))
+    })
 }
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use share::models::api::{
+    ApiData, ApiResponse, ResultsByProfessionRequest, ResultsByProfessionResponse,
+    ResultsFinalOrderRequest,
+};
+
+use crate::{
+    AppState, api::results::results_final_order::final_order, api::utils::ApiJson, error::AppError,
+};
+
+#[utoipa::path(
+    post,
+    path = "/results/by_profession",
+    request_body = ResultsByProfessionRequest,
+    responses(
+        (status = 200, description = "Get the final order of a topic grouped by profession", body = ApiResponse<ResultsByProfessionResponse>),
+        (status = 500, description = "Internal server error", body = ApiResponse<String>)
+    ),
+    tag = "Results",
+    operation_id = "resultsByProfession"
+)]
+pub async fn results_by_profession(
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<ResultsByProfessionRequest>,
+) -> Result<ApiResponse<ResultsByProfessionResponse>, AppError> {
+    let final_order_req = ResultsFinalOrderRequest {
+        topic_id: req.topic_id,
+        tier_breakpoints: None,
+        ranking_method: req.ranking_method,
+        limit: None,
+        format: Default::default(),
+    };
+    let final_order = final_order(&state, final_order_req).await?;
+
+    Ok(ApiResponse {
+        status: final_order.status,
+        data: match final_order.data {
+            ApiData::Data(final_order) => ApiData::Data(ResultsByProfessionResponse::build(
+                &final_order,
+                &state.character_infos,
+                req.limit,
+            )),
+            ApiData::Empty => ApiData::Empty,
+        },
+        message: final_order.message,
+    })
+}
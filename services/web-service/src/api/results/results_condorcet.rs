@@ -0,0 +1,43 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::extract::State;
+use redis::AsyncCommands;
+use share::models::api::{ApiResponse, ResultsCondorcetRequest, ResultsCondorcetResponse};
+
+use crate::{AppState, api::utils::ApiJson, error::AppError};
+
+#[utoipa::path(
+    post,
+    path = "/results/condorcet",
+    request_body = ResultsCondorcetRequest,
+    responses(
+        (status = 200, description = "Get the Condorcet winner or the Smith set of a topic", body = ApiResponse<ResultsCondorcetResponse>),
+        (status = 400, description = "Candidate pool too large or topic type without a 1v1 matrix", body = ApiResponse<String>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
+        (status = 500, description = "Internal server error", body = ApiResponse<String>)
+    ),
+    tag = "Results",
+    operation_id = "resultsCondorcet"
+)]
+#[axum::debug_handler]
+pub async fn results_condorcet(
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<ResultsCondorcetRequest>,
+) -> Result<ApiResponse<ResultsCondorcetResponse>, AppError> {
+    let mut conn = state.redis.connection.clone();
+    Ok(ResultsCondorcetResponse::respond(
+        state.topic_service.get_topic(&req.topic_id).await,
+        async |topic_id: &str| {
+            state
+                .topic_service
+                .get_candidate_pool(topic_id, &state.character_infos)
+                .await
+        },
+        async || {
+            conn.hgetall::<_, HashMap<String, i64>>(req.topic_id.op_matrix_key())
+                .await
+        },
+        &state.character_infos,
+    )
+    .await)
+}
@@ -1,15 +1,26 @@
 use std::{collections::HashMap, sync::Arc};
 
-use axum::{Json, extract::State};
-use share::models::{
-    api::{
-        ApiData, ApiMsg, ApiResponse, FinalOrderItem, ResultsFinalOrderRequest,
-        ResultsFinalOrderResponse,
+use axum::{extract::State, http::HeaderMap, response::Response};
+use chrono::Utc;
+use share::{
+    models::{
+        api::{
+            ApiData, ApiMsg, ApiResponse, FinalOrderItem, ResponseStatus, ResultsFinalOrderRequest,
+            ResultsFinalOrderResponse,
+        },
+        excel::CharacterInfo,
+        format::validate_result_format,
+        tier::{assign_tiers, validate_tier_breakpoints},
     },
-    excel::CharacterInfo,
+    ranking::RankingMethod,
+    scores::parse_operator_counts,
 };
 
-use crate::{AppState, error::AppError};
+use crate::{
+    AppState,
+    api::utils::{ApiJson, negotiate},
+    error::AppError,
+};
 
 #[derive(Debug)]
 struct OperatorResult {
@@ -90,6 +101,7 @@ fn generate_operators_info(
     responses(
         (status = 200, description = "Get final order for a topic", body = ApiResponse<ResultsFinalOrderResponse>),
         (status = 400, description = "Bad request", body = ApiResponse<String>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
         (status = 500, description = "Internal server error", body = ApiResponse<String>)
     ),
     tag = "Results",
@@ -98,25 +110,59 @@ fn generate_operators_info(
 #[axum::debug_handler]
 pub async fn results_final_order(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<ResultsFinalOrderRequest>,
-) -> Result<Json<ApiResponse<ResultsFinalOrderResponse>>, AppError> {
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<ResultsFinalOrderRequest>,
+) -> Result<Response, AppError> {
+    let response = final_order(&state, req).await?;
+
+    Ok(negotiate(&headers, response))
+}
+
+pub(crate) async fn final_order(
+    state: &AppState,
+    req: ResultsFinalOrderRequest,
+) -> Result<ApiResponse<ResultsFinalOrderResponse>, AppError> {
+    if let Some(breakpoints) = &req.tier_breakpoints
+        && !validate_tier_breakpoints(breakpoints)
+    {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::InvalidTierBreakpoints,
+        });
+    }
+    if !validate_result_format(&req.format) {
+        return Ok(ApiResponse {
+            status: ResponseStatus::BadRequest,
+            data: ApiData::Empty,
+            message: ApiMsg::InvalidResultFormat,
+        });
+    }
+
     let target_topic = match state.topic_service.get_topic(&req.topic_id).await {
         Ok(Some(topic)) if topic.topic_type.supports_final_order() => topic,
+        Ok(None) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
+                data: ApiData::Empty,
+                message: ApiMsg::TargetTopicNotFound,
+            });
+        }
         Ok(_) => {
             tracing::debug!("Topic {} does not support final order", req.topic_id);
-            return Ok(Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::BadRequest,
                 data: ApiData::Empty,
                 message: ApiMsg::CurTopicNotSupportFinalOrder,
-            }));
+            });
         }
-        Err(_) => {
-            tracing::debug!("Topic {} not found", req.topic_id);
-            return Ok(Json(ApiResponse {
-                status: 404,
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: ApiMsg::InternalError,
+            });
         }
     };
 
@@ -125,13 +171,13 @@ pub async fn results_final_order(
         .get_candidate_pool(&target_topic.id, &state.character_infos)
         .await
     {
-        Some(pool) => pool,
-        None => {
-            return Ok(Json(ApiResponse {
-                status: 404,
+        Ok(pool) => pool,
+        Err(err) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
                 data: ApiData::Empty,
-                message: ApiMsg::TargetTopicNotFound,
-            }));
+                message: err.into(),
+            });
         }
     };
     let operators_info = generate_operators_info(&candidate_pool, &state.character_infos);
@@ -148,7 +194,7 @@ pub async fn results_final_order(
     let (operator_values, total_valid_ballots): (Vec<Option<String>>, Option<i64>) = match state
         .redis
         .final_order_script
-        .key(&req.topic_id)
+        .key(req.topic_id.as_str())
         .arg(&operators_info.op_stats_all_fields)
         .invoke_async(&mut conn)
         .await
@@ -156,11 +202,11 @@ pub async fn results_final_order(
         Ok(result) => result,
         Err(err) => {
             tracing::error!("Failed to execute Lua script for final order: {}", err);
-            return Ok(Json(ApiResponse {
-                status: 500,
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
                 data: ApiData::Empty,
                 message: ApiMsg::InternalError,
-            }));
+            });
         }
     };
 
@@ -170,7 +216,20 @@ pub async fn results_final_order(
         operator_values
     );
 
-    let (win_counts, lose_counts) = parse_operator_counts(&operator_values, num_operators);
+    let Some((win_counts, lose_counts)) = parse_operator_counts(&operator_values, num_operators)
+    else {
+        tracing::error!(
+            "Final order script for topic {} returned {} values, expected {}",
+            req.topic_id,
+            operator_values.len(),
+            2 * num_operators
+        );
+        return Ok(ApiResponse {
+            status: ResponseStatus::InternalError,
+            data: ApiData::Empty,
+            message: ApiMsg::InternalError,
+        });
+    };
 
     let mut results = build_operator_results(
         &operators_info.operator_ids,
@@ -185,8 +244,8 @@ pub async fn results_final_order(
             .unwrap_or(std::cmp::Ordering::Equal)
     });
 
-    let response = ResultsFinalOrderResponse {
-        topic_id: req.topic_id,
+    let mut response = ResultsFinalOrderResponse {
+        topic_id: req.topic_id.into(),
         items: results
             .into_iter()
             .map(|r| FinalOrderItem {
@@ -194,32 +253,33 @@ pub async fn results_final_order(
                 id: r.id,
                 win: r.win,
                 lose: r.lose,
-                score: format!("{:.2}", r.score),
-                rate: format!("{:.1}%", r.rate),
+                score: req.format.score(r.score),
+                rate: req.format.rate(r.rate),
+                tier: None,
+                score_value: r.score,
+                rate_value: r.rate,
             })
             .collect(),
         count: total_valid_ballots.unwrap_or(0),
+        // pair game counts are only recorded by the portable service
+        ranking_method: RankingMethod::Rate,
+        computed_at: Utc::now(),
     };
 
-    Ok(Json(ApiResponse {
-        status: 0,
+    // the portable service ranks a limited head in Redis with its top script,
+    // here the order is by rate alone so the head is cut from the full order
+    if let Some(limit) = req.limit {
+        response.items.truncate(limit);
+    }
+    if let Some(breakpoints) = &req.tier_breakpoints {
+        assign_tiers(&mut response.items, breakpoints);
+    }
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
         data: ApiData::Data(response),
         message: ApiMsg::OK,
-    }))
-}
-
-fn parse_operator_counts(values: &[Option<String>], num_operators: usize) -> (Vec<i64>, Vec<i64>) {
-    let win_counts: Vec<i64> = values[..num_operators]
-        .iter()
-        .map(|v| v.as_ref().and_then(|s| s.parse().ok()).unwrap_or(0))
-        .collect();
-
-    let lose_counts: Vec<i64> = values[num_operators..]
-        .iter()
-        .map(|v| v.as_ref().and_then(|s| s.parse().ok()).unwrap_or(0))
-        .collect();
-
-    (win_counts, lose_counts)
+    })
 }
 
 fn build_operator_results(
@@ -259,20 +319,6 @@ mod tests {
         assert_eq!(result_zero.rate, 0.0);
     }
 
-    #[test]
-    fn test_parse_operator_counts() {
-        let values = vec![
-            Some("10".to_string()),
-            Some("20".to_string()),
-            Some("5".to_string()),
-            Some("15".to_string()),
-        ];
-        let (wins, losses) = parse_operator_counts(&values, 2);
-
-        assert_eq!(wins, vec![10, 20]);
-        assert_eq!(losses, vec![5, 15]);
-    }
-
     #[test]
     fn test_build_operator_results() {
         let operator_ids = vec![101, 102];
@@ -0,0 +1,83 @@
+use std::{collections::HashMap, sync::Arc, time::Instant};
+
+use axum::extract::State;
+use redis::AsyncCommands;
+use share::{
+    metrics::CacheKind,
+    models::api::{
+        ApiData, ApiMsg, ApiResponse, ResponseStatus, ResultsPartnersRequest,
+        ResultsPartnersResponse,
+    },
+};
+
+use crate::{
+    AppState, api::utils::ApiJson, constants::PARTNERS_CACHE_TTL, error::AppError,
+    service::record_cache_lookup,
+};
+
+#[utoipa::path(
+    post,
+    path = "/results/partners",
+    request_body = ResultsPartnersRequest,
+    responses(
+        (status = 200, description = "Get the operators most often shown together with an operator", body = ApiResponse<ResultsPartnersResponse>),
+        (status = 404, description = "Topic not found", body = ApiResponse<String>),
+        (status = 500, description = "Internal server error", body = ApiResponse<String>)
+    ),
+    tag = "Results",
+    operation_id = "resultsPartners"
+)]
+#[axum::debug_handler]
+pub async fn results_partners(
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<ResultsPartnersRequest>,
+) -> Result<ApiResponse<ResultsPartnersResponse>, AppError> {
+    let target_topic = match state.topic_service.get_topic(&req.topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => {
+            return Ok(ApiResponse {
+                status: ResponseStatus::NotFound,
+                data: ApiData::Empty,
+                message: ApiMsg::TargetTopicNotFound,
+            });
+        }
+        Err(e) => {
+            tracing::error!("Failed to load topic {}: {}", req.topic_id, e);
+            return Ok(ApiResponse {
+                status: ResponseStatus::InternalError,
+                data: ApiData::Empty,
+                message: ApiMsg::InternalError,
+            });
+        }
+    };
+
+    let cached = state
+        .partners_cache
+        .get(&target_topic.id)
+        .filter(|entry| entry.0.elapsed() < PARTNERS_CACHE_TTL)
+        .map(|entry| entry.1.clone());
+    record_cache_lookup(CacheKind::Results, cached.is_some());
+    let cooccur = match cached {
+        Some(cooccur) => cooccur,
+        None => {
+            let mut conn = state.redis.connection.clone();
+            let cooccur: Arc<HashMap<String, i64>> =
+                Arc::new(conn.hgetall(req.topic_id.op_cooccur_key()).await?);
+            state
+                .partners_cache
+                .insert(target_topic.id.clone(), (Instant::now(), cooccur.clone()));
+            cooccur
+        }
+    };
+
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(ResultsPartnersResponse::build(
+            target_topic.id,
+            req.operator_id,
+            &cooccur,
+            req.limit,
+        )),
+        message: ApiMsg::OK,
+    })
+}
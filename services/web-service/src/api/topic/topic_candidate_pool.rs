@@ -1,12 +1,23 @@
 use std::sync::Arc;
 
-use axum::{Json, extract::State};
-use share::models::api::{
-    ApiData, ApiMsg, ApiResponse, CharacterPortrait, TopicCandidatePoolRequest,
-    TopicCandidatePoolResponse,
+use axum::{
+    extract::State,
+    http::{
+        HeaderMap, StatusCode,
+        header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    },
+    response::{IntoResponse as _, Response},
+};
+use share::{
+    etag::{EtaggedBody, if_none_match},
+    models::api::{
+        ApiData, ApiMsg, ApiResponse, CandidatePoolOrder, CharacterPortrait, ResponseStatus,
+        TopicCandidatePoolRequest, TopicCandidatePoolResponse,
+    },
+    portrait::sort_portraits,
 };
 
-use crate::{AppState, error::AppError};
+use crate::{AppState, api::utils::ApiJson, error::AppError};
 
 #[utoipa::path(
     post,
@@ -14,6 +25,7 @@ use crate::{AppState, error::AppError};
     request_body = TopicCandidatePoolRequest,
     responses(
         (status = 200, description = "Get candidate pool for a topic", body = ApiResponse<TopicCandidatePoolResponse>),
+        (status = 304, description = "Candidate pool matches If-None-Match"),
         (status = 404, description = "Topic not found", body = ApiResponse<String>),
         (status = 500, description = "Internal server error", body = ApiResponse<String>)
     ),
@@ -23,35 +35,92 @@ use crate::{AppState, error::AppError};
 #[axum::debug_handler]
 pub async fn topic_candidate_pool(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<TopicCandidatePoolRequest>,
-) -> Result<Json<ApiResponse<TopicCandidatePoolResponse>>, AppError> {
-    let candidate_pool = state
-        .topic_service
-        .get_candidate_pool(&payload.topic_id, &state.character_infos)
-        .await;
-
-    match candidate_pool {
-        Some(candidate_pool) => {
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<TopicCandidatePoolRequest>,
+) -> Result<Response, AppError> {
+    // hinted bodies change while portraits are probed, so only the plain,
+    // unfiltered response in the default order is cached
+    let cacheable = !payload.include_image_hints
+        && payload.order_by == CandidatePoolOrder::Id
+        && payload.name_contains.is_none();
+    let cached = match cacheable {
+        true => state
+            .topic_service
+            .get_candidate_pool_response(&payload.topic_id),
+        false => None,
+    };
+    let response = match cached {
+        Some(response) => response,
+        None => {
+            let candidate_pool = match state
+                .topic_service
+                .get_candidate_pool(&payload.topic_id, &state.character_infos)
+                .await
+            {
+                Ok(pool) => pool,
+                Err(err) => {
+                    return Ok(ApiResponse::<()> {
+                        status: ResponseStatus::NotFound,
+                        data: ApiData::Empty,
+                        message: err.into(),
+                    }
+                    .into_response());
+                }
+            };
+
+            let portraits = state.character_portraits.load();
             let mut pool: Vec<CharacterPortrait> = candidate_pool
-                .into_iter()
-                .filter_map(|char_id| state.character_portraits.get(&char_id).cloned())
+                .iter()
+                .filter_map(|char_id| portraits.get(char_id).cloned())
                 .collect();
+            if let Some(query) = &payload.name_contains {
+                pool.retain(|portrait| {
+                    state.operator_aliases.name_matches(
+                        portrait.id,
+                        &[&portrait.name, &portrait.cn_name],
+                        query,
+                    )
+                });
+            }
 
-            pool.sort_unstable_by_key(|info| info.id);
+            sort_portraits(&mut pool, payload.order_by, &state.character_infos);
+            if payload.include_image_hints {
+                pool.iter_mut()
+                    .for_each(|portrait| state.portrait_hints.annotate(portrait));
+            }
 
-            Ok(Json(ApiResponse {
-                status: 0,
+            let body = serde_json::to_vec(&ApiResponse {
+                status: ResponseStatus::Ok,
                 data: ApiData::Data(TopicCandidatePoolResponse {
-                    topic_id: payload.topic_id,
+                    topic_id: payload.topic_id.to_string(),
                     pool,
                 }),
                 message: ApiMsg::OK,
-            }))
+            })?;
+            let response = Arc::new(EtaggedBody::new(body));
+            if cacheable {
+                state.topic_service.cache_candidate_pool_response(
+                    &payload.topic_id,
+                    &candidate_pool,
+                    response.clone(),
+                );
+            }
+
+            response
         }
-        None => Ok(Json(ApiResponse {
-            status: 404,
-            data: ApiData::Empty,
-            message: ApiMsg::TargetTopicNotFound,
-        })),
+    };
+
+    let client_etag = headers.get(IF_NONE_MATCH).and_then(|v| v.to_str().ok());
+    if if_none_match(client_etag, &response.etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(ETAG, response.etag.clone())]).into_response());
     }
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/json".to_string()),
+            (ETAG, response.etag.clone()),
+        ],
+        response.body.clone(),
+    )
+        .into_response())
 }
@@ -1,10 +1,12 @@
 use std::sync::Arc;
 
-use axum::{Json, extract::State};
+use axum::extract::State;
 use mongodb::bson::doc;
-use share::models::api::{ApiData, ApiMsg, ApiResponse, TopicInfoRequest, TopicInfoResponse};
+use share::models::api::{
+    ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicInfoRequest, TopicInfoResponse,
+};
 
-use crate::{AppState, error::AppError};
+use crate::{AppState, api::utils::ApiJson, error::AppError};
 
 #[utoipa::path(
     post,
@@ -21,31 +23,23 @@ use crate::{AppState, error::AppError};
 #[axum::debug_handler]
 pub async fn topic_info(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<TopicInfoRequest>,
-) -> Result<Json<ApiResponse<TopicInfoResponse>>, AppError> {
+    ApiJson(req): ApiJson<TopicInfoRequest>,
+) -> Result<ApiResponse<TopicInfoResponse>, AppError> {
     match state.topic_service.get_topic(&req.topic_id).await {
-        Ok(Some(topic)) => Ok(Json(ApiResponse {
-            status: 0,
-            data: ApiData::Data(TopicInfoResponse {
-                id: topic.id,
-                name: topic.name,
-                title: topic.title,
-                description: topic.description,
-                topic_type: topic.topic_type,
-                open_time: topic.open_time,
-                close_time: topic.close_time,
-            }),
+        Ok(Some(topic)) => Ok(ApiResponse {
+            status: ResponseStatus::Ok,
+            data: ApiData::Data(TopicInfoResponse::from(topic)),
             message: ApiMsg::OK,
-        })),
-        Ok(None) => Ok(Json(ApiResponse {
-            status: 404,
+        }),
+        Ok(None) => Ok(ApiResponse {
+            status: ResponseStatus::NotFound,
             data: ApiData::Empty,
             message: ApiMsg::TargetTopicNotFound,
-        })),
-        Err(_) => Ok(Json(ApiResponse {
-            status: 500,
+        }),
+        Err(_) => Ok(ApiResponse {
+            status: ResponseStatus::InternalError,
             data: ApiData::Empty,
             message: ApiMsg::InternalError,
-        })),
+        }),
     }
 }
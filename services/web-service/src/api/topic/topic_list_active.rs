@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
-use axum::{Json, extract::State};
+use axum::extract::State;
 use mongodb::bson::doc;
-use share::models::api::{ApiData, ApiMsg, ApiResponse, TopicListActiveResponse};
+use share::models::api::{ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicListActiveResponse};
 
 use crate::{AppState, error::AppError};
 
@@ -19,12 +19,12 @@ use crate::{AppState, error::AppError};
 #[axum::debug_handler]
 pub async fn topic_list_active(
     State(state): State<Arc<AppState>>,
-) -> Result<Json<ApiResponse<TopicListActiveResponse>>, AppError> {
+) -> Result<ApiResponse<TopicListActiveResponse>, AppError> {
     let topic_ids = state.topic_service.get_active_topic_ids().await?;
 
-    Ok(Json(ApiResponse {
-        status: 0,
+    Ok(ApiResponse {
+        status: ResponseStatus::Ok,
         data: ApiData::Data(TopicListActiveResponse { topic_ids }),
         message: ApiMsg::OK,
-    }))
+    })
 }
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use share::models::{
+    api::{ApiData, ApiMsg, ApiResponse, ResponseStatus, TopicValidatePoolResponse},
+    candidate_pool_preset::CandidatePoolPreset,
+};
+
+use crate::{AppState, api::utils::ApiJson};
+
+#[utoipa::path(
+    post,
+    path = "/topic/validate_pool",
+    request_body = CandidatePoolPreset,
+    responses(
+        (status = 200, description = "Validate a candidate pool preset without creating a topic", body = ApiResponse<TopicValidatePoolResponse>)
+    ),
+    tag = "Topic",
+    operation_id = "topicValidatePool"
+)]
+#[axum::debug_handler]
+pub async fn topic_validate_pool(
+    State(state): State<Arc<AppState>>,
+    ApiJson(preset): ApiJson<CandidatePoolPreset>,
+) -> ApiResponse<TopicValidatePoolResponse> {
+    let max_depth = state.topic_service.max_preset_depth();
+    let result = preset.validate_pool(&state.character_infos, max_depth, |preset| {
+        state
+            .topic_service
+            .resolve_pool(preset, &state.character_infos)
+    });
+
+    ApiResponse {
+        status: ResponseStatus::Ok,
+        data: ApiData::Data(result.into()),
+        message: ApiMsg::OK,
+    }
+}
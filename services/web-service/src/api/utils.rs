@@ -1,7 +1,28 @@
+use axum::{
+    Json,
+    extract::FromRequest,
+    http::{
+        HeaderMap,
+        header::{ACCEPT, CONTENT_TYPE},
+    },
+    response::{IntoResponse as _, Response},
+};
+use chrono::{DateTime, Utc};
 use rand::{Rng as _, distr::Alphanumeric};
+use serde::Serialize;
+use share::models::{
+    api::{ApiData, ApiResponse, ResponseStatus},
+    proto::{PROTOBUF_CONTENT_TYPE, ToProto, accepts_protobuf},
+};
 
 use crate::error::AppError;
 
+/// `Json` that answers a missing content type or a malformed body with the
+/// `ApiResponse` envelope instead of axum's plain text rejection.
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(AppError))]
+pub struct ApiJson<T>(pub T);
+
 pub async fn publish_and_ack(
     jetstream: &async_nats::jetstream::Context,
     subject: &'static str,
@@ -19,3 +40,41 @@ pub fn generate_random_string(length: usize) -> String {
         .map(char::from)
         .collect()
 }
+
+/// Encodes successful responses as protobuf when the `Accept` header asks for
+/// it, everything else (including errors) is returned as JSON.
+pub fn negotiate<T: Serialize + ToProto>(
+    headers: &HeaderMap,
+    response: ApiResponse<T>,
+) -> Response {
+    match protobuf(headers, &response) {
+        Some(proto) => proto,
+        None => response.into_response(),
+    }
+}
+
+/// `negotiate` for results that send `computed_at` next to `data` in JSON,
+/// see `TimedApiResponse`.
+pub fn negotiate_timed<T: Serialize + ToProto>(
+    headers: &HeaderMap,
+    response: ApiResponse<T>,
+    computed_at: impl FnOnce(&T) -> DateTime<Utc>,
+) -> Response {
+    match protobuf(headers, &response) {
+        Some(proto) => proto,
+        None => response.timed(computed_at).into_response(),
+    }
+}
+
+fn protobuf<T: ToProto>(headers: &HeaderMap, response: &ApiResponse<T>) -> Option<Response> {
+    let accept = headers.get(ACCEPT).and_then(|v| v.to_str().ok());
+
+    match &response.data {
+        ApiData::Data(data)
+            if response.status == ResponseStatus::Ok && accepts_protobuf(accept) =>
+        {
+            Some(([(CONTENT_TYPE, PROTOBUF_CONTENT_TYPE)], data.encode_proto()).into_response())
+        }
+        _ => None,
+    }
+}
@@ -1,4 +1,34 @@
-pub const BALLOT_CODE_RANDOM_LENGTH: usize = 8;
+use std::time::Duration;
+
+pub const BALLOT_CODE_METRICS_INTERVAL: Duration = Duration::from_secs(60);
+
+pub const TASK_METRICS_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Topic closes waiting for their webhooks, closes beyond it are dropped.
+pub const TOPIC_CLOSE_QUEUE_CAPACITY: usize = 256;
+
+/// How long a topic's `op_cooccur` hash is served from memory by
+/// `/results/partners` before it is read again.
+pub const PARTNERS_CACHE_TTL: Duration = Duration::from_secs(1);
+
+pub const WORKER_ID_LEASE_TTL: Duration = Duration::from_secs(60);
+
+pub const WORKER_ID_RENEW_INTERVAL: Duration = Duration::from_secs(10);
+
+pub const LUA_SCRIPT_RENEW_WORKER_ID: &str = r#"
+-- KEYS[1]: worker id key, ARGV[1]: owner token, ARGV[2]: ttl in seconds
+-- Returns 1 when the lease was renewed, 2 when it had expired and was taken
+-- again, 0 when another instance holds it.
+local owner = redis.call('GET', KEYS[1])
+if owner == ARGV[1] then
+    redis.call('EXPIRE', KEYS[1], ARGV[2])
+    return 1
+elseif not owner then
+    redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+    return 2
+end
+return 0
+"#;
 
 pub const LUA_SCRIPT_GET_FINAL_ORDER: &str = r#"
 local topic_id = KEYS[1]
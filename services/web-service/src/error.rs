@@ -1,6 +1,6 @@
-use axum::{Json, http::StatusCode};
+use axum::{extract::rejection::JsonRejection, http::StatusCode, response::IntoResponse};
 use redis::RedisError;
-use share::models::api::{ApiData, ApiMsg, ApiResponse};
+use share::models::api::{ApiData, ApiMsg, ApiResponse, ResponseStatus};
 
 #[derive(thiserror::Error, Debug)]
 pub enum AppError {
@@ -22,33 +22,33 @@ pub enum AppError {
     InsufficientOperators,
     #[error("mongo db error: {0}")]
     MongoDb(#[from] mongodb::error::Error),
-    #[error("missing character table json file")]
-    MissingCharacterTableJson,
     #[error("reqwest error: {0}")]
     Reqwest(#[from] reqwest::Error),
+    #[error("json rejection: {0}")]
+    JsonRejection(#[from] JsonRejection),
 }
 
-impl axum::response::IntoResponse for AppError {
+impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
         let (status, message) = match self {
             AppError::SameParticipant => (
-                StatusCode::BAD_REQUEST,
-                ApiResponse::<()> {
-                    status: 500,
-                    data: ApiData::Empty,
-                    message: ApiMsg::BallotWinnerCannotBeLoser,
-                },
-            ),
-            _ => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                ApiResponse::<()> {
-                    status: 500,
-                    data: ApiData::Empty,
-                    message: ApiMsg::InternalError,
-                },
+                ResponseStatus::BadRequest,
+                ApiMsg::BallotWinnerCannotBeLoser,
             ),
+            AppError::JsonRejection(rejection)
+                if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE =>
+            {
+                (ResponseStatus::PayloadTooLarge, ApiMsg::PayloadTooLarge)
+            }
+            AppError::JsonRejection(_) => (ResponseStatus::BadRequest, ApiMsg::InvalidJson),
+            _ => (ResponseStatus::InternalError, ApiMsg::InternalError),
         };
 
-        (status, Json(message)).into_response()
+        ApiResponse::<()> {
+            status,
+            data: ApiData::Empty,
+            message,
+        }
+        .into_response()
     }
 }
@@ -10,28 +10,36 @@ mod utils;
 mod worker_id;
 
 use async_nats::jetstream;
-use axum::{Json, Router, extract::State, routing::get};
+use axum::{
+    Json, Router,
+    extract::{DefaultBodyLimit, State},
+    routing::get,
+};
 use axum_prometheus::PrometheusMetricLayer;
 use dashmap::DashMap;
 use eyre::Context;
 use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
 use share::{
+    auth::AdminTokens,
+    bootstrap::{self, VoteSettings},
     config::AppConfig,
-    models::{database::VotingTopic, excel::CharacterInfo},
+    portrait::{self, PortraitStore},
+    search::OperatorAliases,
+    selection::{OperatorGamesCache, SelectionRngs, SubProfessionBlocklist},
+    signal,
     snowflake::Snowflake,
 };
 use socket2::{Domain, Socket, Type};
 use tower::ServiceBuilder;
-use tower_http::{cors::CorsLayer, timeout::TimeoutLayer, trace::TraceLayer};
+use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use utoipa::OpenApi as _;
 use utoipa_scalar::{Scalar, Servable as _};
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::{
     api::ApiDoc,
-    constants::LUA_SCRIPT_GET_FINAL_ORDER,
-    error::AppError,
-    service::TopicService,
+    constants::{LUA_SCRIPT_GET_FINAL_ORDER, TOPIC_CLOSE_QUEUE_CAPACITY},
+    service::{BallotBundler, TopicService, spawn_ballot_code_metrics, spawn_topic_close_webhooks},
     state::{AppState, RedisService},
     task::TaskManager,
     worker_id::WorkerIdManager,
@@ -69,113 +77,131 @@ impl WebService {
     }
 
     pub async fn run(self, mut shutdown_rx: share::signal::ShutdownRx) -> eyre::Result<()> {
-        let nats_client = async_nats::connect(&self.config.nats.url)
-            .await
-            .context("failed to connect to nats")?;
+        let nats_client = bootstrap::connect_nats(&self.config.nats).await?;
         let jetstream = jetstream::new(nats_client.clone());
-        tracing::debug!("connected to nats at {}", &self.config.nats.url);
 
-        let redis_client = redis::Client::open(&*self.config.database.redis_url)
-            .context("failed to create Redis client")?;
-        let connection = redis_client.get_multiplexed_async_connection().await?;
-        tracing::debug!("connected to redis at {}", &self.config.database.redis_url);
+        let database_config = &self.config.database;
+        let (redis_client, connection) = bootstrap::connect_redis(database_config).await?;
+        let mongodb = bootstrap::connect_mongodb_database(database_config).await?;
+        let mongodb_read =
+            bootstrap::connect_mongodb_read_database(database_config, &mongodb).await?;
+        let character_table = bootstrap::load_character_table()?;
+        let character_infos = bootstrap::character_infos(&character_table);
+        tracing::debug!("Character infos loaded: {}", character_infos.len());
 
-        let mongodb_client = mongodb::Client::with_uri_str(&self.config.database.mongodb_url)
-            .await
-            .context("failed to connect to MongoDB")?;
-        let mongodb = mongodb_client.database(&self.config.database.mongodb_database);
-        tracing::debug!(
-            "connected to mongodb at {}, database: {}",
-            &self.config.database.mongodb_url,
-            &self.config.database.mongodb_database
-        );
-        use mongodb::bson::doc;
-        let collection = mongodb.collection::<VotingTopic>("topics");
+        self.config.vote.check_ballot_code_length()?;
 
-        for preset_topic in &self.config.vote.preset_vote_topic {
-            let filter = doc! { "id": &preset_topic.id };
+        bootstrap::ensure_topic_index(&mongodb)
+            .await
+            .context("failed to index topic ids")?;
+        bootstrap::upsert_preset_topics(&mongodb, &self.config.vote, &character_infos).await?;
 
-            match collection.find_one(filter).await {
-                Ok(Some(_)) => {
-                    let query = doc! { "id": &preset_topic.id };
-                    collection.replace_one(query, preset_topic).await?;
-                    tracing::info!("updated preset voting topic: {}", preset_topic.id);
-                }
-                Ok(None) => {
-                    tracing::info!("inserting preset voting topic: {}", preset_topic.id);
-                    collection.insert_one(preset_topic).await?;
-                }
-                Err(_) => {
-                    // maybe data structure has changed, we force replace
-                    let query = doc! { "id": &preset_topic.id };
-                    collection.replace_one(query, preset_topic).await?;
-                }
-            }
-        }
+        bootstrap::ensure_ballot_indexes(&mongodb)
+            .await
+            .context("failed to index stored ballots")?;
 
         let manager = Arc::new(WorkerIdManager::new(connection.clone(), 255)?);
         let worker_id = manager.acquire().await?;
-        manager.clone().keep_alive().await;
         tracing::info!("acquired worker_id: {}", worker_id);
 
-        let snowflake = Snowflake::new(
-            self.config.snowflake.datacenter_id,
-            worker_id,
-            self.config.snowflake.epoch,
+        let snowflake = Snowflake::from_config(&self.config.snowflake, worker_id);
+        let worker_id_lost = manager.keep_alive(
+            snowflake.clone(),
+            self.config.snowflake.max_lease_renewal_failures,
         );
         tracing::debug!(
             "snowflake initialized with config: {:?}",
             &self.config.snowflake
         );
 
-        let character_infos: Vec<CharacterInfo> = utils::load_character_table()?
-            .into_iter()
-            .filter_map(|(name, data)| {
-                if !name.starts_with("char_") {
-                    return None;
-                }
-                let mut parts = name["char_".len()..].splitn(2, '_');
-                let charid = parts.next()?.parse::<i32>().ok()?;
-                Some(CharacterInfo {
-                    id: charid,
-                    name: data.name,
-                    rarity: data.rarity,
-                    profession: data.profession,
-                    sub_profession_id: data.sub_profession_id,
-                    is_not_obtainable: data.is_not_obtainable,
-                })
-            })
-            .collect();
-        tracing::debug!("Character infos loaded: {}", character_infos.len());
-
-        let character_portraits = utils::fetch_portrait_image_url().await?;
+        let character_portraits =
+            utils::fetch_portrait_image_url(&character_table, &self.config.portraits).await;
         tracing::debug!("Character portraits fetched");
-
-        let topic_service = TopicService::new(mongodb.clone());
+        let portrait_hints =
+            portrait::spawn_portrait_hints(&character_portraits, &self.config.portraits)
+                .context("failed to start probing portrait images")?;
+        let character_portraits = PortraitStore::new(character_portraits);
+
+        let (closed_topics_tx, closed_topics_rx) = match self.config.webhooks.urls.is_empty() {
+            true => (None, None),
+            false => {
+                let (tx, rx) = tokio::sync::mpsc::channel(TOPIC_CLOSE_QUEUE_CAPACITY);
+                (Some(tx), Some(rx))
+            }
+        };
+        let topic_service = TopicService::new(
+            mongodb.clone(),
+            self.config.topic_cache.clone(),
+            &character_infos,
+            SubProfessionBlocklist::new(self.config.vote.blocked_sub_professions.clone()),
+            self.config.vote.max_preset_depth,
+            closed_topics_tx,
+        );
         tracing::debug!("TopicService initialized");
 
-        let task_manager = TaskManager::new(self.config.task_manager.concurrency);
+        let task_manager = TaskManager::new(
+            self.config.task_manager.concurrency,
+            self.config.task_manager.max_in_flight,
+        );
         tracing::debug!("TaskManager initialized");
 
+        let ballot_bundler = self.config.nats.ballot_bundling.enabled.then(|| {
+            BallotBundler::spawn(
+                jetstream.clone(),
+                "ark-vote.save_score",
+                self.config.nats.ballot_bundling,
+                task_manager.clone(),
+            )
+        });
+
         let state = AppState {
             jetstream,
+            ballot_bundler,
             redis: RedisService {
                 _client: redis_client,
                 connection,
                 final_order_script: redis::Script::new(LUA_SCRIPT_GET_FINAL_ORDER),
             },
-            _mongodb: mongodb,
+            mongodb,
+            mongodb_read,
             snowflake,
             character_infos,
             character_portraits,
+            portrait_hints,
+            operator_aliases: OperatorAliases::new(&self.config.operators.aliases),
+            vote: VoteSettings::from_config(&self.config.vote),
+            base_multiplier: self.config.vote.base_multiplier,
+            selection_rngs: SelectionRngs::default(),
+            operator_games: OperatorGamesCache::default(),
 
             topic_service,
 
             bench_ballot_store: DashMap::new(),
+            partners_cache: DashMap::new(),
             task_manager,
         };
         tracing::debug!("AppState initialized");
 
+        let state = Arc::new(state);
+        if let Some(closed_topics_rx) = closed_topics_rx {
+            spawn_topic_close_webhooks(
+                state.clone(),
+                self.config.webhooks.clone(),
+                closed_topics_rx,
+            );
+            tracing::debug!("Topic close webhooks initialized");
+        }
+        spawn_ballot_code_metrics(state.clone());
+        portrait::spawn_portrait_health_check(
+            state.character_portraits.clone(),
+            &self.config.portraits,
+            {
+                let state = state.clone();
+                move || state.topic_service.clear_candidate_pool_responses()
+            },
+        )
+        .context("failed to start the portrait CDN health check")?;
+
         let sentry_layer = ServiceBuilder::new()
             .layer(NewSentryLayer::new_from_top())
             .layer(SentryHttpLayer::new().enable_transaction());
@@ -211,20 +237,25 @@ impl WebService {
         };
         tracing::debug!("CORS layer initialized");
 
-        let app = Router::new()
+        let timeouts = &self.config.server.timeouts;
+        let misc_routes = Router::new()
             .route("/", get(|| async { "Hello, world!" }))
             .route("/metrics", get(|| async move { metric_handle.render() }))
             .route("/task_stats", get(get_task_stats))
-            .merge(api::routes())
             .merge(SwaggerUi::new("/docs").url("/api-doc/openapi.json", ApiDoc::openapi()))
-            .merge(Scalar::with_url("/scalar", ApiDoc::openapi()))
-            .with_state(Arc::new(state))
+            .merge(Scalar::with_url("/scalar", ApiDoc::openapi()));
+
+        let app = Router::new()
+            .merge(api::with_timeout(misc_routes, timeouts.for_prefix("/")))
+            .merge(api::routes(
+                AdminTokens::from_config(&self.config.admin),
+                timeouts,
+            ))
+            .with_state(state)
+            .layer(DefaultBodyLimit::max(self.config.server.max_body_size))
             .layer(cors_layer)
             .layer(sentry_layer)
-            .layer((
-                TraceLayer::new_for_http(),
-                TimeoutLayer::new(Duration::from_secs(60)),
-            ))
+            .layer(TraceLayer::new_for_http())
             .layer(prometheus_layer);
         tracing::debug!("Router initialized");
 
@@ -251,11 +282,30 @@ impl WebService {
         });
 
         tracing::info!("web service started successfully");
-        shutdown_rx.changed().await?;
+        // duplicate snowflake ids are worse than downtime, stop once the
+        // worker id may be shared with another instance
+        let halted = tokio::select! {
+            changed = shutdown_rx.changed() => {
+                changed?;
+                None
+            }
+            lost = worker_id_lost => Some(lost.unwrap_or_else(eyre::Report::from)),
+        };
 
+        if let Some(err) = &halted {
+            tracing::error!("halting web service: {:#}", err);
+        }
         tracing::info!("shutting down web service");
-        nats_client.drain().await?;
+        let drain_timeout = self.config.shutdown.timeout();
+        if let Some(result) =
+            signal::bounded("nats drain", drain_timeout, nats_client.drain()).await
+        {
+            result?;
+        }
 
-        Ok(())
+        match halted {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
     }
 }
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use redis::AsyncCommands as _;
+use share::metrics::{
+    BALLOT_CODES_CREATED_FIELD, BALLOT_CODES_RESOLVED_FIELD, BallotCodeCounts, ballot_codes_key,
+};
+
+use crate::{AppState, constants::BALLOT_CODE_METRICS_INTERVAL, error::AppError};
+
+/// Periodically publishes the `ballot_codes_unresolved` and
+/// `ballot_code_abandon_ratio` gauges of every active topic from the counters
+/// kept in `ballot_codes_key`, see `BallotCodeCounts` for the trade-off.
+pub fn spawn_ballot_code_metrics(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(BALLOT_CODE_METRICS_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            if let Err(e) = record_ballot_code_metrics(&state).await {
+                tracing::warn!("Failed to update ballot code metrics: {}", e);
+            }
+        }
+    });
+}
+
+async fn record_ballot_code_metrics(state: &AppState) -> Result<(), AppError> {
+    let mut conn = state.redis.connection.clone();
+
+    for topic_id in state.topic_service.get_active_topic_ids().await? {
+        let (created, resolved): (Option<u64>, Option<u64>) = conn
+            .hget(
+                ballot_codes_key(&topic_id),
+                &[BALLOT_CODES_CREATED_FIELD, BALLOT_CODES_RESOLVED_FIELD],
+            )
+            .await?;
+        let counts = BallotCodeCounts {
+            created: created.unwrap_or(0),
+            resolved: resolved.unwrap_or(0),
+        };
+
+        metrics::gauge!("ballot_codes_unresolved", "topic_id" => topic_id.clone())
+            .set(counts.unresolved() as f64);
+        metrics::gauge!("ballot_code_abandon_ratio", "topic_id" => topic_id)
+            .set(counts.abandon_ratio());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use share::config::BallotBundlingConfig;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{api::publish_and_ack, error::AppError, task::TaskManager};
+
+struct PendingBallot {
+    payload: Vec<u8>,
+    published: oneshot::Sender<Result<(), String>>,
+}
+
+/// Collects serialized ballots and publishes them as a single JSON array
+/// message once `max_ballots` are buffered or `window_ms` has passed since
+/// the first one. Each bundle is published as a `TaskManager` task, which
+/// bounds the publishes in flight and reports them in `/task_stats`.
+#[derive(Clone)]
+pub struct BallotBundler {
+    sender: mpsc::Sender<PendingBallot>,
+}
+
+impl BallotBundler {
+    pub fn spawn(
+        jetstream: async_nats::jetstream::Context,
+        subject: &'static str,
+        config: BallotBundlingConfig,
+        task_manager: Arc<TaskManager>,
+    ) -> Self {
+        let max_ballots = config.max_ballots.max(1);
+        let (sender, receiver) = mpsc::channel(max_ballots * 4);
+
+        tokio::spawn(Self::run(
+            jetstream,
+            subject,
+            config,
+            max_ballots,
+            task_manager,
+            receiver,
+        ));
+
+        Self { sender }
+    }
+
+    /// Resolves once the bundle containing `payload` has been published.
+    pub async fn publish(&self, payload: Vec<u8>) -> Result<(), AppError> {
+        let (published, result) = oneshot::channel();
+        self.sender
+            .send(PendingBallot { payload, published })
+            .await
+            .map_err(|_| AppError::InternalError("ballot bundler stopped".to_string()))?;
+
+        result
+            .await
+            .map_err(|_| AppError::InternalError("ballot bundle dropped".to_string()))?
+            .map_err(AppError::InternalError)
+    }
+
+    async fn run(
+        jetstream: async_nats::jetstream::Context,
+        subject: &'static str,
+        config: BallotBundlingConfig,
+        max_ballots: usize,
+        task_manager: Arc<TaskManager>,
+        mut receiver: mpsc::Receiver<PendingBallot>,
+    ) {
+        while let Some(first) = receiver.recv().await {
+            let mut pending = Vec::with_capacity(max_ballots);
+            pending.push(first);
+
+            let deadline = tokio::time::sleep(config.window());
+            tokio::pin!(deadline);
+            while pending.len() < max_ballots {
+                tokio::select! {
+                    ballot = receiver.recv() => match ballot {
+                        Some(ballot) => pending.push(ballot),
+                        None => break,
+                    },
+                    _ = &mut deadline => break,
+                }
+            }
+
+            // a rejected bundle drops its senders, failing each save with
+            // "ballot bundle dropped"
+            let jetstream = jetstream.clone();
+            task_manager.spawn(move || async move {
+                let (payloads, senders): (Vec<_>, Vec<_>) = pending
+                    .into_iter()
+                    .map(|ballot| (ballot.payload, ballot.published))
+                    .unzip();
+
+                let result = publish_and_ack(&jetstream, subject, bundle_payload(&payloads))
+                    .await
+                    .map_err(|e| e.to_string());
+
+                for sender in senders {
+                    let _ = sender.send(result.clone());
+                }
+                // logged by the task manager, which counts the bundle as failed
+                result.map_err(|e| {
+                    format!(
+                        "Failed to publish bundle of {} ballots: {}",
+                        payloads.len(),
+                        e
+                    )
+                })
+            });
+        }
+
+        tracing::info!("Ballot bundler stopped");
+    }
+}
+
+/// Joins serialized ballots into a JSON array.
+fn bundle_payload(payloads: &[Vec<u8>]) -> Vec<u8> {
+    let len = payloads.iter().map(|p| p.len() + 1).sum::<usize>() + 1;
+    let mut bundle = Vec::with_capacity(len);
+
+    bundle.push(b'[');
+    for (i, payload) in payloads.iter().enumerate() {
+        if i > 0 {
+            bundle.push(b',');
+        }
+        bundle.extend_from_slice(payload);
+    }
+    bundle.push(b']');
+
+    bundle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_payload_is_json_array() {
+        let payloads = vec![br#"{"a":1}"#.to_vec(), br#"{"b":2}"#.to_vec()];
+        let bundle: serde_json::Value = serde_json::from_slice(&bundle_payload(&payloads)).unwrap();
+
+        assert_eq!(bundle, serde_json::json!([{"a": 1}, {"b": 2}]));
+        assert_eq!(bundle_payload(&[]), b"[]");
+    }
+}
@@ -1,3 +1,10 @@
+mod ballot_codes;
+mod bundler;
 mod topic;
+mod webhook;
 
+pub use ballot_codes::spawn_ballot_code_metrics;
+pub use bundler::BallotBundler;
 pub use topic::TopicService;
+pub(crate) use topic::record_cache_lookup;
+pub use webhook::spawn_topic_close_webhooks;
@@ -5,18 +5,38 @@ use dashmap::DashMap;
 use futures::TryStreamExt as _;
 use mongodb::{Collection, bson::doc};
 use parking_lot::RwLock;
-use share::models::{
-    database::{CreateTopicStatus, TopicAuditInfo, VotingTopic},
-    excel::CharacterInfo,
+use share::{
+    bootstrap::is_duplicate_key_error,
+    config::TopicCacheConfig,
+    etag::EtaggedBody,
+    metrics::{CACHE_OUTCOME_LABEL, CacheKind, LabelCap, cache_outcome},
+    models::{
+        candidate_pool_preset::{CandidatePoolError, CandidatePoolPreset},
+        database::{CreateTopicStatus, TopicAuditInfo, TopicAuditLogEntry, VotingTopic},
+        excel::CharacterInfo,
+    },
+    selection::SubProfessionBlocklist,
+    webhook::TopicCloseTracker,
 };
-use tokio::sync::RwLock as AsyncRwLock;
+use tokio::sync::{RwLock as AsyncRwLock, mpsc};
 
 use crate::error::AppError;
 
+pub(crate) fn record_cache_lookup(cache: CacheKind, hit: bool) {
+    metrics::counter!(
+        "cache_lookups_total",
+        CacheKind::LABEL => cache.label(),
+        CACHE_OUTCOME_LABEL => cache_outcome(hit)
+    )
+    .increment(1);
+}
+
 #[derive(Debug, Clone)]
 pub struct CacheEntry {
     data: VotingTopic,
     pool: Vec<i32>,
+    /// Serialized `/topic/candidate_pool` response for `pool`.
+    pool_response: Option<Arc<EtaggedBody>>,
     last_accessed: Arc<RwLock<Instant>>,
 }
 
@@ -25,6 +45,7 @@ impl CacheEntry {
         Self {
             data,
             pool: Vec::new(),
+            pool_response: None,
             last_accessed: Arc::new(RwLock::new(Instant::now())),
         }
     }
@@ -39,11 +60,19 @@ impl CacheEntry {
 pub struct TopicCache {
     pub cache: DashMap<String, CacheEntry>,
     pub last_full_refresh: Arc<RwLock<DateTime<Utc>>>,
+    /// Start time of the last successful incremental update, used as the lower
+    /// bound of `updated_at` for the next one.
+    pub last_incremental_refresh: Arc<RwLock<DateTime<Utc>>>,
+    pub blocklist: SubProfessionBlocklist,
+    pub pool_size_labels: Arc<LabelCap>,
+    pub max_preset_depth: usize,
 }
 
 impl TopicCache {
     pub fn get(&self, topic_id: &str) -> Option<VotingTopic> {
-        self.cache.get(topic_id).map(|entry| entry.access())
+        let topic = self.cache.get(topic_id).map(|entry| entry.access());
+        record_cache_lookup(CacheKind::Topic, topic.is_some());
+        topic
     }
 
     pub fn get_pool(&self, topic_id: &str) -> Option<Vec<i32>> {
@@ -52,7 +81,9 @@ impl TopicCache {
 
     pub fn cache_topic_pool(&self, topic_id: &str, pool: Vec<i32>) {
         if let Some(mut entry) = self.cache.get_mut(topic_id) {
+            self.record_pool_size(topic_id, pool.len());
             entry.pool = pool;
+            entry.pool_response = None;
         } else {
             tracing::warn!(
                 "Attempted to cache pool for non-existent topic: {}",
@@ -95,6 +126,93 @@ impl TopicCache {
         inserted_count
     }
 
+    /// Generates and caches the candidate pool of every active topic, returns
+    /// the number of pools cached.
+    pub fn warm_pools(&self, character_infos: &[CharacterInfo]) -> usize {
+        let topics: Vec<_> = self
+            .cache
+            .iter()
+            .filter(|entry| entry.value().data.is_active)
+            .map(|entry| (entry.key().clone(), entry.value().data.clone()))
+            .collect();
+
+        let mut warmed_count = 0;
+        for (topic_id, topic) in topics {
+            let pool = self.generate_topic_pool(&topic, character_infos);
+            if !pool.is_empty() {
+                self.cache_topic_pool(&topic_id, pool);
+                warmed_count += 1;
+            }
+        }
+
+        warmed_count
+    }
+
+    fn generate_pool(
+        &self,
+        preset: &CandidatePoolPreset,
+        character_infos: &[CharacterInfo],
+    ) -> Vec<i32> {
+        self.blocklist.retain_allowed(
+            preset.generate_pool_with_depth(character_infos, self.max_preset_depth),
+            character_infos,
+        )
+    }
+
+    /// Like `generate_pool`, but starts from the topic's frozen pool when it
+    /// has one.
+    fn generate_topic_pool(
+        &self,
+        topic: &VotingTopic,
+        character_infos: &[CharacterInfo],
+    ) -> Vec<i32> {
+        self.blocklist.retain_allowed(
+            topic.resolve_pool(character_infos, self.max_preset_depth),
+            character_infos,
+        )
+    }
+
+    fn record_pool_size(&self, topic_id: &str, size: usize) {
+        if self.pool_size_labels.admit(topic_id) {
+            metrics::gauge!("topic_candidate_pool_size", "topic_id" => topic_id.to_string())
+                .set(size as f64);
+        } else {
+            tracing::debug!(
+                "Pool size metric topic cap reached, not tracking topic {}",
+                topic_id
+            );
+        }
+    }
+
+    /// Drops every cached pool so they are regenerated on next access.
+    pub fn clear_pools(&self) {
+        for mut entry in self.cache.iter_mut() {
+            entry.pool.clear();
+            entry.pool_response = None;
+        }
+    }
+
+    pub fn clear_pool_responses(&self) {
+        for mut entry in self.cache.iter_mut() {
+            entry.pool_response = None;
+        }
+    }
+
+    pub fn get_pool_response(&self, topic_id: &str) -> Option<Arc<EtaggedBody>> {
+        self.cache
+            .get(topic_id)
+            .and_then(|entry| entry.pool_response.clone())
+    }
+
+    /// Caches the response only if it was built from the currently cached pool.
+    pub fn cache_pool_response(&self, topic_id: &str, pool: &[i32], response: Arc<EtaggedBody>) {
+        if let Some(mut entry) = self.cache.get_mut(topic_id)
+            && entry.pool == pool
+        {
+            entry.pool_response = Some(response);
+        }
+    }
+
     pub fn get_active_topic_ids(&self) -> Vec<String> {
         self.cache
             .iter()
@@ -126,36 +244,67 @@ impl TopicCache {
 #[derive(Clone)]
 pub struct TopicService {
     topic_collection: Collection<VotingTopic>,
+    audit_log_collection: Collection<TopicAuditLogEntry>,
     cache: TopicCache,
 
     refresh_lock: Arc<AsyncRwLock<()>>,
 }
 
 impl TopicService {
-    pub fn new(mongo: mongodb::Database) -> Self {
+    pub fn new(
+        mongo: mongodb::Database,
+        cache_config: TopicCacheConfig,
+        character_infos: &[CharacterInfo],
+        blocklist: SubProfessionBlocklist,
+        max_preset_depth: usize,
+        closed_topics: Option<mpsc::Sender<String>>,
+    ) -> Self {
         let topic_collection = mongo.collection::<VotingTopic>("topics");
+        let audit_log_collection = mongo.collection::<TopicAuditLogEntry>("topic_audit_log");
         let topic_cache = TopicCache {
             cache: DashMap::new(),
             last_full_refresh: Arc::new(RwLock::new(Utc::now())),
+            last_incremental_refresh: Arc::new(RwLock::new(Utc::now())),
+            blocklist,
+            pool_size_labels: Arc::new(LabelCap::new(cache_config.pool_size_metric_topics)),
+            max_preset_depth,
         };
         let refresh_lock = Arc::new(AsyncRwLock::new(()));
 
+        let eager_pool_characters = cache_config
+            .eager_pool_generation
+            .then(|| character_infos.to_vec());
+
         tokio::spawn(Self::cache_updater(
             topic_collection.clone(),
             topic_cache.clone(),
+            cache_config,
+            eager_pool_characters,
+            closed_topics,
         ));
 
         Self {
             topic_collection,
+            audit_log_collection,
             cache: topic_cache,
             refresh_lock,
         }
     }
 
-    pub async fn create_topic(&self, topic: &VotingTopic) -> Result<(), AppError> {
-        self.topic_collection.insert_one(topic).await?;
-
-        Ok(())
+    /// Inserts `topic`, or returns the stored topic when its id is taken
+    /// already, e.g. by a concurrent retry of the same creation.
+    pub async fn create_topic(&self, topic: &VotingTopic) -> Result<Option<VotingTopic>, AppError> {
+        match self.topic_collection.insert_one(topic).await {
+            Ok(_) => Ok(None),
+            Err(e) if is_duplicate_key_error(&e) => {
+                let filter = doc! { "id": &topic.id };
+                match self.topic_collection.find_one(filter).await? {
+                    Some(existing) => Ok(Some(existing)),
+                    None => Err(e.into()),
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
     }
 
     pub async fn _update_topic(&self, mut topic: VotingTopic) -> Result<(), AppError> {
@@ -236,30 +385,116 @@ impl TopicService {
         Ok(())
     }
 
+    /// Persists a reopened topic and records the change in the audit log. The
+    /// cache entry is dropped so the next lookup reads the new window.
+    pub async fn reopen_topic(
+        &self,
+        previous: &VotingTopic,
+        mut reopened: VotingTopic,
+    ) -> Result<(), AppError> {
+        let now = Utc::now();
+        reopened.updated_at = Some(now);
+
+        let filter = doc! { "id": &reopened.id };
+        self.topic_collection.replace_one(filter, &reopened).await?;
+        self.cache.cache.remove(&reopened.id);
+
+        let entry = TopicAuditLogEntry::reopen(previous, &reopened, now);
+        self.audit_log_collection.insert_one(&entry).await?;
+
+        Ok(())
+    }
+
+    /// Persists an edited topic and records the changed fields in the audit
+    /// log. Dropping the cache entry also drops its resolved pool, so a new
+    /// candidate pool takes effect on the next lookup.
+    pub async fn update_topic(
+        &self,
+        mut updated: VotingTopic,
+        fields: Vec<String>,
+    ) -> Result<(), AppError> {
+        let now = Utc::now();
+        updated.updated_at = Some(now);
+
+        let filter = doc! { "id": &updated.id };
+        self.topic_collection.replace_one(filter, &updated).await?;
+        self.cache.cache.remove(&updated.id);
+
+        let entry = TopicAuditLogEntry::update(&updated, fields, now);
+        self.audit_log_collection.insert_one(&entry).await?;
+
+        Ok(())
+    }
+
+    pub fn blocked_sub_professions(&self) -> Vec<String> {
+        self.cache.blocklist.sub_professions()
+    }
+
+    /// Replaces the sub profession blocklist and drops the cached pools built
+    /// with the previous one.
+    pub fn set_blocked_sub_professions(&self, sub_professions: Vec<String>) {
+        self.cache.blocklist.replace(sub_professions);
+        self.cache.clear_pools();
+        tracing::info!(
+            "Sub profession blocklist updated: {:?}",
+            self.cache.blocklist.sub_professions()
+        );
+    }
+
+    /// Resolves a preset the way topic pools are, without caching it.
+    pub fn resolve_pool(
+        &self,
+        preset: &CandidatePoolPreset,
+        character_infos: &[CharacterInfo],
+    ) -> Vec<i32> {
+        self.cache.generate_pool(preset, character_infos)
+    }
+
+    pub fn max_preset_depth(&self) -> usize {
+        self.cache.max_preset_depth
+    }
+
+    pub fn get_candidate_pool_response(&self, topic_id: &str) -> Option<Arc<EtaggedBody>> {
+        self.cache.get_pool_response(topic_id)
+    }
+
+    /// Drops the cached candidate pool responses, e.g. after the avatar urls
+    /// changed.
+    pub fn clear_candidate_pool_responses(&self) {
+        self.cache.clear_pool_responses();
+    }
+
+    pub fn cache_candidate_pool_response(
+        &self,
+        topic_id: &str,
+        pool: &[i32],
+        response: Arc<EtaggedBody>,
+    ) {
+        self.cache.cache_pool_response(topic_id, pool, response);
+    }
+
     pub async fn get_candidate_pool(
         &self,
         topic_id: &str,
         character_infos: &[CharacterInfo],
-    ) -> Option<Vec<i32>> {
+    ) -> Result<Vec<i32>, CandidatePoolError> {
         if let Some(pool) = self.cache.get_pool(topic_id)
             && !pool.is_empty()
         {
-            return Some(pool);
+            record_cache_lookup(CacheKind::Pool, true);
+            return Ok(pool);
         }
+        record_cache_lookup(CacheKind::Pool, false);
 
-        match self.get_topic(topic_id).await {
-            Ok(Some(topic)) => {
-                let pool = topic.candidate_pool.generate_pool(character_infos);
-                if !pool.is_empty() {
-                    self.cache.cache_topic_pool(topic_id, pool.clone());
-                    Some(pool)
-                } else {
-                    None
-                }
-            }
-            Ok(None) => None,
-            Err(_) => None,
+        let Ok(Some(topic)) = self.get_topic(topic_id).await else {
+            return Err(CandidatePoolError::TopicNotFound);
+        };
+        let pool = self.cache.generate_topic_pool(&topic, character_infos);
+        if pool.is_empty() {
+            return Err(CandidatePoolError::Empty);
         }
+        self.cache.cache_topic_pool(topic_id, pool.clone());
+        Ok(pool)
     }
 
     pub async fn _refresh_cache(&self) -> Result<usize, AppError> {
@@ -282,38 +517,78 @@ impl TopicService {
         Ok(inserted_count)
     }
 
-    async fn cache_updater(topic_collection: Collection<VotingTopic>, topic_cache: TopicCache) {
+    async fn cache_updater(
+        topic_collection: Collection<VotingTopic>,
+        topic_cache: TopicCache,
+        cache_config: TopicCacheConfig,
+        eager_pool_characters: Option<Vec<CharacterInfo>>,
+        closed_topics: Option<mpsc::Sender<String>>,
+    ) {
         const CACHE_UPDATE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
 
-        Self::initial_warm_cache(&topic_collection, &topic_cache)
-            .await
-            .map_err(|e| {
-                tracing::error!("Failed to warm up cache: {}", e);
-            })
-            .unwrap_or_else(|_| {
-                tracing::info!("Cache warmed up successfully.");
-            });
+        let mut last_full_refresh_attempt = Instant::now();
+        let mut consecutive_errors: u32 = 0;
+        let mut close_tracker = TopicCloseTracker::default();
+
+        Self::initial_warm_cache(
+            &topic_collection,
+            &topic_cache,
+            eager_pool_characters.as_deref(),
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to warm up cache: {}", e);
+        })
+        .unwrap_or_else(|_| {
+            tracing::info!("Cache warmed up successfully.");
+        });
 
         loop {
             let start = std::time::Instant::now();
 
-            match Self::incremental_cache_update(&topic_collection, &topic_cache).await {
+            match Self::incremental_cache_update(&topic_collection, &topic_cache, &cache_config)
+                .await
+            {
                 Ok(updated_count) => {
+                    consecutive_errors = 0;
                     tracing::debug!(
                         "Incremental cache update completed: {} topics updated",
                         updated_count
                     );
                 }
                 Err(e) => {
-                    tracing::error!("Error during incremental cache update: {}", e);
-                    if let Err(e) = Self::full_cache_update(&topic_collection, &topic_cache).await {
-                        tracing::error!("Full cache update failed: {}", e);
+                    consecutive_errors += 1;
+                    tracing::error!(
+                        "Error during incremental cache update ({} consecutive): {}",
+                        consecutive_errors,
+                        e
+                    );
+
+                    if last_full_refresh_attempt.elapsed()
+                        < cache_config.min_full_refresh_interval()
+                    {
+                        tracing::debug!(
+                            "Skipping full cache update, last attempt was {:?} ago",
+                            last_full_refresh_attempt.elapsed()
+                        );
                     } else {
-                        tracing::debug!("Full cache update completed successfully.");
+                        last_full_refresh_attempt = Instant::now();
+                        if let Err(e) =
+                            Self::full_cache_update(&topic_collection, &topic_cache).await
+                        {
+                            tracing::error!("Full cache update failed: {}", e);
+                        } else {
+                            consecutive_errors = 0;
+                            tracing::debug!("Full cache update completed successfully.");
+                        }
                     }
                 }
             }
 
+            if let Some(closed_topics) = &closed_topics {
+                Self::notify_closed_topics(&topic_cache, &mut close_tracker, closed_topics);
+            }
+
             let elapsed = start.elapsed();
             if elapsed < CACHE_UPDATE_INTERVAL {
                 tokio::time::sleep(CACHE_UPDATE_INTERVAL - elapsed).await;
@@ -321,12 +596,39 @@ impl TopicService {
         }
     }
 
+    fn notify_closed_topics(
+        cache: &TopicCache,
+        tracker: &mut TopicCloseTracker,
+        closed_topics: &mpsc::Sender<String>,
+    ) {
+        let topics: Vec<VotingTopic> = cache
+            .cache
+            .iter()
+            .map(|entry| entry.value().data.clone())
+            .collect();
+
+        for topic_id in tracker.observe(&topics, Utc::now()) {
+            tracing::info!("Topic {} closed", topic_id);
+            match closed_topics.try_send(topic_id) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(topic_id)) => {
+                    tracing::warn!("Topic close queue full, no webhooks for {}", topic_id);
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    tracing::warn!("Topic close receiver dropped");
+                }
+            }
+        }
+    }
+
     async fn initial_warm_cache(
         topic_collection: &Collection<VotingTopic>,
         cache: &TopicCache,
+        eager_pool_characters: Option<&[CharacterInfo]>,
     ) -> Result<(), AppError> {
         tracing::info!("Warming up cache...");
 
+        let started_at = Utc::now();
         let filter = doc! {};
         let mut cursor = topic_collection.find(filter).await?;
         let mut topics = Vec::new();
@@ -336,20 +638,33 @@ impl TopicService {
         }
 
         let updated_count = cache.insert_batch(&topics);
-        *cache.last_full_refresh.write() = Utc::now();
+        *cache.last_full_refresh.write() = started_at;
+        *cache.last_incremental_refresh.write() = started_at;
 
         tracing::info!("Cache warmed up with {} topics", updated_count);
 
+        if let Some(character_infos) = eager_pool_characters {
+            let pool_started_at = Instant::now();
+            let warmed_count = cache.warm_pools(character_infos);
+            tracing::info!(
+                "Generated candidate pools for {} active topics in {:?}",
+                warmed_count,
+                pool_started_at.elapsed()
+            );
+        }
+
         Ok(())
     }
 
     async fn incremental_cache_update(
         topic_collection: &Collection<VotingTopic>,
         cache: &TopicCache,
+        cache_config: &TopicCacheConfig,
     ) -> Result<usize, mongodb::error::Error> {
-        let last_refresh = *cache.last_full_refresh.read();
+        let started_at = Utc::now();
+        let since = *cache.last_incremental_refresh.read() - cache_config.incremental_overlap();
         let since_filter = doc! {
-            "updated_at": { "$gte": mongodb::bson::to_bson(&last_refresh).unwrap() }
+            "updated_at": { "$gte": mongodb::bson::to_bson(&since).unwrap() }
         };
 
         let mut cursor = topic_collection.find(since_filter).await?;
@@ -361,6 +676,8 @@ impl TopicService {
             }
         }
 
+        *cache.last_incremental_refresh.write() = started_at;
+
         Ok(updated_count)
     }
 
@@ -368,6 +685,7 @@ impl TopicService {
         topic_collection: &Collection<VotingTopic>,
         cache: &TopicCache,
     ) -> Result<usize, mongodb::error::Error> {
+        let started_at = Utc::now();
         let filter = doc! {};
         let mut cursor = topic_collection.find(filter).await?;
         let mut topics = Vec::new();
@@ -377,7 +695,8 @@ impl TopicService {
         }
 
         let updated_count = cache.insert_batch(&topics);
-        *cache.last_full_refresh.write() = Utc::now();
+        *cache.last_full_refresh.write() = started_at;
+        *cache.last_incremental_refresh.write() = started_at;
 
         Ok(updated_count)
     }
@@ -387,12 +706,52 @@ impl TopicService {
 mod tests {
     use super::*;
     use mongodb::options::ClientOptions;
-    use share::models::{
-        candidate_pool_preset::CandidatePoolPreset,
-        database::{CreateTopicStatus, VotingTopicType},
-        excel::RarityRank,
+    use share::{
+        models::{
+            api::ApiMsg,
+            candidate_pool_preset::{
+                CandidatePoolPreset, CandidatePoolPresetFilter, MAX_PRESET_DEPTH,
+            },
+            excel::{ProfessionCategory, RarityRank},
+        },
+        test_util::test_topic,
     };
-    use tokio;
+
+    /// Needs a local MongoDB: `cargo test -p web-service duplicate_id -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_create_topic_returns_existing_on_duplicate_id() {
+        let client = mongodb::Client::with_uri_str("mongodb://localhost:27017")
+            .await
+            .unwrap();
+        let db = client.database("test_db_topic_index");
+        db.collection::<VotingTopic>("topics").drop().await.unwrap();
+        share::bootstrap::ensure_topic_index(&db).await.unwrap();
+
+        let topic_service = TopicService::new(
+            db.clone(),
+            TopicCacheConfig::default(),
+            &[],
+            SubProfessionBlocklist::default(),
+            MAX_PRESET_DEPTH,
+            None,
+        );
+        let now = Utc::now();
+        let topic = test_topic("duplicate", now, now + chrono::Duration::days(1));
+
+        let (first, second) = tokio::join!(
+            topic_service.create_topic(&topic),
+            topic_service.create_topic(&topic)
+        );
+        let existing: Vec<VotingTopic> = [first.unwrap(), second.unwrap()]
+            .into_iter()
+            .flatten()
+            .collect();
+        assert_eq!(existing.len(), 1);
+        assert_eq!(existing[0].id, "duplicate");
+
+        db.drop().await.unwrap();
+    }
 
     #[tokio::test]
     async fn test_topic_service() {
@@ -403,28 +762,32 @@ mod tests {
         let client = mongodb::Client::with_options(client_options).unwrap();
         let db = client.database("test_db");
 
-        let topic_service = TopicService::new(db.clone());
+        let topic_service = TopicService::new(
+            db.clone(),
+            TopicCacheConfig::default(),
+            &[],
+            SubProfessionBlocklist::default(),
+            MAX_PRESET_DEPTH,
+            None,
+        );
 
+        let now = Utc::now();
         let test_topic = VotingTopic {
-            id: "test_topic_1".to_string(),
-            name: "Test Topic".to_string(),
-            title: "Test Title".to_string(),
-            description: "This is a test topic.".to_string(),
-            topic_type: VotingTopicType::Pairwise,
             candidate_pool: CandidatePoolPreset::ByRarity {
                 rarities: vec![RarityRank::Tier6],
                 include_not_obtainable: false,
             },
-            created_at: chrono::Utc::now(),
-            updated_at: None,
-            open_time: chrono::Utc::now(),
-            close_time: chrono::Utc::now() + chrono::Duration::days(1),
-            is_active: true,
-            status: CreateTopicStatus::WaitingAudit,
+            ..test_topic("test_topic_1", now, now + chrono::Duration::days(1))
         };
 
         // Test create_topic
-        topic_service.create_topic(&test_topic).await.unwrap();
+        assert!(
+            topic_service
+                .create_topic(&test_topic)
+                .await
+                .unwrap()
+                .is_none()
+        );
 
         // Test get_topic_by_id
         let fetched_topic = topic_service
@@ -512,4 +875,181 @@ mod tests {
         topic_service._delete_topic("test_topic_1").await.unwrap();
         db.collection::<VotingTopic>("topics").drop().await.unwrap();
     }
+
+    /// Needs a local MongoDB: `cargo test -p web-service narrows_window -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_incremental_cache_update_narrows_window() {
+        let mongo_uri = "mongodb://localhost:27017";
+        let client_options = ClientOptions::parse(mongo_uri).await.unwrap();
+        let client = mongodb::Client::with_options(client_options).unwrap();
+        let db = client.database("test_db_incremental");
+        let topic_collection = db.collection::<VotingTopic>("topics");
+
+        let cache = TopicCache {
+            cache: DashMap::new(),
+            last_full_refresh: Arc::new(RwLock::new(Utc::now())),
+            last_incremental_refresh: Arc::new(RwLock::new(
+                Utc::now() - chrono::Duration::hours(1),
+            )),
+            blocklist: SubProfessionBlocklist::default(),
+            pool_size_labels: Arc::new(LabelCap::new(64)),
+            max_preset_depth: MAX_PRESET_DEPTH,
+        };
+        let cache_config = TopicCacheConfig::default();
+
+        let now = Utc::now();
+        let test_topic = VotingTopic {
+            updated_at: Some(now),
+            ..test_topic(
+                "test_topic_incremental",
+                now,
+                now + chrono::Duration::days(1),
+            )
+        };
+        topic_collection.insert_one(&test_topic).await.unwrap();
+
+        let first_since = *cache.last_incremental_refresh.read();
+        let updated =
+            TopicService::incremental_cache_update(&topic_collection, &cache, &cache_config)
+                .await
+                .unwrap();
+        assert_eq!(updated, 1);
+
+        let second_since = *cache.last_incremental_refresh.read();
+        assert!(second_since > first_since);
+
+        let updated =
+            TopicService::incremental_cache_update(&topic_collection, &cache, &cache_config)
+                .await
+                .unwrap();
+        assert_eq!(updated, 0);
+        assert!(*cache.last_incremental_refresh.read() >= second_since);
+
+        topic_collection.drop().await.unwrap();
+    }
+
+    #[test]
+    fn test_warm_pools_only_active_topics() {
+        let cache = TopicCache {
+            cache: DashMap::new(),
+            last_full_refresh: Arc::new(RwLock::new(Utc::now())),
+            last_incremental_refresh: Arc::new(RwLock::new(Utc::now())),
+            blocklist: SubProfessionBlocklist::default(),
+            pool_size_labels: Arc::new(LabelCap::new(64)),
+            max_preset_depth: MAX_PRESET_DEPTH,
+        };
+        let character_infos = vec![CharacterInfo {
+            id: 2,
+            name: "Amiya".to_string(),
+            rarity: RarityRank::Tier5,
+            profession: ProfessionCategory::CASTER,
+            sub_profession_id: "corecaster".to_string(),
+            is_not_obtainable: false,
+        }];
+
+        let now = Utc::now();
+        let active_topic = test_topic("test_topic_active", now, now + chrono::Duration::days(1));
+        let inactive_topic = VotingTopic {
+            id: "test_topic_inactive".to_string(),
+            is_active: false,
+            ..active_topic.clone()
+        };
+        cache.insert_batch(&[active_topic, inactive_topic]);
+
+        assert_eq!(cache.warm_pools(&character_infos), 1);
+        assert_eq!(cache.get_pool("test_topic_active"), Some(vec![2]));
+        assert_eq!(cache.get_pool("test_topic_inactive"), Some(vec![]));
+    }
+
+    #[test]
+    fn test_pool_response_invalidated_with_pool() {
+        let cache = TopicCache {
+            cache: DashMap::new(),
+            last_full_refresh: Arc::new(RwLock::new(Utc::now())),
+            last_incremental_refresh: Arc::new(RwLock::new(Utc::now())),
+            blocklist: SubProfessionBlocklist::default(),
+            pool_size_labels: Arc::new(LabelCap::new(64)),
+            max_preset_depth: MAX_PRESET_DEPTH,
+        };
+        let now = Utc::now();
+        let topic = test_topic(
+            "test_topic_pool_response",
+            now,
+            now + chrono::Duration::days(1),
+        );
+        cache.insert(&topic);
+        cache.cache_topic_pool(&topic.id, vec![1, 2]);
+
+        let response = Arc::new(EtaggedBody::new(b"[1,2]".to_vec()));
+        cache.cache_pool_response(&topic.id, &[1, 3], response.clone());
+        assert!(cache.get_pool_response(&topic.id).is_none());
+
+        cache.cache_pool_response(&topic.id, &[1, 2], response);
+        assert!(cache.get_pool_response(&topic.id).is_some());
+
+        cache.cache_topic_pool(&topic.id, vec![1, 2, 3]);
+        assert!(cache.get_pool_response(&topic.id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_empty_candidate_pool_is_not_topic_not_found() {
+        // never connects, both topics below are answered from the cache
+        let client_options = ClientOptions::parse("mongodb://localhost:27017")
+            .await
+            .unwrap();
+        let client = mongodb::Client::with_options(client_options).unwrap();
+        let topic_service = TopicService::new(
+            client.database("test_db"),
+            TopicCacheConfig::default(),
+            &[],
+            SubProfessionBlocklist::default(),
+            MAX_PRESET_DEPTH,
+            None,
+        );
+        let character_infos = vec![CharacterInfo {
+            id: 2,
+            name: "Amiya".to_string(),
+            rarity: RarityRank::Tier5,
+            profession: ProfessionCategory::CASTER,
+            sub_profession_id: "corecaster".to_string(),
+            is_not_obtainable: false,
+        }];
+
+        let now = Utc::now();
+        let topic = VotingTopic {
+            candidate_pool: CandidatePoolPreset::Filter(CandidatePoolPresetFilter {
+                professions: Some(vec![ProfessionCategory::SNIPER]),
+                ..Default::default()
+            }),
+            ..test_topic(
+                "test_topic_empty_pool",
+                now,
+                now + chrono::Duration::days(1),
+            )
+        };
+        let populated_topic = VotingTopic {
+            id: "test_topic_populated_pool".to_string(),
+            candidate_pool: CandidatePoolPreset::All,
+            ..topic.clone()
+        };
+        topic_service.cache.insert_batch(&[topic, populated_topic]);
+
+        assert_eq!(
+            topic_service
+                .get_candidate_pool("test_topic_empty_pool", &character_infos)
+                .await,
+            Err(CandidatePoolError::Empty)
+        );
+        assert_eq!(
+            topic_service
+                .get_candidate_pool("test_topic_populated_pool", &character_infos)
+                .await,
+            Ok(vec![2])
+        );
+        assert!(matches!(
+            ApiMsg::from(CandidatePoolError::Empty),
+            ApiMsg::EmptyCandidatePool
+        ));
+    }
 }
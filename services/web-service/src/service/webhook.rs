@@ -0,0 +1,236 @@
+use std::{sync::Arc, time::Duration};
+
+use reqwest::header::CONTENT_TYPE;
+use share::{
+    config::WebhookConfig,
+    models::{
+        api::{ApiData, ResultsFinalOrderRequest},
+        topic_id::TopicId,
+    },
+    webhook::{WEBHOOK_SIGNATURE_HEADER, WebhookSigner, claim_topic_close, retry_delay},
+};
+use tokio::sync::mpsc;
+
+use crate::{AppState, api::final_order};
+
+/// Posts the final order of every topic received on `closed_topics` to the
+/// configured webhook urls. Every instance sees the close, the webhooks are
+/// sent by the one that claims it in Redis.
+pub fn spawn_topic_close_webhooks(
+    state: Arc<AppState>,
+    config: WebhookConfig,
+    mut closed_topics: mpsc::Receiver<String>,
+) {
+    let client = match reqwest::Client::builder().timeout(config.timeout()).build() {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to build webhook client, webhooks disabled: {}", e);
+            return;
+        }
+    };
+    let signer = WebhookSigner::from_config(&config);
+    if signer.is_none() {
+        tracing::warn!("Webhooks are configured without a secret, payloads are unsigned");
+    }
+
+    tokio::spawn(async move {
+        while let Some(topic_id) = closed_topics.recv().await {
+            let topic_id = match TopicId::parse(&topic_id) {
+                Ok(topic_id) => topic_id,
+                Err(e) => {
+                    tracing::warn!("Skipping webhooks of topic {}: {}", topic_id, e);
+                    continue;
+                }
+            };
+            if !claim_topic_close(state.redis.connection.clone(), &topic_id).await {
+                tracing::debug!(
+                    "Webhooks of topic {} are sent by another instance",
+                    topic_id
+                );
+                continue;
+            }
+
+            let body = match final_order_payload(&state, &topic_id).await {
+                Some(body) => body,
+                None => continue,
+            };
+
+            tracing::info!(
+                "Topic {} closed, notifying {} webhooks",
+                topic_id,
+                config.urls.len()
+            );
+            for url in &config.urls {
+                tokio::spawn(deliver(
+                    client.clone(),
+                    url.clone(),
+                    body.clone(),
+                    signer.clone(),
+                    config.max_retries,
+                    retry_delay,
+                ));
+            }
+        }
+    });
+}
+
+async fn final_order_payload(state: &AppState, topic_id: &TopicId) -> Option<Vec<u8>> {
+    let req = ResultsFinalOrderRequest {
+        topic_id: topic_id.clone(),
+        tier_breakpoints: None,
+        ranking_method: Default::default(),
+        limit: None,
+        format: Default::default(),
+    };
+
+    let response = match final_order(state, req).await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::error!("Failed to build final order of topic {}: {}", topic_id, e);
+            return None;
+        }
+    };
+
+    match response.data {
+        ApiData::Data(data) => serde_json::to_vec(&data)
+            .inspect_err(|e| tracing::error!("Failed to serialize final order: {}", e))
+            .ok(),
+        ApiData::Empty => {
+            tracing::debug!(
+                "No final order for topic {} ({}), skipping webhooks",
+                topic_id,
+                response.message
+            );
+            None
+        }
+    }
+}
+
+/// Posts `body` to `url`, retrying up to `max_retries` times after waiting
+/// `retry_delay(attempt)`. Returns whether it was delivered.
+async fn deliver(
+    client: reqwest::Client,
+    url: String,
+    body: Vec<u8>,
+    signer: Option<WebhookSigner>,
+    max_retries: u32,
+    retry_delay: fn(u32) -> Duration,
+) -> bool {
+    let signature = signer.map(|signer| signer.sign(&body));
+
+    for attempt in 0..=max_retries {
+        if attempt > 0 {
+            tokio::time::sleep(retry_delay(attempt)).await;
+        }
+
+        let mut request = client
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        if let Some(signature) = &signature {
+            request = request.header(WEBHOOK_SIGNATURE_HEADER, signature);
+        }
+
+        match request.send().await.and_then(|rsp| rsp.error_for_status()) {
+            Ok(_) => {
+                metrics::counter!("topic_close_webhooks_total", "outcome" => "delivered")
+                    .increment(1);
+                return true;
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Webhook {} failed (attempt {}/{}): {}",
+                    url,
+                    attempt + 1,
+                    max_retries + 1,
+                    e
+                );
+            }
+        }
+    }
+
+    metrics::counter!("topic_close_webhooks_total", "outcome" => "failed").increment(1);
+    tracing::error!("Giving up on webhook {}", url);
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Mutex,
+        atomic::{AtomicU32, Ordering},
+    };
+
+    use axum::{Router, http::HeaderMap, http::StatusCode, routing::post};
+
+    use super::*;
+
+    /// Serves a webhook receiver answering 500 to the first `failures`
+    /// requests, returns its url, the request count and the last signature.
+    async fn receiver(failures: u32) -> (String, Arc<AtomicU32>, Arc<Mutex<Option<String>>>) {
+        let hits = Arc::new(AtomicU32::new(0));
+        let signature = Arc::new(Mutex::new(None));
+        let app = Router::new().route(
+            "/hook",
+            post({
+                let (hits, signature) = (hits.clone(), signature.clone());
+                move |headers: HeaderMap| async move {
+                    *signature.lock().unwrap() = headers
+                        .get(WEBHOOK_SIGNATURE_HEADER)
+                        .map(|value| value.to_str().unwrap().to_string());
+                    match hits.fetch_add(1, Ordering::SeqCst) < failures {
+                        true => StatusCode::INTERNAL_SERVER_ERROR,
+                        false => StatusCode::OK,
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, hits, signature)
+    }
+
+    fn no_delay(_: u32) -> Duration {
+        Duration::ZERO
+    }
+
+    #[tokio::test]
+    async fn test_deliver_retries_until_delivered() {
+        let (url, hits, signature) = receiver(2).await;
+        let signer = WebhookSigner::new("secret");
+
+        let delivered = deliver(
+            reqwest::Client::new(),
+            url,
+            b"{}".to_vec(),
+            Some(signer.clone()),
+            3,
+            no_delay,
+        )
+        .await;
+
+        assert!(delivered);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert_eq!(*signature.lock().unwrap(), Some(signer.sign(b"{}")));
+    }
+
+    #[tokio::test]
+    async fn test_deliver_gives_up_after_max_retries() {
+        let (url, hits, signature) = receiver(u32::MAX).await;
+
+        let delivered = deliver(
+            reqwest::Client::new(),
+            url,
+            b"{}".to_vec(),
+            None,
+            2,
+            no_delay,
+        )
+        .await;
+
+        assert!(!delivered);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert_eq!(*signature.lock().unwrap(), None);
+    }
+}
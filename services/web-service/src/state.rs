@@ -1,15 +1,19 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
 use dashmap::DashMap;
 use share::{
-    models::{
-        api::{BallotSaveRequest, CharacterPortrait},
-        excel::CharacterInfo,
-    },
+    bootstrap::VoteSettings,
+    models::{api::BallotSaveRequest, excel::CharacterInfo},
+    portrait::{PortraitHints, PortraitStore},
+    search::OperatorAliases,
+    selection::{OperatorGamesCache, SelectionRngs},
     snowflake::Snowflake,
 };
 
-use crate::{service::TopicService, task::TaskManager};
+use crate::{
+    service::{BallotBundler, TopicService},
+    task::TaskManager,
+};
 
 #[derive(Clone)]
 pub struct RedisService {
@@ -21,16 +25,31 @@ pub struct RedisService {
 #[derive(Clone)]
 pub struct AppState {
     pub redis: RedisService,
-    pub _mongodb: mongodb::Database,
+    pub mongodb: mongodb::Database,
+    /// `database.mongodb_read_url`, or `mongodb` when unset. Serves the admin
+    /// ballot lookup and results velocity, everything else uses `mongodb`.
+    pub mongodb_read: mongodb::Database,
     pub jetstream: async_nats::jetstream::Context,
+    /// Set when `nats.ballot_bundling` is enabled, saved ballots are then
+    /// published through it instead of one message each.
+    pub ballot_bundler: Option<BallotBundler>,
     pub snowflake: Snowflake,
 
     pub character_infos: Vec<CharacterInfo>,
-    pub character_portraits: HashMap<i32, CharacterPortrait>,
+    pub character_portraits: PortraitStore,
+    pub portrait_hints: PortraitHints,
+    pub operator_aliases: OperatorAliases,
+    pub vote: VoteSettings,
+    pub base_multiplier: i32,
+    pub selection_rngs: SelectionRngs,
+    pub operator_games: OperatorGamesCache,
 
     pub topic_service: TopicService,
 
     pub bench_ballot_store: DashMap<String, BallotSaveRequest>,
+    /// `op_cooccur` hashes by topic id with the time they were read, see
+    /// `PARTNERS_CACHE_TTL`.
+    pub partners_cache: DashMap<String, (Instant, Arc<HashMap<String, i64>>)>,
 
     pub task_manager: Arc<TaskManager>,
 }
@@ -1,18 +1,23 @@
 use futures::FutureExt;
+use parking_lot::Mutex;
 use serde::Serialize;
 use std::{
+    collections::BTreeMap,
     future::Future,
     panic::AssertUnwindSafe,
     pin::Pin,
     sync::{
         Arc,
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
     },
+    time::Instant,
 };
-use tokio::sync::{Semaphore, mpsc};
+use tokio::sync::{Semaphore, mpsc, watch};
 use tracing::error;
 
-type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+use crate::constants::TASK_METRICS_INTERVAL;
+
+type BoxFuture = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'static>>;
 type Task = Box<dyn FnOnce() -> BoxFuture + Send + 'static>;
 
 #[derive(Default, Debug, Clone, Serialize)]
@@ -20,26 +25,50 @@ pub struct TaskStats {
     pub queued: usize,
     pub running: usize,
     pub completed: usize,
+    /// Tasks that returned an error or panicked.
+    pub failed: usize,
+    /// Tasks dropped by `spawn` because `max_in_flight` were already pending.
+    pub rejected: usize,
+    /// Seconds since the oldest queued or running task was spawned, `None`
+    /// when idle. Growing steadily means the tasks are stalled.
+    pub oldest_in_flight_secs: Option<f64>,
 }
 
 pub struct TaskManager {
-    sender: mpsc::UnboundedSender<Task>,
+    sender: mpsc::UnboundedSender<(u64, Task)>,
     queued: AtomicUsize,
     running: AtomicUsize,
     completed: AtomicUsize,
+    failed: AtomicUsize,
+    rejected: AtomicUsize,
+    next_id: AtomicU64,
+    /// Spawn time of every queued or running task by id, ids increase so the
+    /// first entry is the oldest.
+    in_flight: Mutex<BTreeMap<u64, Instant>>,
+    /// Tasks finished either way, bumped once their outcome is recorded.
+    finished: watch::Sender<usize>,
     concurrency: usize,
+    max_in_flight: usize,
 }
 
 impl TaskManager {
-    pub fn new(concurrency: usize) -> Arc<Self> {
-        let (tx, mut rx) = mpsc::unbounded_channel::<Task>();
+    /// `max_in_flight` bounds the queued and running tasks together, 0 leaves
+    /// them unbounded.
+    pub fn new(concurrency: usize, max_in_flight: usize) -> Arc<Self> {
+        let (tx, mut rx) = mpsc::unbounded_channel::<(u64, Task)>();
 
         let manager = Arc::new(Self {
             sender: tx,
             queued: AtomicUsize::new(0),
             running: AtomicUsize::new(0),
             completed: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            rejected: AtomicUsize::new(0),
+            next_id: AtomicU64::new(0),
+            in_flight: Mutex::new(BTreeMap::new()),
+            finished: watch::Sender::new(0),
             concurrency,
+            max_in_flight,
         });
 
         let stats_for_dispatch = manager.clone();
@@ -48,7 +77,7 @@ impl TaskManager {
         tokio::spawn({
             let semaphore = semaphore.clone();
             async move {
-                while let Some(task) = rx.recv().await {
+                while let Some((id, task)) = rx.recv().await {
                     stats_for_dispatch.queued.fetch_sub(1, Ordering::Relaxed);
 
                     let permit = semaphore.clone().acquire_owned().await.unwrap();
@@ -61,12 +90,28 @@ impl TaskManager {
 
                         let res = AssertUnwindSafe(fut).catch_unwind().await;
 
-                        if let Err(e) = res {
-                            error!("Background task panicked: {:?}", e);
-                        }
-
+                        stats_worker.in_flight.lock().remove(&id);
                         stats_worker.running.fetch_sub(1, Ordering::Relaxed);
-                        stats_worker.completed.fetch_add(1, Ordering::Relaxed);
+                        match res {
+                            Ok(Ok(())) => {
+                                stats_worker.completed.fetch_add(1, Ordering::Relaxed);
+                                metrics::counter!("background_tasks_total", "outcome" => "completed")
+                                    .increment(1);
+                            }
+                            Ok(Err(e)) => {
+                                error!("Background task failed: {}", e);
+                                stats_worker.failed.fetch_add(1, Ordering::Relaxed);
+                                metrics::counter!("background_tasks_total", "outcome" => "failed")
+                                    .increment(1);
+                            }
+                            Err(e) => {
+                                error!("Background task panicked: {:?}", e);
+                                stats_worker.failed.fetch_add(1, Ordering::Relaxed);
+                                metrics::counter!("background_tasks_total", "outcome" => "failed")
+                                    .increment(1);
+                            }
+                        }
+                        stats_worker.finished.send_modify(|finished| *finished += 1);
 
                         drop(permit);
                     });
@@ -76,25 +121,60 @@ impl TaskManager {
             }
         });
 
+        tokio::spawn({
+            let manager = manager.clone();
+            async move {
+                let mut ticker = tokio::time::interval(TASK_METRICS_INTERVAL);
+                ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+                loop {
+                    ticker.tick().await;
+                    manager.record_metrics();
+                }
+            }
+        });
+
         manager
     }
 
-    pub fn spawn<Fut, F>(&self, f: F)
+    /// Queues `f`, or drops it with an error log when `max_in_flight` tasks
+    /// are already pending. Returns whether the task was queued. A task that
+    /// returns an error counts as failed, like one that panics.
+    pub fn spawn<Fut, F>(&self, f: F) -> bool
     where
-        Fut: Future<Output = ()> + Send + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
         F: FnOnce() -> Fut + Send + 'static,
     {
+        let id = {
+            let mut in_flight = self.in_flight.lock();
+            if self.max_in_flight != 0 && in_flight.len() >= self.max_in_flight {
+                drop(in_flight);
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                metrics::counter!("background_tasks_total", "outcome" => "rejected").increment(1);
+                error!(
+                    "Dropped background task, {} tasks are already in flight",
+                    self.max_in_flight
+                );
+                return false;
+            }
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            in_flight.insert(id, Instant::now());
+            id
+        };
         self.queued.fetch_add(1, Ordering::Relaxed);
 
         let task: Task = Box::new(move || Box::pin(f()) as BoxFuture);
 
-        if let Err(e) = self.sender.send(task) {
+        if let Err(e) = self.sender.send((id, task)) {
             self.queued.fetch_sub(1, Ordering::Relaxed);
+            self.in_flight.lock().remove(&id);
             error!(
                 "Failed to enqueue background task (receiver closed): {:?}",
                 e
             );
+            return false;
         }
+        true
     }
 
     pub fn get_stats(&self) -> TaskStats {
@@ -102,10 +182,93 @@ impl TaskManager {
             queued: self.queued.load(Ordering::Relaxed),
             running: self.running.load(Ordering::Relaxed),
             completed: self.completed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            oldest_in_flight_secs: self
+                .in_flight
+                .lock()
+                .first_key_value()
+                .map(|(_, started)| started.elapsed().as_secs_f64()),
         }
     }
 
     pub fn concurrency(&self) -> usize {
         self.concurrency
     }
+
+    fn record_metrics(&self) {
+        let stats = self.get_stats();
+        metrics::gauge!("background_tasks_queued").set(stats.queued as f64);
+        metrics::gauge!("background_tasks_running").set(stats.running as f64);
+        metrics::gauge!("background_tasks_oldest_age_seconds")
+            .set(stats.oldest_in_flight_secs.unwrap_or(0.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    /// Waits until `count` tasks have finished and their outcome is in the
+    /// stats.
+    async fn finished(manager: &TaskManager, count: usize) {
+        let mut finished = manager.finished.subscribe();
+        finished.wait_for(|&n| n >= count).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stats_track_oldest_in_flight_and_outcomes() {
+        let manager = TaskManager::new(4, 0);
+        assert!(manager.get_stats().oldest_in_flight_secs.is_none());
+
+        let (started_tx, started_rx) = oneshot::channel::<()>();
+        let (release_tx, release_rx) = oneshot::channel::<()>();
+        assert!(manager.spawn(move || async move {
+            let _ = started_tx.send(());
+            let _ = release_rx.await;
+            Ok(())
+        }));
+        assert!(manager.spawn(|| async { panic!("publish failed") }));
+        assert!(manager.spawn(|| async { Err("publish failed".to_string()) }));
+
+        started_rx.await.unwrap();
+        finished(&manager, 2).await;
+        let stats = manager.get_stats();
+        assert_eq!(stats.running, 1);
+        assert_eq!(stats.failed, 2);
+        assert_eq!(stats.completed, 0);
+        assert!(stats.oldest_in_flight_secs.is_some());
+
+        release_tx.send(()).unwrap();
+        finished(&manager, 3).await;
+        let stats = manager.get_stats();
+        assert_eq!(stats.running, 0);
+        assert_eq!(stats.completed, 1);
+        assert!(stats.oldest_in_flight_secs.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_spawn_rejects_over_max_in_flight() {
+        let manager = TaskManager::new(1, 2);
+        let (release_tx, release_rx) = oneshot::channel::<()>();
+        let release_rx = Arc::new(Mutex::new(Some(release_rx)));
+
+        for _ in 0..2 {
+            let release_rx = release_rx.clone();
+            assert!(manager.spawn(move || async move {
+                let rx = release_rx.lock().take();
+                if let Some(rx) = rx {
+                    let _ = rx.await;
+                }
+                Ok(())
+            }));
+        }
+        assert!(!manager.spawn(|| async { Ok(()) }));
+        assert_eq!(manager.get_stats().rejected, 1);
+
+        release_tx.send(()).unwrap();
+        finished(&manager, 2).await;
+        assert!(manager.spawn(|| async { Ok(()) }));
+    }
 }
@@ -1,12 +1,105 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use redis::aio::MultiplexedConnection;
-use tokio::sync::Mutex;
+use share::snowflake::{Snowflake, unix_timestamp_ms};
+use tokio::{sync::Mutex, task::JoinHandle};
+use uuid::Uuid;
+
+use crate::constants::{LUA_SCRIPT_RENEW_WORKER_ID, WORKER_ID_LEASE_TTL, WORKER_ID_RENEW_INTERVAL};
+
+/// Outcome of one run of `LUA_SCRIPT_RENEW_WORKER_ID`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Renewal {
+    Renewed,
+    /// The lease had expired and nobody else took it in between.
+    Reacquired,
+    /// Another instance holds the worker id now.
+    Taken,
+    /// Redis could not be reached, the lease is in an unknown state.
+    Failed,
+}
+
+impl Renewal {
+    fn from_script(result: redis::RedisResult<i64>) -> Self {
+        match result {
+            Ok(1) => Self::Renewed,
+            Ok(2) => Self::Reacquired,
+            Ok(_) => Self::Taken,
+            Err(e) => {
+                tracing::warn!("failed to renew worker id lease: {}", e);
+                Self::Failed
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LeaseAction {
+    Generate,
+    /// The lease may expire before the next renewal, stop generating ids
+    /// until it is renewed again.
+    Suspend,
+    /// The worker id is or may soon be used by another instance.
+    Halt,
+}
+
+/// Decides what the id generator may do after each renewal attempt.
+#[derive(Debug)]
+struct LeaseState {
+    failures: u32,
+    max_failures: u32,
+    renewed_at: Instant,
+}
+
+impl LeaseState {
+    fn new(max_failures: u32, renewed_at: Instant) -> Self {
+        Self {
+            failures: 0,
+            max_failures,
+            renewed_at,
+        }
+    }
+
+    fn on_renewal(&mut self, renewal: Renewal, now: Instant) -> LeaseAction {
+        match renewal {
+            Renewal::Renewed | Renewal::Reacquired => {
+                self.failures = 0;
+                self.renewed_at = now;
+                LeaseAction::Generate
+            }
+            Renewal::Taken => LeaseAction::Halt,
+            Renewal::Failed => {
+                self.failures += 1;
+                if self.failures > self.max_failures {
+                    LeaseAction::Halt
+                } else if now.duration_since(self.renewed_at) + WORKER_ID_RENEW_INTERVAL
+                    >= WORKER_ID_LEASE_TTL
+                {
+                    LeaseAction::Suspend
+                } else {
+                    LeaseAction::Generate
+                }
+            }
+        }
+    }
+}
+
+/// Unix milliseconds until which a lease taken or renewed at `started_ms` is
+/// safe to generate ids with. One renew interval is kept as a margin for the
+/// clock skew between this instance and Redis and for the round trip.
+fn lease_deadline_ms(started_ms: u64) -> u64 {
+    started_ms + (WORKER_ID_LEASE_TTL - WORKER_ID_RENEW_INTERVAL).as_millis() as u64
+}
 
 pub struct WorkerIdManager {
     connection: MultiplexedConnection,
-    worker_id: Arc<Mutex<Option<u8>>>,
+    /// The acquired worker id and the unix milliseconds its lease was asked at.
+    worker_id: Arc<Mutex<Option<(u8, u64)>>>,
     max_worker_id: u8,
+    /// Stored as the lease value so a renewal can tell our lease from one
+    /// another instance acquired after ours expired.
+    token: String,
+    renew_script: redis::Script,
 }
 
 impl WorkerIdManager {
@@ -15,23 +108,29 @@ impl WorkerIdManager {
             connection,
             worker_id: Arc::new(Mutex::new(None)),
             max_worker_id,
+            token: Uuid::new_v4().to_string(),
+            renew_script: redis::Script::new(LUA_SCRIPT_RENEW_WORKER_ID),
         })
     }
 
+    fn lease_key(id: u8) -> String {
+        format!("snowflake:worker:{}", id)
+    }
+
     pub async fn acquire(&self) -> eyre::Result<u8> {
         for id in 0..=self.max_worker_id {
-            let key = format!("snowflake:worker:{}", id);
+            let started_ms = unix_timestamp_ms();
             let result: Option<String> = redis::cmd("SET")
-                .arg(&key)
-                .arg("locked")
+                .arg(Self::lease_key(id))
+                .arg(&self.token)
                 .arg("NX")
                 .arg("EX")
-                .arg(60)
+                .arg(WORKER_ID_LEASE_TTL.as_secs())
                 .query_async(&mut self.connection.clone())
                 .await?;
 
             if result.is_some() {
-                *self.worker_id.lock().await = Some(id);
+                *self.worker_id.lock().await = Some((id, started_ms));
                 tracing::debug!("Acquired worker_id = {}", id);
                 return Ok(id);
             }
@@ -39,23 +138,203 @@ impl WorkerIdManager {
         eyre::bail!("No available worker_id");
     }
 
-    pub async fn keep_alive(self: Arc<Self>) {
-        let worker_id = self.worker_id.clone();
-        let mut connection = self.connection.clone();
+    /// Runs `LUA_SCRIPT_RENEW_WORKER_ID` once. A renewal that does not answer
+    /// within a renew interval counts as failed, so a hung connection cannot
+    /// keep `keep_alive` from suspending the generator.
+    async fn renew(&self, key: &str, connection: &mut MultiplexedConnection) -> Renewal {
+        let mut invocation = self.renew_script.key(key);
+        invocation
+            .arg(&self.token)
+            .arg(WORKER_ID_LEASE_TTL.as_secs());
+        let renewal = invocation.invoke_async(connection);
+        match tokio::time::timeout(WORKER_ID_RENEW_INTERVAL, renewal).await {
+            Ok(result) => Renewal::from_script(result),
+            Err(_) => {
+                tracing::warn!("worker id lease renewal timed out");
+                Renewal::Failed
+            }
+        }
+    }
 
+    /// Renews the lease of the acquired worker id until it is lost. The
+    /// returned task ends with the reason once `snowflake` has been suspended
+    /// for good, the service must stop then. Up to `max_failures` consecutive
+    /// Redis errors are tolerated, ids are not generated meanwhile if the lease
+    /// may expire before the next attempt. `snowflake` also stops on its own
+    /// once the lease deadline passes, should this task stall.
+    pub fn keep_alive(
+        self: Arc<Self>,
+        snowflake: Snowflake,
+        max_failures: u32,
+    ) -> JoinHandle<eyre::Report> {
         tokio::spawn(async move {
+            let Some((id, acquired_ms)) = *self.worker_id.lock().await else {
+                snowflake.suspend();
+                return eyre::eyre!("worker id lease kept alive before it was acquired");
+            };
+            let key = Self::lease_key(id);
+            let mut connection = self.connection.clone();
+            let mut lease = LeaseState::new(max_failures, Instant::now());
+            snowflake.extend_lease(lease_deadline_ms(acquired_ms));
+
             loop {
-                if let Some(id) = *worker_id.lock().await {
-                    let key = format!("snowflake:worker:{}", id);
-                    let _: () = redis::cmd("EXPIRE")
-                        .arg(&key)
-                        .arg(60)
-                        .query_async(&mut connection)
-                        .await
-                        .unwrap_or(());
+                tokio::time::sleep(WORKER_ID_RENEW_INTERVAL).await;
+
+                let started_ms = unix_timestamp_ms();
+                let renewal = self.renew(&key, &mut connection).await;
+                if matches!(renewal, Renewal::Renewed | Renewal::Reacquired) {
+                    snowflake.extend_lease(lease_deadline_ms(started_ms));
+                }
+                if renewal == Renewal::Reacquired {
+                    tracing::warn!("worker id {} lease had expired, reacquired it", id);
+                }
+
+                match lease.on_renewal(renewal, Instant::now()) {
+                    LeaseAction::Generate => snowflake.resume(),
+                    LeaseAction::Suspend => {
+                        tracing::error!(
+                            "worker id {} lease may expire before it is renewed, suspending id generation",
+                            id
+                        );
+                        snowflake.suspend();
+                    }
+                    LeaseAction::Halt => {
+                        snowflake.suspend();
+                        return match renewal {
+                            Renewal::Taken => {
+                                eyre::eyre!("worker id {} lease was taken by another instance", id)
+                            }
+                            _ => eyre::eyre!(
+                                "worker id {} lease could not be renewed {} times in a row",
+                                id,
+                                lease.failures
+                            ),
+                        };
+                    }
                 }
-                tokio::time::sleep(std::time::Duration::from_secs(30)).await;
             }
-        });
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lost_lease_halts() {
+        let start = Instant::now();
+        let mut lease = LeaseState::new(3, start);
+
+        assert_eq!(
+            lease.on_renewal(Renewal::Renewed, start + WORKER_ID_RENEW_INTERVAL),
+            LeaseAction::Generate
+        );
+        // Redis came back after the lease expired and another instance took it
+        assert_eq!(
+            lease.on_renewal(Renewal::Taken, start + 2 * WORKER_ID_RENEW_INTERVAL),
+            LeaseAction::Halt
+        );
+    }
+
+    #[test]
+    fn test_transient_failures_are_tolerated_up_to_max() {
+        let start = Instant::now();
+        let mut lease = LeaseState::new(2, start);
+
+        let tick = |n: u32| start + n * WORKER_ID_RENEW_INTERVAL;
+        assert_eq!(
+            lease.on_renewal(Renewal::Failed, tick(1)),
+            LeaseAction::Generate
+        );
+        assert_eq!(
+            lease.on_renewal(Renewal::Failed, tick(2)),
+            LeaseAction::Generate
+        );
+        // a successful renewal resets the count
+        assert_eq!(
+            lease.on_renewal(Renewal::Reacquired, tick(3)),
+            LeaseAction::Generate
+        );
+        assert_eq!(
+            lease.on_renewal(Renewal::Failed, tick(4)),
+            LeaseAction::Generate
+        );
+        assert_eq!(
+            lease.on_renewal(Renewal::Failed, tick(5)),
+            LeaseAction::Generate
+        );
+        assert_eq!(
+            lease.on_renewal(Renewal::Failed, tick(6)),
+            LeaseAction::Halt
+        );
+    }
+
+    #[test]
+    fn test_generation_suspends_before_lease_can_expire() {
+        let start = Instant::now();
+        let mut lease = LeaseState::new(u32::MAX, start);
+
+        let mut now = start;
+        let mut action = LeaseAction::Generate;
+        while action == LeaseAction::Generate {
+            now += WORKER_ID_RENEW_INTERVAL;
+            action = lease.on_renewal(Renewal::Failed, now);
+        }
+        assert_eq!(action, LeaseAction::Suspend);
+        assert!(now - start < WORKER_ID_LEASE_TTL);
+
+        assert_eq!(
+            lease.on_renewal(Renewal::Reacquired, now + WORKER_ID_RENEW_INTERVAL),
+            LeaseAction::Generate
+        );
+    }
+
+    #[test]
+    fn test_renew_script_answer_maps_to_renewal() {
+        assert_eq!(Renewal::from_script(Ok(1)), Renewal::Renewed);
+        assert_eq!(Renewal::from_script(Ok(2)), Renewal::Reacquired);
+        assert_eq!(Renewal::from_script(Ok(0)), Renewal::Taken);
+    }
+
+    /// Needs a local Redis: `cargo test -p web-service renew_taken -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_renew_taken_lease_halts() {
+        let client = redis::Client::open("redis://127.0.0.1:6379").unwrap();
+        let connection = client.get_multiplexed_async_connection().await.unwrap();
+        let manager = WorkerIdManager::new(connection.clone(), 255).unwrap();
+        let id = manager.acquire().await.unwrap();
+        let key = WorkerIdManager::lease_key(id);
+
+        let mut connection = connection;
+        assert_eq!(manager.renew(&key, &mut connection).await, Renewal::Renewed);
+
+        // another instance took the worker id after our lease expired
+        let _: () = redis::cmd("SET")
+            .arg(&key)
+            .arg("another-instance")
+            .query_async(&mut connection)
+            .await
+            .unwrap();
+        let renewal = manager.renew(&key, &mut connection).await;
+        assert_eq!(renewal, Renewal::Taken);
+        assert_eq!(
+            LeaseState::new(3, Instant::now()).on_renewal(renewal, Instant::now()),
+            LeaseAction::Halt
+        );
+
+        let _: () = redis::cmd("DEL")
+            .arg(&key)
+            .query_async(&mut connection)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_lease_deadline_precedes_expiry() {
+        let deadline = lease_deadline_ms(1_000);
+        assert!(deadline > 1_000 + WORKER_ID_RENEW_INTERVAL.as_millis() as u64);
+        assert!(deadline < 1_000 + WORKER_ID_LEASE_TTL.as_millis() as u64);
     }
 }
@@ -3,6 +3,7 @@ use std::fmt;
 use clap::crate_version;
 use git_testament::{git_testament, render_testament};
 use share::config::AppConfig;
+use share::models::topic_id::TopicId;
 use share::{config::TomlConfig as _, tracing::init_tracing_subscriber};
 
 use crate::admin;
@@ -40,7 +41,15 @@ pub struct Cli {
 pub enum Commands {
     WebServer,
     NatsConsumer,
-    ServiceTest,
+    ServiceTest {
+        /// Topic to load test, overrides `test.topic_id` from the config
+        #[arg(long)]
+        topic: Option<TopicId>,
+        /// Also validate the totals and require operators the test did not vote
+        /// on to stay unchanged, sets `test.strict_validation`
+        #[arg(long)]
+        strict: bool,
+    },
     PortableServer,
 }
 
@@ -49,7 +58,7 @@ impl fmt::Display for Commands {
         match self {
             Commands::WebServer => write!(f, "web-server"),
             Commands::NatsConsumer => write!(f, "nats-consumer"),
-            Commands::ServiceTest => write!(f, "service-test"),
+            Commands::ServiceTest { .. } => write!(f, "service-test"),
             Commands::PortableServer => write!(f, "portable-server"),
         }
     }
@@ -57,7 +66,7 @@ impl fmt::Display for Commands {
 
 impl Cli {
     pub async fn drive(self) -> Result<(), eyre::Error> {
-        let config: AppConfig = AppConfig::load_or_create("config/app.toml");
+        let mut config: AppConfig = AppConfig::load_or_create("config/app.toml");
         let service_name = self
             .command
             .as_ref()
@@ -89,7 +98,13 @@ impl Cli {
             ));
         }
 
-        if matches!(self.command, Some(Commands::ServiceTest)) {
+        if let Some(Commands::ServiceTest { topic, strict }) = &self.command {
+            if let Some(topic) = topic {
+                config.test.topic_id = topic.clone();
+            }
+            if *strict {
+                config.test.strict_validation = true;
+            }
             return service_test::ServiceTester::new(config).run().await;
         }
 